@@ -0,0 +1,17 @@
+//! Time the differential corpus in `tests/differential/init.el` in both the
+//! interpreter and the bytecode VM. Run with `cargo bench --bench
+//! differential`. The timings are reported by rune as messages.
+use std::process::Command;
+
+/// How many times each call is made when timing it.
+const ITERATIONS: &str = "1000";
+
+fn main() {
+    let status = Command::new(env!("CARGO_BIN_EXE_rune"))
+        .args(["--batch", "--load", "--init-directory=tests/differential"])
+        .env("RUNE_DIFFERENTIAL_ITERATIONS", ITERATIONS)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .expect("rune should start");
+    assert!(status.success(), "the interpreter and the bytecode VM disagree");
+}
@@ -49,6 +49,92 @@ fn convert(lisp_name: impl Into<String>, rust_name: &str) -> String {
     lisp_name
 }
 
+/// The `///` doc comment directly above the item ending at `end`, skipping
+/// any attributes in between.
+fn doc_comment(contents: &str, end: usize) -> Option<String> {
+    let mut lines: Vec<&str> = contents[..end]
+        .lines()
+        .rev()
+        .map(str::trim)
+        .take_while(|line| line.starts_with("///") || line.starts_with("#["))
+        .filter_map(|line| line.strip_prefix("///"))
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect();
+    lines.reverse();
+    let doc = lines.join("\n");
+    (!doc.trim().is_empty()).then_some(doc)
+}
+
+/// Find the docstrings of the functions defined at top level in the lisp files
+/// under `dir`, like make-docfile does.
+fn lisp_docstrings(dir: &Path, docs: &mut Vec<(char, String, String)>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            lisp_docstrings(&path, docs);
+            continue;
+        }
+        if path.extension().and_then(OsStr::to_str) != Some("el") {
+            continue;
+        }
+        let Ok(contents) = fs::read_to_string(&path) else { continue };
+        for (start, _) in contents.match_indices("\n(") {
+            let rest = &contents[start + 2..];
+            let Some(rest) =
+                ["defun ", "defmacro ", "defsubst "].iter().find_map(|x| rest.strip_prefix(x))
+            else {
+                continue;
+            };
+            let rest = rest.trim_start();
+            let end = rest.find(|c: char| c.is_whitespace() || c == '(').unwrap_or(rest.len());
+            let (name, rest) = rest.split_at(end);
+            let Some(rest) = skip_arglist(rest.trim_start()) else { continue };
+            if let Some(doc) = read_string(rest.trim_start()) {
+                docs.push(('F', name.to_string(), doc));
+            }
+        }
+    }
+}
+
+/// Return the text after the argument list at the start of `text`.
+fn skip_arglist(text: &str) -> Option<&str> {
+    if let Some(rest) = text.strip_prefix("nil") {
+        return Some(rest);
+    }
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 1 => return Some(&text[i + 1..]),
+            ')' => depth -= 1,
+            _ if depth == 0 => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Read the string literal at the start of `text`. Only the escapes that
+/// appear in docstrings are handled.
+fn read_string(text: &str) -> Option<String> {
+    let mut chars = text.strip_prefix('"')?.chars();
+    let mut string = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(string),
+            '\\' => match chars.next()? {
+                'n' => string.push('\n'),
+                't' => string.push('\t'),
+                '\n' | ' ' => {}
+                c => string.push(c),
+            },
+            '\x1f' => return None,
+            c => string.push(c),
+        }
+    }
+    None
+}
+
 #[derive(PartialEq)]
 enum DefvarType {
     Bool,
@@ -60,6 +146,7 @@ fn main() {
     let mut all_defun = Vec::new();
     let mut all_defvar = Vec::new();
     let mut all_defsym = Vec::new();
+    let mut docs = Vec::new();
 
     // rerun for all top level files
     for entry in fs::read_dir("src").unwrap() {
@@ -68,6 +155,8 @@ fn main() {
             println!("cargo:rerun-if-changed={}", path.display());
         }
     }
+    println!("cargo:rerun-if-changed=lisp");
+    lisp_docstrings(Path::new("lisp"), &mut docs);
 
     for entry in fs::read_dir("src").unwrap() {
         let entry = entry.unwrap();
@@ -91,6 +180,9 @@ fn main() {
                     let import_path = basename.to_str().unwrap().replace(MAIN_SEPARATOR, ":");
                     format!("crate::{import_path}::__subr_{name}")
                 };
+                if let Some(doc) = doc_comment(&contents, start) {
+                    docs.push(('F', lisp_name.clone(), doc));
+                }
                 all_defun.push((struct_name, name.to_string(), lisp_name));
             }
             // process all strings starting with defvar
@@ -124,6 +216,9 @@ fn main() {
                     }
                     _ => panic!("defvar form was too long {path:?}"),
                 };
+                if let Some(doc) = doc_comment(&contents, start) {
+                    docs.push(('V', name.clone(), doc));
+                }
                 all_defvar.push((ident, name, value, defvar_type));
             }
 
@@ -140,6 +235,12 @@ fn main() {
     }
 
     let out_dir = std::env::var("OUT_DIR").unwrap();
+    // The docstrings are looked up by `Snarf-documentation`. Builtins come
+    // last so that they take precedence over lisp definitions of the same name.
+    let mut doc_file = File::create(Path::new(&out_dir).join("DOC")).unwrap();
+    for (kind, name, doc) in docs {
+        write!(doc_file, "\x1f{kind}{name}\n{doc}").unwrap();
+    }
     // println!("cargo:warning={out_dir}/sym.rs");
     let dest_path = Path::new(&out_dir).join("sym.rs");
     let mut f = File::create(dest_path).unwrap();
@@ -226,20 +327,30 @@ pub(crate) fn init_symbols() {{
         "
 /// TODO: Use `LazyLock`: https://github.com/CeleritasCelery/rune/issues/34
 use std::sync::OnceLock;
-static INTERNED_SYMBOLS: OnceLock<std::sync::Mutex<SymbolMap>> = OnceLock::new();
+static INTERNED_SYMBOLS: OnceLock<SymbolMap> = OnceLock::new();
+
+/// The table of interned symbols, or the table of the current thread's
+/// [namespace](super::namespace) if it has entered one.
+pub(crate) fn interned_symbols() -> &'static SymbolMap {{
+    if let Some(map) = super::namespace::symbols() {{
+        return map;
+    }}
+    INTERNED_SYMBOLS.get_or_init(|| SymbolMap::with_builtins(Block::new_global()))
+}}
 
-pub(crate) fn interned_symbols() -> &'static std::sync::Mutex<SymbolMap> {{
-    INTERNED_SYMBOLS.get_or_init(|| std::sync::Mutex::new({{
-        let size: usize = {symbol_len};
-        let mut map = SymbolMapCore::with_capacity(size);
+impl SymbolMap {{
+    /// A table that holds only the builtin symbols, with `block` for the
+    /// functions and objects it shares.
+    pub(in crate::core) fn with_builtins(block: Block<true>) -> Self {{
+        let map = SymbolMapCore::new();
         for sym in &sym::BUILTIN_SYMBOLS {{
             map.pre_init(unsafe {{Symbol::from_ptr(sym as *const _)}});
         }}
         SymbolMap {{
             map,
-            block: Block::new_global(),
+            block: Mutex::new(block),
         }}
-    }}))
+    }}
 }}
 "
     )
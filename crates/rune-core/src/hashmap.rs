@@ -1,6 +1,13 @@
-//! Hashmap types used in the crate.
-use fxhash::FxBuildHasher;
+//! Hashmap types used in the crate. [`HashMap`] and [`HashSet`] iterate in
+//! an arbitrary order, so anything whose iteration order can be seen, like
+//! hash tables, variables, and features, uses the insertion ordered
+//! [`IndexMap`] and [`IndexSet`] instead. Removing with `swap_remove` moves
+//! the last entry into the hole, so use `shift_remove` where the order after
+//! a removal matters.
+pub use fxhash::FxBuildHasher;
+pub use indexmap::map::raw_entry_v1::{RawEntryApiV1, RawEntryMut};
 
 pub type HashMap<K, V> = std::collections::HashMap<K, V, FxBuildHasher>;
 pub type HashSet<K> = std::collections::HashSet<K, FxBuildHasher>;
 pub type IndexMap<K, V> = indexmap::IndexMap<K, V, FxBuildHasher>;
+pub type IndexSet<K> = indexmap::IndexSet<K, FxBuildHasher>;
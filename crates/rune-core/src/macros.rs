@@ -118,17 +118,21 @@ macro_rules! __rebind {
 macro_rules! __call {
     ($fn:ident $(,$args:expr)* ; $env:expr, $cx:expr) => {{
         let frame = &mut crate::core::env::CallFrame::new($env);
-        $(frame.push_arg($args);)*
-        crate::core::gc::Rt::<crate::core::gc::Slot<crate::core::object::Gc::<crate::core::object::FunctionType>>>::call(
-            $fn, frame, None, $cx
-        )
+        let mut pushed = Ok(());
+        $(if pushed.is_ok() { pushed = frame.push_arg($args); })*
+        match pushed {
+            Ok(()) => crate::eval::call_function($fn, frame, None, $cx),
+            Err(e) => Err(e.into()),
+        }
     }};
     ($fn:ident $(,$args:expr)* ; $name:expr, $env:expr, $cx:expr) => {{
         let frame = &mut crate::core::env::CallFrame::new($env);
-        $(frame.push_arg($args);)*
-        crate::core::gc::Rt::<crate::core::gc::Slot<crate::core::object::Gc::<crate::core::object::FunctionType>>>::call(
-            $fn, frame, Some($name), $cx
-        )
+        let mut pushed = Ok(());
+        $(if pushed.is_ok() { pushed = frame.push_arg($args); })*
+        match pushed {
+            Ok(()) => crate::eval::call_function($fn, frame, Some($name), $cx),
+            Err(e) => Err(e.into()),
+        }
     }};
 }
 
@@ -134,7 +134,9 @@ fn get_arg_conversion(args: &[ArgType]) -> Vec<TokenStream> {
                     quote! {crate::core::gc::Rt::bind_slice(&args[(#idx).min(args.len())..], cx)};
                 match gc {
                     Gc::Obj => bind,
-                    Gc::Other => quote! {crate::core::object::try_from_slice(#bind)?},
+                    Gc::Other => quote! {
+                        crate::core::object::FromObjectSlice::from_slice(#bind, &mut Vec::new())?
+                    },
                 }
             }
             // &[Rt<Gc<..>>]
@@ -512,6 +514,9 @@ mod test {
         test_args(quote! {x: &[Gc<T>]}, &[ArgType::Slice(Gc::Other)]);
         test_args(quote! {x: &[Gc<T>]}, &[ArgType::Slice(Gc::Other)]);
         test_args(quote! {x: &[u8]}, &[ArgType::Slice(Gc::Other)]);
+        test_args(quote! {x: &[&str]}, &[ArgType::Slice(Gc::Other)]);
+        test_args(quote! {x: &[Symbol]}, &[ArgType::Slice(Gc::Other)]);
+        test_args(quote! {x: &[Number]}, &[ArgType::Slice(Gc::Other)]);
         test_args(quote! {x: ArgSlice}, &[ArgType::ArgSlice]);
         test_args(quote! {x: &[Rt<Slot<Object>>]}, &[ArgType::SliceRt(Gc::Obj)]);
         test_args(quote! {x: &[Rto<Object>]}, &[ArgType::SliceRt(Gc::Obj)]);
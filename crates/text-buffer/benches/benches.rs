@@ -71,6 +71,28 @@ fn resize(c: &mut Criterion) {
                 buffer.insert_char('a');
             });
         });
+        // a larger growth factor means fewer resizes when appending
+        let chunk = "a".repeat(1024);
+        for factor in [2, 4] {
+            group.bench_function(id::new(format!("append_x{factor}"), size), |b| {
+                b.iter(|| {
+                    let buffer = &mut Buffer::new();
+                    buffer.set_gap_growth(factor);
+                    for _ in 0..*size / chunk.len() {
+                        buffer.insert(&chunk);
+                    }
+                    black_box(buffer.capacity());
+                });
+            });
+        }
+        group.bench_function(id::new("shrink_to_fit", size), |b| {
+            b.iter(|| {
+                let buffer = &mut Buffer::from(&*string);
+                buffer.delete_range(0, size / 2);
+                buffer.shrink_to_fit();
+                black_box(buffer.capacity());
+            });
+        });
     }
     group.finish();
 }
@@ -16,7 +16,7 @@ use str_indices::chars;
 
 /// A Gap buffer. This represents the text of a buffer, and allows for
 /// efficient insertion and deletion of text.
-#[derive(Default, GetSize)]
+#[derive(GetSize)]
 pub struct Buffer {
     /// The buffer data
     data: Box<[u8]>,
@@ -33,6 +33,27 @@ pub struct Buffer {
     total: Metric,
     metrics: BufferMetrics,
     new_gap_size: usize,
+    /// How many times bigger the gap gets each time the buffer grows
+    gap_growth: usize,
+    /// The number of edits that changed the text
+    modified_tick: usize,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self {
+            data: Box::default(),
+            gap_start: 0,
+            gap_end: 0,
+            gap_chars: 0,
+            cursor: GapMetric::default(),
+            total: Metric::default(),
+            metrics: BufferMetrics::default(),
+            new_gap_size: 0,
+            gap_growth: Self::GAP_GROWTH,
+            modified_tick: 0,
+        }
+    }
 }
 
 impl Debug for Buffer {
@@ -50,6 +71,7 @@ impl Debug for Buffer {
             .field("metrics", &self.metrics)
             .field("total_chars", &self.total.chars)
             .field("new_gap_size", &self.new_gap_size)
+            .field("gap_growth", &self.gap_growth)
             .finish()
     }
 }
@@ -161,6 +183,8 @@ impl From<String> for Buffer {
             total,
             metrics,
             new_gap_size: calc_start_gap_size(len),
+            gap_growth: Self::GAP_GROWTH,
+            modified_tick: 0,
         }
     }
 }
@@ -188,6 +212,8 @@ impl From<&str> for Buffer {
             total: metrics.len(),
             new_gap_size,
             metrics,
+            gap_growth: Self::GAP_GROWTH,
+            modified_tick: 0,
         }
     }
 }
@@ -217,6 +243,7 @@ impl Buffer {
     #[cfg(test)]
     const GAP_SIZE: usize = 5;
     const MAX_GAP: usize = 1024 * 8;
+    const GAP_GROWTH: usize = 2;
 
     #[must_use]
     pub fn new() -> Self {
@@ -228,6 +255,39 @@ impl Buffer {
         Self { new_gap_size: gap, ..Self::default() }
     }
 
+    /// Set how many times bigger the gap gets each time the buffer has to
+    /// grow. A larger factor means fewer reallocations when a lot of text is
+    /// inserted, at the cost of more unused capacity. The gap is always at
+    /// least 5% of the buffer size, and stops growing at 8KB. The default is
+    /// 2.
+    pub fn set_gap_growth(&mut self, factor: usize) {
+        self.gap_growth = factor.max(1);
+    }
+
+    /// The number of bytes allocated for the buffer, including the gap.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Release the unused capacity of the buffer. This is useful after
+    /// deleting a large amount of text. The next insertion will have to grow
+    /// the buffer again.
+    pub fn shrink_to_fit(&mut self) {
+        if self.gap_len() == 0 {
+            return;
+        }
+        self.move_gap(GapMetric { bytes: self.data.len(), chars: self.total.chars });
+        let mut data = std::mem::take(&mut self.data).into_vec();
+        data.truncate(self.gap_start);
+        self.data = data.into_boxed_slice();
+        self.gap_end = self.gap_start;
+        if self.cursor.bytes > self.gap_end {
+            self.cursor.bytes = self.gap_end;
+        }
+        self.new_gap_size = calc_start_gap_size(self.len());
+    }
+
     /// Grow the buffer to accommodate the new slice. Moves the gap to the
     /// cursor position at the same time.
     fn grow(&mut self, slice: &str) {
@@ -277,7 +337,7 @@ impl Buffer {
         self.gap_start = self.gap_end - self.new_gap_size;
         self.total += new;
         self.new_gap_size =
-            cmp::max(self.len() / 20, cmp::min(self.new_gap_size * 2, Self::MAX_GAP));
+            cmp::max(self.len() / 20, cmp::min(self.new_gap_size * self.gap_growth, Self::MAX_GAP));
     }
 
     #[inline]
@@ -291,6 +351,7 @@ impl Buffer {
         if slice.is_empty() {
             return;
         }
+        self.modified_tick += 1;
         self.metrics.insert(self.to_abs_pos(self.cursor), MetricBuilder::new(slice));
         if self.gap_len() < slice.len() {
             self.grow(slice);
@@ -338,6 +399,7 @@ impl Buffer {
             let end = GapMetric { bytes: end_bytes, chars: end_chars };
             self.metrics.delete(self.to_abs_pos(beg), self.to_abs_pos(end));
             self.delete_byte_range(beg, end);
+            self.modified_tick += 1;
         }
     }
 
@@ -457,6 +519,13 @@ impl Buffer {
         }
     }
 
+    /// A counter that increases every time the text is changed. Comparing it
+    /// with an earlier value is a cheap way to tell if the text changed.
+    #[inline]
+    pub fn modified_tick(&self) -> usize {
+        self.modified_tick
+    }
+
     #[inline]
     pub fn cursor(&self) -> Position {
         Position::new(self.to_abs_pos(self.cursor))
@@ -480,6 +549,44 @@ impl Buffer {
         self.to_str(byte..end).chars().next()
     }
 
+    /// The position of the start of the line that `pos` is on.
+    pub fn line_start(&self, pos: usize) -> usize {
+        let pos = pos.min(self.total.chars);
+        // Only scan back as far as the previous newline
+        let [before, after] = self.slices(..pos);
+        let mut skipped = 0;
+        for text in [after, before] {
+            match text.rfind('\n') {
+                Some(newline) => return pos - skipped - chars::count(&text[newline + 1..]),
+                None => skipped += chars::count(text),
+            }
+        }
+        0
+    }
+
+    /// The position of the end of the line that `pos` is on, which is the
+    /// position of its newline or the end of the buffer.
+    pub fn line_end(&self, pos: usize) -> usize {
+        let pos = pos.min(self.total.chars);
+        // Only scan forward as far as the next newline
+        let [before, after] = self.slices(pos..);
+        let mut skipped = 0;
+        for text in [before, after] {
+            match text.find('\n') {
+                Some(newline) => return pos + skipped + chars::count(&text[..newline]),
+                None => skipped += chars::count(text),
+            }
+        }
+        self.total.chars
+    }
+
+    /// The number of newlines between the positions `beg` and `end`.
+    pub fn count_newlines(&self, beg: usize, end: usize) -> usize {
+        let [before, after] = self.slices(beg.min(end)..beg.max(end));
+        let count = |text: &str| text.bytes().filter(|x| *x == b'\n').count();
+        count(before) + count(after)
+    }
+
     #[inline]
     pub fn move_gap_out_of(&mut self, range: impl RangeBounds<usize>) {
         if !range.contains(&self.gap_chars)
@@ -613,7 +720,10 @@ impl Buffer {
         self.total.chars == 0
     }
 
-    const fn gap_len(&self) -> usize {
+    /// The number of bytes that can be inserted at the gap before the
+    /// buffer has to grow.
+    #[inline]
+    pub const fn gap_len(&self) -> usize {
         self.gap_end - self.gap_start
     }
 
@@ -721,6 +831,41 @@ impl Buffer {
         }
     }
 
+    /// The text between the character positions in `bounds` as the part
+    /// before the gap and the part after it, either of which can be empty.
+    /// Unlike [`Buffer::read`] this never copies. The slices borrow the
+    /// buffer, so it can't be edited (and the gap can't move) while they are
+    /// in use.
+    #[inline]
+    pub fn slices(&self, bounds: impl RangeBounds<usize>) -> [&str; 2] {
+        let Range { start, end } = Self::bounds_to_range(bounds);
+        let end = end.min(self.total.chars);
+        let start = start.min(end);
+        let (start, end) = (self.char_to_byte(start), self.char_to_byte(end));
+        if end <= self.gap_start {
+            [self.to_str(start..end), ""]
+        } else if start >= self.gap_end {
+            ["", self.to_str(start..end)]
+        } else {
+            [self.to_str(start..self.gap_start), self.to_str(self.gap_end..end)]
+        }
+    }
+
+    /// The characters from position `pos` to the end of the buffer.
+    #[inline]
+    pub fn chars_at(&self, pos: usize) -> impl Iterator<Item = char> + '_ {
+        let [before, after] = self.slices(pos..);
+        before.chars().chain(after.chars())
+    }
+
+    /// The characters before position `pos`, going backwards from the
+    /// closest one.
+    #[inline]
+    pub fn chars_before(&self, pos: usize) -> impl Iterator<Item = char> + '_ {
+        let [before, after] = self.slices(..pos);
+        after.chars().rev().chain(before.chars().rev())
+    }
+
     fn assert_char_boundary(&self, pos: usize) {
         if cfg!(debug_assertions) {
             if pos == self.gap_start {
@@ -865,6 +1010,43 @@ mod test {
         assert_eq!(buffer.char_at(3), None);
     }
 
+    #[test]
+    fn test_modified_tick() {
+        let mut buffer = Buffer::from("world");
+        assert_eq!(buffer.modified_tick(), 0);
+        buffer.insert("hello ");
+        assert_eq!(buffer.modified_tick(), 1);
+        buffer.insert("");
+        buffer.delete_range(3, 3);
+        buffer.set_cursor(2);
+        assert_eq!(buffer.modified_tick(), 1);
+        buffer.delete_range(1, 3);
+        buffer.insert_char('x');
+        assert_eq!(buffer.modified_tick(), 3);
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut buffer = Buffer::from("ab\nλcd\n\nef");
+        // The answers are the same wherever the gap is
+        for gap in [0, 4, 8, 10, 3] {
+            buffer.move_gap(GapMetric { bytes: buffer.char_to_byte(gap), chars: gap });
+            assert_eq!(buffer.line_start(0), 0);
+            assert_eq!(buffer.line_start(2), 0);
+            assert_eq!(buffer.line_start(3), 3);
+            assert_eq!(buffer.line_start(6), 3);
+            assert_eq!(buffer.line_start(7), 7);
+            assert_eq!(buffer.line_start(10), 8);
+            assert_eq!(buffer.line_end(0), 2);
+            assert_eq!(buffer.line_end(4), 6);
+            assert_eq!(buffer.line_end(7), 7);
+            assert_eq!(buffer.line_end(8), 10);
+            assert_eq!(buffer.count_newlines(0, 10), 3);
+            assert_eq!(buffer.count_newlines(3, 7), 1);
+            assert_eq!(buffer.count_newlines(8, 3), 2);
+        }
+    }
+
     #[test]
     fn test_delete_nothing() {
         let mut buffer = Buffer::from("world");
@@ -955,6 +1137,65 @@ mod test {
         assert_eq!(buffer.read(4..6), Cow::<str>::Owned(String::from("o ")));
     }
 
+    #[test]
+    fn test_slices() {
+        let mut buffer = Buffer::from("aµ福 world");
+        assert_eq!(buffer.slices(..), ["", "aµ福 world"]);
+        buffer.set_cursor(3);
+        buffer.insert("x");
+        assert_eq!(buffer.slices(..), ["aµ福x", " world"]);
+        assert_eq!(buffer.slices(1..3), ["µ福", ""]);
+        assert_eq!(buffer.slices(2..6), ["福x", " w"]);
+        assert_eq!(buffer.slices(4..20), ["", " world"]);
+        assert_eq!(buffer.slices(4..4), ["", ""]);
+        assert_eq!(buffer.slices(6..2), ["", ""]);
+    }
+
+    #[test]
+    fn test_chars_at() {
+        let mut buffer = Buffer::from("aµ福 x");
+        for gap in [0, 2, 5] {
+            buffer.move_gap(GapMetric { bytes: buffer.char_to_byte(gap), chars: gap });
+            assert_eq!(buffer.chars_at(0).collect::<String>(), "aµ福 x");
+            assert_eq!(buffer.chars_at(1).collect::<String>(), "µ福 x");
+            assert_eq!(buffer.chars_at(5).next(), None);
+            assert_eq!(buffer.chars_before(5).collect::<String>(), "x 福µa");
+            assert_eq!(buffer.chars_before(3).collect::<String>(), "福µa");
+            assert_eq!(buffer.chars_before(0).next(), None);
+        }
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut buffer = Buffer::from("hello world");
+        buffer.set_cursor(5);
+        buffer.delete_forwards(6);
+        assert_eq!(buffer.capacity(), 11 + Buffer::GAP_SIZE);
+        buffer.set_cursor(2);
+        buffer.shrink_to_fit();
+        assert_eq!(buffer.capacity(), 5);
+        assert_eq!(buffer.gap_len(), 0);
+        assert_eq!(buffer, "hello");
+        buffer.insert("y");
+        buffer.set_cursor(6);
+        buffer.shrink_to_fit();
+        buffer.insert("!");
+        assert_eq!(buffer, "heyllo!");
+    }
+
+    #[test]
+    fn test_gap_growth() {
+        let mut slow = Buffer::new();
+        let mut fast = Buffer::new();
+        fast.set_gap_growth(4);
+        for _ in 0..4 {
+            slow.insert("abcdef");
+            fast.insert("abcdef");
+        }
+        assert_eq!(slow.to_string(), fast.to_string());
+        assert!(fast.capacity() > slow.capacity());
+    }
+
     #[test]
     fn test_build_unicode() {
         let string = "aaaaaaaaaՂaaaaaaaaa";
@@ -143,6 +143,10 @@ Earlier variables shadow later ones with the same name.")
       (autoload-do-load fn)
       (setq fn (or (symbol-function name)
                    (cdr (assq name byte-compile-function-environment)))))
+    ;; RUNE-BOOTSTRAP - interpreted closures are objects, so match them in
+    ;; their `(closure ENV ARGS . BODY)' list form
+    (when (interpreted-function-p fn)
+      (setq fn `(closure ,(aref fn 2) ,(aref fn 0) . ,(aref fn 1))))
     (pcase fn
       ('nil
        (byte-compile-warn-x name
@@ -471,6 +471,12 @@ This list lives partly on the stack.")
 Filled in `cconv-analyze-form' but initialized and consulted here.")
 (defvar byte-compile-const-variables nil
   "List of variables declared as constants during compilation of this file.")
+;; RUNE-BOOTSTRAP - the values of constants whose references are substituted
+(defvar byte-compile--constant-values nil
+  "Alist of (VAR . VALUE) for the `defconst's in this compilation unit.")
+;; RUNE-BOOTSTRAP - the source of the functions defsubst calls are inlined from
+(defvar byte-compile--function-definitions nil
+  "Alist of (NAME . (lambda ARGLIST . BODY)) for the functions in this unit.")
 (defvar byte-compile-free-references)
 (defvar byte-compile-free-assignments)
 
@@ -1776,6 +1782,8 @@ It is too wide if it has any lines longer than the largest of
          (byte-compile-bound-variables nil)
          (byte-compile-lexical-variables nil)
          (byte-compile-const-variables nil)
+         (byte-compile--constant-values nil)
+         (byte-compile--function-definitions nil)
          (byte-compile-free-references nil)
          (byte-compile-free-assignments nil)
          ;;
@@ -2529,6 +2537,17 @@ list that represents a doc string reference.
 (defun byte-compile-preprocess (form &optional _for-effect)
   (let ((print-symbols-bare t))         ; Possibly redundant binding.
     (setq form (macroexpand-all form byte-compile-macro-environment)))
+  ;; RUNE-BOOTSTRAP - inline calls to `defsubst' functions, substitute the
+  ;; values of `defconst' variables and fold calls to `pure' functions
+  (when (memq byte-optimize '(t source))
+    (let ((inlined (byte-optimize--inline-defsubsts
+                    form byte-compile--function-definitions)))
+      ;; The inlined bodies may not have been expanded yet
+      (unless (equal inlined form)
+        (setq form (macroexpand-all inlined byte-compile-macro-environment))))
+    (setq form (byte-optimize--fold-pure-calls
+                (byte-optimize--substitute-constants
+                 form byte-compile--constant-values))))
   ;; FIXME: We should run byte-optimize-form here, but it currently does not
   ;; recurse through all the code, so we'd have to fix this first.
   ;; Maybe a good fix would be to merge byte-optimize-form into
@@ -2626,8 +2645,9 @@ list that represents a doc string reference.
 (defun byte-compile-file-form-defvar (form)
   (let ((sym (nth 1 form)))
     (byte-compile--declare-var sym)
-    (if (eq (car form) 'defconst)
-        (push sym byte-compile-const-variables)))
+    (when (eq (car form) 'defconst)
+      (push sym byte-compile-const-variables)
+      (push (cons sym (nth 2 form)) byte-compile--constant-values)))
   (if (and (null (cddr form))		;No `value' provided.
            (eq (car form) 'defvar))     ;Just a declaration.
       nil
@@ -2806,6 +2826,10 @@ not to take responsibility for the actual compilation of the code."
           ;; Tell the caller that we didn't compile it yet.
           nil)
 
+      ;; RUNE-BOOTSTRAP - keep the source so that calls to it can be inlined
+      (unless macro
+        (push (cons bare-name `(lambda ,arglist . ,body))
+              byte-compile--function-definitions))
       (let* ((code (byte-compile-lambda (cons arglist body) t)))
         (if this-one
             ;; A definition in b-c-initial-m-e should always take precedence
@@ -2939,6 +2963,10 @@ If FORM is a lambda or a macro, byte-compile it as a function."
             fun)
            (t
             (let (final-eval)
+              ;; RUNE-BOOTSTRAP - interpreted closures are objects, so turn
+              ;; them back into the `(closure ENV ARGS . BODY)' list form
+              (when (interpreted-function-p fun)
+                (setq fun `(closure ,(aref fun 2) ,(aref fun 0) . ,(aref fun 1))))
               (when (or (symbolp form) (eq (car-safe fun) 'closure))
                 ;; `fun' is a function *value*, so try to recover its corresponding
                 ;; source code.
@@ -4910,8 +4938,9 @@ binding slots have been popped."
 	 (if (< ncall 2) "requires" "accepts only")
 	 "2-3")))
     (push var byte-compile-bound-variables)
-    (if (eq fun 'defconst)
-	(push var byte-compile-const-variables))
+    (when (eq fun 'defconst)
+      (push var byte-compile-const-variables)
+      (push (cons var value) byte-compile--constant-values))
     (when (and string (not (stringp string)))
       (byte-compile-warn-x
        string
@@ -4936,95 +4936,98 @@ Value is t if the function succeeds in setting the attributes."
 
     result))
 
-(defun backup-buffer ()
-  "Make a backup of the disk file visited by the current buffer, if appropriate.
-This is normally done before saving the buffer the first time.
-
-A backup may be done by renaming or by copying; see documentation of
-variable `make-backup-files'.  If it's done by renaming, then the file is
-no longer accessible under its old name.
-
-The value is non-nil after a backup was made by renaming.
-It has the form (MODES EXTENDED-ATTRIBUTES BACKUPNAME).
-MODES is the result of `file-modes' on the original
-file; this means that the caller, after saving the buffer, should change
-the modes of the new file to agree with the old modes.
-EXTENDED-ATTRIBUTES is the result of `file-extended-attributes'
-on the original file; this means that the caller, after saving
-the buffer, should change the extended attributes of the new file
-to agree with the old attributes.
-BACKUPNAME is the backup file name, which is the old file renamed."
-  (when (and make-backup-files (not backup-inhibited) (not buffer-backed-up))
-    (let ((attributes (file-attributes buffer-file-name)))
-      (when (and attributes (memq (aref (elt attributes 8) 0) '(?- ?l)))
-	;; If specified name is a symbolic link, chase it to the target.
-	;; This makes backups in the directory where the real file is.
-	(let* ((real-file-name (file-chase-links buffer-file-name))
-	       (backup-info (find-backup-file-name real-file-name)))
-	  (when backup-info
-	    (let* ((backupname (car backup-info))
-		   (targets (cdr backup-info))
-		   (old-versions
-		    ;; If have old versions to maybe delete,
-		    ;; ask the user to confirm now, before doing anything.
-		    ;; But don't actually delete til later.
-		    (and targets
-			 (booleanp delete-old-versions)
-			 (or delete-old-versions
-			     (y-or-n-p
-			      (format "Delete excess backup versions of %s? "
-				      real-file-name)))
-			 targets))
-		   (modes (file-modes buffer-file-name))
-		   (extended-attributes
-		    (file-extended-attributes buffer-file-name))
-		   (copy-when-priv-mismatch
-		    backup-by-copying-when-privileged-mismatch)
-		   (make-copy
-		    (or file-precious-flag backup-by-copying
-			;; Don't rename a suid or sgid file.
-			(and modes (< 0 (logand modes #o6000)))
-			(not (file-writable-p
-			      (file-name-directory real-file-name)))
-			(and backup-by-copying-when-linked
-			     (< 1 (file-nlinks real-file-name)))
-			(and (or backup-by-copying-when-mismatch
-				 (and (integerp copy-when-priv-mismatch)
-				      (let ((attr (file-attributes
-						   real-file-name
-						   'integer)))
-                                        (or (<= (file-attribute-user-id attr)
-                                                copy-when-priv-mismatch)
-                                            (<= (file-attribute-group-id attr)
-                                                copy-when-priv-mismatch)))))
-			     (not (file-ownership-preserved-p real-file-name
-							      t)))))
-		   setmodes)
-	      (condition-case ()
-		  (progn
-		    ;; Actually make the backup file.
-		    (if make-copy
-			(backup-buffer-copy real-file-name backupname
-					    modes extended-attributes)
-		      ;; rename-file should delete old backup.
-		      (rename-file real-file-name backupname t)
-		      (setq setmodes (list modes extended-attributes
-					   backupname)))
-		    (setq buffer-backed-up t)
-		    ;; Now delete the old versions, if desired.
-		    (dolist (old-version old-versions)
-		      (delete-file old-version)))
-		(file-error nil))
-	      ;; If trouble writing the backup, write it in .emacs.d/%backup%.
-	      (when (not buffer-backed-up)
-		(setq backupname (locate-user-emacs-file "%backup%~"))
-		(message "Cannot write backup file; backing up in %s"
-			 backupname)
-		(sleep-for 1)
-		(backup-buffer-copy real-file-name backupname
-				    modes extended-attributes)
-		(setq buffer-backed-up t))
-	      setmodes)))))))
+;; RUNE-BOOTSTRAP - backup-buffer is defined in src/autosave.rs because
+;; copy-file, rename-file, file-modes and file-extended-attributes are not
+;; implemented yet.
+;; (defun backup-buffer ()
+;;   "Make a backup of the disk file visited by the current buffer, if appropriate.
+;; This is normally done before saving the buffer the first time.
+
+;; A backup may be done by renaming or by copying; see documentation of
+;; variable `make-backup-files'.  If it's done by renaming, then the file is
+;; no longer accessible under its old name.
+
+;; The value is non-nil after a backup was made by renaming.
+;; It has the form (MODES EXTENDED-ATTRIBUTES BACKUPNAME).
+;; MODES is the result of `file-modes' on the original
+;; file; this means that the caller, after saving the buffer, should change
+;; the modes of the new file to agree with the old modes.
+;; EXTENDED-ATTRIBUTES is the result of `file-extended-attributes'
+;; on the original file; this means that the caller, after saving
+;; the buffer, should change the extended attributes of the new file
+;; to agree with the old attributes.
+;; BACKUPNAME is the backup file name, which is the old file renamed."
+;;   (when (and make-backup-files (not backup-inhibited) (not buffer-backed-up))
+;;     (let ((attributes (file-attributes buffer-file-name)))
+;;       (when (and attributes (memq (aref (elt attributes 8) 0) '(?- ?l)))
+;; 	;; If specified name is a symbolic link, chase it to the target.
+;; 	;; This makes backups in the directory where the real file is.
+;; 	(let* ((real-file-name (file-chase-links buffer-file-name))
+;; 	       (backup-info (find-backup-file-name real-file-name)))
+;; 	  (when backup-info
+;; 	    (let* ((backupname (car backup-info))
+;; 		   (targets (cdr backup-info))
+;; 		   (old-versions
+;; 		    ;; If have old versions to maybe delete,
+;; 		    ;; ask the user to confirm now, before doing anything.
+;; 		    ;; But don't actually delete til later.
+;; 		    (and targets
+;; 			 (booleanp delete-old-versions)
+;; 			 (or delete-old-versions
+;; 			     (y-or-n-p
+;; 			      (format "Delete excess backup versions of %s? "
+;; 				      real-file-name)))
+;; 			 targets))
+;; 		   (modes (file-modes buffer-file-name))
+;; 		   (extended-attributes
+;; 		    (file-extended-attributes buffer-file-name))
+;; 		   (copy-when-priv-mismatch
+;; 		    backup-by-copying-when-privileged-mismatch)
+;; 		   (make-copy
+;; 		    (or file-precious-flag backup-by-copying
+;; 			;; Don't rename a suid or sgid file.
+;; 			(and modes (< 0 (logand modes #o6000)))
+;; 			(not (file-writable-p
+;; 			      (file-name-directory real-file-name)))
+;; 			(and backup-by-copying-when-linked
+;; 			     (< 1 (file-nlinks real-file-name)))
+;; 			(and (or backup-by-copying-when-mismatch
+;; 				 (and (integerp copy-when-priv-mismatch)
+;; 				      (let ((attr (file-attributes
+;; 						   real-file-name
+;; 						   'integer)))
+;;                                         (or (<= (file-attribute-user-id attr)
+;;                                                 copy-when-priv-mismatch)
+;;                                             (<= (file-attribute-group-id attr)
+;;                                                 copy-when-priv-mismatch)))))
+;; 			     (not (file-ownership-preserved-p real-file-name
+;; 							      t)))))
+;; 		   setmodes)
+;; 	      (condition-case ()
+;; 		  (progn
+;; 		    ;; Actually make the backup file.
+;; 		    (if make-copy
+;; 			(backup-buffer-copy real-file-name backupname
+;; 					    modes extended-attributes)
+;; 		      ;; rename-file should delete old backup.
+;; 		      (rename-file real-file-name backupname t)
+;; 		      (setq setmodes (list modes extended-attributes
+;; 					   backupname)))
+;; 		    (setq buffer-backed-up t)
+;; 		    ;; Now delete the old versions, if desired.
+;; 		    (dolist (old-version old-versions)
+;; 		      (delete-file old-version)))
+;; 		(file-error nil))
+;; 	      ;; If trouble writing the backup, write it in .emacs.d/%backup%.
+;; 	      (when (not buffer-backed-up)
+;; 		(setq backupname (locate-user-emacs-file "%backup%~"))
+;; 		(message "Cannot write backup file; backing up in %s"
+;; 			 backupname)
+;; 		(sleep-for 1)
+;; 		(backup-buffer-copy real-file-name backupname
+;; 				    modes extended-attributes)
+;; 		(setq buffer-backed-up t))
+;; 	      setmodes)))))))
 
 (defun backup-buffer-copy (from-name to-name modes extended-attributes)
   ;; Create temp files with strict access rights.  It's easy to
@@ -5658,119 +5661,122 @@ in such cases.")
 
 (put 'save-buffer-coding-system 'permanent-local t)
 
-(defun basic-save-buffer (&optional called-interactively)
-  "Save the current buffer in its visited file, if it has been modified.
-
-The hooks `write-contents-functions', `local-write-file-hooks'
-and `write-file-functions' get a chance to do the job of saving;
-if they do not, then the buffer is saved in the visited file in
-the usual way.
-
-Before and after saving the buffer, this function runs
-`before-save-hook' and `after-save-hook', respectively."
-  (interactive '(called-interactively))
-  (save-current-buffer
-    ;; In an indirect buffer, save its base buffer instead.
-    (if (buffer-base-buffer)
-	(set-buffer (buffer-base-buffer)))
-    (if (or (buffer-modified-p)
-	    ;; Handle the case when no modification has been made but
-	    ;; the file disappeared since visited.
-	    (and buffer-file-name
-		 (not (file-exists-p buffer-file-name))))
-	(let ((recent-save (recent-auto-save-p))
-	      setmodes)
-	  (or (null buffer-file-name)
-              (verify-visited-file-modtime (current-buffer))
-	      (not (file-exists-p buffer-file-name))
-	      (yes-or-no-p
-	       (format
-		"%s has changed since visited or saved.  Save anyway? "
-		(file-name-nondirectory buffer-file-name)))
-	      (user-error "Save not confirmed"))
-	  (save-restriction
-	    (widen)
-	    (save-excursion
-	      (and (> (point-max) (point-min))
-		   (not find-file-literally)
-                   (null buffer-read-only)
-		   (/= (char-after (1- (point-max))) ?\n)
-		   (not (and (eq selective-display t)
-			     (= (char-after (1- (point-max))) ?\r)))
-		   (or (eq require-final-newline t)
-		       (eq require-final-newline 'visit-save)
-		       (and require-final-newline
-			    (y-or-n-p
-			     (format "Buffer %s does not end in newline.  Add one? "
-				     (buffer-name)))))
-		   (save-excursion
-		     (goto-char (point-max))
-		     (insert ?\n))))
-	    ;; Don't let errors prevent saving the buffer.
-	    (with-demoted-errors "Before-save hook error: %S"
-	      (run-hooks 'before-save-hook))
-            ;; Give `write-contents-functions' a chance to
-            ;; short-circuit the whole process.
-	    (unless (run-hook-with-args-until-success 'write-contents-functions)
-              ;; If buffer has no file name, ask user for one.
-              (or buffer-file-name
-                  (let ((filename
-                         (expand-file-name
-                          (read-file-name "File to save in: "
-                                          nil (expand-file-name (buffer-name))))))
-                    (if (file-exists-p filename)
-                        (if (file-directory-p filename)
-                            ;; Signal an error if the user specified the name of an
-                            ;; existing directory.
-                            (error "%s is a directory" filename)
-                          (unless (y-or-n-p (format-message
-                                             "File `%s' exists; overwrite? "
-                                             filename))
-                            (error "Canceled"))))
-                    (set-visited-file-name filename)))
-              ;; Support VC version backups.
-	      (vc-before-save)
-	      (or (run-hook-with-args-until-success 'local-write-file-hooks)
-	          (run-hook-with-args-until-success 'write-file-functions)
-	          ;; If a hook returned t, file is already "written".
-	          ;; Otherwise, write it the usual way now.
-	          (let ((dir (file-name-directory
-			      (expand-file-name buffer-file-name))))
-		    (unless (file-exists-p dir)
-		      (if (y-or-n-p
-		           (format-message
-                            "Directory `%s' does not exist; create? " dir))
-		          (make-directory dir t)
-		        (error "Canceled")))
-		    (setq setmodes (basic-save-buffer-1)))))
-	    ;; Now we have saved the current buffer.  Let's make sure
-	    ;; that buffer-file-coding-system is fixed to what
-	    ;; actually used for saving by binding it locally.
-            (when buffer-file-name
-	      (if save-buffer-coding-system
-		  (setq save-buffer-coding-system last-coding-system-used)
-	        (setq buffer-file-coding-system last-coding-system-used))
-	      (setq buffer-file-number
-		    (file-attribute-file-identifier
-                     (file-attributes buffer-file-name)))
-	      (if setmodes
-		  (condition-case ()
-		      (progn
-		        (unless
-			    (with-demoted-errors "Error setting file modes: %S"
-			        (set-file-modes buffer-file-name (car setmodes)))
-			  (set-file-extended-attributes buffer-file-name
-						        (nth 1 setmodes))))
-		    (error nil)))
-              ;; Support VC `implicit' locking.
-	      (vc-after-save))
-            ;; If the auto-save file was recent before this command,
-	    ;; delete it now.
-	    (delete-auto-save-file-if-necessary recent-save))
-	  (run-hooks 'after-save-hook))
-      (or noninteractive
-          (not called-interactively)
-          (files--message "(No changes need to be saved)")))))
+;; RUNE-BOOTSTRAP - basic-save-buffer is defined in src/autosave.rs because
+;; write-region and the visited file modtime functions are not implemented
+;; yet.
+;; (defun basic-save-buffer (&optional called-interactively)
+;;   "Save the current buffer in its visited file, if it has been modified.
+
+;; The hooks `write-contents-functions', `local-write-file-hooks'
+;; and `write-file-functions' get a chance to do the job of saving;
+;; if they do not, then the buffer is saved in the visited file in
+;; the usual way.
+
+;; Before and after saving the buffer, this function runs
+;; `before-save-hook' and `after-save-hook', respectively."
+;;   (interactive '(called-interactively))
+;;   (save-current-buffer
+;;     ;; In an indirect buffer, save its base buffer instead.
+;;     (if (buffer-base-buffer)
+;; 	(set-buffer (buffer-base-buffer)))
+;;     (if (or (buffer-modified-p)
+;; 	    ;; Handle the case when no modification has been made but
+;; 	    ;; the file disappeared since visited.
+;; 	    (and buffer-file-name
+;; 		 (not (file-exists-p buffer-file-name))))
+;; 	(let ((recent-save (recent-auto-save-p))
+;; 	      setmodes)
+;; 	  (or (null buffer-file-name)
+;;               (verify-visited-file-modtime (current-buffer))
+;; 	      (not (file-exists-p buffer-file-name))
+;; 	      (yes-or-no-p
+;; 	       (format
+;; 		"%s has changed since visited or saved.  Save anyway? "
+;; 		(file-name-nondirectory buffer-file-name)))
+;; 	      (user-error "Save not confirmed"))
+;; 	  (save-restriction
+;; 	    (widen)
+;; 	    (save-excursion
+;; 	      (and (> (point-max) (point-min))
+;; 		   (not find-file-literally)
+;;                    (null buffer-read-only)
+;; 		   (/= (char-after (1- (point-max))) ?\n)
+;; 		   (not (and (eq selective-display t)
+;; 			     (= (char-after (1- (point-max))) ?\r)))
+;; 		   (or (eq require-final-newline t)
+;; 		       (eq require-final-newline 'visit-save)
+;; 		       (and require-final-newline
+;; 			    (y-or-n-p
+;; 			     (format "Buffer %s does not end in newline.  Add one? "
+;; 				     (buffer-name)))))
+;; 		   (save-excursion
+;; 		     (goto-char (point-max))
+;; 		     (insert ?\n))))
+;; 	    ;; Don't let errors prevent saving the buffer.
+;; 	    (with-demoted-errors "Before-save hook error: %S"
+;; 	      (run-hooks 'before-save-hook))
+;;             ;; Give `write-contents-functions' a chance to
+;;             ;; short-circuit the whole process.
+;; 	    (unless (run-hook-with-args-until-success 'write-contents-functions)
+;;               ;; If buffer has no file name, ask user for one.
+;;               (or buffer-file-name
+;;                   (let ((filename
+;;                          (expand-file-name
+;;                           (read-file-name "File to save in: "
+;;                                           nil (expand-file-name (buffer-name))))))
+;;                     (if (file-exists-p filename)
+;;                         (if (file-directory-p filename)
+;;                             ;; Signal an error if the user specified the name of an
+;;                             ;; existing directory.
+;;                             (error "%s is a directory" filename)
+;;                           (unless (y-or-n-p (format-message
+;;                                              "File `%s' exists; overwrite? "
+;;                                              filename))
+;;                             (error "Canceled"))))
+;;                     (set-visited-file-name filename)))
+;;               ;; Support VC version backups.
+;; 	      (vc-before-save)
+;; 	      (or (run-hook-with-args-until-success 'local-write-file-hooks)
+;; 	          (run-hook-with-args-until-success 'write-file-functions)
+;; 	          ;; If a hook returned t, file is already "written".
+;; 	          ;; Otherwise, write it the usual way now.
+;; 	          (let ((dir (file-name-directory
+;; 			      (expand-file-name buffer-file-name))))
+;; 		    (unless (file-exists-p dir)
+;; 		      (if (y-or-n-p
+;; 		           (format-message
+;;                             "Directory `%s' does not exist; create? " dir))
+;; 		          (make-directory dir t)
+;; 		        (error "Canceled")))
+;; 		    (setq setmodes (basic-save-buffer-1)))))
+;; 	    ;; Now we have saved the current buffer.  Let's make sure
+;; 	    ;; that buffer-file-coding-system is fixed to what
+;; 	    ;; actually used for saving by binding it locally.
+;;             (when buffer-file-name
+;; 	      (if save-buffer-coding-system
+;; 		  (setq save-buffer-coding-system last-coding-system-used)
+;; 	        (setq buffer-file-coding-system last-coding-system-used))
+;; 	      (setq buffer-file-number
+;; 		    (file-attribute-file-identifier
+;;                      (file-attributes buffer-file-name)))
+;; 	      (if setmodes
+;; 		  (condition-case ()
+;; 		      (progn
+;; 		        (unless
+;; 			    (with-demoted-errors "Error setting file modes: %S"
+;; 			        (set-file-modes buffer-file-name (car setmodes)))
+;; 			  (set-file-extended-attributes buffer-file-name
+;; 						        (nth 1 setmodes))))
+;; 		    (error nil)))
+;;               ;; Support VC `implicit' locking.
+;; 	      (vc-after-save))
+;;             ;; If the auto-save file was recent before this command,
+;; 	    ;; delete it now.
+;; 	    (delete-auto-save-file-if-necessary recent-save))
+;; 	  (run-hooks 'after-save-hook))
+;;       (or noninteractive
+;;           (not called-interactively)
+;;           (files--message "(No changes need to be saved)")))))
 
 ;; This does the "real job" of writing a buffer into its visited file
 ;; and making a backup file.  This is what is normally done
@@ -6925,58 +6931,60 @@ auto-save file, if that is more recent than the visited file."
       (user-error "This buffer is not visiting a file"))
   (recover-file buffer-file-name))
 
-(defun recover-file (file)
-  "Visit file FILE, but get contents from its last auto-save file."
-  ;; Actually putting the file name in the minibuffer should be used
-  ;; only rarely.
-  ;; Not just because users often use the default.
-  (interactive "FRecover file: ")
-  (setq file (expand-file-name file))
-  (if (auto-save-file-name-p (file-name-nondirectory file))
-      (user-error "%s is an auto-save file" (abbreviate-file-name file)))
-  (let ((file-name (let ((buffer-file-name file))
-		     (make-auto-save-file-name))))
-    (cond ((and (file-exists-p file)
-                (not (file-exists-p file-name)))
-           (error "Auto save file %s does not exist"
-                  (abbreviate-file-name file-name)))
-          ((if (file-exists-p file)
-	       (not (file-newer-than-file-p file-name file))
-	     (not (file-exists-p file-name)))
-	   (user-error "Auto-save file %s not current"
-                       (abbreviate-file-name file-name)))
-	  ((with-temp-buffer-window
-	    "*Directory*" nil
-	    #'(lambda (window _value)
-		(with-selected-window window
-		  (unwind-protect
-		      (yes-or-no-p (format "Recover auto save file %s? " file-name))
-		    (when (window-live-p window)
-		      (quit-restore-window window 'kill)))))
-	    (with-current-buffer standard-output
-	      (let ((switches dired-listing-switches))
-		(if (file-symlink-p file)
-		    (setq switches (concat switches " -L")))
-		;; Use insert-directory-safely, not insert-directory,
-		;; because these files might not exist.  In particular,
-		;; FILE might not exist if the auto-save file was for
-		;; a buffer that didn't visit a file, such as "*mail*".
-		;; The code in v20.x called `ls' directly, so we need
-		;; to emulate what `ls' did in that case.
-		(insert-directory-safely file switches)
-		(insert-directory-safely file-name switches))))
-	   (switch-to-buffer (find-file-noselect file t))
-	   (let ((inhibit-read-only t)
-		 ;; Keep the current buffer-file-coding-system.
-		 (coding-system buffer-file-coding-system)
-		 ;; Auto-saved file should be read with special coding.
-		 (coding-system-for-read 'auto-save-coding))
-	     (erase-buffer)
-	     (insert-file-contents file-name nil)
-	     (set-buffer-file-coding-system coding-system)
-             (set-buffer-auto-saved))
-	   (after-find-file nil nil t))
-	  (t (user-error "Recover-file canceled")))))
+;; RUNE-BOOTSTRAP - recover-file is defined in src/autosave.rs because
+;; insert-file-contents and the file-name prompts are not implemented yet.
+;; (defun recover-file (file)
+;;   "Visit file FILE, but get contents from its last auto-save file."
+;;   ;; Actually putting the file name in the minibuffer should be used
+;;   ;; only rarely.
+;;   ;; Not just because users often use the default.
+;;   (interactive "FRecover file: ")
+;;   (setq file (expand-file-name file))
+;;   (if (auto-save-file-name-p (file-name-nondirectory file))
+;;       (user-error "%s is an auto-save file" (abbreviate-file-name file)))
+;;   (let ((file-name (let ((buffer-file-name file))
+;; 		     (make-auto-save-file-name))))
+;;     (cond ((and (file-exists-p file)
+;;                 (not (file-exists-p file-name)))
+;;            (error "Auto save file %s does not exist"
+;;                   (abbreviate-file-name file-name)))
+;;           ((if (file-exists-p file)
+;; 	       (not (file-newer-than-file-p file-name file))
+;; 	     (not (file-exists-p file-name)))
+;; 	   (user-error "Auto-save file %s not current"
+;;                        (abbreviate-file-name file-name)))
+;; 	  ((with-temp-buffer-window
+;; 	    "*Directory*" nil
+;; 	    #'(lambda (window _value)
+;; 		(with-selected-window window
+;; 		  (unwind-protect
+;; 		      (yes-or-no-p (format "Recover auto save file %s? " file-name))
+;; 		    (when (window-live-p window)
+;; 		      (quit-restore-window window 'kill)))))
+;; 	    (with-current-buffer standard-output
+;; 	      (let ((switches dired-listing-switches))
+;; 		(if (file-symlink-p file)
+;; 		    (setq switches (concat switches " -L")))
+;; 		;; Use insert-directory-safely, not insert-directory,
+;; 		;; because these files might not exist.  In particular,
+;; 		;; FILE might not exist if the auto-save file was for
+;; 		;; a buffer that didn't visit a file, such as "*mail*".
+;; 		;; The code in v20.x called `ls' directly, so we need
+;; 		;; to emulate what `ls' did in that case.
+;; 		(insert-directory-safely file switches)
+;; 		(insert-directory-safely file-name switches))))
+;; 	   (switch-to-buffer (find-file-noselect file t))
+;; 	   (let ((inhibit-read-only t)
+;; 		 ;; Keep the current buffer-file-coding-system.
+;; 		 (coding-system buffer-file-coding-system)
+;; 		 ;; Auto-saved file should be read with special coding.
+;; 		 (coding-system-for-read 'auto-save-coding))
+;; 	     (erase-buffer)
+;; 	     (insert-file-contents file-name nil)
+;; 	     (set-buffer-file-coding-system coding-system)
+;;              (set-buffer-auto-saved))
+;; 	   (after-find-file nil nil t))
+;; 	  (t (user-error "Recover-file canceled")))))
 
 (defvar dired-mode-hook)
 
@@ -7146,67 +7154,70 @@ Also rename any existing auto save file, if it was made in this session."
 	     (recent-auto-save-p))
 	(rename-file osave buffer-auto-save-file-name t))))
 
-(defun make-auto-save-file-name ()
-  "Return file name to use for auto-saves of current buffer.
-Does not consider `auto-save-visited-file-name' as that variable is checked
-before calling this function.
-See also `auto-save-file-name-p'."
-  (if buffer-file-name
-      (let ((handler (find-file-name-handler
-                      buffer-file-name 'make-auto-save-file-name)))
-	(if handler
-	    (funcall handler 'make-auto-save-file-name)
-          (files--transform-file-name
-           buffer-file-name auto-save-file-name-transforms
-                                          "#" "#")))
-    ;; Deal with buffers that don't have any associated files.  (Mail
-    ;; mode tends to create a good number of these.)
-    (let ((buffer-name (buffer-name))
-	  (limit 0)
-	  file-name)
-      ;; Restrict the characters used in the file name to those that
-      ;; are known to be safe on all filesystems, url-encoding the
-      ;; rest.
-      ;; We do this on all platforms, because even if we are not
-      ;; running on DOS/Windows, the current directory may be on a
-      ;; mounted VFAT filesystem, such as a USB memory stick.
-      (while (string-match "[^A-Za-z0-9_.~#+-]" buffer-name limit)
-	(let* ((character (aref buffer-name (match-beginning 0)))
-	       (replacement
-                ;; For multibyte characters, this will produce more than
-                ;; 2 hex digits, so is not true URL encoding.
-                (format "%%%02X" character)))
-	  (setq buffer-name (replace-match replacement t t buffer-name))
-	  (setq limit (1+ (match-end 0)))))
-      ;; Generate the file name.
-      (setq file-name
-	    (make-temp-file
-	     (let ((fname
-		    (expand-file-name
-		     (format "#%s#" buffer-name)
-		     ;; Try a few alternative directories, to get one we can
-		     ;; write it.
-		     (cond
-		      ((file-writable-p default-directory) default-directory)
-		      ((file-writable-p "/var/tmp/") "/var/tmp/")
-		      ("~/")))))
-	       (if (and (memq system-type '(ms-dos windows-nt cygwin))
-			;; Don't modify remote filenames
-			(not (file-remote-p fname)))
-		   ;; The call to convert-standard-filename is in case
-		   ;; buffer-name includes characters not allowed by the
-		   ;; DOS/Windows filesystems.  make-temp-file writes to the
-		   ;; file it creates, so we must fix the file name _before_
-		   ;; make-temp-file is called.
-		   (convert-standard-filename fname)
-		 fname))
-	     nil "#"))
-      ;; make-temp-file creates the file,
-      ;; but we don't want it to exist until we do an auto-save.
-      (condition-case ()
-	  (delete-file file-name)
-	(file-error nil))
-      file-name)))
+;; RUNE-BOOTSTRAP - make-auto-save-file-name is defined in src/autosave.rs
+;; because find-file-name-handler, make-temp-file and rename-file are not
+;; implemented yet.
+;; (defun make-auto-save-file-name ()
+;;   "Return file name to use for auto-saves of current buffer.
+;; Does not consider `auto-save-visited-file-name' as that variable is checked
+;; before calling this function.
+;; See also `auto-save-file-name-p'."
+;;   (if buffer-file-name
+;;       (let ((handler (find-file-name-handler
+;;                       buffer-file-name 'make-auto-save-file-name)))
+;; 	(if handler
+;; 	    (funcall handler 'make-auto-save-file-name)
+;;           (files--transform-file-name
+;;            buffer-file-name auto-save-file-name-transforms
+;;                                           "#" "#")))
+;;     ;; Deal with buffers that don't have any associated files.  (Mail
+;;     ;; mode tends to create a good number of these.)
+;;     (let ((buffer-name (buffer-name))
+;; 	  (limit 0)
+;; 	  file-name)
+;;       ;; Restrict the characters used in the file name to those that
+;;       ;; are known to be safe on all filesystems, url-encoding the
+;;       ;; rest.
+;;       ;; We do this on all platforms, because even if we are not
+;;       ;; running on DOS/Windows, the current directory may be on a
+;;       ;; mounted VFAT filesystem, such as a USB memory stick.
+;;       (while (string-match "[^A-Za-z0-9_.~#+-]" buffer-name limit)
+;; 	(let* ((character (aref buffer-name (match-beginning 0)))
+;; 	       (replacement
+;;                 ;; For multibyte characters, this will produce more than
+;;                 ;; 2 hex digits, so is not true URL encoding.
+;;                 (format "%%%02X" character)))
+;; 	  (setq buffer-name (replace-match replacement t t buffer-name))
+;; 	  (setq limit (1+ (match-end 0)))))
+;;       ;; Generate the file name.
+;;       (setq file-name
+;; 	    (make-temp-file
+;; 	     (let ((fname
+;; 		    (expand-file-name
+;; 		     (format "#%s#" buffer-name)
+;; 		     ;; Try a few alternative directories, to get one we can
+;; 		     ;; write it.
+;; 		     (cond
+;; 		      ((file-writable-p default-directory) default-directory)
+;; 		      ((file-writable-p "/var/tmp/") "/var/tmp/")
+;; 		      ("~/")))))
+;; 	       (if (and (memq system-type '(ms-dos windows-nt cygwin))
+;; 			;; Don't modify remote filenames
+;; 			(not (file-remote-p fname)))
+;; 		   ;; The call to convert-standard-filename is in case
+;; 		   ;; buffer-name includes characters not allowed by the
+;; 		   ;; DOS/Windows filesystems.  make-temp-file writes to the
+;; 		   ;; file it creates, so we must fix the file name _before_
+;; 		   ;; make-temp-file is called.
+;; 		   (convert-standard-filename fname)
+;; 		 fname))
+;; 	     nil "#"))
+;;       ;; make-temp-file creates the file,
+;;       ;; but we don't want it to exist until we do an auto-save.
+;;       (condition-case ()
+;; 	  (delete-file file-name)
+;; 	(file-error nil))
+;;       file-name)))
 
 (defun files--transform-file-name (filename transforms prefix suffix)
   "Transform FILENAME according to TRANSFORMS.
@@ -792,27 +792,29 @@ of course, also replace TO with a slightly larger value
                 next (+ from (* n inc)))))
       (nreverse seq))))
 
-(defun copy-tree (tree &optional vecp)
-  "Make a copy of TREE.
-If TREE is a cons cell, this recursively copies both its car and its cdr.
-Contrast to `copy-sequence', which copies only along the cdrs.  With second
-argument VECP, this copies vectors as well as conses."
-  (if (consp tree)
-      (let (result)
-	(while (consp tree)
-	  (let ((newcar (car tree)))
-	    (if (or (consp (car tree)) (and vecp (vectorp (car tree))))
-		(setq newcar (copy-tree (car tree) vecp)))
-	    (push newcar result))
-	  (setq tree (cdr tree)))
-	(nconc (nreverse result)
-               (if (and vecp (vectorp tree)) (copy-tree tree vecp) tree)))
-    (if (and vecp (vectorp tree))
-	(let ((i (length (setq tree (copy-sequence tree)))))
-	  (while (>= (setq i (1- i)) 0)
-	    (aset tree i (copy-tree (aref tree i) vecp)))
-	  tree)
-      tree)))
+;; RUNE-BOOTSTRAP - copy-tree is defined in Rust so that it can copy
+;; circular structure
+;; (defun copy-tree (tree &optional vecp)
+;;   "Make a copy of TREE.
+;; If TREE is a cons cell, this recursively copies both its car and its cdr.
+;; Contrast to `copy-sequence', which copies only along the cdrs.  With second
+;; argument VECP, this copies vectors as well as conses."
+;;   (if (consp tree)
+;;       (let (result)
+;; 	(while (consp tree)
+;; 	  (let ((newcar (car tree)))
+;; 	    (if (or (consp (car tree)) (and vecp (vectorp (car tree))))
+;; 		(setq newcar (copy-tree (car tree) vecp)))
+;; 	    (push newcar result))
+;; 	  (setq tree (cdr tree)))
+;; 	(nconc (nreverse result)
+;;                (if (and vecp (vectorp tree)) (copy-tree tree vecp) tree)))
+;;     (if (and vecp (vectorp tree))
+;; 	(let ((i (length (setq tree (copy-sequence tree)))))
+;; 	  (while (>= (setq i (1- i)) 0)
+;; 	    (aset tree i (copy-tree (aref tree i) vecp)))
+;; 	  tree)
+;;       tree)))
 
 ;;;; Various list-search functions.
 
@@ -4227,7 +4229,91 @@ if it's an autoloaded macro."
             nil                         ;Re-try `get' on the same `f'.
           (setq f fundef))))
     val))
-
+
+;; RUNE-BOOTSTRAP - cl-macs is not loaded, so define
+;; `cl-define-compiler-macro' here.  `cl--transform-lambda' is not available
+;; either, so the cl lambda list is bound by `cl--compiler-macro-bindings'.
+(defun cl--compiler-macro-bindings (args list)
+  "Return the `let*' bindings that bind the cl lambda list ARGS to LIST.
+LIST is a symbol bound to the arguments.  The bindings are returned in
+reverse order.  ARGS can use `&optional', `&rest', `&body', `&key',
+`&allow-other-keys' and `&aux', with default values and SVARs, a dotted
+tail for the rest of the arguments, and a lambda list in place of any
+variable to destructure the argument."
+  (let ((bindings nil)
+        (mode nil))
+    (while args
+      (let ((spec (if (consp args) (pop args)
+                    ;; A dotted tail binds the rest of the arguments
+                    (prog1 args (setq args nil mode '&rest)))))
+        (cond
+         ((memq spec '(&optional &rest &body &key &aux))
+          (setq mode spec))
+         ((eq spec '&allow-other-keys))
+         ((memq mode '(&rest &body))
+          (setq bindings (cl--compiler-macro-bind spec list bindings)))
+         ((eq mode '&aux)
+          (push (list (if (consp spec) (car spec) spec)
+                      (car-safe (cdr-safe spec)))
+                bindings))
+         ((eq mode '&key)
+          (let* ((var (if (consp spec) (car spec) spec))
+                 (keyword (if (consp var) (car var)
+                            (intern (format ":%s" var))))
+                 (var (if (consp var) (cadr var) var))
+                 (found (make-symbol "found")))
+            (push (list found `(plist-member ,list ',keyword)) bindings)
+            (when (cdr-safe (cdr-safe spec))
+              (push (list (nth 2 spec) `(and ,found t)) bindings))
+            (setq bindings
+                  (cl--compiler-macro-bind
+                   var `(if ,found (cadr ,found) ,(car-safe (cdr-safe spec)))
+                   bindings))))
+         ((eq mode '&optional)
+          (let ((var (if (consp spec) (car spec) spec)))
+            (when (cdr-safe (cdr-safe spec))
+              (push (list (nth 2 spec) `(and ,list t)) bindings))
+            (setq bindings
+                  (cl--compiler-macro-bind
+                   var `(if ,list (car ,list) ,(car-safe (cdr-safe spec)))
+                   bindings))
+            (push (list list `(cdr ,list)) bindings)))
+         (t
+          (setq bindings (cl--compiler-macro-bind spec `(car ,list) bindings))
+          (push (list list `(cdr ,list)) bindings)))))
+    bindings))
+
+(defun cl--compiler-macro-bind (var value bindings)
+  "Add the binding of VAR to VALUE to the front of BINDINGS.
+If VAR is a lambda list, bind it to the elements of VALUE instead."
+  (if (not (consp var))
+      (cons (list var value) bindings)
+    (let ((list (make-symbol "list")))
+      (append (cl--compiler-macro-bindings var list)
+              (cons (list list value) bindings)))))
+
+(defmacro cl-define-compiler-macro (func args &rest body)
+  "Define a compiler-only macro for FUNC.
+When the compiler reaches a call to FUNC, it calls this macro with the
+arguments of the call bound to ARGS, which is a cl lambda list, and
+compiles the form BODY returns instead.  If ARGS starts with `&whole
+FORM', FORM is bound to the whole call, and returning FORM unchanged
+compiles the call as usual."
+  (declare (debug cl-defmacro) (indent 2) (doc-string 3))
+  (let ((whole '_cl-whole-arg)
+        ;; Interned names, since the compiler mishandles uninterned ones
+        ;; in top level definitions
+        (fname (intern (concat (symbol-name func) "--cmacro"))))
+    (when (eq (car-safe args) '&whole)
+      (setq whole (cadr args))
+      (setq args (cddr args)))
+    `(eval-and-compile
+       (defalias ',fname
+         #'(lambda (,whole &rest --cl-rest--)
+             (let* ,(nreverse (cl--compiler-macro-bindings args '--cl-rest--))
+               ,@body)))
+       (define-symbol-prop ',func 'compiler-macro #',fname))))
+
 ;;;; Support for yanking and text properties.
 ;; Why here in subr.el rather than in simple.el?  --Stef
 
@@ -4806,17 +4892,29 @@ of that nature."
                    (eq ,modified 'autosaved))
            (restore-buffer-modified-p ,modified))))))
 
+;; RUNE-BOOTSTRAP - collect the output in a string output stream instead of a
+;; buffer
+;; (defmacro with-output-to-string (&rest body)
+;;   "Execute BODY, return the text it sent to `standard-output', as a string."
+;;   (declare (indent 0) (debug t))
+;;   `(let ((standard-output (generate-new-buffer " *string-output*" t)))
+;;      (unwind-protect
+;; 	 (progn
+;; 	   (let ((standard-output standard-output))
+;; 	     ,@body)
+;; 	   (with-current-buffer standard-output
+;; 	     (buffer-string)))
+;;        (kill-buffer standard-output))))
 (defmacro with-output-to-string (&rest body)
   "Execute BODY, return the text it sent to `standard-output', as a string."
   (declare (indent 0) (debug t))
-  `(let ((standard-output (generate-new-buffer " *string-output*" t)))
+  `(let ((standard-output (internal--make-string-output-stream)))
      (unwind-protect
 	 (progn
 	   (let ((standard-output standard-output))
 	     ,@body)
-	   (with-current-buffer standard-output
-	     (buffer-string)))
-       (kill-buffer standard-output))))
+	   (internal--string-output-stream-contents standard-output))
+       (internal--close-string-output-stream standard-output))))
 
 (defmacro with-local-quit (&rest body)
   "Execute BODY, allowing quits to terminate BODY but not escape further.
@@ -0,0 +1,363 @@
+//! Abbrev tables.
+//!
+//! An abbrev table is an obarray. Each abbrev is a symbol in it whose value is
+//! the expansion, whose function is the hook to run after expanding it, and
+//! whose property list holds its properties such as `:count`. The properties
+//! of the table itself are kept on the symbol with the empty name.
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env},
+    error::{Type, TypeError},
+    gc::{Context, Rt},
+    object::{Object, ObjectType, Symbol, NIL},
+};
+use crate::data::get;
+use crate::obarray::Obarray;
+use anyhow::{bail, Result};
+use rune_core::macros::{call, root};
+use rune_macros::defun;
+
+/// The symbol holding the properties of `table`.
+fn properties<'ob>(table: Obarray<'ob>, cx: &'ob Context) -> Symbol<'ob> {
+    table.intern("", cx)
+}
+
+/// Set the properties of `table` from the plist `props`.
+fn put_properties(table: Obarray, props: &[Object], env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let symbol = properties(table, cx);
+    for pair in props.chunks(2) {
+        let [prop, value] = pair else {
+            bail!("Missing value for abbrev table property {}", pair[0])
+        };
+        env.set_prop(symbol, (*prop).try_into()?, *value);
+    }
+    Ok(())
+}
+
+fn new_table<'ob>(props: &[Object], env: &mut Rt<Env>, cx: &'ob Context) -> Result<Obarray<'ob>> {
+    let table = Obarray::new(cx);
+    env.set_prop(properties(table, cx), sym::KW_ABBREV_TABLE_MODIFF, cx.add(0));
+    put_properties(table, props, env, cx)?;
+    Ok(table)
+}
+
+fn as_abbrev_table<'ob>(
+    object: Object<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Option<Obarray<'ob>> {
+    let table = Obarray::try_from(object).ok()?;
+    let modiff = abbrev_table_get(table, sym::KW_ABBREV_TABLE_MODIFF, env, cx);
+    matches!(modiff.untag(), ObjectType::Int(_)).then_some(table)
+}
+
+#[defun]
+fn make_abbrev_table<'ob>(
+    props: &[Object<'ob>],
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    Ok(new_table(props, env, cx)?.into())
+}
+
+#[defun]
+fn abbrev_table_p(object: Object, env: &Rt<Env>, cx: &Context) -> bool {
+    as_abbrev_table(object, env, cx).is_some()
+}
+
+#[defun]
+fn abbrev_table_get<'ob>(
+    table: Obarray<'ob>,
+    prop: Symbol,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    table.get("", cx).map_or(NIL, |symbol| get(symbol, prop, env, cx))
+}
+
+#[defun]
+fn abbrev_table_put<'ob>(
+    table: Obarray,
+    prop: Symbol,
+    val: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Object<'ob> {
+    env.set_prop(properties(table, cx), prop, val);
+    val
+}
+
+#[defun]
+fn define_abbrev_table(
+    tablename: Symbol,
+    definitions: Object,
+    docstring: Option<Object>,
+    props: &[Object],
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let mut props = props.to_vec();
+    match docstring.map(|x| x.untag()) {
+        Some(ObjectType::String(_)) => {
+            env.set_prop(tablename, sym::VARIABLE_DOCUMENTATION, docstring.unwrap());
+        }
+        // The docstring can be left out when there are properties
+        Some(ObjectType::Symbol(prop)) if prop != sym::NIL => props.insert(0, prop.into()),
+        _ => {}
+    }
+    let existing = env.vars.get(tablename).map(|x| x.bind(cx));
+    let table = match existing.and_then(|x| as_abbrev_table(x, env, cx)) {
+        Some(table) => {
+            put_properties(table, &props, env, cx)?;
+            table
+        }
+        None => {
+            let table = new_table(&props, env, cx)?;
+            env.set_default(tablename, table.into())?;
+            let names = env.vars.get(sym::ABBREV_TABLE_NAME_LIST).map_or(NIL, |x| x.bind(cx));
+            env.set_default(sym::ABBREV_TABLE_NAME_LIST, Cons::new(tablename, names, cx).into())?;
+            table
+        }
+    };
+    for definition in definitions.as_list()? {
+        let definition = definition?.as_list()?.collect::<Result<Vec<_>, _>>()?;
+        let [name, expansion, rest @ ..] = &definition[..] else {
+            bail!("Invalid abbrev definition: {}", definitions)
+        };
+        let hook = rest.first().copied();
+        let props = rest.get(1..).unwrap_or_default();
+        define_abbrev(table, (*name).try_into()?, *expansion, hook, props, env, cx)?;
+    }
+    Ok(())
+}
+
+#[defun]
+fn define_abbrev<'ob>(
+    table: Obarray,
+    abbrev: &str,
+    expansion: Object,
+    hook: Option<Object>,
+    props: &[Object],
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let symbol = table.intern(abbrev, cx);
+    env.set_default(symbol, expansion)?;
+    crate::data::fset(symbol, hook.unwrap_or_default())?;
+    env.set_prop(symbol, sym::KW_COUNT, cx.add(0));
+    match props {
+        // The old calling convention was COUNT and SYSTEM-FLAG
+        [count, rest @ ..] if matches!(count.untag(), ObjectType::Int(_)) => {
+            env.set_prop(symbol, sym::KW_COUNT, *count);
+            if let Some(system) = rest.first().filter(|x| !x.is_nil()) {
+                env.set_prop(symbol, sym::KW_SYSTEM, *system);
+            }
+        }
+        _ => {
+            for pair in props.chunks(2) {
+                let [prop, value] = pair else { bail!("Missing value for abbrev property") };
+                env.set_prop(symbol, (*prop).try_into()?, *value);
+            }
+        }
+    }
+    let table_props = properties(table, cx);
+    let modiff = match get(table_props, sym::KW_ABBREV_TABLE_MODIFF, env, cx).untag() {
+        ObjectType::Int(x) => x + 1,
+        _ => 1,
+    };
+    env.set_prop(table_props, sym::KW_ABBREV_TABLE_MODIFF, cx.add(modiff));
+    Ok(cx.add(abbrev))
+}
+
+/// The abbrev named `name` in `table`, if it has an expansion. Unless the
+/// table is `:case-fixed`, abbrevs are looked up in lower case.
+fn abbrev_symbol<'ob>(
+    name: &str,
+    table: Obarray<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Option<Symbol<'ob>> {
+    let symbol = match abbrev_table_get(table, sym::KW_CASE_FIXED, env, cx).is_nil() {
+        true => table.get(&name.to_lowercase(), cx)?,
+        false => table.get(name, cx)?,
+    };
+    let value = env.vars.get(symbol)?.bind(cx);
+    (!symbol.name().is_empty() && value != NIL).then_some(symbol)
+}
+
+/// The tables abbrevs are looked up in: those of `local-abbrev-table`, which
+/// can be a table or a list of them, followed by `global-abbrev-table`.
+fn active_tables<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Vec<Obarray<'ob>> {
+    let mut tables = Vec::new();
+    let var = |name| env.var(name, cx).map_or(NIL, |x| x.bind(cx));
+    let local = var(sym::LOCAL_ABBREV_TABLE);
+    match local.as_list() {
+        Ok(list) => tables.extend(list.flatten().filter_map(|x| as_abbrev_table(x, env, cx))),
+        Err(_) => tables.extend(as_abbrev_table(local, env, cx)),
+    }
+    tables.extend(as_abbrev_table(var(sym::GLOBAL_ABBREV_TABLE), env, cx));
+    tables
+}
+
+#[defun]
+fn abbrev_expansion<'ob>(
+    abbrev: &str,
+    table: Option<Obarray<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let tables = match table {
+        Some(table) => vec![table],
+        None => active_tables(env, cx),
+    };
+    match tables.into_iter().find_map(|table| abbrev_symbol(abbrev, table, env, cx)) {
+        Some(symbol) => env.vars.get(symbol).map_or(NIL, |x| x.bind(cx)),
+        None => NIL,
+    }
+}
+
+/// Change the case of `expansion` to match how its abbrev was typed. An
+/// abbrev typed in all caps is expanded in all caps, or with each word
+/// capitalized if the expansion has several words and `all_caps` is false.
+/// Otherwise only the first letter is capitalized.
+fn adapt_case(typed: &str, expansion: &str, all_caps: bool) -> String {
+    if typed.chars().any(char::is_lowercase) {
+        let mut capitalized = false;
+        return expansion
+            .chars()
+            .flat_map(|chr| match !capitalized && chr.is_alphabetic() {
+                true => {
+                    capitalized = true;
+                    chr.to_uppercase().collect::<Vec<_>>()
+                }
+                false => vec![chr],
+            })
+            .collect();
+    }
+    if all_caps || expansion.split_whitespace().nth(1).is_none() {
+        return expansion.to_uppercase();
+    }
+    let mut in_word = false;
+    expansion
+        .chars()
+        .flat_map(|chr| {
+            let initial = !in_word && chr.is_alphanumeric();
+            in_word = chr.is_alphanumeric();
+            match initial {
+                true => chr.to_uppercase().collect::<Vec<_>>(),
+                false => vec![chr],
+            }
+        })
+        .collect()
+}
+
+/// Expand the abbrev before point. Returns the abbrev symbol, or nil if there
+/// was no abbrev to expand.
+#[defun]
+fn expand_abbrev<'ob>(env: &mut Rt<Env>, cx: &'ob mut Context) -> Result<Object<'ob>> {
+    let Some(buffer) = env.current_buffer.as_ref() else { return Ok(NIL) };
+    let end = buffer.text.cursor().chars();
+    let mut start = end;
+    while start > 0 && buffer.text.char_at(start - 1).is_some_and(char::is_alphanumeric) {
+        start -= 1;
+    }
+    let typed: String = (start..end).filter_map(|i| buffer.text.char_at(i)).collect();
+    if typed.is_empty() {
+        return Ok(NIL);
+    }
+    let tables = active_tables(env, cx);
+    let Some(symbol) = tables.into_iter().find_map(|table| abbrev_symbol(&typed, table, env, cx))
+    else {
+        return Ok(NIL);
+    };
+    let expansion = match env.vars.get(symbol).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::String(string)) => string.to_string(),
+        Some(x) => bail!(TypeError::new(Type::String, x)),
+        None => return Ok(NIL),
+    };
+    let expansion = match symbol.name() == typed {
+        true => expansion,
+        false => {
+            let all_caps = env.var(sym::ABBREV_ALL_CAPS, cx).is_some_and(|x| x.bind(cx) != NIL);
+            adapt_case(&typed, &expansion, all_caps)
+        }
+    };
+    env.current_buffer.as_mut().unwrap().replace_region(start, end, &expansion);
+
+    let count = match get(symbol, sym::KW_COUNT, env, cx).untag() {
+        ObjectType::Int(x) => x + 1,
+        _ => 1,
+    };
+    env.set_prop(symbol, sym::KW_COUNT, cx.add(count));
+    env.set_default(sym::LAST_ABBREV, symbol.into())?;
+    env.set_default(sym::LAST_ABBREV_TEXT, cx.add(typed))?;
+    env.set_default(sym::LAST_ABBREV_LOCATION, cx.add(start as i64 + 1))?;
+    let hook = symbol.func(cx);
+    root!(symbol, cx);
+    if let Some(hook) = hook {
+        root!(hook, cx);
+        call!(hook; env, cx)?;
+    }
+    Ok(symbol.bind(cx).into())
+}
+
+/// Create `global-abbrev-table` and make `local-abbrev-table` buffer-local.
+pub(crate) fn init_abbrevs(env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    sym::LOCAL_ABBREV_TABLE.make_buffer_local();
+    let table = new_table(&[], env, cx)?;
+    env.set_default(sym::GLOBAL_ABBREV_TABLE, table.into())?;
+    let names = Cons::new(sym::GLOBAL_ABBREV_TABLE, NIL, cx);
+    env.set_default(sym::ABBREV_TABLE_NAME_LIST, names.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::{get_buffer_create, set_buffer};
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_adapt_case() {
+        assert_eq!(adapt_case("Foo", "foo bar", false), "Foo bar");
+        assert_eq!(adapt_case("FOO", "foo bar", false), "Foo Bar");
+        assert_eq!(adapt_case("FOO", "foo bar", true), "FOO BAR");
+        assert_eq!(adapt_case("FOO", "foobar", false), "FOOBAR");
+    }
+
+    #[test]
+    fn test_expand_abbrev() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        init_abbrevs(env, cx).unwrap();
+        let buffer = get_buffer_create(cx.add("test_expand_abbrev"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        let table = env.vars.get(sym::GLOBAL_ABBREV_TABLE).unwrap().bind(cx);
+        let table = Obarray::try_from(table).unwrap();
+        define_abbrev(table, "foo", cx.add("find outer otter"), None, &[], env, cx).unwrap();
+        assert_eq!(abbrev_expansion("foo", None, env, cx), cx.add("find outer otter"));
+        assert_eq!(abbrev_expansion("bar", None, env, cx), NIL);
+
+        env.current_buffer.as_mut().unwrap().insert(cx.add("say Foo")).unwrap();
+        assert_ne!(expand_abbrev(env, cx).unwrap(), NIL);
+        let buffer = env.current_buffer.as_ref().unwrap();
+        assert_eq!(buffer, "say Find outer otter");
+        assert_eq!(buffer.text.cursor().chars(), 20);
+        let symbol = table.get("foo", cx).unwrap();
+        assert_eq!(get(symbol, sym::KW_COUNT, env, cx), cx.add(1));
+        assert_eq!(expand_abbrev(env, cx).unwrap(), NIL);
+    }
+}
+
+defsym!(KW_ABBREV_TABLE_MODIFF);
+defsym!(KW_CASE_FIXED);
+defsym!(KW_COUNT);
+defsym!(KW_SYSTEM);
+defvar!(ABBREV_TABLE_NAME_LIST);
+defvar!(GLOBAL_ABBREV_TABLE);
+defvar!(LOCAL_ABBREV_TABLE);
+defvar!(LAST_ABBREV);
+defvar!(LAST_ABBREV_TEXT);
+defvar!(LAST_ABBREV_LOCATION, 0);
+defvar_bool!(ABBREV_ALL_CAPS, false);
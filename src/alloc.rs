@@ -1,11 +1,16 @@
 //! builtin lisp data structures.
 use crate::core::cons::Cons;
-use crate::core::gc::Context;
+use crate::core::env::{intern, interned_symbols, namespace, sym, Env};
+use crate::core::gc::{Context, MemoryReport, ObjectUsage, Rt};
 use crate::core::object::{
-    ByteFn, ByteString, FnArgs, Gc, IntoObject, LispVec, Object, RecordBuilder, Symbol, NIL,
+    address, Ancestors, ByteFn, ByteString, FnArgs, Gc, HashTable, IntoObject, LispHashTable,
+    LispVec, Object, ObjectType, RecordBuilder, Symbol, NIL,
 };
-use anyhow::{ensure, Result};
+use anyhow::{bail, ensure, Result};
+use rune_core::macros::list;
 use rune_macros::defun;
+use std::cell::Cell;
+use std::hash::{DefaultHasher, Hash, Hasher};
 
 #[defun]
 pub(crate) fn list<'ob>(objects: &[Object<'ob>], cx: &'ob Context) -> Object<'ob> {
@@ -43,7 +48,7 @@ pub(crate) fn make_closure<'ob>(
 
 #[defun]
 #[allow(clippy::too_many_arguments)]
-pub(crate) fn make_byte_code<'ob>(
+fn make_byte_code<'ob>(
     arglist: u64,
     byte_code: &'ob ByteString,
     constants: &'ob LispVec,
@@ -51,15 +56,201 @@ pub(crate) fn make_byte_code<'ob>(
     _docstring: Option<Object>,
     _interactive_spec: Option<Object>,
     _elements: &[Object],
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob ByteFn> {
+    let pool = constant_pool(env, cx)?;
+    let constants = constants.iter().map(|x| share_constant(x.get(), pool, cx)).collect();
+    new_byte_code(arglist, byte_code, constants, depth, cx)
+}
+
+/// Create a byte-code function, using `constants` as is.
+pub(crate) fn new_byte_code<'ob>(
+    arglist: u64,
+    byte_code: &[u8],
+    constants: Vec<Object<'ob>>,
+    depth: usize,
     cx: &'ob Context,
 ) -> Result<&'ob ByteFn> {
     unsafe {
-        let bytefn =
-            ByteFn::make(byte_code, constants.to_vec(), FnArgs::from_arg_spec(arglist)?, depth);
+        let bytefn = ByteFn::make(byte_code, constants, FnArgs::from_arg_spec(arglist)?, depth);
         Ok(bytefn.into_obj(cx).untag())
     }
 }
 
+/// Constants made of more objects than this are not shared, to bound the
+/// cost of hashing and comparing them.
+const MAX_SHARED_SIZE: usize = 256;
+
+/// The pool is emptied when it holds this many hashes, so that it doesn't
+/// keep every constant ever loaded alive.
+const MAX_POOL_LEN: usize = 4096;
+
+thread_local! {
+    static SHARED_CONSTANTS: Cell<ObjectUsage> = Cell::default();
+}
+
+/// The constants that [`make_byte_code`] replaced with an identical constant
+/// of an earlier function, and the heap they would have used.
+pub(crate) fn shared_constants() -> ObjectUsage {
+    SHARED_CONSTANTS.get()
+}
+
+/// The pool of byte-code constants, keyed by a hash of their structure. Each
+/// entry is a list of the constants with that hash.
+fn constant_pool<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<&'ob LispHashTable> {
+    let pool = match env.vars.get(sym::INTERNAL__BYTE_CODE_CONSTANTS).map(|x| x.bind(cx)) {
+        Some(pool) if !pool.is_nil() => pool,
+        _ => {
+            let pool = cx.add(HashTable::default());
+            env.set_default(sym::INTERNAL__BYTE_CODE_CONSTANTS, pool)?;
+            pool
+        }
+    };
+    match pool.untag() {
+        ObjectType::HashTable(pool) => Ok(pool),
+        _ => bail!("`internal--byte-code-constants' is not a hash table"),
+    }
+}
+
+/// Whether `obj` can be shared between functions. These are strings, floats,
+/// and lists and vectors of them. Symbols and fixnums are already unique, and
+/// other objects have an identity that matters. Circular constants and those
+/// made of more than [`MAX_SHARED_SIZE`] objects are not shared.
+fn shareable(obj: Object) -> bool {
+    fn walk(obj: Object, path: &mut Ancestors<()>, size: &mut usize) -> bool {
+        *size += 1;
+        if *size > MAX_SHARED_SIZE {
+            return false;
+        }
+        match obj.untag() {
+            ObjectType::Int(_)
+            | ObjectType::Symbol(_)
+            | ObjectType::String(_)
+            | ObjectType::ByteString(_)
+            | ObjectType::Float(_) => true,
+            ObjectType::Vec(vec) => {
+                if !path.enter(address(vec), ()) {
+                    return false;
+                }
+                let shareable = vec.iter().all(|x| walk(x.get(), path, size));
+                path.leave(address(vec));
+                shareable
+            }
+            ObjectType::Cons(cons) => {
+                if !path.enter(address(cons), ()) {
+                    return false;
+                }
+                let shareable = walk(cons.car(), path, size) && walk(cons.cdr(), path, size);
+                path.leave(address(cons));
+                shareable
+            }
+            _ => false,
+        }
+    }
+    let compound = !matches!(obj.untag(), ObjectType::Int(_) | ObjectType::Symbol(_));
+    compound && walk(obj, &mut Ancestors::default(), &mut 0)
+}
+
+/// Hash a shareable constant by its structure. Floats are hashed by their
+/// bits, which keeps `0.0` and `-0.0` apart.
+fn hash_constant(obj: Object, state: &mut DefaultHasher) {
+    std::mem::discriminant(&obj.untag()).hash(state);
+    match obj.untag() {
+        ObjectType::Int(x) => x.hash(state),
+        ObjectType::Symbol(x) => x.hash(state),
+        ObjectType::String(x) => str::hash(x, state),
+        ObjectType::ByteString(x) => <[u8]>::hash(x, state),
+        ObjectType::Float(x) => x.to_bits().hash(state),
+        ObjectType::Vec(vec) => {
+            vec.len().hash(state);
+            vec.iter().for_each(|x| hash_constant(x.get(), state));
+        }
+        ObjectType::Cons(cons) => {
+            hash_constant(cons.car(), state);
+            hash_constant(cons.cdr(), state);
+        }
+        _ => {}
+    }
+}
+
+/// Whether two shareable constants have the same structure and contents.
+fn identical(a: Object, b: Object) -> bool {
+    match (a.untag(), b.untag()) {
+        (ObjectType::String(a), ObjectType::String(b)) => a == b,
+        (ObjectType::ByteString(a), ObjectType::ByteString(b)) => **a == **b,
+        (ObjectType::Float(a), ObjectType::Float(b)) => a.to_bits() == b.to_bits(),
+        (ObjectType::Vec(a), ObjectType::Vec(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| identical(a.get(), b.get()))
+        }
+        (ObjectType::Cons(a), ObjectType::Cons(b)) => {
+            identical(a.car(), b.car()) && identical(a.cdr(), b.cdr())
+        }
+        _ => a.ptr_eq(b),
+    }
+}
+
+/// The heap used by `obj`, not counting symbols.
+fn constant_size(obj: Object, report: &mut MemoryReport) {
+    match obj.untag() {
+        ObjectType::Symbol(_) => return,
+        ObjectType::Cons(cons) => {
+            constant_size(cons.car(), report);
+            constant_size(cons.cdr(), report);
+        }
+        ObjectType::Vec(vec) => vec.iter().for_each(|x| constant_size(x.get(), report)),
+        _ => {}
+    }
+    report.record(obj);
+}
+
+/// Return a constant from `pool` that is identical to `obj`, adding `obj` to
+/// the pool if there is none.
+fn share_constant<'ob>(
+    obj: Object<'ob>,
+    pool: &'ob LispHashTable,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    if !shareable(obj) {
+        return obj;
+    }
+    let mut hasher = DefaultHasher::new();
+    hash_constant(obj, &mut hasher);
+    // Keep the key within fixnum range
+    let key: Object = cx.add(hasher.finish() as i64 >> 8);
+    let entries = pool.get(key).unwrap_or(NIL);
+    for entry in entries.as_list().into_iter().flatten().flatten() {
+        if identical(entry, obj) {
+            let mut report = MemoryReport::default();
+            constant_size(obj, &mut report);
+            let saved = report.total();
+            SHARED_CONSTANTS.set(ObjectUsage {
+                count: shared_constants().count + 1,
+                bytes: shared_constants().bytes + saved.bytes,
+            });
+            return entry;
+        }
+    }
+    let entries = match pool.len() >= MAX_POOL_LEN {
+        true => {
+            pool.clear();
+            NIL
+        }
+        false => entries,
+    };
+    pool.insert(key, Cons::new(obj, entries, cx).into());
+    obj
+}
+
+/// Return the number of byte-code constants that were shared with an
+/// identical constant of an earlier function, and the bytes this saved, as
+/// `(COUNT . BYTES)`.
+#[defun]
+fn byte_code_shared_constants<'ob>(cx: &'ob Context) -> Object<'ob> {
+    let shared = shared_constants();
+    Cons::new(shared.count, shared.bytes, cx).into()
+}
+
 #[defun]
 fn make_vector(length: usize, init: Object) -> Vec<Object> {
     vec![init; length]
@@ -89,16 +280,134 @@ fn make_symbol<'ob>(name: &str, cx: &'ob Context) -> Symbol<'ob> {
 }
 
 #[defun]
-fn garbage_collect(cx: &mut Context) -> bool {
+fn garbage_collect(env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    sync_gc_policy(env, cx)?;
+    let purge = env.vars.get(sym::GC_PURGE_SYMBOLS).is_some_and(|x| !x.bind(cx).is_nil());
+    if purge {
+        purge_symbols(env, cx);
+    } else {
+        cx.garbage_collect(true);
+    }
+    sync_gc_policy(env, cx)?;
+    Ok(true)
+}
+
+/// Collect, and then remove the interned symbols that nothing refers to from
+/// the intern table. Symbols can only be purged when this thread has the only
+/// context, because the heaps of other threads aren't traced, and when there
+/// are no snapshots, which hold every symbol.
+fn purge_symbols(env: &Rt<Env>, cx: &mut Context) {
+    if !cx.is_only_context() || namespace::in_namespace() || env.has_snapshots() {
+        cx.garbage_collect(true);
+        return;
+    }
+    let map = interned_symbols();
+    map.clear_reached();
     cx.garbage_collect(true);
-    true
+    let features = crate::data::with_features(|x| x.clone());
+    // SAFETY: The collection traced the only heap, and the features are the
+    // other place that holds symbols.
+    unsafe {
+        map.purge(|symbol| features.iter().any(|x| *x == symbol));
+    }
+}
+
+/// Return the live heap usage as an alist of `(TYPE COUNT BYTES)` entries,
+/// followed by a `total` entry. This runs a full garbage collection. The
+/// intern table is not part of the heap, so it is reported after the total,
+/// as `interned-symbols`, and the symbols removed from it by
+/// `gc-purge-symbols` as `purged-symbols`.
+#[defun]
+fn memory_report<'ob>(cx: &'ob mut Context) -> Object<'ob> {
+    let report = cx.memory_report();
+    let map = interned_symbols();
+    let entries = report.categories().into_iter().chain([
+        ("total", report.total()),
+        ("interned-symbols", map.usage()),
+        ("purged-symbols", map.purged()),
+    ]);
+    let entries: Vec<Object> = entries
+        .map(|(name, usage)| list![intern(name, cx), usage.count, usage.bytes; cx])
+        .collect();
+    crate::fns::slice_into_list(&entries, None, cx)
 }
 
+/// Run a collection if more than 1/`factor` of the allocation needed to
+/// trigger an automatic collection has happened since the last one.
+#[defun]
+fn garbage_collect_maybe(factor: usize, env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    ensure!(factor > 0, "garbage-collect-maybe factor must be positive");
+    sync_gc_policy(env, cx)?;
+    if cx.bytes_since_gc() < cx.gc_allowance() / factor {
+        return Ok(false);
+    }
+    cx.garbage_collect(true);
+    sync_gc_policy(env, cx)?;
+    Ok(true)
+}
+
+/// Called when the REPL is waiting for input. Collects according to
+/// `gc-idle-factor` so that garbage left over from the last command is cleaned
+/// up while nothing else is happening.
+pub(crate) fn idle_garbage_collect(env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    let factor = match env.vars.get(sym::GC_IDLE_FACTOR) {
+        Some(factor) => factor.bind(cx),
+        None => NIL,
+    };
+    if factor.is_nil() {
+        return Ok(false);
+    }
+    garbage_collect_maybe(factor.try_into()?, env, cx)
+}
+
+/// Bring the collector in sync with lisp. The policy is read from
+/// `gc-cons-threshold` and `gc-cons-percentage`, and the counters are written
+/// back to `gcs-done` and `gc-elapsed`. This runs before every toplevel
+/// evaluation, so changes to the policy take effect at the next form.
+pub(crate) fn sync_gc_policy(env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let mut policy = cx.gc_policy();
+    if let Some(threshold) = env.vars.get(sym::GC_CONS_THRESHOLD) {
+        // Like Emacs, values that are not positive fixnums are ignored
+        if let Ok(threshold) = usize::try_from(threshold.bind(cx)) {
+            policy.threshold = threshold;
+        }
+    }
+    if let Some(percentage) = env.vars.get(sym::GC_CONS_PERCENTAGE) {
+        if let Ok(percentage) = f64::try_from(percentage.bind(cx)) {
+            policy.percentage = percentage.max(0.0);
+        }
+    }
+    if policy != cx.gc_policy() {
+        cx.set_gc_policy(policy);
+    }
+
+    let stats = cx.gc_stats();
+    let gcs_done = env.vars.get(sym::GCS_DONE).map(|x| x.bind(cx));
+    if gcs_done.and_then(|x| usize::try_from(x).ok()) != Some(stats.gcs_done) {
+        let elapsed = stats.elapsed.as_secs_f64();
+        env.set_default(sym::GCS_DONE, cx.add(stats.gcs_done))?;
+        env.set_default(sym::GC_ELAPSED, cx.add(elapsed))?;
+    }
+    Ok(())
+}
+
+defvar!(GC_CONS_THRESHOLD, 800_000);
+defvar!(GC_CONS_PERCENTAGE, 0.1);
+defvar!(GCS_DONE, 0);
+defvar!(GC_ELAPSED, 0.0);
+defvar!(GC_IDLE_FACTOR, 4);
+defvar!(GC_PURGE_SYMBOLS);
+defsym!(INTERNAL__BYTE_CODE_CONSTANTS);
+
 #[cfg(test)]
 mod test {
     use rune_core::macros::root;
 
-    use crate::core::{env::intern, gc::RootSet, object::ObjectType};
+    use crate::core::{
+        env::{intern, sym, Env},
+        gc::RootSet,
+        object::ObjectType,
+    };
 
     use super::*;
 
@@ -121,4 +430,94 @@ mod test {
         assert_eq!(record[1].get(), "slot1");
         assert_eq!(record[2].get(), "slot2");
     }
+
+    fn make<'ob>(consts: Vec<Object<'ob>>, env: &mut Rt<Env>, cx: &'ob Context) -> &'ob ByteFn {
+        let code: Gc<&ByteString> = cx.add_as(vec![0_u8]);
+        let consts: Gc<&LispVec> = cx.add_as(consts);
+        make_byte_code(0, code.untag(), consts.untag(), 1, None, None, &[], env, cx).unwrap()
+    }
+
+    #[test]
+    fn share_constants() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let before = shared_constants();
+        let foo: Object = intern("foo", cx).into();
+        let first = make(vec![cx.add("foo"), list![1, foo; cx], cx.add(0.0)], env, cx);
+        let second = make(vec![cx.add("foo"), list![1, foo; cx], cx.add(-0.0)], env, cx);
+        let as_cons = |x: Object<'_>| match x.untag() {
+            ObjectType::Cons(cons) => std::ptr::from_ref(cons),
+            _ => unreachable!(),
+        };
+        assert!(first.consts()[0].ptr_eq(second.consts()[0]));
+        assert!(std::ptr::eq(as_cons(first.consts()[1]), as_cons(second.consts()[1])));
+        assert!(!first.consts()[2].ptr_eq(second.consts()[2]));
+        let shared = shared_constants();
+        assert_eq!(shared.count, before.count + 2);
+        assert!(shared.bytes > before.bytes);
+
+        // Circular constants are left alone
+        let circular = || {
+            let list = list![1, 2; cx];
+            let ObjectType::Cons(cons) = list.untag() else { unreachable!() };
+            cons.set_car(list).unwrap();
+            list
+        };
+        let (one, two) = (circular(), circular());
+        let first = make(vec![one], env, cx);
+        let second = make(vec![two], env, cx);
+        assert!(std::ptr::eq(as_cons(first.consts()[0]), as_cons(one)));
+        assert!(std::ptr::eq(as_cons(second.consts()[0]), as_cons(two)));
+        assert_eq!(shared_constants().count, shared.count);
+    }
+
+    #[test]
+    fn memory_report() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let before = cx.memory_report();
+        {
+            let list = list![1, 2.5, "foo", vec![cx.add(1)]; cx];
+            root!(list, cx);
+            let report = cx.memory_report();
+            assert_eq!(report.conses.count, before.conses.count + 4);
+            assert_eq!(report.floats.count, before.floats.count + 1);
+            assert_eq!(report.strings.count, before.strings.count + 1);
+            assert_eq!(report.vectors.count, before.vectors.count + 1);
+            assert!(report.strings.bytes >= before.strings.bytes + 3);
+            assert_eq!(report.total().count, before.total().count + 7);
+        }
+        let after = cx.memory_report();
+        assert_eq!(after, before);
+    }
+
+    #[test]
+    fn gc_policy() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        env.set_default(sym::GC_CONS_THRESHOLD, cx.add(1_000_000)).unwrap();
+        env.set_default(sym::GC_CONS_PERCENTAGE, cx.add(0)).unwrap();
+        sync_gc_policy(env, cx).unwrap();
+        assert_eq!(cx.gc_allowance(), 1_000_000);
+        assert!(!garbage_collect_maybe(1, env, cx).unwrap());
+
+        let done = cx.gc_stats().gcs_done;
+        assert!(garbage_collect(env, cx).unwrap());
+        assert_eq!(cx.gc_stats().gcs_done, done + 1);
+        let gcs_done = env.vars.get(sym::GCS_DONE).unwrap().bind(cx);
+        assert_eq!(usize::try_from(gcs_done).unwrap(), done + 1);
+
+        // A threshold of zero collects on every check
+        env.set_default(sym::GC_CONS_THRESHOLD, cx.add(0)).unwrap();
+        assert!(garbage_collect_maybe(1, env, cx).unwrap());
+        assert_eq!(cx.gc_stats().gcs_done, done + 2);
+        // Invalid values leave the policy unchanged
+        env.set_default(sym::GC_CONS_THRESHOLD, cx.add("big")).unwrap();
+        sync_gc_policy(env, cx).unwrap();
+        assert_eq!(cx.gc_policy().threshold, 0);
+    }
 }
@@ -0,0 +1,205 @@
+//! Generating autoloads. A definition preceded by a `;;;###autoload` cookie is
+//! turned into an `autoload` call, so that loading the generated file makes the
+//! definition available without loading the file it is in. Other forms after
+//! a cookie are copied as they are, as is the rest of a cookie's line.
+use crate::core::{
+    env::Env,
+    gc::{Context, Rt},
+    object::{Object, ObjectType},
+};
+use crate::reader;
+use anyhow::Result;
+use rune_macros::defun;
+use std::fmt::Write as _;
+use std::path::Path;
+
+const COOKIE: &str = ";;;###autoload";
+
+/// Definitions of functions, and whether they define macros.
+fn function_definer(name: &str) -> Option<bool> {
+    match name {
+        "defun" | "defsubst" | "cl-defun" | "cl-defsubst" | "define-inline" => Some(false),
+        "defmacro" | "cl-defmacro" => Some(true),
+        _ => None,
+    }
+}
+
+/// Definitions of commands that have no arglist.
+fn mode_definer(name: &str) -> bool {
+    matches!(
+        name,
+        "define-minor-mode"
+            | "define-derived-mode"
+            | "define-globalized-minor-mode"
+            | "define-global-minor-mode"
+            | "define-generic-mode"
+    )
+}
+
+/// The autoload form for the definition `form` in the library `file`, or
+/// `None` if it is not a definition that can be autoloaded.
+fn make_autoload(form: Object, file: &str) -> Option<String> {
+    let ObjectType::Cons(cons) = form.untag() else { return None };
+    let elements: Vec<Object> = cons.elements().collect::<Result<_, _>>().ok()?;
+    let ObjectType::Symbol(definer) = elements[0].untag() else { return None };
+    let name = elements.get(1)?;
+    if !matches!(name.untag(), ObjectType::Symbol(_)) {
+        return None;
+    }
+    let is_string = |x: &&Object| matches!(x.untag(), ObjectType::String(_));
+    let definer = definer.name();
+    if definer == "defcustom" {
+        let value = elements.get(2)?;
+        let doc = elements.get(3).filter(is_string).map_or("nil".into(), ToString::to_string);
+        return Some(format!("(defvar {name} {value} {doc})"));
+    }
+    let (doc, interactive, macro_p) = if let Some(macro_p) = function_definer(definer) {
+        let body = elements.get(3..).unwrap_or_default();
+        let doc = body.first().filter(|_| body.len() > 1).filter(is_string);
+        let interactive = body.iter().any(|form| match form.untag() {
+            ObjectType::Cons(cons) => {
+                matches!(cons.car().untag(), ObjectType::Symbol(x) if x.name() == "interactive")
+            }
+            _ => false,
+        });
+        (doc, interactive, macro_p)
+    } else if mode_definer(definer) {
+        (elements[2..].iter().find(is_string), true, false)
+    } else {
+        return None;
+    };
+    let doc = doc.map_or("nil".into(), ToString::to_string);
+    let interactive = if interactive { "t" } else { "nil" };
+    let kind = if macro_p { " 'macro" } else { "" };
+    Some(format!("(autoload '{name} {file:?} {doc} {interactive}{kind})"))
+}
+
+/// The autoload forms for the cookies in `text`, the contents of the library
+/// `file`.
+fn scan_cookies(text: &str, file: &str, cx: &Context) -> Vec<String> {
+    let mut forms = Vec::new();
+    let mut pos = 0;
+    while let Some(found) = text[pos..].find(COOKIE) {
+        let start = pos + found;
+        pos = start + COOKIE.len();
+        // Cookies have to start a line
+        if start > 0 && !text[..start].ends_with('\n') {
+            continue;
+        }
+        let line_end = text[pos..].find('\n').map_or(text.len(), |i| pos + i);
+        let rest = text[pos..line_end].trim();
+        if !rest.is_empty() {
+            forms.push(rest.to_owned());
+            pos = line_end;
+            continue;
+        }
+        let Ok((form, len)) = reader::read(&text[line_end..], cx) else { continue };
+        let source = text[line_end..line_end + len].trim();
+        forms.push(make_autoload(form, file).unwrap_or_else(|| source.to_owned()));
+        pos = line_end + len;
+    }
+    forms
+}
+
+/// Write the autoloads of the libraries in `dir` to `output`.
+pub(crate) fn make_directory_autoloads(dir: &Path, output: &Path, cx: &Context) -> Result<()> {
+    let mut libraries: Vec<_> = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>, std::io::Error>>()?
+        .into_iter()
+        .filter(|path| path.extension().is_some_and(|x| x == "el") && path != output)
+        .collect();
+    libraries.sort();
+
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    let feature = name.strip_suffix(".el").unwrap_or(&name);
+    let mut out = String::new();
+    writeln!(
+        out,
+        ";;; {name} --- automatically extracted autoloads  -*- lexical-binding: t -*-"
+    )?;
+    writeln!(out, ";;\n;;; Code:\n")?;
+    for library in libraries {
+        let text = std::fs::read_to_string(&library)?;
+        let Some(file) = library.file_stem() else { continue };
+        let forms = scan_cookies(&text, &file.to_string_lossy(), cx);
+        if forms.is_empty() {
+            continue;
+        }
+        let file_name = library.file_name().unwrap_or_default().to_string_lossy();
+        writeln!(out, ";;; Generated autoloads from {file_name}\n")?;
+        for form in forms {
+            writeln!(out, "{form}\n")?;
+        }
+    }
+    writeln!(out, "(provide '{feature})\n\n;;; {name} ends here")?;
+    std::fs::write(output, out)?;
+    Ok(())
+}
+
+#[defun(name = "make-directory-autoloads")]
+fn make_directory_autoloads_lisp(
+    dir: &str,
+    output_file: &str,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let dir = crate::fileio::expand_file_name(dir, None, env, cx)?;
+    let output = crate::fileio::expand_file_name(output_file, Some(&dir), env, cx)?;
+    make_directory_autoloads(Path::new(&dir), Path::new(&output), cx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{env::sym, gc::RootSet};
+
+    #[test]
+    fn test_make_directory_autoloads() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let dir = std::env::temp_dir().join(format!("rune-autoload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let library = r#";;; foo.el --- a test
+;;;###autoload
+(defun foo-command (arg) "Do foo." (interactive) arg)
+;;;###autoload
+(defmacro foo-macro (&rest body) ; a comment
+  "Expand BODY."
+  `(progn ,@body))
+;;;###autoload
+(defun foo-doc-only () "Not a docstring")
+(defun foo-private ())
+;;;###autoload (add-to-list 'auto-mode-alist '("\\.foo\\'" . foo-mode))
+;;;###autoload
+(define-minor-mode foo-mode "Toggle foo.")
+;;;###autoload
+(defcustom foo-level 3 "The level.")
+;;;###autoload
+(put 'foo-level 'safe-local-variable #'integerp)
+"#;
+        std::fs::write(dir.join("foo.el"), library).unwrap();
+        std::fs::write(dir.join("bar.txt"), ";;;###autoload\n(defun bar ())").unwrap();
+        let output = dir.join("foo-autoloads.el");
+        make_directory_autoloads(&dir, &output, cx).unwrap();
+        let generated = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let expected = [
+            r#"(autoload 'foo-command "foo" "Do foo." t)"#,
+            r#"(autoload 'foo-macro "foo" "Expand BODY." nil 'macro)"#,
+            r#"(autoload 'foo-doc-only "foo" nil nil)"#,
+            r#"(add-to-list 'auto-mode-alist '("\\.foo\\'" . foo-mode))"#,
+            r#"(autoload 'foo-mode "foo" "Toggle foo." t)"#,
+            r#"(defvar foo-level 3 "The level.")"#,
+            "(put 'foo-level 'safe-local-variable #'integerp)",
+            "(provide 'foo-autoloads)",
+        ];
+        for form in expected {
+            assert!(generated.contains(form), "{form} not in {generated}");
+        }
+        assert!(!generated.contains("foo-private"));
+        assert!(!generated.contains("bar"));
+    }
+}
@@ -0,0 +1,345 @@
+//! Auto-saving and backups of buffers. While rune waits for events, buffers
+//! with an auto-save file name are written to it every `auto-save-timeout`
+//! seconds if they have changed. The auto-save file of a buffer visiting
+//! `file` is `#file#`, and the first time the buffer is saved the old
+//! contents of `file` are kept in `file~`.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{LispBuffer, Number, Object, ObjectType, Symbol, NIL},
+};
+use anyhow::{bail, Context as _, Result};
+use rune_macros::defun;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+defvar!(AUTO_SAVE_TIMEOUT, 30);
+defvar!(DELETE_AUTO_SAVE_FILES, true);
+defvar!(BUFFER_AUTO_SAVE_FILE_NAME);
+defvar!(BUFFER_BACKED_UP);
+// Options defined in files.el
+defsym!(BACKUP_BY_COPYING);
+defsym!(BACKUP_INHIBITED);
+defsym!(MAKE_BACKUP_FILES);
+defsym!(TOGGLE);
+
+/// When buffers were last auto-saved by the timer
+static LAST_AUTO_SAVE: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Make the auto-save and backup state of a buffer buffer-local.
+pub(crate) fn init_autosave() {
+    sym::BUFFER_AUTO_SAVE_FILE_NAME.make_buffer_local();
+    sym::BUFFER_BACKED_UP.make_buffer_local();
+}
+
+/// The value of `var` in `buffer`.
+fn buffer_var<'ob>(
+    var: Symbol,
+    buffer: &LispBuffer,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    env.local_var(var, buffer)
+        .or_else(|| env.vars.get(var))
+        .map_or(NIL, |x| x.bind(cx))
+}
+
+/// The file name in `var` for `buffer`, if it is set.
+fn file_var(var: Symbol, buffer: &LispBuffer, env: &Rt<Env>, cx: &Context) -> Option<String> {
+    match buffer_var(var, buffer, env, cx).untag() {
+        ObjectType::String(file) => Some(file.to_string()),
+        _ => None,
+    }
+}
+
+/// Whether the option `var` is set. Options that files.el hasn't defined yet
+/// have their `default`.
+fn option(var: Symbol, default: bool, env: &Rt<Env>, cx: &Context) -> bool {
+    env.var(var, cx).map_or(default, |x| !x.bind(cx).is_nil())
+}
+
+fn current_buffer<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Result<&'ob LispBuffer> {
+    match env.current_buffer.as_ref() {
+        Some(buffer) => Ok(buffer.lisp_buffer(cx)),
+        None => bail!("No current buffer"),
+    }
+}
+
+/// `#name#` in the directory of `file`.
+fn auto_save_name(file: &str) -> String {
+    let path = Path::new(file);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("#{name}#")).to_string_lossy().into_owned()
+}
+
+/// Return the file name to auto-save the current buffer in. A buffer visiting
+/// a file is saved to `#file#` next to it, and other buffers to `#%name#` in
+/// `default-directory`.
+#[defun]
+fn make_auto_save_file_name(env: &Rt<Env>, cx: &Context) -> Result<String> {
+    let buffer = current_buffer(env, cx)?;
+    if let Some(file) = file_var(sym::BUFFER_FILE_NAME, buffer, env, cx) {
+        return Ok(auto_save_name(&file));
+    }
+    let name = env.with_buffer(Some(buffer), |b| b.name.replace('/', "!")).unwrap_or_default();
+    let dir = file_var(sym::DEFAULT_DIRECTORY, buffer, env, cx).unwrap_or_default();
+    Ok(Path::new(&dir).join(format!("#%{name}#")).to_string_lossy().into_owned())
+}
+
+/// Toggle auto-saving in the current buffer. It is turned on if `arg` is nil
+/// or positive, off if it is zero or negative, and toggled if it is
+/// `toggle`. Returns whether auto-saving is on.
+#[defun]
+fn auto_save_mode(arg: Option<Object>, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let buffer = current_buffer(env, cx)?;
+    let on = match arg.map(|x| x.untag()) {
+        None | Some(ObjectType::NIL) => true,
+        Some(ObjectType::Int(x)) => x > 0,
+        Some(ObjectType::Symbol(x)) if x == sym::TOGGLE => {
+            buffer_var(sym::BUFFER_AUTO_SAVE_FILE_NAME, buffer, env, cx).is_nil()
+        }
+        Some(_) => true,
+    };
+    let file: Object = match on {
+        true => cx.add(make_auto_save_file_name(env, cx)?),
+        false => NIL,
+    };
+    env.set_local_var(sym::BUFFER_AUTO_SAVE_FILE_NAME, file, buffer)?;
+    Ok(on)
+}
+
+/// Mark the current buffer as auto-saved with its current text.
+#[defun]
+fn set_buffer_auto_saved(env: &mut Rt<Env>) {
+    if let Some(buffer) = env.current_buffer.as_mut() {
+        buffer.auto_save_tick = buffer.text.modified_tick();
+    }
+}
+
+/// Return t if the current buffer has been auto-saved since it was last
+/// saved.
+#[defun]
+fn recent_auto_save_p(env: &Rt<Env>) -> bool {
+    env.current_buffer.as_ref().is_some_and(|b| b.auto_save_tick > b.save_tick)
+}
+
+/// Auto-save `buffer` if it has changed since it was last auto-saved. Returns
+/// true if it was saved.
+fn auto_save_buffer(buffer: &LispBuffer, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let Some(file) = file_var(sym::BUFFER_AUTO_SAVE_FILE_NAME, buffer, env, cx) else {
+        return Ok(false);
+    };
+    let text = env.with_buffer(Some(buffer), |b| {
+        (b.modified && b.text.modified_tick() > b.auto_save_tick).then(|| b.text.to_string())
+    });
+    let Some(Some(text)) = text else { return Ok(false) };
+    std::fs::write(&file, text).with_context(|| format!("Auto-saving to {file}"))?;
+    env.with_buffer_mut(Some(buffer), |b| b.auto_save_tick = b.text.modified_tick());
+    Ok(true)
+}
+
+/// Auto-save every buffer that needs it, or only the current buffer if
+/// `current-only` is non-nil. Returns t if any buffer was saved.
+#[defun]
+fn do_auto_save(
+    _no_message: Option<Object>,
+    current_only: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let buffers = match current_only {
+        Some(x) if !x.is_nil() => vec![current_buffer(env, cx)?],
+        _ => crate::buffer::live_buffers(env).into_iter().map(|x| cx.bind(x)).collect(),
+    };
+    let mut saved = false;
+    for buffer in buffers {
+        saved |= auto_save_buffer(buffer, env, cx)?;
+    }
+    Ok(saved)
+}
+
+/// The interval between auto-saves, or `None` if `auto-save-timeout`
+/// disables them.
+fn auto_save_timeout(env: &Rt<Env>, cx: &Context) -> Option<f64> {
+    let timeout: Number = env.vars.get(sym::AUTO_SAVE_TIMEOUT)?.bind(cx).try_into().ok()?;
+    let timeout = crate::eventloop::timeout_seconds(timeout, None);
+    (timeout > 0.0).then_some(timeout)
+}
+
+/// When buffers should next be auto-saved. The first call starts the timer.
+pub(crate) fn next_auto_save(env: &Rt<Env>, cx: &Context) -> Option<Instant> {
+    let timeout = auto_save_timeout(env, cx)?;
+    let last = *LAST_AUTO_SAVE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_or_insert_with(Instant::now);
+    last.checked_add(std::time::Duration::try_from_secs_f64(timeout).ok()?)
+}
+
+/// Auto-save the buffers if `auto-save-timeout` seconds have passed since
+/// they were last auto-saved. Errors are reported without interrupting
+/// whatever was waiting.
+pub(crate) fn auto_save_if_due(env: &mut Rt<Env>, cx: &Context) {
+    let Some(due) = next_auto_save(env, cx) else { return };
+    if Instant::now() < due {
+        return;
+    }
+    *LAST_AUTO_SAVE.lock().unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+    if let Err(e) = do_auto_save(None, None, env, cx) {
+        eprintln!("Error: {e}");
+    }
+}
+
+/// Back up the file the current buffer is visiting to `file~`, if this is
+/// the first time the buffer is saved. The file is copied if
+/// `backup-by-copying` is set or it is a symbolic link, and renamed
+/// otherwise. Returns the name of the backup, or nil if none was made.
+#[defun]
+fn backup_buffer(env: &mut Rt<Env>, cx: &Context) -> Result<Option<String>> {
+    let buffer = current_buffer(env, cx)?;
+    if !option(sym::MAKE_BACKUP_FILES, true, env, cx)
+        || option(sym::BACKUP_INHIBITED, false, env, cx)
+        || !buffer_var(sym::BUFFER_BACKED_UP, buffer, env, cx).is_nil()
+    {
+        return Ok(None);
+    }
+    let Some(file) = file_var(sym::BUFFER_FILE_NAME, buffer, env, cx) else {
+        return Ok(None);
+    };
+    let Ok(metadata) = std::fs::symlink_metadata(&file) else { return Ok(None) };
+    if metadata.is_dir() {
+        return Ok(None);
+    }
+    let backup = format!("{file}~");
+    if option(sym::BACKUP_BY_COPYING, false, env, cx) || metadata.is_symlink() {
+        std::fs::copy(&file, &backup)
+    } else {
+        std::fs::rename(&file, &backup).map(|_| 0)
+    }
+    .with_context(|| format!("Backing up {file}"))?;
+    env.set_local_var(sym::BUFFER_BACKED_UP, sym::TRUE.into(), buffer)?;
+    Ok(Some(backup))
+}
+
+/// Save the current buffer to the file it is visiting, if it has changed.
+/// The old file is backed up first, and the auto-save file is deleted if
+/// `delete-auto-save-files` is set.
+#[defun]
+fn basic_save_buffer(
+    _called_interactively: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let buffer = current_buffer(env, cx)?;
+    let Some(file) = file_var(sym::BUFFER_FILE_NAME, buffer, env, cx) else {
+        let name = env.with_buffer(Some(buffer), |b| b.name.clone()).unwrap_or_default();
+        bail!("Buffer {name} is not visiting a file");
+    };
+    let Some(Some(text)) =
+        env.with_buffer(Some(buffer), |b| b.modified.then(|| b.text.to_string()))
+    else {
+        return Ok(());
+    };
+    backup_buffer(env, cx)?;
+    std::fs::write(&file, text).with_context(|| format!("Writing {file}"))?;
+    env.with_buffer_mut(Some(buffer), |b| {
+        b.modified = false;
+        b.save_tick = b.text.modified_tick();
+        b.auto_save_tick = b.save_tick;
+    });
+    if option(sym::DELETE_AUTO_SAVE_FILES, true, env, cx) {
+        if let Some(auto_save) = file_var(sym::BUFFER_AUTO_SAVE_FILE_NAME, buffer, env, cx) {
+            if auto_save != file {
+                // The auto-save file may never have been written
+                let _ = std::fs::remove_file(auto_save);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Visit `file` with the text of its auto-save file, and make that buffer
+/// current. The buffer is left modified, so the recovered text is written
+/// when it is saved.
+#[defun]
+fn recover_file<'ob>(file: &str, env: &mut Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let file = crate::fileio::expand_file_name(file, None, env, cx)?;
+    let auto_save = auto_save_name(&file);
+    let modified = |name: &str| std::fs::metadata(name).and_then(|x| x.modified()).ok();
+    let Some(saved) = modified(&auto_save) else {
+        bail!("Auto-save file {auto_save} does not exist");
+    };
+    if modified(&file).is_some_and(|x| x > saved) {
+        bail!("Auto-save file {auto_save} not current");
+    }
+    let text =
+        std::fs::read_to_string(&auto_save).with_context(|| format!("Reading {auto_save}"))?;
+    let name = Path::new(&file).file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let buffer = crate::buffer::get_buffer_create(cx.add(name), None, cx)?;
+    let ObjectType::Buffer(lisp_buffer) = buffer.untag() else { unreachable!() };
+    env.set_local_var(sym::BUFFER_FILE_NAME, cx.add(file), lisp_buffer)?;
+    env.set_local_var(sym::BUFFER_AUTO_SAVE_FILE_NAME, cx.add(auto_save), lisp_buffer)?;
+    env.set_buffer(lisp_buffer)?;
+    let current = env.current_buffer.as_mut().unwrap();
+    let len = current.text.len_chars();
+    current.delete(0, len);
+    current.text.insert(&text);
+    current.auto_save_tick = current.text.modified_tick();
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::{buffer_modified_p, get_buffer_create, set_buffer};
+    use crate::core::gc::RootSet;
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_auto_save_and_backup() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        init_autosave();
+        root!(env, new(Env), cx);
+        let dir = std::env::temp_dir().join(format!("rune-autosave-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("notes.txt").to_string_lossy().into_owned();
+        std::fs::write(&file, "old").unwrap();
+
+        let buffer = get_buffer_create(cx.add("test_auto_save"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        let ObjectType::Buffer(lisp_buffer) = buffer.untag() else { unreachable!() };
+        env.set_local_var(sym::BUFFER_FILE_NAME, cx.add(file.as_str()), lisp_buffer)
+            .unwrap();
+        let auto_save = make_auto_save_file_name(env, cx).unwrap();
+        assert_eq!(auto_save, dir.join("#notes.txt#").to_string_lossy());
+        assert!(auto_save_mode(None, env, cx).unwrap());
+
+        // Only changed buffers are auto-saved
+        assert!(!do_auto_save(None, Some(sym::TRUE.into()), env, cx).unwrap());
+        env.current_buffer.as_mut().unwrap().insert(cx.add("new")).unwrap();
+        assert!(do_auto_save(None, Some(sym::TRUE.into()), env, cx).unwrap());
+        assert_eq!(std::fs::read_to_string(&auto_save).unwrap(), "new");
+        assert!(recent_auto_save_p(env));
+        assert!(!do_auto_save(None, Some(sym::TRUE.into()), env, cx).unwrap());
+
+        recover_file(&file, env, cx).unwrap();
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "new");
+        assert!(buffer_modified_p(None, env));
+        set_buffer(buffer, env, cx).unwrap();
+
+        basic_save_buffer(None, env, cx).unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "new");
+        assert_eq!(std::fs::read_to_string(format!("{file}~")).unwrap(), "old");
+        assert!(!Path::new(&auto_save).exists());
+        assert!(!recent_auto_save_p(env));
+        // The backup is only made on the first save
+        env.current_buffer.as_mut().unwrap().insert(cx.add("er")).unwrap();
+        basic_save_buffer(None, env, cx).unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "newer");
+        assert_eq!(std::fs::read_to_string(format!("{file}~")).unwrap(), "old");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
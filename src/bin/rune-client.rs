@@ -0,0 +1,66 @@
+//! Send expressions to a rune started with `--daemon`.
+//!
+//! Usage: `rune-client [-s SOCKET] eval EXPR...`
+#[path = "../server/protocol.rs"]
+mod protocol;
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+fn usage() -> ExitCode {
+    eprintln!("Usage: rune-client [-s SOCKET] eval EXPR...");
+    ExitCode::FAILURE
+}
+
+#[cfg(unix)]
+fn eval(socket: &Path, expr: &str) -> std::io::Result<(String, String)> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(socket)?;
+    stream.write_all(protocol::encode(protocol::EVAL, expr).as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    let (command, arg) = protocol::decode(&reply);
+    Ok((command.to_owned(), arg))
+}
+
+#[cfg(not(unix))]
+fn eval(_socket: &Path, _expr: &str) -> std::io::Result<(String, String)> {
+    Err(std::io::Error::other("rune-client requires unix domain sockets"))
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut socket = protocol::default_socket_path();
+    if args.peek().is_some_and(|x| x == "-s") {
+        args.next();
+        let Some(path) = args.next() else { return usage() };
+        socket = PathBuf::from(path);
+    }
+    if args.next().as_deref() != Some("eval") {
+        return usage();
+    }
+    let expr = args.collect::<Vec<_>>().join(" ");
+    if expr.is_empty() {
+        return usage();
+    }
+    match eval(&socket, &expr) {
+        Ok((command, value)) if command == protocol::PRINT => {
+            println!("{value}");
+            ExitCode::SUCCESS
+        }
+        Ok((command, message)) if command == protocol::ERROR => {
+            eprintln!("*ERROR*: {message}");
+            ExitCode::FAILURE
+        }
+        Ok((command, _)) => {
+            eprintln!("rune-client: unexpected reply {command}");
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("rune-client: can't connect to {}: {e}", socket.display());
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,23 +1,23 @@
 //! Buffer operations.
 use crate::core::{
-    env::{interned_symbols, Env},
+    env::{interned_symbols, sym, Env},
     error::{Type, TypeError},
     gc::{Context, Rt},
     object::{Gc, LispBuffer, Object, ObjectType, NIL},
 };
 use anyhow::{bail, Result};
-use rune_core::hashmap::HashMap;
+use rune_core::hashmap::IndexMap;
 use rune_macros::defun;
-use std::sync::Mutex;
 use std::sync::OnceLock;
+use std::sync::{Mutex, PoisonError};
 
-// static hashmap containing all the buffers
-static BUFFERS: OnceLock<Mutex<HashMap<String, &'static LispBuffer>>> = OnceLock::new();
+// static map of all the buffers, in the order they were created
+static BUFFERS: OnceLock<Mutex<IndexMap<String, &'static LispBuffer>>> = OnceLock::new();
 
 /// Helper function to avoid calling `get_or_init` on each of the calls to `lock()` on the Mutex.
 ///
 /// TODO: Use `LazyLock`: <https://github.com/CeleritasCelery/rune/issues/34>
-fn buffers() -> &'static Mutex<HashMap<String, &'static LispBuffer>> {
+fn buffers() -> &'static Mutex<IndexMap<String, &'static LispBuffer>> {
     BUFFERS.get_or_init(Mutex::default)
 }
 
@@ -32,11 +32,14 @@ pub(crate) fn set_buffer<'ob>(
     Ok(cx.add(buffer))
 }
 
-fn resolve_buffer<'ob>(buffer_or_name: Object, cx: &'ob Context) -> Result<&'ob LispBuffer> {
+pub(crate) fn resolve_buffer<'ob>(
+    buffer_or_name: Object,
+    cx: &'ob Context,
+) -> Result<&'ob LispBuffer> {
     match buffer_or_name.untag() {
         ObjectType::Buffer(b) => Ok(b),
         ObjectType::String(name) => {
-            let buffer_list = buffers().lock().unwrap();
+            let buffer_list = buffers().lock().unwrap_or_else(PoisonError::into_inner);
             let Some(buffer) = buffer_list.get(name.as_ref()) else {
                 bail!("No buffer named {}", name);
             };
@@ -46,9 +49,41 @@ fn resolve_buffer<'ob>(buffer_or_name: Object, cx: &'ob Context) -> Result<&'ob
     }
 }
 
+/// All buffers that have not been killed.
+pub(crate) fn live_buffers(env: &Rt<Env>) -> Vec<&'static LispBuffer> {
+    let buffers = buffers().lock().unwrap_or_else(PoisonError::into_inner);
+    let live = buffers.values().filter(|x| env.with_buffer(Some(**x), |_| {}).is_some());
+    live.copied().collect()
+}
+
+#[defun]
+fn buffer_modified_p(buffer: Option<Gc<&LispBuffer>>, env: &Rt<Env>) -> bool {
+    env.with_buffer(buffer.map(Gc::untag), |b| b.modified).unwrap_or(false)
+}
+
+/// Return the modification tick of `buffer`. It increases whenever the text
+/// of the buffer changes.
+#[defun]
+fn buffer_modified_tick(buffer: Option<Gc<&LispBuffer>>, env: &Rt<Env>) -> usize {
+    env.with_buffer(buffer.map(Gc::untag), |b| b.text.modified_tick()).unwrap_or(0)
+}
+
+/// Return the tick of the last change to the characters of `buffer`. Text
+/// properties are not supported, so every change is a change to the
+/// characters, and this is the same as `buffer-modified-tick`.
+#[defun]
+fn buffer_chars_modified_tick(buffer: Option<Gc<&LispBuffer>>, env: &Rt<Env>) -> usize {
+    buffer_modified_tick(buffer, env)
+}
+
 #[defun]
-fn set_buffer_modified_p(flag: Object) -> Object {
-    // TODO: implement
+fn set_buffer_modified_p<'ob>(flag: Object<'ob>, env: &mut Rt<Env>) -> Object<'ob> {
+    if let Some(buffer) = env.current_buffer.as_mut() {
+        buffer.modified = !flag.is_nil();
+        if flag.is_nil() {
+            buffer.save_tick = buffer.text.modified_tick();
+        }
+    }
     flag
 }
 
@@ -65,6 +100,26 @@ fn buffer_name(buffer: Option<Gc<&LispBuffer>>, env: &Rt<Env>) -> Option<String>
     env.with_buffer(buffer.map(Gc::untag), |b| b.name.to_string())
 }
 
+/// Return the name of the file `buffer` is visiting, or nil if it isn't
+/// visiting one.
+#[defun]
+fn buffer_file_name<'ob>(
+    buffer: Option<Gc<&LispBuffer>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let buffer = match buffer {
+        Some(buffer) => buffer.untag(),
+        None => match env.current_buffer.as_ref() {
+            Some(current) => current.lisp_buffer(cx),
+            None => return NIL,
+        },
+    };
+    env.local_var(sym::BUFFER_FILE_NAME, buffer)
+        .or_else(|| env.vars.get(sym::BUFFER_FILE_NAME))
+        .map_or(NIL, |x| x.bind(cx))
+}
+
 #[defun]
 pub(crate) fn get_buffer_create<'ob>(
     buffer_or_name: Object<'ob>,
@@ -73,19 +128,13 @@ pub(crate) fn get_buffer_create<'ob>(
 ) -> Result<Object<'ob>> {
     match buffer_or_name.untag() {
         ObjectType::String(name) => {
-            let mut buffer_list = buffers().lock().unwrap();
+            let mut buffer_list = buffers().lock().unwrap_or_else(PoisonError::into_inner);
             match buffer_list.get(name.as_ref()) {
                 Some(b) => Ok(cx.add(*b)),
                 None => {
                     // If not already in the global buffer list, create a new
                     // buffer and add it
-                    let buffer: &'static _ = {
-                        let global = interned_symbols().lock().unwrap();
-                        let buffer = global.create_buffer(name);
-                        // SAFETY: This can be 'static because it is stored in the
-                        // global block. Eventually it will be garbage collected
-                        unsafe { &*(buffer as *const LispBuffer) }
-                    };
+                    let buffer = interned_symbols().create_buffer(name);
                     buffer_list.insert(name.to_string(), buffer);
                     Ok(cx.add(buffer))
                 }
@@ -103,7 +152,7 @@ pub(crate) fn get_buffer<'ob>(
 ) -> Result<Object<'ob>> {
     match buffer_or_name.untag() {
         ObjectType::String(name) => {
-            let buffer_list = buffers().lock().unwrap();
+            let buffer_list = buffers().lock().unwrap_or_else(PoisonError::into_inner);
             match buffer_list.get(name.as_ref()) {
                 Some(b) => Ok(cx.add(*b)),
                 None => Ok(NIL),
@@ -128,7 +177,7 @@ pub(crate) fn get_buffer<'ob>(
 #[defun]
 fn generate_new_buffer_name(name: &str, ignore: Option<&str>) -> String {
     // check if the name exists
-    let buffer_list = buffers().lock().unwrap();
+    let buffer_list = buffers().lock().unwrap_or_else(PoisonError::into_inner);
     let valid_name =
         |name: &str| ignore.is_some_and(|x| x == name) || !buffer_list.contains_key(name);
 
@@ -174,6 +223,7 @@ defvar!(TAB_WIDTH, 8);
 defvar!(TRUNCATE_LINES);
 defvar!(WORD_WRAP);
 defvar!(BIDI_DISPLAY_REORDERING);
+defvar!(BUFFER_FILE_NAME);
 
 #[cfg(test)]
 mod test {
@@ -1,7 +1,7 @@
 #![allow(unstable_name_collisions)]
 //! The main bytecode interpeter.
-use crate::core::cons::Cons;
 use crate::core::env::{sym, CallFrame, Env};
+use crate::core::error::VoidError;
 use crate::core::gc::{Context, IntoRoot, Rt, Rto, Slot};
 use crate::core::object::{
     ByteFn, ByteString, Function, FunctionType, Gc, LispVec, Object, ObjectType, Symbol,
@@ -14,6 +14,7 @@ use rune_macros::{defun, Trace};
 use sptr::Strict;
 
 mod opcode;
+use opcode::OpCode;
 
 /// An program counter. This is implemented as a bound checked range pointer.
 // TODO: If the GC moves the bytecode, this will be invalid. We need to fix this
@@ -107,6 +108,67 @@ impl<'old, 'new> WithLifetime<'new> for Handler<'old> {
     }
 }
 
+/// Prints each opcode as it is executed. This is enabled by setting
+/// `byte-code-trace` to `t`, or to a list of the names of the functions to
+/// trace. At most `byte-code-trace-limit` opcodes are printed each time lisp
+/// enters the VM.
+struct OpTracer {
+    /// The functions to trace, or `None` for all of them
+    functions: Option<Vec<String>>,
+    remaining: Option<usize>,
+    /// The name of the function running in each bytecode frame
+    names: Vec<(usize, String)>,
+}
+
+impl OpTracer {
+    fn new(env: &Rt<Env>, cx: &Context) -> Option<Self> {
+        let trace = env.vars.get(sym::BYTE_CODE_TRACE)?.bind(cx);
+        if trace.is_nil() {
+            return None;
+        }
+        let functions = match trace.untag() {
+            ObjectType::Cons(_) => {
+                let names = trace.as_list().ok()?.flatten().filter_map(|x| match x.untag() {
+                    ObjectType::Symbol(sym) => Some(sym.name().to_owned()),
+                    _ => <&str>::try_from(x).ok().map(ToOwned::to_owned),
+                });
+                Some(names.collect())
+            }
+            _ => None,
+        };
+        let limit = env.vars.get(sym::BYTE_CODE_TRACE_LIMIT);
+        let remaining = limit.and_then(|x| usize::try_from(x.bind(cx)).ok());
+        Some(OpTracer { functions, remaining, names: Vec::new() })
+    }
+
+    /// Note that `name` is now running in `frame`.
+    fn enter(&mut self, frame: usize, name: &str) {
+        self.names.push((frame, name.to_owned()));
+    }
+
+    fn trace(&mut self, op: OpCode, pc: usize, frame: usize, stack: &[Rto<Object>]) {
+        // Frames above this one have returned or been unwound
+        while self.names.last().is_some_and(|x| x.0 > frame) {
+            self.names.pop();
+        }
+        let name = self.names.last().map_or("", |x| x.1.as_str());
+        if self.functions.as_ref().is_some_and(|x| !x.iter().any(|x| x == name)) {
+            return;
+        }
+        if let Some(remaining) = &mut self.remaining {
+            if *remaining == 0 {
+                return;
+            }
+            *remaining -= 1;
+        }
+        let top = stack.iter().rev().take(3).map(ToString::to_string);
+        eprintln!("{name}:{pc}: {op:?} [{}]", top.collect::<Vec<_>>().join(" "));
+        if self.remaining == Some(0) {
+            eprintln!("byte-code-trace: limit reached");
+        }
+    }
+}
+
 /// The bytecode VM. This hold all the current call frames and handlers. The
 /// execution stack is part of the Environment.
 #[derive(Trace)]
@@ -122,6 +184,8 @@ struct VM<'brw, 'env, 'rt> {
     /// The runtime environment
     #[no_trace]
     env: &'brw mut Rt<Env<'env>>,
+    #[no_trace]
+    tracer: Option<OpTracer>,
 }
 
 impl<'brw, 'env> IntoRoot<VM<'brw, 'env, 'static>> for VM<'brw, 'env, '_> {
@@ -134,34 +198,43 @@ impl<'ob> RootedVM<'_, '_, '_> {
     fn varref(&mut self, idx: u16, cx: &'ob Context) -> Result<()> {
         let symbol = self.get_const(idx as usize, cx);
         if let ObjectType::Symbol(sym) = symbol.untag() {
-            let Some(var) = self.env.vars.get(sym) else { bail!("Void Variable: {sym}") };
+            let Some(var) = self.env.var(sym, cx) else { bail!(VoidError::variable(sym)) };
             let var = var.bind(cx);
-            self.env.stack.push(var);
+            self.env.stack.push(var)?;
             Ok(())
         } else {
             unreachable!("Varref was not a symbol: {:?}", symbol);
         }
     }
 
-    fn varset(&mut self, idx: usize, cx: &Context) -> Result<()> {
+    fn varset(&mut self, idx: usize, cx: &mut Context) -> Result<()> {
         let obj = self.get_const(idx, cx);
         let symbol: Symbol = obj.try_into()?;
         let value = self.env.stack.pop(cx);
-        crate::data::set(symbol, value, self.env)?;
-        Ok(())
+        root!(symbol, cx);
+        root!(value, cx);
+        crate::data::set_variable(symbol, value, self.env, cx)
     }
 
-    fn varbind(&mut self, idx: u16, cx: &'ob Context) {
+    fn varbind(&mut self, idx: u16, cx: &mut Context) -> Result<()> {
         let value = self.env.stack.pop(cx);
         let symbol = self.get_const(idx as usize, cx);
-        let ObjectType::Symbol(sym) = symbol.untag() else {
+        let ObjectType::Symbol(var) = symbol.untag() else {
             unreachable!("Varbind was not a symbol: {:?}", symbol)
         };
-        self.env.varbind(sym, value, cx);
+        if var.is_watched() {
+            root!(var, cx);
+            root!(value, cx);
+            crate::data::notify_variable_watchers(var, value, sym::LET, true, self.env, cx)?;
+            self.env.varbind(var.bind(cx), value.bind(cx), cx);
+        } else {
+            self.env.varbind(var, value, cx);
+        }
+        Ok(())
     }
 
-    fn unbind(&mut self, idx: u16, cx: &'ob Context) {
-        self.env.unbind(idx, cx);
+    fn unbind(&mut self, idx: u16, cx: &mut Context) -> Result<()> {
+        crate::data::unbind(idx, self.env, cx)
     }
 
     fn get_const(&self, i: usize, cx: &'ob Context) -> Object<'ob> {
@@ -203,7 +276,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
     ) -> Result<()> {
         let arg_cnt = arg_cnt as u16;
         let fill_args = func.args.num_of_fill_args(arg_cnt, name)?;
-        self.env.stack.fill_extra_args(fill_args);
+        self.env.stack.fill_extra_args(fill_args)?;
         let total_args = arg_cnt + fill_args;
         let rest_size = total_args - (func.args.required + func.args.optional);
         if rest_size > 0 {
@@ -213,7 +286,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
             self.env.stack[0].set(list);
             self.env.stack.set_arg_count(total_args - rest_size + 1, true);
         } else if func.args.rest {
-            self.env.stack.push(NIL);
+            self.env.stack.push(NIL)?;
             self.env.stack.set_arg_count(total_args + 1, true)
         } else {
             self.env.stack.set_arg_count(total_args, false)
@@ -238,13 +311,16 @@ impl<'ob> RootedVM<'_, '_, '_> {
             let frame_start = len - (arg_cnt + 1);
             self.env
                 .stack
-                .push_bytecode_frame(frame_start, next_fn.depth, prev_fn, pc_offset);
+                .push_bytecode_frame(frame_start, next_fn.depth, prev_fn, pc_offset)?;
+            if let Some(tracer) = &mut self.tracer {
+                tracer.enter(self.env.stack.current_frame(), &name);
+            }
             self.prepare_lisp_args(next_fn, arg_cnt, &name, cx)?;
         } else {
             // Otherwise, call the function directly.
             let mut frame = CallFrame::new_with_args(self.env, arg_cnt);
             root!(func, cx);
-            let result = func.call(&mut frame, Some(&name), cx)?;
+            let result = crate::eval::call_function(func, &mut frame, Some(&name), cx)?;
             drop(frame); // removes the arguments from the stack
             self.env.stack.top().set(result);
             cx.garbage_collect(false);
@@ -259,36 +335,18 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 Err(e) => e,
             };
 
-            // we will fix this once we can handle different error types
-            #[allow(clippy::never_loop)]
+            if matches!(err.error, ErrorType::Throw(..)) {
+                return Err(err);
+            }
             while let Some(handler) = self.handlers.bind_mut(cx).pop() {
-                match handler.condition.untag() {
-                    ObjectType::Symbol(sym::ERROR) => {}
-                    ObjectType::Cons(conditions) => {
-                        for condition in conditions {
-                            let condition = condition?;
-                            // TODO: Handle different error symbols
-                            if condition != sym::DEBUG && condition != sym::ERROR {
-                                bail_err!("non-error conditions {condition} not yet supported")
-                            }
-                        }
-                    }
-                    x => bail_err!("Invalid condition handler: {x}"),
+                let error_symbol = err.symbol(cx);
+                if !crate::eval::handles_error(*handler.condition, error_symbol, self.env, cx)? {
+                    continue;
                 }
-
-                let error = if let EvalError { error: ErrorType::Signal(id), .. } = err {
-                    let Some((sym, data)) = self.env.get_exception(id) else {
-                        unreachable!("Exception not found")
-                    };
-                    Cons::new(sym, data, cx)
-                } else {
-                    // TODO: Need to remove the anyhow branch once
-                    // full errors are implemented
-                    Cons::new(sym::ERROR, format!("{err}"), cx)
-                };
+                let error = err.to_lisp(cx);
                 self.unwind(handler.stack_frame, cx);
                 self.env.stack.truncate(handler.stack_size);
-                self.env.stack.push(Object::from(error));
+                self.env.stack.push(Object::from(error))?;
                 self.pc.goto(handler.jump_code);
                 continue 'main;
             }
@@ -304,7 +362,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
         loop {
             let op = match self.pc.next().try_into() {
                 Ok(x) => x,
-                Err(e) => panic!("Invalid Bytecode: {e}"),
+                Err(e) => bail_err!("Invalid Bytecode: {e}"),
             };
 
             if Self::debug_enabled() {
@@ -316,20 +374,24 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 let byte_offset = self.pc.pc as i64 - self.pc.range.start as i64 - 1;
                 println!("op :{byte_offset}: {op:?}");
             }
+            if let Some(tracer) = &mut self.tracer {
+                let frame = self.env.stack.current_frame();
+                tracer.trace(op, self.pc.as_offset() - 1, frame, self.env.stack.frames());
+            }
             match op {
-                op::StackRef0 => self.env.stack.push_ref(0, cx),
-                op::StackRef1 => self.env.stack.push_ref(1, cx),
-                op::StackRef2 => self.env.stack.push_ref(2, cx),
-                op::StackRef3 => self.env.stack.push_ref(3, cx),
-                op::StackRef4 => self.env.stack.push_ref(4, cx),
-                op::StackRef5 => self.env.stack.push_ref(5, cx),
+                op::StackRef0 => self.env.stack.push_ref(0, cx)?,
+                op::StackRef1 => self.env.stack.push_ref(1, cx)?,
+                op::StackRef2 => self.env.stack.push_ref(2, cx)?,
+                op::StackRef3 => self.env.stack.push_ref(3, cx)?,
+                op::StackRef4 => self.env.stack.push_ref(4, cx)?,
+                op::StackRef5 => self.env.stack.push_ref(5, cx)?,
                 op::StackRefN => {
                     let idx = self.pc.arg1();
-                    self.env.stack.push_ref(idx, cx);
+                    self.env.stack.push_ref(idx, cx)?;
                 }
                 op::StackRefN2 => {
                     let idx = self.pc.arg2();
-                    self.env.stack.push_ref(idx, cx);
+                    self.env.stack.push_ref(idx, cx)?;
                 }
                 op::StackSetN => {
                     let idx = self.pc.arg1();
@@ -367,19 +429,19 @@ impl<'ob> RootedVM<'_, '_, '_> {
                     let idx = self.pc.arg2();
                     self.varset(idx.into(), cx)?;
                 }
-                op::VarBind0 => self.varbind(0, cx),
-                op::VarBind1 => self.varbind(1, cx),
-                op::VarBind2 => self.varbind(2, cx),
-                op::VarBind3 => self.varbind(3, cx),
-                op::VarBind4 => self.varbind(4, cx),
-                op::VarBind5 => self.varbind(5, cx),
+                op::VarBind0 => self.varbind(0, cx)?,
+                op::VarBind1 => self.varbind(1, cx)?,
+                op::VarBind2 => self.varbind(2, cx)?,
+                op::VarBind3 => self.varbind(3, cx)?,
+                op::VarBind4 => self.varbind(4, cx)?,
+                op::VarBind5 => self.varbind(5, cx)?,
                 op::VarBindN => {
                     let idx = self.pc.arg1();
-                    self.varbind(idx, cx);
+                    self.varbind(idx, cx)?;
                 }
                 op::VarBindN2 => {
                     let idx = self.pc.arg2();
-                    self.varbind(idx, cx);
+                    self.varbind(idx, cx)?;
                 }
                 op::Call0 => self.call(0, cx)?,
                 op::Call1 => self.call(1, cx)?,
@@ -395,19 +457,19 @@ impl<'ob> RootedVM<'_, '_, '_> {
                     let idx = self.pc.arg2();
                     self.call(idx, cx)?;
                 }
-                op::Unbind0 => self.unbind(0, cx),
-                op::Unbind1 => self.unbind(1, cx),
-                op::Unbind2 => self.unbind(2, cx),
-                op::Unbind3 => self.unbind(3, cx),
-                op::Unbind4 => self.unbind(4, cx),
-                op::Unbind5 => self.unbind(5, cx),
+                op::Unbind0 => self.unbind(0, cx)?,
+                op::Unbind1 => self.unbind(1, cx)?,
+                op::Unbind2 => self.unbind(2, cx)?,
+                op::Unbind3 => self.unbind(3, cx)?,
+                op::Unbind4 => self.unbind(4, cx)?,
+                op::Unbind5 => self.unbind(5, cx)?,
                 op::UnbindN => {
                     let idx = self.pc.arg1();
-                    self.unbind(idx, cx);
+                    self.unbind(idx, cx)?;
                 }
                 op::UnbindN2 => {
                     let idx = self.pc.arg2();
-                    self.unbind(idx, cx);
+                    self.unbind(idx, cx)?;
                 }
                 op::PopHandler => {
                     self.handlers.pop();
@@ -427,7 +489,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 op::Nth => {
                     let list = self.env.stack.pop(cx);
                     let top = self.env.stack.top();
-                    top.set(fns::nth(top.bind_as(cx)?, list.try_into()?)?);
+                    top.set(fns::nth(top.bind_as(cx)?, list)?);
                 }
                 op::Symbolp => {
                     let top = self.env.stack.top();
@@ -507,7 +569,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
                     let newlet = self.env.stack.pop(cx);
                     let idx = self.env.stack.pop(cx);
                     let top = self.env.stack.top();
-                    top.set(data::aset(top.bind(cx), idx.try_into()?, newlet)?);
+                    top.set(data::aset(top.bind(cx), idx.try_into()?, newlet, cx)?);
                 }
                 op::SymbolValue => {
                     let top = self.env.stack.top().bind_as(cx)?;
@@ -520,9 +582,11 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 }
                 op::Set => {
                     let newlet = self.env.stack.pop(cx);
-                    let top = self.env.stack.top().bind_as(cx)?;
-                    let value = data::set(top, newlet, self.env)?;
-                    self.env.stack.top().set(value);
+                    let top: Symbol = self.env.stack.top().bind_as(cx)?;
+                    root!(newlet, cx);
+                    root!(top, cx);
+                    data::set_variable(top, newlet, self.env, cx)?;
+                    self.env.stack.top().set(newlet.bind(cx));
                 }
                 op::Fset => {
                     let def = self.env.stack.pop(cx);
@@ -632,7 +696,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 op::ConstantN2 => {
                     let idx = self.pc.arg2();
                     let cnst = self.get_const(idx.into(), cx);
-                    self.env.stack.push(cnst);
+                    self.env.stack.push(cnst)?;
                 }
                 op::Goto => {
                     let offset = self.pc.arg2();
@@ -673,7 +737,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
                         self.set_current_frame(f.bind(cx), offset);
                         let top = self.env.stack.top().bind(cx);
                         self.env.stack.pop_frame();
-                        self.env.stack.push(top);
+                        self.env.stack.push(top)?;
                     } else {
                         let top = self.env.stack.pop(cx);
                         return Ok(top);
@@ -697,7 +761,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 }
                 op::Duplicate => {
                     let top = self.env.stack[0].bind(cx);
-                    self.env.stack.push(top);
+                    self.env.stack.push(top)?;
                 }
                 op::SaveExcursion => todo!("SaveExcursion bytecode"),
                 op::SaveRestriction => todo!("SaveRestriction bytecode"),
@@ -717,7 +781,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 op::Nthcdr => {
                     let list = self.env.stack.pop(cx);
                     let top = self.env.stack.top();
-                    top.set(fns::nthcdr(top.bind_as(cx)?, list.try_into()?)?.copy_as_obj(cx));
+                    top.set(fns::nthcdr(top.bind_as(cx)?, list)?);
                 }
                 op::Elt => {
                     let n = self.env.stack.pop(cx);
@@ -859,7 +923,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 | op::Constant63 => {
                     let idx = (op as u8) - (op::Constant0 as u8);
                     let cnst = self.get_const(idx as usize, cx);
-                    self.env.stack.push(cnst);
+                    self.env.stack.push(cnst)?;
                 }
             }
         }
@@ -874,16 +938,8 @@ fn byte_code<'ob>(
     env: &mut Rt<Env>,
     cx: &'ob mut Context,
 ) -> Result<Object<'ob>> {
-    let fun = crate::alloc::make_byte_code(
-        0,
-        bytestr.untag(cx),
-        vector.untag(cx),
-        maxdepth,
-        None,
-        None,
-        &[],
-        cx,
-    )?;
+    let constants = vector.untag(cx).to_vec();
+    let fun = crate::alloc::new_byte_code(0, bytestr.untag(cx), constants, maxdepth, cx)?;
     root!(fun, cx);
     Ok(call(fun, 0, "unnamed", &mut CallFrame::new(env), cx)?)
 }
@@ -900,22 +956,37 @@ pub(crate) fn call<'ob>(
     frame: &mut CallFrame,
     cx: &'ob mut Context,
 ) -> EvalResult<'ob> {
-    frame.stack.set_depth(func.bind(cx).depth);
+    frame.stack.set_depth(func.bind(cx).depth)?;
     let func = func.bind(cx);
+    let mut tracer = OpTracer::new(frame, cx);
+    if let Some(tracer) = &mut tracer {
+        tracer.enter(frame.stack.current_frame(), name);
+    }
     let vm = VM {
         pc: ProgramCounter::new(func.codes()),
         func: Slot::new(func),
         env: frame,
         handlers: Vec::new(),
+        tracer,
     };
     root!(vm, cx);
+    let base_frame = vm.env.stack.current_frame();
     vm.prepare_lisp_args(func, arg_cnt, name, cx)?;
-    vm.run(cx).map_err(|e| e.add_trace(name, vm.env.stack.current_args()))
+    vm.run(cx).map_err(|e| {
+        // Remove the frames of the bytecode functions the error escaped from,
+        // so the caller sees the stack as it left it.
+        vm.env.stack.unwind_frames(base_frame);
+        e.add_trace(name, vm.env.stack.current_args())
+    })
 }
 
+defvar!(BYTE_CODE_TRACE);
+defvar!(BYTE_CODE_TRACE_LIMIT);
+
 #[cfg(test)]
 mod test {
     use crate::core::{
+        cons::Cons,
         gc::RootSet,
         object::{HashTable, IntoObject},
     };
@@ -944,14 +1015,11 @@ mod test {
         };
         // TODO: we should probably caculate the actual depth
         let depth = 10;
-        let bytecode = crate::alloc::make_byte_code(
+        let bytecode = crate::alloc::new_byte_code(
             $arglist,
             &opcodes,
-            constants,
+            constants.to_vec(),
             depth,
-            None,
-            None,
-            &[],
             cx1
         ).unwrap();
         root!(bytecode, cx1);
@@ -998,6 +1066,32 @@ mod test {
         assert_eq!(val, expect);
     }
 
+    #[test]
+    fn test_op_tracer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        assert!(OpTracer::new(env, cx).is_none());
+        env.set_default(sym::BYTE_CODE_TRACE, list!["foo"; cx]).unwrap();
+        env.set_default(sym::BYTE_CODE_TRACE_LIMIT, cx.add(2)).unwrap();
+        let mut tracer = OpTracer::new(env, cx).unwrap();
+        assert_eq!(tracer.functions, Some(vec!["foo".to_owned()]));
+
+        tracer.enter(0, "foo");
+        tracer.trace(OpCode::Constant0, 0, 0, &[]);
+        assert_eq!(tracer.remaining, Some(1));
+        // functions that are not in the list are not traced
+        tracer.enter(1, "bar");
+        tracer.trace(OpCode::Constant0, 0, 1, &[]);
+        assert_eq!(tracer.remaining, Some(1));
+        // bar has returned
+        tracer.trace(OpCode::Return, 1, 0, &[]);
+        assert_eq!(tracer.remaining, Some(0));
+        tracer.trace(OpCode::Return, 1, 0, &[]);
+        assert_eq!(tracer.remaining, Some(0));
+    }
+
     #[test]
     fn test_basic() {
         use OpCode::*;
@@ -1248,4 +1342,28 @@ mod test {
         root!(inner, cx);
         check_bytecode!(outer, [inner], 7, cx);
     }
+
+    #[test]
+    fn test_error_unwinds_frames() {
+        use OpCode as O;
+
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        // (lambda () (floor))
+        make_bytecode!(inner, 0, [O::Constant0, O::Call0, O::Return], [sym::FLOOR], cx);
+        // (lambda (x) (funcall x))
+        make_bytecode!(outer, 257, [O::Duplicate, O::Call0, O::Return], [], cx);
+        let inner = cx.add(inner.bind(cx));
+        root!(inner, cx);
+        root!(env, new(Env), cx);
+        {
+            let frame = &mut CallFrame::new(env);
+            frame.push_arg(inner.bind(cx)).unwrap();
+            frame.finalize_arguments();
+            assert!(call(outer, frame.arg_count(), "test", frame, cx).is_err());
+        }
+        assert_eq!(env.stack.current_frame(), 0);
+        assert_eq!(env.stack.len(), 0);
+    }
 }
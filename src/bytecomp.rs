@@ -0,0 +1,1612 @@
+//! Warnings from the byte compiler. Before compiling, each form is macro
+//! expanded and walked to find unused lexical variables, references to free
+//! variables, calls with the wrong number of arguments, and calls to obsolete
+//! functions. The kinds of warnings reported are controlled by
+//! `byte-compile-warnings`, as in Emacs. Functions can also be compiled as
+//! they are defined, see `compile-on-define`. When `byte-optimize` is on,
+//! calls to functions defined with `defsubst` are inlined and references to
+//! variables defined with `defconst` are replaced with their values before
+//! compiling, and calls to functions declared `pure` whose arguments are all
+//! constant are replaced with their values. Top level `eval-when-compile` and
+//! `eval-and-compile` forms are evaluated as the checker reaches them, as the
+//! compiler does. Calls to functions with a `compiler-macro` property are
+//! replaced by the expansion the handler returns, so the code that is
+//! actually compiled is what gets checked.
+use crate::core::{
+    cons::Cons,
+    env::{sym, CallFrame, Env},
+    gc::{Context, Rt, Rto, Slot},
+    object::{Function, FunctionType, Gc, LispString, List, Object, ObjectType, Symbol, NIL},
+};
+use crate::eval::EvalError;
+use crate::reader;
+use anyhow::{bail, Result};
+use rune_core::hashmap::{HashMap, HashSet};
+use rune_core::macros::{call, list, rebind, root};
+use rune_macros::defun;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WarningKind {
+    FreeVars,
+    Lexical,
+    Callargs,
+    Obsolete,
+    IgnoredReturnValue,
+}
+
+impl WarningKind {
+    const ALL: [Self; 5] = [
+        Self::FreeVars,
+        Self::Lexical,
+        Self::Callargs,
+        Self::Obsolete,
+        Self::IgnoredReturnValue,
+    ];
+
+    /// The name of this kind in `byte-compile-warnings`.
+    fn name(self) -> &'static str {
+        match self {
+            Self::FreeVars => "free-vars",
+            Self::Lexical => "lexical",
+            Self::Callargs => "callargs",
+            Self::Obsolete => "obsolete",
+            Self::IgnoredReturnValue => "ignored-return-value",
+        }
+    }
+}
+
+/// A warning at a position in the source. Lines and columns start at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Warning {
+    pub(crate) kind: WarningKind,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    pub(crate) message: String,
+}
+
+impl Warning {
+    fn new(kind: WarningKind, source: &str, offset: usize, message: String) -> Self {
+        let (line, column) = line_column(source, offset);
+        Self { kind, line, column, message }
+    }
+}
+
+/// The line and column of byte `offset` in `source`, both starting at 1.
+pub(crate) fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |x| x + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: Warning: {}", self.line, self.column, self.message)
+    }
+}
+
+/// The kinds of warnings enabled by `byte-compile-warnings`. It is either t
+/// for all of them, a list of the kinds to enable, or a list starting with
+/// `not` of the kinds to disable.
+fn enabled_warnings(env: &Rt<Env>, cx: &Context) -> Vec<WarningKind> {
+    let Some(value) = env.var(sym::BYTE_COMPILE_WARNINGS, cx) else {
+        return WarningKind::ALL.to_vec();
+    };
+    let Ok(list) = value.bind(cx).as_list() else { return WarningKind::ALL.to_vec() };
+    let names: Vec<String> = list.flatten().map(|x| x.to_string()).collect();
+    match names.split_first() {
+        Some((not, disabled)) if not == "not" => WarningKind::ALL
+            .into_iter()
+            .filter(|x| !disabled.iter().any(|name| name == x.name()))
+            .collect(),
+        _ => WarningKind::ALL
+            .into_iter()
+            .filter(|x| names.iter().any(|name| name == x.name()))
+            .collect(),
+    }
+}
+
+/// The number of arguments a function takes, with `None` for no maximum.
+type Arity = (usize, Option<usize>);
+
+fn arglist_arity(arglist: Object) -> Option<Arity> {
+    let (required, optional, rest) = crate::interpreter::parse_arg_list(arglist).ok()?;
+    let max = rest.is_none().then_some(required.len() + optional.len());
+    Some((required.len(), max))
+}
+
+/// Functions and special variables defined earlier in the file being checked.
+#[derive(Default)]
+struct FileScope {
+    specials: HashSet<String>,
+    arities: HashMap<String, Arity>,
+    /// Functions declared obsolete, with the rest of the warning message
+    obsolete: HashMap<String, String>,
+    /// Functions declared `pure` or `side-effect-free`
+    side_effect_free: HashSet<String>,
+}
+
+impl FileScope {
+    /// Record a `declare` spec, or the property it sets, for `func`.
+    fn declare(&mut self, func: Symbol, prop: &str, values: &[Object]) {
+        let name = func.name().to_owned();
+        match prop {
+            "obsolete" => {
+                let suffix = obsolete_suffix(values.first().copied(), values.get(1).copied());
+                self.obsolete.insert(name, suffix);
+            }
+            "pure" | "side-effect-free" if values.first().is_some_and(|x| !x.is_nil()) => {
+                self.side_effect_free.insert(name);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The part of an obsolete function warning that follows the function name.
+fn obsolete_suffix(new: Option<Object>, when: Option<Object>) -> String {
+    let when = match when {
+        Some(when) if !when.is_nil() => format!(" (as of {})", display(when)),
+        _ => String::new(),
+    };
+    let instead = match new.map(|x| x.untag()) {
+        None | Some(ObjectType::NIL) => String::new(),
+        Some(ObjectType::String(advice)) => format!("; {advice}"),
+        Some(new) => format!("; use `{new}' instead"),
+    };
+    format!("{when}{instead}")
+}
+
+struct Binding<'ob> {
+    name: Symbol<'ob>,
+    argument: bool,
+    used: bool,
+}
+
+/// Walks a macro expanded form, collecting warnings along with the name of the
+/// symbol they are about, which is used to find their position.
+struct Checker<'a, 'ob> {
+    lexical: bool,
+    scope: Vec<Binding<'ob>>,
+    file: &'a mut FileScope,
+    found: Vec<(WarningKind, String, String)>,
+    env: &'a Rt<Env>,
+    cx: &'ob Context,
+}
+
+impl<'a, 'ob> Checker<'a, 'ob> {
+    fn warn(&mut self, kind: WarningKind, name: Symbol, message: String) {
+        self.found.push((kind, name.name().to_owned(), message));
+    }
+
+    fn is_special(&self, var: Symbol) -> bool {
+        var.is_special()
+            || self.env.vars.get(var).is_some()
+            || self.file.specials.contains(var.name())
+    }
+
+    fn form(&mut self, form: Object<'ob>) {
+        match form.untag() {
+            ObjectType::Symbol(var) => self.variable(var),
+            ObjectType::Cons(cons) => self.sexp(cons),
+            _ => {}
+        }
+    }
+
+    fn body(&mut self, forms: Object<'ob>) {
+        for form in elements(forms) {
+            self.form(form);
+        }
+    }
+
+    /// Check a body where only the value of the last form is used.
+    fn progn(&mut self, forms: Object<'ob>) {
+        let forms = elements(forms);
+        if let Some((last, rest)) = forms.split_last() {
+            rest.iter().for_each(|x| self.effect(*x));
+            self.form(*last);
+        }
+    }
+
+    /// Check `count` forms whose values are used, followed by a body.
+    fn values_then_progn(&mut self, forms: Object<'ob>, count: usize) {
+        let mut forms = forms;
+        for _ in 0..count {
+            let ObjectType::Cons(cons) = forms.untag() else { return };
+            self.form(cons.car());
+            forms = cons.cdr();
+        }
+        self.progn(forms);
+    }
+
+    /// Check a form whose value is not used. Calls to functions without side
+    /// effects do nothing there.
+    fn effect(&mut self, form: Object<'ob>) {
+        if let ObjectType::Cons(cons) = form.untag() {
+            if let ObjectType::Symbol(func) = cons.car().untag() {
+                if self.side_effect_free(func) {
+                    let message = format!("value from call to `{func}' is unused");
+                    self.warn(WarningKind::IgnoredReturnValue, func, message);
+                }
+            }
+        }
+        self.form(form);
+    }
+
+    fn side_effect_free(&self, func: Symbol) -> bool {
+        if self.file.side_effect_free.contains(func.name()) {
+            return true;
+        }
+        [sym::PURE, sym::SIDE_EFFECT_FREE]
+            .into_iter()
+            .any(|prop| !crate::data::get(func, prop, self.env, self.cx).is_nil())
+    }
+
+    fn variable(&mut self, var: Symbol<'ob>) {
+        if var.is_const() {
+            return;
+        }
+        if let Some(binding) = self.scope.iter_mut().rev().find(|x| x.name == var) {
+            binding.used = true;
+        } else if self.lexical && !self.is_special(var) {
+            self.warn(WarningKind::FreeVars, var, format!("reference to free variable `{var}'"));
+        }
+    }
+
+    fn sexp(&mut self, cons: &'ob Cons) {
+        let args = cons.cdr();
+        let head = match cons.car().untag() {
+            ObjectType::Symbol(head) => head,
+            ObjectType::Cons(lambda) if lambda.car() == sym::LAMBDA => {
+                self.lambda(lambda.cdr());
+                self.body(args);
+                return;
+            }
+            _ => return,
+        };
+        match head {
+            sym::QUOTE | sym::INTERACTIVE | sym::CL_DESTRUCTURING_BIND => {}
+            sym::FUNCTION => {
+                if let Some(ObjectType::Cons(lambda)) = elements(args).first().map(|x| x.untag()) {
+                    if lambda.car() == sym::LAMBDA {
+                        self.lambda(lambda.cdr());
+                    }
+                }
+            }
+            sym::LAMBDA => self.lambda(args),
+            sym::SETQ => self.setq(args),
+            sym::LET => self.let_form(args, true),
+            sym::LET_STAR => self.let_form(args, false),
+            sym::COND => {
+                for clause in elements(args) {
+                    if let ObjectType::Cons(clause) = clause.untag() {
+                        self.form(clause.car());
+                        self.progn(clause.cdr());
+                    }
+                }
+            }
+            sym::CONDITION_CASE => self.condition_case(args),
+            sym::CL_LETF | sym::CL_LETF_STAR => {
+                let ObjectType::Cons(args) = args.untag() else { return };
+                for binding in elements(args.car()) {
+                    // The arguments of a place are evaluated, as is its value
+                    if let [place, value @ ..] = elements(binding).as_slice() {
+                        if let ObjectType::Cons(place) = place.untag() {
+                            self.body(place.cdr());
+                        }
+                        value.iter().for_each(|x| self.form(*x));
+                    }
+                }
+                self.progn(args.cdr());
+            }
+            sym::DEFVAR | sym::DEFCONST | sym::DEFCUSTOM | sym::DEFGROUP => {
+                let mut args = elements(args).into_iter();
+                if let Some(ObjectType::Symbol(var)) = args.next().map(|x| x.untag()) {
+                    if head != sym::DEFGROUP {
+                        self.file.specials.insert(var.name().to_owned());
+                    }
+                }
+                args.for_each(|x| self.form(x));
+            }
+            sym::PROGN | sym::INLINE | sym::SAVE_CURRENT_BUFFER | sym::SAVE_EXCURSION => {
+                self.progn(args);
+            }
+            sym::WHEN | sym::UNLESS | sym::CATCH => self.values_then_progn(args, 1),
+            sym::IF => self.values_then_progn(args, 2),
+            sym::AND | sym::OR | sym::THROW => self.body(args),
+            sym::WHILE | sym::PROG1 | sym::UNWIND_PROTECT => {
+                let args = elements(args);
+                if let Some((first, rest)) = args.split_first() {
+                    self.form(*first);
+                    rest.iter().for_each(|x| self.effect(*x));
+                }
+            }
+            sym::PROG2 => {
+                for (i, arg) in elements(args).into_iter().enumerate() {
+                    if i == 1 {
+                        self.form(arg);
+                    } else {
+                        self.effect(arg);
+                    }
+                }
+            }
+            // Only seen when macroexpand-all is not defined yet
+            _ if matches!(head.name(), "defun" | "defmacro") => self.defun(args),
+            _ if head.name() == "declare" => {}
+            _ => self.call(head, args),
+        }
+    }
+
+    fn lambda(&mut self, lambda: Object<'ob>) {
+        let ObjectType::Cons(lambda) = lambda.untag() else { return };
+        let Ok((required, optional, rest)) = crate::interpreter::parse_arg_list(lambda.car())
+        else {
+            return;
+        };
+        let depth = self.scope.len();
+        for name in required.into_iter().chain(optional).chain(rest) {
+            self.scope.push(Binding { name, argument: true, used: false });
+        }
+        self.progn(lambda.cdr());
+        self.pop_scope(depth);
+    }
+
+    fn defun(&mut self, args: Object<'ob>) {
+        let ObjectType::Cons(args) = args.untag() else { return };
+        if let ObjectType::Symbol(name) = args.car().untag() {
+            if let Some(arglist) = elements(args.cdr()).first() {
+                if let Some(arity) = arglist_arity(*arglist) {
+                    self.file.arities.insert(name.name().to_owned(), arity);
+                }
+            }
+            if let ObjectType::Cons(lambda) = args.cdr().untag() {
+                self.declarations(name, lambda.cdr());
+            }
+        }
+        self.lambda(args.cdr());
+    }
+
+    /// Record the `declare` forms at the start of a function body. When
+    /// `defun` is expanded they become calls to `make-obsolete` and
+    /// `function-put` instead, which are handled by [`Self::property`].
+    fn declarations(&mut self, func: Symbol, body: Object<'ob>) {
+        for form in elements(body) {
+            let form = match form.untag() {
+                ObjectType::String(_) => continue,
+                ObjectType::Cons(form) => form,
+                _ => break,
+            };
+            match form.car().untag() {
+                ObjectType::Symbol(sym::INTERACTIVE) => continue,
+                ObjectType::Symbol(head) if head.name() == "declare" => {}
+                _ => break,
+            }
+            for spec in elements(form.cdr()) {
+                let spec = elements(spec);
+                if let Some((ObjectType::Symbol(prop), values)) =
+                    spec.split_first().map(|(x, rest)| (x.untag(), rest))
+                {
+                    self.file.declare(func, prop.name(), values);
+                }
+            }
+        }
+    }
+
+    /// Record the properties set at compile time by `make-obsolete` and
+    /// `function-put`, like the byte compiler does.
+    fn property(&mut self, func: Symbol, args: &[Object<'ob>]) {
+        let constant = |x: &Object<'ob>| match x.untag() {
+            ObjectType::Cons(_) => quoted(*x),
+            ObjectType::Symbol(var) if !var.is_const() => None,
+            _ => Some(*x),
+        };
+        let args: Vec<_> = args.iter().map(constant).collect();
+        let Some(Some(target)) = args.first() else { return };
+        let ObjectType::Symbol(target) = target.untag() else { return };
+        match func.name() {
+            "make-obsolete" => {
+                let values: Vec<_> = args[1..].iter().map(|x| x.unwrap_or(NIL)).collect();
+                self.file.declare(target, "obsolete", &values);
+            }
+            "function-put" | "put" => {
+                if let [_, Some(prop), Some(value)] = args[..] {
+                    if let ObjectType::Symbol(prop) = prop.untag() {
+                        self.file.declare(target, prop.name(), &[value]);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Record the arity of `(defalias 'NAME #'(lambda ARGS ...))` before
+    /// checking the body, so that recursive calls are checked too.
+    fn defalias(&mut self, args: &[Object<'ob>]) {
+        let [name, definition, ..] = args else { return };
+        let (Some(name), Some(lambda)) = (quoted(*name), quoted(*definition)) else { return };
+        let (ObjectType::Symbol(name), ObjectType::Cons(lambda)) = (name.untag(), lambda.untag())
+        else {
+            return;
+        };
+        if lambda.car() != sym::LAMBDA {
+            return;
+        }
+        if let Some(arity) = elements(lambda.cdr()).first().and_then(|x| arglist_arity(*x)) {
+            self.file.arities.insert(name.name().to_owned(), arity);
+        }
+    }
+
+    fn pop_scope(&mut self, depth: usize) {
+        let unused: Vec<_> = self.scope.drain(depth..).filter(|x| !x.used).collect();
+        if !self.lexical {
+            return;
+        }
+        for binding in unused {
+            let name = binding.name;
+            if name.name().starts_with('_') || self.is_special(name) {
+                continue;
+            }
+            let what = if binding.argument { "argument" } else { "variable" };
+            self.warn(WarningKind::Lexical, name, format!("Unused lexical {what} `{name}'"));
+        }
+    }
+
+    fn let_form(&mut self, args: Object<'ob>, parallel: bool) {
+        let ObjectType::Cons(args) = args.untag() else { return };
+        let depth = self.scope.len();
+        let mut pending = Vec::new();
+        for binding in elements(args.car()) {
+            let name = match binding.untag() {
+                ObjectType::Symbol(name) => name,
+                ObjectType::Cons(binding) => {
+                    if let Some(init) = elements(binding.cdr()).first() {
+                        self.form(*init);
+                    }
+                    let ObjectType::Symbol(name) = binding.car().untag() else { continue };
+                    name
+                }
+                _ => continue,
+            };
+            let binding = Binding { name, argument: false, used: false };
+            if parallel {
+                pending.push(binding);
+            } else {
+                self.scope.push(binding);
+            }
+        }
+        self.scope.extend(pending);
+        self.progn(args.cdr());
+        self.pop_scope(depth);
+    }
+
+    fn setq(&mut self, args: Object<'ob>) {
+        let args = elements(args);
+        for pair in args.chunks(2) {
+            if let Some(value) = pair.get(1) {
+                self.form(*value);
+            }
+            let ObjectType::Symbol(var) = pair[0].untag() else { continue };
+            let bound = self.scope.iter().any(|x| x.name == var);
+            if !bound && self.lexical && !var.is_const() && !self.is_special(var) {
+                let message = format!("assignment to free variable `{var}'");
+                self.warn(WarningKind::FreeVars, var, message);
+            }
+        }
+    }
+
+    fn condition_case(&mut self, args: Object<'ob>) {
+        let args = elements(args);
+        let [var, protected, handlers @ ..] = &args[..] else { return };
+        self.form(*protected);
+        let depth = self.scope.len();
+        if let ObjectType::Symbol(name) = var.untag() {
+            if name != sym::NIL {
+                self.scope.push(Binding { name, argument: false, used: true });
+            }
+        }
+        for handler in handlers {
+            if let ObjectType::Cons(handler) = handler.untag() {
+                self.progn(handler.cdr());
+            }
+        }
+        self.pop_scope(depth);
+    }
+
+    fn call(&mut self, func: Symbol<'ob>, args: Object<'ob>) {
+        // A macro that was not expanded, so its arguments are not forms
+        if crate::eval::get_macro_func(func, self.cx).is_some() {
+            return;
+        }
+        let args = elements(args);
+        if func == sym::DEFALIAS {
+            self.defalias(&args);
+        }
+        self.property(func, &args);
+        self.check_obsolete(func);
+        self.check_arity(func, args.len());
+        for arg in args {
+            self.form(arg);
+        }
+    }
+
+    fn check_obsolete(&mut self, func: Symbol<'ob>) {
+        let suffix = match self.file.obsolete.get(func.name()) {
+            Some(suffix) => suffix.clone(),
+            None => {
+                let info = crate::data::get(func, sym::BYTE_OBSOLETE_INFO, self.env, self.cx);
+                let ObjectType::Cons(info) = info.untag() else { return };
+                let info: Vec<_> = info.elements().flatten().collect();
+                obsolete_suffix(info.first().copied(), info.get(2).copied())
+            }
+        };
+        let message = format!("`{func}' is an obsolete function{suffix}.");
+        self.warn(WarningKind::Obsolete, func, message);
+    }
+
+    fn check_arity(&mut self, func: Symbol<'ob>, count: usize) {
+        let arity = match self.file.arities.get(func.name()) {
+            Some(arity) => *arity,
+            None => {
+                let Some(function) = func.follow_indirect(self.cx) else { return };
+                let Ok(arity) = crate::eval::func_arity(function, self.cx) else { return };
+                let count = |x: Object| match x.untag() {
+                    ObjectType::Int(x) => usize::try_from(x).ok(),
+                    _ => None,
+                };
+                let Some(min) = count(arity.car()) else { return };
+                (min, count(arity.cdr()))
+            }
+        };
+        let (min, max) = arity;
+        let verb = if count < min {
+            "requires"
+        } else if max.is_some_and(|max| count > max) {
+            "accepts only"
+        } else {
+            return;
+        };
+        let expected = match max {
+            None => format!("{min}+"),
+            Some(max) if max == min => format!("{min}"),
+            Some(max) => format!("{min}-{max}"),
+        };
+        let plural = if count == 1 { "" } else { "s" };
+        let message =
+            format!("`{func}' called with {count} argument{plural}, but {verb} {expected}");
+        self.warn(WarningKind::Callargs, func, message);
+    }
+}
+
+/// The elements of a list, ignoring an improper tail.
+fn elements(list: Object) -> Vec<Object> {
+    list.as_list().map(|x| x.flatten().collect()).unwrap_or_default()
+}
+
+/// The argument of a `quote` or `function` form.
+fn quoted(form: Object) -> Option<Object> {
+    let ObjectType::Cons(cons) = form.untag() else { return None };
+    if cons.car() != sym::QUOTE && cons.car() != sym::FUNCTION {
+        return None;
+    }
+    elements(cons.cdr()).first().copied()
+}
+
+/// Strings are shown without quotes.
+fn display(obj: Object) -> String {
+    match obj.untag() {
+        ObjectType::String(s) => s.to_string(),
+        _ => obj.to_string(),
+    }
+}
+
+/// Find the first occurrence of the symbol `name` in `text`.
+pub(crate) fn locate(text: &str, name: &str) -> Option<usize> {
+    if name.is_empty() {
+        return None;
+    }
+    text.match_indices(name).map(|(i, _)| i).find(|&i| {
+        let before = text[..i].chars().next_back();
+        let after = text[i + name.len()..].chars().next();
+        !before.is_some_and(reader::symbol_char) && !after.is_some_and(reader::symbol_char)
+    })
+}
+
+/// Check the forms in `source`, the contents of a lisp file, and return the
+/// warnings in the order they were found.
+pub(crate) fn check_source(
+    source: &str,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<Vec<Warning>> {
+    let lexical = crate::lread::file_local_variables(source, cx)?
+        .into_iter()
+        .rev()
+        .find_map(|(var, value)| (var == sym::LEXICAL_BINDING).then_some(value != NIL))
+        .unwrap_or(false);
+    // Macros expand differently depending on the binding mode
+    env.varbind(sym::LEXICAL_BINDING, lexical.into(), cx);
+    let result = check_forms(source, lexical, env, cx);
+    env.unbind(1, cx);
+    result
+}
+
+/// Evaluate the body of a top level `eval-when-compile` or `eval-and-compile`
+/// form when the compiler reaches it, so the macros and variables it defines
+/// are known to the rest of the file. The body is evaluated in the binding
+/// mode of the file. Return what is left to run when the file is loaded:
+/// nothing for `eval-when-compile`, whose value is quoted in its place, and
+/// the whole body for `eval-and-compile`. The body is only expanded once, and
+/// what is returned is already expanded, so macros that run code as they
+/// expand, like a nested `eval-when-compile`, don't run it again.
+fn eval_at_compile_time<'ob>(
+    form: &Rto<Object>,
+    lexical: bool,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Option<Object<'ob>>> {
+    let ObjectType::Cons(cons) = form.bind(cx).untag() else { return Ok(None) };
+    let head = cons.car();
+    if head != sym::EVAL_WHEN_COMPILE && head != sym::EVAL_AND_COMPILE {
+        return Ok(None);
+    }
+    let compile_only = head == sym::EVAL_WHEN_COMPILE;
+    let body: Object = Cons::new(sym::PROGN, cons.cdr(), cx).into();
+    root!(body, cx);
+    let expanded = rebind!(expand(body, env, cx)?);
+    body.set(expanded);
+    let value = rebind!(crate::interpreter::eval_in_mode(body, None, lexical, env, cx)?);
+    if compile_only {
+        Ok(Some(list![sym::QUOTE, value; cx]))
+    } else {
+        Ok(Some(body.bind(cx)))
+    }
+}
+
+/// Macro expand `form` with `macroexpand-all` once it is loaded. Before that
+/// only the calls to functions with a compiler macro are expanded.
+fn expand<'ob>(form: &Rto<Object>, env: &mut Rt<Env>, cx: &'ob mut Context) -> Result<Object<'ob>> {
+    match sym::MACROEXPAND_ALL.func(cx) {
+        Some(func) => {
+            root!(func, cx);
+            Ok(rebind!(call!(func, form; env, cx)?))
+        }
+        None => expand_compiler_macros(form, env, cx),
+    }
+}
+
+/// Expand the calls in `form` to functions with a `compiler-macro` property,
+/// as `macroexpand-all` does once it is loaded. The handler is called with
+/// the whole form followed by its arguments, and a handler that returns the
+/// form unchanged declines to expand it. Quoted forms are left alone.
+fn expand_compiler_macros<'ob>(
+    form: &Rto<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let ObjectType::Cons(cons) = form.untag(cx) else { return Ok(form.bind(cx)) };
+    let head = cons.car();
+    if head == sym::QUOTE || head == sym::FUNCTION {
+        return Ok(form.bind(cx));
+    }
+    if let ObjectType::Symbol(func) = head.untag() {
+        let handler = crate::data::get(func, sym::COMPILER_MACRO, env, cx);
+        if let Ok(handler) = Function::try_from(handler) {
+            root!(handler, cx);
+            let expanded = {
+                let frame = &mut CallFrame::new(env);
+                frame.push_arg(form.bind(cx))?;
+                for arg in elements(cons.cdr()) {
+                    frame.push_arg(arg)?;
+                }
+                rebind!(crate::eval::call_function(handler, frame, None, cx)?)
+            };
+            if !crate::fns::eq(expanded, form.bind(cx)) {
+                root!(expanded, cx);
+                return Ok(rebind!(expand_compiler_macros(expanded, env, cx)?));
+            }
+        }
+    }
+    root!(parts, new(Vec), cx);
+    parts.extend_from_slice(&elements(form.bind(cx)));
+    let mut changed = false;
+    for i in 0..parts.len() {
+        let expanded = rebind!(expand_compiler_macros(&parts[i], env, cx)?);
+        if !crate::fns::eq(expanded, parts[i].bind(cx)) {
+            parts[i].set(expanded);
+            changed = true;
+        }
+    }
+    if changed {
+        Ok(crate::fns::slice_into_list(Rt::bind_slice(parts, cx), None, cx))
+    } else {
+        Ok(form.bind(cx))
+    }
+}
+
+fn check_forms(
+    source: &str,
+    lexical: bool,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<Vec<Warning>> {
+    let enabled = enabled_warnings(env, cx);
+    let mut file = FileScope::default();
+    let mut warnings = Vec::new();
+    let mut pos = 0;
+    loop {
+        let (obj, len) = match reader::read(&source[pos..], cx) {
+            Ok(x) => x,
+            Err(reader::Error::EmptyStream) => return Ok(warnings),
+            Err(mut e) => {
+                e.update_pos(pos);
+                bail!(e);
+            }
+        };
+        let start = pos + crate::lread::form_offset(&source[pos..]);
+        let end = pos + len;
+        pos = end;
+        root!(obj, cx);
+        match eval_at_compile_time(obj, lexical, env, cx) {
+            Ok(Some(form)) => obj.set(form),
+            Ok(None) => {
+                // If expansion fails the form is checked as written
+                if let Ok(expanded) = expand(obj, env, cx) {
+                    obj.set(expanded);
+                }
+            }
+            // The rest of the file may depend on what the form defines, so
+            // checking stops here, as compiling does in Emacs
+            Err(e) => {
+                let (line, column) = line_column(source, start);
+                let message = match e.downcast_ref::<EvalError>() {
+                    Some(e) => e.message(env, cx),
+                    None => e.to_string(),
+                };
+                bail!("{line}:{column}: Error: {message}");
+            }
+        }
+        let mut checker =
+            Checker { lexical, scope: Vec::new(), file: &mut file, found: Vec::new(), env, cx };
+        checker.form(obj.bind(cx));
+        // Warnings are mostly found in source order, so look for each one
+        // after the previous one first
+        let text = &source[start..end];
+        let mut cursor = 0;
+        for (kind, name, message) in checker.found {
+            if !enabled.contains(&kind) {
+                continue;
+            }
+            let offset = match locate(&text[cursor..], &name) {
+                Some(offset) => {
+                    cursor += offset;
+                    cursor
+                }
+                None => locate(text, &name).unwrap_or(0),
+            };
+            warnings.push(Warning::new(kind, source, start + offset, message));
+        }
+    }
+}
+
+#[defun]
+fn byte_compile_check_file(
+    file: &Rto<Gc<&LispString>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<usize> {
+    let file = file.untag(cx).to_string();
+    let source = std::fs::read_to_string(&file)?;
+    let warnings = check_source(&source, env, cx)?;
+    for warning in &warnings {
+        println!("{file}:{warning}");
+    }
+    Ok(warnings.len())
+}
+
+fn interpreted(symbol: Symbol, cx: &Context) -> bool {
+    match symbol.func(cx).map(|x| x.untag()) {
+        Some(FunctionType::Closure(_)) => true,
+        Some(FunctionType::Cons(cons)) => cons.car() == sym::LAMBDA,
+        _ => false,
+    }
+}
+
+/// Called when `symbol` is given a new function definition. If it is
+/// interpreted and `compile-on-define` is set, it is compiled now, or on its
+/// first call when `compile-on-define` is `lazy`.
+pub(crate) fn compile_on_define(symbol: &Rto<Symbol>, env: &mut Rt<Env>, cx: &mut Context) {
+    let symbol_ref = symbol.bind(cx);
+    symbol_ref.set_compile_pending(false);
+    let Some(policy) = env.vars.get(sym::COMPILE_ON_DEFINE).map(|x| x.bind(cx)) else {
+        return;
+    };
+    if policy.is_nil() || !interpreted(symbol_ref, cx) {
+        return;
+    }
+    if policy == sym::LAZY {
+        symbol_ref.set_compile_pending(true);
+    } else {
+        compile(symbol, env, cx);
+    }
+}
+
+/// Compile `symbol` if it is waiting for its first call.
+pub(crate) fn compile_pending(symbol: &Rto<Symbol>, env: &mut Rt<Env>, cx: &mut Context) {
+    if symbol.bind(cx).take_compile_pending() {
+        compile(symbol, env, cx);
+    }
+}
+
+fn compile(symbol: &Rto<Symbol>, env: &mut Rt<Env>, cx: &mut Context) {
+    let Some(func) = sym::BYTE_COMPILE.func(cx) else { return };
+    if !interpreted(symbol.bind(cx), cx) {
+        return;
+    }
+    root!(func, cx);
+    // Helpers defined by the compiler are not compiled in turn
+    env.varbind(sym::COMPILE_ON_DEFINE, NIL, cx);
+    let result = call!(func, symbol; env, cx).map(|_| ());
+    if let Err(e) = result {
+        // The interpreted definition is kept
+        let message = format!("Failed to compile `{}': {e}", symbol.bind(cx));
+        display_warning(message, env, cx);
+    }
+    env.unbind(1, cx);
+}
+
+/// Report `message` as a warning of type `bytecomp`, with `display-warning`
+/// if it is defined and otherwise by adding it to `delayed-warnings-list`.
+fn display_warning(message: String, env: &mut Rt<Env>, cx: &mut Context) {
+    let message = cx.add(message);
+    root!(message, cx);
+    let (kind, level): (Object, Object) = (sym::BYTECOMP.into(), sym::KW_WARNING.into());
+    if let Some(func) = sym::DISPLAY_WARNING.func(cx) {
+        root!(func, cx);
+        if call!(func, kind, message.bind(cx), level; env, cx).is_ok() {
+            return;
+        }
+    }
+    let warning = list![kind, message.bind(cx), level; cx];
+    let delayed = env.var(sym::DELAYED_WARNINGS_LIST, cx).map_or(NIL, |x| x.bind(cx));
+    let delayed = Cons::new(warning, delayed, cx);
+    // The list is a plain special variable, so setting it can't fail
+    let _ = env.set_var(sym::DELAYED_WARNINGS_LIST, delayed.into(), cx);
+}
+
+defsym!(BYTECOMP);
+defsym!(DISPLAY_WARNING);
+defsym!(KW_WARNING);
+defvar!(DELAYED_WARNINGS_LIST);
+
+/// Whether `value` can replace a reference to a variable without being
+/// quoted.
+fn self_evaluating(value: Object) -> bool {
+    match value.untag() {
+        ObjectType::Int(_) | ObjectType::Float(_) | ObjectType::String(_) => true,
+        ObjectType::Symbol(symbol) => symbol.is_const(),
+        _ => false,
+    }
+}
+
+/// Whether `symbol` appears anywhere in `obj`.
+fn mentions(obj: Object, symbol: Symbol) -> bool {
+    match obj.untag() {
+        ObjectType::Symbol(x) => x == symbol,
+        ObjectType::Cons(cons) => mentions(cons.car(), symbol) || mentions(cons.cdr(), symbol),
+        _ => false,
+    }
+}
+
+/// Whether `form` binds or sets `var` anywhere, in which case a reference to
+/// `var` could see a value other than its constant one.
+fn binds(form: Object, var: Symbol) -> bool {
+    let ObjectType::Cons(cons) = form.untag() else { return false };
+    let args = elements(cons.cdr());
+    let is_var = |x: &Object| *x == var;
+    let binds_here = match cons.car().untag() {
+        ObjectType::Symbol(sym::LET | sym::LET_STAR) => args.first().is_some_and(|bindings| {
+            elements(*bindings).iter().any(|binding| match binding.untag() {
+                ObjectType::Cons(binding) => binding.car() == var,
+                _ => is_var(binding),
+            })
+        }),
+        ObjectType::Symbol(sym::LAMBDA) => {
+            args.first().is_some_and(|arglist| elements(*arglist).iter().any(is_var))
+        }
+        ObjectType::Symbol(sym::CONDITION_CASE) => args.first().is_some_and(is_var),
+        ObjectType::Symbol(sym::SETQ) => args.iter().step_by(2).any(is_var),
+        _ => false,
+    };
+    binds_here || (cons.car() != sym::QUOTE && elements(form).iter().any(|x| binds(*x, var)))
+}
+
+/// Replaces references to constants, and calls to functions defined with
+/// `defsubst`, in a macro expanded form.
+struct Substituter<'a, 'ob> {
+    constants: Vec<(Symbol<'ob>, Object<'ob>)>,
+    /// Variables bound around the form being walked, which hide constants of
+    /// the same name
+    bound: Vec<Symbol<'ob>>,
+    /// Set when calls to defsubsts are inlined, along with whether the form
+    /// uses lexical binding and the alist of the functions defined in the
+    /// compilation unit
+    inline: Option<(&'a Rt<Env>, bool, Object<'ob>)>,
+    /// The defsubsts being inlined, so a recursive one is only inlined once
+    inlining: Vec<Symbol<'ob>>,
+    /// Set when calls to pure functions are folded, along with the calls
+    /// that have been evaluated and their values
+    fold: Option<(&'a Rt<Env>, Vec<(Object<'ob>, Object<'ob>)>)>,
+    /// The calls to pure functions with constant arguments found in the form
+    foldable: Vec<Object<'ob>>,
+    cx: &'ob Context,
+}
+
+impl<'ob> Substituter<'_, 'ob> {
+    fn form(&mut self, form: Object<'ob>) -> Object<'ob> {
+        match form.untag() {
+            ObjectType::Symbol(var) if !self.bound.contains(&var) => {
+                self.constants.iter().find(|x| x.0 == var).map_or(form, |x| x.1)
+            }
+            ObjectType::Cons(cons) => self.sexp(cons, form),
+            _ => form,
+        }
+    }
+
+    fn forms(&mut self, forms: &[Object<'ob>]) -> Vec<Object<'ob>> {
+        forms.iter().map(|x| self.form(*x)).collect()
+    }
+
+    fn list(&self, head: Object<'ob>, args: &[Object<'ob>]) -> Object<'ob> {
+        Cons::new(head, crate::fns::slice_into_list(args, None, self.cx), self.cx).into()
+    }
+
+    fn sexp(&mut self, cons: &'ob Cons, form: Object<'ob>) -> Object<'ob> {
+        if let Some((_, folded)) = &self.fold {
+            if let Some((_, value)) = folded.iter().find(|x| crate::fns::eq(x.0, form)) {
+                return match self_evaluating(*value) {
+                    true => *value,
+                    false => list![sym::QUOTE, *value; self.cx],
+                };
+            }
+        }
+        let ObjectType::Symbol(head) = cons.car().untag() else { return form };
+        let args = elements(cons.cdr());
+        let args = match head {
+            sym::QUOTE | sym::INTERACTIVE => return form,
+            sym::FUNCTION => match args.first().map(|x| x.untag()) {
+                Some(ObjectType::Cons(lambda)) if lambda.car() == sym::LAMBDA => {
+                    vec![self.lambda(lambda)]
+                }
+                _ => return form,
+            },
+            sym::LAMBDA => return self.lambda(cons),
+            sym::LET | sym::LET_STAR => return self.let_form(head, &args),
+            sym::SETQ => args
+                .iter()
+                .enumerate()
+                .map(|(i, x)| if i % 2 == 1 { self.form(*x) } else { *x })
+                .collect(),
+            sym::COND => args
+                .iter()
+                .map(|clause| match clause.untag() {
+                    ObjectType::Cons(_) => {
+                        let clause = self.forms(&elements(*clause));
+                        crate::fns::slice_into_list(&clause, None, self.cx)
+                    }
+                    _ => *clause,
+                })
+                .collect(),
+            sym::CONDITION_CASE => {
+                let [var, protected, handlers @ ..] = &args[..] else { return form };
+                let mut args = vec![*var, self.form(*protected)];
+                let depth = self.bound.len();
+                if let ObjectType::Symbol(var) = var.untag() {
+                    self.bound.push(var);
+                }
+                for handler in handlers {
+                    args.push(match handler.untag() {
+                        ObjectType::Cons(handler) => {
+                            let body = self.forms(&elements(handler.cdr()));
+                            self.list(handler.car(), &body)
+                        }
+                        _ => *handler,
+                    });
+                }
+                self.bound.truncate(depth);
+                args
+            }
+            sym::DEFVAR | sym::DEFCONST => match args.split_first() {
+                Some((var, rest)) => std::iter::once(*var).chain(self.forms(rest)).collect(),
+                None => return form,
+            },
+            _ => {
+                let args = self.forms(&args);
+                if let Some(inlined) = self.inline_call(head, &args) {
+                    return inlined;
+                }
+                if self.is_foldable(head, &args) {
+                    self.foldable.push(form);
+                }
+                args
+            }
+        };
+        self.list(head.into(), &args)
+    }
+
+    /// Replace a call to a defsubst with its body, wrapped in a `let` that
+    /// binds its parameters to `args`. The arguments are still evaluated once
+    /// each, in order, before the body. Calls with the wrong number of
+    /// arguments, and functions that can't be inlined in the binding mode of
+    /// the form, are left alone. A definition earlier in the compilation unit
+    /// is used over the one in the running image, which may be out of date.
+    fn inline_call(&mut self, func: Symbol<'ob>, args: &[Object<'ob>]) -> Option<Object<'ob>> {
+        let (env, lexical, definitions) = self.inline?;
+        let optimizer = crate::data::get(func, sym::BYTE_OPTIMIZER, env, self.cx);
+        if optimizer != sym::BYTE_COMPILE_INLINE_EXPAND || self.inlining.contains(&func) {
+            return None;
+        }
+        // The most recent definition comes first
+        let local = elements(definitions).into_iter().find_map(|x| match x.untag() {
+            ObjectType::Cons(definition) if definition.car() == func => Some(definition.cdr()),
+            _ => None,
+        });
+        let (arglist, body) = match local {
+            // Defined in the same file, so in the binding mode of the form
+            Some(lambda) => {
+                let ObjectType::Cons(lambda) = lambda.untag() else { return None };
+                let ObjectType::Cons(rest) = lambda.cdr().untag() else { return None };
+                if lambda.car() != sym::LAMBDA {
+                    return None;
+                }
+                (rest.car(), rest.cdr())
+            }
+            // Closures can only be inlined into lexical code and lambdas into
+            // dynamic code
+            None => match func.func(self.cx)?.untag() {
+                FunctionType::Closure(closure) if lexical && closure.env().is_empty() => {
+                    (closure.arg_list(), closure.body())
+                }
+                FunctionType::Cons(lambda) if !lexical && lambda.car() == sym::LAMBDA => {
+                    let ObjectType::Cons(rest) = lambda.cdr().untag() else { return None };
+                    (rest.car(), rest.cdr())
+                }
+                _ => return None,
+            },
+        };
+        let (required, optional, rest) = crate::interpreter::parse_arg_list(arglist).ok()?;
+        let max = required.len() + optional.len();
+        if args.len() < required.len() || (rest.is_none() && args.len() > max) {
+            return None;
+        }
+        let params: Vec<_> = required.into_iter().chain(optional).chain(rest).collect();
+        // A free variable in the body would refer to a lexical binding at the
+        // call
+        if lexical && self.bound.iter().any(|x| !params.contains(x) && mentions(body, *x)) {
+            return None;
+        }
+        // Copied so that changing the inlined body doesn't change the function
+        let mut body = elements(crate::fns::copy_tree(body, None, self.cx).ok()?);
+        if body.len() > 1 && matches!(body[0].untag(), ObjectType::String(_)) {
+            body.remove(0);
+        }
+        // The interactive spec only matters when the function is called as a
+        // command
+        if body.first().is_some_and(
+            |x| matches!(x.untag(), ObjectType::Cons(form) if form.car() == sym::INTERACTIVE),
+        ) {
+            body.remove(0);
+        }
+        let mut bindings = Vec::new();
+        for (i, param) in params.iter().enumerate() {
+            let value = if i < max {
+                args.get(i).copied().unwrap_or(NIL)
+            } else {
+                match args.get(max..) {
+                    Some(rest_args @ [_, ..]) => self.list(sym::LIST.into(), rest_args),
+                    _ => NIL,
+                }
+            };
+            bindings.push(list![*param, value; self.cx]);
+        }
+        let depth = self.bound.len();
+        self.bound.extend(&params);
+        self.inlining.push(func);
+        let body = self.forms(&body);
+        self.inlining.pop();
+        self.bound.truncate(depth);
+        if bindings.is_empty() {
+            return Some(self.list(sym::PROGN.into(), &body));
+        }
+        let mut let_args = vec![crate::fns::slice_into_list(&bindings, None, self.cx)];
+        let_args.extend(body);
+        Some(self.list(sym::LET.into(), &let_args))
+    }
+
+    /// Whether a call to `func` with `args` can be replaced with its value,
+    /// because `func` is declared `pure` and the arguments are constants. The
+    /// declarations made earlier in the compilation unit are in
+    /// `overriding-plist-environment`.
+    fn is_foldable(&self, func: Symbol, args: &[Object]) -> bool {
+        let Some((env, _)) = &self.fold else { return false };
+        if !args.iter().all(|x| self_evaluating(*x) || quoted(*x).is_some()) {
+            return false;
+        }
+        let overriding = env.vars.get(sym::OVERRIDING_PLIST_ENVIRONMENT).map(|x| x.bind(self.cx));
+        let declared = overriding.into_iter().flat_map(elements).any(|entry| match entry.untag() {
+            ObjectType::Cons(entry) if entry.car() == func => {
+                crate::fns::plist_get(entry.cdr(), sym::PURE.into()).is_ok_and(|x| !x.is_nil())
+            }
+            _ => false,
+        });
+        declared || !crate::data::get(func, sym::PURE, env, self.cx).is_nil()
+    }
+
+    fn lambda(&mut self, lambda: &'ob Cons) -> Object<'ob> {
+        let args = elements(lambda.cdr());
+        let Some((arglist, body)) = args.split_first() else { return lambda.into() };
+        let depth = self.bound.len();
+        for arg in elements(*arglist) {
+            if let ObjectType::Symbol(arg) = arg.untag() {
+                self.bound.push(arg);
+            }
+        }
+        let mut args = vec![*arglist];
+        args.extend(self.forms(body));
+        self.bound.truncate(depth);
+        self.list(sym::LAMBDA.into(), &args)
+    }
+
+    fn let_form(&mut self, head: Symbol<'ob>, args: &[Object<'ob>]) -> Object<'ob> {
+        let Some((bindings, body)) = args.split_first() else {
+            return self.list(head.into(), args);
+        };
+        let depth = self.bound.len();
+        let mut pending = Vec::new();
+        let mut new_bindings = Vec::new();
+        for mut binding in elements(*bindings) {
+            let var = match binding.untag() {
+                ObjectType::Symbol(var) => Some(var),
+                ObjectType::Cons(cons) => {
+                    if let [value] = elements(cons.cdr())[..] {
+                        binding = list![cons.car(), self.form(value); self.cx];
+                    }
+                    match cons.car().untag() {
+                        ObjectType::Symbol(var) => Some(var),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            new_bindings.push(binding);
+            match var {
+                Some(var) if head == sym::LET => pending.push(var),
+                Some(var) => self.bound.push(var),
+                None => {}
+            }
+        }
+        self.bound.extend(pending);
+        let mut args = vec![crate::fns::slice_into_list(&new_bindings, None, self.cx)];
+        args.extend(self.forms(body));
+        self.bound.truncate(depth);
+        self.list(head.into(), &args)
+    }
+}
+
+/// Replace references to constants in `form`, which is macro expanded, with
+/// their values. `constants` is an alist of `(VAR . VALUE)` for the
+/// `defconst`s seen earlier in the compilation unit. Only values that
+/// evaluate to themselves are substituted, and a variable that `form` binds
+/// or sets anywhere is left alone. Called by `byte-compile-preprocess` when
+/// `byte-optimize` is on.
+#[defun]
+fn byte_optimize__substitute_constants<'ob>(
+    form: Object<'ob>,
+    constants: List<'ob>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let mut substituted = Vec::new();
+    for constant in constants.elements() {
+        let ObjectType::Cons(constant) = constant?.untag() else { continue };
+        let ObjectType::Symbol(var) = constant.car().untag() else { continue };
+        // Later definitions come first
+        if substituted.iter().any(|(x, _)| *x == var) {
+            continue;
+        }
+        substituted.push((var, constant.cdr()));
+    }
+    let constants: Vec<_> = substituted
+        .into_iter()
+        .filter(|(var, value)| self_evaluating(*value) && !binds(form, *var))
+        .collect();
+    if constants.is_empty() {
+        return Ok(form);
+    }
+    let mut substituter = Substituter {
+        constants,
+        bound: Vec::new(),
+        inline: None,
+        inlining: Vec::new(),
+        fold: None,
+        foldable: Vec::new(),
+        cx,
+    };
+    Ok(substituter.form(form))
+}
+
+/// Replace calls in `form`, which is macro expanded, to functions defined
+/// with `defsubst` with their bodies. `definitions` is an alist of `(NAME
+/// . (lambda ARGS . BODY))` for the functions defined in the compilation unit
+/// so far. A defsubst is only inlined when it was defined in the binding mode
+/// of `form` and its body can't see variables bound around the call. Called
+/// by `byte-compile-preprocess` when `byte-optimize` is on.
+#[defun]
+fn byte_optimize__inline_defsubsts<'ob>(
+    form: Object<'ob>,
+    definitions: Object<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let lexical = env.vars.get(sym::LEXICAL_BINDING).is_some_and(|x| !x.bind(cx).is_nil());
+    let inline = Some((env, lexical, definitions));
+    Substituter {
+        constants: Vec::new(),
+        bound: Vec::new(),
+        inline,
+        inlining: Vec::new(),
+        fold: None,
+        foldable: Vec::new(),
+        cx,
+    }
+    .form(form)
+}
+
+/// Replace calls in `form`, which is macro expanded, to functions declared
+/// `pure` whose arguments are all constants with the values they return.
+/// Calls are folded from the inside out, so `(+ 1 (* 2 3))` becomes `7`. A
+/// call that signals an error is left to signal it at run time. Called by
+/// `byte-compile-preprocess` when `byte-optimize` is on.
+#[defun]
+fn byte_optimize__fold_pure_calls<'ob>(
+    form: &Rto<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Object<'ob> {
+    root!(form, form.bind(cx), cx);
+    root!(calls, new(Vec<Slot<Object>>), cx);
+    root!(folded, new(Vec<(Slot<Object>, Slot<Object>)>), cx);
+    loop {
+        let mut substituter = Substituter {
+            constants: Vec::new(),
+            bound: Vec::new(),
+            inline: None,
+            inlining: Vec::new(),
+            fold: Some((&*env, Vec::new())),
+            foldable: Vec::new(),
+            cx,
+        };
+        substituter.form(form.bind(cx));
+        calls.truncate(0);
+        calls.extend_from_slice(&substituter.foldable);
+        folded.truncate(0);
+        for i in 0..calls.len() {
+            if let Ok(value) = crate::interpreter::eval(&calls[i], None, env, cx) {
+                let value = rebind!(value, cx);
+                folded.push((calls[i].bind(cx), value));
+            }
+        }
+        if folded.is_empty() {
+            return form.bind(cx);
+        }
+        let values = folded.bind_ref(cx).iter().map(|(call, value)| (**call, **value)).collect();
+        let result = Substituter {
+            constants: Vec::new(),
+            bound: Vec::new(),
+            inline: None,
+            inlining: Vec::new(),
+            fold: Some((&*env, values)),
+            foldable: Vec::new(),
+            cx,
+        }
+        .form(form.bind(cx));
+        form.set(result);
+    }
+}
+
+defvar!(BYTE_COMPILE_WARNINGS, true);
+defvar!(COMPILE_ON_DEFINE);
+defsym!(BYTE_COMPILE);
+defsym!(BYTE_COMPILE_INLINE_EXPAND);
+defsym!(BYTE_OBSOLETE_INFO);
+defsym!(BYTE_OPTIMIZER);
+defsym!(COMPILER_MACRO);
+defsym!(EVAL_AND_COMPILE);
+defsym!(EVAL_WHEN_COMPILE);
+defsym!(LAZY);
+defsym!(MACROEXPAND_ALL);
+defvar!(OVERRIDING_PLIST_ENVIRONMENT);
+defsym!(PURE);
+defsym!(SIDE_EFFECT_FREE);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::rebind;
+
+    fn eval(code: &str, env: &mut Rt<Env>, cx: &mut Context) {
+        let obj = crate::reader::read(code, cx).unwrap().0;
+        root!(obj, cx);
+        rebind!(crate::interpreter::eval(obj, None, env, cx).unwrap());
+    }
+
+    fn messages(warnings: &[Warning]) -> Vec<String> {
+        warnings.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_check_source() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        eval("(put 'check-old-fn 'byte-obsolete-info '(check-new-fn nil \"29.1\"))", env, cx);
+        let source = ";;; -*- lexical-binding: t -*-
+(defvar check-special)
+(defun check-fn (a &optional b) (list a b))
+(defun check-user (arg)
+  (let ((unused 1) (used 2) (_ignored 3))
+    (check-fn used 1 2 3)
+    (check-old-fn)
+    (setq check-special free-var)))
+";
+        let warnings = check_source(source, env, cx).unwrap();
+        assert_eq!(
+            messages(&warnings),
+            vec![
+                "6:6: Warning: `check-fn' called with 4 arguments, but accepts only 1-2",
+                "7:6: Warning: `check-old-fn' is an obsolete function (as of 29.1); use `check-new-fn' instead.",
+                "8:25: Warning: reference to free variable `free-var'",
+                "5:10: Warning: Unused lexical variable `unused'",
+                "4:20: Warning: Unused lexical argument `arg'",
+            ]
+        );
+
+        // Dynamic binding has no lexical variables to check
+        let source = source.replacen("lexical-binding: t", "lexical-binding: nil", 1);
+        let warnings = check_source(&source, env, cx).unwrap();
+        assert_eq!(warnings.len(), 2);
+
+        eval("(setq byte-compile-warnings '(not obsolete callargs))", env, cx);
+        assert!(check_source(&source, env, cx).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_eval_at_compile_time() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        eval("(setq ewc-count 0)", env, cx);
+        let source = ";;; -*- lexical-binding: t -*-
+(eval-when-compile (defvar ewc-var 1) (setq ewc-count (1+ ewc-count)))
+(eval-and-compile (defun eac-fn (a) a))
+(defun ewc-user () (eac-fn ewc-var 2))
+";
+        let warnings = check_source(source, env, cx).unwrap();
+        assert_eq!(
+            messages(&warnings),
+            vec!["4:21: Warning: `eac-fn' called with 2 arguments, but accepts only 1"]
+        );
+        // Each body was evaluated once, when the checker reached it
+        let count = crate::core::env::intern("ewc-count", cx);
+        assert_eq!(env.vars.get(count).unwrap().bind(cx), cx.add(1));
+        let eac_fn = crate::core::env::intern("eac-fn", cx);
+        assert!(eac_fn.func(cx).is_some());
+        // The body is evaluated in the binding mode of the file
+        let source = "(eval-when-compile (setq ewc-dynamic (let ((ewc-x 1)) (boundp 'ewc-x))))";
+        check_source(source, env, cx).unwrap();
+        let dynamic = crate::core::env::intern("ewc-dynamic", cx);
+        assert_eq!(env.vars.get(dynamic).unwrap().bind(cx), sym::TRUE);
+        // An error stops the check instead of being ignored
+        let source = "(defvar ewc-before)\n(eval-when-compile (signal 'error '(\"ewc failed\")))";
+        let error = check_source(source, env, cx).unwrap_err();
+        assert_eq!(error.to_string(), "2:1: Error: ewc failed");
+    }
+
+    #[test]
+    fn test_compiler_macro() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        // Calls with extra arguments are open coded as calls to cm-target
+        eval(
+            "(put 'cm-fn 'compiler-macro
+                  (lambda (form x &rest ys) (if ys (cons 'cm-target (cons x ys)) form)))",
+            env,
+            cx,
+        );
+        let source = ";;; -*- lexical-binding: t -*-
+(defun cm-fn (a) a)
+(defun cm-target (a b) (list a b))
+(defun cm-user () (list (cm-fn 1) (cm-fn 1 2) '(cm-fn 1 2 3)))
+(defun cm-other () (cm-fn 1 2 3))
+";
+        let warnings = check_source(source, env, cx).unwrap();
+        assert_eq!(
+            messages(&warnings),
+            vec!["5:1: Warning: `cm-target' called with 3 arguments, but accepts only 2"]
+        );
+    }
+
+    #[test]
+    fn test_compile_on_define() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        // Record which functions would be compiled
+        eval("(setq compiled nil)", env, cx);
+        eval(
+            "(defalias 'byte-compile #'(lambda (f) (setq compiled (cons f compiled))))",
+            env,
+            cx,
+        );
+        let compiled = |env: &Rt<Env>, cx: &Context| {
+            let var = crate::core::env::intern("compiled", cx);
+            env.vars.get(var).unwrap().bind(cx).to_string()
+        };
+
+        eval("(defalias 'cod-never #'(lambda () 1))", env, cx);
+        assert_eq!(compiled(env, cx), "nil");
+
+        eval("(setq compile-on-define t)", env, cx);
+        eval("(defalias 'cod-eager #'(lambda () 1))", env, cx);
+        eval("(defalias 'cod-builtin 'car)", env, cx);
+        assert_eq!(compiled(env, cx), "(cod-eager)");
+
+        eval("(setq compile-on-define 'lazy)", env, cx);
+        eval("(defalias 'cod-lazy #'(lambda () 1))", env, cx);
+        assert_eq!(compiled(env, cx), "(cod-eager)");
+        eval("(cod-lazy)", env, cx);
+        eval("(cod-lazy)", env, cx);
+        assert_eq!(compiled(env, cx), "(cod-lazy cod-eager)");
+
+        // A failure keeps the interpreted definition and is reported as a
+        // warning
+        eval("(setq compile-on-define nil)", env, cx);
+        eval("(defalias 'byte-compile #'(lambda (f) (error \"boom\")))", env, cx);
+        eval("(setq compile-on-define 'lazy)", env, cx);
+        eval("(defalias 'cod-failing #'(lambda () 2))", env, cx);
+        eval("(setq cod-result (cod-failing))", env, cx);
+        let var = |name: &str, env: &Rt<Env>, cx: &Context| {
+            let var = crate::core::env::intern(name, cx);
+            env.vars.get(var).unwrap().bind(cx).to_string()
+        };
+        assert_eq!(var("cod-result", env, cx), "2");
+        let warnings = var("delayed-warnings-list", env, cx);
+        assert!(warnings.starts_with("((bytecomp \"Failed to compile `cod-failing'"));
+        assert!(warnings.ends_with(" :warning))"));
+    }
+
+    #[test]
+    fn test_substitute_constants() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let constants = "((subst-number . 3) (subst-list quote (1 2)) (subst-number . 2))";
+        let constants = reader::read(constants, cx).unwrap().0;
+        let substitute = |form| {
+            let form = reader::read(form, cx).unwrap().0;
+            let constants = List::try_from(constants).unwrap();
+            byte_optimize__substitute_constants(form, constants, cx).unwrap()
+        };
+        let read = |form| reader::read(form, cx).unwrap().0;
+        assert_eq!(
+            substitute("(list subst-number subst-list subst-var 'subst-number)"),
+            read("(list 3 subst-list subst-var 'subst-number)")
+        );
+        // a variable that is bound or set anywhere in the form is left alone
+        for form in [
+            "(list subst-number (let* ((a 1) (subst-number 1)) a))",
+            "(list subst-number (function (lambda (subst-number) nil)))",
+            "(progn subst-number (setq subst-number 5))",
+            "(condition-case subst-number subst-number (error nil))",
+        ] {
+            assert_eq!(substitute(form), read(form));
+        }
+    }
+
+    #[test]
+    fn test_inline_defsubsts() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        eval(
+            "(defalias 'inl-add #'(lambda (a &optional b &rest c) \"Doc.\" (list a b c)))",
+            env,
+            cx,
+        );
+        eval("(defalias 'inl-rec #'(lambda (n) (inl-rec (1- n))))", env, cx);
+        eval("(defalias 'inl-plain #'(lambda (a) a))", env, cx);
+        for name in ["inl-add", "inl-rec"] {
+            eval(&format!("(put '{name} 'byte-optimizer 'byte-compile-inline-expand)"), env, cx);
+        }
+        let form = "(list (inl-add 1) (inl-add x 2 3 4) (inl-add) '(inl-add 1)
+      (inl-rec 5) (inl-plain 6))";
+        let expected = "(list (let ((a 1) (b nil) (c nil)) (list a b c))
+      (let ((a x) (b 2) (c (list 3 4))) (list a b c))
+      (inl-add)
+      '(inl-add 1)
+      (let ((n 5)) (inl-rec (1- n)))
+      (inl-plain 6))";
+        let form = reader::read(form, cx).unwrap().0;
+        let result = byte_optimize__inline_defsubsts(form, NIL, env, cx);
+        assert_eq!(result, reader::read(expected, cx).unwrap().0);
+
+        // The definition in the compilation unit is newer than the running
+        // one, and the interactive spec is dropped
+        eval("(defalias 'inl-new #'(lambda (a) (inl-old a)))", env, cx);
+        eval("(put 'inl-new 'byte-optimizer 'byte-compile-inline-expand)", env, cx);
+        let definitions = "((inl-new lambda (a) \"Doc.\" (interactive) (inl-current a)))";
+        let definitions = reader::read(definitions, cx).unwrap().0;
+        let form = reader::read("(inl-new 8)", cx).unwrap().0;
+        let result = byte_optimize__inline_defsubsts(form, definitions, env, cx);
+        let expected = "(let ((a 8)) (inl-current a))";
+        assert_eq!(result, reader::read(expected, cx).unwrap().0);
+    }
+
+    #[test]
+    fn test_fold_pure_calls() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        for name in ["+", "*", "car"] {
+            eval(&format!("(put '{name} 'pure t)"), env, cx);
+        }
+        eval("(defalias 'fold-list #'(lambda (x) (list x)))", env, cx);
+        // Declared earlier in the compilation unit
+        eval("(setq overriding-plist-environment '((fold-list pure t)))", env, cx);
+        let form = "(list (+ 1 (* 2 3)) (+ x 1) '(+ 1 2) (car-safe '(1)) (fold-list 'a) (car 1))";
+        let expected = "(list 7 (+ x 1) '(+ 1 2) (car-safe '(1)) '(a) (car 1))";
+        let form = reader::read(form, cx).unwrap().0;
+        root!(form, cx);
+        let result = rebind!(byte_optimize__fold_pure_calls(form, env, cx));
+        assert_eq!(result, reader::read(expected, cx).unwrap().0);
+    }
+
+    #[test]
+    fn test_declare() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        eval("(put 'car-safe 'side-effect-free t)", env, cx);
+        let source = ";;; -*- lexical-binding: t -*-
+(defun decl-old (x)
+  \"Return X.\"
+  (declare (obsolete decl-new \"30.1\") (side-effect-free t))
+  x)
+(function-put 'decl-pure 'pure t)
+(defun decl-user (y)
+  (decl-old y)
+  (decl-pure y)
+  (car-safe y)
+  (progn (decl-pure y) y))
+";
+        let warnings = check_source(source, env, cx).unwrap();
+        assert_eq!(
+            messages(&warnings),
+            vec![
+                "8:4: Warning: value from call to `decl-old' is unused",
+                "8:4: Warning: `decl-old' is an obsolete function (as of 30.1); use `decl-new' instead.",
+                "9:4: Warning: value from call to `decl-pure' is unused",
+                "10:4: Warning: value from call to `car-safe' is unused",
+                "11:11: Warning: value from call to `decl-pure' is unused",
+            ]
+        );
+    }
+}
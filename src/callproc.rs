@@ -0,0 +1,482 @@
+//! Synchronous subprocesses and the process environment.
+use crate::core::{
+    env::{sym, Env},
+    error::{Type, TypeError},
+    gc::{Context, Rt},
+    object::{Object, ObjectType, NIL},
+};
+use crate::fns::slice_into_list;
+use crate::lread::{file_accessible, locate_file};
+use anyhow::{bail, Context as _, Result};
+use rune_core::hashmap::HashSet;
+use rune_macros::defun;
+use std::fs::File;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+defvar!(PROCESS_ENVIRONMENT);
+defvar!(INITIAL_ENVIRONMENT);
+defvar!(EXEC_PATH);
+defvar!(EXEC_SUFFIXES);
+defvar!(SHELL_FILE_NAME, "/bin/sh");
+
+/// Initialize `process-environment`, `initial-environment`, and `exec-path`
+/// from the environment rune was started in. After this the environment of
+/// child processes is controlled entirely from lisp.
+pub(crate) fn init_process_environment(env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let environment = || {
+        let vars: Vec<Object> = std::env::vars_os()
+            .map(|(name, value)| {
+                cx.add(format!("{}={}", name.to_string_lossy(), value.to_string_lossy()))
+            })
+            .collect();
+        slice_into_list(&vars, None, cx)
+    };
+    // These need to be separate lists because `setenv' modifies
+    // `process-environment' in place.
+    env.set_default(sym::PROCESS_ENVIRONMENT, environment())?;
+    env.set_default(sym::INITIAL_ENVIRONMENT, environment())?;
+
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let dirs: Vec<Object> = std::env::split_paths(&path)
+        .map(|dir| cx.add(dir.to_string_lossy().into_owned()))
+        .collect();
+    env.set_default(sym::EXEC_PATH, slice_into_list(&dirs, None, cx))?;
+    Ok(())
+}
+
+/// Find `variable` in an environment list like `process-environment`. Returns
+/// `Some(None)` if the variable is explicitly unset by an entry without `=`.
+fn lookup_env<'ob>(environment: Object<'ob>, variable: &str) -> Result<Option<Option<&'ob str>>> {
+    for entry in environment.as_list()? {
+        let entry: &str = entry?.try_into()?;
+        match entry.split_once('=') {
+            Some((name, value)) if name == variable => return Ok(Some(Some(value))),
+            None if entry == variable => return Ok(Some(None)),
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+#[defun]
+fn getenv_internal<'ob>(
+    variable: &str,
+    environment: Option<Object<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Option<&'ob str>> {
+    let environment = match environment {
+        Some(list) if matches!(list.untag(), ObjectType::Cons(_)) => list,
+        // a frame argument, or no argument
+        _ => match env.var(sym::PROCESS_ENVIRONMENT, cx) {
+            Some(x) => x.bind(cx),
+            None => NIL,
+        },
+    };
+    Ok(lookup_env(environment, variable)?.flatten())
+}
+
+/// Build the environment for a child process from `process-environment`.
+/// Earlier entries shadow later ones, and an entry without `=` removes the
+/// variable. Returns `None` if `process-environment` is unbound, in which case
+/// the child inherits the environment of rune.
+pub(crate) fn child_environment(
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<Option<Vec<(String, String)>>> {
+    let Some(environment) = env.var(sym::PROCESS_ENVIRONMENT, cx) else { return Ok(None) };
+    let mut seen = HashSet::default();
+    let mut vars = Vec::new();
+    for entry in environment.bind(cx).as_list()? {
+        let entry: &str = entry?.try_into()?;
+        let (name, value) = match entry.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (entry, None),
+        };
+        if seen.insert(name) {
+            if let Some(value) = value {
+                vars.push((name.to_owned(), value.to_owned()));
+            }
+        }
+    }
+    Ok(Some(vars))
+}
+
+/// Create a [`Command`] for `program` the way Emacs launches subprocesses: the
+/// program is searched for in `exec-path`, it runs in `default-directory`, and
+/// it gets a snapshot of `process-environment`.
+pub(crate) fn make_command(program: &str, env: &Rt<Env>, cx: &Context) -> Result<Command> {
+    let Some(path) = find_executable(program, env, cx)? else {
+        bail!("Searching for program: No such file or directory, {program}")
+    };
+    let mut command = Command::new(path);
+    let dir = default_directory(env, cx);
+    if !dir.is_empty() {
+        command.current_dir(dir);
+    }
+    if let Some(vars) = child_environment(env, cx)? {
+        command.env_clear().envs(vars);
+    }
+    Ok(command)
+}
+
+fn default_directory(env: &Rt<Env>, cx: &Context) -> String {
+    match env.var(sym::DEFAULT_DIRECTORY, cx).map(|x| x.untag(cx)) {
+        Some(ObjectType::String(dir)) => dir.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn find_executable(command: &str, env: &Rt<Env>, cx: &Context) -> Result<Option<PathBuf>> {
+    let exec_path = match env.var(sym::EXEC_PATH, cx) {
+        Some(x) => x.bind(cx),
+        None => NIL,
+    };
+    let exec_suffixes = match env.var(sym::EXEC_SUFFIXES, cx) {
+        Some(x) => x.bind(cx),
+        None => NIL,
+    };
+    let path = exec_path
+        .as_list()?
+        .map(|x| x?.try_into())
+        .collect::<Result<Vec<Option<&str>>>>()?;
+    let suffixes =
+        exec_suffixes.as_list()?.map(|x| x?.try_into()).collect::<Result<Vec<&str>>>()?;
+    // A name with a directory in it is not searched for
+    let path = if command.contains(std::path::MAIN_SEPARATOR) { vec![None] } else { path };
+    let dir = default_directory(env, cx);
+    Ok(locate_file(command, &path, &suffixes, &dir, |x| file_accessible(x, 1)))
+}
+
+#[defun]
+fn executable_find(
+    command: &str,
+    _remote: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<Option<String>> {
+    let file = find_executable(command, env, cx)?;
+    Ok(file.map(|x| x.to_string_lossy().into_owned()))
+}
+
+/// Where the output of a synchronous process goes.
+enum Destination<'ob> {
+    Discard,
+    Buffer(Option<Object<'ob>>),
+    File(&'ob str),
+}
+
+impl<'ob> Destination<'ob> {
+    fn parse(obj: Object<'ob>) -> Result<Self> {
+        Ok(match obj.untag() {
+            ObjectType::NIL => Self::Discard,
+            ObjectType::Symbol(sym::TRUE) => Self::Buffer(None),
+            ObjectType::Buffer(_) | ObjectType::String(_) => Self::Buffer(Some(obj)),
+            _ => bail!(TypeError::new(Type::BufferOrName, obj)),
+        })
+    }
+}
+
+#[defun]
+fn call_process<'ob>(
+    program: &str,
+    infile: Option<&str>,
+    destination: Option<Object<'ob>>,
+    _display: Option<Object>,
+    args: &[&str],
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let mut command = make_command(program, env, cx)?;
+    command.args(args);
+    command.stdin(match infile {
+        Some(file) => {
+            let dir = default_directory(env, cx);
+            let file = crate::fileio::expand_file_name(file, Some(&dir), env, cx)?;
+            File::open(&file)
+                .with_context(|| format!("Opening process input file {file}"))?
+                .into()
+        }
+        None => Stdio::null(),
+    });
+
+    // (REAL-DESTINATION ERROR-DESTINATION) or (:file FILE)
+    let destination = destination.unwrap_or_default();
+    let (output, error) = match destination.untag() {
+        ObjectType::Int(0) => {
+            let mut child = command.stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+            // Nobody waits for a discarded process, so reap it in the
+            // background to keep it from becoming a zombie
+            std::thread::spawn(move || child.wait());
+            return Ok(NIL);
+        }
+        ObjectType::Cons(cons) if cons.car() == sym::KW_FILE => {
+            let file: &str = cons.cdr().as_list()?.next().unwrap_or(Ok(NIL))?.try_into()?;
+            (Destination::File(file), Destination::Discard)
+        }
+        ObjectType::Cons(cons) => {
+            let mut elems = cons.elements();
+            let real = Destination::parse(elems.next().unwrap_or(Ok(NIL))?)?;
+            let error = match elems.next().unwrap_or(Ok(NIL))?.untag() {
+                ObjectType::NIL => Destination::Discard,
+                ObjectType::Symbol(sym::TRUE) => Destination::Buffer(None),
+                ObjectType::String(file) => Destination::File(file),
+                x => bail!(TypeError::new(Type::String, x)),
+            };
+            (real, error)
+        }
+        _ => (Destination::parse(destination)?, Destination::Discard),
+    };
+
+    let result = command.output()?;
+    // stderr sent to the same place as stdout is inserted after it
+    for (text, dest) in [(result.stdout, &output), (result.stderr, &error)] {
+        match dest {
+            Destination::Discard => {}
+            Destination::File(file) => std::fs::write(file, text)?,
+            Destination::Buffer(buffer) => {
+                let buffer = match buffer {
+                    Some(buffer) => Some(crate::buffer::get_buffer_create(*buffer, None, cx)?),
+                    None => None,
+                };
+                let buffer = match buffer.map(Object::untag) {
+                    Some(ObjectType::Buffer(b)) => Some(b),
+                    _ => None,
+                };
+                let text = cx.add(String::from_utf8_lossy(&text).into_owned());
+                match env.with_buffer_mut(buffer, |b| b.insert(text)) {
+                    Some(result) => result?,
+                    None => bail!("Selecting deleted buffer"),
+                }
+            }
+        }
+    }
+
+    Ok(match result.status.code() {
+        Some(code) => cx.add(code),
+        // killed by a signal
+        None => cx.add(result.status.to_string()),
+    })
+}
+
+#[defun]
+fn call_process_shell_command<'ob>(
+    command: &str,
+    infile: Option<&str>,
+    buffer: Option<Object<'ob>>,
+    display: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let var = |name| match env.var(name, cx).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::String(value)) => Ok(value.to_string()),
+        Some(x) => Err(TypeError::new(Type::String, x)),
+        None => Ok(String::new()),
+    };
+    let shell = var(sym::SHELL_FILE_NAME)?;
+    let switch = var(sym::SHELL_COMMAND_SWITCH)?;
+    call_process(&shell, infile, buffer, display, &[switch.as_str(), command], env, cx)
+}
+
+/// Characters that don't need to be quoted for a POSIX shell.
+fn shell_safe(chr: char) -> bool {
+    chr.is_ascii_alphanumeric() || "-_./:=@%+,".contains(chr)
+}
+
+#[defun]
+fn shell_quote_argument(argument: &str, posix: Option<()>) -> String {
+    if cfg!(windows) && posix.is_none() {
+        // cmd.exe quoting: backslashes are only special before a quote
+        let mut quoted = String::from('"');
+        let mut backslashes = 0;
+        for chr in argument.chars() {
+            match chr {
+                '\\' => backslashes += 1,
+                '"' => {
+                    quoted.extend(std::iter::repeat('\\').take(backslashes + 1));
+                    backslashes = 0;
+                }
+                _ => backslashes = 0,
+            }
+            quoted.push(chr);
+        }
+        quoted.extend(std::iter::repeat('\\').take(backslashes));
+        quoted.push('"');
+        return quoted;
+    }
+    if argument.is_empty() {
+        return "''".to_owned();
+    }
+    let mut quoted = String::with_capacity(argument.len());
+    for chr in argument.chars() {
+        match chr {
+            // A backslash before a newline is a line continuation
+            '\n' => quoted.push_str("'\n'"),
+            _ if shell_safe(chr) => quoted.push(chr),
+            _ => {
+                quoted.push('\\');
+                quoted.push(chr);
+            }
+        }
+    }
+    quoted
+}
+
+/// Split `command` into words the way a POSIX shell does, handling quotes
+/// and backslash escapes. Other shell syntax is not interpreted.
+fn split_shell_words(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            _ if chr.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(chr) => word.push(chr),
+                        None => bail!("Unmatched single quote in {command}"),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(chr @ ('"' | '\\' | '$' | '`')) => word.push(chr),
+                            Some('\n') => {}
+                            Some(chr) => {
+                                word.push('\\');
+                                word.push(chr);
+                            }
+                            None => bail!("Unmatched double quote in {command}"),
+                        },
+                        Some(chr) => word.push(chr),
+                        None => bail!("Unmatched double quote in {command}"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(chr) => word.get_or_insert_with(String::new).push(chr),
+                None => word.get_or_insert_with(String::new).push('\\'),
+            },
+            _ => word.get_or_insert_with(String::new).push(chr),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[defun]
+fn split_string_shell_command<'ob>(string: &str, cx: &'ob Context) -> Result<Object<'ob>> {
+    let words: Vec<Object> = split_shell_words(string)?.into_iter().map(|x| cx.add(x)).collect();
+    Ok(slice_into_list(&words, None, cx))
+}
+
+/// Join `strings` with `separator`, quoting the ones that a shell would
+/// otherwise split or interpret.
+#[defun]
+fn combine_and_quote_strings(strings: &[&str], separator: Option<&str>) -> String {
+    let quoted: Vec<String> = strings
+        .iter()
+        .map(|x| match x.chars().all(shell_safe) && !x.is_empty() {
+            true => (*x).to_owned(),
+            false => shell_quote_argument(x, None),
+        })
+        .collect();
+    quoted.join(separator.unwrap_or(" "))
+}
+
+defsym!(KW_FILE);
+defvar!(SHELL_COMMAND_SWITCH, "-c");
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{gc::RootSet, object::TRUE};
+    use rune_core::macros::{list, root};
+
+    #[test]
+    fn test_child_environment() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        assert_eq!(child_environment(env, cx).unwrap(), None);
+
+        let vars = list!["FOO=1", "BAR", "FOO=2", "BAR=3", "BAZ=a=b"; cx];
+        env.set_default(sym::PROCESS_ENVIRONMENT, vars).unwrap();
+        let expect = vec![("FOO".to_owned(), "1".to_owned()), ("BAZ".to_owned(), "a=b".to_owned())];
+        assert_eq!(child_environment(env, cx).unwrap(), Some(expect));
+
+        assert_eq!(getenv_internal("FOO", None, env, cx).unwrap(), Some("1"));
+        assert_eq!(getenv_internal("BAR", None, env, cx).unwrap(), None);
+        assert_eq!(getenv_internal("BAZ", None, env, cx).unwrap(), Some("a=b"));
+        let other = list!["BAR=4"; cx];
+        assert_eq!(getenv_internal("BAR", Some(other), env, cx).unwrap(), Some("4"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote_argument("foo/bar.txt", None), "foo/bar.txt");
+        assert_eq!(shell_quote_argument("", None), "''");
+        assert_eq!(shell_quote_argument("it's $HOME", None), "it\\'s\\ \\$HOME");
+        assert_eq!(shell_quote_argument("a\nb", None), "a'\n'b");
+        assert_eq!(combine_and_quote_strings(&["ls", "-l", "my file"], None), "ls -l my\\ file");
+
+        let split = |x: &str| split_shell_words(x).unwrap();
+        assert_eq!(split("  ls  -l "), ["ls", "-l"]);
+        assert_eq!(
+            split(r#"echo 'a b' "c \"d\" \e" f\ g ''"#),
+            ["echo", "a b", r#"c "d" \e"#, "f g", ""]
+        );
+        assert!(split_shell_words("echo 'a").is_err());
+        for arg in ["it's $HOME", "a\nb", "tab\there", ""] {
+            assert_eq!(split(&shell_quote_argument(arg, None)), [arg]);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_call_process() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let exec_path = list!["/nonexistent", "/bin", "/usr/bin"; cx];
+        env.set_default(sym::EXEC_PATH, exec_path).unwrap();
+        assert!(executable_find("sh", None, env, cx).unwrap().is_some());
+        assert_eq!(executable_find("rune-no-such-program", None, env, cx).unwrap(), None);
+
+        // The child only sees `process-environment'
+        let vars = list!["RUNE_TEST_VAR=hello"; cx];
+        env.set_default(sym::PROCESS_ENVIRONMENT, vars).unwrap();
+        let buffer =
+            crate::buffer::get_buffer_create(cx.add("call-process-test"), None, cx).unwrap();
+        crate::buffer::set_buffer(buffer, env, cx).unwrap();
+        let args = ["-c", "printf \"$RUNE_TEST_VAR:$HOME\"; exit 3"];
+        let status = call_process("sh", None, Some(TRUE.into()), None, &args, env, cx).unwrap();
+        assert_eq!(status, 3_i64);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "hello:");
+
+        // INFILE is relative to `default-directory'
+        let dir = std::env::temp_dir().join(format!("rune-call-process-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("input"), " world").unwrap();
+        let dir_name = cx.add(dir.to_string_lossy().into_owned());
+        env.set_default(sym::DEFAULT_DIRECTORY, dir_name).unwrap();
+        let status = call_process("cat", Some("input"), Some(TRUE.into()), None, &[], env, cx);
+        assert_eq!(status.unwrap(), 0_i64);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "hello: world");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // A discarded process returns right away
+        let status = call_process("true", None, Some(cx.add(0)), None, &[], env, cx).unwrap();
+        assert_eq!(status, NIL);
+    }
+}
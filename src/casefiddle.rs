@@ -1,13 +1,214 @@
 //! String and character case conversion.
+use crate::casetab::{case_table, push_downcase, push_upcase};
+use crate::chartab::CharTable;
+use crate::core::{
+    env::Env,
+    error::{ArgRangeError, Type, TypeError},
+    gc::{Context, Rt},
+    object::{Object, ObjectType},
+};
+use anyhow::{bail, ensure, Result};
 use rune_macros::defun;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CaseOp {
+    Up,
+    Down,
+    Capitalize,
+    /// Upcase the first character of each word and leave the rest alone
+    UpInitials,
+}
+
+/// Convert the case of `text` using `table`. When capitalizing, the first
+/// character of each word is upcased and the rest are downcased.
+pub(crate) fn casify(op: CaseOp, text: &str, table: Option<CharTable>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_word = false;
+    for chr in text.chars() {
+        match op {
+            CaseOp::Up => push_upcase(&mut out, chr, table),
+            CaseOp::Down => push_downcase(&mut out, chr, table),
+            CaseOp::Capitalize if in_word => push_downcase(&mut out, chr, table),
+            CaseOp::Capitalize => push_upcase(&mut out, chr, table),
+            CaseOp::UpInitials if in_word => out.push(chr),
+            CaseOp::UpInitials => push_upcase(&mut out, chr, table),
+        }
+        in_word = chr.is_alphanumeric();
+    }
+    out
+}
+
+fn casify_object<'ob>(
+    op: CaseOp,
+    obj: Object<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let table = case_table(env, cx);
+    match obj.untag() {
+        ObjectType::Int(_) => {
+            let chr: char = obj.try_into()?;
+            let mut chars = casify(op, chr.encode_utf8(&mut [0; 4]), table).chars();
+            // A character whose case mapping is more than one character is
+            // left alone
+            match (chars.next(), chars.next()) {
+                (Some(new), None) => Ok(cx.add(new)),
+                _ => Ok(obj),
+            }
+        }
+        ObjectType::String(s) => Ok(cx.add(casify(op, s, table))),
+        _ => bail!(TypeError::new(Type::String, obj)),
+    }
+}
+
+#[defun]
+fn capitalize<'ob>(obj: Object<'ob>, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    casify_object(CaseOp::Capitalize, obj, env, cx)
+}
+
 #[defun]
-fn capitalize(s: &str) -> String {
-    titlecase::titlecase(s)
+fn upcase_initials<'ob>(obj: Object<'ob>, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    casify_object(CaseOp::UpInitials, obj, env, cx)
+}
+
+#[defun]
+fn upcase<'ob>(obj: Object<'ob>, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    casify_object(CaseOp::Up, obj, env, cx)
+}
+
+#[defun]
+fn downcase<'ob>(obj: Object<'ob>, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    casify_object(CaseOp::Down, obj, env, cx)
+}
+
+/// Convert the case of the text between `beg` and `end` in the current
+/// buffer.
+fn casify_region(
+    op: CaseOp,
+    beg: usize,
+    end: usize,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let (beg, end) = (beg.min(end), beg.max(end));
+    let table = case_table(env, cx);
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    ensure!(end <= buffer.text.len_chars(), ArgRangeError::new(&[beg, end]));
+    let text = buffer.text.read(beg..end).into_owned();
+    let new = casify(op, &text, table);
+    if new != text {
+        buffer.replace_region(beg, end, &new);
+    }
+    Ok(())
 }
 
 #[defun]
-fn upcase(s: &str) -> String {
-    // TODO: use unicode
-    s.to_uppercase()
+fn upcase_region(
+    beg: usize,
+    end: usize,
+    _region_noncontiguous_p: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    casify_region(CaseOp::Up, beg, end, env, cx)
+}
+
+#[defun]
+fn downcase_region(
+    beg: usize,
+    end: usize,
+    _region_noncontiguous_p: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    casify_region(CaseOp::Down, beg, end, env, cx)
+}
+
+#[defun]
+fn capitalize_region(
+    beg: usize,
+    end: usize,
+    _region_noncontiguous_p: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    casify_region(CaseOp::Capitalize, beg, end, env, cx)
+}
+
+#[defun]
+fn upcase_initials_region(
+    beg: usize,
+    end: usize,
+    _region_noncontiguous_p: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    casify_region(CaseOp::UpInitials, beg, end, env, cx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::{get_buffer_create, set_buffer};
+    use crate::core::{env::sym, gc::RootSet, object::NIL};
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_casify_region() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let buffer = get_buffer_create(cx.add("test_casify_region"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        env.current_buffer.as_mut().unwrap().insert(cx.add("hello big world")).unwrap();
+
+        upcase_region(6, 9, None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "hello BIG world");
+        upcase_region(2, 0, None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "HEllo BIG world");
+        downcase_region(0, 15, None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "hello big world");
+        capitalize_region(0, 15, None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "Hello Big World");
+        downcase_region(0, 15, None, env, cx).unwrap();
+        upcase_initials_region(6, 15, None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "hello Big World");
+        capitalize_region(0, 5, None, env, cx).unwrap();
+        assert!(upcase_region(0, 100, None, env, cx).is_err());
+
+        // point after the region moves with the text
+        let buffer = env.current_buffer.as_mut().unwrap();
+        buffer.text.set_cursor(15);
+        buffer.insert(cx.add(" straße")).unwrap();
+        buffer.text.set_cursor(22);
+        upcase_region(16, 22, None, env, cx).unwrap();
+        let buffer = env.current_buffer.as_ref().unwrap();
+        assert_eq!(buffer, "Hello Big World STRASSE");
+        assert_eq!(buffer.text.cursor().chars(), 23);
+    }
+
+    #[test]
+    fn test_casify_object() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        assert_eq!(upcase(cx.add("foo bar"), env, cx).unwrap(), cx.add("FOO BAR"));
+        assert_eq!(downcase(cx.add("FOO"), env, cx).unwrap(), cx.add("foo"));
+        assert_eq!(upcase(cx.add('a'), env, cx).unwrap(), cx.add('A'));
+        assert_eq!(upcase(cx.add('ß'), env, cx).unwrap(), cx.add('ß'));
+        assert!(upcase(cx.add(1.5), env, cx).is_err());
+        assert_eq!(capitalize(cx.add("hELLO wORLD"), env, cx).unwrap(), cx.add("Hello World"));
+        assert_eq!(upcase_initials(cx.add("hello wORLD"), env, cx).unwrap(), cx.add("Hello WORLD"));
+        assert_eq!(capitalize(cx.add('a'), env, cx).unwrap(), cx.add('A'));
+
+        // The mappings of the case table are used
+        crate::casetab::init_case_table(env, cx).unwrap();
+        let table = case_table(env, cx).unwrap();
+        let up_table = CharTable::new(sym::CASE_TABLE, 0, NIL, cx).unwrap();
+        up_table.set('h'.into(), cx.add('J'));
+        table.set_extra_slot(0, up_table.into()).unwrap();
+        table.set('O'.into(), cx.add('0'));
+        assert_eq!(capitalize(cx.add("hello WORLD"), env, cx).unwrap(), cx.add("Jello W0rld"));
+        assert_eq!(upcase_initials(cx.add("hello oLD"), env, cx).unwrap(), cx.add("Jello OLD"));
+    }
 }
@@ -0,0 +1,150 @@
+//! Case tables.
+//!
+//! A case table is a char-table with purpose `case-table` that maps characters
+//! to their lower case. Its first extra slot holds the table that maps
+//! characters to their upper case. Characters a table doesn't mention use
+//! their Unicode case mappings, so the standard case table starts out empty.
+use crate::chartab::{char_table_char, CharTable};
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Object, NIL},
+};
+use anyhow::{bail, Result};
+use rune_macros::defun;
+
+const UP: usize = 0;
+
+/// Setup the `case-table` purpose and create the standard case table.
+pub(crate) fn init_case_table(env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    env.set_prop(sym::CASE_TABLE, sym::CHAR_TABLE_EXTRA_SLOTS, cx.add(3));
+    let table = CharTable::new(sym::CASE_TABLE, 3, NIL, cx)?;
+    env.set_default(sym::INTERNAL__CASE_TABLE, table.into())
+}
+
+/// The case table of the current buffer, or the standard case table if
+/// there is no current buffer. Returns `None` if no case table has been
+/// setup, in which case only the Unicode mappings are used.
+pub(crate) fn case_table<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Option<CharTable<'ob>> {
+    env.var(sym::INTERNAL__CASE_TABLE, cx)?.bind(cx).try_into().ok()
+}
+
+/// Convert `chr` to lower case, pushing the result to `out`.
+pub(crate) fn push_downcase(out: &mut String, chr: char, table: Option<CharTable>) {
+    match table.and_then(|table| char_table_char(table, chr)) {
+        Some(down) => out.push(down),
+        None => out.extend(chr.to_lowercase()),
+    }
+}
+
+/// Convert `chr` to upper case, pushing the result to `out`.
+pub(crate) fn push_upcase(out: &mut String, chr: char, table: Option<CharTable>) {
+    let up: Option<CharTable> = table.and_then(|table| table.extra_slot(UP).ok()?.try_into().ok());
+    match up.and_then(|up| char_table_char(up, chr)) {
+        Some(up) => out.push(up),
+        None => out.extend(chr.to_uppercase()),
+    }
+}
+
+fn is_case_table(object: Object) -> bool {
+    let Ok(table) = CharTable::try_from(object) else { return false };
+    table.purpose() == sym::CASE_TABLE
+        && table.extra_slots() == 3
+        && (0..3).all(|n| {
+            let slot = table.extra_slot(n).unwrap_or_default();
+            slot.is_nil() || CharTable::try_from(slot).is_ok()
+        })
+}
+
+fn check_case_table(object: Object) -> Result<CharTable> {
+    if !is_case_table(object) {
+        bail!("Wrong type argument: case-table-p, {object}");
+    }
+    Ok(object.try_into()?)
+}
+
+#[defun]
+fn case_table_p(object: Object) -> bool {
+    is_case_table(object)
+}
+
+#[defun]
+fn standard_case_table<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    if env.vars.get(sym::INTERNAL__CASE_TABLE).is_none() {
+        init_case_table(env, cx)?;
+    }
+    Ok(env.vars.get(sym::INTERNAL__CASE_TABLE).unwrap().bind(cx))
+}
+
+#[defun]
+fn current_case_table<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    match case_table(env, cx) {
+        Some(table) => Ok(table.into()),
+        None => standard_case_table(env, cx),
+    }
+}
+
+#[defun]
+fn set_case_table<'ob>(
+    table: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    check_case_table(table)?;
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    let buffer = buffer.lisp_buffer(cx);
+    env.set_local_var(sym::INTERNAL__CASE_TABLE, table, buffer)?;
+    Ok(table)
+}
+
+#[defun]
+fn set_standard_case_table<'ob>(table: Object<'ob>, env: &mut Rt<Env>) -> Result<Object<'ob>> {
+    check_case_table(table)?;
+    env.set_default(sym::INTERNAL__CASE_TABLE, table)?;
+    Ok(table)
+}
+
+defsym!(CASE_TABLE);
+defsym!(INTERNAL__CASE_TABLE);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_case_table() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let down = |chr, table| {
+            let mut out = String::new();
+            push_downcase(&mut out, chr, table);
+            out
+        };
+        let up = |chr, table| {
+            let mut out = String::new();
+            push_upcase(&mut out, chr, table);
+            out
+        };
+        // Without a case table
+        assert_eq!(down('A', None), "a");
+        assert_eq!(up('ß', None), "SS");
+
+        let table = standard_case_table(env, cx).unwrap();
+        assert!(case_table_p(table));
+        assert!(!case_table_p(NIL));
+        let table = case_table(env, cx).unwrap();
+        assert_eq!(down('Q', Some(table)), "q");
+
+        // Override the mappings for a single character
+        table.set('A'.into(), cx.add('x'));
+        let up_table = CharTable::new(sym::CASE_TABLE, 0, NIL, cx).unwrap();
+        up_table.set('a'.into(), cx.add('Y'));
+        table.set_extra_slot(UP, up_table.into()).unwrap();
+        assert_eq!(down('A', Some(table)), "x");
+        assert_eq!(up('a', Some(table)), "Y");
+        assert_eq!(up('b', Some(table)), "B");
+    }
+}
@@ -1,10 +1,13 @@
 //! Character and string utilities.
 use crate::core::{
+    env::{intern, sym},
     gc::Context,
-    object::{int_to_char, Gc, Object, ObjectType},
+    object::{int_to_char, Gc, Object, ObjectType, Symbol, NIL},
 };
 use anyhow::Result;
 use rune_macros::defun;
+use unicode_general_category::{get_general_category, GeneralCategory};
+use unicode_script::UnicodeScript;
 
 #[defun]
 fn unibyte_string(bytes: &[Gc<i64>]) -> Result<Vec<u8>> {
@@ -45,7 +48,8 @@ fn make_string<'ob>(
     multibyte: Option<()>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    if multibyte.is_some() {
+    // Characters that don't fit in a unibyte string make it multibyte
+    if multibyte.is_some() || init > 0x7F {
         let chr = int_to_char(i64::try_from(init)?)?;
         let size = chr.len_utf8();
         let mut string = cx.string_with_capacity(length * size);
@@ -59,3 +63,139 @@ fn make_string<'ob>(
         Ok(cx.add(string))
     }
 }
+
+/// The two letter abbreviation of a general category, as used by the Unicode
+/// character database.
+fn category_name(category: GeneralCategory) -> &'static str {
+    use GeneralCategory as G;
+    match category {
+        G::UppercaseLetter => "Lu",
+        G::LowercaseLetter => "Ll",
+        G::TitlecaseLetter => "Lt",
+        G::ModifierLetter => "Lm",
+        G::OtherLetter => "Lo",
+        G::NonspacingMark => "Mn",
+        G::SpacingMark => "Mc",
+        G::EnclosingMark => "Me",
+        G::DecimalNumber => "Nd",
+        G::LetterNumber => "Nl",
+        G::OtherNumber => "No",
+        G::ConnectorPunctuation => "Pc",
+        G::DashPunctuation => "Pd",
+        G::OpenPunctuation => "Ps",
+        G::ClosePunctuation => "Pe",
+        G::InitialPunctuation => "Pi",
+        G::FinalPunctuation => "Pf",
+        G::OtherPunctuation => "Po",
+        G::MathSymbol => "Sm",
+        G::CurrencySymbol => "Sc",
+        G::ModifierSymbol => "Sk",
+        G::OtherSymbol => "So",
+        G::SpaceSeparator => "Zs",
+        G::LineSeparator => "Zl",
+        G::ParagraphSeparator => "Zp",
+        G::Control => "Cc",
+        G::Format => "Cf",
+        G::Surrogate => "Cs",
+        G::PrivateUse => "Co",
+        G::Unassigned => "Cn",
+    }
+}
+
+/// The value of a decimal digit. Unicode encodes every set of decimal digits
+/// as a contiguous run from zero to nine, so the value is the offset from the
+/// start of the run.
+fn digit_value(chr: char) -> Option<u32> {
+    let is_digit = |c: u32| {
+        char::from_u32(c).is_some_and(|c| get_general_category(c) == GeneralCategory::DecimalNumber)
+    };
+    if !is_digit(chr as u32) {
+        return None;
+    }
+    let mut start = chr as u32;
+    while start > 0 && is_digit(start - 1) {
+        start -= 1;
+    }
+    Some((chr as u32 - start) % 10)
+}
+
+/// The simple case mapping of `chr`, or `chr` itself if it maps to more than
+/// one character.
+fn simple_case(chr: char, mut mapping: impl ExactSizeIterator<Item = char>) -> char {
+    match mapping.len() {
+        1 => mapping.next().unwrap(),
+        _ => chr,
+    }
+}
+
+fn titlecase(chr: char) -> char {
+    // The only characters whose titlecase differs from their uppercase
+    match chr {
+        '\u{1C4}'..='\u{1C6}' => '\u{1C5}',
+        '\u{1C7}'..='\u{1C9}' => '\u{1C8}',
+        '\u{1CA}'..='\u{1CC}' => '\u{1CB}',
+        '\u{1F1}'..='\u{1F3}' => '\u{1F2}',
+        _ => simple_case(chr, chr.to_uppercase()),
+    }
+}
+
+#[defun]
+fn get_char_code_property<'ob>(chr: char, propname: Symbol, cx: &'ob Context) -> Object<'ob> {
+    match propname {
+        sym::GENERAL_CATEGORY => intern(category_name(get_general_category(chr)), cx).into(),
+        sym::NAME => match unicode_names2::name(chr) {
+            Some(name) => cx.add(name.to_string()),
+            None => NIL,
+        },
+        sym::DECIMAL_DIGIT_VALUE | sym::DIGIT_VALUE | sym::NUMERIC_VALUE => {
+            match digit_value(chr) {
+                Some(value) => cx.add(i64::from(value)),
+                None => NIL,
+            }
+        }
+        sym::UPPERCASE => cx.add(simple_case(chr, chr.to_uppercase())),
+        sym::LOWERCASE => cx.add(simple_case(chr, chr.to_lowercase())),
+        sym::TITLECASE => cx.add(titlecase(chr)),
+        _ => NIL,
+    }
+}
+
+/// The script of `chr` as a symbol, such as `latin` or `greek`.
+#[defun]
+fn char_script(chr: char, cx: &Context) -> Symbol {
+    let name = chr.script().full_name().to_lowercase().replace('_', "-");
+    intern(&name, cx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_char_code_property() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let prop = |chr, name| get_char_code_property(chr, name, cx);
+        assert_eq!(prop('a', sym::GENERAL_CATEGORY), intern("Ll", cx));
+        assert_eq!(prop('A', sym::NAME), cx.add("LATIN CAPITAL LETTER A"));
+        assert_eq!(prop('7', sym::DECIMAL_DIGIT_VALUE), cx.add(7));
+        assert_eq!(prop('\u{0663}', sym::NUMERIC_VALUE), cx.add(3));
+        assert_eq!(prop('x', sym::DIGIT_VALUE), NIL);
+        assert_eq!(prop('a', sym::UPPERCASE), cx.add('A'));
+        assert_eq!(prop('\u{DF}', sym::UPPERCASE), cx.add('\u{DF}'));
+        assert_eq!(prop('\u{1C6}', sym::TITLECASE), cx.add('\u{1C5}'));
+        assert_eq!(char_script('a', cx), intern("latin", cx));
+        assert_eq!(char_script('\u{3B1}', cx), intern("greek", cx));
+    }
+}
+
+defsym!(GENERAL_CATEGORY);
+defsym!(NAME);
+defsym!(DECIMAL_DIGIT_VALUE);
+defsym!(DIGIT_VALUE);
+defsym!(NUMERIC_VALUE);
+defsym!(UPPERCASE);
+defsym!(LOWERCASE);
+defsym!(TITLECASE);
@@ -0,0 +1,436 @@
+//! Char-tables.
+//!
+//! A char-table maps characters to values. It is stored as a record of the
+//! form `#s(char-table PURPOSE DEFAULT PARENT DATA RANGES EXTRA-SLOTS...)`,
+//! where DATA is a hash table of values set for single characters and RANGES
+//! is an alist of `((FROM . TO) . VALUE)` entries that don't overlap. Setting
+//! a range replaces the parts of the ranges it covers and merges with the
+//! ranges next to it that have the same value. Values for single characters
+//! are removed when a range covering them is set, so a character found in
+//! DATA always takes precedence over RANGES.
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env},
+    error::{ArgRangeError, Type, TypeError},
+    gc::{Context, Rt},
+    object::{
+        int_to_char, HashTable, LispHashTable, Object, ObjectType, Record, RecordBuilder, Symbol,
+        NIL,
+    },
+};
+use anyhow::{bail, ensure, Result};
+use rune_macros::defun;
+
+const PURPOSE: usize = 1;
+const DEFAULT: usize = 2;
+const PARENT: usize = 3;
+const DATA: usize = 4;
+const RANGES: usize = 5;
+const EXTRA: usize = 6;
+
+/// The maximum number of extra slots, the same as Emacs.
+const MAX_EXTRA_SLOTS: usize = 10;
+
+/// The largest character code a char-table can hold.
+const MAX_CHAR: u32 = 0x3F_FFFF;
+
+#[derive(Copy, Clone)]
+pub(crate) struct CharTable<'ob>(&'ob Record);
+
+impl<'ob> TryFrom<Object<'ob>> for CharTable<'ob> {
+    type Error = TypeError;
+
+    fn try_from(obj: Object<'ob>) -> Result<Self, Self::Error> {
+        match obj.untag() {
+            ObjectType::Record(record) if is_char_table(record) => Ok(CharTable(record)),
+            _ => Err(TypeError::new(Type::CharTable, obj)),
+        }
+    }
+}
+
+impl<'ob> TryFrom<Object<'ob>> for Option<CharTable<'ob>> {
+    type Error = TypeError;
+
+    fn try_from(obj: Object<'ob>) -> Result<Self, Self::Error> {
+        if obj.is_nil() {
+            Ok(None)
+        } else {
+            obj.try_into().map(Some)
+        }
+    }
+}
+
+impl<'ob> From<CharTable<'ob>> for Object<'ob> {
+    fn from(table: CharTable<'ob>) -> Self {
+        table.0.into()
+    }
+}
+
+pub(crate) fn is_char_table(record: &Record) -> bool {
+    record.len() >= EXTRA && record[0].get() == sym::CHAR_TABLE
+}
+
+impl<'ob> CharTable<'ob> {
+    /// Create a new char-table where every character and extra slot is `init`.
+    pub(crate) fn new(
+        purpose: Symbol,
+        extra_slots: usize,
+        init: Object<'ob>,
+        cx: &'ob Context,
+    ) -> Result<Self> {
+        ensure!(extra_slots <= MAX_EXTRA_SLOTS, ArgRangeError::new(&[extra_slots]));
+        let mut record = cx.vec_with_capacity(EXTRA + extra_slots);
+        record.push(sym::CHAR_TABLE.into());
+        record.push(purpose.into());
+        record.push(init);
+        record.push(NIL);
+        record.push(cx.add(HashTable::default()));
+        record.push(NIL);
+        record.extend(std::iter::repeat(init).take(extra_slots));
+        let record = cx.add(RecordBuilder(record));
+        Ok(record.try_into()?)
+    }
+
+    fn slot(self, idx: usize) -> Object<'ob> {
+        self.0[idx].get()
+    }
+
+    fn set_slot(self, idx: usize, value: Object) -> Result<()> {
+        self.0.try_mut()?[idx].set(value);
+        Ok(())
+    }
+
+    fn data(self) -> &'ob LispHashTable {
+        match self.slot(DATA).untag() {
+            ObjectType::HashTable(data) => data,
+            _ => unreachable!("char-table data was not a hash table"),
+        }
+    }
+
+    pub(crate) fn purpose(self) -> Object<'ob> {
+        self.slot(PURPOSE)
+    }
+
+    pub(crate) fn default_value(self) -> Object<'ob> {
+        self.slot(DEFAULT)
+    }
+
+    pub(crate) fn parent(self) -> Option<CharTable<'ob>> {
+        self.slot(PARENT).try_into().ok()
+    }
+
+    pub(crate) fn extra_slots(self) -> usize {
+        self.0.len() - EXTRA
+    }
+
+    pub(crate) fn extra_slot(self, n: usize) -> Result<Object<'ob>> {
+        let len = self.extra_slots();
+        ensure!(n < len, ArgRangeError::new(&[n, len]));
+        Ok(self.slot(EXTRA + n))
+    }
+
+    pub(crate) fn set_extra_slot(self, n: usize, value: Object) -> Result<()> {
+        let len = self.extra_slots();
+        ensure!(n < len, ArgRangeError::new(&[n, len]));
+        self.set_slot(EXTRA + n, value)
+    }
+
+    /// The value set for `chr` in this table, ignoring the default and parent.
+    fn own_value(self, chr: u32) -> Object<'ob> {
+        if let Some(value) = self.data().get(i64::from(chr).into()) {
+            return value;
+        }
+        let chr = i64::from(chr);
+        let range = self.ranges().into_iter().find(|(from, to, _)| (*from..=*to).contains(&chr));
+        range.map_or(NIL, |x| x.2)
+    }
+
+    /// Look up the value for `chr`. Characters without a value use the
+    /// default value of the table, and then the value from the parent.
+    pub(crate) fn get(self, chr: u32) -> Object<'ob> {
+        let mut table = self;
+        loop {
+            let value = table.own_value(chr);
+            if !value.is_nil() {
+                return value;
+            }
+            let default = table.default_value();
+            if !default.is_nil() {
+                return default;
+            }
+            match table.parent() {
+                Some(parent) => table = parent,
+                None => return NIL,
+            }
+        }
+    }
+
+    pub(crate) fn set(self, chr: u32, value: Object) {
+        self.data().insert(i64::from(chr).into(), value);
+    }
+
+    pub(crate) fn set_range(
+        self,
+        from: u32,
+        to: u32,
+        value: Object,
+        cx: &'ob Context,
+    ) -> Result<()> {
+        if from > to {
+            return Ok(());
+        }
+        if from == to {
+            self.set(from, value);
+            return Ok(());
+        }
+        // Remove single characters that are covered by the new range
+        let data = self.data();
+        let in_range = |key: Object| match key.untag() {
+            ObjectType::Int(x) => (i64::from(from)..=i64::from(to)).contains(&x),
+            _ => false,
+        };
+        let covered: Vec<_> = (0..data.len())
+            .filter_map(|i| data.get_index(i).map(|(key, _)| key))
+            .filter(|key| in_range(*key))
+            .collect();
+        for key in covered {
+            data.shift_remove(key);
+        }
+        let (mut from, mut to) = (i64::from(from), i64::from(to));
+        let mut ranges = Vec::new();
+        for (start, end, old) in self.ranges() {
+            if end < from || start > to {
+                if old == value && (end + 1 == from || start == to + 1) {
+                    from = from.min(start);
+                    to = to.max(end);
+                } else {
+                    ranges.push((start, end, old));
+                }
+                continue;
+            }
+            if old == value {
+                from = from.min(start);
+                to = to.max(end);
+                continue;
+            }
+            // Keep the parts of an overlapping range outside the new one
+            if start < from {
+                ranges.push((start, from - 1, old));
+            }
+            if end > to {
+                ranges.push((to + 1, end, old));
+            }
+        }
+        let mut list = NIL;
+        for (start, end, value) in ranges.into_iter().rev() {
+            let entry = Cons::new(Cons::new(start, end, cx), value, cx);
+            list = Cons::new(entry, list, cx).into();
+        }
+        let entry = Cons::new(Cons::new(from, to, cx), value, cx);
+        self.set_slot(RANGES, Cons::new(entry, list, cx).into())
+    }
+
+    /// The entries of RANGES as `(FROM, TO, VALUE)`.
+    fn ranges(self) -> Vec<(i64, i64, Object<'ob>)> {
+        let Ok(ranges) = self.slot(RANGES).as_list() else { return Vec::new() };
+        ranges
+            .flatten()
+            .filter_map(|entry| {
+                let ObjectType::Cons(entry) = entry.untag() else { return None };
+                let ObjectType::Cons(range) = entry.car().untag() else { return None };
+                match (range.car().untag(), range.cdr().untag()) {
+                    (ObjectType::Int(from), ObjectType::Int(to)) => Some((from, to, entry.cdr())),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+fn char_code(obj: Object) -> Result<u32> {
+    match obj.untag() {
+        ObjectType::Int(x) => match u32::try_from(x) {
+            Ok(chr) if chr <= MAX_CHAR => Ok(chr),
+            _ => bail!(ArgRangeError::new(&[x])),
+        },
+        _ => bail!(TypeError::new(Type::Char, obj)),
+    }
+}
+
+#[defun]
+fn make_char_table<'ob>(
+    purpose: Symbol,
+    init: Option<Object<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let extra_slots = match crate::data::get(purpose, sym::CHAR_TABLE_EXTRA_SLOTS, env, cx).untag()
+    {
+        ObjectType::NIL => 0,
+        ObjectType::Int(n) => usize::try_from(n).map_err(|_| ArgRangeError::new(&[n]))?,
+        x => bail!(TypeError::new(Type::Int, x)),
+    };
+    let table = CharTable::new(purpose, extra_slots, init.unwrap_or_default(), cx)?;
+    Ok(table.into())
+}
+
+#[defun]
+fn char_table_p(object: Object) -> bool {
+    CharTable::try_from(object).is_ok()
+}
+
+#[defun]
+fn char_table_subtype(char_table: CharTable) -> Object {
+    char_table.purpose()
+}
+
+#[defun]
+fn char_table_parent(char_table: CharTable) -> Object {
+    char_table.slot(PARENT)
+}
+
+#[defun]
+fn set_char_table_parent<'ob>(
+    char_table: CharTable<'ob>,
+    parent: Option<CharTable<'ob>>,
+) -> Result<Object<'ob>> {
+    // Make sure the new parent doesn't create a cycle
+    let mut ancestor = parent;
+    while let Some(table) = ancestor {
+        ensure!(
+            !std::ptr::eq(table.0, char_table.0),
+            "Attempt to make a chartable be its own parent"
+        );
+        ancestor = table.parent();
+    }
+    let parent = parent.map_or(NIL, Object::from);
+    char_table.set_slot(PARENT, parent)?;
+    Ok(parent)
+}
+
+#[defun]
+fn char_table_extra_slot(char_table: CharTable, n: usize) -> Result<Object> {
+    char_table.extra_slot(n)
+}
+
+#[defun]
+fn set_char_table_extra_slot<'ob>(
+    char_table: CharTable,
+    n: usize,
+    value: Object<'ob>,
+) -> Result<Object<'ob>> {
+    char_table.set_extra_slot(n, value)?;
+    Ok(value)
+}
+
+#[defun]
+fn char_table_range<'ob>(char_table: CharTable<'ob>, range: Object) -> Result<Object<'ob>> {
+    Ok(match range.untag() {
+        ObjectType::NIL => char_table.default_value(),
+        ObjectType::Int(_) => char_table.get(char_code(range)?),
+        // Like Emacs, this returns the value of the start of the range
+        ObjectType::Cons(cons) => char_table.get(char_code(cons.car())?),
+        _ => bail!("Invalid RANGE argument to `char-table-range': {range}"),
+    })
+}
+
+#[defun]
+fn set_char_table_range<'ob>(
+    char_table: CharTable<'ob>,
+    range: Object,
+    value: Object<'ob>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    match range.untag() {
+        ObjectType::NIL => char_table.set_slot(DEFAULT, value)?,
+        ObjectType::Symbol(sym::TRUE) => char_table.set_range(0, MAX_CHAR, value, cx)?,
+        ObjectType::Int(_) => char_table.set(char_code(range)?, value),
+        ObjectType::Cons(cons) => {
+            let from = char_code(cons.car())?;
+            let to = char_code(cons.cdr())?;
+            char_table.set_range(from, to, value, cx)?;
+        }
+        _ => bail!("Invalid RANGE argument to `set-char-table-range': {range}"),
+    }
+    Ok(value)
+}
+
+/// Look up `chr` in a char-table that maps characters to characters. Returns
+/// `None` if the table has no character for it.
+pub(crate) fn char_table_char(table: CharTable, chr: char) -> Option<char> {
+    match table.get(chr.into()).untag() {
+        ObjectType::Int(x) => int_to_char(x).ok(),
+        _ => None,
+    }
+}
+
+defsym!(CHAR_TABLE);
+defsym!(CHAR_TABLE_EXTRA_SLOTS);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_char_table() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let purpose = crate::core::env::intern("test-table", cx);
+        env.set_prop(purpose, sym::CHAR_TABLE_EXTRA_SLOTS, cx.add(2));
+        let table = make_char_table(purpose, None, env, cx).unwrap();
+        assert!(char_table_p(table));
+        let table = CharTable::try_from(table).unwrap();
+        assert_eq!(table.extra_slots(), 2);
+        assert_eq!(char_table_subtype(table), purpose);
+
+        set_char_table_range(table, cx.add('a'), cx.add(1), cx).unwrap();
+        set_char_table_range(table, range('x', 'z', cx), cx.add(2), cx).unwrap();
+        assert_eq!(table.get('a'.into()), 1);
+        assert_eq!(table.get('y'.into()), 2);
+        assert_eq!(table.get('b'.into()), NIL);
+
+        // A range replaces characters set before it
+        set_char_table_range(table, range('a', 'c', cx), cx.add(3), cx).unwrap();
+        assert_eq!(table.get('a'.into()), 3);
+        set_char_table_range(table, cx.add('b'), cx.add(4), cx).unwrap();
+        assert_eq!(table.get('b'.into()), 4);
+        assert_eq!(table.get('c'.into()), 3);
+
+        // Ranges replace the parts of older ranges they cover, and merge with
+        // ranges next to them that have the same value
+        set_char_table_range(table, range('a', 'z', cx), cx.add(1), cx).unwrap();
+        set_char_table_range(table, range('m', 'p', cx), cx.add(2), cx).unwrap();
+        assert_eq!(table.ranges().len(), 3);
+        assert_eq!(table.get('l'.into()), 1);
+        assert_eq!(table.get('n'.into()), 2);
+        assert_eq!(table.get('q'.into()), 1);
+        set_char_table_range(table, range('g', 'r', cx), cx.add(1), cx).unwrap();
+        assert_eq!(table.ranges(), vec![('a' as i64, 'z' as i64, cx.add(1))]);
+        for _ in 0..3 {
+            set_char_table_range(table, range('a', 'c', cx), cx.add(3), cx).unwrap();
+        }
+        assert_eq!(table.ranges().len(), 2);
+        assert_eq!(table.get('b'.into()), 3);
+        assert_eq!(table.get('d'.into()), 1);
+
+        // Default value and parent
+        set_char_table_range(table, NIL, cx.add(5), cx).unwrap();
+        assert_eq!(table.get('q'.into()), 5);
+        let parent = CharTable::new(purpose, 0, cx.add(6), cx).unwrap();
+        set_char_table_parent(table, Some(parent)).unwrap();
+        set_char_table_range(table, NIL, NIL, cx).unwrap();
+        assert_eq!(table.get('q'.into()), 6);
+        assert!(set_char_table_parent(parent, Some(table)).is_err());
+
+        set_char_table_extra_slot(table, 1, cx.add(7)).unwrap();
+        assert_eq!(char_table_extra_slot(table, 1).unwrap(), 7);
+        assert!(char_table_extra_slot(table, 2).is_err());
+    }
+
+    fn range<'ob>(from: char, to: char, cx: &'ob Context) -> Object<'ob> {
+        Cons::new(from, to, cx).into()
+    }
+}
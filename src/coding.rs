@@ -0,0 +1,157 @@
+//! Coding systems, which convert between the bytes of files and processes and
+//! the text of strings. Only UTF-8 and Latin-1 are supported, under any of
+//! their Emacs names. Strings can't hold raw bytes, so bytes that aren't valid
+//! UTF-8 are decoded as U+FFFD.
+use crate::core::{
+    env::intern,
+    error::{Type, TypeError},
+    gc::Context,
+    object::{Object, ObjectType, Symbol},
+};
+use anyhow::{anyhow, bail, Result};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Coding {
+    #[default]
+    Utf8,
+    Latin1,
+}
+
+impl Coding {
+    /// The coding system called `name`. The end of line conversion, like the
+    /// `-unix` in `utf-8-unix`, is ignored.
+    pub(crate) fn from_name(name: &str) -> Result<Self> {
+        let base = ["-unix", "-dos", "-mac"]
+            .iter()
+            .find_map(|eol| name.strip_suffix(eol))
+            .unwrap_or(name);
+        match base {
+            "utf-8"
+            | "utf-8-emacs"
+            | "utf-8-auto"
+            | "utf-8-with-signature"
+            | "prefer-utf-8"
+            | "undecided"
+            | "us-ascii"
+            | "emacs-internal" => Ok(Coding::Utf8),
+            "latin-1" | "iso-latin-1" | "iso-8859-1" | "raw-text" | "binary" | "no-conversion" => {
+                Ok(Coding::Latin1)
+            }
+            _ => bail!("Unsupported coding system: {name}"),
+        }
+    }
+
+    /// The coding system named by the symbol `obj`. `nil` is the default.
+    pub(crate) fn from_object(obj: Object) -> Result<Self> {
+        match obj.untag() {
+            ObjectType::NIL => Ok(Coding::default()),
+            ObjectType::Symbol(name) => Self::from_name(name.name()),
+            _ => bail!(TypeError::new(Type::Symbol, obj)),
+        }
+    }
+
+    pub(crate) fn symbol<'ob>(self, cx: &'ob Context) -> Symbol<'ob> {
+        match self {
+            Coding::Utf8 => intern("utf-8", cx),
+            Coding::Latin1 => intern("iso-latin-1", cx),
+        }
+    }
+
+    /// Decode all of `bytes`.
+    pub(crate) fn decode(self, bytes: &[u8]) -> String {
+        let mut decoder = Decoder::default();
+        decoder.decode(bytes, self) + &decoder.finish()
+    }
+
+    pub(crate) fn encode(self, text: &str) -> Result<Cow<'_, [u8]>> {
+        match self {
+            Coding::Utf8 => Ok(Cow::Borrowed(text.as_bytes())),
+            Coding::Latin1 => text
+                .chars()
+                .map(|chr| {
+                    u8::try_from(chr).map_err(|_| anyhow!("Can't encode {chr:?} as Latin-1"))
+                })
+                .collect::<Result<_>>()
+                .map(Cow::Owned),
+        }
+    }
+}
+
+/// Decodes text that arrives in chunks, like the output of a process. A UTF-8
+/// sequence that is split between two chunks is held back until the rest of
+/// it arrives.
+#[derive(Default)]
+pub(crate) struct Decoder {
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    pub(crate) fn decode(&mut self, bytes: &[u8], coding: Coding) -> String {
+        self.pending.extend_from_slice(bytes);
+        if coding == Coding::Latin1 {
+            return self.pending.drain(..).map(char::from).collect();
+        }
+        let mut text = String::new();
+        let mut rest = &self.pending[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, invalid) = rest.split_at(e.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &invalid[len..];
+                        }
+                        // The sequence continues in the next chunk
+                        None => {
+                            rest = invalid;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        let decoded = self.pending.len() - rest.len();
+        self.pending.drain(..decoded);
+        text
+    }
+
+    /// Decode the bytes held back at the end of the text, which can only be
+    /// an incomplete sequence.
+    pub(crate) fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        String::from_utf8_lossy(&rest).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decoder() {
+        let text = "aé€😀b".as_bytes();
+        // Split the text at every byte
+        for i in 0..=text.len() {
+            let mut decoder = Decoder::default();
+            let mut decoded = decoder.decode(&text[..i], Coding::Utf8);
+            decoded += &decoder.decode(&text[i..], Coding::Utf8);
+            assert_eq!(decoded + &decoder.finish(), "aé€😀b");
+        }
+        let mut decoder = Decoder::default();
+        assert_eq!(decoder.decode(b"a\xffb\xe2\x82", Coding::Utf8), "a\u{FFFD}b");
+        assert_eq!(decoder.finish(), "\u{FFFD}");
+        assert_eq!(Coding::Latin1.decode(b"\xe9"), "é");
+        assert_eq!(Coding::Latin1.encode("é").unwrap(), &b"\xe9"[..]);
+        assert!(Coding::Latin1.encode("€").is_err());
+        assert_eq!(Coding::from_name("utf-8-unix").unwrap(), Coding::Utf8);
+        assert!(Coding::from_name("euc-jp").is_err());
+    }
+}
@@ -1,20 +1,45 @@
+use super::env::sym;
 use super::gc::{Block, GcHeap, GcState, Trace};
-use super::object::{CloneIn, Gc, IntoObject, ObjCell, Object, ObjectType, NIL};
+use super::object::{
+    address, Ancestors, CloneIn, Gc, IntoObject, ObjCell, Object, ObjectType, NIL,
+};
 use crate::NewtypeMarkable;
 use anyhow::{anyhow, Result};
-use rune_core::hashmap::HashSet;
 use rune_macros::Trace;
+use std::cell::Cell;
 use std::fmt::{self, Debug, Display, Write};
 
 mod iter;
 
 pub(crate) use iter::*;
 
+thread_local! {
+    /// Whether forms like `(quote x)` are printed as `'x`. Follows
+    /// `print-quoted`, see [`set_print_quoted`].
+    static PRINT_QUOTED: Cell<bool> = const { Cell::new(true) };
+    /// The number of times a watched cons was modified on this thread. See
+    /// [`Cons::watch`].
+    static WATCHED_CHANGES: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The number of times a cons marked with [`Cons::watch`] was modified on this
+/// thread.
+pub(crate) fn watched_changes() -> u64 {
+    WATCHED_CHANGES.get()
+}
+
+/// Set whether conses print with reader abbreviations on this thread, and
+/// return the previous setting.
+pub(crate) fn set_print_quoted(value: bool) -> bool {
+    PRINT_QUOTED.replace(value)
+}
+
 mod sealed {
     use super::*;
     #[derive(Eq)]
     pub(crate) struct ConsInner {
         pub(super) mutable: bool,
+        pub(super) watched: Cell<bool>,
         pub(super) car: ObjCell,
         pub(super) cdr: ObjCell,
     }
@@ -47,7 +72,12 @@ impl Cons {
     // the stack. Otherwise it could outlive it's objects since it has no
     // lifetimes.
     unsafe fn new_unchecked(car: Object, cdr: Object) -> ConsInner {
-        ConsInner { mutable: true, car: ObjCell::new(car), cdr: ObjCell::new(cdr) }
+        ConsInner {
+            mutable: true,
+            watched: Cell::new(false),
+            car: ObjCell::new(car),
+            cdr: ObjCell::new(cdr),
+        }
     }
 
     /// Create a new cons cell
@@ -93,8 +123,27 @@ impl ConsInner {
         self.cdr.get()
     }
 
+    /// Whether this cons can be modified. Conses that are part of a function
+    /// definition in the global block are immutable and never move.
+    pub(crate) fn is_mutable(&self) -> bool {
+        self.mutable
+    }
+
+    /// Count changes to this cons in [`watched_changes`], so that anything
+    /// derived from it can tell when it is out of date.
+    pub(crate) fn watch(&self) {
+        self.watched.set(true);
+    }
+
+    fn note_change(&self) {
+        if self.watched.get() {
+            WATCHED_CHANGES.set(WATCHED_CHANGES.get() + 1);
+        }
+    }
+
     pub(crate) fn set_car(&self, new_car: Object) -> Result<()> {
         if self.mutable {
+            self.note_change();
             unsafe { self.car.as_mut().set(new_car) }
             Ok(())
         } else {
@@ -104,6 +153,7 @@ impl ConsInner {
 
     pub(crate) fn set_cdr(&self, new_cdr: Object) -> Result<()> {
         if self.mutable {
+            self.note_change();
             unsafe { self.cdr.as_mut().set(new_cdr) }
             Ok(())
         } else {
@@ -129,13 +179,13 @@ impl Trace for ConsInner {
 
 impl Display for Cons {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.display_walk(f, &mut HashSet::default())
+        self.display_walk(f, &mut Ancestors::default())
     }
 }
 
 impl Debug for Cons {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.display_walk(f, &mut HashSet::default())
+        self.display_walk(f, &mut Ancestors::default())
     }
 }
 
@@ -143,45 +193,78 @@ impl Cons {
     pub(super) fn display_walk(
         &self,
         f: &mut fmt::Formatter,
-        seen: &mut HashSet<*const u8>,
+        path: &mut Ancestors<()>,
     ) -> fmt::Result {
-        if self.is_backref(seen) {
+        if !path.enter(address(self), ()) {
             return f.write_str("#0");
         }
+        let mut conses = vec![address(self)];
+        let result = self.display_conses(f, path, &mut conses);
+        for cons in conses {
+            path.leave(cons);
+        }
+        result
+    }
+
+    /// Print the list starting at this cons, adding the conses along its cdrs
+    /// to `conses` as they are entered.
+    fn display_conses(
+        &self,
+        f: &mut fmt::Formatter,
+        path: &mut Ancestors<()>,
+        conses: &mut Vec<*const u8>,
+    ) -> fmt::Result {
+        if let Some((prefix, obj)) = self.abbreviation() {
+            f.write_str(prefix)?;
+            return obj.untag().display_walk(f, path);
+        }
 
         f.write_char('(')?;
         let mut cons = self;
 
         loop {
-            cons.car().untag().display_walk(f, seen)?;
+            cons.car().untag().display_walk(f, path)?;
             match cons.cdr().untag() {
+                ObjectType::Cons(tail) if !path.enter(address(tail), ()) => {
+                    f.write_str(" . #0")?;
+                    break;
+                }
                 ObjectType::Cons(tail) => {
+                    conses.push(address(tail));
                     cons = tail;
                     f.write_char(' ')?;
                 }
                 ObjectType::NIL => break,
                 x => {
                     write!(f, " . ")?;
-                    x.display_walk(f, seen)?;
+                    x.display_walk(f, path)?;
                     break;
                 }
             }
-            if cons.is_backref(seen) {
-                f.write_str(". #0")?;
-                break;
-            }
         }
         f.write_char(')')
     }
 
-    fn is_backref(&self, seen: &mut HashSet<*const u8>) -> bool {
-        let ptr = (self as *const Self).cast();
-        if seen.contains(&ptr) {
-            true
-        } else {
-            seen.insert(ptr);
-            false
+    /// The reader abbreviation of a form like `(quote x)` and the object it
+    /// applies to. Always `None` when abbreviations are turned off.
+    pub(crate) fn abbreviation(&self) -> Option<(&'static str, Object)> {
+        if !PRINT_QUOTED.get() {
+            return None;
+        }
+        let ObjectType::Symbol(head) = self.car().untag() else { return None };
+        let ObjectType::Cons(tail) = self.cdr().untag() else { return None };
+        if tail.cdr() != NIL {
+            return None;
         }
+        let prefix = match head {
+            sym::QUOTE => "'",
+            sym::FUNCTION => "#'",
+            sym::BACKQUOTE => "`",
+            sym::UNQUOTE => ",",
+            sym::SPLICE => ",@",
+            _ => return None,
+        };
+        Some((prefix, tail.car()))
     }
 }
 
@@ -225,4 +308,21 @@ mod test {
         assert_eq!(lhs, list![5, 1, 1.5, "foo"; cx]);
         assert_ne!(lhs, list![5, 1, 1.5, "bar"; cx]);
     }
+
+    #[test]
+    fn print_quoted() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let quoted = list![sym::QUOTE, list![sym::FUNCTION, sym::CAR; cx]; cx];
+        assert_eq!(quoted.to_string(), "'#'car");
+        let splice = list![sym::BACKQUOTE, list![1, list![sym::SPLICE, 2; cx]; cx]; cx];
+        assert_eq!(splice.to_string(), "`(1 ,@2)");
+        // Only forms with exactly one argument are abbreviated
+        assert_eq!(list![sym::QUOTE, 1, 2; cx].to_string(), "(quote 1 2)");
+        assert_eq!(list![1, sym::QUOTE, 2; cx].to_string(), "(1 quote 2)");
+
+        set_print_quoted(false);
+        assert_eq!(quoted.to_string(), "(quote (function car))");
+        set_print_quoted(true);
+    }
 }
@@ -1,40 +1,130 @@
 use super::gc::{Context, ObjectMap, Rto, Slot};
-use super::object::{LispBuffer, Object, OpenBuffer, Symbol, WithLifetime};
+use super::object::{LispBuffer, Object, OpenBuffer, Symbol, WithLifetime, TRUE};
 use anyhow::{anyhow, Result};
 use rune_macros::Trace;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+pub(crate) mod namespace;
+mod snapshot;
 mod stack;
 mod symbol_map;
 pub(crate) use stack::*;
 pub(crate) use symbol_map::*;
 
 type PropertyMap<'a> = ObjectMap<Slot<Symbol<'a>>, Vec<(Slot<Symbol<'a>>, Slot<Object<'a>>)>>;
+type BufferLocalMap<'a> =
+    ObjectMap<Slot<Symbol<'a>>, Vec<(Slot<&'a LispBuffer>, Slot<Object<'a>>)>>;
 #[derive(Debug, Default, Trace)]
 pub(crate) struct Env<'a> {
     pub(crate) vars: ObjectMap<Slot<Symbol<'a>>, Slot<Object<'a>>>,
     pub(crate) props: PropertyMap<'a>,
+    /// Buffer-local values of variables. A variable without a local value in
+    /// the current buffer uses its default value from `vars`.
+    buffer_locals: BufferLocalMap<'a>,
     pub(crate) catch_stack: Vec<Slot<Object<'a>>>,
-    exception: (Slot<Object<'a>>, Slot<Object<'a>>),
-    #[no_trace]
-    exception_id: u32,
     binding_stack: Vec<(Slot<Symbol<'a>>, Option<Slot<Object<'a>>>)>,
+    /// The buffer of each entry in `binding_stack` that bound a buffer-local
+    /// value, or `None` if it bound the default value
+    binding_buffers: Vec<Option<Slot<&'a LispBuffer>>>,
     pub(crate) match_data: Slot<Object<'a>>,
+    /// Saved copies of the global state, most recent last
+    snapshots: Vec<snapshot::Snapshot<'a>>,
     #[no_trace]
     pub(crate) current_buffer: Option<OpenBuffer<'a>>,
     pub(crate) stack: LispStack<'a>,
+    /// Which environment events from other threads are delivered to
+    #[no_trace]
+    pub(crate) id: EnvId,
+}
+
+/// Identifies an [`Env`]. Every environment gets a new id when it is created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EnvId(u64);
+
+impl Default for EnvId {
+    fn default() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        EnvId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
 }
 
 // RootedEnv created by #[derive(Trace)]
 impl<'a> RootedEnv<'a> {
-    pub(crate) fn set_var(&mut self, sym: Symbol, value: Object) -> Result<()> {
-        if sym.is_const() {
-            Err(anyhow!("Attempt to set a constant symbol: {sym}"))
+    /// Get the value of `var` in the current buffer. This is the buffer-local
+    /// value if there is one, otherwise the default value.
+    pub(crate) fn var(&self, var: Symbol, cx: &Context) -> Option<&Rto<Object<'a>>> {
+        match &self.current_buffer {
+            Some(buffer) => {
+                self.local_var(var, buffer.lisp_buffer(cx)).or_else(|| self.vars.get(var))
+            }
+            None => self.vars.get(var),
+        }
+    }
+
+    /// Set the value of `var` in the current buffer. If `var` has a
+    /// buffer-local value or is automatically buffer-local, this sets the local
+    /// value. Otherwise it sets the default value.
+    pub(crate) fn set_var(&mut self, var: Symbol, value: Object, cx: &Context) -> Result<()> {
+        if let Some(buffer) = self.current_buffer.as_ref().map(|x| x.lisp_buffer(cx)) {
+            if var.is_buffer_local() || self.local_var(var, buffer).is_some() {
+                return self.set_local_var(var, value, buffer);
+            }
+        }
+        self.set_default(var, value)
+    }
+
+    /// Set the default value of `var`, which is seen in buffers that don't
+    /// have a local value.
+    pub(crate) fn set_default(&mut self, var: Symbol, value: Object) -> Result<()> {
+        if var.is_const() {
+            Err(anyhow!("Attempt to set a constant symbol: {var}"))
         } else {
-            self.vars.insert(sym, value);
+            self.vars.insert(var, value);
             Ok(())
         }
     }
 
+    /// Get the buffer-local value of `var` in `buffer`, if it has one.
+    pub(crate) fn local_var(&self, var: Symbol, buffer: &LispBuffer) -> Option<&Rto<Object<'a>>> {
+        let locals = self.buffer_locals.get(var)?;
+        locals.iter().find(|x| x.0 == buffer).map(|x| &x.1)
+    }
+
+    /// Set the buffer-local value of `var` in `buffer`, creating it if needed.
+    pub(crate) fn set_local_var(
+        &mut self,
+        var: Symbol,
+        value: Object,
+        buffer: &LispBuffer,
+    ) -> Result<()> {
+        if var.is_const() {
+            return Err(anyhow!("Attempt to set a constant symbol: {var}"));
+        }
+        match self.buffer_locals.get_mut(var) {
+            Some(locals) => match locals.iter_mut().find(|x| x.0 == buffer) {
+                Some(x) => x.1.set(value),
+                None => locals.push((buffer, value)),
+            },
+            None => {
+                self.buffer_locals.insert(var, vec![(buffer, value)]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the buffer-local value of `var` in `buffer`, so that it uses the
+    /// default value again.
+    pub(crate) fn kill_local_var(&mut self, var: Symbol, buffer: &LispBuffer) {
+        if let Some(locals) = self.buffer_locals.get_mut(var) {
+            if let Some(idx) = locals.iter().position(|x| x.0 == buffer) {
+                locals.remove(idx);
+            }
+            if locals.is_empty() {
+                self.buffer_locals.remove(var);
+            }
+        }
+    }
+
     pub(crate) fn set_prop(&mut self, symbol: Symbol, propname: Symbol, value: Object) {
         match self.props.get_mut(symbol) {
             Some(plist) => match plist.iter_mut().find(|x| x.0 == propname) {
@@ -47,40 +137,88 @@ impl<'a> RootedEnv<'a> {
         }
     }
 
-    pub(crate) fn set_exception(&mut self, tag: Object, data: Object) -> u32 {
-        self.exception.0.set(tag);
-        self.exception.1.set(data);
-        self.exception_id += 1;
-        self.exception_id
-    }
-
-    pub(crate) fn get_exception(&self, id: u32) -> Option<(&Rto<Object<'a>>, &Rto<Object<'a>>)> {
-        (id == self.exception_id).then_some((&self.exception.0, &self.exception.1))
-    }
-
+    /// Dynamically bind `var` to `value`. If `var` has a buffer-local value in
+    /// the current buffer then that is what gets bound, and unbinding restores
+    /// it in that buffer even if another buffer is current by then. Otherwise
+    /// the default value is bound.
     pub(crate) fn varbind(&mut self, var: Symbol, value: Object, cx: &Context) {
-        let prev_value = self.vars.get(var).map(|x| x.bind(cx));
+        let buffer = self.current_buffer.as_ref().map(|x| x.lisp_buffer(cx));
+        let local = buffer.filter(|buffer| self.local_var(var, buffer).is_some());
+        let prev_value = match local {
+            Some(buffer) => self.local_var(var, buffer).map(|x| x.bind(cx)),
+            None => self.vars.get(var).map(|x| x.bind(cx)),
+        };
         self.binding_stack.push((var, prev_value));
-        self.vars.insert(var, value);
+        self.binding_buffers.push(local);
+        self.set_binding(var, Some(value), local);
+    }
+
+    /// The variable that the next call to [`unbind`](Self::unbind) restores,
+    /// and the value it restores.
+    pub(crate) fn next_unbinding<'ob>(
+        &self,
+        cx: &'ob Context,
+    ) -> Option<(Symbol<'ob>, Option<Object<'ob>>)> {
+        let (var, value) = self.binding_stack.bind_ref(cx).last()?;
+        Some((**var, value.as_ref().map(|x| **x)))
     }
 
     pub(crate) fn unbind(&mut self, count: u16, cx: &Context) {
         for _ in 0..count {
             match self.binding_stack.bind_mut(cx).pop() {
-                Some((sym, val)) => match val {
-                    Some(val) => self.vars.insert(*sym, *val),
-                    None => self.vars.remove(*sym),
-                },
+                Some((sym, val)) => {
+                    let buffer = self.binding_buffers.bind_mut(cx).pop().flatten();
+                    self.set_binding(*sym, val.map(|x| *x), buffer.map(|x| *x));
+                }
                 None => panic!("Binding stack was empty"),
             }
         }
     }
 
+    /// The number of dynamic bindings in effect.
+    pub(crate) fn binding_depth(&self) -> usize {
+        self.binding_stack.len()
+    }
+
+    /// Return to the state of the top level after an error escaped from lisp.
+    /// Dynamic bindings are undone, and the stack and catch tags are cleared,
+    /// so nothing is left over from the code that was interrupted.
+    pub(crate) fn reset_to_toplevel(&mut self, cx: &Context) {
+        while !self.binding_stack.is_empty() {
+            self.unbind(1, cx);
+        }
+        self.catch_stack.truncate(0);
+        self.stack.clear();
+    }
+
+    /// Set the binding of `var` that a dynamic binding applies to: its local
+    /// value in `buffer`, or the default value if `buffer` is `None`. If the
+    /// local value has been killed since it was bound there is nothing to set.
+    /// A value of `None` makes it unbound.
+    fn set_binding(&mut self, var: Symbol, value: Option<Object>, buffer: Option<&LispBuffer>) {
+        let Some(buffer) = buffer else {
+            match value {
+                Some(value) => self.vars.insert(var, value),
+                None => self.vars.remove(var),
+            }
+            return;
+        };
+        match value {
+            Some(value) => {
+                let locals = self.buffer_locals.get_mut(var);
+                if let Some(local) = locals.and_then(|x| x.iter_mut().find(|x| x.0 == buffer)) {
+                    local.1.set(value);
+                }
+            }
+            None => self.kill_local_var(var, buffer),
+        }
+    }
+
     pub(crate) fn defvar(&mut self, var: Symbol, value: Object) -> Result<()> {
         // TOOD: Handle `eval-sexp` on defvar, which should always update the
         // value
         if self.vars.get(var).is_none() {
-            self.set_var(var, value)?;
+            self.set_default(var, value)?;
             var.make_special();
         }
 
@@ -94,6 +232,15 @@ impl<'a> RootedEnv<'a> {
         Ok(())
     }
 
+    /// Define `var` as a constant. Unlike [`Env::defvar`], the value is always
+    /// set, and the variable is marked as risky to set as a file local.
+    pub(crate) fn defconst(&mut self, var: Symbol, value: Object) -> Result<()> {
+        self.set_default(var, value)?;
+        var.make_special();
+        self.set_prop(var, sym::RISKY_LOCAL_VARIABLE, TRUE);
+        Ok(())
+    }
+
     pub(crate) fn set_buffer(&mut self, buffer: &LispBuffer) -> Result<()> {
         if let Some(current) = &self.current_buffer {
             if buffer == current {
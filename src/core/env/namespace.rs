@@ -0,0 +1,188 @@
+//! Per-thread namespaces, so that several independent interpreters can run in
+//! one process. A thread that has entered a namespace interns symbols in a
+//! table of its own, and keeps the functions and features it defines to
+//! itself. Global variables already belong to each [`Env`](super::Env). Only
+//! the builtin symbols are shared with the rest of the process, so the
+//! namespace still sees the builtin subroutines, but not functions defined on
+//! threads outside of it.
+use super::SymbolMap;
+use crate::core::{
+    gc::Block,
+    object::{CloneIn, Function, FunctionType, Symbol, SymbolCellInner, WithLifetime},
+};
+use anyhow::{bail, Result};
+use rune_core::hashmap::{HashMap, IndexSet};
+use std::cell::{Cell, RefCell};
+
+struct Namespace {
+    /// Functions are cloned here, like the symbol map's block
+    block: Block<true>,
+    /// The functions defined in the namespace. `None` means the function was
+    /// unbound, which hides the builtin as well. Builtin symbols are shared
+    /// with every other table, so their functions can't be kept in the
+    /// symbol.
+    functions: HashMap<*const SymbolCellInner, Option<Function<'static>>>,
+    features: IndexSet<Symbol<'static>>,
+}
+
+thread_local! {
+    static NAMESPACE: RefCell<Option<Namespace>> = const { RefCell::new(None) };
+    /// Kept apart from [`NAMESPACE`] because symbols are interned while it is
+    /// borrowed.
+    static SYMBOLS: Cell<Option<&'static SymbolMap>> = const { Cell::new(None) };
+}
+
+/// Give the current thread a namespace of its own. It lasts until the thread
+/// exits, except for its table of symbols, which is never freed because the
+/// symbols can outlive the thread, like those of the global table.
+pub(crate) fn enter_namespace() {
+    NAMESPACE.with_borrow_mut(|namespace| {
+        assert!(namespace.is_none(), "thread is already in a namespace");
+        *namespace = Some(Namespace {
+            block: Block::default(),
+            functions: HashMap::default(),
+            features: IndexSet::default(),
+        });
+    });
+    let symbols = SymbolMap::with_builtins(Block::default());
+    SYMBOLS.set(Some(Box::leak(Box::new(symbols))));
+}
+
+/// The table of symbols of the current namespace, or `None` if the thread is
+/// not in a namespace.
+pub(in crate::core) fn symbols() -> Option<&'static SymbolMap> {
+    SYMBOLS.get()
+}
+
+pub(crate) fn in_namespace() -> bool {
+    NAMESPACE.with_borrow(Option::is_some)
+}
+
+/// Look up the function of an interned symbol in the current namespace.
+/// Returns `None` if the thread is not in a namespace, in which case the
+/// symbol's own function cell applies.
+pub(in crate::core) fn func<'a>(
+    symbol: &SymbolCellInner,
+    global: Option<Function<'a>>,
+) -> Option<Option<Function<'a>>> {
+    NAMESPACE.with_borrow(|namespace| {
+        let namespace = namespace.as_ref()?;
+        // SAFETY: the function lives in the namespace's block or is a builtin
+        Some(match namespace.functions.get(&std::ptr::from_ref(symbol)) {
+            Some(func) => func.map(|x| unsafe { x.with_lifetime() }),
+            None => global.filter(|x| matches!(x.untag(), FunctionType::SubrFn(_))),
+        })
+    })
+}
+
+/// Set the function of `symbol` in the current namespace. If `clone` is true
+/// the function is first copied into the namespace.
+pub(in crate::core) fn set_func(
+    symbol: &SymbolCellInner,
+    func: Function,
+    clone: bool,
+) -> Result<()> {
+    if symbol.is_const() {
+        bail!("Attempt to set a constant symbol: {symbol}");
+    }
+    NAMESPACE.with_borrow_mut(|namespace| {
+        let namespace = namespace.as_mut().expect("thread should be in a namespace");
+        let func = match clone {
+            true => {
+                let func = func.clone_in(&namespace.block);
+                namespace.block.uninterned_symbol_map.clear();
+                func
+            }
+            false => func,
+        };
+        // SAFETY: the function is read-only and lives as long as the namespace
+        let func = unsafe { func.with_lifetime() };
+        namespace.functions.insert(symbol, Some(func));
+    });
+    Ok(())
+}
+
+/// Unbind the function of `symbol` in the current namespace. Returns false if
+/// the thread is not in a namespace.
+pub(in crate::core) fn unbind_func(symbol: &SymbolCellInner) -> bool {
+    NAMESPACE.with_borrow_mut(|namespace| match namespace {
+        Some(namespace) => {
+            namespace.functions.insert(symbol, None);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Run `f` on the features of the current namespace, or `None` if the thread is
+/// not in a namespace.
+pub(crate) fn with_features<T>(f: impl FnOnce(&mut IndexSet<Symbol<'static>>) -> T) -> Option<T> {
+    NAMESPACE.with_borrow_mut(|namespace| namespace.as_mut().map(|x| f(&mut x.features)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{
+        env::{intern, interned_symbols, sym, Env},
+        gc::{Context, RootSet, Rt},
+    };
+    use rune_core::macros::root;
+
+    fn eval(code: &str, env: &mut Rt<Env>, cx: &mut Context) -> String {
+        let obj = crate::reader::read(code, cx).unwrap().0;
+        root!(obj, cx);
+        match crate::interpreter::eval(obj, None, env, cx) {
+            Ok(x) => x.to_string(),
+            Err(_) => "error".into(),
+        }
+    }
+
+    fn provided() -> bool {
+        crate::data::with_features(|x| x.iter().any(|x| x.name() == "namespace-feature"))
+    }
+
+    #[test]
+    fn test_namespace() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        eval("(defalias 'namespace-outer #'(lambda () 1))", env, cx);
+        eval("(defalias 'namespace-shared #'(lambda () 1))", env, cx);
+        let thread = std::thread::spawn(|| {
+            enter_namespace();
+            let roots = &RootSet::default();
+            let cx = &mut Context::new(roots);
+            root!(env, new(Env), cx);
+            assert!(in_namespace());
+            let results = [
+                eval("(fboundp 'namespace-outer)", env, cx),
+                eval("(+ 1 2)", env, cx),
+                eval("(defalias 'namespace-shared #'(lambda () 2))", env, cx),
+                eval("(namespace-shared)", env, cx),
+                eval("(provide 'namespace-feature)", env, cx),
+                provided().to_string(),
+                eval("(fset 'car nil)", env, cx),
+                eval("(fboundp 'car)", env, cx),
+                eval("(intern-soft \"namespace-outer\")", env, cx),
+                eval("(defvar namespace-inner 1)", env, cx),
+            ];
+            results
+        });
+        let results = thread.join().unwrap();
+        assert_eq!(results[0], "nil");
+        assert_eq!(results[1], "3");
+        assert_eq!(results[3], "2");
+        assert_eq!(results[5], "true");
+        assert_eq!(results[7], "nil");
+        // The namespace has its own table of symbols
+        assert_eq!(results[8], "nil");
+        assert!(interned_symbols().get("namespace-inner").is_none());
+
+        assert!(!in_namespace());
+        assert_eq!(eval("(namespace-shared)", env, cx), "1");
+        assert!(!provided());
+        assert!(intern("car", cx).has_func());
+    }
+}
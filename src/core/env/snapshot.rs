@@ -0,0 +1,127 @@
+//! Saving and restoring the global state, so that code can be evaluated and
+//! then undone without building a new environment.
+use super::{interned_symbols, PropertyMap, RootedEnv};
+use crate::core::{
+    gc::{Context, IntoRoot, ObjectMap, Slot},
+    object::{Function, Object, Symbol, WithLifetime},
+};
+use rune_core::hashmap::{HashMap, IndexSet};
+use rune_macros::Trace;
+
+/// A copy of the global state. Created by [`RootedEnv::save_snapshot`].
+#[derive(Debug, Default, Trace)]
+pub(super) struct Snapshot<'a> {
+    vars: ObjectMap<Slot<Symbol<'a>>, Slot<Object<'a>>>,
+    props: PropertyMap<'a>,
+    /// The function of each interned symbol. Interned symbols and their
+    /// functions live in the symbol map's block, so they are never collected,
+    /// and symbols are not purged while there is a snapshot.
+    #[no_trace]
+    functions: Vec<(Symbol<'static>, Option<Function<'static>>)>,
+    #[no_trace]
+    features: IndexSet<Symbol<'static>>,
+}
+
+impl<'old, 'new> IntoRoot<Snapshot<'new>> for Snapshot<'old> {
+    unsafe fn into_root(self) -> Snapshot<'new> {
+        std::mem::transmute(self)
+    }
+}
+
+impl RootedEnv<'_> {
+    /// Save the global state: the default values and properties of variables,
+    /// the functions of interned symbols, and the loaded features.
+    /// [`restore_snapshot`](Self::restore_snapshot) rolls back to it. Snapshots
+    /// can be nested.
+    pub(crate) fn save_snapshot(&mut self, cx: &Context) {
+        let functions = interned_symbols()
+            .symbols()
+            // SAFETY: see `Snapshot::functions`
+            .map(|symbol| unsafe {
+                (symbol.with_lifetime(), symbol.func(cx).map(|x| x.with_lifetime()))
+            })
+            .collect();
+        let features = crate::data::with_features(|features| features.clone());
+        self.snapshots.push(Snapshot { functions, features, ..Snapshot::default() });
+        let snapshot = self.snapshots.last_mut().unwrap();
+        snapshot.vars.copy_from(&self.vars);
+        snapshot.props.copy_from(&self.props);
+    }
+
+    /// Whether any snapshots are saved.
+    pub(crate) fn has_snapshots(&self) -> bool {
+        !self.snapshots.is_empty()
+    }
+
+    /// Roll back to the most recent snapshot and discard it. Symbols interned
+    /// since then stay interned, but lose their functions. Function cells and
+    /// features are shared by every environment, so this undoes definitions
+    /// made through other environments as well. Returns false if there is no
+    /// snapshot.
+    pub(crate) fn restore_snapshot(&mut self) -> bool {
+        let Some(snapshot) = self.snapshots.last() else { return false };
+        self.vars.copy_from(&snapshot.vars);
+        self.props.copy_from(&snapshot.props);
+        let functions: HashMap<_, _> =
+            snapshot.functions.iter().map(|(symbol, func)| (symbol.name(), *func)).collect();
+        let map = interned_symbols();
+        for symbol in map.symbols() {
+            match functions.get(symbol.name()).copied().flatten() {
+                Some(func) => map.restore_func(symbol, func).expect("symbol was not constant"),
+                None => symbol.unbind_func(),
+            }
+        }
+        crate::data::with_features(|features| *features = snapshot.features.clone());
+        self.snapshots.pop();
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::core::{
+        env::{sym, Env},
+        gc::{Context, RootSet, Rt},
+    };
+    use rune_core::macros::root;
+
+    fn eval(code: &str, env: &mut Rt<Env>, cx: &mut Context) -> String {
+        let obj = crate::reader::read(code, cx).unwrap().0;
+        root!(obj, cx);
+        match crate::interpreter::eval(obj, None, env, cx) {
+            Ok(x) => x.to_string(),
+            Err(_) => "error".into(),
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        eval("(setq snapshot-var 1)", env, cx);
+        eval("(defalias 'snapshot-fn #'(lambda () 1))", env, cx);
+        assert!(!env.restore_snapshot());
+
+        env.save_snapshot(cx);
+        eval("(setq snapshot-var 2 snapshot-new-var 3)", env, cx);
+        eval("(put 'snapshot-var 'snapshot-prop t)", env, cx);
+        eval("(defalias 'snapshot-fn #'(lambda () 2))", env, cx);
+        eval("(defalias 'snapshot-new-fn #'(lambda () 3))", env, cx);
+        eval("(provide 'snapshot-feature)", env, cx);
+        cx.garbage_collect(true);
+        env.save_snapshot(cx);
+        eval("(setq snapshot-var 4)", env, cx);
+        assert!(env.restore_snapshot());
+        assert_eq!(eval("snapshot-var", env, cx), "2");
+
+        assert!(env.restore_snapshot());
+        assert_eq!(eval("snapshot-var", env, cx), "1");
+        assert_eq!(eval("snapshot-new-var", env, cx), "error");
+        assert_eq!(eval("(get 'snapshot-var 'snapshot-prop)", env, cx), "nil");
+        assert_eq!(eval("(snapshot-fn)", env, cx), "1");
+        assert_eq!(eval("(fboundp 'snapshot-new-fn)", env, cx), "nil");
+        assert_eq!(eval("(featurep 'snapshot-feature)", env, cx), "nil");
+    }
+}
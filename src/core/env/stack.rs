@@ -2,9 +2,14 @@ use crate::core::{
     gc::{Context, IntoRoot, Rt, Rto, Slot},
     object::{ByteFn, Object, WithLifetime, NIL},
 };
+use anyhow::{ensure, Result};
 use rune_macros::Trace;
 use std::ops::{Deref, DerefMut, Index, IndexMut, RangeBounds, RangeTo};
 
+/// The most objects the stack can hold. Calls that would need more signal an
+/// error instead of growing it without bound.
+const MAX_STACK_SIZE: usize = 1 << 20;
+
 /// The stack of lisp objects used to pass and store arguments in the bytecode
 /// VM and interpreter. The top of the stack is index 0 and all indexing
 /// functions operate from top to bottom. The stack is partitioned into frames.
@@ -135,18 +140,19 @@ impl<'a> RootedLispStack<'a> {
         depth: usize,
         func: &ByteFn,
         pc: usize,
-    ) {
+    ) -> Result<()> {
         assert!(start <= self.len());
         assert!(self.current.start <= start);
-        self.frames.push(FrameStore::new_bytecode(self.current, func, pc));
         let end = start + depth;
+        ensure!(end <= MAX_STACK_SIZE, "Lisp stack overflow");
+        self.frames.push(FrameStore::new_bytecode(self.current, func, pc));
         // allocate space so that we don't have to reallocate later. This will
         // also let us do unchecked pushes later.
         if end > self.vec.capacity() {
-            assert!(end - self.vec.len() < 100); // make sure this doesn't blow up
             self.vec.reserve(end - self.vec.len());
         }
         self.current = Frame { start, end, ..Frame::default() };
+        Ok(())
     }
 
     pub(crate) fn push_frame(&mut self, arg_cnt: usize) {
@@ -195,30 +201,43 @@ impl<'a> RootedLispStack<'a> {
         self.vec.len()
     }
 
-    pub(crate) fn set_depth(&mut self, depth: usize) {
+    /// Remove every frame, leaving the stack as it was before any function
+    /// was called.
+    pub(crate) fn clear(&mut self) {
+        self.vec.truncate(0);
+        self.frames.truncate(0);
+        self.current = Frame::default();
+    }
+
+    /// Limit the current frame to `depth` elements, which is the maximum stack
+    /// usage of the bytecode function running in it.
+    pub(crate) fn set_depth(&mut self, depth: usize) -> Result<()> {
         let end = self.current.start + depth;
+        ensure!(end <= MAX_STACK_SIZE, "Lisp stack overflow");
         self.current.end = end;
 
         if end > self.vec.capacity() {
-            assert!(end - self.vec.len() < 1000); // make sure this doesn't blow up if we have a bug
             self.vec.reserve(end - self.vec.len());
         }
+        Ok(())
     }
 
     pub(crate) fn set_arg_count(&mut self, arg_cnt: u16, rest: bool) {
         self.current.arg_cnt = (arg_cnt, rest);
     }
 
-    pub(crate) fn push<T: IntoRoot<Slot<Object<'a>>>>(&mut self, value: T) {
-        if self.len() >= self.current.end {
-            panic!(
-                "overflowed max depth - len was {}, but limit was {}",
-                self.len(),
-                self.current.end
-            );
-        }
+    /// Push `value` onto the current frame. Going past the depth of the frame
+    /// or the size of the whole stack is an error.
+    pub(crate) fn push<T: IntoRoot<Slot<Object<'a>>>>(&mut self, value: T) -> Result<()> {
+        ensure!(
+            self.len() < self.current.end,
+            "Lisp stack overflow: frame depth is {}",
+            self.current.end - self.current.start
+        );
+        ensure!(self.len() < MAX_STACK_SIZE, "Lisp stack overflow");
         // could use https://github.com/rust-lang/rust/issues/100486
         self.vec.push(value);
+        Ok(())
     }
 
     pub(crate) fn pop<'ob>(&mut self, cx: &'ob Context) -> Object<'ob> {
@@ -238,9 +257,9 @@ impl<'a> RootedLispStack<'a> {
         from_end
     }
 
-    pub(crate) fn push_ref(&mut self, i: impl Into<i32>, cx: &Context) {
+    pub(crate) fn push_ref(&mut self, i: impl Into<i32>, cx: &Context) -> Result<()> {
         let obj = self[i.into() as usize].bind(cx);
-        self.push(obj);
+        self.push(obj)
     }
 
     pub(crate) fn set_ref(&mut self, i: impl Into<usize>) {
@@ -248,10 +267,11 @@ impl<'a> RootedLispStack<'a> {
         self.vec.swap_remove(index);
     }
 
-    pub(crate) fn fill_extra_args(&mut self, fill_args: u16) {
+    pub(crate) fn fill_extra_args(&mut self, fill_args: u16) -> Result<()> {
         for _ in 0..fill_args {
-            self.push(NIL);
+            self.push(NIL)?;
         }
+        Ok(())
     }
 
     pub(crate) fn remove_top(&mut self, i: usize) {
@@ -318,8 +338,8 @@ impl<'brw, 'rt> CallFrame<'brw, 'rt> {
     }
 
     /// Push an argument onto the stack as part of this call frame
-    pub(crate) fn push_arg(&mut self, arg: impl IntoRoot<Slot<Object<'rt>>>) {
-        self.env.stack.push(arg);
+    pub(crate) fn push_arg(&mut self, arg: impl IntoRoot<Slot<Object<'rt>>>) -> Result<()> {
+        self.env.stack.push(arg)
     }
 
     /// Set the total argument count before a function call
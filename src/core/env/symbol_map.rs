@@ -1,64 +1,349 @@
+//! The table of interned symbols. It is shared by every thread outside of a
+//! [namespace](super::namespace), which has a table of its own. Looking up a
+//! symbol does not take a lock, and interning a new one only locks the stripe
+//! of the table it goes in. The table grows as symbols are added. Symbols are
+//! listed in the order they were interned, so `mapatoms` is the same from run
+//! to run.
+//!
+//! Interned symbols are not part of any heap, so the collector never frees
+//! them. [`SymbolMap::purge`] removes the ones that nothing refers to, like
+//! typos and transient names made with `intern`.
+use super::namespace;
 use crate::core::{
-    gc::{Block, Context},
+    gc::{Block, Context, ObjectUsage},
     object::{CloneIn, Function, LispBuffer, Symbol, WithLifetime},
 };
 use anyhow::Result;
-use rune_core::hashmap::HashMap;
+use rune_core::hashmap::FxBuildHasher;
+use std::hash::BuildHasher;
+use std::mem::{size_of, size_of_val};
+use std::sync::atomic::{fence, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 
 pub(crate) struct SymbolMap {
     map: SymbolMapCore,
-    block: Block<true>,
+    block: Mutex<Block<true>>,
+}
+
+/// The number of buckets the table starts with. It doubles whenever the
+/// chains get longer than [`MAX_LOAD`] on average.
+const INITIAL_BUCKETS: usize = 1 << 12;
+/// The average number of symbols per bucket that makes the table grow.
+const MAX_LOAD: usize = 2;
+/// The number of locks that new symbols are spread over. The number of
+/// buckets is always a multiple of this, so a name is in the same stripe
+/// whatever the size of the table.
+const STRIPES: usize = 64;
+
+/// An entry in the chain of a bucket. Readers follow the chain without
+/// locking, so a node is only freed once no reader can be on it.
+struct Node {
+    symbol: Symbol<'static>,
+    /// When the symbol was interned, relative to the others
+    order: usize,
+    next: AtomicPtr<Node>,
+}
+
+/// The buckets of the table. When the table grows, the nodes are copied into
+/// a new one, and the old one is freed once it has no readers.
+struct Table {
+    buckets: Box<[AtomicPtr<Node>]>,
+}
+
+impl Table {
+    fn new(len: usize) -> Self {
+        Self { buckets: (0..len).map(|_| AtomicPtr::default()).collect() }
+    }
+
+    fn bucket(&self, hash: usize) -> &AtomicPtr<Node> {
+        &self.buckets[hash % self.buckets.len()]
+    }
+
+    /// Push a node for `symbol` on the front of the chain for `hash`.
+    fn push(&self, hash: usize, symbol: Symbol<'static>, order: usize) {
+        let head = self.bucket(hash);
+        let next = AtomicPtr::new(head.load(Ordering::Acquire));
+        head.store(Box::into_raw(Box::new(Node { symbol, order, next })), Ordering::Release);
+    }
+
+    /// Call `f` on each node in the table.
+    fn for_each(&self, mut f: impl FnMut(&Node)) {
+        for head in &*self.buckets {
+            let mut node = head.load(Ordering::Acquire);
+            // SAFETY: Callers make sure the nodes are not freed meanwhile
+            while let Some(current) = unsafe { node.as_ref() } {
+                f(current);
+                node = current.next.load(Ordering::Acquire);
+            }
+        }
+    }
+}
+
+impl Drop for Table {
+    fn drop(&mut self) {
+        for head in &*self.buckets {
+            let mut node = head.load(Ordering::Acquire);
+            while !node.is_null() {
+                // SAFETY: The table owns its nodes, and is only dropped once
+                // it has no readers. The symbols are not freed.
+                let current = unsafe { Box::from_raw(node) };
+                node = current.next.load(Ordering::Acquire);
+            }
+        }
+    }
 }
 
 struct SymbolMapCore {
-    map: HashMap<&'static str, Symbol<'static>>,
+    table: AtomicPtr<Table>,
+    /// The number of symbols in the table
+    len: AtomicUsize,
+    /// Held while changing a bucket, so a symbol is only added once. All of
+    /// them are held to grow the table or remove symbols.
+    stripes: Box<[Mutex<()>]>,
+    /// The number of readers following a chain in each stripe
+    readers: Box<[AtomicUsize]>,
+    /// The order of the next symbol interned
+    next_order: AtomicUsize,
+    /// The symbols removed by purges so far
+    purged: Mutex<ObjectUsage>,
+}
+
+/// Counts a reader of a stripe until it is dropped.
+struct ReadGuard<'a>(&'a AtomicUsize);
+
+impl<'a> ReadGuard<'a> {
+    fn new(readers: &'a AtomicUsize) -> Self {
+        readers.fetch_add(1, Ordering::SeqCst);
+        // Pairs with the fence in `SymbolMapCore::wait_for_readers`, so either
+        // the reader sees the change or the writer sees the reader.
+        fence(Ordering::SeqCst);
+        Self(readers)
+    }
+}
+
+impl Drop for ReadGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Release);
+    }
 }
 
 impl SymbolMapCore {
-    fn with_capacity(cap: usize) -> Self {
+    fn new() -> Self {
         Self {
-            map: HashMap::with_capacity_and_hasher(cap, std::hash::BuildHasherDefault::default()),
+            table: AtomicPtr::new(Box::into_raw(Box::new(Table::new(INITIAL_BUCKETS)))),
+            len: AtomicUsize::new(0),
+            stripes: (0..STRIPES).map(|_| Mutex::default()).collect(),
+            readers: (0..STRIPES).map(|_| AtomicUsize::new(0)).collect(),
+            next_order: AtomicUsize::new(0),
+            purged: Mutex::default(),
         }
     }
 
-    fn get(&self, name: &str) -> Option<Symbol> {
-        self.map.get(name).map(|x| unsafe { x.with_lifetime() })
-    }
-
-    fn intern<'ob>(&mut self, name: &str, block: &Block<true>, cx: &'ob Context) -> Symbol<'ob> {
-        match self.get(name) {
-            Some(x) => cx.bind(x),
-            None => {
-                let name = name.to_owned();
-                // Leak the memory so that it is static
-                let static_name: &'static str = unsafe {
-                    let name_ptr: *const str = Box::into_raw(name.into_boxed_str());
-                    &*name_ptr
-                };
-                let sym = Symbol::new(static_name, block);
-                self.map.insert(static_name, unsafe { sym.with_lifetime() });
-                cx.bind(sym)
+    fn hash(name: &str) -> usize {
+        FxBuildHasher::default().hash_one(name) as usize
+    }
+
+    /// The current table. It is only replaced or freed while every stripe is
+    /// locked and there are no readers.
+    ///
+    /// # Safety
+    ///
+    /// The caller has to hold a stripe lock or a [`ReadGuard`] while using it.
+    unsafe fn table(&self) -> &Table {
+        unsafe { &*self.table.load(Ordering::Acquire) }
+    }
+
+    fn lock(&self, stripe: usize) -> MutexGuard<'_, ()> {
+        self.stripes[stripe].lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Lock every stripe, which stops symbols from being added or removed.
+    fn lock_all(&self) -> Vec<MutexGuard<'_, ()>> {
+        (0..STRIPES).map(|stripe| self.lock(stripe)).collect()
+    }
+
+    /// Wait for the readers that could have reached something that was just
+    /// unlinked. Readers that start after this can't find it.
+    fn wait_for_readers(&self) {
+        fence(Ordering::SeqCst);
+        for readers in &*self.readers {
+            while readers.load(Ordering::Acquire) != 0 {
+                std::hint::spin_loop();
             }
         }
     }
 
-    fn pre_init(&mut self, sym: Symbol<'static>) {
-        use std::collections::hash_map::Entry;
+    fn find(&self, hash: usize, name: &str) -> Option<Symbol<'static>> {
+        let _guard = ReadGuard::new(&self.readers[hash % STRIPES]);
+        // SAFETY: The table and its nodes are not freed while there are
+        // readers in the stripe
+        let mut node = unsafe { self.table() }.bucket(hash).load(Ordering::Acquire);
+        while let Some(current) = unsafe { node.as_ref() } {
+            if current.symbol.name() == name {
+                return Some(current.symbol);
+            }
+            node = current.next.load(Ordering::Acquire);
+        }
+        None
+    }
+
+    fn get(&self, name: &str) -> Option<Symbol> {
+        self.find(Self::hash(name), name)
+    }
+
+    /// Get the symbol named `name`, adding the symbol returned by `new` if
+    /// there is none.
+    fn get_or_insert(&self, name: &str, new: impl FnOnce() -> Symbol<'static>) -> Symbol<'static> {
+        let hash = Self::hash(name);
+        if let Some(symbol) = self.find(hash, name) {
+            return symbol;
+        }
+        let guard = self.lock(hash % STRIPES);
+        // Another thread may have added it while we waited for the lock
+        if let Some(symbol) = self.find(hash, name) {
+            return symbol;
+        }
+        let symbol = new();
+        let order = self.next_order.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: The stripe is locked
+        let table = unsafe { self.table() };
+        table.push(hash, symbol, order);
+        let len = self.len.fetch_add(1, Ordering::Relaxed) + 1;
+        let buckets = table.buckets.len();
+        drop(guard);
+        if len > buckets * MAX_LOAD {
+            self.grow(buckets);
+        }
+        symbol
+    }
+
+    /// Double the number of buckets, unless another thread already has since
+    /// the table had `buckets`.
+    fn grow(&self, buckets: usize) {
+        let _guards = self.lock_all();
+        // SAFETY: Every stripe is locked
+        let old = unsafe { self.table() };
+        if old.buckets.len() != buckets {
+            return;
+        }
+        // The nodes are copied, so readers still on the old table see all of
+        // its chains intact
+        let new = Table::new(buckets * 2);
+        old.for_each(|node| {
+            let hash = Self::hash(node.symbol.name());
+            new.push(hash, node.symbol, node.order);
+        });
+        let old = self.table.swap(Box::into_raw(Box::new(new)), Ordering::AcqRel);
+        self.wait_for_readers();
+        // SAFETY: The old table is unreachable and has no readers
+        drop(unsafe { Box::from_raw(old) });
+    }
+
+    fn intern<'ob>(&self, name: &str, cx: &'ob Context) -> Symbol<'ob> {
+        let symbol = self.get_or_insert(name, || {
+            let name = name.to_owned();
+            // Leak the memory so that it is static
+            let static_name: &'static str = unsafe {
+                let name_ptr: *const str = Box::into_raw(name.into_boxed_str());
+                &*name_ptr
+            };
+            Symbol::new(static_name)
+        });
+        cx.bind(symbol)
+    }
+
+    fn pre_init(&self, sym: Symbol<'static>) {
         let name = sym.get().name();
-        let entry = self.map.entry(name);
-        assert!(matches!(entry, Entry::Vacant(_)), "Attempt to intitalize {name} twice");
-        entry.or_insert_with(|| sym);
+        let existing = self.get_or_insert(name, || sym);
+        assert!(existing == sym, "Attempt to intitalize {name} twice");
+    }
+
+    /// All the symbols with the order they were interned in, in no
+    /// particular order.
+    fn symbols(&self) -> Vec<(usize, Symbol)> {
+        let _guards = self.lock_all();
+        let mut entries = Vec::with_capacity(self.len.load(Ordering::Relaxed));
+        // SAFETY: Every stripe is locked, so no node is freed
+        unsafe { self.table() }.for_each(|node| entries.push((node.order, node.symbol)));
+        entries
+    }
+
+    /// Unlink `symbol` from the table without freeing it. Returns false if it
+    /// is not in the table.
+    fn unlink(&self, symbol: Symbol) -> bool {
+        let hash = Self::hash(symbol.name());
+        let _guard = self.lock(hash % STRIPES);
+        // SAFETY: The stripe is locked, so the table and the nodes in this
+        // bucket are not freed
+        let mut link = unsafe { self.table() }.bucket(hash);
+        while let Some(node) = unsafe { link.load(Ordering::Acquire).as_ref() } {
+            if node.symbol == symbol {
+                link.store(node.next.load(Ordering::Acquire), Ordering::Release);
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                let node = std::ptr::from_ref(node).cast_mut();
+                self.wait_for_readers();
+                // SAFETY: The node is unlinked and has no readers
+                drop(unsafe { Box::from_raw(node) });
+                return true;
+            }
+            link = &node.next;
+        }
+        false
+    }
+
+    /// Unlink and free the symbols that `remove` selects.
+    ///
+    /// # Safety
+    ///
+    /// Nothing can refer to the removed symbols.
+    unsafe fn remove(&self, mut remove: impl FnMut(Symbol) -> bool) -> ObjectUsage {
+        let _guards = self.lock_all();
+        // SAFETY: Every stripe is locked
+        let table = unsafe { self.table() };
+        let mut removed = Vec::new();
+        for head in &*table.buckets {
+            let mut link = head;
+            // SAFETY: Nodes are only freed while every stripe is locked
+            while let Some(node) = unsafe { link.load(Ordering::Acquire).as_ref() } {
+                if remove(node.symbol) {
+                    link.store(node.next.load(Ordering::Acquire), Ordering::Release);
+                    removed.push(std::ptr::from_ref(node).cast_mut());
+                } else {
+                    link = &node.next;
+                }
+            }
+        }
+        let mut usage = ObjectUsage::default();
+        if removed.is_empty() {
+            return usage;
+        }
+        self.wait_for_readers();
+        self.len.fetch_sub(removed.len(), Ordering::Relaxed);
+        for node in removed {
+            // SAFETY: The node is unlinked and has no readers
+            let node = unsafe { Box::from_raw(node) };
+            usage.count += 1;
+            usage.bytes += size_of::<Node>() + unsafe { node.symbol.free() };
+        }
+        let mut purged = self.purged.lock().unwrap_or_else(PoisonError::into_inner);
+        purged.count += usage.count;
+        purged.bytes += usage.bytes;
+        usage
     }
 }
 
 impl SymbolMap {
-    pub(crate) fn intern<'ob>(&mut self, name: &str, cx: &'ob Context) -> Symbol<'ob> {
-        self.map.intern(name, &self.block, cx)
+    pub(crate) fn intern<'ob>(&self, name: &str, cx: &'ob Context) -> Symbol<'ob> {
+        self.map.intern(name, cx)
     }
 
     pub(crate) fn set_func(&self, symbol: Symbol, func: Function) -> Result<()> {
-        let new_func = func.clone_in(&self.block);
-        self.block.uninterned_symbol_map.clear();
+        if symbol.interned() && namespace::in_namespace() {
+            return namespace::set_func(&symbol, func, true);
+        }
+        let block = self.global_block();
+        let new_func = func.clone_in(&*block);
+        block.uninterned_symbol_map.clear();
         #[cfg(miri)]
         new_func.untag().set_as_miri_root();
         // SAFETY: The object is marked read-only, we have cloned in the map's
@@ -66,17 +351,106 @@ impl SymbolMap {
         unsafe { symbol.set_func(new_func) }
     }
 
-    pub(crate) fn global_block(&self) -> &Block<true> {
-        &self.block
+    /// The block for objects shared by every thread. It is locked until the
+    /// guard is dropped.
+    pub(crate) fn global_block(&self) -> MutexGuard<'_, Block<true>> {
+        self.block.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
-    pub(crate) fn create_buffer(&self, name: &str) -> &LispBuffer {
-        LispBuffer::create(name.to_owned(), &self.block)
+    pub(crate) fn create_buffer(&self, name: &str) -> &'static LispBuffer {
+        let block = self.global_block();
+        let buffer = LispBuffer::create(name.to_owned(), &*block);
+        // SAFETY: This can be 'static because the global block is never
+        // dropped. Eventually it will be garbage collected
+        unsafe { &*(buffer as *const LispBuffer) }
     }
 
     pub(crate) fn get(&self, name: &str) -> Option<Symbol> {
         self.map.get(name)
     }
+
+    /// Remove `symbol` from the table, so interning its name makes a new
+    /// symbol. The symbol stays valid, since objects can still refer to it.
+    /// Returns false if it was not interned.
+    pub(crate) fn unintern(&self, symbol: Symbol) -> bool {
+        self.map.unlink(symbol)
+    }
+
+    /// All the interned symbols, in no particular order.
+    pub(crate) fn symbols(&self) -> impl Iterator<Item = Symbol> {
+        self.map.symbols().into_iter().map(|x| x.1)
+    }
+
+    /// All the interned symbols, in the order they were interned. Use this
+    /// where the order can be seen, so that it is the same from run to run.
+    pub(crate) fn symbols_in_order(&self) -> impl Iterator<Item = Symbol> {
+        let mut entries = self.map.symbols();
+        entries.sort_unstable_by_key(|x| x.0);
+        entries.into_iter().map(|x| x.1)
+    }
+
+    /// The number of interned symbols and the memory they use, including
+    /// their names and their entries in the table.
+    pub(crate) fn usage(&self) -> ObjectUsage {
+        let mut usage = ObjectUsage::default();
+        for symbol in self.symbols() {
+            usage.count += 1;
+            usage.bytes += size_of::<Node>() + size_of_val(symbol.get());
+            if !symbol.is_builtin() {
+                usage.bytes += symbol.name().len();
+            }
+        }
+        usage
+    }
+
+    /// The symbols removed by [`Self::purge`] so far, and the memory freed.
+    pub(crate) fn purged(&self) -> ObjectUsage {
+        *self.map.purged.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Forget which symbols the collector reached. This is called before the
+    /// collection that [`Self::purge`] relies on.
+    pub(crate) fn clear_reached(&self) {
+        for symbol in self.symbols() {
+            symbol.clear_reached();
+        }
+    }
+
+    /// Remove the symbols that are unused and that `keep` doesn't select. A
+    /// symbol is unused if it has no function, was never made a variable,
+    /// isn't referred to by the global block, and the collector didn't reach
+    /// it since [`Self::clear_reached`]. Builtin symbols are never removed.
+    /// Returns the number of symbols removed and the memory freed.
+    ///
+    /// # Safety
+    ///
+    /// The collection must have traced every heap that can refer to a
+    /// symbol, and `keep` has to select the symbols held anywhere else. This
+    /// means no other thread can have a context.
+    pub(crate) unsafe fn purge(&self, keep: impl Fn(Symbol) -> bool) -> ObjectUsage {
+        let unused = |symbol: Symbol| {
+            !symbol.is_builtin()
+                && !symbol.reached()
+                && !symbol.pinned()
+                && symbol.global_func().is_none()
+                && (symbol.is_const() || !symbol.is_special())
+                && !symbol.is_buffer_local()
+                && !symbol.is_watched()
+                && !keep(symbol)
+        };
+        unsafe { self.map.remove(unused) }
+    }
+
+    /// Set the function of `symbol` back to `func`, which was taken from a
+    /// symbol in this map.
+    pub(crate) fn restore_func(&self, symbol: Symbol, func: Function) -> Result<()> {
+        if namespace::in_namespace() {
+            return namespace::set_func(&symbol, func, false);
+        }
+        // SAFETY: The function was already cloned into this map's block when
+        // it was first set, so it is read-only and lives as long as the map.
+        unsafe { symbol.set_func(func) }
+    }
 }
 
 // This file includes all symbol definitions. Generated by build.rs
@@ -84,7 +458,7 @@ include!(concat!(env!("OUT_DIR"), "/sym.rs"));
 
 /// Intern a new symbol based on `name`
 pub(crate) fn intern<'ob>(name: &str, cx: &'ob Context) -> Symbol<'ob> {
-    interned_symbols().lock().unwrap().intern(name, cx)
+    interned_symbols().intern(name, cx)
 }
 
 #[cfg(test)]
@@ -94,7 +468,7 @@ mod test {
     use crate::core::object::FunctionType;
     use crate::core::{cons::Cons, env::Env, object::Object};
     use rune_core::macros::{list, root};
-    use std::mem::size_of;
+    use std::mem::{size_of, size_of_val};
 
     #[test]
     fn size() {
@@ -102,6 +476,25 @@ mod test {
         assert_eq!(size_of::<isize>(), size_of::<Function>());
     }
 
+    #[test]
+    fn grow() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let map = SymbolMapCore::new();
+        let count = INITIAL_BUCKETS * MAX_LOAD * 3;
+        let names: Vec<_> = (0..count).map(|i| format!("grow-test-{i}")).collect();
+        let symbols: Vec<_> = names.iter().map(|name| map.intern(name, cx)).collect();
+        assert!(unsafe { map.table() }.buckets.len() > INITIAL_BUCKETS);
+        for (name, symbol) in names.iter().zip(symbols) {
+            assert!(map.get(name).is_some_and(|x| std::ptr::eq(x.get(), symbol.get())));
+        }
+        let mut entries = map.symbols();
+        assert_eq!(entries.len(), count);
+        entries.sort_unstable_by_key(|x| x.0);
+        assert_eq!(entries[0].1.name(), "grow-test-0");
+        unsafe { map.remove(|_| true) };
+    }
+
     #[test]
     fn init() {
         let roots = &RootSet::default();
@@ -175,4 +568,104 @@ mod test {
         root!(env, new(Env), cx);
         init_variables(cx, env);
     }
+
+    /// Intern the same names on several threads at once
+    fn intern_on_threads(names: &[String], threads: usize) -> Vec<Vec<usize>> {
+        std::thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| {
+                    s.spawn(|| {
+                        let roots = &RootSet::default();
+                        let cx = &Context::new(roots);
+                        names.iter().map(|name| intern(name, cx).as_ptr() as usize).collect()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|x| x.join().unwrap()).collect()
+        })
+    }
+
+    #[test]
+    fn test_concurrent_intern() {
+        let names: Vec<_> = (0..500).map(|i| format!("concurrent-intern-{i}")).collect();
+        let symbols = intern_on_threads(&names, 4);
+        assert!(symbols.windows(2).all(|x| x[0] == x[1]));
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let map = interned_symbols();
+        assert_eq!(map.get("concurrent-intern-7"), Some(intern("concurrent-intern-7", cx)));
+        assert_eq!(
+            map.symbols().filter(|x| x.name().starts_with("concurrent-intern-")).count(),
+            500
+        );
+    }
+
+    #[test]
+    fn test_symbol_order() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let names = ["symbol-order-c", "symbol-order-a", "symbol-order-b"];
+        for name in names {
+            intern(name, cx);
+        }
+        let order: Vec<_> = interned_symbols()
+            .symbols_in_order()
+            .map(|x| x.name())
+            .filter(|x| x.starts_with("symbol-order-"))
+            .collect();
+        assert_eq!(order, names);
+    }
+
+    #[test]
+    fn test_purge() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        let map = interned_symbols();
+        intern("purge-test-typo", cx);
+        let kept = intern("purge-test-kept", cx);
+        root!(kept, cx);
+        let func = intern("purge-test-func", cx);
+        crate::data::fset(func, list![intern("purge-test-pinned", cx); cx]).unwrap();
+        intern("purge-test-special", cx).make_special();
+        let before = map.purged();
+
+        map.clear_reached();
+        cx.garbage_collect(true);
+        // Symbols from other tests are kept, since their heaps aren't traced
+        let removed = unsafe { map.purge(|x| !x.name().starts_with("purge-test-")) };
+        assert_eq!(removed.count, 1);
+        assert!(map.get("purge-test-typo").is_none());
+        for name in
+            ["purge-test-kept", "purge-test-func", "purge-test-pinned", "purge-test-special"]
+        {
+            assert!(map.get(name).is_some(), "{name} was purged");
+        }
+        assert_eq!(map.purged().count, before.count + 1);
+        assert_eq!(kept.bind(cx).name(), "purge-test-kept");
+        assert_eq!(intern("purge-test-typo", cx).name(), "purge-test-typo");
+    }
+
+    /// Measure how interning scales with the number of threads. Each thread
+    /// looks up existing names and adds new ones.
+    /// Run with `cargo test --release bench_concurrent_intern -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_concurrent_intern() {
+        let count = 100_000;
+        let existing: Vec<_> = (0..count).map(|i| format!("bench-intern-{}", i % 1000)).collect();
+        intern_on_threads(&existing[..1000], 1);
+        for threads in [1, 2, 4, 8] {
+            let new: Vec<_> = (0..count).map(|i| format!("bench-intern-{threads}-{i}")).collect();
+            let start = std::time::Instant::now();
+            intern_on_threads(&existing, threads);
+            let lookups = start.elapsed();
+            let start = std::time::Instant::now();
+            intern_on_threads(&new, threads);
+            let inserts = start.elapsed();
+            println!(
+                "{threads} threads: lookups {lookups:?}, new symbols {inserts:?} for {count} names"
+            );
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use super::gc::{Context, HeldObject};
+use super::object::{Object, ObjectType, TagType, MAX_FIXNUM, MIN_FIXNUM};
 use std::fmt::{Display, Formatter};
 
 /// The function or form has the wrong number of arguments.
@@ -21,6 +23,39 @@ impl ArgError {
     pub(crate) fn new(expect: u16, actual: u16, name: impl AsRef<str>) -> ArgError {
         Self { expect, actual, name: name.as_ref().to_owned() }
     }
+
+    /// The name of the function and the number of arguments it was given.
+    pub(crate) fn call(&self) -> (&str, u16) {
+        (&self.name, self.actual)
+    }
+}
+
+/// A variable or function was used that has no value or definition.
+#[derive(Debug, PartialEq)]
+pub(crate) enum VoidError {
+    Variable(String),
+    Function(String),
+}
+
+impl std::error::Error for VoidError {}
+
+impl Display for VoidError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            VoidError::Variable(name) => write!(f, "Void variable: {name}"),
+            VoidError::Function(name) => write!(f, "Void function: {name}"),
+        }
+    }
+}
+
+impl VoidError {
+    pub(crate) fn variable(symbol: impl Display) -> Self {
+        VoidError::Variable(symbol.to_string())
+    }
+
+    pub(crate) fn function(symbol: impl Display) -> Self {
+        VoidError::Function(symbol.to_string())
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -30,6 +65,9 @@ pub(crate) enum Type {
     Cons,
     Vec,
     Record,
+    CharTable,
+    Process,
+    Obarray,
     HashTable,
     Sequence,
     BufferOrName,
@@ -40,6 +78,34 @@ pub(crate) enum Type {
     Number,
     List,
     Buffer,
+    StringStream,
+}
+
+impl Type {
+    /// The predicate that objects of this type satisfy, which is the data of
+    /// a `wrong-type-argument` signal.
+    pub(crate) fn predicate(&self) -> &'static str {
+        match self {
+            Type::Int => "integerp",
+            Type::Char => "characterp",
+            Type::Cons => "consp",
+            Type::Vec => "vectorp",
+            Type::Record => "recordp",
+            Type::CharTable => "char-table-p",
+            Type::Process => "processp",
+            Type::Obarray => "obarrayp",
+            Type::HashTable => "hash-table-p",
+            Type::Sequence => "sequencep",
+            Type::BufferOrName | Type::String => "stringp",
+            Type::Symbol => "symbolp",
+            Type::Float => "floatp",
+            Type::Func => "functionp",
+            Type::Number => "numberp",
+            Type::List => "listp",
+            Type::Buffer => "bufferp",
+            Type::StringStream => "string-output-stream-p",
+        }
+    }
 }
 
 /// Error provided if object was the wrong type
@@ -48,13 +114,14 @@ pub(crate) struct TypeError {
     expect: Type,
     actual: Type,
     print: String,
+    object: HeldObject,
 }
 
 impl std::error::Error for TypeError {}
 
 impl Display for TypeError {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        let Self { expect, actual, print } = self;
+        let Self { expect, actual, print, .. } = self;
         write!(f, "expected {expect:?}, found {actual:?}: {print}")
     }
 }
@@ -63,9 +130,104 @@ impl TypeError {
     /// Get a type error from an object.
     pub(crate) fn new<'ob, T>(expect: Type, obj: T) -> Self
     where
-        T: Into<super::object::ObjectType<'ob>>,
+        T: Into<ObjectType<'ob>>,
     {
         let obj = obj.into();
-        Self { expect, actual: obj.get_type(), print: obj.to_string() }
+        let print = obj.to_string();
+        Self { expect, actual: obj.get_type(), print, object: HeldObject::new(obj.tag()) }
+    }
+
+    /// The type that was expected and the object found, if it is still
+    /// available on this thread.
+    pub(crate) fn expected<'ob>(&self, cx: &'ob Context) -> (&Type, Option<Object<'ob>>) {
+        (&self.expect, self.object.get(cx))
+    }
+}
+
+/// An argument of an [`ArgRangeError`].
+#[derive(Debug, PartialEq)]
+pub(crate) enum RangeArg {
+    Int(i64),
+    Object(HeldObject, String),
+    /// A number that can't be represented as a fixnum
+    Text(String),
+}
+
+impl Display for RangeArg {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            RangeArg::Int(x) => write!(f, "{x}"),
+            RangeArg::Object(_, print) | RangeArg::Text(print) => write!(f, "{print}"),
+        }
+    }
+}
+
+impl RangeArg {
+    /// The lisp value of this argument.
+    pub(crate) fn value<'ob>(&self, cx: &'ob Context) -> Object<'ob> {
+        match self {
+            RangeArg::Int(x) => cx.add(*x),
+            RangeArg::Object(obj, print) => obj.get(cx).unwrap_or_else(|| cx.add(print.as_str())),
+            RangeArg::Text(print) => cx.add(print.as_str()),
+        }
+    }
+}
+
+impl<'ob> From<Object<'ob>> for RangeArg {
+    fn from(obj: Object<'ob>) -> Self {
+        match obj.untag() {
+            ObjectType::Int(x) => RangeArg::Int(x),
+            _ => RangeArg::Object(HeldObject::new(obj), obj.to_string()),
+        }
+    }
+}
+
+macro_rules! range_arg_from_int {
+    ($($ty:ty),+) => {$(
+        impl From<$ty> for RangeArg {
+            fn from(x: $ty) -> Self {
+                match i64::try_from(x) {
+                    Ok(x) if (MIN_FIXNUM..=MAX_FIXNUM).contains(&x) => RangeArg::Int(x),
+                    _ => RangeArg::Text(x.to_string()),
+                }
+            }
+        }
+    )+};
+}
+
+range_arg_from_int!(i64, i32, u64, usize, u32, u16, u8);
+
+/// Error provided if an argument was outside the range a function accepts.
+/// This is `args-out-of-range` in Emacs.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ArgRangeError {
+    args: Vec<RangeArg>,
+}
+
+impl std::error::Error for ArgRangeError {}
+
+impl Display for ArgRangeError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Args out of range: ")?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{arg}")?;
+        }
+        Ok(())
+    }
+}
+
+impl ArgRangeError {
+    /// Create an error from the offending arguments, typically the value
+    /// followed by the bounds it was checked against.
+    pub(crate) fn new<T: Copy + Into<RangeArg>>(args: &[T]) -> Self {
+        Self { args: args.iter().map(|x| (*x).into()).collect() }
+    }
+
+    /// The offending arguments.
+    pub(crate) fn args(&self) -> &[RangeArg] {
+        &self.args
     }
 }
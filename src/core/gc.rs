@@ -3,7 +3,9 @@ mod trace;
 #[macro_use]
 mod context;
 mod heap;
+mod held;
 pub(crate) use context::*;
 pub(crate) use heap::*;
+pub(crate) use held::HeldObject;
 pub(crate) use root::*;
 pub(crate) use trace::*;
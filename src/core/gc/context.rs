@@ -1,12 +1,14 @@
 use super::GcState;
 use super::Trace;
-use crate::core::object::{Gc, IntoObject, Object, UninternedSymbolMap, WithLifetime};
+use crate::core::object::{Gc, IntoObject, Object, ObjectType, UninternedSymbolMap, WithLifetime};
 use bumpalo::collections::String as GcString;
 use bumpalo::collections::Vec as GcVec;
 use std::cell::{Cell, RefCell};
 use std::fmt::Debug;
+use std::mem::{size_of, size_of_val};
 use std::ops::Deref;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 /// A global store of all gc roots. This struct should be passed to the [Context]
 /// when it is created.
@@ -38,10 +40,128 @@ pub(crate) struct Context<'rt> {
     pub(crate) block: Block<false>,
     root_set: &'rt RootSet,
     next_limit: usize,
+    gc_policy: GcPolicy,
+    gc_stats: GcStats,
+}
+
+const MIN_GC_BYTES: usize = 2000;
+
+/// The number of live objects of some type and the bytes they occupy.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ObjectUsage {
+    pub(crate) count: usize,
+    pub(crate) bytes: usize,
+}
+
+impl ObjectUsage {
+    fn add(&mut self, bytes: usize) {
+        self.count += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Live heap usage broken down by object type. Created by
+/// [`Context::memory_report`]. Byte counts include the out-of-line storage of
+/// strings, vectors and functions, and the names of uninterned symbols, but
+/// not arena fragmentation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MemoryReport {
+    pub(crate) conses: ObjectUsage,
+    pub(crate) strings: ObjectUsage,
+    pub(crate) vectors: ObjectUsage,
+    pub(crate) functions: ObjectUsage,
+    pub(crate) symbols: ObjectUsage,
+    pub(crate) floats: ObjectUsage,
+    pub(crate) hash_tables: ObjectUsage,
+    pub(crate) buffers: ObjectUsage,
+}
+
+impl MemoryReport {
+    /// Usage for every category, paired with its lisp name.
+    pub(crate) fn categories(&self) -> [(&'static str, ObjectUsage); 8] {
+        [
+            ("conses", self.conses),
+            ("strings", self.strings),
+            ("vectors", self.vectors),
+            ("functions", self.functions),
+            ("symbols", self.symbols),
+            ("floats", self.floats),
+            ("hash-tables", self.hash_tables),
+            ("buffers", self.buffers),
+        ]
+    }
+
+    pub(crate) fn total(&self) -> ObjectUsage {
+        self.categories()
+            .iter()
+            .fold(ObjectUsage::default(), |acc, (_, x)| ObjectUsage {
+                count: acc.count + x.count,
+                bytes: acc.bytes + x.bytes,
+            })
+    }
+
+    pub(crate) fn record(&mut self, obj: Object) {
+        let obj_size = size_of::<Object>();
+        match obj.untag() {
+            ObjectType::Int(_) | ObjectType::SubrFn(_) => {}
+            ObjectType::Cons(x) => self.conses.add(size_of_val(x)),
+            ObjectType::String(x) => self.strings.add(size_of_val(x) + x.len()),
+            ObjectType::ByteString(x) => self.strings.add(size_of_val(x) + x.len()),
+            ObjectType::Vec(x) => self.vectors.add(size_of_val(x) + x.len() * obj_size),
+            ObjectType::Record(x) => self.vectors.add(size_of_val(x) + x.len() * obj_size),
+            ObjectType::ByteFn(x) => self
+                .functions
+                .add(size_of_val(x) + x.codes().len() + x.consts().len() * obj_size),
+            ObjectType::Closure(x) => self.functions.add(size_of_val(x)),
+            ObjectType::Symbol(x) => {
+                // The name of an uninterned symbol is in the heap with it
+                let name = if x.interned() { 0 } else { x.name().len() };
+                self.symbols.add(size_of_val(x.get()) + name);
+            }
+            ObjectType::Float(x) => self.floats.add(size_of_val(x)),
+            ObjectType::HashTable(x) => {
+                self.hash_tables.add(size_of_val(x) + x.len() * 2 * obj_size);
+            }
+            ObjectType::Buffer(x) => self.buffers.add(size_of_val(x)),
+            ObjectType::StringStream(x) => {
+                let len = x.contents().map_or(0, str::len);
+                self.strings.add(size_of_val(x) + len);
+            }
+        }
+    }
+}
+
+/// Controls when [`Context::garbage_collect`] decides to run a collection. A
+/// collection is triggered once the bytes allocated since the last collection
+/// exceed the larger of `threshold` and `percentage` of the live heap. This
+/// mirrors `gc-cons-threshold` and `gc-cons-percentage`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct GcPolicy {
+    pub(crate) threshold: usize,
+    pub(crate) percentage: f64,
+}
+
+impl Default for GcPolicy {
+    fn default() -> Self {
+        Self { threshold: MIN_GC_BYTES, percentage: 0.2 }
+    }
+}
+
+/// Counters describing the garbage collections run by a [`Context`].
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct GcStats {
+    /// Number of collections run so far
+    pub(crate) gcs_done: usize,
+    /// Total time spent collecting
+    pub(crate) elapsed: Duration,
+    /// Bytes that survived the last collection
+    pub(crate) live_bytes: usize,
 }
 
 impl<'rt> Drop for Context<'rt> {
     fn drop(&mut self) {
+        CONTEXTS.fetch_sub(1, Ordering::SeqCst);
+        super::held::release_held();
         self.garbage_collect(true);
         if self.block.objects.allocated_bytes() == 0 {
             return;
@@ -57,11 +177,23 @@ impl<'rt> Drop for Context<'rt> {
 thread_local! {
     /// Ensure there is only one context per thread.
     static SINGLETON_CHECK: Cell<bool> = const { Cell::new(false) };
+    /// The number of collections run on this thread by any context.
+    static COLLECTIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The number of collections run on this thread. Objects in the heap of a
+/// thread are only moved or freed by a collection, so an address taken on this
+/// thread stays valid until this changes.
+pub(crate) fn collections() -> u64 {
+    COLLECTIONS.get()
 }
 
 /// Ensure there is only one global context.
 static GLOBAL_CHECK: AtomicBool = AtomicBool::new(false);
 
+/// The number of contexts alive in every thread.
+static CONTEXTS: AtomicUsize = AtomicUsize::new(0);
+
 impl Block<true> {
     pub(crate) fn new_global() -> Self {
         use std::sync::atomic::Ordering::SeqCst as Ord;
@@ -125,15 +257,24 @@ impl<const CONST: bool> Block<CONST> {
 }
 
 impl<'ob, 'rt> Context<'rt> {
-    const MIN_GC_BYTES: usize = 2000;
-    const GC_GROWTH_FACTOR: usize = 12; // divide by 10
     pub(crate) fn new(roots: &'rt RootSet) -> Self {
-        Self { block: Block::new_local(), root_set: roots, next_limit: Self::MIN_GC_BYTES }
+        Self::with_block(Block::new_local(), roots)
     }
 
     pub(crate) fn from_block(block: Block<false>, roots: &'rt RootSet) -> Self {
         Block::assert_unique();
-        Context { block, root_set: roots, next_limit: Self::MIN_GC_BYTES }
+        Self::with_block(block, roots)
+    }
+
+    fn with_block(block: Block<false>, roots: &'rt RootSet) -> Self {
+        CONTEXTS.fetch_add(1, Ordering::SeqCst);
+        Context {
+            block,
+            root_set: roots,
+            next_limit: MIN_GC_BYTES,
+            gc_policy: GcPolicy::default(),
+            gc_stats: GcStats::default(),
+        }
     }
 
     pub(crate) fn bind<T>(&'ob self, obj: T) -> <T as WithLifetime>::Out
@@ -147,13 +288,64 @@ impl<'ob, 'rt> Context<'rt> {
         self.root_set
     }
 
+    pub(crate) fn gc_policy(&self) -> GcPolicy {
+        self.gc_policy
+    }
+
+    /// Change when automatic collections are triggered. Takes effect
+    /// immediately, so lowering the threshold can make the next call to
+    /// [`garbage_collect`](Self::garbage_collect) collect.
+    pub(crate) fn set_gc_policy(&mut self, policy: GcPolicy) {
+        self.gc_policy = policy;
+        self.next_limit = self.gc_stats.live_bytes + self.gc_allowance();
+    }
+
+    pub(crate) fn gc_stats(&self) -> GcStats {
+        self.gc_stats
+    }
+
+    /// Bytes allocated since the last collection.
+    pub(crate) fn bytes_since_gc(&self) -> usize {
+        self.block.objects.allocated_bytes().saturating_sub(self.gc_stats.live_bytes)
+    }
+
+    /// Bytes that can be allocated after a collection before the next
+    /// automatic collection is triggered.
+    pub(crate) fn gc_allowance(&self) -> usize {
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let proportional = (self.gc_stats.live_bytes as f64 * self.gc_policy.percentage) as usize;
+        self.gc_policy.threshold.max(proportional)
+    }
+
+    /// Whether this is the only context in the process, so no other thread
+    /// can be using lisp objects.
+    pub(crate) fn is_only_context(&self) -> bool {
+        CONTEXTS.load(Ordering::SeqCst) == 1
+    }
+
     pub(crate) fn garbage_collect(&mut self, force: bool) {
         let bytes = self.block.objects.allocated_bytes();
         if cfg!(not(test)) && !force && bytes < self.next_limit {
             return;
         }
+        self.collect(GcState::new());
+    }
 
+    /// Run a full collection and report the objects that survived it. This is
+    /// the only way to get an accurate picture of the live heap, because the
+    /// arena has no object headers to walk between collections.
+    pub(crate) fn memory_report(&mut self) -> MemoryReport {
         let mut state = GcState::new();
+        state.report = Some(MemoryReport::default());
+        self.collect(state).unwrap_or_default()
+    }
+
+    fn collect(&mut self, mut state: GcState) -> Option<MemoryReport> {
+        let start = Instant::now();
         for x in self.root_set.roots.borrow().iter() {
             // SAFETY: The contract of root structs will ensure that it removes
             // itself from this list before it drops.
@@ -161,12 +353,19 @@ impl<'ob, 'rt> Context<'rt> {
                 (**x).trace(&mut state);
             }
         }
+        super::held::trace_held(&mut state);
 
         state.trace_stack();
 
-        self.next_limit = (state.to_space.allocated_bytes() * Self::GC_GROWTH_FACTOR) / 10;
+        self.gc_stats.live_bytes = state.to_space.allocated_bytes();
+        self.gc_stats.gcs_done += 1;
+        COLLECTIONS.set(COLLECTIONS.get() + 1);
+        self.next_limit = self.gc_stats.live_bytes + self.gc_allowance();
         self.block.drop_stack.borrow_mut().clear();
+        let report = state.report.take();
         self.block.objects = state.to_space;
+        self.gc_stats.elapsed += start.elapsed();
+        report
     }
 }
 
@@ -261,5 +460,7 @@ mod test {
         assert_eq!(string, "string");
         assert_eq!(**float, 1.5);
         assert_eq!(int, 1);
+        // The name of an uninterned symbol moves with it
+        assert_eq!(key.to_string(), "sym");
     }
 }
@@ -0,0 +1,116 @@
+//! Objects held by values that can't be rooted on the stack, like the errors
+//! returned from Rust functions. Errors are passed around in `anyhow::Error`
+//! and outlive the frame that created them, so the objects they refer to are
+//! kept in a table that every collection on this thread traces.
+use super::{GcState, Slot, Trace};
+use crate::core::object::{Object, WithLifetime};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, ThreadId};
+
+thread_local! {
+    static HELD: RefCell<HashMap<u64, Slot<Object<'static>>>> = RefCell::default();
+}
+
+/// A handle to an object that stays alive and is updated by the collector
+/// until the handle is dropped. The object can only be retrieved on the thread
+/// that created the handle.
+#[derive(Debug)]
+pub(crate) struct HeldObject {
+    id: u64,
+    thread: ThreadId,
+}
+
+impl HeldObject {
+    pub(crate) fn new(obj: Object) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: The object is traced by every collection until it is removed
+        // from the table, so the 'static lifetime is never observed.
+        let obj: Object<'static> = unsafe { obj.with_lifetime() };
+        HELD.with(|held| held.borrow_mut().insert(id, Slot::new(obj)));
+        Self { id, thread: thread::current().id() }
+    }
+
+    /// The held object, or `None` if this is called on another thread or the
+    /// context the object belonged to has been dropped.
+    pub(crate) fn get<'ob>(&self, _cx: &'ob super::Context) -> Option<Object<'ob>> {
+        if self.thread != thread::current().id() {
+            return None;
+        }
+        HELD.with(|held| {
+            // SAFETY: Binding to the context ensures the object can't be used
+            // across a collection.
+            held.borrow().get(&self.id).map(|obj| unsafe { (**obj).with_lifetime() })
+        })
+    }
+}
+
+impl PartialEq for HeldObject {
+    fn eq(&self, other: &Self) -> bool {
+        if self.id == other.id {
+            return true;
+        }
+        if self.thread != other.thread || self.thread != thread::current().id() {
+            return false;
+        }
+        HELD.with(|held| {
+            let held = held.borrow();
+            matches!((held.get(&self.id), held.get(&other.id)), (Some(x), Some(y)) if **x == **y)
+        })
+    }
+}
+
+impl Drop for HeldObject {
+    fn drop(&mut self) {
+        if self.thread == thread::current().id() {
+            // The table is already gone if this thread is exiting
+            let _ = HELD.try_with(|held| held.borrow_mut().remove(&self.id));
+        }
+    }
+}
+
+/// Trace all held objects on this thread.
+pub(super) fn trace_held(state: &mut GcState) {
+    HELD.with(|held| {
+        for obj in held.borrow().values() {
+            obj.trace(state);
+        }
+    });
+}
+
+/// Release all held objects on this thread. Called when the context they
+/// were allocated in is dropped, after which the handles return `None`.
+pub(super) fn release_held() {
+    HELD.with(|held| held.borrow_mut().clear());
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Context, RootSet};
+    use super::*;
+    use crate::eval::EvalError;
+
+    fn is_held(id: u64) -> bool {
+        HELD.with(|held| held.borrow().contains_key(&id))
+    }
+
+    #[test]
+    fn release_on_drop() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let obj = cx.add("held");
+        let held = HeldObject::new(obj);
+        let id = held.id;
+        cx.garbage_collect(true);
+        assert_eq!(held.get(cx).unwrap().to_string(), "\"held\"");
+        drop(held);
+        assert!(!is_held(id));
+        // A signal that is dropped without being handled releases its data
+        let count = HELD.with(|held| held.borrow().len());
+        let data = cx.add("data");
+        drop(EvalError::signal(data, data));
+        assert_eq!(HELD.with(|held| held.borrow().len()), count);
+    }
+}
@@ -376,6 +376,14 @@ impl<T> Rt<T> {
     }
 }
 
+impl<T: Clone> Rt<T> {
+    /// Replace the contents of this root with a copy of `other`. Both are
+    /// rooted, so nothing is exposed to the collector.
+    pub(crate) fn copy_from(&mut self, other: &Self) {
+        *self.inner_mut() = other.inner().clone();
+    }
+}
+
 impl<T> Rt<Slot<T>> {
     pub(crate) fn bind<'ob>(&self, _: &'ob Context) -> <T as WithLifetime<'ob>>::Out
     where
@@ -668,6 +676,14 @@ impl<K, V> Default for ObjectMap<K, V> {
     }
 }
 
+impl<K: Clone, V: Clone> Clone for ObjectMap<K, V> {
+    fn clone(&self) -> Self {
+        // SAFETY: The map is only mutated during collection, which can't
+        // happen while we hold a reference to it.
+        Self(UnsafeCell::new(unsafe { &*self.0.get() }.clone()))
+    }
+}
+
 impl<K, V> Rt<ObjectMap<K, V>>
 where
     K: Eq + Hash,
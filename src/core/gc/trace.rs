@@ -1,4 +1,5 @@
 use super::super::object::RawObj;
+use super::MemoryReport;
 use crate::core::object::{Gc, Object};
 use rune_core::hashmap::{HashMap, HashSet};
 
@@ -9,11 +10,13 @@ pub(crate) trait Trace {
 pub(crate) struct GcState {
     stack: Vec<RawObj>,
     pub(in crate::core) to_space: bumpalo::Bump,
+    /// When set, every object that survives the collection is recorded here
+    pub(in crate::core) report: Option<MemoryReport>,
 }
 
 impl GcState {
     pub fn new() -> Self {
-        GcState { stack: Vec::new(), to_space: bumpalo::Bump::new() }
+        GcState { stack: Vec::new(), to_space: bumpalo::Bump::new(), report: None }
     }
 
     pub fn push(&mut self, obj: Object) {
@@ -19,6 +19,7 @@ mod string;
 mod symbol;
 mod tagged;
 mod vector;
+mod walk;
 
 pub(crate) use buffer::*;
 pub(super) use cell::*;
@@ -30,6 +31,7 @@ pub(crate) use string::*;
 pub(crate) use symbol::*;
 pub(crate) use tagged::*;
 pub(crate) use vector::*;
+pub(crate) use walk::*;
 
 use std::fmt::Write as _;
 
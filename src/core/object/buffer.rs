@@ -13,7 +13,7 @@ use rune_macros::Trace;
 use std::{
     fmt::Display,
     ops::{Deref, DerefMut},
-    sync::{Mutex, MutexGuard},
+    sync::{Mutex, MutexGuard, PoisonError},
 };
 use text_buffer::Buffer as TextBuffer;
 
@@ -56,11 +56,34 @@ impl<'a> OpenBuffer<'a> {
             ObjectType::String(s) => self.get_mut().text.insert(s),
             x => bail!(TypeError::new(Type::String, x)),
         }
+        self.get_mut().modified = true;
         Ok(())
     }
 
     pub(crate) fn delete(&mut self, beg: usize, end: usize) {
         self.get_mut().text.delete_range(beg, end);
+        self.get_mut().modified = true;
+    }
+
+    /// Replace the text between `beg` and `end` with `text`. Point stays at the
+    /// same place relative to the surrounding text.
+    pub(crate) fn replace_region(&mut self, beg: usize, end: usize, text: &str) {
+        let (beg, end) = (beg.min(end), beg.max(end));
+        self.get_mut().modified = true;
+        let buffer = &mut self.get_mut().text;
+        let point = buffer.cursor().chars();
+        buffer.delete_range(beg, end);
+        buffer.set_cursor(beg);
+        buffer.insert(text);
+        let new_end = beg + text.chars().count();
+        let point = if point <= beg {
+            point
+        } else if point >= end {
+            point - end + new_end
+        } else {
+            point.min(new_end)
+        };
+        buffer.set_cursor(point);
     }
 }
 
@@ -98,6 +121,12 @@ impl DerefMut for OpenBuffer<'_> {
 pub(crate) struct BufferData {
     pub(crate) name: String,
     pub(crate) text: TextBuffer,
+    /// Whether the text has changed since the buffer was last saved.
+    pub(crate) modified: bool,
+    /// The modification tick of the text when it was last saved
+    pub(crate) save_tick: usize,
+    /// The modification tick of the text when it was last auto-saved
+    pub(crate) auto_save_tick: usize,
 }
 
 #[derive(Debug)]
@@ -120,13 +149,19 @@ impl LispBuffer {
 
     pub(crate) unsafe fn new(name: String, _: &Block<true>) -> LispBuffer {
         let new = LispBufferInner {
-            text_buffer: Mutex::new(Some(BufferData { name, text: TextBuffer::new() })),
+            text_buffer: Mutex::new(Some(BufferData {
+                name,
+                text: TextBuffer::new(),
+                modified: false,
+                save_tick: 0,
+                auto_save_tick: 0,
+            })),
         };
         Self(GcHeap::new(new, true))
     }
 
     pub(in crate::core) fn lock(&self) -> Result<OpenBuffer<'_>> {
-        let guard = self.text_buffer.lock().unwrap();
+        let guard = self.text_buffer.lock().unwrap_or_else(PoisonError::into_inner);
         if guard.is_none() {
             bail!("selecting deleted buffer");
         }
@@ -156,7 +191,7 @@ impl Eq for LispBufferInner {}
 
 impl Display for LispBufferInner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let data = self.text_buffer.lock().unwrap();
+        let data = self.text_buffer.lock().unwrap_or_else(PoisonError::into_inner);
         let name = match data.as_ref() {
             Some(buf) => &buf.name,
             None => "deleted buffer",
@@ -3,11 +3,11 @@
 //! those are ever stabalized.
 
 use super::{
-    super::error::{ArgError, Type, TypeError},
-    ByteString, LispHashTable, LispString, LispVec, NIL, TRUE,
+    super::error::{ArgError, ArgRangeError, RangeArg, Type, TypeError},
+    int_to_char, ByteString, LispHashTable, LispString, LispVec, MAX_FIXNUM, MIN_FIXNUM, NIL, TRUE,
 };
 use super::{Gc, LispFloat, Object, ObjectType, Symbol};
-use anyhow::Context;
+use crate::core::cons::Cons;
 
 impl<'ob> TryFrom<Object<'ob>> for &'ob str {
     type Error = anyhow::Error;
@@ -41,44 +41,57 @@ impl<'ob> TryFrom<Object<'ob>> for Option<&'ob str> {
     }
 }
 
-impl<'ob> TryFrom<Object<'ob>> for usize {
-    type Error = anyhow::Error;
-    fn try_from(obj: Object<'ob>) -> Result<Self, Self::Error> {
-        match obj.untag() {
-            ObjectType::Int(x) => {
-                x.try_into().with_context(|| format!("Integer must be positive, but was {x}"))
+/// Conversions for integer types narrower than a fixnum. Values that don't
+/// fit in the type signal `args-out-of-range` instead of being truncated.
+macro_rules! define_int_conversion {
+    ($($ty:ty),+) => {$(
+        impl<'ob> TryFrom<Object<'ob>> for $ty {
+            type Error = anyhow::Error;
+            fn try_from(obj: Object<'ob>) -> Result<Self, Self::Error> {
+                match obj.untag() {
+                    ObjectType::Int(x) => Ok(x.try_into().map_err(|_| ArgRangeError::new(&[x]))?),
+                    x => Err(TypeError::new(Type::Int, x).into()),
+                }
             }
-            x => Err(TypeError::new(Type::Int, x).into()),
         }
-    }
-}
 
-impl<'ob> TryFrom<Object<'ob>> for u64 {
-    type Error = anyhow::Error;
-    fn try_from(obj: Object<'ob>) -> Result<Self, Self::Error> {
-        match obj.untag() {
-            ObjectType::Int(x) => {
-                x.try_into().with_context(|| format!("Integer must be positive, but was {x}"))
+        impl<'ob> TryFrom<Object<'ob>> for Option<$ty> {
+            type Error = anyhow::Error;
+            fn try_from(obj: Object<'ob>) -> Result<Self, Self::Error> {
+                match obj.untag() {
+                    ObjectType::NIL => Ok(None),
+                    _ => Ok(Some(obj.try_into()?)),
+                }
             }
-            x => Err(TypeError::new(Type::Int, x).into()),
         }
-    }
+    )+};
 }
 
-impl<'ob> TryFrom<Object<'ob>> for Option<usize> {
-    type Error = anyhow::Error;
+define_int_conversion!(usize, u64, u32, u16, u8, i32);
+
+impl<'ob> TryFrom<Object<'ob>> for char {
+    type Error = TypeError;
     fn try_from(obj: Object<'ob>) -> Result<Self, Self::Error> {
         match obj.untag() {
-            ObjectType::Int(x) => match x.try_into() {
-                Ok(x) => Ok(Some(x)),
-                Err(e) => Err(e).with_context(|| format!("Integer must be positive, but was {x}")),
-            },
-            ObjectType::NIL => Ok(None),
-            _ => Err(TypeError::new(Type::Int, obj).into()),
+            ObjectType::Int(x) => int_to_char(x),
+            _ => Err(TypeError::new(Type::Char, obj)),
         }
     }
 }
 
+/// Convert an integer to a fixnum, signaling `args-out-of-range` if it can't
+/// be represented. Converting with [`Into`] will instead clamp the value to
+/// the fixnum range.
+pub(crate) fn checked_fixnum<T>(value: T) -> Result<i64, ArgRangeError>
+where
+    T: TryInto<i64> + Into<RangeArg> + Copy,
+{
+    match value.try_into() {
+        Ok(x) if (MIN_FIXNUM..=MAX_FIXNUM).contains(&x) => Ok(x),
+        _ => Err(ArgRangeError::new(&[value])),
+    }
+}
+
 impl<'ob> TryFrom<Object<'ob>> for bool {
     type Error = ArgError;
     fn try_from(obj: Object) -> Result<Self, Self::Error> {
@@ -112,6 +125,49 @@ where
     Ok(unsafe { std::slice::from_raw_parts(ptr, len) })
 }
 
+/// Conversion from the rest arguments of a `#[defun]` to a slice of `Self`.
+/// Tagged pointer types are checked and then converted in place with
+/// [`try_from_slice`]. Other types are converted into `buffer`, which only
+/// needs to live as long as the returned slice.
+pub(crate) trait FromObjectSlice<'ob>: Sized {
+    fn from_slice<'brw>(
+        slice: &'brw [Object<'ob>],
+        buffer: &'brw mut Vec<Self>,
+    ) -> anyhow::Result<&'brw [Self]>;
+}
+
+impl<'ob, T, E> FromObjectSlice<'ob> for Gc<T>
+where
+    Gc<T>: TryFrom<Object<'ob>, Error = E> + 'ob,
+    anyhow::Error: From<E>,
+{
+    fn from_slice<'brw>(
+        slice: &'brw [Object<'ob>],
+        _: &'brw mut Vec<Self>,
+    ) -> anyhow::Result<&'brw [Self]> {
+        Ok(try_from_slice(slice)?)
+    }
+}
+
+macro_rules! define_slice_conversion {
+    ($($ty:ty),+) => {$(
+        impl<'ob> FromObjectSlice<'ob> for $ty {
+            fn from_slice<'brw>(
+                slice: &'brw [Object<'ob>],
+                buffer: &'brw mut Vec<Self>,
+            ) -> anyhow::Result<&'brw [Self]> {
+                buffer.clear();
+                for obj in slice {
+                    buffer.push(Self::try_from(*obj)?);
+                }
+                Ok(buffer)
+            }
+        }
+    )+};
+}
+
+define_slice_conversion!(Symbol<'ob>, &'ob str, &'ob Cons, i64, usize, f64, char);
+
 impl<'ob> From<bool> for Object<'ob> {
     fn from(b: bool) -> Self {
         if b {
@@ -144,9 +200,8 @@ where
 
 #[cfg(test)]
 mod test {
-    use crate::core::cons::Cons;
-
     use super::super::super::gc::{Context, RootSet};
+    use crate::core::{env::sym, object::Number};
 
     use super::*;
 
@@ -173,4 +228,55 @@ mod test {
         let res = wrapper(vec.as_slice());
         assert_eq!(6, res.unwrap());
     }
+
+    #[test]
+    fn test_checked_conversions() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        assert_eq!(usize::try_from(cx.add(3)).unwrap(), 3);
+        let err = usize::try_from(cx.add(-3)).unwrap_err();
+        assert_eq!(err.downcast::<ArgRangeError>().unwrap(), ArgRangeError::new(&[-3]));
+        assert!(u64::try_from(cx.add(-1)).is_err());
+        assert!(Option::<usize>::try_from(cx.add(-1)).is_err());
+        assert_eq!(Option::<usize>::try_from(NIL).unwrap(), None);
+
+        assert_eq!(char::try_from(cx.add(97)).unwrap(), 'a');
+        assert!(char::try_from(cx.add(0xD800)).is_err());
+        assert!(char::try_from(cx.add(-1)).is_err());
+        assert!(char::try_from(cx.add(0x11_0000)).is_err());
+
+        assert_eq!(checked_fixnum(MAX_FIXNUM), Ok(MAX_FIXNUM));
+        assert!(checked_fixnum(MAX_FIXNUM + 1).is_err());
+        assert!(checked_fixnum(MIN_FIXNUM - 1).is_err());
+        assert!(checked_fixnum(u64::MAX).is_err());
+        assert_eq!(checked_fixnum(7_usize), Ok(7));
+
+        assert_eq!(u8::try_from(cx.add(255)).unwrap(), 255);
+        assert!(u8::try_from(cx.add(256)).is_err());
+        assert_eq!(Option::<u32>::try_from(NIL).unwrap(), None);
+        assert_eq!(Option::<i32>::try_from(cx.add(-5)).unwrap(), Some(-5));
+        assert!(i32::try_from(cx.add(i64::from(i32::MAX) + 1)).is_err());
+    }
+
+    #[test]
+    fn test_from_slice() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let ints = [cx.add(1), cx.add(2)];
+        let numbers: &[Number] = FromObjectSlice::from_slice(&ints, &mut Vec::new()).unwrap();
+        assert_eq!(numbers, [1_i64, 2]);
+        let ints: &[i64] = FromObjectSlice::from_slice(&ints, &mut Vec::new()).unwrap();
+        assert_eq!(ints, [1_i64, 2]);
+
+        let strings = [cx.add("a"), cx.add("b")];
+        let strs: &[&str] = FromObjectSlice::from_slice(&strings, &mut Vec::new()).unwrap();
+        assert_eq!(strs, ["a", "b"]);
+        let symbols = [sym::TRUE.into(), NIL];
+        let syms: &[Symbol] = FromObjectSlice::from_slice(&symbols, &mut Vec::new()).unwrap();
+        assert_eq!(syms, [sym::TRUE, sym::NIL]);
+
+        let mixed = [cx.add("a"), cx.add(1)];
+        assert!(<&str>::from_slice(&mixed, &mut Vec::new()).is_err());
+        assert!(Gc::<i64>::from_slice(&mixed, &mut Vec::new()).is_err());
+    }
 }
@@ -35,12 +35,103 @@ impl<'new> CloneIn<'new, &'new LispFloat> for LispFloat {
 
 impl Display for LispFloat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let float = **self;
-        if float.fract() == 0.0_f64 {
-            write!(f, "{float:.1}")
+        f.write_str(&float_to_string(**self))
+    }
+}
+
+/// Print a float the way Emacs does: the shortest `%g` representation that
+/// reads back as the same value, always including either a decimal point or
+/// an exponent. Infinities and NaN use the `1.0e+INF` and `0.0e+NaN` syntax.
+pub(crate) fn float_to_string(float: f64) -> String {
+    let sign = if float.is_sign_negative() { "-" } else { "" };
+    if float.is_nan() {
+        return format!("{sign}0.0e+NaN");
+    }
+    if float.is_infinite() {
+        return format!("{sign}1.0e+INF");
+    }
+    // Like gnulib's dtoastr, start at the precision that round-trips most
+    // values and only use more digits when needed.
+    let start = if float.abs() < f64::MIN_POSITIVE { 1 } else { f64::DIGITS as usize };
+    let mut string = String::new();
+    for precision in start..=17 {
+        string = format_general(float, precision);
+        if string.parse::<f64>() == Ok(float) {
+            break;
+        }
+    }
+    if string.chars().all(|c| c.is_ascii_digit() || c == '-') {
+        string.push_str(".0");
+    }
+    string
+}
+
+/// Equivalent of C's `%.{precision}g`.
+fn format_general(float: f64, precision: usize) -> String {
+    fn trim_zeros(string: &str) -> &str {
+        if string.contains('.') {
+            string.trim_end_matches('0').trim_end_matches('.')
         } else {
-            write!(f, "{float}")
+            string
+        }
+    }
+    let scientific = format!("{:.*e}", precision - 1, float);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if exponent < -4 || exponent >= precision as i32 {
+        let exp_sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{exp_sign}{:02}", trim_zeros(mantissa), exponent.abs())
+    } else {
+        let fixed = format!("{:.*}", (precision as i32 - 1 - exponent) as usize, float);
+        trim_zeros(&fixed).to_owned()
+    }
+}
+
+/// Parse a float using the Emacs reader syntax. Unlike Rust's parser, a float
+/// needs digits after the decimal point or an exponent (`1.` is an integer),
+/// names like `inf` and `nan` are not numbers, and infinities and NaNs are
+/// written `1.0e+INF` and `0.0e+NaN`.
+pub(crate) fn parse_float(string: &str) -> Option<f64> {
+    let bytes = string.as_bytes();
+    let digits_from = |mut idx: usize| {
+        while idx < bytes.len() && bytes[idx].is_ascii_digit() {
+            idx += 1;
+        }
+        idx
+    };
+    let mut idx = usize::from(matches!(bytes.first(), Some(b'+' | b'-')));
+    let negative = bytes.first() == Some(&b'-');
+    let lead_end = digits_from(idx);
+    let has_lead = lead_end > idx;
+    idx = lead_end;
+    let mut has_trail = false;
+    if bytes.get(idx) == Some(&b'.') {
+        let trail_end = digits_from(idx + 1);
+        has_trail = trail_end > idx + 1;
+        idx = trail_end;
+    }
+    if !has_lead && !has_trail {
+        return None;
+    }
+    match bytes.get(idx) {
+        None if has_trail => string.parse().ok(),
+        Some(b'e' | b'E') => {
+            let mantissa = &string[..idx];
+            let sign = if negative { -1.0 } else { 1.0 };
+            match &string[idx + 1..] {
+                "+INF" => Some(sign * f64::INFINITY),
+                "+NaN" => Some(if negative { -f64::NAN } else { f64::NAN }),
+                exponent => {
+                    let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+                    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                        return None;
+                    }
+                    let mantissa = mantissa.strip_suffix('.').unwrap_or(mantissa);
+                    format!("{mantissa}e{exponent}").parse().ok()
+                }
+            }
         }
+        _ => None,
     }
 }
 
@@ -49,3 +140,51 @@ impl Debug for LispFloat {
         write!(f, "{self}")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_float_to_string() {
+        assert_eq!(float_to_string(1.0), "1.0");
+        assert_eq!(float_to_string(-3.0), "-3.0");
+        assert_eq!(float_to_string(0.1), "0.1");
+        assert_eq!(float_to_string(100.0), "100.0");
+        assert_eq!(float_to_string(1.5e-7), "1.5e-07");
+        assert_eq!(float_to_string(0.0001), "0.0001");
+        assert_eq!(float_to_string(1e15), "1e+15");
+        assert_eq!(float_to_string(1e100), "1e+100");
+        assert_eq!(float_to_string(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(float_to_string(123_456.789), "123456.789");
+        assert_eq!(float_to_string(5e-324), "5e-324");
+        assert_eq!(float_to_string(0.0), "0.0");
+        assert_eq!(float_to_string(-0.0), "-0.0");
+        assert_eq!(float_to_string(f64::INFINITY), "1.0e+INF");
+        assert_eq!(float_to_string(f64::NEG_INFINITY), "-1.0e+INF");
+        assert_eq!(float_to_string(f64::NAN), "0.0e+NaN");
+        assert_eq!(float_to_string(-f64::NAN), "-0.0e+NaN");
+    }
+
+    #[test]
+    fn test_parse_float() {
+        assert_eq!(parse_float("1.5"), Some(1.5));
+        assert_eq!(parse_float(".5"), Some(0.5));
+        assert_eq!(parse_float("-0.0").map(f64::is_sign_negative), Some(true));
+        assert_eq!(parse_float("1e+100"), Some(1e100));
+        assert_eq!(parse_float("1.e3"), Some(1000.0));
+        assert_eq!(parse_float("+2E-2"), Some(0.02));
+        assert_eq!(parse_float("1.0e+INF"), Some(f64::INFINITY));
+        assert_eq!(parse_float("-1.0e+INF"), Some(f64::NEG_INFINITY));
+        assert!(parse_float("0.0e+NaN").is_some_and(|x| x.is_nan() && x.is_sign_positive()));
+        assert!(parse_float("-0.0e+NaN").is_some_and(|x| x.is_nan() && x.is_sign_negative()));
+        assert_eq!(parse_float("1"), None);
+        assert_eq!(parse_float("1."), None);
+        assert_eq!(parse_float("."), None);
+        assert_eq!(parse_float("e5"), None);
+        assert_eq!(parse_float("1e"), None);
+        assert_eq!(parse_float("inf"), None);
+        assert_eq!(parse_float("nan"), None);
+        assert_eq!(parse_float("1.5x"), None);
+    }
+}
@@ -3,11 +3,12 @@ use super::{
         error::ArgError,
         gc::{Block, Context},
     },
-    display_slice, CloneIn, IntoObject,
+    CloneIn, IntoObject,
 };
-use super::{Object, WithLifetime};
+use super::{Object, ObjectType, Symbol, WithLifetime, NIL};
 use crate::{
     core::{
+        cons::Cons,
         env::Env,
         gc::{GcHeap, Rt, Slot},
     },
@@ -18,6 +19,7 @@ use macro_attr_2018::macro_attr;
 use newtype_derive_2018::*;
 use rune_macros::Trace;
 use std::fmt::{self, Debug, Display};
+use std::panic::AssertUnwindSafe;
 
 #[derive(PartialEq, Eq, Trace)]
 pub(crate) struct ByteFnPrototype {
@@ -98,13 +100,27 @@ impl<'new> CloneIn<'new, &'new Self> for ByteFn {
     }
 }
 
+/// Byte-code is printed as `#[ARGS CODE CONSTANTS DEPTH]`, with the code as a
+/// unibyte string, which is how Emacs prints it.
 impl Display for ByteFn {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let spec = self.args.into_arg_spec();
-        let code = display_slice(&self.op_codes);
-        let consts = display_slice(&self.constants);
-        let depth = self.depth;
-        write!(f, "#[{spec} {code} {consts} {depth}]")
+        write!(f, "#[{spec} \"")?;
+        for &byte in &*self.op_codes {
+            match byte {
+                b'"' | b'\\' => write!(f, "\\{}", byte as char)?,
+                b' '..=b'~' => write!(f, "{}", byte as char)?,
+                _ => write!(f, "\\{byte:03o}")?,
+            }
+        }
+        f.write_str("\" [")?;
+        for (i, x) in self.consts().iter().enumerate() {
+            if i != 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{x}")?;
+        }
+        write!(f, "] {}]", self.depth)
     }
 }
 
@@ -118,6 +134,128 @@ impl Debug for ByteFn {
     }
 }
 
+#[derive(PartialEq, Eq, Trace)]
+pub(crate) struct ClosureInner {
+    #[no_trace]
+    pub(crate) args: FnArgs,
+    /// The parameters in the order they are bound: required, optional, then
+    /// rest.
+    params: Vec<Slot<Symbol<'static>>>,
+    /// The argument list as written, kept so the closure can be printed.
+    arg_list: Slot<Object<'static>>,
+    /// The captured lexical bindings. The highest priority bindings are at the
+    /// end.
+    env: Vec<Slot<&'static Cons>>,
+    body: Slot<Object<'static>>,
+}
+
+macro_attr! {
+    /// An interpreted function that has captured its lexical environment. The
+    /// argument list is parsed once when the closure is created instead of on
+    /// every call. It prints the same as the `(closure ENV ARGS . BODY)` list
+    /// form, so the printed representation can still be read back and called.
+    #[derive(PartialEq, Eq, NewtypeDeref!, NewtypeMarkable!, Trace)]
+    pub(crate) struct Closure(GcHeap<ClosureInner>);
+}
+
+define_unbox!(Closure, Func, &'ob Closure);
+
+impl Closure {
+    pub(in crate::core) fn new(inner: ClosureInner, constant: bool) -> Closure {
+        Closure(GcHeap::new(inner, constant))
+    }
+
+    // SAFETY: The caller must ensure that all the objects are part of the same
+    // block as the closure and that it is immediately put into the GC heap.
+    pub(crate) unsafe fn make(
+        arg_list: Object,
+        params: Vec<Symbol>,
+        args: FnArgs,
+        env: Vec<&Cons>,
+        body: Object,
+    ) -> ClosureInner {
+        unsafe {
+            ClosureInner {
+                args,
+                params: params.into_iter().map(|x| Slot::new(x.with_lifetime())).collect(),
+                arg_list: Slot::new(arg_list.with_lifetime()),
+                env: env.into_iter().map(|x| Slot::new(x.with_lifetime())).collect(),
+                body: Slot::new(body.with_lifetime()),
+            }
+        }
+    }
+}
+
+impl ClosureInner {
+    pub(crate) fn params<'ob>(&'ob self) -> &'ob [Symbol<'ob>] {
+        unsafe {
+            std::mem::transmute::<&'ob [Slot<Symbol<'static>>], &'ob [Symbol<'ob>]>(&self.params)
+        }
+    }
+
+    pub(crate) fn env<'ob>(&'ob self) -> &'ob [&'ob Cons] {
+        unsafe { std::mem::transmute::<&'ob [Slot<&'static Cons>], &'ob [&'ob Cons]>(&self.env) }
+    }
+
+    pub(crate) fn arg_list(&self) -> Object {
+        *self.arg_list
+    }
+
+    pub(crate) fn body(&self) -> Object {
+        *self.body
+    }
+
+    /// The captured bindings in the `((x . 1) (y . 2) t)` list form of a
+    /// lexical environment, highest priority first.
+    pub(crate) fn env_list<'ob>(&'ob self, cx: &'ob Context) -> Object<'ob> {
+        let mut tail = Object::from(Cons::new1(true, cx));
+        for binding in self.env() {
+            tail = Cons::new(*binding, tail, cx).into();
+        }
+        tail
+    }
+}
+
+impl<'new> CloneIn<'new, &'new Self> for Closure {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> super::Gc<&'new Self> {
+        let params = self.params().iter().map(|x| x.clone_in(bk).untag()).collect();
+        let env = self.env().iter().map(|x| x.clone_in(bk).untag()).collect();
+        let arg_list = self.arg_list().clone_in(bk);
+        let body = self.body().clone_in(bk);
+        let closure = unsafe { Closure::make(arg_list, params, self.args, env, body) };
+        closure.into_obj(bk)
+    }
+}
+
+impl Display for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(closure (")?;
+        for binding in self.env().iter().rev() {
+            write!(f, "{binding} ")?;
+        }
+        write!(f, "t) {}", self.arg_list())?;
+        let mut body = self.body();
+        while let ObjectType::Cons(cons) = body.untag() {
+            write!(f, " {}", cons.car())?;
+            body = cons.cdr();
+        }
+        if body != NIL {
+            write!(f, " . {body}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("args", &self.args)
+            .field("arg_list", &self.arg_list())
+            .field("body", &self.body())
+            .finish_non_exhaustive()
+    }
+}
+
 /// Argument requirments to a function.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
 pub(crate) struct FnArgs {
@@ -185,13 +323,39 @@ pub(crate) struct SubrFn {
 define_unbox!(SubrFn, Func, &'ob SubrFn);
 
 impl SubrFn {
+    /// Call the builtin. A panic in the builtin is turned into an error, so
+    /// that a bug in one function doesn't take down the whole process. The
+    /// frames, stack values, and dynamic bindings it left behind are removed.
     pub(crate) fn call<'ob>(
         &self,
         arg_cnt: usize,
         env: &mut Rt<Env>,
         cx: &'ob mut Context,
     ) -> Result<Object<'ob>> {
-        (self.subr)(arg_cnt, env, cx)
+        let frame = env.stack.current_frame();
+        let stack_len = env.stack.len();
+        let bindings = env.binding_depth();
+        let call = AssertUnwindSafe(|| {
+            let result = (self.subr)(arg_cnt, &mut *env, &mut *cx);
+            // SAFETY: The result is rebound to `cx` as soon as the call
+            // returns, and nothing can be collected in between.
+            result.map(|x| unsafe { WithLifetime::<'static>::with_lifetime(x) })
+        });
+        match std::panic::catch_unwind(call) {
+            Ok(result) => result.map(|x| cx.bind(x)),
+            Err(payload) => {
+                env.stack.unwind_frames(frame);
+                env.stack.truncate(stack_len);
+                while env.binding_depth() > bindings {
+                    env.unbind(1, cx);
+                }
+                let msg = match payload.downcast_ref::<&str>() {
+                    Some(msg) => msg,
+                    None => payload.downcast_ref::<String>().map_or("unknown panic", |x| x),
+                };
+                bail!("Internal error in {}: {msg}", self.name)
+            }
+        }
     }
 }
 
@@ -244,4 +408,37 @@ mod test {
         assert!(FnArgs::from_arg_spec(1).is_err());
         assert!(FnArgs::from_arg_spec(0xFFFF).is_err());
     }
+
+    #[test]
+    fn test_display_byte_fn() {
+        use crate::core::gc::RootSet;
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let constants = vec![cx.add("ab"), NIL];
+        let byte_fn = crate::alloc::new_byte_code(257, b"\xc0\x87 \"", constants, 2, cx).unwrap();
+        assert_eq!(byte_fn.to_string(), r#"#[257 "\300\207 \"" ["ab" nil] 2]"#);
+    }
+
+    #[test]
+    fn test_subr_panic() {
+        use crate::core::{env::sym, gc::RootSet};
+        use rune_core::macros::root;
+
+        fn buggy<'ob>(_: usize, env: &mut Rt<Env>, cx: &'ob mut Context) -> Result<Object<'ob>> {
+            // leave behind a binding, a frame, and a value on the stack
+            env.varbind(sym::FILL_COLUMN, NIL, cx);
+            env.stack.push_frame(0);
+            env.stack.push(NIL).unwrap();
+            panic!("buggy subr")
+        }
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let subr = SubrFn { subr: buggy, args: FnArgs::default(), name: "buggy" };
+        let err = subr.call(0, env, cx).unwrap_err();
+        assert_eq!(err.to_string(), "Internal error in buggy: buggy subr");
+        assert_eq!(env.binding_depth(), 0);
+        assert_eq!(env.stack.current_frame(), 0);
+        assert_eq!(env.stack.len(), 0);
+    }
 }
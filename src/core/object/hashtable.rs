@@ -2,18 +2,23 @@
 //! need it to support being both thread local and global. Second we need
 //! iterate and mutate at the same time. Third we need to be able to clean up
 //! the heap allocation when it is garbage collected.
-use super::{CloneIn, Gc, IntoObject, ObjCell, Object, WithLifetime};
-use crate::core::env::interned_symbols;
+use super::{
+    address, Ancestors, CloneIn, Gc, IntoObject, ObjCell, Object, ObjectType, Symbol, WithLifetime,
+};
+use crate::core::env::{interned_symbols, sym};
 use crate::core::gc::{Block, GcHeap, GcState, Trace};
 use crate::NewtypeMarkable;
 use macro_attr_2018::macro_attr;
 use newtype_derive_2018::{NewtypeDebug, NewtypeDeref, NewtypeDisplay};
-use rune_core::hashmap::{HashSet, IndexMap};
+use rune_core::hashmap::{IndexMap, RawEntryApiV1, RawEntryMut};
 use rune_macros::Trace;
 use std::cell::RefCell;
 use std::fmt::{self, Debug, Display, Write};
-use std::sync::Mutex;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::{Mutex, PoisonError};
 
+/// The entries of a hash table before it is made into an object. Tables made
+/// from one compare keys with `equal`.
 pub(crate) type HashTable<'ob> = IndexMap<Object<'ob>, Object<'ob>>;
 
 macro_attr! {
@@ -27,6 +32,84 @@ impl LispHashTable {
     }
 }
 
+/// How a hash table compares its keys. This is the `:test` it was made with.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum HashTableTest {
+    Eq,
+    #[default]
+    Eql,
+    Equal,
+}
+
+impl HashTableTest {
+    pub(crate) fn symbol(self) -> Symbol<'static> {
+        match self {
+            HashTableTest::Eq => sym::EQ,
+            HashTableTest::Eql => sym::EQL,
+            HashTableTest::Equal => sym::EQUAL,
+        }
+    }
+
+    pub(crate) fn from_symbol(symbol: Symbol) -> Option<Self> {
+        [HashTableTest::Eq, HashTableTest::Eql, HashTableTest::Equal]
+            .into_iter()
+            .find(|x| x.symbol() == symbol)
+    }
+
+    fn matches(self, key: Object, other: Object) -> bool {
+        match (self, key.untag(), other.untag()) {
+            (HashTableTest::Equal, _, _) => key.untag() == other.untag(),
+            (HashTableTest::Eql, ObjectType::Float(x), ObjectType::Float(y)) => {
+                x.to_bits() == y.to_bits()
+            }
+            _ => key.ptr_eq(other),
+        }
+    }
+}
+
+/// Hash `obj` so that objects that are `equal` have the same hash. Only the
+/// contents are hashed and never an address, so the hash doesn't change when
+/// the garbage collector moves the object. Like `sxhash-equal`, only the start
+/// of long or deep structure is used.
+fn hash_equal<H: Hasher>(obj: Object, depth: usize, state: &mut H) {
+    const MAX_DEPTH: usize = 3;
+    const MAX_LEN: usize = 7;
+    let obj = obj.untag();
+    std::mem::discriminant(&obj).hash(state);
+    match obj {
+        ObjectType::Int(x) => x.hash(state),
+        ObjectType::Float(x) => x.to_bits().hash(state),
+        ObjectType::Symbol(x) => x.name().hash(state),
+        ObjectType::String(x) => str::hash(x, state),
+        ObjectType::ByteString(x) => <[u8]>::hash(x, state),
+        ObjectType::Cons(cons) if depth < MAX_DEPTH => {
+            let mut tail = cons.cdr();
+            hash_equal(cons.car(), depth + 1, state);
+            for _ in 1..MAX_LEN {
+                let ObjectType::Cons(cons) = tail.untag() else { break };
+                hash_equal(cons.car(), depth + 1, state);
+                tail = cons.cdr();
+            }
+            if !matches!(tail.untag(), ObjectType::Cons(_)) {
+                hash_equal(tail, depth + 1, state);
+            }
+        }
+        ObjectType::Vec(vec) if depth < MAX_DEPTH => {
+            vec.len().hash(state);
+            for x in vec.iter().take(MAX_LEN) {
+                hash_equal(x.get(), depth + 1, state);
+            }
+        }
+        ObjectType::Record(record) if depth < MAX_DEPTH => {
+            record.len().hash(state);
+            for x in record.iter().take(MAX_LEN) {
+                hash_equal(x.get(), depth + 1, state);
+            }
+        }
+        _ => {}
+    }
+}
+
 pub(crate) struct HashTableCore<'ob>(HashTableType<'ob>);
 
 // Hashtables are currently the only data structure that can be shared between
@@ -41,13 +124,55 @@ struct HashTableInner<'ob> {
     // The current index of a [`maphash`] iterator. This is needed because we
     // can't hold the hashtable across calls to elisp (it might mutate it).
     iter_idx: usize,
+    test: HashTableTest,
+    // The entries are found with the raw entry API, so that keys are hashed
+    // and compared according to `test`
     inner: HashTable<'ob>,
 }
 
+impl<'ob> HashTableInner<'ob> {
+    fn hash_key(&self, key: Object) -> u64 {
+        match self.test {
+            HashTableTest::Equal => {
+                let mut state = self.inner.hasher().build_hasher();
+                hash_equal(key, 0, &mut state);
+                state.finish()
+            }
+            HashTableTest::Eq | HashTableTest::Eql => self.inner.hasher().hash_one(key),
+        }
+    }
+
+    fn index_of(&self, key: Object) -> Option<usize> {
+        let hash = self.hash_key(key);
+        self.inner.raw_entry_v1().index_from_hash(hash, |k| self.test.matches(*k, key))
+    }
+
+    fn insert(&mut self, key: Object<'ob>, value: Object<'ob>) {
+        let hash = self.hash_key(key);
+        let test = self.test;
+        match self.inner.raw_entry_mut_v1().from_hash(hash, |k| test.matches(*k, key)) {
+            RawEntryMut::Occupied(mut entry) => {
+                entry.insert(value);
+            }
+            RawEntryMut::Vacant(entry) => {
+                entry.insert_hashed_nocheck(hash, key, value);
+            }
+        }
+    }
+
+    /// Add the entries again, so that they are hashed for the current test.
+    fn reindex(&mut self) {
+        for (key, value) in std::mem::take(&mut self.inner) {
+            self.insert(key, value);
+        }
+    }
+}
+
 impl<'a> HashTableCore<'a> {
     pub(in crate::core) unsafe fn new(table: HashTable, constant: bool) -> Self {
         let table = std::mem::transmute::<HashTable<'_>, HashTable<'a>>(table);
-        let inner = HashTableInner { iter_idx: 0, inner: table };
+        let mut inner = HashTableInner { iter_idx: 0, test: HashTableTest::Equal, inner: table };
+        inner.reindex();
         if constant {
             HashTableCore(HashTableType::Global(Mutex::new(inner)))
         } else {
@@ -55,67 +180,105 @@ impl<'a> HashTableCore<'a> {
         }
     }
 
-    fn with<F, T>(&self, mut f: F) -> T
+    fn with_inner<F, T>(&self, f: F) -> T
     where
-        F: FnMut(&mut HashTable<'a>) -> T,
+        F: FnOnce(&mut HashTableInner<'a>) -> T,
     {
         match &self.0 {
-            HashTableType::Local(table) => f(&mut table.borrow_mut().inner),
-            HashTableType::Global(table) => f(&mut table.lock().unwrap().inner),
+            HashTableType::Local(table) => f(&mut table.borrow_mut()),
+            HashTableType::Global(table) => {
+                f(&mut table.lock().unwrap_or_else(PoisonError::into_inner))
+            }
         }
     }
 
+    fn with<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut HashTable<'a>) -> T,
+    {
+        self.with_inner(|x| f(&mut x.inner))
+    }
+
     pub(crate) fn len(&self) -> usize {
         self.with(|x| x.len())
     }
 
+    pub(crate) fn test(&self) -> HashTableTest {
+        self.with_inner(|x| x.test)
+    }
+
+    /// Change how the table compares its keys. This is meant for tables that
+    /// were just made, since keys that now match each other are merged.
+    pub(crate) fn set_test(&self, test: HashTableTest) {
+        self.with_inner(|x| {
+            x.test = test;
+            x.reindex();
+        });
+    }
+
     pub(crate) fn get(&self, key: Object) -> Option<Object<'_>> {
-        self.with(|x| x.get(&key).copied())
+        self.with_inner(|x| x.index_of(key).map(|i| x.inner[i]))
     }
 
     pub(crate) fn get_index(&self, index: usize) -> Option<(Object, Object)> {
         self.with(|x| x.get_index(index).map(|(k, v)| (*k, *v)))
     }
 
-    pub(crate) fn get_index_of(&self, key: Object) -> Option<usize> {
-        self.with(|x| x.get_index_of(&key))
-    }
-
     pub(crate) fn insert(&self, key: Object, value: Object) {
         match &self.0 {
             HashTableType::Local(table) => {
                 let key = unsafe { key.with_lifetime() };
                 let value = unsafe { value.with_lifetime() };
-                table.borrow_mut().inner.insert(key, value)
+                table.borrow_mut().insert(key, value)
             }
             HashTableType::Global(table) => {
-                let map = interned_symbols().lock().unwrap();
-                let block = map.global_block();
+                let global = interned_symbols().global_block();
+                let block = &*global;
                 // Need to clone these objects in the global block since this
                 // hashtable is globally shared
                 let key = unsafe { key.clone_in(block).with_lifetime() };
                 let value = unsafe { value.clone_in(block).with_lifetime() };
-                table.lock().unwrap().inner.insert(key, value)
+                table.lock().unwrap_or_else(PoisonError::into_inner).insert(key, value)
             }
         };
     }
 
-    pub(crate) fn shift_remove(&self, key: Object) {
-        let key = unsafe { key.with_lifetime() };
-        self.with(|x| x.shift_remove(&key));
+    /// Remove the entry for `key` by moving the last entry into its place.
+    /// An entry that a `maphash` in progress has not reached yet is still
+    /// visited.
+    pub(crate) fn swap_remove(&self, key: Object) {
+        self.with_inner(|x| {
+            let Some(mut index) = x.index_of(key) else { return };
+            if index < x.iter_idx {
+                // Move the hole to the last visited entry, and visit the entry
+                // that fills it
+                x.iter_idx -= 1;
+                x.inner.swap_indices(index, x.iter_idx);
+                index = x.iter_idx;
+            }
+            x.inner.swap_remove_index(index);
+        });
+    }
+
+    pub(crate) fn clear(&self) {
+        self.with(|x| x.clear());
     }
 
     pub(crate) fn get_iter_index(&self) -> usize {
         match &self.0 {
             HashTableType::Local(table) => table.borrow().iter_idx,
-            HashTableType::Global(table) => table.lock().unwrap().iter_idx,
+            HashTableType::Global(table) => {
+                table.lock().unwrap_or_else(PoisonError::into_inner).iter_idx
+            }
         }
     }
 
     pub(crate) fn set_iter_index(&self, index: usize) {
         match &self.0 {
             HashTableType::Local(table) => table.borrow_mut().iter_idx = index,
-            HashTableType::Global(table) => table.lock().unwrap().iter_idx = index,
+            HashTableType::Global(table) => {
+                table.lock().unwrap_or_else(PoisonError::into_inner).iter_idx = index
+            }
         }
     }
 }
@@ -125,18 +288,28 @@ impl Trace for HashTableCore<'_> {
         let HashTableType::Local(table) = &self.0 else {
             panic!("Global hash table should not be traced")
         };
-        let table = &mut table.borrow_mut().inner;
+        let inner = &mut *table.borrow_mut();
+        let test = inner.test;
         // ObjCell are updated in place when traced, so casting to ObjCell will
         // allow all the objects to be updated.
         let table = unsafe {
             std::mem::transmute::<&mut IndexMap<Object, Object>, &mut IndexMap<ObjCell, ObjCell>>(
-                table,
+                &mut inner.inner,
             )
         };
-        table.rehash_keys(|key, val| {
-            key.trace(state);
-            val.trace(state);
-        });
+        if test == HashTableTest::Equal {
+            // The hashes don't depend on where the keys are, so they are
+            // still right after the keys move
+            for (key, val) in table.iter() {
+                key.trace(state);
+                val.trace(state);
+            }
+        } else {
+            table.rehash_keys(|key, val| {
+                key.trace(state);
+                val.trace(state);
+            });
+        }
     }
 }
 
@@ -149,13 +322,13 @@ impl PartialEq for HashTableCore<'_> {
 
 impl Debug for HashTableCore<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.display_walk(f, &mut HashSet::default())
+        self.display_walk(f, &mut Ancestors::default())
     }
 }
 
 impl Display for HashTableCore<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.display_walk(f, &mut HashSet::default())
+        self.display_walk(f, &mut Ancestors::default())
     }
 }
 
@@ -169,7 +342,9 @@ impl<'new> CloneIn<'new, &'new Self> for LispHashTable {
                 table.insert(new_key, new_value);
             }
         });
-        table.into_obj(bk)
+        let copy = table.into_obj(bk);
+        copy.untag().set_test(self.test());
+        copy
     }
 }
 
@@ -177,26 +352,25 @@ impl HashTableCore<'_> {
     pub(super) fn display_walk(
         &self,
         f: &mut fmt::Formatter,
-        seen: &mut HashSet<*const u8>,
+        path: &mut Ancestors<()>,
     ) -> fmt::Result {
-        let ptr = (self as *const Self).cast();
-        if seen.contains(&ptr) {
+        if !path.enter(address(self), ()) {
             return write!(f, "#0");
         }
-        seen.insert(ptr);
 
-        write!(f, "#s(hash-table (")?;
+        write!(f, "#s(hash-table test {} data (", self.test().symbol())?;
         self.with(|x| {
             for (i, (k, v)) in x.iter().enumerate() {
                 if i != 0 {
                     f.write_char(' ')?;
                 }
-                k.untag().display_walk(f, seen)?;
+                k.untag().display_walk(f, path)?;
                 f.write_char(' ')?;
-                v.untag().display_walk(f, seen)?;
+                v.untag().display_walk(f, path)?;
             }
             Ok(())
         })?;
+        path.leave(address(self));
         write!(f, "))")
     }
 }
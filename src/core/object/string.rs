@@ -1,6 +1,7 @@
 use super::{CloneIn, IntoObject};
 use crate::core::gc::{Block, GcHeap, GcState, Trace};
 use crate::NewtypeMarkable;
+use anyhow::{bail, ensure, Result};
 use bumpalo::collections::String as GcString;
 use macro_attr_2018::macro_attr;
 use newtype_derive_2018::*;
@@ -14,11 +15,14 @@ macro_attr! {
     pub(crate) struct LispString(GcHeap<LispStringInner>);
 }
 
-struct LispStringInner(Cell<*mut str>);
+struct LispStringInner {
+    string: Cell<*mut str>,
+    is_const: bool,
+}
 
 impl LispStringInner {
     fn get_str(&self) -> &str {
-        unsafe { &*self.0.get() }
+        unsafe { &*self.string.get() }
     }
 }
 
@@ -26,7 +30,7 @@ impl Trace for LispStringInner {
     fn trace(&self, state: &mut GcState) {
         let slice = self.get_str();
         let new = state.to_space.alloc_str(slice);
-        self.0.set(new);
+        self.string.set(new);
     }
 }
 
@@ -66,7 +70,8 @@ impl Deref for LispString {
 
 impl LispString {
     pub(in crate::core) unsafe fn new(string: *const str, constant: bool) -> Self {
-        Self(GcHeap::new(LispStringInner(Cell::new(string as *mut str)), constant))
+        let inner = LispStringInner { string: Cell::new(string as *mut str), is_const: constant };
+        Self(GcHeap::new(inner, constant))
     }
 
     pub(crate) fn inner(&self) -> &str {
@@ -86,6 +91,36 @@ impl LispString {
     pub(crate) fn len(&self) -> usize {
         self.chars().count()
     }
+
+    /// Replace the character at char index `idx`. If `chr` takes up a
+    /// different number of bytes than the character it replaces, the string is
+    /// copied into `block` with the new character.
+    pub(crate) fn set_char<const C: bool>(
+        &self,
+        idx: usize,
+        chr: char,
+        block: &Block<C>,
+    ) -> Result<()> {
+        ensure!(!self.0.is_const, "Attempt to mutate constant string");
+        let string = self.inner();
+        let Some((start, old)) = string.char_indices().nth(idx) else {
+            bail!("index {idx} is out of bounds. Length was {}", self.len())
+        };
+        let end = start + old.len_utf8();
+        if old.len_utf8() == chr.len_utf8() {
+            // SAFETY: A whole character is replaced by one of the same length,
+            // so the string stays valid UTF-8.
+            let bytes = unsafe { (*self.0.string.get()).as_bytes_mut() };
+            chr.encode_utf8(&mut bytes[start..end]);
+        } else {
+            let mut new = String::with_capacity(string.len() - old.len_utf8() + chr.len_utf8());
+            new.push_str(&string[..start]);
+            new.push(chr);
+            new.push_str(&string[end..]);
+            self.0.string.set(block.objects.alloc_str(&new));
+        }
+        Ok(())
+    }
 }
 
 impl<'new> CloneIn<'new, &'new Self> for LispString {
@@ -147,3 +182,117 @@ impl Debug for ByteString {
         write!(f, "{self}")
     }
 }
+
+macro_attr! {
+    /// A string output stream, which collects printed text until it is
+    /// closed. The text is kept in the GC heap and grows by doubling, so an
+    /// unreachable stream is reclaimed like any other object.
+    #[derive(NewtypeMarkable!, Trace)]
+    pub(crate) struct StringStream(GcHeap<StringStreamInner>);
+}
+
+define_unbox!(StringStream, &'ob StringStream);
+
+pub(crate) struct StringStreamInner {
+    /// The buffer the text is written to. Only the first `len` bytes are used.
+    buffer: Cell<*mut [u8]>,
+    len: Cell<usize>,
+    closed: Cell<bool>,
+}
+
+impl StringStreamInner {
+    pub(crate) fn new() -> Self {
+        let buffer: &mut [u8] = &mut [];
+        Self {
+            buffer: Cell::new(buffer as *mut [u8]),
+            len: Cell::new(0),
+            closed: Cell::new(false),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { &(*self.buffer.get())[..self.len.get()] }
+    }
+}
+
+impl Trace for StringStreamInner {
+    fn trace(&self, state: &mut GcState) {
+        // Only the text is copied, so the spare capacity is released
+        let new = state.to_space.alloc_slice_copy(self.bytes());
+        self.buffer.set(new);
+    }
+}
+
+impl StringStream {
+    pub(in crate::core) fn new(inner: StringStreamInner, constant: bool) -> Self {
+        Self(GcHeap::new(inner, constant))
+    }
+
+    /// The text written so far, or `None` if the stream is closed.
+    pub(crate) fn contents(&self) -> Option<&str> {
+        if self.0.closed.get() {
+            return None;
+        }
+        // SAFETY: Only whole strings are written to the buffer
+        Some(unsafe { std::str::from_utf8_unchecked(self.0.bytes()) })
+    }
+
+    /// Append `text` to the stream, growing the buffer in `block` if needed.
+    pub(crate) fn push_str<const C: bool>(&self, text: &str, block: &Block<C>) -> Result<()> {
+        ensure!(!self.0.closed.get(), "String output stream is closed");
+        let len = self.0.len.get();
+        let new_len = len + text.len();
+        // SAFETY: The buffer is only replaced below, after the last use of
+        // this reference.
+        let capacity = unsafe { (*self.0.buffer.get()).len() };
+        if new_len > capacity {
+            let capacity = new_len.max(capacity * 2).max(64);
+            let new = block.objects.alloc_slice_fill_copy(capacity, 0);
+            new[..len].copy_from_slice(self.0.bytes());
+            self.0.buffer.set(new);
+        }
+        unsafe { (*self.0.buffer.get())[len..new_len].copy_from_slice(text.as_bytes()) };
+        self.0.len.set(new_len);
+        Ok(())
+    }
+
+    /// Close the stream and release its text. Closing a stream twice does
+    /// nothing.
+    pub(crate) fn close(&self) {
+        self.0.closed.set(true);
+        let empty: &mut [u8] = &mut [];
+        self.0.buffer.set(empty);
+        self.0.len.set(0);
+    }
+}
+
+impl PartialEq for StringStream {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for StringStream {}
+
+impl<'new> CloneIn<'new, &'new Self> for StringStream {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> super::Gc<&'new Self> {
+        let stream = StringStreamInner::new().into_obj(bk);
+        match self.contents() {
+            Some(text) => stream.untag().push_str(text, bk).expect("new stream is open"),
+            None => stream.untag().close(),
+        }
+        stream
+    }
+}
+
+impl Display for StringStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#<string-output-stream>")
+    }
+}
+
+impl Debug for StringStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("StringStream").field(&self.contents()).finish()
+    }
+}
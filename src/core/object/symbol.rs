@@ -1,5 +1,5 @@
 #![allow(unstable_name_collisions)]
-use crate::core::env::sym::BUILTIN_SYMBOLS;
+use crate::core::env::{namespace, sym::BUILTIN_SYMBOLS};
 use crate::core::gc::{Block, Context, GcHeap, GcState, Markable, Trace};
 use crate::core::object::{CloneIn, FunctionType, Gc, IntoObject, TagType, WithLifetime};
 use anyhow::{bail, Result};
@@ -18,6 +18,14 @@ mod sealed {
         // https://github.com/crossbeam-rs/crossbeam/issues/748
         pub(super) func: Option<AtomicPtr<u8>>,
         pub(super) special: AtomicBool,
+        pub(super) buffer_local: AtomicBool,
+        pub(super) watched: AtomicBool,
+        /// Set when the garbage collector finds a reference to the symbol
+        pub(super) reached: AtomicBool,
+        /// Set when an object in the global block refers to the symbol
+        pub(super) pinned: AtomicBool,
+        /// Set when the function is waiting to be compiled on its first call
+        pub(super) compile_pending: AtomicBool,
     }
 }
 
@@ -37,7 +45,9 @@ pub(crate) type SymbolCell = GcHeap<SymbolCellInner>;
 #[derive(Debug)]
 enum SymbolName {
     Interned(&'static str),
-    Uninterned(Box<str>),
+    /// The name is allocated in the same heap as the symbol, and is moved
+    /// with it by the collector.
+    Uninterned(AtomicPtr<u8>, usize),
 }
 
 #[derive(PartialEq, Eq, Copy, Clone)]
@@ -95,6 +105,42 @@ impl<'a> Symbol<'a> {
     pub(crate) fn is_special(self) -> bool {
         self.special.load(Ordering::Acquire)
     }
+
+    /// Make this variable automatically buffer-local whenever it is set.
+    pub(crate) fn make_buffer_local(self) {
+        self.buffer_local.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn is_buffer_local(self) -> bool {
+        self.buffer_local.load(Ordering::Acquire)
+    }
+
+    /// Mark whether this variable has watchers, so that setting an unwatched
+    /// variable doesn't need to look them up.
+    pub(crate) fn set_watched(self, watched: bool) {
+        self.watched.store(watched, Ordering::Release);
+    }
+
+    pub(crate) fn is_watched(self) -> bool {
+        self.watched.load(Ordering::Acquire)
+    }
+
+    /// Mark whether the function is waiting to be compiled on its first call.
+    pub(crate) fn set_compile_pending(self, pending: bool) {
+        self.compile_pending.store(pending, Ordering::Release);
+    }
+
+    /// Clear the mark set by [`Symbol::set_compile_pending`], returning
+    /// whether it was set.
+    pub(crate) fn take_compile_pending(self) -> bool {
+        // Check first so the common case doesn't write to the symbol
+        self.compile_pending.load(Ordering::Acquire)
+            && self.compile_pending.swap(false, Ordering::AcqRel)
+    }
+
+    pub(in crate::core) fn is_builtin(self) -> bool {
+        BUILTIN_SYMBOLS.as_ptr_range().contains(&std::ptr::from_ref(self.get()))
+    }
 }
 
 unsafe impl Send for Symbol<'_> {}
@@ -110,6 +156,7 @@ impl<'old, 'new> WithLifetime<'new> for Symbol<'old> {
 
 impl Trace for Symbol<'_> {
     fn trace(&self, state: &mut GcState) {
+        self.get().note_reached();
         self.get().trace(state);
     }
 }
@@ -122,15 +169,19 @@ impl<'a> Markable for Symbol<'a> {
     }
 
     fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+        self.get().note_reached();
         let val = self.get().move_value(to_space);
         val.map(|(ptr, moved)| (unsafe { Self::from_ptr(ptr.as_ptr()) }, moved))
     }
 }
 
 impl Trace for SymbolCellInner {
-    fn trace(&self, _state: &mut GcState) {
-        // interned symbols are not collected yet
-        if matches!(self.name, SymbolName::Uninterned(_)) {
+    fn trace(&self, state: &mut GcState) {
+        // Interned symbols live outside the heap, and are only removed by
+        // `SymbolMap::purge`
+        if let SymbolName::Uninterned(name, _) = &self.name {
+            let new = state.to_space.alloc_str(self.name());
+            name.store(new.as_mut_ptr(), Ordering::Release);
             if let Some(func) = self.get() {
                 assert!(func.as_obj().is_marked());
             }
@@ -161,11 +212,11 @@ impl<'old, 'new> Symbol<'old> {
         self,
         bk: &'new crate::core::gc::Block<C>,
     ) -> Gc<Symbol<'new>> {
-        if let SymbolName::Uninterned(name) = &self.name {
+        if !self.interned() {
             match bk.uninterned_symbol_map.get(self) {
                 Some(new) => new.tag(),
                 None => {
-                    let sym = Symbol::new_uninterned(name, bk);
+                    let sym = Symbol::new_uninterned(self.name(), bk);
                     if let Some(old_func) = self.get().get() {
                         let new_func = old_func.clone_in(bk);
                         unsafe {
@@ -178,6 +229,11 @@ impl<'old, 'new> Symbol<'old> {
                 }
             }
         } else {
+            // The global block is never collected, so a symbol it refers to
+            // must stay in the intern table
+            if C {
+                self.pinned.store(true, Ordering::Relaxed);
+            }
             unsafe { self.with_lifetime().tag() }
         }
     }
@@ -191,11 +247,35 @@ impl PartialEq for SymbolCell {
     }
 }
 
-impl<'ob> Symbol<'ob> {
-    pub(in crate::core) fn new(name: &'static str, block: &'ob Block<true>) -> Self {
-        SymbolCell::new_normal(name, block).into_obj(block).untag()
+impl Symbol<'static> {
+    /// Create a symbol for the intern table. It is only freed if it is
+    /// purged from the table.
+    pub(in crate::core) fn new(name: &'static str) -> Self {
+        let cell: &'static SymbolCell = Box::leak(Box::new(SymbolCell::new_normal(name)));
+        unsafe { Self::from_ptr(cell) }
+    }
+
+    /// Free a symbol and its name, returning the bytes they used.
+    ///
+    /// # Safety
+    ///
+    /// The symbol must have been created by [`Symbol::new`] with a leaked
+    /// name, and nothing can refer to it anymore.
+    pub(in crate::core) unsafe fn free(self) -> usize {
+        let cell = std::ptr::from_ref(self.get());
+        let SymbolName::Interned(name) = self.get().name else {
+            unreachable!("Attempt to free uninterned symbol {self}")
+        };
+        let bytes = std::mem::size_of::<SymbolCell>() + name.len();
+        unsafe {
+            drop(Box::from_raw(std::ptr::from_ref(name).cast_mut()));
+            drop(Box::from_raw(cell.cast_mut()));
+        }
+        bytes
     }
+}
 
+impl<'ob> Symbol<'ob> {
     pub(crate) fn new_uninterned<const C: bool>(name: &str, block: &'ob Block<C>) -> Self {
         SymbolCell::new_uninterned(name, block).into_obj(block).untag()
     }
@@ -206,16 +286,21 @@ impl SymbolCell {
     #[allow(clippy::declare_interior_mutable_const)]
     const EMTPTY: AtomicPtr<u8> = AtomicPtr::new(Self::NULL);
 
-    fn new_normal(name: &'static str, block: &Block<true>) -> Self {
+    fn new_normal(name: &'static str) -> Self {
         // We have to do this workaround because starts_with is not const
         if name.as_bytes()[0] == b':' {
-            Self::new_const(name, block)
+            Self::new_const(name)
         } else {
             GcHeap::new(
                 SymbolCellInner {
                     name: SymbolName::Interned(name),
                     func: Some(Self::EMTPTY),
                     special: AtomicBool::new(false),
+                    buffer_local: AtomicBool::new(false),
+                    watched: AtomicBool::new(false),
+                    reached: AtomicBool::new(false),
+                    pinned: AtomicBool::new(false),
+                    compile_pending: AtomicBool::new(false),
                 },
                 true,
             )
@@ -231,6 +316,11 @@ impl SymbolCell {
                 name: SymbolName::Interned(name),
                 func: Some(Self::EMTPTY),
                 special: AtomicBool::new(false),
+                buffer_local: AtomicBool::new(false),
+                watched: AtomicBool::new(false),
+                reached: AtomicBool::new(false),
+                pinned: AtomicBool::new(false),
+                compile_pending: AtomicBool::new(false),
             })
         }
     }
@@ -240,15 +330,25 @@ impl SymbolCell {
             name: SymbolName::Interned(name),
             func: Some(Self::EMTPTY),
             special: AtomicBool::new(true),
+            buffer_local: AtomicBool::new(false),
+            watched: AtomicBool::new(false),
+            reached: AtomicBool::new(false),
+            pinned: AtomicBool::new(false),
+            compile_pending: AtomicBool::new(false),
         })
     }
 
-    fn new_const(name: &'static str, _block: &Block<true>) -> Self {
+    fn new_const(name: &'static str) -> Self {
         GcHeap::new(
             SymbolCellInner {
                 name: SymbolName::Interned(name),
                 func: None,
                 special: AtomicBool::new(true),
+                buffer_local: AtomicBool::new(false),
+                watched: AtomicBool::new(false),
+                reached: AtomicBool::new(false),
+                pinned: AtomicBool::new(false),
+                compile_pending: AtomicBool::new(false),
             },
             true,
         )
@@ -259,15 +359,26 @@ impl SymbolCell {
             name: SymbolName::Interned(name),
             func: None,
             special: AtomicBool::new(true),
+            buffer_local: AtomicBool::new(false),
+            watched: AtomicBool::new(false),
+            reached: AtomicBool::new(false),
+            pinned: AtomicBool::new(false),
+            compile_pending: AtomicBool::new(false),
         })
     }
 
-    fn new_uninterned<const C: bool>(name: &str, _bk: &Block<C>) -> Self {
+    fn new_uninterned<const C: bool>(name: &str, bk: &Block<C>) -> Self {
+        let ptr = bk.objects.alloc_str(name).as_mut_ptr();
         GcHeap::new(
             SymbolCellInner {
-                name: SymbolName::Uninterned(name.to_owned().into_boxed_str()),
+                name: SymbolName::Uninterned(AtomicPtr::new(ptr), name.len()),
                 func: Some(Self::EMTPTY),
                 special: AtomicBool::new(false),
+                buffer_local: AtomicBool::new(false),
+                watched: AtomicBool::new(false),
+                reached: AtomicBool::new(false),
+                pinned: AtomicBool::new(false),
+                compile_pending: AtomicBool::new(false),
             },
             C,
         )
@@ -280,7 +391,10 @@ impl SymbolCellInner {
     pub(crate) fn name(&self) -> &str {
         match &self.name {
             SymbolName::Interned(x) => x,
-            SymbolName::Uninterned(x) => x,
+            SymbolName::Uninterned(ptr, len) => unsafe {
+                let bytes = std::slice::from_raw_parts(ptr.load(Ordering::Acquire), *len);
+                std::str::from_utf8_unchecked(bytes)
+            },
         }
     }
 
@@ -288,6 +402,27 @@ impl SymbolCellInner {
         matches!(self.name, SymbolName::Interned(_))
     }
 
+    fn note_reached(&self) {
+        if !self.reached.load(Ordering::Relaxed) {
+            self.reached.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the garbage collector found a reference to this symbol since
+    /// the last call to [`Self::clear_reached`].
+    pub(in crate::core) fn reached(&self) -> bool {
+        self.reached.load(Ordering::Relaxed)
+    }
+
+    pub(in crate::core) fn clear_reached(&self) {
+        self.reached.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether an object in the global block refers to this symbol.
+    pub(in crate::core) fn pinned(&self) -> bool {
+        self.pinned.load(Ordering::Relaxed)
+    }
+
     #[inline(always)]
     /// Check if the symbol is constant like nil, t, or :keyword
     pub(crate) fn is_const(&self) -> bool {
@@ -295,13 +430,22 @@ impl SymbolCellInner {
     }
 
     pub(crate) fn has_func(&self) -> bool {
-        match &self.func {
-            Some(func) => !func.load(Ordering::Acquire).is_null(),
-            None => false,
-        }
+        self.get().is_some()
     }
 
     fn get(&self) -> Option<Function> {
+        let func = self.global_func();
+        // A thread in a namespace has its own functions for interned symbols
+        if self.interned() {
+            if let Some(local) = namespace::func(self, func) {
+                return local;
+            }
+        }
+        func
+    }
+
+    /// The function of the symbol outside of any namespace.
+    pub(in crate::core) fn global_func(&self) -> Option<Function> {
         if let Some(func) = &self.func {
             let ptr = func.load(Ordering::Acquire);
             // nil is represented as zero (null pointer).
@@ -340,6 +484,9 @@ impl SymbolCellInner {
     }
 
     pub(crate) fn unbind_func(&self) {
+        if self.interned() && namespace::unbind_func(self) {
+            return;
+        }
         if let Some(func) = &self.func {
             func.store(Self::NULL, Ordering::Release);
         }
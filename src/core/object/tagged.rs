@@ -5,11 +5,11 @@ use super::{
         error::{Type, TypeError},
         gc::Block,
     },
-    ByteFnPrototype, ByteString, LispBuffer,
+    ByteFnPrototype, ByteString, ClosureInner, LispBuffer, StringStream, StringStreamInner,
 };
 use super::{
-    ByteFn, HashTable, LispFloat, LispHashTable, LispString, LispVec, Record, RecordBuilder,
-    SubrFn, Symbol, SymbolCell,
+    Ancestors, ByteFn, Closure, HashTable, LispFloat, LispHashTable, LispString, LispVec, Record,
+    RecordBuilder, SubrFn, Symbol, SymbolCell,
 };
 use crate::core::{
     env::sym,
@@ -18,7 +18,6 @@ use crate::core::{
 use bumpalo::collections::String as GcString;
 use bumpalo::collections::Vec as GcVec;
 use private::{Tag, TaggedPtr};
-use rune_core::hashmap::HashSet;
 use sptr::Strict;
 use std::marker::PhantomData;
 use std::{fmt, ptr::NonNull};
@@ -278,12 +277,14 @@ impl GcPtr for Symbol<'_> {}
 object_trait_impls!(LispFloat);
 object_trait_impls!(Cons);
 object_trait_impls!(ByteFn);
+object_trait_impls!(Closure);
 object_trait_impls!(LispString);
 object_trait_impls!(ByteString);
 object_trait_impls!(LispVec);
 object_trait_impls!(Record);
 object_trait_impls!(LispHashTable);
 object_trait_impls!(LispBuffer);
+object_trait_impls!(StringStream);
 
 /// Trait for types that can be managed by the GC. This trait is implemented for
 /// as many types as possible, even for types that are already Gc managed, Like
@@ -377,6 +378,24 @@ impl IntoObject for ByteFnPrototype {
     }
 }
 
+impl IntoObject for ClosureInner {
+    type Out<'ob> = &'ob Closure;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let ptr = block.objects.alloc(Closure::new(self, C));
+        unsafe { Self::Out::tag_ptr(ptr) }
+    }
+}
+
+impl IntoObject for StringStreamInner {
+    type Out<'ob> = &'ob StringStream;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let ptr = block.objects.alloc(StringStream::new(self, C));
+        unsafe { Self::Out::tag_ptr(ptr) }
+    }
+}
+
 impl IntoObject for SymbolCell {
     type Out<'ob> = Symbol<'ob>;
 
@@ -509,6 +528,8 @@ mod private {
         SubrFn,
         ByteFn,
         Buffer,
+        Closure,
+        StringStream,
     }
 
     /// Trait for tagged pointers. Anything that can be stored and passed around
@@ -615,6 +636,8 @@ impl<'a> TaggedPtr for ObjectType<'a> {
                 Tag::Record => ObjectType::Record(<&Record>::from_obj_ptr(ptr)),
                 Tag::HashTable => ObjectType::HashTable(<&LispHashTable>::from_obj_ptr(ptr)),
                 Tag::Buffer => ObjectType::Buffer(<&LispBuffer>::from_obj_ptr(ptr)),
+                Tag::Closure => ObjectType::Closure(<&Closure>::from_obj_ptr(ptr)),
+                Tag::StringStream => ObjectType::StringStream(<&StringStream>::from_obj_ptr(ptr)),
             }
         }
     }
@@ -633,6 +656,8 @@ impl<'a> TaggedPtr for ObjectType<'a> {
             ObjectType::ByteFn(x) => TaggedPtr::tag(x).into(),
             ObjectType::SubrFn(x) => TaggedPtr::tag(x).into(),
             ObjectType::Buffer(x) => TaggedPtr::tag(x).into(),
+            ObjectType::Closure(x) => TaggedPtr::tag(x).into(),
+            ObjectType::StringStream(x) => TaggedPtr::tag(x).into(),
         }
     }
 }
@@ -678,6 +703,7 @@ impl<'a> TaggedPtr for FunctionType<'a> {
                 // SubrFn does not have IntoObject implementation, so we cast it directly
                 Tag::SubrFn => FunctionType::SubrFn(&*ptr.cast::<SubrFn>()),
                 Tag::ByteFn => FunctionType::ByteFn(<&ByteFn>::from_obj_ptr(ptr)),
+                Tag::Closure => FunctionType::Closure(<&Closure>::from_obj_ptr(ptr)),
                 Tag::Symbol => FunctionType::Symbol(<Symbol>::from_obj_ptr(ptr)),
                 _ => unreachable!(),
             }
@@ -689,6 +715,7 @@ impl<'a> TaggedPtr for FunctionType<'a> {
             FunctionType::Cons(x) => TaggedPtr::tag(x).into(),
             FunctionType::SubrFn(x) => TaggedPtr::tag(x).into(),
             FunctionType::ByteFn(x) => TaggedPtr::tag(x).into(),
+            FunctionType::Closure(x) => TaggedPtr::tag(x).into(),
             FunctionType::Symbol(x) => TaggedPtr::tag(x).into(),
         }
     }
@@ -721,8 +748,12 @@ impl<'a> TaggedPtr for NumberType<'a> {
     }
 }
 
-const MAX_FIXNUM: i64 = i64::MAX >> 8;
-const MIN_FIXNUM: i64 = i64::MIN >> 8;
+/// The largest integer that fits in a tagged pointer. This is
+/// `most-positive-fixnum` in lisp.
+pub(crate) const MAX_FIXNUM: i64 = i64::MAX >> 8;
+/// The smallest integer that fits in a tagged pointer. This is
+/// `most-negative-fixnum` in lisp.
+pub(crate) const MIN_FIXNUM: i64 = i64::MIN >> 8;
 
 impl TaggedPtr for i64 {
     type Ptr = i64;
@@ -815,6 +846,30 @@ impl TaggedPtr for &ByteFn {
     }
 }
 
+impl TaggedPtr for &Closure {
+    type Ptr = Closure;
+    const TAG: Tag = Tag::Closure;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
+impl TaggedPtr for &StringStream {
+    type Ptr = StringStream;
+    const TAG: Tag = Tag::StringStream;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
 impl TaggedPtr for &LispString {
     type Ptr = LispString;
     const TAG: Tag = Tag::String;
@@ -965,10 +1020,11 @@ impl<'old, 'new> WithLifetime<'new> for ListType<'old> {
 pub(crate) enum FunctionType<'ob> {
     ByteFn(&'ob ByteFn) = Tag::ByteFn as u8,
     SubrFn(&'static SubrFn) = Tag::SubrFn as u8,
+    Closure(&'ob Closure) = Tag::Closure as u8,
     Cons(&'ob Cons) = Tag::Cons as u8,
     Symbol(Symbol<'ob>) = Tag::Symbol as u8,
 }
-cast_gc!(FunctionType<'ob> => &'ob ByteFn, &'ob SubrFn, &'ob Cons, Symbol<'ob>);
+cast_gc!(FunctionType<'ob> => &'ob ByteFn, &'ob SubrFn, &'ob Closure, &'ob Cons, Symbol<'ob>);
 
 /// Represents a tagged pointer to a lisp object that could be interpreted as a
 /// function. Note that not all `Function` types are valid functions (it could
@@ -1032,6 +1088,8 @@ pub(crate) enum ObjectType<'ob> {
     ByteFn(&'ob ByteFn) = Tag::ByteFn as u8,
     SubrFn(&'static SubrFn) = Tag::SubrFn as u8,
     Buffer(&'static LispBuffer) = Tag::Buffer as u8,
+    Closure(&'ob Closure) = Tag::Closure as u8,
+    StringStream(&'ob StringStream) = Tag::StringStream as u8,
 }
 
 /// The Object defintion that contains all other possible lisp objects. This
@@ -1052,7 +1110,9 @@ cast_gc!(ObjectType<'ob> => NumberType<'ob>,
          &'ob ByteString,
          &'ob ByteFn,
          &'ob SubrFn,
-         &'ob LispBuffer
+         &'ob LispBuffer,
+         &'ob Closure,
+         &'ob StringStream
 );
 
 impl ObjectType<'_> {
@@ -1070,8 +1130,9 @@ impl ObjectType<'_> {
             ObjectType::HashTable(_) => Type::HashTable,
             ObjectType::String(_) => Type::String,
             ObjectType::ByteString(_) => Type::String,
-            ObjectType::ByteFn(_) | ObjectType::SubrFn(_) => Type::Func,
+            ObjectType::ByteFn(_) | ObjectType::SubrFn(_) | ObjectType::Closure(_) => Type::Func,
             ObjectType::Buffer(_) => Type::Buffer,
+            ObjectType::StringStream(_) => Type::StringStream,
         }
     }
 }
@@ -1096,15 +1157,15 @@ impl<'new> WithLifetime<'new> for i64 {
 
 impl<'ob> From<usize> for Object<'ob> {
     fn from(x: usize) -> Self {
-        let ptr = sptr::invalid(x);
-        unsafe { i64::tag_ptr(ptr).into() }
+        TagType::tag(x).into()
     }
 }
 
 impl TagType for usize {
     type Out = i64;
     fn tag(self) -> Gc<Self::Out> {
-        TagType::tag(self as i64)
+        // saturate instead of wrapping to a negative number
+        TagType::tag(i64::try_from(self).unwrap_or(i64::MAX))
     }
 }
 
@@ -1132,7 +1193,8 @@ impl TagType for char {
 impl TagType for u64 {
     type Out = i64;
     fn tag(self) -> Gc<Self::Out> {
-        TagType::tag(self as i64)
+        // saturate instead of wrapping to a negative number
+        TagType::tag(i64::try_from(self).unwrap_or(i64::MAX))
     }
 }
 
@@ -1213,7 +1275,9 @@ impl<'ob> TryFrom<Object<'ob>> for Function<'ob> {
 
     fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
         match value.get_tag() {
-            Tag::ByteFn | Tag::SubrFn | Tag::Cons | Tag::Symbol => unsafe { Ok(cast_gc(value)) },
+            Tag::ByteFn | Tag::SubrFn | Tag::Closure | Tag::Cons | Tag::Symbol => unsafe {
+                Ok(cast_gc(value))
+            },
             _ => Err(TypeError::new(Type::Func, value)),
         }
     }
@@ -1354,6 +1418,8 @@ where
             ObjectType::Record(x) => x.clone_in(bk).into(),
             ObjectType::HashTable(x) => x.clone_in(bk).into(),
             ObjectType::Buffer(x) => x.clone_in(bk).into(),
+            ObjectType::Closure(x) => x.clone_in(bk).into(),
+            ObjectType::StringStream(x) => x.clone_in(bk).into(),
         };
         let Ok(x) = Gc::<U>::try_from(obj) else { unreachable!() };
         x
@@ -1362,6 +1428,9 @@ where
 
 impl<T> Trace for Gc<T> {
     fn trace(&self, state: &mut GcState) {
+        if let Some(report) = &mut state.report {
+            report.record(self.as_obj());
+        }
         match self.as_obj().untag() {
             ObjectType::Int(_) | ObjectType::SubrFn(_) => {}
             ObjectType::Float(x) => x.trace(state),
@@ -1374,6 +1443,8 @@ impl<T> Trace for Gc<T> {
             ObjectType::Symbol(x) => x.trace(state),
             ObjectType::ByteFn(x) => x.trace(state),
             ObjectType::Buffer(x) => x.trace(state),
+            ObjectType::Closure(x) => x.trace(state),
+            ObjectType::StringStream(x) => x.trace(state),
         }
     }
 }
@@ -1410,6 +1481,8 @@ impl Markable for Object<'_> {
             ObjectType::ByteFn(x) => x.is_marked(),
             ObjectType::Symbol(x) => x.is_marked(),
             ObjectType::Buffer(x) => x.is_marked(),
+            ObjectType::Closure(x) => x.is_marked(),
+            ObjectType::StringStream(x) => x.is_marked(),
         }
     }
 
@@ -1425,6 +1498,8 @@ impl Markable for Object<'_> {
             ObjectType::ByteString(x) => cast_pair(x.move_value(to_space)?),
             ObjectType::ByteFn(x) => cast_pair(x.move_value(to_space)?),
             ObjectType::Buffer(x) => cast_pair(x.move_value(to_space)?),
+            ObjectType::Closure(x) => cast_pair(x.move_value(to_space)?),
+            ObjectType::StringStream(x) => cast_pair(x.move_value(to_space)?),
             ObjectType::Symbol(x) => {
                 // Need to handle specially because a symbol is not a pointer,
                 // but rather an offset
@@ -1446,6 +1521,7 @@ impl Markable for Function<'_> {
             FunctionType::SubrFn(_) => true,
             FunctionType::Cons(x) => x.is_marked(),
             FunctionType::ByteFn(x) => x.is_marked(),
+            FunctionType::Closure(x) => x.is_marked(),
             FunctionType::Symbol(x) => x.is_marked(),
         }
     }
@@ -1455,6 +1531,7 @@ impl Markable for Function<'_> {
             FunctionType::SubrFn(_) => return None,
             FunctionType::Cons(x) => cast_pair(x.move_value(to_space)?),
             FunctionType::ByteFn(x) => cast_pair(x.move_value(to_space)?),
+            FunctionType::Closure(x) => cast_pair(x.move_value(to_space)?),
             FunctionType::Symbol(x) => {
                 let (sym, moved) = x.move_value(to_space)?;
                 cast_pair((NonNull::from(sym.get()), moved))
@@ -1576,19 +1653,23 @@ impl<T> Eq for Gc<T> {}
 use std::hash::{Hash, Hasher};
 impl<T> Hash for Gc<T> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.ptr.hash(state);
+        // Floats are hashed by value so that `eql` hash tables can find them
+        match self.as_obj().untag() {
+            ObjectType::Float(x) => x.to_bits().hash(state),
+            _ => self.ptr.hash(state),
+        }
     }
 }
 
 impl fmt::Display for ObjectType<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.display_walk(f, &mut HashSet::default())
+        self.display_walk(f, &mut Ancestors::default())
     }
 }
 
 impl fmt::Debug for ObjectType<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.display_walk(f, &mut HashSet::default())
+        self.display_walk(f, &mut Ancestors::default())
     }
 }
 
@@ -1596,15 +1677,15 @@ impl ObjectType<'_> {
     pub(crate) fn display_walk(
         &self,
         f: &mut fmt::Formatter,
-        seen: &mut HashSet<*const u8>,
+        path: &mut Ancestors<()>,
     ) -> fmt::Result {
         use fmt::Display as D;
         match self {
             ObjectType::Int(x) => D::fmt(x, f),
-            ObjectType::Cons(x) => x.display_walk(f, seen),
-            ObjectType::Vec(x) => x.display_walk(f, seen),
-            ObjectType::Record(x) => x.display_walk(f, seen),
-            ObjectType::HashTable(x) => x.display_walk(f, seen),
+            ObjectType::Cons(x) => x.display_walk(f, path),
+            ObjectType::Vec(x) => x.display_walk(f, path),
+            ObjectType::Record(x) => x.display_walk(f, path),
+            ObjectType::HashTable(x) => x.display_walk(f, path),
             ObjectType::String(x) => write!(f, "\"{x}\""),
             ObjectType::ByteString(x) => write!(f, "\"{x}\""),
             ObjectType::Symbol(x) => D::fmt(x, f),
@@ -1612,6 +1693,8 @@ impl ObjectType<'_> {
             ObjectType::SubrFn(x) => D::fmt(x, f),
             ObjectType::Float(x) => D::fmt(x, f),
             ObjectType::Buffer(x) => D::fmt(x, f),
+            ObjectType::Closure(x) => D::fmt(x, f),
+            ObjectType::StringStream(x) => D::fmt(x, f),
         }
     }
 }
@@ -1630,6 +1713,8 @@ impl<'ob> Object<'ob> {
             ObjectType::ByteFn(x) => x.is_marked(),
             ObjectType::Symbol(x) => x.is_marked(),
             ObjectType::Buffer(x) => x.is_marked(),
+            ObjectType::Closure(x) => x.is_marked(),
+            ObjectType::StringStream(x) => x.is_marked(),
         }
     }
 }
@@ -1646,7 +1731,7 @@ impl<'ob> ListType<'ob> {
 
 #[cfg(test)]
 mod test {
-    use super::{TagType, MAX_FIXNUM, MIN_FIXNUM};
+    use super::{Object, TagType, MAX_FIXNUM, MIN_FIXNUM};
     use crate::core::gc::{Context, RootSet};
     use rune_core::macros::list;
 
@@ -1658,6 +1743,9 @@ mod test {
         assert_eq!(MAX_FIXNUM.tag().untag(), MAX_FIXNUM);
         assert_eq!(i64::MIN.tag().untag(), MIN_FIXNUM);
         assert_eq!(MIN_FIXNUM.tag().untag(), MIN_FIXNUM);
+        assert_eq!(usize::MAX.tag().untag(), MAX_FIXNUM);
+        assert_eq!(Object::from(usize::MAX), MAX_FIXNUM);
+        assert_eq!(u64::MAX.tag().untag(), MAX_FIXNUM);
     }
 
     #[test]
@@ -1670,5 +1758,12 @@ mod test {
 
         cons.as_cons().set_car(cons).unwrap();
         assert_eq!(format!("{cons}"), "(#0 . #0)");
+
+        // Shared structure that isn't circular is printed in full
+        let shared = list![1; cx];
+        let list = list![shared, shared; cx];
+        assert_eq!(format!("{list}"), "((1) (1))");
+        let vec = cx.add(vec![list, list]);
+        assert_eq!(format!("{vec}"), "[((1) (1)) ((1) (1))]");
     }
 }
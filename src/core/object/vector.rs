@@ -1,13 +1,15 @@
-use super::{CloneIn, Gc, IntoObject, MutObjCell, ObjCell, Object};
+use super::{address, Ancestors, CloneIn, Gc, IntoObject, MutObjCell, ObjCell, Object};
 use crate::{
-    core::gc::{Block, GcHeap, GcState, Trace},
+    core::{
+        env::sym,
+        gc::{Block, GcHeap, GcState, Trace},
+    },
     NewtypeMarkable,
 };
 use anyhow::{anyhow, Result};
 use bumpalo::collections::Vec as GcVec;
 use macro_attr_2018::macro_attr;
 use newtype_derive_2018::*;
-use rune_core::hashmap::HashSet;
 use rune_macros::Trace;
 use std::{
     cell::Cell,
@@ -102,13 +104,13 @@ impl Trace for LispVecInner {
 
 impl fmt::Display for LispVecInner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.display_walk(f, &mut HashSet::default())
+        self.display_walk(f, &mut Ancestors::default())
     }
 }
 
 impl fmt::Debug for LispVecInner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.display_walk(f, &mut HashSet::default())
+        self.display_walk(f, &mut Ancestors::default())
     }
 }
 
@@ -121,21 +123,19 @@ impl LispVecInner {
     pub(super) fn display_walk(
         &self,
         f: &mut fmt::Formatter<'_>,
-        seen: &mut HashSet<*const u8>,
+        path: &mut Ancestors<()>,
     ) -> fmt::Result {
-        let ptr = (self as *const Self).cast();
-        if seen.contains(&ptr) {
+        if !path.enter(address(self), ()) {
             return write!(f, "#0");
         }
-        seen.insert(ptr);
-
         f.write_char('[')?;
         for (i, x) in self.iter().enumerate() {
             if i != 0 {
                 f.write_char(' ')?;
             }
-            x.get().untag().display_walk(f, seen)?;
+            x.get().untag().display_walk(f, path)?;
         }
+        path.leave(address(self));
         f.write_char(']')
     }
 }
@@ -158,28 +158,34 @@ impl<'new> CloneIn<'new, &'new Self> for Record {
 
 impl fmt::Display for Record {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.display_walk(f, &mut HashSet::default())
+        self.display_walk(f, &mut Ancestors::default())
     }
 }
 
 impl Record {
-    fn display_walk(
+    /// Records are printed as `#s(TYPE SLOTS...)`, except for char-tables which
+    /// are printed as `#^[SLOTS...]`.
+    pub(super) fn display_walk(
         &self,
         f: &mut fmt::Formatter<'_>,
-        seen: &mut HashSet<*const u8>,
+        path: &mut Ancestors<()>,
     ) -> fmt::Result {
-        let ptr = (self as *const Self).cast();
-        if seen.contains(&ptr) {
+        if !path.enter(address(self), ()) {
             return write!(f, "#0");
         }
-        seen.insert(ptr);
-        write!(f, "#s(")?;
-        for (i, x) in self.iter().enumerate() {
+        let char_table = self.first().is_some_and(|x| x.get() == sym::CHAR_TABLE);
+        let (open, close, slots) = match char_table {
+            true => ("#^[", ']', &self[1..]),
+            false => ("#s(", ')', &self[..]),
+        };
+        f.write_str(open)?;
+        for (i, x) in slots.iter().enumerate() {
             if i != 0 {
                 f.write_char(' ')?;
             }
-            x.get().untag().display_walk(f, seen)?;
+            x.get().untag().display_walk(f, path)?;
         }
-        f.write_char(')')
+        path.leave(address(self));
+        f.write_char(close)
     }
 }
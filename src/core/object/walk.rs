@@ -0,0 +1,50 @@
+//! Support for walking circular structure. A walk that recurses into conses
+//! and vectors keeps track of the objects it is inside of, so it can tell when
+//! it has looped back to one of them. The printer uses this to print a back
+//! reference, and `copy-tree` to make the copy circular in the same way.
+use rune_core::hashmap::HashMap;
+
+/// The address that identifies an object in a walk.
+pub(crate) fn address<T>(obj: &T) -> *const u8 {
+    std::ptr::from_ref(obj).cast()
+}
+
+/// The objects on the path from the root of a walk to the current object,
+/// each with a value the walk associates with it. Objects that are shared but
+/// not circular are only on the path while they are being walked, so they
+/// are not mistaken for a loop when they are reached again.
+pub(crate) struct Ancestors<T> {
+    path: HashMap<*const u8, T>,
+}
+
+impl<T> Default for Ancestors<T> {
+    fn default() -> Self {
+        Self { path: HashMap::default() }
+    }
+}
+
+impl<T> Ancestors<T> {
+    /// Add the object at `ptr` to the path. Returns false if it is already on
+    /// the path, which means the structure is circular.
+    pub(crate) fn enter(&mut self, ptr: *const u8, value: T) -> bool {
+        if self.path.contains_key(&ptr) {
+            return false;
+        }
+        self.path.insert(ptr, value);
+        true
+    }
+
+    /// Remove the object at `ptr` from the path once it has been walked.
+    pub(crate) fn leave(&mut self, ptr: *const u8) {
+        self.path.remove(&ptr);
+    }
+
+    /// The value of the object at `ptr`, if it is on the path.
+    pub(crate) fn get(&self, ptr: *const u8) -> Option<&T> {
+        self.path.get(&ptr)
+    }
+
+    pub(crate) fn contains(&self, ptr: *const u8) -> bool {
+        self.path.contains_key(&ptr)
+    }
+}
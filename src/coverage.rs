@@ -0,0 +1,345 @@
+//! Coverage instrumentation, in the style of testcover. When `load-coverage`
+//! is non-nil, each form loaded from a file is instrumented after it is macro
+//! expanded: every call and special form that is evaluated for its value is
+//! wrapped in `(coverage--hit ID FORM)`, which counts how often FORM returns.
+//! `coverage-report` then lists the forms of each file that never did.
+use crate::bytecomp;
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{FunctionType, Object, ObjectType, Symbol},
+};
+use crate::fns::slice_into_list;
+use rune_core::macros::list;
+use rune_macros::defun;
+use std::fmt::Write as _;
+use std::sync::{Mutex, PoisonError};
+
+/// The longest a form is shown in a report.
+const MAX_FORM_WIDTH: usize = 60;
+
+/// An instrumented form.
+struct Point {
+    file: String,
+    line: usize,
+    /// The printed form, shortened to fit in a report
+    form: String,
+    count: u64,
+    /// False once the file has been loaded again, which creates new points
+    live: bool,
+}
+
+/// Every instrumented form, indexed by the ID in its `coverage--hit` call.
+static POINTS: Mutex<Vec<Point>> = Mutex::new(Vec::new());
+
+/// Whether files should be instrumented as they are loaded.
+pub(crate) fn enabled(env: &Rt<Env>, cx: &Context) -> bool {
+    env.var(sym::LOAD_COVERAGE, cx).is_some_and(|x| !x.bind(cx).is_nil())
+}
+
+/// Forget the points of `file` from an earlier load.
+pub(crate) fn start_file(file: &str) {
+    for point in POINTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter_mut()
+        .filter(|x| x.file == file)
+    {
+        point.live = false;
+    }
+}
+
+/// A top-level form being instrumented.
+pub(crate) struct Source<'a> {
+    pub(crate) file: &'a str,
+    /// The contents of the file
+    pub(crate) text: &'a str,
+    /// Where the form is in `text`
+    pub(crate) span: std::ops::Range<usize>,
+}
+
+struct Instrumenter<'a, 'ob> {
+    source: &'a Source<'a>,
+    /// Forms are found in the source in order, so each search starts after
+    /// the last form found
+    cursor: usize,
+    cx: &'ob Context,
+}
+
+impl<'ob> Instrumenter<'_, 'ob> {
+    /// Add a point for `form`, whose head is `head`.
+    fn point(&mut self, head: Symbol, form: Object) -> usize {
+        let text = &self.source.text[self.source.span.clone()];
+        let offset = match bytecomp::locate(&text[self.cursor..], head.name()) {
+            Some(offset) => {
+                let found = self.cursor + offset;
+                self.cursor = found + head.name().len();
+                found
+            }
+            // Made by a macro
+            None => 0,
+        };
+        let (line, _) = bytecomp::line_column(self.source.text, self.source.span.start + offset);
+        let mut printed = form.to_string();
+        if printed.chars().count() > MAX_FORM_WIDTH {
+            printed = printed.chars().take(MAX_FORM_WIDTH - 3).collect::<String>() + "...";
+        }
+        let mut points = POINTS.lock().unwrap_or_else(PoisonError::into_inner);
+        let file = self.source.file.to_owned();
+        points.push(Point { file, line, form: printed, count: 0, live: true });
+        points.len() - 1
+    }
+
+    /// Instrument a form whose value is used.
+    fn form(&mut self, form: Object<'ob>) -> Object<'ob> {
+        let ObjectType::Cons(cons) = form.untag() else { return form };
+        let ObjectType::Symbol(head) = cons.car().untag() else { return form };
+        let cx = self.cx;
+        match head {
+            sym::QUOTE | sym::INTERACTIVE => return form,
+            sym::FUNCTION => return self.function(cons),
+            _ => {}
+        }
+        let Some(args) = elements(cons.cdr()) else { return form };
+        let id = self.point(head, form);
+        let args = match head {
+            sym::LET | sym::LET_STAR | sym::CL_LETF | sym::CL_LETF_STAR => {
+                let Some((bindings, body)) = args.split_first() else { return form };
+                let bindings = match elements(*bindings) {
+                    Some(bindings) => bindings.into_iter().map(|x| self.binding(x)).collect(),
+                    None => vec![],
+                };
+                let mut args = vec![slice_into_list(&bindings, None, cx)];
+                args.extend(self.forms(body));
+                args
+            }
+            sym::COND => args
+                .iter()
+                .map(|clause| match elements(*clause) {
+                    Some(clause) => slice_into_list(&self.forms(&clause), None, cx),
+                    None => *clause,
+                })
+                .collect(),
+            sym::SETQ => args
+                .iter()
+                .enumerate()
+                .map(|(i, x)| if i % 2 == 1 { self.form(*x) } else { *x })
+                .collect(),
+            sym::DEFVAR | sym::DEFCONST => self.nth(&args, 1),
+            sym::DEFCUSTOM if !head.has_func() => self.nth(&args, 1),
+            sym::CONDITION_CASE => {
+                let mut args = self.nth(&args, 1);
+                for handler in args.iter_mut().skip(2) {
+                    if let Some([conditions, body @ ..]) = elements(*handler).as_deref() {
+                        *handler = Cons::new(*conditions, self.body(body), cx).into();
+                    }
+                }
+                args
+            }
+            sym::CL_DESTRUCTURING_BIND => {
+                let Some((arglist, rest)) = args.split_first() else { return form };
+                let mut args = vec![*arglist];
+                args.extend(self.forms(rest));
+                args
+            }
+            sym::IF
+            | sym::WHEN
+            | sym::UNLESS
+            | sym::AND
+            | sym::OR
+            | sym::WHILE
+            | sym::PROGN
+            | sym::INLINE
+            | sym::PROG1
+            | sym::PROG2
+            | sym::CATCH
+            | sym::THROW
+            | sym::SAVE_CURRENT_BUFFER
+            | sym::SAVE_EXCURSION
+            | sym::UNWIND_PROTECT => self.forms(&args),
+            _ if is_function(head, cx) => self.forms(&args),
+            // A macro or special form whose arguments might not be evaluated
+            _ => args,
+        };
+        let form: Object = Cons::new(head, slice_into_list(&args, None, cx), cx).into();
+        list![sym::COVERAGE__HIT, id as i64, form; cx]
+    }
+
+    fn forms(&mut self, forms: &[Object<'ob>]) -> Vec<Object<'ob>> {
+        forms.iter().map(|x| self.form(*x)).collect()
+    }
+
+    fn body(&mut self, forms: &[Object<'ob>]) -> Object<'ob> {
+        let forms = self.forms(forms);
+        slice_into_list(&forms, None, self.cx)
+    }
+
+    /// Instrument only the `n`th of `args`.
+    fn nth(&mut self, args: &[Object<'ob>], n: usize) -> Vec<Object<'ob>> {
+        let mut args = args.to_vec();
+        if let Some(arg) = args.get_mut(n) {
+            *arg = self.form(*arg);
+        }
+        args
+    }
+
+    /// Instrument a `let` binding.
+    fn binding(&mut self, binding: Object<'ob>) -> Object<'ob> {
+        match elements(binding).as_deref() {
+            Some([var, value]) => list![*var, self.form(*value); self.cx],
+            _ => binding,
+        }
+    }
+
+    /// Instrument the body of a `function` form if it holds a lambda.
+    fn function(&mut self, cons: &'ob Cons) -> Object<'ob> {
+        let form = cons.into();
+        let lambda = match elements(cons.cdr()).as_deref() {
+            Some([lambda]) => *lambda,
+            _ => return form,
+        };
+        match elements(lambda).as_deref() {
+            Some([head, arglist, body @ ..]) if *head == sym::LAMBDA => {
+                let body = self.body(body);
+                let lambda: Object =
+                    Cons::new(*head, Cons::new(*arglist, body, self.cx), self.cx).into();
+                list![sym::FUNCTION, lambda; self.cx]
+            }
+            _ => form,
+        }
+    }
+}
+
+/// The elements of a proper list.
+fn elements(list: Object) -> Option<Vec<Object>> {
+    list.as_list().ok()?.collect::<Result<_, _>>().ok()
+}
+
+/// Whether a form headed by `head` is a function call, so all its arguments
+/// are evaluated. Symbols with no function are assumed to be functions
+/// defined later.
+fn is_function(head: Symbol, cx: &Context) -> bool {
+    match head.func(cx).map(|x| x.untag()) {
+        Some(FunctionType::Cons(cons)) => cons.car() != sym::MACRO,
+        Some(_) => true,
+        // Special forms that aren't handled by the instrumenter
+        None => !matches!(head, sym::DEFCUSTOM | sym::DEFGROUP),
+    }
+}
+
+/// Instrument `form`, the macro expanded top-level form at `source`.
+pub(crate) fn instrument<'ob>(form: Object<'ob>, source: &Source, cx: &'ob Context) -> Object<'ob> {
+    Instrumenter { source, cursor: 0, cx }.form(form)
+}
+
+/// Count an evaluation of the form with `id` and return its value.
+#[defun]
+fn coverage__hit(id: usize, value: Object) -> Object {
+    if let Some(point) = POINTS.lock().unwrap_or_else(PoisonError::into_inner).get_mut(id) {
+        point.count += 1;
+    }
+    value
+}
+
+/// A report of the forms that were never evaluated, for each file or just
+/// `file`.
+pub(crate) fn report(file: Option<&str>) -> String {
+    let points = POINTS.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut files: Vec<&str> = points.iter().filter(|x| x.live).map(|x| x.file.as_str()).collect();
+    files.sort_unstable();
+    files.dedup();
+    let mut out = String::new();
+    for name in files.into_iter().filter(|x| file.map_or(true, |file| file == *x)) {
+        let mut missed: Vec<_> =
+            points.iter().filter(|x| x.live && x.file == name && x.count == 0).collect();
+        missed.sort_by_key(|x| x.line);
+        let total = points.iter().filter(|x| x.live && x.file == name).count();
+        let covered = total - missed.len();
+        let percent = covered as f64 * 100.0 / total as f64;
+        _ = writeln!(out, "{name}: {covered} of {total} forms evaluated ({percent:.1}%)");
+        for point in missed {
+            _ = writeln!(out, "  {}: {}", point.line, point.form);
+        }
+    }
+    out
+}
+
+#[defun]
+fn coverage_report(file: Option<&str>) -> String {
+    report(file)
+}
+
+/// The instrumented forms as a list of `(FILE LINE COUNT FORM)`.
+#[defun]
+fn coverage_data(cx: &Context) -> Object {
+    let points = POINTS.lock().unwrap_or_else(PoisonError::into_inner);
+    let data: Vec<Object> = points
+        .iter()
+        .filter(|x| x.live)
+        .map(|x| list![x.file.as_str(), x.line as i64, x.count as i64, x.form.as_str(); cx])
+        .collect();
+    slice_into_list(&data, None, cx)
+}
+
+/// Set the counts of all the instrumented forms back to zero.
+#[defun]
+fn coverage_reset() {
+    for point in POINTS.lock().unwrap_or_else(PoisonError::into_inner).iter_mut() {
+        point.count = 0;
+    }
+}
+
+defvar!(LOAD_COVERAGE);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_coverage() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        crate::core::env::init_variables(cx, env);
+        env.set_default(sym::LOAD_COVERAGE, sym::TRUE.into()).unwrap();
+        let dir = std::env::temp_dir().join(format!("rune-coverage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("coverage-test.el");
+        let source = "(defalias 'coverage-test-fn
+  #'(lambda (x)
+      (if (> x 0)
+          (list 'positive)
+        (list 'negative))))
+(setq coverage-test-a (coverage-test-fn 1)
+      coverage-test-b (coverage-test-fn 2))
+(let ((y (car '(1))))
+  (cond ((= y 1) (symbol-name 'one))
+        (t (symbol-name 'other))))
+";
+        std::fs::write(&path, source).unwrap();
+        let file = path.to_string_lossy().into_owned();
+        for _ in 0..2 {
+            let name: crate::core::object::Gc<&crate::core::object::LispString> =
+                cx.add_as(file.clone());
+            root!(name, cx);
+            crate::lread::load(name, None, Some(()), cx, env).unwrap();
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let value = env.var(crate::core::env::intern("coverage-test-b", cx), cx);
+        assert_eq!(value.unwrap().bind(cx).to_string(), "(positive)");
+        let expected = format!(
+            "{file}: 12 of 14 forms evaluated (85.7%)
+  5: (list 'negative)
+  10: (symbol-name 'other)
+"
+        );
+        assert_eq!(report(Some(&file)), expected);
+        let data = coverage_data(cx).to_string();
+        assert!(data.contains("3 2 \"(> x 0)\""), "{data}");
+    }
+}
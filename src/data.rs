@@ -1,18 +1,27 @@
 //! Utilities for variables and values.
+use crate::chartab::{is_char_table, CharTable};
 use crate::core::{
     cons::Cons,
-    env::{interned_symbols, sym, Env},
-    error::{Type, TypeError},
-    gc::{Context, Rt},
-    object::{List, ListType, Number, Object, ObjectType, SubrFn, Symbol, WithLifetime, NIL},
+    env::{interned_symbols, namespace, sym, Env},
+    error::{ArgRangeError, Type, TypeError, VoidError},
+    gc::{Context, Rt, Rto},
+    object::{
+        checked_fixnum, int_to_char, parse_float, Function, Gc, LispBuffer, List, ListType, Number,
+        Object, ObjectType, SubrFn, Symbol, WithLifetime, NIL,
+    },
 };
-use anyhow::{anyhow, Result};
-use rune_core::hashmap::HashSet;
+use crate::obarray::is_obarray;
+use crate::process::is_process;
+use crate::rooted_iter;
+use anyhow::{anyhow, bail, Result};
+use fallible_streaming_iterator::FallibleStreamingIterator;
+use rune_core::hashmap::IndexSet;
+use rune_core::macros::{call, root};
 use rune_macros::defun;
-use std::sync::Mutex;
 use std::sync::OnceLock;
+use std::sync::{Mutex, PoisonError};
 
-static FEATURES: OnceLock<Mutex<HashSet<Symbol<'static>>>> = OnceLock::new();
+static FEATURES: OnceLock<Mutex<IndexSet<Symbol<'static>>>> = OnceLock::new();
 
 /// Rust translation of the `features` variable: A list of symbols are the features
 /// of the executing Emacs. Used by [`featurep`](`crate::fns::featurep`) and [`require`](`crate::fns::require`),
@@ -20,39 +29,179 @@ static FEATURES: OnceLock<Mutex<HashSet<Symbol<'static>>>> = OnceLock::new();
 /// to `lock()` on the Mutex.
 ///
 /// TODO: Use `LazyLock`: <https://github.com/CeleritasCelery/rune/issues/34>
-pub(crate) fn features() -> &'static Mutex<HashSet<Symbol<'static>>> {
+fn features() -> &'static Mutex<IndexSet<Symbol<'static>>> {
     FEATURES.get_or_init(Mutex::default)
 }
 
+/// Run `f` on the features. A thread in a
+/// [`namespace`](crate::core::env::namespace) has features of its own.
+pub(crate) fn with_features<T>(f: impl FnOnce(&mut IndexSet<Symbol<'static>>) -> T) -> T {
+    match namespace::in_namespace() {
+        true => namespace::with_features(f).unwrap(),
+        false => f(&mut features().lock().unwrap_or_else(PoisonError::into_inner)),
+    }
+}
+
 #[defun]
 pub(crate) fn fset<'ob>(symbol: Symbol<'ob>, definition: Object) -> Result<Symbol<'ob>> {
     if definition.is_nil() {
         symbol.unbind_func();
     } else {
         let func = definition.try_into()?;
-        let map = interned_symbols().lock().unwrap();
-        map.set_func(symbol, func)?;
+        interned_symbols().set_func(symbol, func)?;
     }
     Ok(symbol)
 }
 
 #[defun]
 pub(crate) fn defalias<'ob>(
-    symbol: Symbol<'ob>,
+    symbol: Symbol,
     definition: Object,
     _docstring: Option<&str>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
 ) -> Result<Symbol<'ob>> {
-    fset(symbol, definition)
+    fset(symbol, definition)?;
+    let entry = Cons::new(sym::DEFUN, symbol, cx);
+    crate::lread::record_definition(entry.into(), env, cx);
+    root!(symbol, cx);
+    crate::bytecomp::compile_on_define(symbol, env, cx);
+    Ok(symbol.bind(cx))
 }
 
 #[defun]
 pub(crate) fn set<'ob>(
     place: Symbol,
-    newlet: Object<'ob>,
+    newlet: Object,
     env: &mut Rt<Env>,
+    cx: &'ob mut Context,
 ) -> Result<Object<'ob>> {
-    env.set_var(place, newlet)?;
-    Ok(newlet)
+    root!(place, cx);
+    root!(newlet, cx);
+    set_variable(place, newlet, env, cx)?;
+    Ok(newlet.bind(cx))
+}
+
+/// Set the value of `place` in the current buffer, notifying its watchers
+/// first.
+pub(crate) fn set_variable(
+    place: &Rto<Symbol>,
+    newlet: &Rto<Object>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    notify_variable_watchers(place, newlet, sym::SET, true, env, cx)?;
+    env.set_var(place.bind(cx), newlet.bind(cx), cx)
+}
+
+/// Undo the last `count` dynamic bindings. The watchers of each variable are
+/// told the value it is returning to. Every binding is undone even if a
+/// watcher signals an error.
+pub(crate) fn unbind(count: u16, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let mut result = Ok(());
+    for _ in 0..count {
+        if let Some((var, value)) = env.next_unbinding(cx) {
+            if var.is_watched() {
+                let value = value.unwrap_or_default();
+                root!(var, cx);
+                root!(value, cx);
+                let notified = notify_variable_watchers(var, value, sym::UNLET, true, env, cx);
+                result = result.and(notified);
+            }
+        }
+        env.unbind(1, cx);
+    }
+    result
+}
+
+/// Call the watchers of `symbol` before its value changes. Each one is called
+/// with `(symbol newval operation where)`. `where` is the current buffer if
+/// the buffer-local value is changing, otherwise nil. `local` is false for
+/// operations that only change the default value.
+pub(crate) fn notify_variable_watchers(
+    symbol: &Rto<Symbol>,
+    newval: &Rto<Object>,
+    operation: Symbol,
+    local: bool,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let var = symbol.bind(cx);
+    if !var.is_watched() {
+        return Ok(());
+    }
+    let location = match env.current_buffer.as_ref().map(|x| x.lisp_buffer(cx)) {
+        Some(buffer)
+            if local
+                && (env.local_var(var, buffer).is_some()
+                    || (operation == sym::SET && var.is_buffer_local())) =>
+        {
+            cx.add(buffer)
+        }
+        _ => NIL,
+    };
+    let watchers = get(var, sym::WATCHERS, env, cx);
+    let var = Object::from(var);
+    let operation = Object::from(operation);
+    root!(location, cx);
+    root!(watchers, cx);
+    root!(var, cx);
+    root!(operation, cx);
+    // Setting the variable from a watcher should not notify them again
+    symbol.bind(cx).set_watched(false);
+    let result = call_watchers(watchers, [var, newval, operation, location], env, cx);
+    let watched = get(symbol.bind(cx), sym::WATCHERS, env, cx) != NIL;
+    symbol.bind(cx).set_watched(watched);
+    result
+}
+
+fn call_watchers(
+    watchers: &Rto<Object>,
+    args: [&Rto<Object>; 4],
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let [symbol, newval, operation, location] = args;
+    rooted_iter!(watchers, watchers, cx);
+    while let Some(watcher) = watchers.next()? {
+        let watcher: &Rto<Function> = watcher.try_as()?;
+        call!(watcher, symbol, newval, operation, location; env, cx)?;
+    }
+    Ok(())
+}
+
+#[defun]
+fn add_variable_watcher(
+    symbol: Symbol,
+    watch_function: Object,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let watchers = get(symbol, sym::WATCHERS, env, cx);
+    if crate::fns::member(watch_function, watchers.try_into()?)? == NIL {
+        env.set_prop(symbol, sym::WATCHERS, Cons::new(watch_function, watchers, cx).into());
+    }
+    symbol.set_watched(true);
+    Ok(())
+}
+
+#[defun]
+fn remove_variable_watcher(
+    symbol: Symbol,
+    watch_function: Object,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let watchers = get(symbol, sym::WATCHERS, env, cx);
+    let watchers = crate::fns::delete(watch_function, watchers.try_into()?)?;
+    env.set_prop(symbol, sym::WATCHERS, watchers);
+    symbol.set_watched(watchers != NIL);
+    Ok(())
+}
+
+#[defun]
+fn get_variable_watchers<'ob>(symbol: Symbol, env: &Rt<Env>, cx: &'ob Context) -> Object<'ob> {
+    get(symbol, sym::WATCHERS, env, cx)
 }
 
 #[defun]
@@ -82,10 +231,36 @@ pub(crate) fn get<'ob>(
     }
 }
 
+/// `buffer`, or the current buffer if it is nil.
+fn buffer_or_current<'ob>(
+    buffer: Option<Gc<&'ob LispBuffer>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Option<&'ob LispBuffer> {
+    match buffer {
+        Some(buffer) => Some(buffer.untag()),
+        None => env.current_buffer.as_ref().map(|x| x.lisp_buffer(cx)),
+    }
+}
+
 #[defun]
-pub(crate) fn local_variable_if_set_p(_sym: Symbol) -> bool {
-    // TODO: Implement buffer locals
-    false
+pub(crate) fn local_variable_p(
+    variable: Symbol,
+    buffer: Option<Gc<&LispBuffer>>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> bool {
+    buffer_or_current(buffer, env, cx).is_some_and(|b| env.local_var(variable, b).is_some())
+}
+
+#[defun]
+pub(crate) fn local_variable_if_set_p(
+    variable: Symbol,
+    buffer: Option<Gc<&LispBuffer>>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> bool {
+    variable.is_buffer_local() || local_variable_p(variable, buffer, env, cx)
 }
 
 #[defun]
@@ -94,8 +269,48 @@ pub(crate) fn default_value<'ob>(
     env: &Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    // TODO: Implement buffer locals
-    symbol_value(symbol, env, cx).ok_or_else(|| anyhow!("Void variable: {symbol}"))
+    match env.vars.get(symbol) {
+        Some(value) => Ok(value.bind(cx)),
+        None => Err(VoidError::variable(symbol).into()),
+    }
+}
+
+#[defun]
+fn buffer_local_value<'ob>(
+    variable: Symbol,
+    buffer: Gc<&LispBuffer>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    match env.local_var(variable, buffer.untag()).or_else(|| env.vars.get(variable)) {
+        Some(value) => Ok(value.bind(cx)),
+        None => Err(VoidError::variable(variable).into()),
+    }
+}
+
+#[defun]
+fn make_local_variable<'ob>(
+    variable: Symbol<'ob>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<Symbol<'ob>> {
+    let Some(buffer) = env.current_buffer.as_ref().map(|x| x.lisp_buffer(cx)) else {
+        return Ok(variable);
+    };
+    if env.local_var(variable, buffer).is_none() {
+        // The local value starts out as the default value
+        let value = env.vars.get(variable).map_or(NIL, |x| x.bind(cx));
+        env.set_local_var(variable, value, buffer)?;
+    }
+    Ok(variable)
+}
+
+#[defun]
+fn kill_local_variable<'ob>(variable: Symbol<'ob>, env: &mut Rt<Env>, cx: &Context) -> Symbol<'ob> {
+    if let Some(buffer) = env.current_buffer.as_ref().map(|x| x.lisp_buffer(cx)) {
+        env.kill_local_var(variable, buffer);
+    }
+    variable
 }
 
 #[defun]
@@ -112,7 +327,7 @@ pub(crate) fn symbol_value<'ob>(
     env: &Rt<Env>,
     cx: &'ob Context,
 ) -> Option<Object<'ob>> {
-    env.vars.get(symbol).map(|x| x.bind(cx))
+    env.var(symbol, cx).map(|x| x.bind(cx))
 }
 
 #[defun]
@@ -137,14 +352,21 @@ pub(crate) fn fmakunbound(symbol: Symbol) -> Symbol {
 }
 
 #[defun]
-pub(crate) fn boundp(symbol: Symbol, env: &Rt<Env>) -> bool {
-    env.vars.get(symbol).is_some()
+pub(crate) fn boundp(symbol: Symbol, env: &Rt<Env>, cx: &Context) -> bool {
+    env.var(symbol, cx).is_some()
 }
 
 #[defun]
-pub(crate) fn makunbound<'ob>(symbol: Symbol<'ob>, env: &mut Rt<Env>) -> Symbol<'ob> {
-    env.vars.remove(symbol);
-    symbol
+pub(crate) fn makunbound<'ob>(
+    symbol: Symbol,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Symbol<'ob>> {
+    root!(symbol, cx);
+    root!(value, NIL, cx);
+    notify_variable_watchers(symbol, value, sym::MAKUNBOUND, false, env, cx)?;
+    env.vars.remove(symbol.bind(cx));
+    Ok(symbol.bind(cx))
 }
 
 #[defun]
@@ -170,13 +392,18 @@ pub(crate) fn symbolp(object: Object) -> bool {
 #[defun]
 pub(crate) fn functionp(object: Object) -> bool {
     match object.untag() {
-        ObjectType::ByteFn(_) | ObjectType::SubrFn(_) => true,
+        ObjectType::ByteFn(_) | ObjectType::SubrFn(_) | ObjectType::Closure(_) => true,
         ObjectType::Cons(cons) => cons.car() == sym::CLOSURE,
         ObjectType::Symbol(sym) => sym.has_func(),
         _ => false,
     }
 }
 
+#[defun]
+fn interpreted_function_p(object: Object) -> bool {
+    matches!(object.untag(), ObjectType::Closure(_))
+}
+
 #[defun]
 pub(crate) fn subrp(object: Object) -> bool {
     matches!(object.untag(), ObjectType::SubrFn(_))
@@ -205,7 +432,7 @@ pub(crate) fn vectorp(object: Object) -> bool {
 
 #[defun]
 pub(crate) fn recordp(object: Object) -> bool {
-    matches!(object.untag(), ObjectType::Record(_))
+    matches!(object.untag(), ObjectType::Record(x) if !is_char_table(x) && !is_process(x) && !is_obarray(x))
 }
 
 #[defun]
@@ -258,17 +485,20 @@ pub(crate) fn multibyte_string_p(object: Object) -> bool {
 }
 
 #[defun]
-fn string_to_number<'ob>(string: &str, base: Option<i64>, cx: &'ob Context) -> Number<'ob> {
+fn string_to_number<'ob>(string: &str, base: Option<i64>, cx: &'ob Context) -> Result<Number<'ob>> {
     // TODO: Handle trailing characters, which should be ignored
     let base = base.unwrap_or(10);
+    if !(2..=16).contains(&base) {
+        bail!(ArgRangeError::new(&[base]));
+    }
     let string = string.trim();
-    match i64::from_str_radix(string, base as u32) {
+    Ok(match i64::from_str_radix(string, base as u32) {
         Ok(x) => x.into(),
-        Err(_) => match string.parse::<f64>() {
-            Ok(x) => cx.add_as(x),
-            Err(_) => 0.into(),
+        Err(_) => match parse_float(string) {
+            Some(x) if base == 10 => cx.add_as(x),
+            _ => 0.into(),
         },
-    }
+    })
 }
 
 #[defun]
@@ -279,13 +509,20 @@ pub(crate) fn defvar<'ob>(
     env: &mut Rt<Env>,
 ) -> Result<Object<'ob>> {
     let value = initvalue.unwrap_or_default();
-    set(symbol, value, env)
+    env.set_default(symbol, value)?;
+    Ok(value)
 }
 
 #[defun]
-pub(crate) fn make_variable_buffer_local(variable: Symbol) -> Symbol {
-    // TODO: Implement
-    variable
+pub(crate) fn make_variable_buffer_local<'ob>(
+    variable: Symbol<'ob>,
+    env: &mut Rt<Env>,
+) -> Result<Symbol<'ob>> {
+    if env.vars.get(variable).is_none() {
+        env.set_default(variable, NIL)?;
+    }
+    variable.make_buffer_local();
+    Ok(variable)
 }
 
 #[defun]
@@ -302,13 +539,16 @@ fn subr_arity<'ob>(subr: &SubrFn, cx: &'ob Context) -> Object<'ob> {
 }
 
 #[defun]
-fn ash(value: i64, count: i64) -> i64 {
-    let shift = if count >= 0 { std::ops::Shl::shl } else { std::ops::Shr::shr };
-    let result = shift(value.abs(), count.abs());
-    if value >= 0 {
-        result
-    } else {
-        -result
+fn ash(value: i64, count: i64) -> Result<i64> {
+    if count < 0 {
+        // Arithmetic shift, so negative values round towards negative infinity
+        return Ok(value >> count.unsigned_abs().min(63));
+    }
+    let shifted = u32::try_from(count).ok().and_then(|count| value.checked_shl(count));
+    match shifted {
+        Some(result) if result >> count == value => Ok(checked_fixnum(result)?),
+        _ if value == 0 => Ok(0),
+        _ => bail!(ArgRangeError::new(&[value, count])),
     }
 }
 
@@ -317,8 +557,18 @@ pub(crate) fn aset<'ob>(
     array: Object<'ob>,
     idx: usize,
     newlet: Object<'ob>,
+    cx: &'ob Context,
 ) -> Result<Object<'ob>> {
     match array.untag() {
+        ObjectType::String(string) => {
+            let chr = int_to_char(newlet.try_into()?)?;
+            string.set_char(idx, chr, cx)?;
+            Ok(newlet)
+        }
+        ObjectType::Record(record) if is_char_table(record) => {
+            CharTable::try_from(array)?.set(u32::try_from(idx)?, newlet);
+            Ok(newlet)
+        }
         ObjectType::Vec(vec) => {
             let vec = vec.try_mut()?;
             if idx < vec.len() {
@@ -346,6 +596,9 @@ pub(crate) fn aset<'ob>(
 #[defun]
 pub(crate) fn aref<'ob>(array: Object<'ob>, idx: usize, cx: &'ob Context) -> Result<Object<'ob>> {
     match array.untag() {
+        ObjectType::Record(record) if is_char_table(record) => {
+            Ok(CharTable::try_from(array)?.get(u32::try_from(idx)?))
+        }
         ObjectType::Vec(vec) => match vec.get(idx) {
             Some(x) => Ok(x.get()),
             None => {
@@ -378,6 +631,13 @@ pub(crate) fn aref<'ob>(array: Object<'ob>, idx: usize, cx: &'ob Context) -> Res
             Some(x) => Ok(x),
             None => Err(anyhow!("index {idx} is out of bounds")),
         },
+        // The slots of an interpreted function, as in Emacs
+        ObjectType::Closure(closure) => match idx {
+            0 => Ok(closure.arg_list()),
+            1 => Ok(closure.body()),
+            2 => Ok(closure.env_list(cx)),
+            _ => Err(anyhow!("index {idx} is out of bounds")),
+        },
         x => Err(TypeError::new(Type::Sequence, x).into()),
     }
 }
@@ -396,6 +656,8 @@ fn type_of(object: Object) -> Object {
         ObjectType::String(_) | ObjectType::ByteString(_) => sym::STRING.into(),
         ObjectType::SubrFn(_) => sym::SUBR.into(),
         ObjectType::Buffer(_) => sym::BUFFER.into(),
+        ObjectType::Closure(_) => sym::INTERPRETED_FUNCTION.into(),
+        ObjectType::StringStream(_) => sym::STRING_OUTPUT_STREAM.into(),
     }
 }
 
@@ -412,10 +674,9 @@ pub(crate) fn indirect_function<'ob>(object: Object<'ob>, cx: &'ob Context) -> O
 
 #[defun]
 pub(crate) fn provide<'ob>(feature: Symbol<'ob>, _subfeatures: Option<&Cons>) -> Symbol<'ob> {
-    let mut features = features().lock().unwrap();
     // TODO: SYMBOL - need to trace this
     let feat = unsafe { feature.with_lifetime() };
-    features.insert(feat);
+    with_features(|features| features.insert(feat));
     feature
 }
 
@@ -484,14 +745,49 @@ fn symbol_with_pos_p(_sym: Object) -> bool {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_aset_string() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let chr = |c: char| Object::from(i64::from(u32::from(c)));
+        let string = cx.add("héllo".to_owned());
+        aset(string, 0, chr('j'), cx).unwrap();
+        assert_eq!(string, cx.add("jéllo"));
+        aset(string, 1, chr('e'), cx).unwrap();
+        assert_eq!(string, cx.add("jello"));
+        aset(string, 4, chr('😀'), cx).unwrap();
+        assert_eq!(string, cx.add("jell😀"));
+        assert_eq!(aref(string, 4, cx).unwrap(), chr('😀'));
+        assert!(aset(string, 5, chr('a'), cx).is_err());
+        assert!(aset(string, 0, Object::from(-1), cx).is_err());
+    }
 
     #[test]
     fn test_ash() {
-        assert_eq!(ash(4, 1), 8);
-        assert_eq!(ash(4, -1), 2);
-        assert_eq!(ash(-8, -1), -4);
-        assert_eq!(ash(256, -8), 1);
-        assert_eq!(ash(-8, 1), -16);
+        assert_eq!(ash(4, 1).unwrap(), 8);
+        assert_eq!(ash(4, -1).unwrap(), 2);
+        assert_eq!(ash(-8, -1).unwrap(), -4);
+        assert_eq!(ash(256, -8).unwrap(), 1);
+        assert_eq!(ash(-8, 1).unwrap(), -16);
+        assert_eq!(ash(-7, -1).unwrap(), -4);
+        assert_eq!(ash(-1, -100).unwrap(), -1);
+        assert_eq!(ash(5, -100).unwrap(), 0);
+        assert_eq!(ash(0, 100).unwrap(), 0);
+        assert!(ash(1, 60).is_err());
+        assert!(ash(1, 64).is_err());
+        assert!(ash(-1, 200).is_err());
+    }
+
+    #[test]
+    fn test_string_to_number() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        assert_eq!(string_to_number("ff", Some(16), cx).unwrap(), 255);
+        assert_eq!(string_to_number("1.5", None, cx).unwrap(), 1.5);
+        assert!(string_to_number("10", Some(1), cx).is_err());
+        assert!(string_to_number("10", Some(40), cx).is_err());
     }
 }
 
@@ -499,6 +795,11 @@ defsym!(MANY);
 defsym!(INTEGER);
 defsym!(SYMBOL);
 defsym!(COMPILED_FUNCTION);
+defsym!(INTERPRETED_FUNCTION);
 defsym!(HASH_TABLE);
 defsym!(BUFFER);
 defsym!(SUBR);
+defsym!(UNLET);
+defsym!(WATCHERS);
+defvar!(MOST_POSITIVE_FIXNUM, crate::core::object::MAX_FIXNUM);
+defvar!(MOST_NEGATIVE_FIXNUM, crate::core::object::MIN_FIXNUM);
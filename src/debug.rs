@@ -26,3 +26,55 @@ macro_rules! debug {
         }
     }}
 }
+
+/// The global allocator for tests. It passes every allocation on to the
+/// allocator rune normally uses, and only counts the allocations a thread
+/// makes inside [`count_allocations`], so tests can check that a code path
+/// doesn't allocate.
+#[cfg(test)]
+pub(crate) struct CountingAllocator;
+
+#[cfg(all(test, not(target_env = "msvc"), not(miri)))]
+static INNER: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+#[cfg(all(test, any(target_env = "msvc", miri)))]
+static INNER: std::alloc::System = std::alloc::System;
+
+#[cfg(test)]
+thread_local! {
+    /// The allocations made inside `count_allocations`, or `None` when they
+    /// aren't being counted.
+    static ALLOCATIONS: std::cell::Cell<Option<usize>> = const { std::cell::Cell::new(None) };
+}
+
+#[cfg(test)]
+fn record_allocation() {
+    // the thread local may already be destroyed when a thread exits
+    let _ = ALLOCATIONS.try_with(|x| x.set(x.get().map(|count| count + 1)));
+}
+
+#[cfg(test)]
+unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        record_allocation();
+        std::alloc::GlobalAlloc::alloc(&INNER, layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        std::alloc::GlobalAlloc::dealloc(&INNER, ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: std::alloc::Layout, new_size: usize) -> *mut u8 {
+        record_allocation();
+        std::alloc::GlobalAlloc::realloc(&INNER, ptr, layout, new_size)
+    }
+}
+
+/// Run `f` and return its value along with the number of allocations the
+/// current thread made while it ran.
+#[cfg(test)]
+pub(crate) fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    ALLOCATIONS.set(Some(0));
+    let value = f();
+    let count = ALLOCATIONS.replace(None).unwrap_or(0);
+    (value, count)
+}
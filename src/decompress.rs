@@ -0,0 +1,117 @@
+//! Decompressing gzip and zlib data.
+use crate::core::{
+    env::Env,
+    error::ArgRangeError,
+    gc::{Context, Rt},
+    object::{Object, NIL, TRUE},
+};
+use anyhow::{bail, ensure, Context as _, Result};
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use rune_macros::defun;
+use std::io::Read;
+
+/// Inflate gzip or zlib data, detecting the format from its header. Returns
+/// the decompressed data along with the error that stopped decompression, if
+/// any.
+fn inflate(bytes: &[u8]) -> (Vec<u8>, std::io::Result<usize>) {
+    let mut out = Vec::new();
+    let result = if bytes.starts_with(&[0x1F, 0x8B]) {
+        MultiGzDecoder::new(bytes).read_to_end(&mut out)
+    } else {
+        ZlibDecoder::new(bytes).read_to_end(&mut out)
+    };
+    (out, result)
+}
+
+pub(crate) fn decompress(bytes: &[u8]) -> Result<Vec<u8>> {
+    let (out, result) = inflate(bytes);
+    result.context("Error decompressing data")?;
+    Ok(out)
+}
+
+#[defun]
+fn zlib_available_p() -> bool {
+    true
+}
+
+#[defun]
+fn zlib_decompress_region<'ob>(
+    start: usize,
+    end: usize,
+    allow_partial: Option<()>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let (beg, end) = (start.min(end), start.max(end));
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    ensure!(end <= buffer.text.len_chars(), ArgRangeError::new(&[beg, end]));
+    // Raw bytes are stored in the buffer as the characters up to 255
+    let bytes = buffer
+        .text
+        .read(beg..end)
+        .chars()
+        .map(|chr| u8::try_from(u32::from(chr)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| anyhow::anyhow!("This function can be called only in unibyte buffers"))?;
+    let (out, result) = inflate(&bytes);
+    let value = match result {
+        Ok(_) => TRUE,
+        Err(_) if allow_partial.is_some() => cx.add(out.len()),
+        Err(_) => return Ok(NIL),
+    };
+    let text: String = out.into_iter().map(char::from).collect();
+    buffer.replace_region(beg, end, &text);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::{get_buffer_create, set_buffer};
+    use crate::core::gc::RootSet;
+    use flate2::{
+        write::{GzEncoder, ZlibEncoder},
+        Compression,
+    };
+    use rune_core::macros::root;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompress() {
+        let text = b"(setq foo 1)\n";
+        assert_eq!(decompress(&gzip(text)).unwrap(), text);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(text).unwrap();
+        assert_eq!(decompress(&encoder.finish().unwrap()).unwrap(), text);
+        assert!(decompress(b"not compressed").is_err());
+    }
+
+    #[test]
+    fn test_zlib_decompress_region() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let buffer =
+            get_buffer_create(cx.add("test_zlib_decompress_region"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        let compressed: String = gzip(b"hello world").into_iter().map(char::from).collect();
+        let len = compressed.chars().count();
+        env.current_buffer
+            .as_mut()
+            .unwrap()
+            .insert(cx.add(format!("<{compressed}>")))
+            .unwrap();
+
+        // Truncated data leaves the buffer alone
+        assert_eq!(zlib_decompress_region(1, len - 4, None, env, cx).unwrap(), NIL);
+        assert_eq!(zlib_decompress_region(1, len + 1, None, env, cx).unwrap(), TRUE);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "<hello world>");
+        assert!(zlib_decompress_region(0, 100, None, env, cx).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! Documentation strings. To avoid keeping every docstring in memory, the
+//! docstrings of builtin functions and variables, and of the functions in the
+//! lisp files, are written to a `DOC` file at build time. `Snarf-documentation`
+//! records where each docstring starts in the file, and `documentation` reads
+//! it from there when it is asked for.
+use crate::core::{
+    cons::Cons,
+    env::{intern, interned_symbols, sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, FunctionType, Object, ObjectType, Symbol, NIL},
+};
+use anyhow::{bail, Context as _, Result};
+use rune_core::hashmap::HashMap;
+use rune_core::macros::{call, rebind, root};
+use rune_macros::defun;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// Separates the entries of the DOC file.
+const SEPARATOR: u8 = 0x1f;
+
+/// Offsets of the builtin function docstrings in the DOC file, by name.
+fn subr_docs() -> &'static Mutex<HashMap<&'static str, usize>> {
+    static SUBR_DOCS: OnceLock<Mutex<HashMap<&'static str, usize>>> = OnceLock::new();
+    SUBR_DOCS.get_or_init(Mutex::default)
+}
+
+struct Entry<'a> {
+    /// `F` for a function or `V` for a variable
+    kind: char,
+    name: &'a str,
+    offset: usize,
+    text: &'a [u8],
+}
+
+/// Split the contents of a DOC file into its entries. Each one is a separator,
+/// the kind and name on one line, and then the docstring.
+fn entries(contents: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    for entry in contents.split(|x| *x == SEPARATOR) {
+        let start = pos;
+        pos += entry.len() + 1;
+        let Some(newline) = entry.iter().position(|x| *x == b'\n') else { continue };
+        let Ok(header) = std::str::from_utf8(&entry[..newline]) else { continue };
+        let mut chars = header.chars();
+        let Some(kind) = chars.next() else { continue };
+        let offset = start + newline + 1;
+        entries.push(Entry { kind, name: chars.as_str(), offset, text: &entry[newline + 1..] });
+    }
+    entries
+}
+
+fn doc_file(env: &Rt<Env>, cx: &Context) -> Result<PathBuf> {
+    match env.var(sym::INTERNAL_DOC_FILE_NAME, cx).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::String(file)) => Ok(PathBuf::from(&**file)),
+        _ => bail!("Documentation file is not loaded"),
+    }
+}
+
+/// Read the docstring at `offset` in the DOC file, which runs until the next
+/// entry.
+fn read_doc_string(file: &Path, offset: usize) -> Result<String> {
+    let mut file = File::open(file)?;
+    file.seek(SeekFrom::Start(u64::try_from(offset)?))?;
+    let mut doc = Vec::new();
+    BufReader::new(file).read_until(SEPARATOR, &mut doc)?;
+    if doc.last() == Some(&SEPARATOR) {
+        doc.pop();
+    }
+    Ok(String::from_utf8(doc)?)
+}
+
+/// The cons whose car is the docstring of an interpreted function. A string
+/// that is the whole body is the return value, not a docstring, so it is not
+/// included.
+fn doc_cell(function: Function) -> Option<&Cons> {
+    let (body, skip) = match function.untag() {
+        FunctionType::Closure(closure) => (closure.body(), 0),
+        FunctionType::Cons(cons) => match cons.car().untag() {
+            ObjectType::Symbol(sym::MACRO) => return doc_cell(cons.cdr().try_into().ok()?),
+            ObjectType::Symbol(sym::LAMBDA) => (cons.cdr(), 1),
+            ObjectType::Symbol(sym::CLOSURE) => (cons.cdr(), 2),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let mut cell: &Cons = body.try_into().ok()?;
+    for _ in 0..skip {
+        cell = cell.cdr().try_into().ok()?;
+    }
+    (!cell.cdr().is_nil()).then_some(cell)
+}
+
+#[defun(name = "Snarf-documentation")]
+fn snarf_documentation(filename: &str, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let dir = match env.var(sym::DOC_DIRECTORY, cx) {
+        Some(dir) => dir.bind(cx).try_into()?,
+        None => "",
+    };
+    let path = Path::new(dir).join(filename);
+    let contents = std::fs::read(&path)
+        .with_context(|| format!("Cannot open doc string file \"{}\"", path.display()))?;
+    let mut subrs = subr_docs().lock().unwrap_or_else(PoisonError::into_inner);
+    for entry in entries(&contents) {
+        // Don't intern the names of functions that were never loaded
+        if interned_symbols().get(entry.name).is_none() {
+            continue;
+        }
+        let symbol = intern(entry.name, cx);
+        let offset = cx.add(i64::try_from(entry.offset)?);
+        match entry.kind {
+            'V' => env.set_prop(symbol, sym::VARIABLE_DOCUMENTATION, offset),
+            'F' => {
+                let Some(function) = symbol.func(cx) else { continue };
+                if let FunctionType::SubrFn(subr) = function.untag() {
+                    subrs.insert(subr.name, entry.offset);
+                } else if let Some(cell) = doc_cell(function) {
+                    // Only drop the docstring if the definition that was
+                    // loaded is the one the DOC file describes
+                    let same = matches!(cell.car().untag(), ObjectType::String(doc)
+                        if doc.as_bytes() == entry.text);
+                    if same {
+                        _ = cell.set_car(offset);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let path = path.to_string_lossy().into_owned();
+    env.set_default(sym::INTERNAL_DOC_FILE_NAME, cx.add(path))?;
+    Ok(())
+}
+
+/// Turn the value of a documentation property into a docstring. An integer is
+/// an offset into the DOC file and other non-string values are evaluated.
+fn doc_value<'ob>(
+    value: &Rto<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    match value.bind(cx).untag() {
+        ObjectType::String(_) | ObjectType::NIL => Ok(value.bind(cx)),
+        ObjectType::Int(offset) => {
+            let doc = read_doc_string(&doc_file(env, cx)?, usize::try_from(offset)?)?;
+            Ok(cx.add(doc))
+        }
+        _ => Ok(rebind!(crate::interpreter::eval(value, None, env, cx)?)),
+    }
+}
+
+/// The docstring stored in a function object.
+fn function_doc<'ob>(function: Function, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    if let FunctionType::SubrFn(subr) = function.untag() {
+        let offset = subr_docs()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(subr.name)
+            .copied();
+        return match offset {
+            Some(offset) => Ok(cx.add(read_doc_string(&doc_file(env, cx)?, offset)?)),
+            None => Ok(NIL),
+        };
+    }
+    let Some(cell) = doc_cell(function) else { return Ok(NIL) };
+    match cell.car().untag() {
+        ObjectType::String(_) => Ok(cx.bind(cell.car())),
+        ObjectType::Int(offset) => {
+            let doc = read_doc_string(&doc_file(env, cx)?, usize::try_from(offset)?)?;
+            Ok(cx.add(doc))
+        }
+        _ => Ok(NIL),
+    }
+}
+
+/// Substitute key bindings into `doc` unless `raw` is set. This is left to
+/// `substitute-command-keys` once help.el defines it.
+fn substitute<'ob>(
+    doc: &Rto<Object>,
+    raw: Option<()>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    if raw.is_none() && matches!(doc.bind(cx).untag(), ObjectType::String(_)) {
+        if let Some(func) = sym::SUBSTITUTE_COMMAND_KEYS.func(cx) {
+            root!(func, cx);
+            return Ok(rebind!(call!(func, doc; env, cx)?));
+        }
+    }
+    Ok(doc.bind(cx))
+}
+
+#[defun]
+pub(crate) fn documentation<'ob>(
+    function: &Rto<Object>,
+    raw: Option<()>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    if let ObjectType::Symbol(name) = function.bind(cx).untag() {
+        let prop = crate::data::get(name, sym::FUNCTION_DOCUMENTATION, env, cx);
+        if !prop.is_nil() {
+            root!(prop, cx);
+            let doc = rebind!(doc_value(prop, env, cx)?);
+            root!(doc, cx);
+            return substitute(doc, raw, env, cx);
+        }
+    }
+    let func: Function = match function.bind(cx).untag() {
+        ObjectType::Symbol(name) => match name.follow_indirect(cx) {
+            Some(func) => func,
+            None => bail!("Symbol's function definition is void: {name}"),
+        },
+        other => other.try_into()?,
+    };
+    let doc = function_doc(func, env, cx)?;
+    root!(doc, cx);
+    substitute(doc, raw, env, cx)
+}
+
+#[defun]
+pub(crate) fn documentation_property<'ob>(
+    symbol: Symbol,
+    prop: Symbol,
+    raw: Option<()>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let value = crate::data::get(symbol, prop, env, cx);
+    root!(value, cx);
+    let doc = rebind!(doc_value(value, env, cx)?);
+    root!(doc, cx);
+    substitute(doc, raw, env, cx)
+}
+
+defvar!(DOC_DIRECTORY, concat!(env!("OUT_DIR"), "/"));
+defvar!(INTERNAL_DOC_FILE_NAME);
+defsym!(VARIABLE_DOCUMENTATION);
+defsym!(FUNCTION_DOCUMENTATION);
+defsym!(SUBSTITUTE_COMMAND_KEYS);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    fn eval<'ob>(code: &str, env: &mut Rt<Env>, cx: &'ob mut Context) -> Object<'ob> {
+        let obj = crate::reader::read(code, cx).unwrap().0;
+        root!(obj, cx);
+        rebind!(crate::interpreter::eval(obj, None, env, cx).unwrap())
+    }
+
+    #[test]
+    fn test_entries() {
+        let contents = b"\x1fFcar\nReturn the car.\x1fVload-path\nDirectories\nto search.";
+        let entries = entries(contents);
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].kind, entries[0].name), ('F', "car"));
+        assert_eq!(entries[0].text, b"Return the car.");
+        assert_eq!((entries[1].kind, entries[1].name), ('V', "load-path"));
+        assert_eq!(&contents[entries[1].offset..], b"Directories\nto search.");
+    }
+
+    #[test]
+    fn test_documentation() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let dir = std::env::temp_dir().join(format!("rune-doc-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let contents = "\x1fFcar\nReturn the car of LIST.\x1fVload-path\nDirectories to search.\
+                        \x1fFdoc-test-fn\nAdd one to X.\x1fFdoc-test-other\nAn old docstring.";
+        std::fs::write(dir.join("DOC"), contents).unwrap();
+        let dir_name = format!("{}/", dir.to_string_lossy());
+        env.set_default(sym::DOC_DIRECTORY, cx.add(dir_name)).unwrap();
+        eval("(defalias 'doc-test-fn #'(lambda (x) \"Add one to X.\" (1+ x)))", env, cx);
+        eval("(defalias 'doc-test-other #'(lambda () \"A new docstring.\" nil))", env, cx);
+        snarf_documentation("DOC", env, cx).unwrap();
+
+        assert_eq!(eval("(documentation 'car)", env, cx), cx.add("Return the car of LIST."));
+        assert_eq!(eval("(documentation 'doc-test-fn)", env, cx), cx.add("Add one to X."));
+        assert_eq!(eval("(doc-test-fn 1)", env, cx), 2);
+        // A definition that doesn't match the DOC file keeps its own docstring
+        assert_eq!(eval("(documentation 'doc-test-other)", env, cx), cx.add("A new docstring."));
+        let doc = eval("(documentation-property 'load-path 'variable-documentation)", env, cx);
+        assert_eq!(doc, cx.add("Directories to search."));
+        eval("(put 'doc-test-fn 'function-documentation '(concat \"Computed\"))", env, cx);
+        assert_eq!(eval("(documentation 'doc-test-fn)", env, cx), cx.add("Computed"));
+        assert_eq!(eval("(documentation 'cdr)", env, cx), NIL);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,26 +1,61 @@
 //! Buffer editing utilities.
 use crate::core::{
-    env::{ArgSlice, Env},
+    env::{sym, ArgSlice, Env},
     gc::{Context, Rt},
-    object::{Object, ObjectType},
+    object::{Object, ObjectType, Symbol},
 };
 use anyhow::{bail, ensure, Result};
 use rune_macros::defun;
 use std::{fmt::Write as _, io::Write};
+use text_buffer::Buffer as TextBuffer;
 
+/// Display a message. In batch mode it is written to stderr, otherwise it is
+/// printed and logged in the `*Messages*` buffer.
 #[defun]
-fn message(format_string: &str, args: &[Object]) -> Result<String> {
-    let message = format(format_string, args)?;
+fn message(
+    format_string: &str,
+    args: &[Object],
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<String> {
+    let message = format(format_string, args, env, cx)?;
+    if crate::emacs::noninteractive(env, cx) {
+        eprintln!("{message}");
+        return Ok(message);
+    }
     println!("MESSAGE: {message}");
     std::io::stdout().flush()?;
+    log_message(&message, env, cx)?;
     Ok(message)
 }
 
+/// Add `message` to the end of the `*Messages*` buffer.
+fn log_message(message: &str, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let buffer = crate::buffer::get_buffer_create(cx.add("*Messages*"), None, cx)?;
+    let ObjectType::Buffer(buffer) = buffer.untag() else { unreachable!() };
+    env.with_buffer_mut(Some(buffer), |b| {
+        let end = b.text.len_chars();
+        let point = b.text.cursor().chars();
+        b.text.set_cursor(end);
+        b.text.insert(message);
+        b.text.insert("\n");
+        if point != end {
+            b.text.set_cursor(point);
+        }
+    });
+    Ok(())
+}
+
 defvar!(MESSAGE_NAME);
 defvar!(MESSAGE_TYPE, "new message");
 
 #[defun]
-fn format(string: &str, objects: &[Object]) -> Result<String> {
+fn format(string: &str, objects: &[Object], env: &Rt<Env>, cx: &Context) -> Result<String> {
+    let _settings = crate::print::load_print_settings(env, cx);
+    format_objects(string, objects)
+}
+
+fn format_objects(string: &str, objects: &[Object]) -> Result<String> {
     let mut result = String::new();
     let mut arguments = objects.iter();
     let mut remaining = string;
@@ -62,27 +97,114 @@ fn format(string: &str, objects: &[Object]) -> Result<String> {
     Ok(result)
 }
 
+/// How grave accents and apostrophes are displayed in messages. See
+/// `text-quoting-style`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuotingStyle {
+    /// ‘like this’
+    Curve,
+    /// 'like this'
+    Straight,
+    /// `like this'
+    Grave,
+}
+
+impl QuotingStyle {
+    fn get(env: &Rt<Env>, cx: &Context) -> Self {
+        match env.var(sym::TEXT_QUOTING_STYLE, cx).map(|x| x.bind(cx)) {
+            Some(style) if style == sym::STRAIGHT => Self::Straight,
+            Some(style) if style == sym::GRAVE => Self::Grave,
+            _ => Self::Curve,
+        }
+    }
+
+    fn translate(self, c: char) -> char {
+        match (self, c) {
+            (Self::Curve, '`') => '‘',
+            (Self::Curve, '\'') => '’',
+            (Self::Straight, '`') => '\'',
+            _ => c,
+        }
+    }
+}
+
+#[defun]
+fn text_quoting_style(env: &Rt<Env>, cx: &Context) -> Symbol<'static> {
+    match QuotingStyle::get(env, cx) {
+        QuotingStyle::Curve => sym::CURVE,
+        QuotingStyle::Straight => sym::STRAIGHT,
+        QuotingStyle::Grave => sym::GRAVE,
+    }
+}
+
+/// Like `format`, but quotes in `string` are translated according to
+/// `text-quoting-style`. Quotes in the arguments are left alone.
 #[defun]
-fn format_message(string: &str, objects: &[Object]) -> Result<String> {
-    let formatted = format(string, objects)?;
-    // TODO: implement support for `text-quoting-style`.
-    Ok(formatted
-        .chars()
-        .map(|c| if matches!(c, '`' | '\'') { '"' } else { c })
-        .collect())
+fn format_message(string: &str, objects: &[Object], env: &Rt<Env>, cx: &Context) -> Result<String> {
+    let style = QuotingStyle::get(env, cx);
+    let string: String = string.chars().map(|c| style.translate(c)).collect();
+    format(&string, objects, env, cx)
 }
 
+/// Substitute key descriptions and translate quotes in `string`. There are no
+/// keymaps yet, so `\\[COMMAND]` is always shown as `M-x COMMAND` and keymap
+/// descriptions are dropped.
+#[defun]
+fn substitute_command_keys(
+    string: &str,
+    _no_face: Option<()>,
+    _include_menus: Option<()>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<String> {
+    let style = QuotingStyle::get(env, cx);
+    let mut result = String::with_capacity(string.len());
+    let mut chars = string.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(style.translate(c));
+            continue;
+        }
+        match chars.peek() {
+            // \\= quotes the following character
+            Some('=') => {
+                chars.next();
+                result.extend(chars.next());
+            }
+            Some('[') => {
+                chars.next();
+                let command: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                write!(result, "M-x {command}")?;
+            }
+            Some(open @ ('<' | '{')) => {
+                let close = if *open == '<' { '>' } else { '}' };
+                chars.next();
+                chars.by_ref().take_while(|c| *c != close).for_each(drop);
+            }
+            // \\`KEY' inserts KEY verbatim
+            Some('`') => {
+                chars.next();
+                result.extend(chars.by_ref().take_while(|c| *c != '\''));
+            }
+            _ => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+defvar!(TEXT_QUOTING_STYLE);
+defsym!(CURVE);
+defsym!(STRAIGHT);
+defsym!(GRAVE);
+
 #[defun]
 fn string_to_char(string: &str) -> char {
     string.chars().next().unwrap_or('\0')
 }
 
 #[defun]
-fn char_to_string(chr: u64) -> Result<String> {
-    let Some(chr) = std::char::from_u32(u32::try_from(chr)?) else {
-        bail!("Invalid character")
-    };
-    Ok(format!("{chr}"))
+fn char_to_string(chr: char) -> String {
+    chr.to_string()
 }
 
 // TODO: this should not throw and error. Buffer will always be present.
@@ -133,12 +255,116 @@ fn delete_region(start: usize, end: usize, env: &mut Rt<Env>) -> Result<()> {
 #[defun]
 fn bolp(env: &Rt<Env>) -> bool {
     env.with_buffer(None, |b| {
-        let chars = b.text.cursor().chars();
-        chars == 0 || b.text.char_at(chars - 1).unwrap() == '\n'
+        let point = b.text.cursor().chars();
+        b.text.line_start(point) == point
     })
     .unwrap_or(false)
 }
 
+#[defun]
+fn eolp(env: &Rt<Env>) -> bool {
+    env.with_buffer(None, |b| {
+        let point = b.text.cursor().chars();
+        b.text.line_end(point) == point
+    })
+    .unwrap_or(false)
+}
+
+/// The start of the line `n - 1` lines after the one point is on. This
+/// stops at the first or last line of the buffer.
+fn nth_line_start(text: &TextBuffer, n: i64) -> usize {
+    let mut pos = text.line_start(text.cursor().chars());
+    for _ in 1..n {
+        let end = text.line_end(pos);
+        if end == text.len_chars() {
+            return end;
+        }
+        pos = end + 1;
+    }
+    for _ in n..1 {
+        if pos == 0 {
+            break;
+        }
+        pos = text.line_start(pos - 1);
+    }
+    pos
+}
+
+/// Return the position of the start of the current line. With `n`, move
+/// forward `n - 1` lines first.
+#[defun]
+fn line_beginning_position(n: Option<i64>, env: &Rt<Env>) -> usize {
+    env.with_buffer(None, |b| nth_line_start(&b.text, n.unwrap_or(1))).unwrap_or(0)
+}
+
+/// Return the position of the end of the current line. With `n`, move
+/// forward `n - 1` lines first.
+#[defun]
+fn line_end_position(n: Option<i64>, env: &Rt<Env>) -> usize {
+    env.with_buffer(None, |b| b.text.line_end(nth_line_start(&b.text, n.unwrap_or(1))))
+        .unwrap_or(0)
+}
+
+/// Return the number of lines between `start` and `end`. This is the number
+/// of newlines between them, plus one if the text doesn't end at the start
+/// of a line.
+#[defun]
+fn count_lines(
+    start: usize,
+    end: usize,
+    _ignore_invisible_lines: Option<Object>,
+    env: &Rt<Env>,
+) -> Result<usize> {
+    let (start, end) = (start.min(end), start.max(end));
+    let count = env.with_buffer(None, |b| {
+        let newlines = b.text.count_newlines(start, end);
+        let partial = start != end && b.text.line_start(end) != end;
+        newlines + usize::from(partial)
+    });
+    let Some(count) = count else { bail!("No current buffer") };
+    Ok(count)
+}
+
+/// Skip over `count` words in `chars`, along with the characters before
+/// each one. Return the number of characters skipped and whether all the
+/// words were found.
+fn skip_words(chars: impl Iterator<Item = char>, count: u64) -> (usize, bool) {
+    let mut chars = chars.peekable();
+    let mut skipped = 0;
+    for _ in 0..count {
+        while chars.next_if(|x| !x.is_alphanumeric()).is_some() {
+            skipped += 1;
+        }
+        if chars.peek().is_none() {
+            return (skipped, false);
+        }
+        while chars.next_if(|x| x.is_alphanumeric()).is_some() {
+            skipped += 1;
+        }
+    }
+    (skipped, true)
+}
+
+/// Move point forward `arg` words, or backward if `arg` is negative. Return
+/// t if all the words were moved over, or nil if the edge of the buffer was
+/// reached first. There are no syntax tables yet, so a word is a run of
+/// alphanumeric characters.
+#[defun]
+fn forward_word(arg: Option<i64>, env: &mut Rt<Env>) -> Result<bool> {
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    let arg = arg.unwrap_or(1);
+    let point = buffer.text.cursor().chars();
+    let (pos, found) = if arg < 0 {
+        let (skipped, found) = skip_words(buffer.text.chars_before(point), arg.unsigned_abs());
+        (point - skipped, found)
+    } else {
+        let (skipped, found) = skip_words(buffer.text.chars_at(point), arg.unsigned_abs());
+        (point + skipped, found)
+    };
+    buffer.text.set_cursor(pos);
+    Ok(found)
+}
+
 #[defun]
 fn point(env: &Rt<Env>) -> usize {
     env.with_buffer(None, |b| b.text.cursor().chars()).unwrap_or(0)
@@ -165,18 +391,56 @@ mod test {
 
     #[test]
     fn test_format() {
-        assert_eq!(&format("%s", &[1.into()]).unwrap(), "1");
-        assert_eq!(&format("foo-%s", &[2.into()]).unwrap(), "foo-2");
-        assert_eq!(&format("%%", &[]).unwrap(), "%");
-        assert_eq!(&format("_%%_", &[]).unwrap(), "_%_");
-        assert_eq!(&format("foo-%s %s", &[3.into(), 4.into()]).unwrap(), "foo-3 4");
+        assert_eq!(&format_objects("%s", &[1.into()]).unwrap(), "1");
+        assert_eq!(&format_objects("foo-%s", &[2.into()]).unwrap(), "foo-2");
+        assert_eq!(&format_objects("%%", &[]).unwrap(), "%");
+        assert_eq!(&format_objects("_%%_", &[]).unwrap(), "_%_");
+        assert_eq!(&format_objects("foo-%s %s", &[3.into(), 4.into()]).unwrap(), "foo-3 4");
         let sym = crate::core::env::sym::FUNCTION.into();
-        assert_eq!(&format("%s", &[sym]).unwrap(), "function");
+        assert_eq!(&format_objects("%s", &[sym]).unwrap(), "function");
 
-        assert!(&format("%s", &[]).is_err());
-        assert!(&format("%s", &[1.into(), 2.into()]).is_err());
+        assert!(&format_objects("%s", &[]).is_err());
+        assert!(&format_objects("%s", &[1.into(), 2.into()]).is_err());
 
-        assert!(format("`%s' %s%s%s", &[0.into(), 1.into(), 2.into(), 3.into()]).is_ok());
+        assert!(format_objects("`%s' %s%s%s", &[0.into(), 1.into(), 2.into(), 3.into()]).is_ok());
+    }
+
+    #[test]
+    fn test_format_message() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let arg = cx.add("`x'");
+        assert_eq!(&format_message("`%s'", &[arg], env, cx).unwrap(), "‘`x'’");
+        env.set_default(sym::TEXT_QUOTING_STYLE, sym::STRAIGHT.into()).unwrap();
+        assert_eq!(&format_message("`%s'", &[arg], env, cx).unwrap(), "'`x''");
+        env.set_default(sym::TEXT_QUOTING_STYLE, sym::GRAVE.into()).unwrap();
+        assert_eq!(&format_message("`%s'", &[arg], env, cx).unwrap(), "``x''");
+
+        let quoted = crate::reader::read("'x", cx).unwrap().0;
+        env.set_default(sym::PRINT_QUOTED, NIL).unwrap();
+        assert_eq!(&format_message("%s", &[quoted], env, cx).unwrap(), "(quote x)");
+        // The setting only applies while printing
+        assert_eq!(quoted.to_string(), "'x");
+    }
+
+    #[test]
+    fn test_substitute_command_keys() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let subst = |s: &str, env: &Rt<Env>, cx: &Context| {
+            substitute_command_keys(s, None, None, env, cx).unwrap()
+        };
+        assert_eq!(subst("Use `foo'", env, cx), "Use ‘foo’");
+        assert_eq!(subst("Type \\[save-buffer] to save", env, cx), "Type M-x save-buffer to save");
+        assert_eq!(subst("\\<foo-map>Keys:\\{foo-map}", env, cx), "Keys:");
+        assert_eq!(subst("\\=\\[x] \\=`", env, cx), "\\[x] `");
+        assert_eq!(subst("Press \\`C-c'", env, cx), "Press C-c");
+        env.set_default(sym::TEXT_QUOTING_STYLE, sym::GRAVE.into()).unwrap();
+        assert_eq!(subst("Use `foo'", env, cx), "Use `foo'");
     }
 
     #[test]
@@ -187,11 +451,11 @@ mod test {
         let buffer = get_buffer_create(cx.add("test_insert"), Some(NIL), cx).unwrap();
         set_buffer(buffer, env, cx).unwrap();
         cx.garbage_collect(true);
-        env.stack.push(104);
-        env.stack.push(101);
-        env.stack.push(108);
-        env.stack.push(108);
-        env.stack.push(111);
+        env.stack.push(104).unwrap();
+        env.stack.push(101).unwrap();
+        env.stack.push(108).unwrap();
+        env.stack.push(108).unwrap();
+        env.stack.push(111).unwrap();
         insert(ArgSlice::new(5), env, cx).unwrap();
         assert_eq!(env.current_buffer.as_ref().unwrap(), "hello");
     }
@@ -204,12 +468,58 @@ mod test {
         let buffer = get_buffer_create(cx.add("test_delete_region"), Some(NIL), cx).unwrap();
         set_buffer(buffer, env, cx).unwrap();
         cx.garbage_collect(true);
-        env.stack.push(cx.add("hello"));
-        env.stack.push(cx.add(" world"));
+        env.stack.push(cx.add("hello")).unwrap();
+        env.stack.push(cx.add(" world")).unwrap();
         insert(ArgSlice::new(2), env, cx).unwrap();
 
         assert_eq!(env.current_buffer.as_ref().unwrap(), "hello world");
         delete_region(1, 3, env).unwrap();
         assert_eq!(env.current_buffer.as_ref().unwrap(), "hlo world");
     }
+
+    #[test]
+    fn test_lines() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let buffer = get_buffer_create(cx.add("test_lines"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        env.current_buffer.as_mut().unwrap().insert(cx.add("one\ntwo\n\nfour")).unwrap();
+        assert_eq!(count_lines(0, 13, None, env).unwrap(), 4);
+        assert_eq!(count_lines(4, 8, None, env).unwrap(), 1);
+        assert_eq!(count_lines(5, 5, None, env).unwrap(), 0);
+
+        goto_char(5, env).unwrap();
+        assert!(!bolp(env) && !eolp(env));
+        assert_eq!(line_beginning_position(None, env), 4);
+        assert_eq!(line_end_position(None, env), 7);
+        assert_eq!(line_beginning_position(Some(3), env), 9);
+        assert_eq!(line_end_position(Some(3), env), 13);
+        assert_eq!(line_beginning_position(Some(0), env), 0);
+        assert_eq!(line_beginning_position(Some(-5), env), 0);
+        assert_eq!(line_end_position(Some(10), env), 13);
+        goto_char(8, env).unwrap();
+        assert!(bolp(env) && eolp(env));
+    }
+
+    #[test]
+    fn test_forward_word() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let buffer = get_buffer_create(cx.add("test_forward_word"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        env.current_buffer.as_mut().unwrap().insert(cx.add("föo, bar  baz.")).unwrap();
+        goto_char(0, env).unwrap();
+        assert!(forward_word(None, env).unwrap());
+        assert_eq!(point(env), 3);
+        assert!(forward_word(Some(2), env).unwrap());
+        assert_eq!(point(env), 13);
+        assert!(forward_word(Some(-1), env).unwrap());
+        assert_eq!(point(env), 10);
+        assert!(!forward_word(Some(-3), env).unwrap());
+        assert_eq!(point(env), 0);
+        assert!(!forward_word(Some(4), env).unwrap());
+        assert_eq!(point(env), 14);
+    }
 }
@@ -1,8 +1,128 @@
 //! The Emacs environment and runtime.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Object, ObjectType, Symbol, NIL},
+};
+use crate::timefns::{DAY_NAMES, MONTH_NAMES};
+use anyhow::{bail, Result};
+use rune_core::macros::{call, root};
 use rune_macros::defun;
+use std::io::{self, Write};
 
+/// Whether rune is running in batch mode, without a user to interact with.
+pub(crate) fn noninteractive(env: &Rt<Env>, cx: &Context) -> bool {
+    env.vars.get(sym::NONINTERACTIVE).map_or(true, |x| !x.bind(cx).is_nil())
+}
+
+/// The names of buffers visiting files that have unsaved changes.
+fn unsaved_buffers(env: &Rt<Env>, cx: &Context) -> Vec<String> {
+    let mut unsaved = Vec::new();
+    for buffer in crate::buffer::live_buffers(env) {
+        let visiting = env.local_var(sym::BUFFER_FILE_NAME, buffer).is_some_and(|x| {
+            let file: Object = x.bind(cx);
+            !file.is_nil()
+        });
+        if visiting {
+            if let Some((name, true)) =
+                env.with_buffer(Some(buffer), |b| (b.name.clone(), b.modified))
+            {
+                unsaved.push(name);
+            }
+        }
+    }
+    unsaved.sort();
+    unsaved
+}
+
+fn run_kill_emacs_hook(env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    if let Some(func) = sym::RUN_HOOKS.func(cx) {
+        let hook: Object = sym::KILL_EMACS_HOOK.into();
+        root!(func, cx);
+        call!(func, hook; env, cx)?;
+    }
+    Ok(())
+}
+
+/// Run `kill-emacs-hook` and release the resources held outside of lisp, so
+/// that rune can exit.
+pub(crate) fn shutdown(env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    run_kill_emacs_hook(env, cx)?;
+    crate::process::kill_all();
+    crate::filenotify::remove_all_watches();
+    crate::server::stop();
+    io::stdout().flush()?;
+    Ok(())
+}
+
+#[defun]
+fn kill_emacs(
+    arg: Option<Object>,
+    _restart: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let code = match arg.map(|x| x.untag()) {
+        Some(ObjectType::Int(code)) => i32::try_from(code).unwrap_or(1),
+        _ => 0,
+    };
+    shutdown(env, cx)?;
+    std::process::exit(code)
+}
+
+/// Check that quitting won't lose unsaved changes. In batch mode unsaved
+/// buffers are an error, otherwise the user is asked.
+fn confirm_kill(env: &Rt<Env>, cx: &Context) -> Result<bool> {
+    let unsaved = unsaved_buffers(env, cx);
+    if unsaved.is_empty() {
+        return Ok(true);
+    }
+    if noninteractive(env, cx) {
+        bail!("Unsaved buffers: {}", unsaved.join(", "));
+    }
+    let prompt = format!("Modified buffers exist ({}); exit anyway? ", unsaved.join(", "));
+    crate::minibuf::yes_or_no_p(&prompt, env, cx)
+}
+
+#[defun]
+fn save_buffers_kill_emacs(force: Option<()>, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    if force.is_some() || confirm_kill(env, cx)? {
+        kill_emacs(None, None, env, cx)?;
+    }
+    Ok(())
+}
+
+/// The locale named by the environment for `category`, following the POSIX
+/// order of precedence.
+fn environment_locale(category: &str) -> String {
+    ["LC_ALL", category, "LANG"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|x| !x.is_empty()))
+        .unwrap_or_else(|| "C".to_owned())
+}
+
+/// The locale used for messages. This is `system-messages-locale` if it is a
+/// string, otherwise the locale named by the environment.
+#[defun]
+fn current_locale(env: &Rt<Env>, cx: &Context) -> String {
+    match env.var(sym::SYSTEM_MESSAGES_LOCALE, cx).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::String(locale)) => locale.to_string(),
+        _ => environment_locale("LC_MESSAGES"),
+    }
+}
+
+/// Information about the locale. Only the C locale is supported, so this
+/// describes it whatever the current locale is.
 #[defun]
-fn kill_emacs() {}
+fn locale_info<'ob>(item: Symbol, cx: &'ob Context) -> Object<'ob> {
+    let names = |names: &[&str]| cx.add(names.iter().map(|x| cx.add(*x)).collect::<Vec<_>>());
+    match item {
+        sym::CODESET => cx.add("UTF-8"),
+        sym::DAYS => names(&DAY_NAMES),
+        sym::MONTHS => names(&MONTH_NAMES),
+        _ => NIL,
+    }
+}
 
 defvar!(EMACS_VERSION, "27.1");
 defvar!(SYSTEM_TYPE, "darwin");
@@ -11,3 +131,71 @@ defvar!(COMMAND_LINE_ARGS, list![""]);
 defvar!(DEFAULT_DIRECTORY, "");
 defvar_bool!(NONINTERACTIVE, true);
 defvar!(AFTER_INIT_TIME);
+defvar!(KILL_EMACS_HOOK);
+defvar!(SYSTEM_MESSAGES_LOCALE);
+defvar!(SYSTEM_TIME_LOCALE);
+defsym!(CODESET);
+defsym!(DAYS);
+defsym!(MONTHS);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::{get_buffer_create, set_buffer};
+    use crate::core::gc::RootSet;
+    use rune_core::macros::rebind;
+
+    fn eval<'ob>(code: &str, env: &mut Rt<Env>, cx: &'ob mut Context) -> Object<'ob> {
+        let obj = crate::reader::read(code, cx).unwrap().0;
+        root!(obj, cx);
+        rebind!(crate::interpreter::eval(obj, None, env, cx).unwrap())
+    }
+
+    #[test]
+    fn test_locale() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        env.set_default(sym::SYSTEM_MESSAGES_LOCALE, cx.add("fr_FR.UTF-8")).unwrap();
+        assert_eq!(current_locale(env, cx), "fr_FR.UTF-8");
+        let month = eval("(aref (locale-info 'months) 1)", env, cx).to_string();
+        assert_eq!(month, "\"February\"");
+        assert_eq!(locale_info(sym::CODESET, cx), cx.add("UTF-8"));
+        let paper = crate::core::env::intern("paper", cx);
+        assert_eq!(locale_info(paper, cx), NIL);
+    }
+
+    #[test]
+    fn test_kill_emacs_hook() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        eval("(setq kill-emacs-hook (list (lambda () (setq kill-test-ran t))))", env, cx);
+        run_kill_emacs_hook(env, cx).unwrap();
+        assert_eq!(eval("kill-test-ran", env, cx), sym::TRUE);
+    }
+
+    #[test]
+    fn test_unsaved_buffers() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        env.set_default(sym::NONINTERACTIVE, sym::TRUE.into()).unwrap();
+        let buffer = get_buffer_create(cx.add("test_unsaved_buffers"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        env.current_buffer.as_mut().unwrap().insert(cx.add("text")).unwrap();
+        // Buffers that aren't visiting a file don't need saving
+        assert!(confirm_kill(env, cx).unwrap());
+
+        let ObjectType::Buffer(lisp_buffer) = buffer.untag() else { unreachable!() };
+        env.set_local_var(sym::BUFFER_FILE_NAME, cx.add("/tmp/unsaved"), lisp_buffer)
+            .unwrap();
+        assert!(unsaved_buffers(env, cx).contains(&"test_unsaved_buffers".to_owned()));
+        assert!(confirm_kill(env, cx).is_err());
+        eval("(set-buffer-modified-p nil)", env, cx);
+        assert!(!unsaved_buffers(env, cx).contains(&"test_unsaved_buffers".to_owned()));
+    }
+}
@@ -1,8 +1,8 @@
 //! Lisp evaluation primitives.
 use crate::core::cons::{Cons, ConsError};
-use crate::core::env::{sym, ArgSlice, CallFrame, Env};
-use crate::core::error::{ArgError, Type, TypeError};
-use crate::core::gc::{Rt, Rto};
+use crate::core::env::{intern, sym, ArgSlice, CallFrame, Env};
+use crate::core::error::{ArgError, ArgRangeError, Type, TypeError, VoidError};
+use crate::core::gc::{HeldObject, Rt, Rto};
 use crate::core::object::{display_slice, FnArgs, Function, LispString, ObjectType, Symbol, NIL};
 use crate::core::{
     gc::Context,
@@ -13,7 +13,7 @@ use crate::rooted_iter;
 use anyhow::{anyhow, bail, ensure, Result};
 use fallible_iterator::FallibleIterator;
 use fallible_streaming_iterator::FallibleStreamingIterator;
-use rune_core::macros::{bail_err, call, list, root};
+use rune_core::macros::{call, list, root};
 use rune_macros::defun;
 use std::fmt::{Display, Formatter};
 
@@ -25,8 +25,11 @@ pub(crate) struct EvalError {
 
 #[derive(Debug)]
 pub(crate) enum ErrorType {
-    Throw(u32),
-    Signal(u32),
+    /// The tag and value of a `throw`. They are held by the error, so they
+    /// are released whenever it is dropped, whether or not it was caught.
+    Throw(HeldObject, HeldObject),
+    /// The error symbol and data of a `signal`
+    Signal(HeldObject, HeldObject),
     Err(anyhow::Error),
 }
 
@@ -36,8 +39,8 @@ impl Display for EvalError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match &self.error {
             ErrorType::Err(e) => writeln!(f, "{e}")?,
-            ErrorType::Throw(_) => writeln!(f, "No catch for throw")?,
-            ErrorType::Signal(_) => writeln!(f, "Signal")?,
+            ErrorType::Throw(..) => writeln!(f, "No catch for throw")?,
+            ErrorType::Signal(..) => writeln!(f, "Signal")?,
         }
         Ok(())
     }
@@ -48,15 +51,22 @@ impl EvalError {
         Self { backtrace: Vec::new(), error: ErrorType::Err(error) }
     }
 
-    pub(crate) fn signal(error_symbol: Object, data: Object, env: &mut Rt<Env>) -> Self {
-        Self {
-            backtrace: Vec::new(),
-            error: ErrorType::Signal(env.set_exception(error_symbol, data)),
-        }
+    pub(crate) fn signal(error_symbol: Object, data: Object) -> Self {
+        let error = ErrorType::Signal(HeldObject::new(error_symbol), HeldObject::new(data));
+        Self { backtrace: Vec::new(), error }
+    }
+
+    pub(crate) fn throw(tag: Object, data: Object) -> Self {
+        let error = ErrorType::Throw(HeldObject::new(tag), HeldObject::new(data));
+        Self { backtrace: Vec::new(), error }
     }
 
-    pub(crate) fn throw(tag: Object, data: Object, env: &mut Rt<Env>) -> Self {
-        Self { backtrace: Vec::new(), error: ErrorType::Throw(env.set_exception(tag, data)) }
+    /// The tag and value of this error if it is a `throw`.
+    pub(crate) fn thrown<'ob>(&self, cx: &'ob Context) -> Option<(Object<'ob>, Object<'ob>)> {
+        match &self.error {
+            ErrorType::Throw(tag, data) => Some((tag.get(cx)?, data.get(cx)?)),
+            _ => None,
+        }
     }
 
     pub(crate) fn new(error: impl Into<Self>) -> Self {
@@ -75,6 +85,70 @@ impl EvalError {
         self
     }
 
+    /// The signal that an error raised from Rust stands for, if it is one of
+    /// the kinds Emacs signals with a symbol. The data holds the offending
+    /// objects themselves.
+    fn rust_signal<'ob>(&self, cx: &'ob Context) -> Option<(Symbol<'ob>, Vec<Object<'ob>>)> {
+        let ErrorType::Err(e) = &self.error else { return None };
+        if let Some(e) = e.downcast_ref::<TypeError>() {
+            let (expect, obj) = e.expected(cx);
+            let predicate = intern(expect.predicate(), cx).into();
+            // The object is only missing if the error crossed threads
+            let obj = obj.unwrap_or_else(|| cx.add(e.to_string()));
+            Some((sym::WRONG_TYPE_ARGUMENT, vec![predicate, obj]))
+        } else if let Some(e) = e.downcast_ref::<ArgError>() {
+            let (name, actual) = e.call();
+            let data = vec![intern(name, cx).into(), cx.add(i64::from(actual))];
+            Some((sym::WRONG_NUMBER_OF_ARGUMENTS, data))
+        } else if let Some(e) = e.downcast_ref::<ArgRangeError>() {
+            Some((sym::ARGS_OUT_OF_RANGE, e.args().iter().map(|x| x.value(cx)).collect()))
+        } else if let Some(e) = e.downcast_ref::<VoidError>() {
+            let (symbol, name) = match e {
+                VoidError::Variable(name) => (sym::VOID_VARIABLE, name),
+                VoidError::Function(name) => (sym::VOID_FUNCTION, name),
+            };
+            Some((symbol, vec![intern(name, cx).into()]))
+        } else {
+            None
+        }
+    }
+
+    /// The symbol this error was signaled with. Errors raised from Rust have
+    /// the symbol Emacs would signal them with, if there is one.
+    pub(crate) fn symbol<'ob>(&self, cx: &'ob Context) -> Option<Object<'ob>> {
+        match &self.error {
+            ErrorType::Signal(symbol, _) => symbol.get(cx),
+            _ => self.rust_signal(cx).map(|(symbol, _)| symbol.into()),
+        }
+    }
+
+    /// The `(ERROR-SYMBOL . DATA)` form of this error, as bound by
+    /// `condition-case`. Other errors raised from Rust are plain errors with
+    /// their message as the data.
+    pub(crate) fn to_lisp<'ob>(&self, cx: &'ob Context) -> Object<'ob> {
+        if let ErrorType::Signal(symbol, data) = &self.error {
+            if let (Some(symbol), Some(data)) = (symbol.get(cx), data.get(cx)) {
+                return Cons::new(symbol, data, cx).into();
+            }
+        }
+        if let Some((symbol, data)) = self.rust_signal(cx) {
+            let data = crate::fns::slice_into_list(&data, None, cx);
+            return Cons::new(symbol, data, cx).into();
+        }
+        list![sym::ERROR, self.to_string().trim_end(); cx]
+    }
+
+    /// The message for this error, formatted by `error-message-string` if it
+    /// was signaled.
+    pub(crate) fn message(&self, env: &Rt<Env>, cx: &Context) -> String {
+        if let ErrorType::Signal(..) = self.error {
+            if let Ok(message) = crate::print::error_message_string(self.to_lisp(cx), env, cx) {
+                return message;
+            }
+        }
+        self.to_string().trim_end().to_owned()
+    }
+
     pub(crate) fn print_backtrace(&self) {
         println!("BEGIN_BACKTRACE");
         for (i, x) in self.backtrace.iter().enumerate() {
@@ -86,7 +160,12 @@ impl EvalError {
 
 impl From<anyhow::Error> for EvalError {
     fn from(e: anyhow::Error) -> Self {
-        Self::new_error(e)
+        // A signal or throw that passed through a Rust function keeps its id,
+        // so that the tag and data can still be found.
+        match e.downcast::<Self>() {
+            Ok(e) => e,
+            Err(e) => Self::new_error(e),
+        }
     }
 }
 
@@ -144,13 +223,13 @@ pub(crate) fn apply<'ob>(
         env.stack.extend_as_vec_from_within(beg..end);
         for element in last.as_list()? {
             let e = cx.bind(element?);
-            env.stack.push(e);
+            env.stack.push(e)?;
         }
         let args = env.stack.len() - len;
         let frame = &mut CallFrame::new_with_args(env, args);
-        function.call(frame, None, cx).map_err(Into::into)
+        call_function(function, frame, None, cx).map_err(Into::into)
     } else {
-        function.call(&mut CallFrame::new(env), None, cx).map_err(Into::into)
+        call_function(function, &mut CallFrame::new(env), None, cx).map_err(Into::into)
     }
 }
 
@@ -164,7 +243,7 @@ pub(crate) fn funcall<'ob>(
     let beg = env.stack.len() - arguments.len();
     env.stack.extend_as_vec_from_within(beg..);
     let frame = &mut CallFrame::new_with_args(env, arguments.len());
-    function.call(frame, None, cx).map_err(Into::into)
+    call_function(function, frame, None, cx).map_err(Into::into)
 }
 
 #[defun]
@@ -174,7 +253,7 @@ fn run_hooks<'ob>(hooks: ArgSlice, env: &mut Rt<Env>, cx: &'ob mut Context) -> R
         let hook = env.stack[hook_count - i - 1].bind(cx);
         match hook.untag() {
             ObjectType::Symbol(sym) => {
-                if let Some(val) = env.vars.get(sym) {
+                if let Some(val) = env.var(sym, cx) {
                     let val = val.bind(cx);
                     match val.untag() {
                         ObjectType::Cons(hook_list) => {
@@ -208,7 +287,7 @@ fn run_hook_with_args<'ob>(
 ) -> Result<Object<'ob>> {
     match hook.untag(cx) {
         ObjectType::Symbol(sym) => {
-            if let Some(val) = env.vars.get(sym) {
+            if let Some(val) = env.var(sym, cx) {
                 let val = val.bind(cx);
                 match val.untag() {
                     ObjectType::Cons(hook_list) => {
@@ -218,7 +297,7 @@ fn run_hook_with_args<'ob>(
                             let beg = env.stack.len() - args.len();
                             env.stack.extend_as_vec_from_within(beg..);
                             let frame = &mut CallFrame::new_with_args(env, args.len());
-                            func.call(frame, None, cx)?;
+                            call_function(func, frame, None, cx)?;
                         }
                     }
                     ObjectType::NIL => {}
@@ -306,15 +385,10 @@ pub(crate) fn macroexpand<'ob>(
         _ => get_macro_func(sym, cx),
     };
     let Some(macro_func) = func else { return Ok(form.bind(cx)) };
-    let mut iter = cons.cdr().as_list()?.fallible();
-    let mut frame = CallFrame::new(env);
-    while let Some(arg) = iter.next()? {
-        frame.push_arg(arg);
-    }
-    root!(macro_func, cx);
     let name = sym.name().to_owned();
-    let new_form = macro_func.call(&mut frame, Some(&name), cx)?;
-    drop(frame);
+    root!(args, cons.cdr(), cx);
+    root!(macro_func, cx);
+    let new_form = call_macro(macro_func, args, &name, env, cx)?;
     root!(new_form, cx); // polonius
     if eq(new_form.bind(cx), form.bind(cx)) {
         Ok(form.bind(cx))
@@ -324,7 +398,7 @@ pub(crate) fn macroexpand<'ob>(
     }
 }
 
-fn get_macro_func<'ob>(name: Symbol, cx: &'ob Context) -> Option<Function<'ob>> {
+pub(crate) fn get_macro_func<'ob>(name: Symbol, cx: &'ob Context) -> Option<Function<'ob>> {
     if let Some(callable) = name.follow_indirect(cx) {
         if let FunctionType::Cons(cons) = callable.untag() {
             if cons.car() == sym::MACRO {
@@ -336,7 +410,7 @@ fn get_macro_func<'ob>(name: Symbol, cx: &'ob Context) -> Option<Function<'ob>>
 }
 
 #[defun]
-fn func_arity<'ob>(function: Function, cx: &'ob Context) -> Result<&'ob Cons> {
+pub(crate) fn func_arity<'ob>(function: Function, cx: &'ob Context) -> Result<&'ob Cons> {
     let from_args = |args: FnArgs| {
         let min = args.required;
         if args.rest {
@@ -349,6 +423,7 @@ fn func_arity<'ob>(function: Function, cx: &'ob Context) -> Result<&'ob Cons> {
     match function.untag() {
         FunctionType::ByteFn(func) => Ok(from_args(func.args)),
         FunctionType::SubrFn(func) => Ok(from_args(func.args)),
+        FunctionType::Closure(func) => Ok(from_args(func.args)),
         FunctionType::Cons(func) => {
             let arg_pos = match func.car().untag() {
                 ObjectType::Symbol(sym::CLOSURE) => 2,
@@ -368,7 +443,7 @@ fn func_arity<'ob>(function: Function, cx: &'ob Context) -> Result<&'ob Cons> {
             Ok(from_args(args))
         }
         FunctionType::Symbol(sym) => {
-            let Some(func) = sym.follow_indirect(cx) else { bail!("Void Function: {sym}") };
+            let Some(func) = sym.follow_indirect(cx) else { bail!(VoidError::function(sym)) };
             func_arity(func, cx)
         }
     }
@@ -377,19 +452,215 @@ fn func_arity<'ob>(function: Function, cx: &'ob Context) -> Result<&'ob Cons> {
 #[defun]
 #[allow(non_snake_case)]
 fn internal__define_uninitialized_variable<'ob>(
-    _symbol: Symbol<'ob>,
+    symbol: Symbol<'ob>,
     _doc: Option<Object>,
 ) -> Object<'ob> {
     // TODO: implement doc strings
+    symbol.make_special();
     NIL
 }
 
 #[defun]
-fn signal(mut error_symbol: Object, data: Object, env: &mut Rt<Env>) -> Result<bool> {
+fn signal(mut error_symbol: Object, data: Object) -> Result<bool> {
     if error_symbol.is_nil() && data.is_nil() {
         error_symbol = sym::ERROR.into();
     }
-    Err(EvalError::signal(error_symbol, data, env).into())
+    Err(EvalError::signal(error_symbol, data).into())
+}
+
+/// The error that exits to the `catch` for `tag` with `value`. If nothing is
+/// catching `tag`, it signals `no-catch` with the tag and value instead, so
+/// that `condition-case` can handle it.
+pub(crate) fn throw_error(
+    tag: Object,
+    value: Object,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> EvalError {
+    if env.catch_stack.iter().any(|x| eq(x.bind(cx), tag)) {
+        EvalError::throw(tag, value)
+    } else {
+        let data = list![tag, value; cx];
+        EvalError::signal(sym::NO_CATCH.into(), data)
+    }
+}
+
+#[defun]
+fn throw(tag: Object, value: Object, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    Err(throw_error(tag, value, env, cx).into())
+}
+
+/// The errors that are always defined, with their message and parent. Parents
+/// come before their children. Errors that are their own parent have no other
+/// conditions, so they are not caught by `error` handlers.
+const STANDARD_ERRORS: &[(&str, &str, &str)] = &[
+    ("error", "error", "error"),
+    ("quit", "Quit", "quit"),
+    ("minibuffer-quit", "Quit", "quit"),
+    ("user-error", "", "error"),
+    ("args-out-of-range", "Args out of range", "error"),
+    ("arith-error", "Arithmetic error", "error"),
+    ("domain-error", "Arithmetic domain error", "arith-error"),
+    ("singularity-error", "Arithmetic singularity error", "domain-error"),
+    ("range-error", "Arithmetic range error", "arith-error"),
+    ("overflow-error", "Arithmetic overflow error", "range-error"),
+    ("underflow-error", "Arithmetic underflow error", "range-error"),
+    ("beginning-of-buffer", "Beginning of buffer", "error"),
+    ("end-of-buffer", "End of buffer", "error"),
+    ("buffer-read-only", "Buffer is read-only", "error"),
+    ("text-read-only", "Text is read-only", "buffer-read-only"),
+    ("circular-list", "List contains a loop", "error"),
+    (
+        "cyclic-function-indirection",
+        "Symbol's chain of function indirections contains a loop",
+        "error",
+    ),
+    (
+        "cyclic-variable-indirection",
+        "Symbol's chain of variable indirections contains a loop",
+        "error",
+    ),
+    ("end-of-file", "End of file during parsing", "error"),
+    ("file-error", "File error", "error"),
+    ("file-already-exists", "File already exists", "file-error"),
+    ("file-date-error", "Cannot set file date", "file-error"),
+    ("file-missing", "No such file or directory", "file-error"),
+    ("permission-denied", "Permission denied", "file-error"),
+    ("invalid-function", "Invalid function", "error"),
+    ("invalid-read-syntax", "Invalid read syntax", "error"),
+    ("invalid-regexp", "Invalid regexp", "error"),
+    ("mark-inactive", "The mark is not active now", "error"),
+    ("no-catch", "No catch for tag", "error"),
+    ("scan-error", "Scan error", "error"),
+    ("search-failed", "Search failed", "error"),
+    ("setting-constant", "Attempt to set a constant symbol", "error"),
+    ("void-function", "Symbol's function definition is void", "error"),
+    ("void-variable", "Symbol's value as variable is void", "error"),
+    ("wrong-length-argument", "Wrong length argument", "error"),
+    ("wrong-number-of-arguments", "Wrong number of arguments", "error"),
+    ("wrong-type-argument", "Wrong type argument", "error"),
+];
+
+/// Set the `error-conditions` and `error-message` properties of the standard
+/// errors.
+pub(crate) fn init_errors(env: &mut Rt<Env>, cx: &Context) {
+    for (name, message, parent) in STANDARD_ERRORS {
+        let name = intern(name, cx);
+        let parent = intern(parent, cx);
+        define_error(name, cx.add(*message), Some(parent.into()), env, cx)
+            .expect("standard errors should be defined");
+    }
+}
+
+#[defun]
+pub(crate) fn define_error<'ob>(
+    name: Symbol<'ob>,
+    message: Object<'ob>,
+    parent: Option<Object<'ob>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<()> {
+    let parent = parent.filter(|x| !x.is_nil()).unwrap_or(sym::ERROR.into());
+    let (parents, listed): (Vec<Object>, _) = match parent.untag() {
+        ObjectType::Cons(parents) => (parents.elements().collect::<Result<_, _>>()?, true),
+        _ => (vec![parent], false),
+    };
+    let mut conditions = vec![name.into()];
+    for parent in parents {
+        let parent: Symbol = parent.try_into()?;
+        let inherited = crate::data::get(parent, sym::ERROR_CONDITIONS, env, cx);
+        // A list of parents must all be existing errors
+        if listed && inherited.is_nil() {
+            bail!("Unknown signal `{parent}'");
+        }
+        conditions.push(parent.into());
+        for condition in inherited.as_list()? {
+            conditions.push(condition?);
+        }
+    }
+    let mut unique: Vec<Object> = Vec::new();
+    for condition in conditions {
+        if !unique.contains(&condition) {
+            unique.push(condition);
+        }
+    }
+    let conditions = crate::fns::slice_into_list(&unique, None, cx);
+    env.set_prop(name, sym::ERROR_CONDITIONS, conditions);
+    if !message.is_nil() {
+        env.set_prop(name, sym::ERROR_MESSAGE, message);
+    }
+    Ok(())
+}
+
+/// The `error-conditions` of `symbol`.
+fn error_conditions<'ob>(
+    symbol: Object,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Vec<Object<'ob>>> {
+    let mut conditions = Vec::new();
+    if let ObjectType::Symbol(symbol) = symbol.untag() {
+        for condition in crate::data::get(symbol, sym::ERROR_CONDITIONS, env, cx).as_list()? {
+            conditions.push(condition?);
+        }
+    }
+    Ok(conditions)
+}
+
+/// Return true if a `condition-case` handler for `condition` catches an error
+/// signaled with `error_symbol`. `condition` is a condition name or a list of
+/// them. Errors that were never defined are treated as plain errors. Errors
+/// raised from Rust without a symbol are plain errors as well, so only `error`
+/// and `t` catch them.
+pub(crate) fn handles_error(
+    condition: Object,
+    error_symbol: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let matches = |condition: Object| -> Result<bool> {
+        if condition == sym::TRUE {
+            return Ok(true);
+        }
+        Ok(match error_symbol {
+            Some(error_symbol) => {
+                let conditions = error_conditions(error_symbol, env, cx)?;
+                match conditions.is_empty() {
+                    true => condition == error_symbol || condition == sym::ERROR,
+                    false => conditions.contains(&condition),
+                }
+            }
+            None => condition == sym::ERROR,
+        })
+    };
+    match condition.untag() {
+        ObjectType::Symbol(_) => matches(condition),
+        ObjectType::Cons(list) => {
+            for condition in list {
+                if matches(condition?)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        _ => bail!("Invalid condition handler: {condition}"),
+    }
+}
+
+/// The function behind `defconst`, which compiled code calls. Returns
+/// `symbol`.
+#[defun]
+pub(crate) fn defconst_1<'ob>(
+    symbol: Symbol<'ob>,
+    value: Object,
+    doc: Option<Object>,
+    env: &mut Rt<Env>,
+) -> Result<Symbol<'ob>> {
+    env.defconst(symbol, value)?;
+    if let Some(doc) = doc.filter(|x| matches!(x.untag(), ObjectType::String(_))) {
+        env.set_prop(symbol, sym::VARIABLE_DOCUMENTATION, doc);
+    }
+    Ok(symbol)
 }
 
 #[defun]
@@ -403,70 +674,163 @@ fn set_default_toplevel_value<'ob>(
     value: Object,
     env: &'ob mut Rt<Env>,
 ) -> Result<Object<'ob>> {
-    env.set_var(symbol, value)?;
+    env.set_default(symbol, value)?;
+    Ok(NIL)
+}
+
+/// Add `member` to the `custom-group` property of `group` as a `widget`
+/// entry, unless it is already present.
+#[defun]
+pub(crate) fn custom_add_to_group(
+    group: Symbol,
+    member: Symbol,
+    widget: Symbol,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let entry = list![member, widget; cx];
+    let mut members = Vec::new();
+    for x in crate::data::get(group, sym::CUSTOM_GROUP, env, cx).as_list()? {
+        let x = x?;
+        if x == entry {
+            return Ok(());
+        }
+        members.push(x);
+    }
+    members.push(entry);
+    let members = crate::fns::slice_into_list(&members, None, cx);
+    env.set_prop(group, sym::CUSTOM_GROUP, members);
+    Ok(())
+}
+
+/// Record saved values for customizable variables. Each argument has the form
+/// `(SYMBOL EXP [NOW [REQUEST [COMMENT]]])`. `EXP` is stored as the
+/// `saved-value` of `SYMBOL`, and is evaluated and installed as the default
+/// value if the variable is already bound or `NOW` is non-nil. Unbound
+/// variables pick up their saved value when their `defcustom` is evaluated.
+#[defun]
+fn custom_set_variables<'ob>(
+    args: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let arg_count = args.len();
+    for i in 0..arg_count {
+        let spec = env.stack[arg_count - i - 1].bind(cx);
+        let mut spec = spec.as_list()?;
+        let Some(name) = spec.next() else { bail!("Invalid custom-set-variables entry") };
+        let name: Symbol = name?.try_into()?;
+        let exp = spec.next().transpose()?.unwrap_or_default();
+        let now = spec.next().transpose()?.unwrap_or_default();
+        env.set_prop(name, sym::SAVED_VALUE, list![exp; cx]);
+        if now != NIL || env.var(name, cx).is_some() {
+            root!(name, cx);
+            root!(exp, cx);
+            let value = crate::interpreter::eval(exp, None, env, cx)?;
+            env.set_default(name.bind(cx), value)?;
+        }
+    }
     Ok(NIL)
 }
 
 #[defun]
 fn set_default<'ob>(
     symbol: Symbol,
-    value: Object<'ob>,
-    env: &'ob mut Rt<Env>,
+    value: Object,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
 ) -> Result<Object<'ob>> {
-    // TODO: implement buffer local variables
-    env.set_var(symbol, value)?;
-    Ok(value)
-}
-
-impl Rto<Function<'_>> {
-    pub(crate) fn call<'ob>(
-        &self,
-        frame: &mut CallFrame<'_, '_>,
-        name: Option<&str>,
-        cx: &'ob mut Context,
-    ) -> EvalResult<'ob> {
-        debug!("calling: {self}");
-        let name = name.unwrap_or("lambda");
-        frame.finalize_arguments();
-        let arg_cnt = frame.arg_count();
-        cx.garbage_collect(false);
-        match self.untag(cx) {
-            FunctionType::ByteFn(f) => {
-                root!(f, cx);
-                crate::bytecode::call(f, arg_cnt, name, frame, cx)
-                    .map_err(|e| e.add_trace(name, frame.arg_slice()))
-            }
-            FunctionType::SubrFn(f) => {
-                (*f).call(arg_cnt, frame, cx).map_err(|e| add_trace(e, name, frame.arg_slice()))
-            }
-            FunctionType::Cons(_) => {
-                crate::interpreter::call_closure(self.try_as().unwrap(), arg_cnt, name, frame, cx)
-                    .map_err(|e| e.add_trace(name, frame.arg_slice()))
-            }
-            FunctionType::Symbol(sym) => {
-                let Some(func) = sym.follow_indirect(cx) else { bail_err!("Void Function: {sym}") };
-                match func.untag() {
-                    FunctionType::Cons(cons) if cons.car() == sym::AUTOLOAD => {
-                        // TODO: inifinite loop if autoload does not resolve
-                        root!(sym, cx);
-                        crate::eval::autoload_do_load(self.cast(), None, None, frame, cx)
-                            .map_err(|e| add_trace(e, name, frame.arg_slice()))?;
-                        let Some(func) = sym.bind(cx).follow_indirect(cx) else {
-                            bail_err!("autoload for {sym} failed to define function")
-                        };
-                        root!(func, cx);
-                        let name = sym.bind(cx).name().to_owned();
-                        func.call(frame, Some(&name), cx)
-                    }
-                    _ => {
-                        root!(func, cx);
-                        let name = sym.name().to_owned();
-                        func.call(frame, Some(&name), cx)
-                    }
-                }
-            }
+    root!(symbol, cx);
+    root!(value, cx);
+    crate::data::notify_variable_watchers(symbol, value, sym::SET, false, env, cx)?;
+    env.set_default(symbol.bind(cx), value.bind(cx))?;
+    Ok(value.bind(cx))
+}
+
+/// Call `function` with the arguments in `frame`. This is the single entry
+/// point for calling a lisp function: the interpreter, the bytecode VM,
+/// `funcall`, `apply`, and the hook runners all dispatch through here so that
+/// calling semantics are the same everywhere.
+pub(crate) fn call_function<'ob>(
+    function: &Rto<Function>,
+    frame: &mut CallFrame<'_, '_>,
+    name: Option<&str>,
+    cx: &'ob mut Context,
+) -> EvalResult<'ob> {
+    debug!("calling: {function}");
+    let name = name.unwrap_or("lambda");
+    frame.finalize_arguments();
+    let arg_cnt = frame.arg_count();
+    cx.garbage_collect(false);
+    match function.untag(cx) {
+        FunctionType::ByteFn(f) => {
+            root!(f, cx);
+            crate::bytecode::call(f, arg_cnt, name, frame, cx)
+                .map_err(|e| e.add_trace(name, frame.arg_slice()))
         }
+        FunctionType::SubrFn(f) => {
+            (*f).call(arg_cnt, frame, cx).map_err(|e| add_trace(e, name, frame.arg_slice()))
+        }
+        FunctionType::Closure(f) => {
+            root!(f, cx);
+            crate::interpreter::call_closure_object(f, arg_cnt, name, frame, cx)
+                .map_err(|e| e.add_trace(name, frame.arg_slice()))
+        }
+        FunctionType::Cons(_) => {
+            crate::interpreter::call_closure(function.try_as().unwrap(), arg_cnt, name, frame, cx)
+                .map_err(|e| e.add_trace(name, frame.arg_slice()))
+        }
+        FunctionType::Symbol(sym) => {
+            root!(sym, cx);
+            let func = resolve_function(sym, frame, cx)
+                .map_err(|e| add_trace(e, name, frame.arg_slice()))?;
+            root!(func, cx);
+            let name = sym.bind(cx).name().to_owned();
+            call_function(func, frame, Some(&name), cx)
+        }
+    }
+}
+
+/// Get the function definition of `symbol`, following aliases. If the
+/// function is autoloaded, load it first, and if it is waiting to be compiled
+/// by `compile-on-define`, compile it.
+pub(crate) fn resolve_function<'ob>(
+    symbol: &Rto<Symbol>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Function<'ob>> {
+    let Some(func) = symbol.bind(cx).follow_indirect(cx) else {
+        bail!(VoidError::function(symbol))
+    };
+    if let FunctionType::Cons(cons) = func.untag() {
+        if cons.car() == sym::AUTOLOAD {
+            // TODO: inifinite loop if autoload does not resolve
+            root!(func, cx);
+            autoload_do_load(func.cast(), None, None, env, cx)?;
+        }
+    }
+    crate::bytecomp::compile_pending(symbol, env, cx);
+    match symbol.bind(cx).follow_indirect(cx) {
+        Some(func) => Ok(func),
+        None => Err(anyhow!("autoload for {symbol} failed to define function")),
+    }
+}
+
+/// Call the macro function `mcro` with the unevaluated `args` and return its
+/// expansion.
+pub(crate) fn call_macro<'ob>(
+    mcro: &Rto<Function>,
+    args: &Rto<Object>,
+    name: &str,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> EvalResult<'ob> {
+    let frame = &mut CallFrame::new(env);
+    let mut iter = args.bind(cx).as_list()?.fallible();
+    while let Some(arg) = iter.next()? {
+        frame.push_arg(arg)?;
     }
+    call_function(mcro, frame, Some(name), cx)
 }
 
 pub(crate) fn add_trace(err: anyhow::Error, name: &str, args: &[Rto<Object>]) -> EvalError {
@@ -484,6 +848,20 @@ defsym!(SPLICE, ",@");
 defsym!(BACKQUOTE, "`");
 defsym!(AND_OPTIONAL, "&optional");
 defsym!(AND_REST, "&rest");
+defsym!(AND_BODY, "&body");
+defsym!(AND_KEY, "&key");
+defsym!(AND_AUX, "&aux");
+defsym!(AND_WHOLE, "&whole");
+defsym!(AND_ALLOW_OTHER_KEYS, "&allow-other-keys");
+defsym!(KW_ALLOW_OTHER_KEYS);
+defsym!(KW_SUCCESS);
+defsym!(CL_DESTRUCTURING_BIND);
+defsym!(PCASE_LET);
+defsym!(PCASE_LET_STAR, "pcase-let*");
+defsym!(SEQ_LET);
+defsym!(CL_LETF);
+defsym!(CL_LETF_STAR, "cl-letf*");
+defsym!(SETF);
 defsym!(LAMBDA);
 defsym!(CLOSURE);
 defsym!(CONDITION_CASE);
@@ -501,14 +879,39 @@ defsym!(COND);
 defsym!(LET);
 defsym!(LET_STAR, "let*");
 defsym!(IF);
+defsym!(WHEN);
+defsym!(UNLESS);
+defsym!(DEFCUSTOM);
+defsym!(DEFGROUP);
+defsym!(STANDARD_VALUE);
+defsym!(SAVED_VALUE);
+defsym!(CUSTOM_TYPE);
+defsym!(CUSTOM_GROUP);
+defsym!(CUSTOM_VARIABLE);
+defsym!(CUSTOM_SET);
+defsym!(GROUP_DOCUMENTATION);
+defsym!(SAFE_LOCAL_VARIABLE);
+defsym!(RISKY_LOCAL_VARIABLE);
+defsym!(KW_TYPE);
+defsym!(KW_GROUP);
+defsym!(KW_LOCAL);
+defsym!(KW_SAFE);
+defsym!(KW_RISKY);
+defsym!(KW_SET);
 defsym!(AND);
 defsym!(OR);
 defsym!(INTERACTIVE);
 defsym!(CATCH);
-defsym!(THROW);
+defsym!(NO_CATCH);
 defsym!(ERROR);
 defsym!(DEBUG);
+defsym!(ERROR_CONDITIONS);
+defsym!(ERROR_MESSAGE);
 defsym!(VOID_VARIABLE);
+defsym!(VOID_FUNCTION);
+defsym!(WRONG_TYPE_ARGUMENT);
+defsym!(WRONG_NUMBER_OF_ARGUMENTS);
+defsym!(ARGS_OUT_OF_RANGE);
 
 defvar!(DEBUG_ON_ERROR, false);
 defvar!(INTERNAL_MAKE_INTERPRETED_CLOSURE_FUNCTION);
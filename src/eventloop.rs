@@ -0,0 +1,134 @@
+//! Delivery of asynchronous events to lisp. Sources like file watches run on
+//! other threads and queue their events here. The events are handled on the
+//! main thread whenever rune waits, such as in `sleep-for`, between forms in
+//! the REPL, or while running as a daemon. Each event belongs to the [`Env`]
+//! that started its source, and only that environment handles it. Buffers are
+//! auto-saved at the same points once `auto-save-timeout` has passed.
+use crate::arith::NumberValue;
+use crate::core::{
+    env::{Env, EnvId},
+    gc::{Context, Rt},
+    object::Number,
+};
+use crate::filenotify::FileEvent;
+use crate::process::ProcessEvent;
+use crate::server::Request;
+use anyhow::Result;
+use rune_macros::defun;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub(crate) enum Event {
+    FileNotify(FileEvent),
+    Process(ProcessEvent),
+    Server(Request),
+}
+
+#[derive(Default)]
+struct Queue {
+    events: Mutex<VecDeque<(EnvId, Event)>>,
+    ready: Condvar,
+}
+
+fn queue() -> &'static Queue {
+    static QUEUE: OnceLock<Queue> = OnceLock::new();
+    QUEUE.get_or_init(Queue::default)
+}
+
+/// Add an event for the environment `owner` to the queue. This can be called
+/// from any thread.
+pub(crate) fn push_event(owner: EnvId, event: Event) {
+    let queue = queue();
+    queue
+        .events
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push_back((owner, event));
+    queue.ready.notify_all();
+}
+
+/// The time `seconds` from now, or `None` if that is too far away to
+/// represent.
+pub(crate) fn deadline(seconds: f64) -> Option<Instant> {
+    let duration = Duration::try_from_secs_f64(seconds.max(0.0)).ok()?;
+    Instant::now().checked_add(duration)
+}
+
+/// Convert a lisp timeout of `seconds` plus `milliseconds` to seconds.
+pub(crate) fn timeout_seconds(seconds: Number, milliseconds: Option<i64>) -> f64 {
+    let seconds = match seconds.val() {
+        NumberValue::Int(x) => x as f64,
+        NumberValue::Float(x) => x,
+    };
+    seconds + milliseconds.unwrap_or(0) as f64 / 1000.0
+}
+
+/// Block until an event for `owner` is queued or `deadline` has passed. `None`
+/// waits forever. Returns false without waiting if the deadline has already
+/// passed.
+pub(crate) fn wait_for_event(deadline: Option<Instant>, owner: EnvId) -> bool {
+    let timeout = match deadline {
+        Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+            Some(timeout) if !timeout.is_zero() => Some(timeout),
+            _ => return false,
+        },
+        None => None,
+    };
+    let queue = queue();
+    let events = queue.events.lock().unwrap_or_else(PoisonError::into_inner);
+    let none_queued =
+        |events: &mut VecDeque<(EnvId, Event)>| !events.iter().any(|(id, _)| *id == owner);
+    match timeout {
+        Some(timeout) => drop(queue.ready.wait_timeout_while(events, timeout, none_queued)),
+        None => drop(queue.ready.wait_while(events, none_queued)),
+    }
+    true
+}
+
+/// Remove the oldest event queued for `owner`. Events for other
+/// environments stay in the queue.
+fn pop_event(owner: EnvId) -> Option<Event> {
+    let mut events = queue().events.lock().unwrap_or_else(PoisonError::into_inner);
+    let idx = events.iter().position(|(id, _)| *id == owner)?;
+    events.remove(idx).map(|(_, event)| event)
+}
+
+/// Handle all events queued for `env`. Returns true if there were any.
+pub(crate) fn dispatch_events(env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    crate::autosave::auto_save_if_due(env, cx);
+    let owner = env.id;
+    let mut handled = false;
+    loop {
+        // Don't hold the lock while running lisp, which may queue more events
+        let Some(event) = pop_event(owner) else { return Ok(handled) };
+        handled = true;
+        match event {
+            Event::FileNotify(event) => crate::filenotify::handle_event(event, env, cx)?,
+            Event::Process(event) => crate::process::handle_event(event, env, cx)?,
+            Event::Server(request) => crate::server::handle_event(request, env, cx),
+        }
+    }
+}
+
+#[defun]
+fn sleep_for(
+    seconds: Number,
+    milliseconds: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let deadline = deadline(timeout_seconds(seconds, milliseconds));
+    loop {
+        dispatch_events(env, cx)?;
+        // Wake up for auto-saves that are due before the sleep ends
+        let wake = match (deadline, crate::autosave::next_auto_save(env, cx)) {
+            (Some(deadline), Some(auto_save)) => Some(deadline.min(auto_save)),
+            (deadline, auto_save) => deadline.or(auto_save),
+        };
+        if !wait_for_event(wake, env.id) && deadline.is_some_and(|x| Instant::now() >= x) {
+            return Ok(());
+        }
+    }
+}
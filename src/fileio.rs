@@ -2,13 +2,15 @@
 use crate::core::{
     cons::Cons,
     env::{sym, Env},
-    error::{Type, TypeError},
     gc::{Context, Rt},
-    object::{Number, Object, ObjectType},
+    object::{Number, Object, ObjectType, NIL, TRUE},
 };
-use anyhow::{bail, Result};
+use crate::fns::slice_into_list;
+use anyhow::{ensure, Result};
 use rune_macros::defun;
-use std::path::{Component, Path, MAIN_SEPARATOR};
+use std::borrow::Cow;
+use std::fs::{self, Metadata};
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
 
 defvar!(FILE_NAME_HANDLER_ALIST);
 
@@ -21,13 +23,20 @@ pub(crate) fn expand_file_name(
 ) -> Result<String> {
     // TODO: this needs to be tested to ensure it has the same behavior as GNU
     // Emacs. It doesn't do any normalization for one thing.
+    if let Some(home) = home_directory() {
+        if let Some(rest) = name.strip_prefix('~') {
+            if rest.is_empty() || rest.starts_with(MAIN_SEPARATOR) {
+                return Ok(format!("{home}{rest}"));
+            }
+        }
+    }
     if Path::new(name).is_absolute() {
         Ok(name.to_owned())
     } else if let Some(dir) = default_directory {
         let path = Path::new(dir);
         Ok(path.join(name).to_string_lossy().to_string())
     } else {
-        let dir = env.vars.get(sym::DEFAULT_DIRECTORY).unwrap();
+        let dir = env.var(sym::DEFAULT_DIRECTORY, cx).unwrap();
         match dir.untag(cx) {
             ObjectType::String(dir) => {
                 let path = Path::new(dir.as_ref());
@@ -131,28 +140,320 @@ fn file_name_nondirectory(filename: &str) -> &str {
 
 /// Concatenate components to directory, inserting path separators as required.
 #[defun]
-fn file_name_concat(directory: &str, rest_components: &[Object]) -> Result<String> {
+fn file_name_concat(directory: &str, rest_components: &[&str]) -> String {
     let mut path = String::from(directory);
-
-    // All components must be stringp...
-    for r_c in rest_components {
-        let ObjectType::String(s) = r_c.untag() else {
-            bail!(TypeError::new(Type::String, r_c));
-        };
-
+    for component in rest_components {
         // Append separator before adding the new element, but only if the
         // existing path isn't already terminated with a "/"
         if !path.ends_with(MAIN_SEPARATOR) {
             path.push(MAIN_SEPARATOR)
         }
 
-        path.push_str(s.as_ref());
+        path.push_str(component);
     }
+    path
+}
+
+pub(crate) fn home_directory() -> Option<String> {
+    std::env::var("HOME").ok().filter(|x| !x.is_empty())
+}
 
-    Ok(path)
+/// Normalize `filename` for comparing it to other file names. macOS stores file
+/// names decomposed, so a name read from the file system may not match the
+/// same name typed by the user unless both are composed.
+fn comparable_file_name(filename: &str) -> Cow<'_, str> {
+    match cfg!(target_os = "macos") {
+        true => Cow::Owned(crate::fns::ucs_normalize_nfc_string(filename)),
+        false => Cow::Borrowed(filename),
+    }
+}
+
+fn abbreviate_home(filename: &str, home: &str) -> String {
+    let filename = comparable_file_name(filename);
+    let home = comparable_file_name(home);
+    let home = home.trim_end_matches(MAIN_SEPARATOR);
+    // A home directory of / would abbreviate every file name
+    if home.is_empty() {
+        return filename.into_owned();
+    }
+    match filename.strip_prefix(home) {
+        Some(rest) if rest.is_empty() || rest.starts_with(MAIN_SEPARATOR) => format!("~{rest}"),
+        _ => filename.into_owned(),
+    }
+}
+
+/// Return a version of `filename` shortened using the home directory.
+#[defun]
+fn abbreviate_file_name(filename: &str) -> String {
+    match home_directory() {
+        Some(home) => abbreviate_home(filename, &home),
+        None => filename.to_owned(),
+    }
+}
+
+/// The number of symlinks that can be followed before assuming there is a cycle.
+const MAX_SYMLINKS: usize = 100;
+
+/// Resolve every symlink in `file`. Components that don't exist are kept as
+/// they are.
+fn truename(file: &Path) -> Result<PathBuf> {
+    let mut pending: Vec<PathBuf> =
+        file.components().rev().map(|x| PathBuf::from(x.as_os_str())).collect();
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+    while let Some(component) = pending.pop() {
+        match component.components().next() {
+            Some(Component::CurDir) | None => {}
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+            Some(Component::Normal(name)) => {
+                let candidate = resolved.join(name);
+                match fs::read_link(&candidate) {
+                    Ok(target) => {
+                        links += 1;
+                        ensure!(
+                            links <= MAX_SYMLINKS,
+                            "Apparent cycle of symbolic links for {}",
+                            file.display()
+                        );
+                        if target.is_absolute() {
+                            resolved = PathBuf::new();
+                        }
+                        let components = target.components().rev();
+                        pending.extend(components.map(|x| PathBuf::from(x.as_os_str())));
+                    }
+                    Err(_) => resolved = candidate,
+                }
+            }
+            Some(root) => resolved.push(root.as_os_str()),
+        }
+    }
+    Ok(resolved)
+}
+
+#[defun]
+fn file_truename(
+    filename: &str,
+    _counter: Option<Object>,
+    _prev_dirs: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<String> {
+    let file = expand_file_name(filename, None, env, cx)?;
+    let mut truename = truename(Path::new(&file))?.to_string_lossy().into_owned();
+    if file.ends_with(MAIN_SEPARATOR) && !truename.ends_with(MAIN_SEPARATOR) {
+        truename.push(MAIN_SEPARATOR);
+    }
+    Ok(truename)
+}
+
+/// Return the target of `filename` if it is a symbolic link.
+#[defun]
+fn file_symlink_p(filename: &str, env: &Rt<Env>, cx: &Context) -> Result<Option<String>> {
+    let file = expand_file_name(filename, None, env, cx)?;
+    Ok(fs::read_link(file).ok().map(|x| x.to_string_lossy().into_owned()))
+}
+
+/// The `ls` style mode string of a file, like "drwxr-xr-x".
+#[cfg(unix)]
+fn mode_string(metadata: &Metadata) -> String {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_block_device() {
+        'b'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else {
+        '-'
+    };
+    let mode = metadata.permissions().mode();
+    let mut string = String::from(kind);
+    // (read, write, execute, special bit, special char)
+    for (shift, special, chr) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = mode >> shift;
+        string.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        string.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        string.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => chr,
+            (false, true) => chr.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    string
+}
+
+/// Find the name of a user or group id in a file like /etc/passwd.
+#[cfg(unix)]
+fn id_name(database: &str, id: u32) -> Option<String> {
+    let contents = fs::read_to_string(database).ok()?;
+    contents.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let entry_id = fields.nth(1)?;
+        (entry_id.parse() == Ok(id)).then(|| name.to_owned())
+    })
+}
+
+#[cfg(unix)]
+fn metadata_attributes<'ob>(
+    metadata: &Metadata,
+    names: bool,
+    cx: &'ob Context,
+) -> [Object<'ob>; 6] {
+    use std::os::unix::fs::MetadataExt;
+    let id = |database, id: u32| match id_name(database, id) {
+        Some(name) if names => cx.add(name),
+        _ => cx.add(i64::from(id)),
+    };
+    let ctime = std::time::UNIX_EPOCH
+        + std::time::Duration::new(metadata.ctime().max(0) as u64, metadata.ctime_nsec() as u32);
+    [
+        cx.add(metadata.nlink()),
+        id("/etc/passwd", metadata.uid()),
+        id("/etc/group", metadata.gid()),
+        crate::timefns::system_time_to_list(ctime, cx),
+        cx.add(metadata.ino()),
+        cx.add(metadata.dev()),
+    ]
+}
+
+#[cfg(not(unix))]
+fn metadata_attributes<'ob>(
+    metadata: &Metadata,
+    _names: bool,
+    cx: &'ob Context,
+) -> [Object<'ob>; 6] {
+    let ctime = metadata.modified().unwrap_or(std::time::UNIX_EPOCH);
+    [
+        cx.add(1),
+        cx.add(0),
+        cx.add(0),
+        crate::timefns::system_time_to_list(ctime, cx),
+        cx.add(0),
+        cx.add(0),
+    ]
+}
+
+#[cfg(not(unix))]
+fn mode_string(metadata: &Metadata) -> String {
+    let kind = if metadata.is_dir() { 'd' } else { '-' };
+    let write = if metadata.permissions().readonly() { '-' } else { 'w' };
+    format!("{kind}r{write}-r{write}-r{write}-")
+}
+
+/// Return the attributes of `filename` without following symlinks, or nil if
+/// it doesn't exist. The list has the file type, link count, uid, gid, access
+/// time, modification time, status change time, size, mode string, an unused
+/// t, inode number, and device number.
+#[defun]
+fn file_attributes<'ob>(
+    filename: &str,
+    id_format: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let file = expand_file_name(filename, None, env, cx)?;
+    let Ok(metadata) = fs::symlink_metadata(&file) else { return Ok(NIL) };
+    let file_type = if metadata.is_dir() {
+        TRUE
+    } else if metadata.is_symlink() {
+        match fs::read_link(&file) {
+            Ok(target) => cx.add(target.to_string_lossy().into_owned()),
+            Err(_) => NIL,
+        }
+    } else {
+        NIL
+    };
+    let names = id_format == Some(sym::STRING.into());
+    let [links, uid, gid, ctime, inode, device] = metadata_attributes(&metadata, names, cx);
+    let time = |time: std::io::Result<std::time::SystemTime>| {
+        crate::timefns::system_time_to_list(time.unwrap_or(std::time::UNIX_EPOCH), cx)
+    };
+    let attributes = [
+        file_type,
+        links,
+        uid,
+        gid,
+        time(metadata.accessed()),
+        time(metadata.modified()),
+        ctime,
+        cx.add(metadata.len()),
+        cx.add(mode_string(&metadata)),
+        TRUE,
+        inode,
+        device,
+    ];
+    Ok(slice_into_list(&attributes, None, cx))
 }
 
 // TODO: file-relative-name -- requires knowing the current buffer's default directory
 // TODO: file-name-sans-versions
 // TODO: find-file-name-handler: https://www.gnu.org/software/emacs/manual/html_node/elisp/Magic-File-Names.html
 //   required by file-name-extension  & file-name-sans-extension library & file-relative-name functions (among others)
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_abbreviate_file_name() {
+        assert_eq!(abbreviate_home("/home/user/src", "/home/user"), "~/src");
+        assert_eq!(abbreviate_home("/home/user", "/home/user/"), "~");
+        assert_eq!(abbreviate_home("/home/username", "/home/user"), "/home/username");
+        assert_eq!(abbreviate_home("/etc/hosts", "/home/user"), "/etc/hosts");
+        assert_eq!(abbreviate_home("/etc/hosts", "/"), "/etc/hosts");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_truename() {
+        use std::os::unix::fs::symlink;
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let dir = std::env::temp_dir().join(format!("rune-truename-{}", std::process::id()));
+        let dir = truename(&dir).unwrap();
+        fs::create_dir_all(dir.join("real")).unwrap();
+        fs::write(dir.join("real/file"), "contents").unwrap();
+        symlink(dir.join("real"), dir.join("abs")).unwrap();
+        symlink("real/file", dir.join("rel")).unwrap();
+        symlink("loop", dir.join("loop")).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        let real_file = path("real/file");
+        assert_eq!(file_truename(&path("abs/file"), None, None, env, cx).unwrap(), real_file);
+        assert_eq!(file_truename(&path("rel"), None, None, env, cx).unwrap(), real_file);
+        assert_eq!(file_truename(&path("abs/../rel"), None, None, env, cx).unwrap(), real_file);
+        assert_eq!(file_truename(&path("abs/"), None, None, env, cx).unwrap(), path("real/"));
+        assert_eq!(file_truename(&path("abs/new"), None, None, env, cx).unwrap(), path("real/new"));
+        assert!(file_truename(&path("loop"), None, None, env, cx).is_err());
+
+        assert_eq!(file_symlink_p(&path("rel"), env, cx).unwrap(), Some("real/file".to_owned()));
+        assert_eq!(file_symlink_p(&path("real"), env, cx).unwrap(), None);
+
+        let attrs = file_attributes(&path("real/file"), None, env, cx).unwrap();
+        let attrs: Vec<_> = attrs.as_list().unwrap().map(Result::unwrap).collect();
+        assert_eq!(attrs.len(), 12);
+        assert_eq!(attrs[0], NIL);
+        assert_eq!(attrs[7], 8_i64);
+        assert!(matches!(attrs[8].untag(), ObjectType::String(s) if s.starts_with("-rw")));
+        let attrs = file_attributes(&path("abs"), None, env, cx).unwrap();
+        assert_eq!(attrs.as_list().unwrap().next().unwrap().unwrap(), cx.add(path("real")));
+        let attrs = file_attributes(&path("real"), None, env, cx).unwrap();
+        assert_eq!(attrs.as_list().unwrap().next().unwrap().unwrap(), TRUE);
+        assert_eq!(file_attributes(&path("missing"), None, env, cx).unwrap(), NIL);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
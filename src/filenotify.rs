@@ -0,0 +1,333 @@
+//! File notification. Watches are backed by the `notify` crate, which uses
+//! inotify, kqueue, FSEvents, or ReadDirectoryChangesW depending on the
+//! platform. Events are delivered to the watch callbacks through the
+//! [event loop](crate::eventloop).
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Function, HashTable, Object, ObjectType, Symbol},
+};
+use crate::eventloop::{push_event, Event};
+use crate::fns::slice_into_list;
+use anyhow::{bail, Result};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use rune_core::hashmap::HashMap;
+use rune_core::macros::{call, root};
+use rune_macros::defun;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+defvar!(FILE_NOTIFY_DESCRIPTORS);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Created,
+    Deleted,
+    Changed,
+    Renamed,
+    AttributeChanged,
+    Stopped,
+}
+
+impl Action {
+    fn symbol(self) -> Symbol<'static> {
+        match self {
+            Action::Created => sym::CREATED,
+            Action::Deleted => sym::DELETED,
+            Action::Changed => sym::CHANGED,
+            Action::Renamed => sym::RENAMED,
+            Action::AttributeChanged => sym::ATTRIBUTE_CHANGED,
+            Action::Stopped => sym::STOPPED,
+        }
+    }
+}
+
+/// An event for the callback of a watch, `(DESCRIPTOR ACTION FILE [FILE1])`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct FileEvent {
+    descriptor: i64,
+    action: Action,
+    files: Vec<PathBuf>,
+}
+
+/// What a watch reports. A watch on a file is implemented as a watch on its
+/// directory that only reports events for that file.
+#[derive(Debug, Clone)]
+struct WatchSpec {
+    descriptor: i64,
+    file: PathBuf,
+    is_dir: bool,
+    change: bool,
+    attribute_change: bool,
+}
+
+impl WatchSpec {
+    fn wants(&self, path: &Path) -> bool {
+        self.is_dir || path == self.file
+    }
+
+    /// Convert a `notify` event to the events this watch reports.
+    fn translate(&self, event: &notify::Event) -> Vec<FileEvent> {
+        let new = |action, files: &[PathBuf]| FileEvent {
+            descriptor: self.descriptor,
+            action,
+            files: files.to_vec(),
+        };
+        let each = |action| -> Vec<FileEvent> {
+            let paths = event.paths.iter().filter(|x| self.wants(x));
+            paths.map(|path| new(action, &[path.clone()])).collect()
+        };
+        let mut events = match event.kind {
+            EventKind::Create(_) if self.change => each(Action::Created),
+            EventKind::Remove(_) if self.change => each(Action::Deleted),
+            EventKind::Modify(ModifyKind::Metadata(_)) if self.attribute_change => {
+                each(Action::AttributeChanged)
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if self.change => {
+                match &event.paths[..] {
+                    [from, to] if self.wants(from) || self.wants(to) => {
+                        vec![new(Action::Renamed, &[from.clone(), to.clone()])]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) if self.change => {
+                each(Action::Deleted)
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) if self.change => {
+                each(Action::Created)
+            }
+            EventKind::Modify(ModifyKind::Name(_)) if self.change => {
+                let paths = event.paths.iter().filter(|x| self.wants(x));
+                let action =
+                    |x: &PathBuf| if x.exists() { Action::Created } else { Action::Deleted };
+                paths.map(|path| new(action(path), &[path.clone()])).collect()
+            }
+            EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any | ModifyKind::Other)
+                if self.change =>
+            {
+                each(Action::Changed)
+            }
+            _ => Vec::new(),
+        };
+        // The watch ends when the watched file is removed
+        let removed = matches!(
+            event.kind,
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From))
+        );
+        if removed && event.paths.iter().any(|x| *x == self.file) {
+            events.push(new(Action::Stopped, &[self.file.clone()]));
+        }
+        events
+    }
+}
+
+fn watches() -> &'static Mutex<HashMap<i64, RecommendedWatcher>> {
+    static WATCHES: OnceLock<Mutex<HashMap<i64, RecommendedWatcher>>> = OnceLock::new();
+    WATCHES.get_or_init(Mutex::default)
+}
+
+/// Stop all watches. Called when rune exits.
+pub(crate) fn remove_all_watches() {
+    watches().lock().unwrap_or_else(PoisonError::into_inner).clear();
+}
+
+/// The table mapping descriptors to callbacks, creating it if needed.
+fn callbacks<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    match env.vars.get(sym::FILE_NOTIFY_DESCRIPTORS).map(|x| x.bind(cx)) {
+        Some(table) if !table.is_nil() => Ok(table),
+        _ => {
+            let table = cx.add(HashTable::default());
+            env.set_default(sym::FILE_NOTIFY_DESCRIPTORS, table)?;
+            Ok(table)
+        }
+    }
+}
+
+/// Run the callback of the watch an event belongs to.
+pub(crate) fn handle_event(event: FileEvent, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let descriptor = cx.add(event.descriptor);
+    let ObjectType::HashTable(table) = callbacks(env, cx)?.untag() else {
+        bail!("`file-notify-descriptors' is not a hash table")
+    };
+    // The watch was removed before the event was delivered
+    let Some(callback) = table.get(descriptor) else { return Ok(()) };
+    if event.action == Action::Stopped {
+        table.swap_remove(descriptor);
+        watches()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&event.descriptor);
+    }
+    let mut elems = vec![descriptor, event.action.symbol().into()];
+    for file in &event.files {
+        elems.push(cx.add(file.to_string_lossy().into_owned()));
+    }
+    let arg = slice_into_list(&elems, None, cx);
+    let func: Function = callback.try_into()?;
+    root!(func, cx);
+    call!(func, arg; env, cx)?;
+    Ok(())
+}
+
+#[defun]
+fn file_notify_add_watch(
+    file: &str,
+    flags: Object,
+    callback: Object,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<i64> {
+    static NEXT_DESCRIPTOR: AtomicI64 = AtomicI64::new(1);
+    let file = PathBuf::from(crate::fileio::expand_file_name(file, None, env, cx)?);
+    if !file.exists() {
+        bail!("No such file or directory, {}", file.display());
+    }
+    let mut spec = WatchSpec {
+        descriptor: NEXT_DESCRIPTOR.fetch_add(1, Ordering::Relaxed),
+        is_dir: file.is_dir(),
+        file,
+        change: false,
+        attribute_change: false,
+    };
+    for flag in flags.as_list()? {
+        match flag?.untag() {
+            ObjectType::Symbol(sym::CHANGE) => spec.change = true,
+            ObjectType::Symbol(sym::ATTRIBUTE_CHANGE) => spec.attribute_change = true,
+            _ => {}
+        }
+    }
+    let dir = match spec.file.parent() {
+        Some(parent) if !spec.is_dir => parent.to_owned(),
+        _ => spec.file.clone(),
+    };
+    let descriptor = spec.descriptor;
+    let owner = env.id;
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            for event in spec.translate(&event) {
+                push_event(owner, Event::FileNotify(event));
+            }
+        }
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    let ObjectType::HashTable(table) = callbacks(env, cx)?.untag() else {
+        bail!("`file-notify-descriptors' is not a hash table")
+    };
+    table.insert(cx.add(descriptor), callback);
+    watches()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(descriptor, watcher);
+    Ok(descriptor)
+}
+
+#[defun]
+fn file_notify_rm_watch(descriptor: i64, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    if watches()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&descriptor)
+        .is_none()
+    {
+        return Ok(());
+    }
+    // The callback is told that the watch has stopped
+    let event = FileEvent { descriptor, action: Action::Stopped, files: Vec::new() };
+    handle_event(event, env, cx)
+}
+
+#[defun]
+fn file_notify_valid_p(descriptor: i64) -> bool {
+    watches()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .contains_key(&descriptor)
+}
+
+defsym!(CHANGE);
+defsym!(ATTRIBUTE_CHANGE);
+defsym!(CREATED);
+defsym!(DELETED);
+defsym!(CHANGED);
+defsym!(RENAMED);
+defsym!(ATTRIBUTE_CHANGED);
+defsym!(STOPPED);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{gc::RootSet, object::NIL};
+    use notify::event::{CreateKind, DataChange, MetadataKind, RemoveKind};
+    use rune_core::macros::{list, rebind};
+
+    #[test]
+    fn test_translate() {
+        let spec = WatchSpec {
+            descriptor: 1,
+            file: PathBuf::from("/dir/file"),
+            is_dir: false,
+            change: true,
+            attribute_change: false,
+        };
+        let file = PathBuf::from("/dir/file");
+        let other = PathBuf::from("/dir/other");
+        let event = |kind, paths: &[&PathBuf]| {
+            paths.iter().fold(notify::Event::new(kind), |e, x| e.add_path((*x).clone()))
+        };
+        let expect = |action, files: &[&PathBuf]| FileEvent {
+            descriptor: 1,
+            action,
+            files: files.iter().map(|x| (*x).clone()).collect(),
+        };
+
+        let created = event(EventKind::Create(CreateKind::File), &[&file]);
+        assert_eq!(spec.translate(&created), vec![expect(Action::Created, &[&file])]);
+        let changed = event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), &[&file]);
+        assert_eq!(spec.translate(&changed), vec![expect(Action::Changed, &[&file])]);
+        let other_changed = event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), &[&other]);
+        assert_eq!(spec.translate(&other_changed), vec![]);
+        let renamed =
+            event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[&other, &file]);
+        assert_eq!(spec.translate(&renamed), vec![expect(Action::Renamed, &[&other, &file])]);
+        let metadata = event(EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)), &[&file]);
+        assert_eq!(spec.translate(&metadata), vec![]);
+        let removed = event(EventKind::Remove(RemoveKind::File), &[&file]);
+        let expected = vec![expect(Action::Deleted, &[&file]), expect(Action::Stopped, &[&file])];
+        assert_eq!(spec.translate(&removed), expected);
+
+        let dir_spec = WatchSpec { file: PathBuf::from("/dir"), is_dir: true, ..spec };
+        assert_eq!(dir_spec.translate(&other_changed), vec![expect(Action::Changed, &[&other])]);
+    }
+
+    #[test]
+    fn test_file_notify_watch() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let dir = std::env::temp_dir();
+        let dir = dir.to_string_lossy();
+        let callback =
+            crate::reader::read("(lambda (event) (setq file-notify-test-event event))", cx)
+                .unwrap()
+                .0;
+        root!(callback, cx);
+        let callback = rebind!(crate::interpreter::eval(callback, None, env, cx).unwrap());
+        let flags = list![sym::CHANGE; cx];
+        let desc = file_notify_add_watch(&dir, flags, callback, env, cx).unwrap();
+        assert!(file_notify_valid_p(desc));
+
+        file_notify_rm_watch(desc, env, cx).unwrap();
+        assert!(!file_notify_valid_p(desc));
+        let test_event = crate::reader::read("file-notify-test-event", cx).unwrap().0;
+        root!(test_event, cx);
+        let event = rebind!(crate::interpreter::eval(test_event, None, env, cx).unwrap());
+        assert_eq!(event, list![desc, sym::STOPPED; cx]);
+        assert!(file_notify_add_watch("/rune/no/such/file", NIL, NIL, env, cx).is_err());
+    }
+}
@@ -3,11 +3,12 @@ use crate::{
     arith::NumberValue,
     core::{
         cons::Cons,
+        error::ArgRangeError,
         gc::Context,
-        object::{Number, NumberType, Object},
+        object::{checked_fixnum, Number, NumberType, Object},
     },
 };
-
+use anyhow::{bail, Result};
 use rune_macros::defun;
 
 #[inline(always)]
@@ -123,14 +124,22 @@ fn exp(arg: Number) -> f64 {
 }
 
 #[defun]
-fn expt(x: Number, y: Number) -> NumberValue {
-    // If either is a float, we use the float version
+fn expt(x: Number, y: Number) -> Result<NumberValue> {
+    // If either is a float or the exponent is negative, we use the float version
     match (x.untag(), y.untag()) {
-        (NumberType::Int(x), NumberType::Int(y)) => NumberValue::Int(x.pow(y as u32)),
+        (NumberType::Int(x), NumberType::Int(y)) if y >= 0 => {
+            // Only the parity of the exponent matters for -1, 0, and 1
+            let exp = if (-1..=1).contains(&x) && y > 1 { 2 - y % 2 } else { y };
+            let result = u32::try_from(exp).ok().and_then(|exp| x.checked_pow(exp));
+            match result.map(checked_fixnum) {
+                Some(Ok(result)) => Ok(NumberValue::Int(result)),
+                _ => bail!(ArgRangeError::new(&[x, y])),
+            }
+        }
         _ => {
             let x = coerce(x);
             let y = coerce(y);
-            NumberValue::Float(x.powf(y))
+            Ok(NumberValue::Float(x.powf(y)))
         }
     }
 }
@@ -159,8 +168,16 @@ fn abs(arg: Number) -> NumberValue {
 
 #[defun]
 fn ldexp(s: Number, e: i64) -> f64 {
-    // TODO: overflow check -> bail!?
-    coerce(s) * 2f64.powi(e as i32)
+    // Scale in steps so that the power of two itself never overflows. Past
+    // this bound every finite value is already zero or infinite.
+    let mut exp = e.clamp(-2200, 2200) as i32;
+    let mut result = coerce(s);
+    while exp != 0 && result.is_finite() && result != 0.0 {
+        let step = exp.clamp(-1000, 1000);
+        result *= 2f64.powi(step);
+        exp -= step;
+    }
+    result
 }
 
 #[defun]
@@ -189,3 +206,35 @@ fn frexp<'ob>(x: Number, cx: &'ob Context) -> Object<'ob> {
     let (significand, exponent) = frexp_f(f);
     Cons::new(significand, exponent, cx).into()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_expt() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let expt = |x: i64, y: i64| expt(x.into(), y.into()).unwrap();
+        assert_eq!(expt(2, 10), NumberValue::Int(1024));
+        assert_eq!(expt(2, -1), NumberValue::Float(0.5));
+        assert_eq!(expt(-1, 1 << 40), NumberValue::Int(1));
+        assert_eq!(expt(-1, (1 << 40) + 1), NumberValue::Int(-1));
+        assert_eq!(expt(0, 0), NumberValue::Int(1));
+        assert!(super::expt(2_i64.into(), 60_i64.into()).is_err());
+        assert!(super::expt(3_i64.into(), (1_i64 << 40).into()).is_err());
+        let float = cx.add_as(2.0);
+        assert_eq!(super::expt(float, 3_i64.into()).unwrap(), NumberValue::Float(8.0));
+    }
+
+    #[test]
+    fn test_ldexp() {
+        assert_eq!(ldexp(3_i64.into(), 2), 12.0);
+        assert_eq!(ldexp(1_i64.into(), -1), 0.5);
+        assert_eq!(ldexp(1_i64.into(), 5000), f64::INFINITY);
+        assert_eq!(ldexp(1_i64.into(), -5000), 0.0);
+        assert_eq!(ldexp(0_i64.into(), i64::MAX), 0.0);
+        assert_eq!(ldexp(1_i64.into(), -1074), 5e-324);
+    }
+}
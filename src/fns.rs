@@ -1,13 +1,15 @@
 //! General purpose lisp functions
 use crate::{
+    arith::NumberValue,
     core::{
-        cons::Cons,
-        env::{sym, Env},
-        error::{Type, TypeError},
-        gc::{Context, Rt, Rto},
+        cons::{Cons, ConsError},
+        env::{sym, ArgSlice, CallFrame, Env},
+        error::{ArgRangeError, Type, TypeError},
+        gc::{Context, Rt, Rto, Slot},
         object::{
-            Function, Gc, HashTable, IntoObject, LispHashTable, LispString, LispVec, List,
-            ListType, Object, ObjectType, Symbol, WithLifetime, NIL,
+            address, Ancestors, Function, Gc, HashTable, HashTableTest, IntoObject, LispHashTable,
+            LispString, LispVec, List, ListType, Number, Object, ObjectType, RecordBuilder, Symbol,
+            WithLifetime, NIL,
         },
     },
     data::aref,
@@ -18,6 +20,8 @@ use fallible_iterator::FallibleIterator;
 use fallible_streaming_iterator::FallibleStreamingIterator;
 use rune_core::macros::{call, list, rebind, root};
 use rune_macros::defun;
+use std::ops::Range;
+use unicode_normalization::UnicodeNormalization;
 
 #[defun]
 fn identity(arg: Object) -> Object {
@@ -73,7 +77,7 @@ fn equal_including_properties<'ob>(o1: Object<'ob>, o2: Object<'ob>) -> bool {
 }
 
 #[defun]
-fn plist_get<'ob>(plist: Object<'ob>, prop: Object<'ob>) -> Result<Object<'ob>> {
+pub(crate) fn plist_get<'ob>(plist: Object<'ob>, prop: Object<'ob>) -> Result<Object<'ob>> {
     let Ok(plist) = List::try_from(plist) else { return Ok(NIL) };
     // TODO: this function should never fail. Need to implement safe iterator
     let mut iter = plist.elements();
@@ -107,7 +111,13 @@ fn plist_member<'ob>(
 }
 
 #[defun]
-pub(crate) fn prin1_to_string(object: Object, _noescape: Option<Object>) -> String {
+pub(crate) fn prin1_to_string(
+    object: Object,
+    _noescape: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> String {
+    let _settings = crate::print::load_print_settings(env, cx);
     format!("{object}")
 }
 
@@ -123,6 +133,217 @@ fn string_search(needle: &str, haystack: &str, start_pos: Option<usize>) -> Opti
     haystack[start..].find(needle).map(|x| x + start)
 }
 
+#[defun(name = "ucs-normalize-NFC-string")]
+pub(crate) fn ucs_normalize_nfc_string(string: &str) -> String {
+    string.nfc().collect()
+}
+
+#[defun(name = "ucs-normalize-NFD-string")]
+fn ucs_normalize_nfd_string(string: &str) -> String {
+    string.nfd().collect()
+}
+
+#[defun(name = "ucs-normalize-NFKC-string")]
+fn ucs_normalize_nfkc_string(string: &str) -> String {
+    string.nfkc().collect()
+}
+
+#[defun(name = "ucs-normalize-NFKD-string")]
+fn ucs_normalize_nfkd_string(string: &str) -> String {
+    string.nfkd().collect()
+}
+
+/// Compose the characters of `string` into precomposed characters where
+/// possible.
+#[defun]
+fn string_glyph_compose(string: &str) -> String {
+    ucs_normalize_nfc_string(string)
+}
+
+/// Decompose the precomposed characters of `string`.
+#[defun]
+fn string_glyph_decompose(string: &str) -> String {
+    ucs_normalize_nfd_string(string)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn levenshtein<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if x == y { diagonal } else { 1 + diagonal.min(above).min(row[j]) };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[defun]
+fn string_distance(string1: &str, string2: &str, bytecompare: Option<()>) -> usize {
+    if bytecompare.is_some() {
+        levenshtein(string1.as_bytes(), string2.as_bytes())
+    } else {
+        let chars1: Vec<char> = string1.chars().collect();
+        let chars2: Vec<char> = string2.chars().collect();
+        levenshtein(&chars1, &chars2)
+    }
+}
+
+/// Compare strings so that runs of digits are ordered by their numeric value,
+/// ignoring leading zeros. Strings that only differ in leading zeros are
+/// ordered as plain strings.
+fn string_version_cmp(s1: &str, s2: &str) -> std::cmp::Ordering {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (mut a, mut b) = (s1, s2);
+    while let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) {
+        let (len_a, len_b) = (digits(a), digits(b));
+        if len_a > 0 && len_b > 0 {
+            let num_a = a[..len_a].trim_start_matches('0');
+            let num_b = b[..len_b].trim_start_matches('0');
+            let ord = num_a.len().cmp(&num_b.len()).then_with(|| num_a.cmp(num_b));
+            if ord.is_ne() {
+                return ord;
+            }
+            (a, b) = (&a[len_a..], &b[len_b..]);
+        } else if x != y {
+            return x.cmp(&y);
+        } else {
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+        }
+    }
+    a.len().min(1).cmp(&b.len().min(1)).then_with(|| s1.cmp(s2))
+}
+
+#[defun]
+fn string_version_lessp(string1: &str, string2: &str) -> bool {
+    string_version_cmp(string1, string2).is_lt()
+}
+
+/// The priority of a non-numeric version part like "-beta" or "pre". These
+/// are the defaults of `version-regexp-alist'.
+fn version_priority(part: &str) -> Option<i64> {
+    let part = part.to_ascii_lowercase();
+    if matches!(part.as_str(), "-" | "." | "_" | "+") {
+        return Some(-4);
+    }
+    let name = part.strip_prefix(['-', '.', '_', '+', ' ']).unwrap_or(&part);
+    match name {
+        "snapshot" | "cvs" | "git" | "bzr" | "svn" | "hg" | "darcs" | "unknown" => Some(-4),
+        "alpha" => Some(-3),
+        "beta" => Some(-2),
+        "pre" | "rc" => Some(-1),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_version(version: &str) -> Result<Vec<i64>> {
+    // .5 is 0.5
+    let version = if version.starts_with('.') {
+        format!("0{version}")
+    } else {
+        version.to_owned()
+    };
+    let is_digit = |c: char| c.is_ascii_digit();
+    ensure!(
+        version.starts_with(is_digit),
+        "Invalid version syntax: `{version}' (must start with a number)"
+    );
+    let mut list = Vec::new();
+    let mut rest = version.as_str();
+    while rest.starts_with(is_digit) {
+        let end = rest.find(|c: char| !is_digit(c)).unwrap_or(rest.len());
+        list.push(rest[..end].parse()?);
+        rest = &rest[end..];
+        let end = rest.find(is_digit).unwrap_or(rest.len());
+        let part = &rest[..end];
+        rest = &rest[end..];
+        if part.is_empty() || part == "." {
+            continue;
+        }
+        if let Some(priority) = version_priority(part) {
+            list.push(priority);
+            continue;
+        }
+        // 22.3a is 22.3.1, but only at the end so that 22.8X3 is invalid
+        let letter = part.strip_prefix(['-', '.', '_', '+', ' ']).unwrap_or(part);
+        match letter.as_bytes() {
+            [c] if c.is_ascii_alphabetic() && rest.is_empty() => {
+                list.push(i64::from(c.to_ascii_lowercase() - b'a' + 1));
+            }
+            _ => bail!("Invalid version syntax: `{version}'"),
+        }
+    }
+    Ok(list)
+}
+
+/// Compare version lists, where missing elements are treated as 0.
+pub(crate) fn version_list_cmp(v1: &[i64], v2: &[i64]) -> std::cmp::Ordering {
+    let get = |list: &[i64], i| list.get(i).copied().unwrap_or(0);
+    (0..v1.len().max(v2.len()))
+        .map(|i| get(v1, i).cmp(&get(v2, i)))
+        .find(|x| x.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+#[defun]
+fn version_to_list<'ob>(ver: &str, cx: &'ob Context) -> Result<Object<'ob>> {
+    let list: Vec<Object> = parse_version(ver)?.into_iter().map(Object::from).collect();
+    Ok(slice_into_list(&list, None, cx))
+}
+
+#[defun(name = "version<")]
+fn version_less(v1: &str, v2: &str) -> Result<bool> {
+    Ok(version_list_cmp(&parse_version(v1)?, &parse_version(v2)?).is_lt())
+}
+
+#[defun(name = "version<=")]
+fn version_less_equal(v1: &str, v2: &str) -> Result<bool> {
+    Ok(version_list_cmp(&parse_version(v1)?, &parse_version(v2)?).is_le())
+}
+
+#[defun(name = "version=")]
+fn version_equal(v1: &str, v2: &str) -> Result<bool> {
+    Ok(version_list_cmp(&parse_version(v1)?, &parse_version(v2)?).is_eq())
+}
+
+/// The elements of `sequence`. Characters of strings are returned as integers.
+fn sequence_elements<'ob>(sequence: Object<'ob>, cx: &'ob Context) -> Result<Vec<Object<'ob>>> {
+    let mut elements = Vec::new();
+    extend_elements(&mut elements, sequence, cx)?;
+    Ok(elements)
+}
+
+/// Add the elements of `sequence` to `elements`. The characters of strings
+/// are added as integers. This is how the functions that combine sequences of
+/// any type, like `append`, `vconcat` and `concat`, read their arguments.
+fn extend_elements<'ob>(
+    elements: &mut Vec<Object<'ob>>,
+    sequence: Object<'ob>,
+    cx: &'ob Context,
+) -> Result<()> {
+    match sequence.untag() {
+        ObjectType::NIL => {}
+        ObjectType::Cons(cons) => {
+            for x in cons {
+                elements.push(x?);
+            }
+        }
+        ObjectType::Vec(vec) => elements.extend(vec.iter().map(|x| x.get())),
+        ObjectType::String(string) => elements.extend(string.chars().map(|x| (x as i64).into())),
+        ObjectType::ByteString(string) => {
+            elements.extend(string.iter().map(|x| i64::from(*x).into()));
+        }
+        ObjectType::ByteFn(fun) => {
+            elements.extend((0..fun.len()).map(|i| fun.index(i, cx).unwrap()));
+        }
+        _ => bail!(TypeError::new(Type::Sequence, sequence)),
+    }
+    Ok(())
+}
+
 #[defun]
 pub(crate) fn mapcar<'ob>(
     function: &Rto<Function>,
@@ -143,19 +364,18 @@ pub(crate) fn mapcar<'ob>(
             // TODO: remove this intermediate vector
             Ok(slice_into_list(Rt::bind_slice(outputs, cx), None, cx))
         }
-        ObjectType::ByteFn(fun) => {
-            let len = fun.len();
-            root!(fun, cx);
+        _ => {
+            root!(elements, new(Vec), cx);
+            elements.extend_from_slice(&sequence_elements(sequence, cx)?);
             root!(outputs, new(Vec), cx);
-            for i in 0..len {
-                let val = fun.bind(cx).index(i, cx).unwrap();
-                let output = call!(function, val; env, cx)?;
+            for i in 0..elements.len() {
+                let element = elements[i].bind(cx);
+                let output = call!(function, element; env, cx)?;
                 outputs.push(output);
             }
             // TODO: remove this intermediate vector
             Ok(slice_into_list(Rt::bind_slice(outputs, cx), None, cx))
         }
-        _ => Err(TypeError::new(Type::Sequence, sequence).into()),
     }
 }
 
@@ -178,6 +398,8 @@ pub(crate) fn mapc<'ob>(
     }
 }
 
+/// Apply `function` to each element of `sequence` and join the results with
+/// [`nconc`], so the lists it returns are modified.
 #[defun]
 pub(crate) fn mapcan<'ob>(
     function: &Rto<Function>,
@@ -193,16 +415,26 @@ pub(crate) fn mapcan<'ob>(
     nconc(&lists)
 }
 
+/// Apply `function` to each element of `sequence` and concatenate the results,
+/// with `separator` between them. If `function` is nil the elements are
+/// concatenated as they are.
 #[defun]
 pub(crate) fn mapconcat(
-    function: &Rto<Function>,
+    function: &Rto<Object>,
     sequence: &Rto<Object>,
-    seperator: Option<&Rto<Gc<&LispString>>>,
+    separator: Option<&Rto<Gc<&LispString>>>,
     env: &mut Rt<Env>,
     cx: &mut Context,
 ) -> Result<String> {
-    let mapped = rebind!(mapcar(function, sequence, env, cx)?);
-    let sep = match seperator {
+    let mapped = if function.bind(cx).is_nil() {
+        let elements = sequence_elements(sequence.bind(cx), cx)?;
+        slice_into_list(&elements, None, cx)
+    } else {
+        let function: Function = function.bind(cx).try_into()?;
+        root!(function, cx);
+        rebind!(mapcar(function, sequence, env, cx)?)
+    };
+    let sep = match separator {
         Some(sep) => sep.bind(cx).untag(),
         _ => "",
     };
@@ -214,12 +446,54 @@ pub(crate) fn mapconcat(
         } else {
             string.push_str(sep);
         }
-        let element: &str = element?.try_into()?;
-        string.push_str(element);
+        match element?.untag() {
+            ObjectType::NIL => {}
+            ObjectType::String(element) => string.push_str(element),
+            x => bail!(TypeError::new(Type::String, x)),
+        }
     }
     Ok(string)
 }
 
+/// Call `function` with the first element of each of `sequences`, then the
+/// second, and so on, stopping at the end of the shortest one. This is the
+/// general case of `cl-mapcar`. The results are returned as a list if
+/// `accumulate` is non-nil.
+#[defun]
+fn cl__mapcar_many<'ob>(
+    function: &Rto<Function>,
+    sequences: &Rto<Object>,
+    accumulate: Option<()>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    // The elements of all the sequences, one sequence after another
+    root!(elements, new(Vec), cx);
+    let mut starts = Vec::new();
+    let mut count = usize::MAX;
+    for sequence in sequences.bind(cx).as_list()? {
+        let sequence = sequence_elements(sequence?, cx)?;
+        starts.push(elements.len());
+        count = count.min(sequence.len());
+        elements.extend_from_slice(&sequence);
+    }
+    if starts.is_empty() {
+        count = 0;
+    }
+    root!(outputs, new(Vec), cx);
+    for i in 0..count {
+        let frame = &mut CallFrame::new(env);
+        for start in &starts {
+            frame.push_arg(elements[start + i].bind(cx))?;
+        }
+        let output = crate::eval::call_function(function, frame, None, cx)?;
+        if accumulate.is_some() {
+            outputs.push(output);
+        }
+    }
+    Ok(slice_into_list(Rt::bind_slice(outputs, cx), None, cx))
+}
+
 #[defun]
 pub(crate) fn nreverse(seq: List) -> Result<Object> {
     let mut prev = NIL;
@@ -258,46 +532,154 @@ pub(crate) fn nconc<'ob>(lists: &[List<'ob>]) -> Result<Object<'ob>> {
     })
 }
 
-fn join<'ob>(list: &mut Vec<Object<'ob>>, seq: List<'ob>) -> Result<()> {
-    if let ListType::Cons(cons) = seq.untag() {
-        for elt in cons {
-            list.push(elt?);
+#[defun]
+fn take<'ob>(n: i64, list: List<'ob>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let Ok(n) = usize::try_from(n) else { return Ok(NIL) };
+    Ok(build_list(list.elements().take(n), cx)?)
+}
+
+/// Like `take`, but modifies `list` by cutting it after the first `n`
+/// elements.
+#[defun]
+fn ntake<'ob>(n: i64, list: List<'ob>) -> Result<Object<'ob>> {
+    let Ok(n) = usize::try_from(n) else { return Ok(NIL) };
+    if n == 0 {
+        return Ok(NIL);
+    }
+    if let Some(cons) = list.conses().fallible().nth(n - 1)? {
+        if cons.cdr() != NIL {
+            cons.set_cdr(NIL)?;
         }
     }
-    Ok(())
+    Ok(list.into())
 }
 
+/// Return the last `n` conses of `list`, or the last cons if `n` is nil. A
+/// non-nil terminator of a dotted list is not counted.
 #[defun]
-fn take<'ob>(n: i64, list: List<'ob>, cx: &'ob Context) -> Result<Object<'ob>> {
-    let Ok(n) = usize::try_from(n) else { return Ok(NIL) };
-    Ok(build_list(list.elements().take(n), cx)?)
+fn last<'ob>(list: List<'ob>, n: Option<i64>) -> Result<Object<'ob>> {
+    let n = n.unwrap_or(1);
+    if n <= 0 {
+        return Ok(NIL);
+    }
+    let mut conses = Vec::new();
+    for cons in list.conses() {
+        match cons {
+            Ok(cons) => conses.push(cons),
+            Err(ConsError::NonNilCdr) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let Ok(n) = usize::try_from(n) else { return Ok(list.into()) };
+    Ok(match conses.len().checked_sub(n) {
+        Some(idx) => conses[idx].into(),
+        None => list.into(),
+    })
+}
+
+#[defun]
+fn make_list<'ob>(length: usize, init: Object<'ob>, cx: &'ob Context) -> Object<'ob> {
+    (0..length).fold(NIL, |acc, _| Cons::new(init, acc, cx).into())
 }
 
+/// Return a list of numbers from `from` to `to` in increments of `sep`.
 #[defun]
-pub(crate) fn append<'ob>(
-    append: Object<'ob>,
-    sequences: &[Object<'ob>],
+fn number_sequence<'ob>(
+    from: Number,
+    to: Option<Number>,
+    sep: Option<Number>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    let mut list = Vec::new();
-    match append.untag() {
-        ObjectType::String(string) => {
-            for ch in string.chars() {
-                list.push((ch as i64).into());
+    use std::cmp::Ordering;
+    let from = from.val();
+    let to = match to {
+        Some(to) if from.partial_cmp(&to.val()) != Some(Ordering::Equal) => to.val(),
+        _ => return Ok(list![from; cx]),
+    };
+    let sep = sep.map_or(NumberValue::Int(1), Number::val);
+    let ascending = match sep.partial_cmp(&NumberValue::Int(0)) {
+        Some(Ordering::Greater) => true,
+        Some(Ordering::Less) => false,
+        _ => bail!("The increment can not be zero"),
+    };
+    // Each element is computed from `from` rather than by repeated addition to
+    // avoid accumulating floating point error
+    let mut seq = Vec::new();
+    let mut next = from;
+    while if ascending { next <= to } else { next >= to } {
+        seq.push(cx.add(next));
+        next = from + sep * NumberValue::Int(seq.len() as i64);
+    }
+    Ok(slice_into_list(&seq, None, cx))
+}
+
+#[defun]
+fn ensure_list<'ob>(object: Object<'ob>, cx: &'ob Context) -> Object<'ob> {
+    match object.untag() {
+        ObjectType::Cons(_) | ObjectType::NIL => object,
+        _ => list![object; cx],
+    }
+}
+
+/// Return a flat list of the non-nil atoms in `tree`, in depth first order.
+/// The tree is walked with an explicit stack, and a list that contains
+/// itself signals a `circular-list` error.
+#[defun]
+fn flatten_tree<'ob>(tree: Object<'ob>, cx: &'ob Context) -> Result<Object<'ob>> {
+    enum Work<'ob> {
+        Visit(Object<'ob>),
+        /// A list has been flattened, so it is no longer on the path
+        Leave(*const u8),
+    }
+    let mut atoms = Vec::new();
+    let mut path = Ancestors::default();
+    let mut stack = vec![Work::Visit(tree)];
+    while let Some(work) = stack.pop() {
+        let obj = match work {
+            Work::Visit(obj) => obj,
+            Work::Leave(ptr) => {
+                path.leave(ptr);
+                continue;
             }
+        };
+        let ObjectType::Cons(cons) = obj.untag() else {
+            if obj != NIL {
+                atoms.push(obj);
+            }
+            continue;
+        };
+        if !path.enter(address(cons), ()) {
+            return Err(ConsError::CircularList.into());
         }
-        ObjectType::ByteString(string) => {
-            for ch in string.iter() {
-                list.push((*ch as i64).into());
+        stack.push(Work::Leave(address(cons)));
+        let mut elements = Vec::new();
+        for cons in cons.conses() {
+            let cons = match cons {
+                Ok(cons) => cons,
+                Err(ConsError::NonNilCdr) => break,
+                Err(e) => return Err(e.into()),
+            };
+            elements.push(cons.car());
+            if !matches!(cons.cdr().untag(), ObjectType::Cons(_)) {
+                // the terminator of a dotted list
+                elements.push(cons.cdr());
             }
         }
-        _ => join(&mut list, append.try_into()?)?,
+        stack.extend(elements.into_iter().rev().map(Work::Visit));
     }
-    for seq in sequences {
-        join(&mut list, (*seq).try_into()?)?;
+    Ok(slice_into_list(&atoms, None, cx))
+}
+
+/// Concatenate `sequences` into a list. The last argument is not copied, but
+/// becomes the tail of the result, so it can be any object.
+#[defun]
+pub(crate) fn append<'ob>(sequences: &[Object<'ob>], cx: &'ob Context) -> Result<Object<'ob>> {
+    let Some((last, sequences)) = sequences.split_last() else { return Ok(NIL) };
+    let mut elements = Vec::new();
+    for sequence in sequences {
+        extend_elements(&mut elements, *sequence, cx)?;
     }
-    // TODO: Remove this temp vector
-    Ok(slice_into_list(&list, None, cx))
+    Ok(slice_into_list(&elements, Some(*last), cx))
 }
 
 #[defun]
@@ -392,6 +774,97 @@ fn copy_alist_elem<'ob>(elem: Object<'ob>, cx: &'ob Context) -> Object<'ob> {
     }
 }
 
+/// Make a copy of `tree`, copying conses along both their car and cdr. With
+/// `vecp`, vectors and records are copied as well. Circular structure is
+/// copied into the same shape.
+#[defun]
+pub(crate) fn copy_tree<'ob>(
+    tree: Object<'ob>,
+    vecp: Option<()>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    copy_tree_walk(tree, vecp.is_some(), &mut Ancestors::default(), cx)
+}
+
+/// Copy `tree` for [`copy_tree`]. Each object on `path` maps to its copy, so
+/// that reaching one of them again links to the copy.
+fn copy_tree_walk<'ob>(
+    tree: Object<'ob>,
+    vecp: bool,
+    path: &mut Ancestors<Object<'ob>>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    match tree.untag() {
+        ObjectType::Cons(cons) => copy_tree_list(cons, vecp, path, cx),
+        ObjectType::Vec(vec) if vecp => {
+            if let Some(&copy) = path.get(address(vec)) {
+                return Ok(copy);
+            }
+            let copy = cx.add(vec.iter().map(|x| x.get()).collect::<Vec<_>>());
+            let ObjectType::Vec(slots) = copy.untag() else { unreachable!() };
+            path.enter(address(vec), copy);
+            for slot in slots.try_mut()? {
+                slot.set(copy_tree_walk(slot.get(), vecp, path, cx)?);
+            }
+            path.leave(address(vec));
+            Ok(copy)
+        }
+        ObjectType::Record(record) if vecp => {
+            if let Some(&copy) = path.get(address(record)) {
+                return Ok(copy);
+            }
+            let mut builder = cx.vec_new();
+            builder.extend(record.iter().map(|x| x.get()));
+            let copy = cx.add(RecordBuilder(builder));
+            let ObjectType::Record(slots) = copy.untag() else { unreachable!() };
+            path.enter(address(record), copy);
+            for slot in slots.try_mut()? {
+                slot.set(copy_tree_walk(slot.get(), vecp, path, cx)?);
+            }
+            path.leave(address(record));
+            Ok(copy)
+        }
+        _ => Ok(tree),
+    }
+}
+
+/// Copy the list starting at `list` for [`copy_tree_walk`]. The cdrs are
+/// followed in a loop, so only the cars recurse.
+fn copy_tree_list<'ob>(
+    list: &'ob Cons,
+    vecp: bool,
+    path: &mut Ancestors<Object<'ob>>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    if let Some(&copy) = path.get(address(list)) {
+        return Ok(copy);
+    }
+    let head = Cons::new1(NIL, cx);
+    path.enter(address(list), head.into());
+    let mut entered = vec![address(list)];
+    let (mut cons, mut copy) = (list, head);
+    loop {
+        copy.set_car(copy_tree_walk(cons.car(), vecp, path, cx)?)?;
+        let ObjectType::Cons(next) = cons.cdr().untag() else {
+            copy.set_cdr(copy_tree_walk(cons.cdr(), vecp, path, cx)?)?;
+            break;
+        };
+        if let Some(&back) = path.get(address(next)) {
+            copy.set_cdr(back)?;
+            break;
+        }
+        let next_copy = Cons::new1(NIL, cx);
+        path.enter(address(next), next_copy.into());
+        entered.push(address(next));
+        copy.set_cdr(next_copy.into())?;
+        (cons, copy) = (next, next_copy);
+    }
+    for ptr in entered {
+        path.leave(ptr);
+    }
+    Ok(head.into())
+}
+
 fn delete_from_list<'ob>(elt: Object<'ob>, list: List<'ob>, eq_fn: EqFunc) -> Result<Object<'ob>> {
     let mut head = list.into();
     let mut prev: Option<&'ob Cons> = None;
@@ -443,49 +916,179 @@ pub(crate) fn member<'ob>(elt: Object<'ob>, list: List<'ob>) -> Result<Object<'o
     member_of_list(elt, list, equal)
 }
 
-// TODO: Handle sorting vectors
-// TODO: Sort items in place
+/// Sort `seq`, a list or vector. This supports both the traditional
+/// `(sort SEQ PREDICATE)` form, which sorts in place, and the
+/// `(sort SEQ &key KEY LESSP REVERSE IN-PLACE)` form, which returns a sorted
+/// copy unless `IN-PLACE` is non-nil. The sort is always stable.
 #[defun]
 fn sort<'ob>(
-    seq: &Rto<List>,
-    predicate: &Rto<Function>,
+    seq: &Rto<Object>,
+    args: ArgSlice,
     env: &mut Rt<Env>,
     cx: &'ob mut Context,
 ) -> Result<Object<'ob>> {
-    let vec: Vec<_> = seq.bind(cx).elements().fallible().collect()?;
-    let len = vec.len();
-    if len <= 1 {
-        return Ok(seq.bind(cx).into());
-    }
-
-    root!(tmp, NIL, cx);
-    root!(vec, cx);
-    // A simple insertion sort
-    // TODO: use a better sort like tim sort
-    for i in 1..len {
-        tmp.set(&vec[i]);
-        let mut j = i;
-        while j > 0 {
-            let result = call!(predicate, &*tmp, &vec[j - 1]; env, cx);
-            // check if elements are out of order
-            match result {
-                Ok(cmp) => {
-                    if cmp == NIL {
-                        break;
-                    }
-                }
-                Err(e) => {
-                    vec[j].set(&*tmp);
-                    return Err(e.into());
+    root!(key, NIL, cx);
+    root!(lessp, NIL, cx);
+    let mut reverse = false;
+    let mut in_place = false;
+    // The arguments have to be read before calling any lisp functions, since
+    // that will push new frames on the stack.
+    let arg_count = args.len();
+    if arg_count == 1 {
+        lessp.set(env.stack[0].bind(cx));
+        in_place = true;
+    } else {
+        ensure!(arg_count % 2 == 0, "Odd number of keyword arguments to sort");
+        for i in (0..arg_count).step_by(2) {
+            let keyword = env.stack[arg_count - i - 1].bind(cx);
+            let value = env.stack[arg_count - i - 2].bind(cx);
+            match keyword.untag() {
+                ObjectType::Symbol(sym::KW_KEY) => key.set(value),
+                ObjectType::Symbol(sym::KW_LESSP) => lessp.set(value),
+                ObjectType::Symbol(sym::KW_REVERSE) => reverse = value != NIL,
+                ObjectType::Symbol(sym::KW_IN_PLACE) => in_place = value != NIL,
+                _ => bail!("Invalid keyword argument to sort: {keyword}"),
+            }
+        }
+    }
+
+    root!(values, new(Vec<Slot<Object>>), cx);
+    match seq.untag(cx) {
+        ObjectType::NIL => return Ok(NIL),
+        ObjectType::Cons(cons) => {
+            for x in cons.elements() {
+                values.push(x?);
+            }
+        }
+        ObjectType::Vec(vec) => {
+            for x in vec.iter() {
+                values.push(x.get());
+            }
+        }
+        x => bail!(TypeError::new(Type::Sequence, x)),
+    }
+
+    // The key function is called once per element
+    root!(keys, new(Vec<Slot<Object>>), cx);
+    if let Some(key) = key.try_as_option::<Function, _>()? {
+        for i in 0..values.len() {
+            let result = call!(key, &values[i]; env, cx)?;
+            keys.push(result);
+        }
+    }
+    let keys = if keys.is_empty() { &*values } else { &*keys };
+
+    let lessp = lessp.try_as_option::<Function, _>()?;
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    // Reversing before and after a stable sort keeps equal elements in their
+    // original order
+    if reverse {
+        order.reverse();
+    }
+    merge_sort(&mut order, |a, b| match lessp {
+        Some(lessp) => Ok(call!(lessp, &keys[a], &keys[b]; env, cx)? != NIL),
+        None => value_less_p(keys[a].bind(cx), keys[b].bind(cx)),
+    })?;
+    if reverse {
+        order.reverse();
+    }
+
+    let seq = seq.bind(cx);
+    let sorted: Vec<Object> = order.iter().map(|&i| values[i].bind(cx)).collect();
+    match seq.untag() {
+        ObjectType::Cons(cons)
+            if in_place && cons.conses().all(|x| x.is_ok_and(|x| x.is_mutable())) =>
+        {
+            for (cons, value) in cons.conses().zip(sorted) {
+                cons?.set_car(value)?;
+            }
+            Ok(seq)
+        }
+        ObjectType::Vec(vec) if in_place => {
+            for (cell, value) in vec.try_mut()?.iter().zip(sorted) {
+                cell.set(value);
+            }
+            Ok(seq)
+        }
+        ObjectType::Vec(_) => Ok(cx.add(sorted)),
+        // Lists that can't be modified are sorted into a new list
+        _ => Ok(slice_into_list(&sorted, None, cx)),
+    }
+}
+
+/// A stable bottom-up merge sort of `order`. The standard library sorts can't
+/// be used because `less` calls into lisp and can fail.
+fn merge_sort(
+    order: &mut Vec<usize>,
+    mut less: impl FnMut(usize, usize) -> Result<bool>,
+) -> Result<()> {
+    let len = order.len();
+    let mut merged = order.clone();
+    let mut width = 1;
+    while width < len {
+        for start in (0..len).step_by(2 * width) {
+            let mid = (start + width).min(len);
+            let end = (start + 2 * width).min(len);
+            let (mut left, mut right, mut out) = (start, mid, start);
+            while left < mid && right < end {
+                // Only take from the right when it is strictly less, so that
+                // equal elements keep their order
+                if less(order[right], order[left])? {
+                    merged[out] = order[right];
+                    right += 1;
+                } else {
+                    merged[out] = order[left];
+                    left += 1;
                 }
+                out += 1;
             }
-            let left = vec[j - 1].bind(cx);
-            vec[j].set(left);
-            j -= 1;
+            let rest = mid - left;
+            merged[out..out + rest].copy_from_slice(&order[left..mid]);
+            merged[out + rest..end].copy_from_slice(&order[right..end]);
         }
-        vec[j].set(&*tmp);
+        std::mem::swap(order, &mut merged);
+        width *= 2;
     }
-    Ok(slice_into_list(Rt::bind_slice(vec, cx), None, cx))
+    Ok(())
+}
+
+/// The default ordering used by `sort`, following `value<`. Numbers, strings
+/// and symbols compare naturally, and lists and vectors compare
+/// lexicographically.
+#[defun(name = "value<")]
+fn value_less_p(a: Object, b: Object) -> Result<bool> {
+    Ok(match (a.untag(), b.untag()) {
+        (ObjectType::Int(_) | ObjectType::Float(_), ObjectType::Int(_) | ObjectType::Float(_)) => {
+            let (a, b): (Number, Number) = (a.try_into()?, b.try_into()?);
+            a.val() < b.val()
+        }
+        (ObjectType::String(a), ObjectType::String(b)) => a.as_ref() < b.as_ref(),
+        (ObjectType::Symbol(a), ObjectType::Symbol(b)) => a.name() < b.name(),
+        (ObjectType::Cons(_) | ObjectType::NIL, ObjectType::Cons(_) | ObjectType::NIL) => {
+            let (mut a, mut b) = (a.as_list()?, b.as_list()?);
+            loop {
+                match (a.next().transpose()?, b.next().transpose()?) {
+                    (Some(x), Some(y)) if value_less_p(x, y)? => break true,
+                    (Some(x), Some(y)) if value_less_p(y, x)? => break false,
+                    (Some(_), Some(_)) => {}
+                    (None, rest) => break rest.is_some(),
+                    (Some(_), None) => break false,
+                }
+            }
+        }
+        (ObjectType::Vec(a), ObjectType::Vec(b)) => {
+            for (x, y) in a.iter().zip(b.iter()) {
+                if value_less_p(x.get(), y.get())? {
+                    return Ok(true);
+                }
+                if value_less_p(y.get(), x.get())? {
+                    return Ok(false);
+                }
+            }
+            a.len() < b.len()
+        }
+        _ => bail!("value<: cannot compare {a} with {b}"),
+    })
 }
 
 #[defun]
@@ -512,7 +1115,7 @@ pub(crate) fn require<'ob>(
 ) -> Result<Symbol<'ob>> {
     // TODO: Fix this unsafe into_root
     let feat = unsafe { feature.untag(cx).with_lifetime() };
-    if crate::data::features().lock().unwrap().contains(&feat) {
+    if crate::data::with_features(|features| features.contains(&feat)) {
         return Ok(feature.untag(cx));
     }
     let file = match filename {
@@ -527,43 +1130,34 @@ pub(crate) fn require<'ob>(
     }
 }
 
+/// Concatenate `sequences` into a string. Elements of lists and vectors
+/// have to be characters.
 #[defun]
-pub(crate) fn concat(sequences: &[Object]) -> Result<String> {
+pub(crate) fn concat(sequences: &[Object], cx: &Context) -> Result<String> {
     let mut concat = String::new();
-    for elt in sequences {
-        match elt.untag() {
-            ObjectType::String(string) => concat += string,
-            ObjectType::NIL => continue,
-            _ => bail!("Currently only concatenating strings are supported"),
+    for sequence in sequences {
+        if let ObjectType::String(string) = sequence.untag() {
+            concat += string;
+            continue;
+        }
+        for elt in sequence_elements(*sequence, cx)? {
+            let chr = match elt.untag() {
+                ObjectType::Int(x) => u32::try_from(x).ok().and_then(char::from_u32),
+                _ => None,
+            };
+            let Some(chr) = chr else { bail!(TypeError::new(Type::Char, elt)) };
+            concat.push(chr);
         }
     }
     Ok(concat)
 }
 
+/// Concatenate `sequences` into a vector.
 #[defun]
 pub(crate) fn vconcat<'ob>(sequences: &[Object], cx: &'ob Context) -> Result<Gc<&'ob LispVec>> {
-    let mut concated: Vec<Object> = Vec::new();
-    for elt in sequences {
-        match elt.untag() {
-            // TODO: need to correctly handle unibyte strings (no unicode codepoints)
-            ObjectType::String(string) => {
-                for chr in string.chars() {
-                    concated.push((chr as i64).into());
-                }
-            }
-            ObjectType::Cons(cons) => {
-                for x in cons {
-                    concated.push(x?);
-                }
-            }
-            ObjectType::Vec(vec) => {
-                for x in vec.iter() {
-                    concated.push(x.get());
-                }
-            }
-            ObjectType::NIL => {}
-            obj => bail!(TypeError::new(Type::Sequence, obj)),
-        }
+    let mut concated = Vec::new();
+    for sequence in sequences {
+        extend_elements(&mut concated, *sequence, cx)?;
     }
     Ok(concated.into_obj(cx))
 }
@@ -596,29 +1190,64 @@ pub(crate) fn proper_list_p(object: Object) -> Option<usize> {
     }
 }
 
+/// Return the `n`th element of `list`, counting from zero. A negative `n`
+/// is treated as zero, and nil is returned if the list is too short.
 #[defun]
-pub(crate) fn nth(n: usize, list: List) -> Result<Object> {
-    Ok(list.elements().fallible().nth(n)?.unwrap_or_default())
+pub(crate) fn nth(n: i64, list: Object) -> Result<Object> {
+    match nthcdr(n, list)?.untag() {
+        ObjectType::Cons(cons) => Ok(cons.car()),
+        ObjectType::NIL => Ok(NIL),
+        other => Err(TypeError::new(Type::List, other).into()),
+    }
 }
 
+/// Take `cdr` `n` times on `list` and return the result. A negative `n` is
+/// treated as zero, and the tail of a dotted list is returned if it is
+/// reached exactly.
 #[defun]
-pub(crate) fn nthcdr(n: usize, list: List) -> Result<List> {
-    match list.conses().fallible().nth(n)? {
-        Some(x) => Ok(x.into()),
-        None => Ok(ListType::empty()),
+pub(crate) fn nthcdr(n: i64, list: Object) -> Result<Object> {
+    let mut tail = list;
+    let mut remaining = n;
+    // `slow` moves one cons for every two that `tail` does. If they meet the
+    // list is circular, and the distance between them is a multiple of its
+    // length, so an index much larger than the list doesn't walk all of it.
+    let mut slow = list;
+    let mut steps: i64 = 0;
+    while remaining > 0 {
+        match tail.untag() {
+            ObjectType::Cons(cons) => tail = cons.cdr(),
+            ObjectType::NIL => return Ok(NIL),
+            other => bail!(TypeError::new(Type::List, other)),
+        }
+        remaining -= 1;
+        steps += 1;
+        if steps % 2 == 0 {
+            if let ObjectType::Cons(cons) = slow.untag() {
+                slow = cons.cdr();
+            }
+            if eq(slow, tail) && matches!(tail.untag(), ObjectType::Cons(_)) {
+                remaining %= steps / 2;
+            }
+        }
     }
+    Ok(tail)
 }
 
+/// Return element `n` of `sequence`. Lists behave like `nth`, while arrays
+/// signal `args-out-of-range` for an index outside of them.
 #[defun]
-pub(crate) fn elt<'ob>(sequence: Object<'ob>, n: usize, cx: &'ob Context) -> Result<Object<'ob>> {
-    match sequence.untag() {
-        ObjectType::Cons(x) => nth(n, x.into()),
-        ObjectType::NIL => Ok(NIL),
-        ObjectType::Vec(x) => aref(x.into(), n, cx),
-        ObjectType::Record(x) => aref(x.into(), n, cx),
-        ObjectType::String(x) => aref(x.into(), n, cx),
-        ObjectType::ByteFn(x) => aref(x.into(), n, cx),
-        other => Err(TypeError::new(Type::Sequence, other).into()),
+pub(crate) fn elt<'ob>(sequence: Object<'ob>, n: i64, cx: &'ob Context) -> Result<Object<'ob>> {
+    let len = match sequence.untag() {
+        ObjectType::Cons(_) | ObjectType::NIL => return nth(n, sequence),
+        ObjectType::Vec(x) => x.len(),
+        ObjectType::Record(x) => x.len(),
+        ObjectType::String(x) => x.chars().count(),
+        ObjectType::ByteFn(x) => x.len(),
+        other => bail!(TypeError::new(Type::Sequence, other)),
+    };
+    match usize::try_from(n) {
+        Ok(idx) if idx < len => aref(sequence, idx, cx),
+        _ => Err(ArgRangeError::new(&[sequence, n.into()]).into()),
     }
 }
 
@@ -627,6 +1256,10 @@ pub(crate) fn elt<'ob>(sequence: Object<'ob>, n: usize, cx: &'ob Context) -> Res
 ///////////////
 
 defsym!(KW_TEST);
+defsym!(KW_KEY);
+defsym!(KW_LESSP);
+defsym!(KW_REVERSE);
+defsym!(KW_IN_PLACE);
 defsym!(KW_DOCUMENTATION);
 
 #[defun]
@@ -635,18 +1268,41 @@ pub(crate) fn make_hash_table<'ob>(
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
     let kw_test_pos = keyword_args.iter().step_by(2).position(|&x| x == sym::KW_TEST);
+    let mut test = HashTableTest::default();
     if let Some(i) = kw_test_pos {
         let Some(val) = keyword_args.get((i * 2) + 1) else {
             bail!("Missing keyword value for :test")
         };
-        if *val != sym::EQ && *val != sym::EQUAL && *val != sym::EQL {
-            // TODO: we are currently only using `equal', but eq should be okay
-            bail!("only `eq' and `equal' keywords support for make-hash-table :test. Found {val}");
-        }
+        let name: Symbol = (*val).try_into()?;
+        let Some(x) = HashTableTest::from_symbol(name) else {
+            bail!("Invalid hash table test: {name}")
+        };
+        test = x;
     }
     // TODO, the rest of the keywords need to be supported here
-    let map = HashTable::with_hasher(std::hash::BuildHasherDefault::default());
-    Ok(cx.add(map))
+    let table = HashTable::default().into_obj(cx);
+    table.untag().set_test(test);
+    Ok(table.into())
+}
+
+/// Return the symbol of the function `table` compares keys with.
+#[defun]
+fn hash_table_test(table: &LispHashTable) -> Symbol {
+    table.test().symbol()
+}
+
+/// Return the number of entries in `table`.
+#[defun]
+fn hash_table_count(table: &LispHashTable) -> usize {
+    table.len()
+}
+
+/// Remove all entries from `table`.
+#[defun]
+fn clrhash<'ob>(table: &'ob LispHashTable) -> &'ob LispHashTable {
+    table.clear();
+    table.set_iter_index(0);
+    table
 }
 
 #[defun]
@@ -678,16 +1334,7 @@ pub(crate) fn puthash<'ob>(
 
 #[defun]
 fn remhash(key: Object, table: &LispHashTable) -> Result<()> {
-    let Some(idx) = table.get_index_of(key) else { return Ok(()) };
-    // If the removed element is before our iterator, then we need to shift the
-    // iterator back one because the whole map get's shifted when something is
-    // removed.
-    let iter_idx = table.get_iter_index();
-    if idx < iter_idx {
-        table.set_iter_index(iter_idx - 1);
-    }
-    // TODO: can we use swap_remove?
-    table.shift_remove(key);
+    table.swap_remove(key);
     Ok(())
 }
 
@@ -743,26 +1390,237 @@ fn copy_sequence<'ob>(arg: Object<'ob>, cx: &'ob Context) -> Result<Object<'ob>>
             Ok(slice_into_list(&elements, tail, cx))
         }
         ObjectType::String(x) => Ok(cx.add(x.to_owned())),
+        ObjectType::ByteString(x) => Ok(cx.add(x.to_vec())),
+        ObjectType::Record(x) => {
+            let mut record = cx.vec_with_capacity(x.len());
+            record.extend(x.iter().map(|x| x.get()));
+            Ok(cx.add(RecordBuilder(record)))
+        }
         ObjectType::NIL => Ok(NIL),
         _ => Err(TypeError::new(Type::Sequence, arg).into()),
     }
 }
 
+/// The byte range of `string` between the char indices `from` and `to`, which
+/// count from the end of the string when negative.
+fn char_range(string: &LispString, from: Option<i64>, to: Option<i64>) -> Result<Range<usize>> {
+    let text = string.inner();
+    let len = text.chars().count() as i64;
+    let resolve = |idx: i64| if idx < 0 { idx + len } else { idx };
+    let start = resolve(from.unwrap_or(0));
+    let end = resolve(to.unwrap_or(len));
+    if start < 0 || start > end || end > len {
+        bail!(ArgRangeError::new(&[Object::from(string), from.into(), to.into()]));
+    }
+    let offset = |idx: i64| text.char_indices().nth(idx as usize).map_or(text.len(), |x| x.0);
+    Ok(offset(start)..offset(end))
+}
+
+#[defun]
+fn substring(string: &LispString, from: Option<i64>, to: Option<i64>) -> Result<String> {
+    Ok(string.inner()[char_range(string, from, to)?].to_owned())
+}
+
+/// Strings don't have text properties, so this is the same as `substring`.
+#[defun]
+fn substring_no_properties(
+    string: &LispString,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<String> {
+    substring(string, from, to)
+}
+
+#[defun]
+fn string_pad(
+    string: &str,
+    length: usize,
+    padding: Option<usize>,
+    start: Option<()>,
+) -> Result<String> {
+    let padding = match padding {
+        Some(chr) => crate::core::object::int_to_char(i64::try_from(chr)?)?,
+        None => ' ',
+    };
+    let pad_length = length.saturating_sub(string.chars().count());
+    let pad: String = std::iter::repeat(padding).take(pad_length).collect();
+    Ok(match start {
+        Some(()) => pad + string,
+        None => string.to_owned() + &pad,
+    })
+}
+
+/// Word wrap `paragraph` so that no lines are longer than `length`, except
+/// for single words that don't fit.
+fn fill_paragraph(paragraph: &str, length: usize) -> String {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in paragraph.split_whitespace() {
+        let width = line.chars().count() + 1 + word.chars().count();
+        if !line.is_empty() && width > length {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
 #[defun]
-fn substring(string: &str, from: Option<usize>, to: Option<usize>) -> Result<String> {
-    if from.unwrap_or_default() > string.len() || to.unwrap_or_default() > string.len() {
-        bail!("substring args out of range for {string} : {from:?} {to:?}");
+fn string_fill(string: &str, length: usize) -> String {
+    let paragraphs: Vec<_> = string.split("\n\n").map(|x| fill_paragraph(x, length)).collect();
+    paragraphs.join("\n\n")
+}
+
+#[defun]
+fn string_chop_newline(string: &str) -> String {
+    string.strip_suffix('\n').unwrap_or(string).to_owned()
+}
+
+/// Apply the flags, width, and precision of a `format-spec` specifier to
+/// `text`. The precision truncates the text, and so does the width when the
+/// `<` or `>` flag is given. Truncation removes characters from the end unless
+/// the `<` flag is given.
+fn format_spec_flags(
+    text: &str,
+    flags: &[char],
+    width: Option<usize>,
+    precision: Option<usize>,
+) -> String {
+    let truncate = |text: String, max: usize| {
+        let len = text.chars().count();
+        match len <= max {
+            true => text,
+            false if flags.contains(&'<') => text.chars().skip(len - max).collect(),
+            false => text.chars().take(max).collect(),
+        }
+    };
+    let mut text = if flags.contains(&'^') {
+        text.to_uppercase()
+    } else if flags.contains(&'_') {
+        text.to_lowercase()
+    } else {
+        text.to_owned()
+    };
+    if let Some(precision) = precision {
+        text = truncate(text, precision);
+    }
+    if let Some(width) = width {
+        let len = text.chars().count();
+        if len < width {
+            let pad = if flags.contains(&'0') { '0' } else { ' ' };
+            let padding: String = std::iter::repeat(pad).take(width - len).collect();
+            text = if flags.contains(&'-') { text + &padding } else { padding + &text };
+        } else if flags.contains(&'<') || flags.contains(&'>') {
+            text = truncate(text, width);
+        }
+    }
+    text
+}
+
+fn format_spec_number(chars: &mut std::iter::Peekable<std::str::CharIndices>) -> Option<usize> {
+    let mut digits = String::new();
+    while let Some((_, digit)) = chars.next_if(|(_, x)| x.is_ascii_digit()) {
+        digits.push(digit);
     }
-    let new_string = match (from, to) {
-        (None, None) => string,
-        (None, Some(t)) => &string[..t],
-        (Some(f), None) => &string[f..],
-        (Some(f), Some(t)) => {
-            let range = if f > t { t..f } else { f..t };
-            &string[range]
+    digits.parse().ok()
+}
+
+/// The text for the specifier `chr` in a `format-spec` specification. Functions
+/// are called to get their value. Returns `None` if `chr` is not in the
+/// specification.
+fn format_spec_value(
+    specification: &Rto<Object>,
+    chr: char,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<Option<String>> {
+    let mut found = None;
+    for entry in specification.bind(cx).as_list()? {
+        if let ObjectType::Cons(entry) = entry?.untag() {
+            if entry.car() == i64::from(u32::from(chr)) {
+                found = Some(entry.cdr());
+                break;
+            }
         }
+    }
+    let Some(value) = found else { return Ok(None) };
+    let value = if crate::data::functionp(value) {
+        let func: Function = value.try_into()?;
+        root!(func, cx);
+        rebind!(call!(func; env, cx)?)
+    } else {
+        value
     };
-    Ok(new_string.to_owned())
+    Ok(Some(match value.untag() {
+        ObjectType::String(string) => string.to_string(),
+        other => other.to_string(),
+    }))
+}
+
+#[defun]
+fn format_spec<'ob>(
+    format: &Rto<Gc<&LispString>>,
+    specification: &Rto<Object>,
+    ignore_missing: Option<Object>,
+    split: Option<()>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    // nil signals an error, `delete` removes the specifier and anything else
+    // leaves it as is
+    let (ignore, delete) = match ignore_missing {
+        None => (false, false),
+        Some(x) => (!x.is_nil(), x == sym::DELETE),
+    };
+    let format = format.untag(cx).to_string();
+    // Each specifier is its own part, for when the result is split
+    let mut parts = vec![String::new()];
+    let mut chars = format.char_indices().peekable();
+    while let Some((start, chr)) = chars.next() {
+        if chr != '%' {
+            parts.last_mut().unwrap().push(chr);
+            continue;
+        }
+        let mut flags = Vec::new();
+        while let Some((_, flag)) = chars.next_if(|(_, x)| "0-^_<>".contains(*x)) {
+            flags.push(flag);
+        }
+        let width = format_spec_number(&mut chars);
+        let precision = match chars.next_if(|(_, x)| *x == '.') {
+            Some(_) => format_spec_number(&mut chars),
+            None => None,
+        };
+        let Some((end, spec)) = chars.next() else { bail!("Invalid format string: {format}") };
+        if spec == '%' {
+            parts.last_mut().unwrap().push('%');
+            continue;
+        }
+        match format_spec_value(specification, spec, env, cx)? {
+            Some(text) => {
+                parts.push(format_spec_flags(&text, &flags, width, precision));
+                parts.push(String::new());
+            }
+            None if delete => {}
+            None if ignore => {
+                let specifier = &format[start..end + spec.len_utf8()];
+                parts.last_mut().unwrap().push_str(specifier);
+            }
+            None => bail!("Invalid format character: `%{spec}'"),
+        }
+    }
+    if split.is_some() {
+        let parts: Vec<Object> =
+            parts.into_iter().filter(|x| !x.is_empty()).map(|x| cx.add(x)).collect();
+        Ok(slice_into_list(&parts, None, cx))
+    } else {
+        Ok(cx.add(parts.concat()))
+    }
 }
 
 defsym!(MD5);
@@ -774,13 +1632,286 @@ defsym!(SHA512);
 
 #[defun]
 fn secure_hash_algorithms<'ob>(cx: &'ob Context) -> Object<'ob> {
-    // https://crates.io/crates/md-5
-    // https://crates.io/crates/sha1
-    // https://crates.io/crates/sha2
-    // https://crates.io/crates/digest ?
     list![sym::MD5, sym::SHA1, sym::SHA224, sym::SHA256, sym::SHA384, sym::SHA512; cx]
 }
 
+/// The hash of the bytes of `object` between `start` and `end`. For strings
+/// negative positions count from the end. Buffer text is hashed in place.
+fn hash_object(
+    algorithm: Symbol,
+    object: Object,
+    start: Option<i64>,
+    end: Option<i64>,
+    env: &Rt<Env>,
+) -> Result<Vec<u8>> {
+    let bounds = |len: usize| {
+        let len = len as i64;
+        let index = |pos: i64| if pos < 0 { pos + len } else { pos };
+        let (from, to) = (index(start.unwrap_or(0)), index(end.unwrap_or(len)));
+        ensure!(0 <= from && from <= to && to <= len, ArgRangeError::new(&[from, to]));
+        Ok((from as usize, to as usize))
+    };
+    match object.untag() {
+        ObjectType::String(string) => {
+            let (from, to) = bounds(string.chars().count())?;
+            let string: String = string.chars().skip(from).take(to - from).collect();
+            digest(algorithm, &[string.as_bytes()])
+        }
+        ObjectType::ByteString(string) => {
+            let (from, to) = bounds(string.len())?;
+            digest(algorithm, &[&string[from..to]])
+        }
+        ObjectType::Buffer(buffer) => {
+            let hash = env.with_buffer(Some(buffer), |b| {
+                let len = b.text.len_chars();
+                let (from, to) = (start.unwrap_or(0), end.unwrap_or(len as i64));
+                let (from, to) = (from.min(to), from.max(to));
+                ensure!(0 <= from && to <= len as i64, ArgRangeError::new(&[from, to]));
+                let [before, after] = b.text.slices(from as usize..to as usize);
+                digest(algorithm, &[before.as_bytes(), after.as_bytes()])
+            });
+            match hash {
+                Some(hash) => hash,
+                None => bail!("Selecting deleted buffer"),
+            }
+        }
+        _ => Err(TypeError::new(Type::String, object).into()),
+    }
+}
+
+/// The hash of the concatenation of `parts`.
+fn digest(algorithm: Symbol, parts: &[&[u8]]) -> Result<Vec<u8>> {
+    fn hash<D: sha2::Digest>(parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = D::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().to_vec()
+    }
+    Ok(match algorithm {
+        sym::MD5 => hash::<::md5::Md5>(parts),
+        sym::SHA1 => hash::<::sha1::Sha1>(parts),
+        sym::SHA224 => hash::<sha2::Sha224>(parts),
+        sym::SHA256 => hash::<sha2::Sha256>(parts),
+        sym::SHA384 => hash::<sha2::Sha384>(parts),
+        sym::SHA512 => hash::<sha2::Sha512>(parts),
+        _ => bail!("Invalid algorithm arg: {algorithm}"),
+    })
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[defun]
+fn secure_hash<'ob>(
+    algorithm: Symbol,
+    object: Object,
+    start: Option<i64>,
+    end: Option<i64>,
+    binary: Option<()>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let hash = hash_object(algorithm, object, start, end, env)?;
+    Ok(match binary {
+        Some(()) => cx.add(hash),
+        None => cx.add(to_hex(&hash)),
+    })
+}
+
+#[defun]
+fn md5(
+    object: Object,
+    start: Option<i64>,
+    end: Option<i64>,
+    _coding_system: Option<Object>,
+    _noerror: Option<Object>,
+    env: &Rt<Env>,
+) -> Result<String> {
+    let hash = hash_object(sym::MD5, object, start, end, env)?;
+    Ok(to_hex(&hash))
+}
+
+#[defun]
+fn sha1<'ob>(
+    object: Object,
+    start: Option<i64>,
+    end: Option<i64>,
+    binary: Option<()>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    secure_hash(sym::SHA1, object, start, end, binary, env, cx)
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+/// Encoded lines are broken at this length, as MIME requires.
+const MIME_LINE_LENGTH: usize = 76;
+
+/// The bytes of `text` for base64 encoding. Characters up to 255 stand for
+/// raw bytes.
+fn base64_bytes(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|chr| u8::try_from(u32::from(chr)))
+        .collect::<Result<_, _>>()
+        .map_err(|_| anyhow::anyhow!("Multibyte character in data for base64 encoding"))
+}
+
+fn base64_encode(bytes: &[u8], url: bool, pad: bool, line_break: bool) -> String {
+    let alphabet = if url { BASE64_URL } else { BASE64 };
+    let mut out = String::with_capacity(bytes.len() * 4 / 3 + 4);
+    let mut line_len = 0;
+    for chunk in bytes.chunks(3) {
+        if line_break && line_len == MIME_LINE_LENGTH {
+            out.push('\n');
+            line_len = 0;
+        }
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, &b)| acc | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(alphabet[(group >> (18 - 6 * i) & 0x3F) as usize]));
+            } else if pad {
+                out.push('=');
+            }
+        }
+        line_len += 4;
+    }
+    out
+}
+
+fn base64_decode(text: &str, url: bool, ignore_invalid: bool) -> Result<Vec<u8>> {
+    let alphabet = if url { BASE64_URL } else { BASE64 };
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut group, mut bits) = (0u32, 0);
+    let mut padding = false;
+    for byte in text.bytes() {
+        if byte.is_ascii_whitespace() {
+            continue;
+        }
+        if byte == b'=' {
+            padding = true;
+            continue;
+        }
+        let Some(value) = alphabet.iter().position(|&x| x == byte) else {
+            ensure!(ignore_invalid, "Invalid base64 data");
+            continue;
+        };
+        // Nothing can follow the padding
+        ensure!(!padding, "Invalid base64 data");
+        group = (group << 6 | value as u32) & 0xFFFF;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((group >> bits) as u8);
+        }
+    }
+    // Leftover bits must be zero padding
+    ensure!(bits < 6 && group & ((1 << bits) - 1) == 0, "Invalid base64 data");
+    ensure!(url || padding || bits == 0, "Invalid base64 data");
+    Ok(out)
+}
+
+fn base64_decoded_object(bytes: Vec<u8>, cx: &Context) -> Object {
+    if bytes.is_ascii() {
+        cx.add(String::from_utf8(bytes).unwrap())
+    } else {
+        cx.add(bytes)
+    }
+}
+
+#[defun]
+fn base64_encode_string(string: Object, no_line_break: Option<()>) -> Result<String> {
+    let bytes = match string.untag() {
+        ObjectType::String(s) => base64_bytes(s)?,
+        ObjectType::ByteString(s) => s.to_vec(),
+        _ => bail!(TypeError::new(Type::String, string)),
+    };
+    Ok(base64_encode(&bytes, false, true, no_line_break.is_none()))
+}
+
+#[defun]
+fn base64url_encode_string(string: Object, no_pad: Option<()>) -> Result<String> {
+    let bytes = match string.untag() {
+        ObjectType::String(s) => base64_bytes(s)?,
+        ObjectType::ByteString(s) => s.to_vec(),
+        _ => bail!(TypeError::new(Type::String, string)),
+    };
+    Ok(base64_encode(&bytes, true, no_pad.is_none(), false))
+}
+
+#[defun]
+fn base64_decode_string<'ob>(
+    string: &str,
+    base64url: Option<()>,
+    ignore_invalid: Option<()>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let url = base64url.is_some();
+    let ignore_invalid = ignore_invalid.is_some();
+    Ok(base64_decoded_object(base64_decode(string, url, ignore_invalid)?, cx))
+}
+
+/// Replace the text between `beg` and `end` with the result of `f` and return
+/// the length of the new text.
+fn base64_region(
+    beg: usize,
+    end: usize,
+    env: &mut Rt<Env>,
+    f: impl FnOnce(&str) -> Result<String>,
+) -> Result<usize> {
+    let (beg, end) = (beg.min(end), beg.max(end));
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    ensure!(end <= buffer.text.len_chars(), ArgRangeError::new(&[beg, end]));
+    let new = f(&buffer.text.read(beg..end))?;
+    buffer.replace_region(beg, end, &new);
+    Ok(new.chars().count())
+}
+
+#[defun]
+fn base64_encode_region(
+    beg: usize,
+    end: usize,
+    no_line_break: Option<()>,
+    env: &mut Rt<Env>,
+) -> Result<usize> {
+    let line_break = no_line_break.is_none();
+    base64_region(beg, end, env, |text| {
+        Ok(base64_encode(&base64_bytes(text)?, false, true, line_break))
+    })
+}
+
+#[defun]
+fn base64url_encode_region(
+    beg: usize,
+    end: usize,
+    no_pad: Option<()>,
+    env: &mut Rt<Env>,
+) -> Result<usize> {
+    let pad = no_pad.is_none();
+    base64_region(beg, end, env, |text| Ok(base64_encode(&base64_bytes(text)?, true, pad, false)))
+}
+
+#[defun]
+fn base64_decode_region(
+    beg: usize,
+    end: usize,
+    base64url: Option<()>,
+    ignore_invalid: Option<()>,
+    env: &mut Rt<Env>,
+) -> Result<usize> {
+    let url = base64url.is_some();
+    let ignore_invalid = ignore_invalid.is_some();
+    base64_region(beg, end, env, |text| {
+        let bytes = base64_decode(text, url, ignore_invalid)?;
+        Ok(bytes.into_iter().map(char::from).collect())
+    })
+}
+
 #[defun]
 fn enable_debug() -> bool {
     crate::debug::enable_debug();
@@ -805,6 +1936,17 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_normalize() {
+        let composed = "caf\u{E9}";
+        let decomposed = "cafe\u{301}";
+        assert_eq!(ucs_normalize_nfc_string(decomposed), composed);
+        assert_eq!(ucs_normalize_nfd_string(composed), decomposed);
+        assert_eq!(string_glyph_compose(decomposed), composed);
+        assert_eq!(ucs_normalize_nfkc_string("\u{FB01}"), "fi");
+        assert_eq!(ucs_normalize_nfkd_string("\u{2460}"), "1");
+    }
+
     #[test]
     fn test_take() {
         let roots = &RootSet::default();
@@ -814,6 +1956,205 @@ mod test {
         assert_eq!(res, list![1, 2; cx]);
     }
 
+    #[test]
+    fn test_substring() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let hello: Gc<&LispString> = cx.add_as("hello");
+        let hello = hello.untag();
+        let accent: Gc<&LispString> = cx.add_as("héllo");
+        let accent = accent.untag();
+        assert_eq!(substring(hello, Some(1), Some(3)).unwrap(), "el");
+        assert_eq!(substring(hello, Some(-3), None).unwrap(), "llo");
+        assert_eq!(substring(hello, None, Some(-1)).unwrap(), "hell");
+        assert_eq!(substring(accent, Some(1), Some(2)).unwrap(), "é");
+        assert_eq!(substring_no_properties(accent, Some(2), None).unwrap(), "llo");
+        assert!(substring(hello, Some(3), Some(2)).is_err());
+        assert!(substring(hello, Some(6), None).is_err());
+        let err = substring(hello, Some(-6), None).unwrap_err();
+        let err = err.downcast::<ArgRangeError>().unwrap();
+        let args = err.args();
+        assert_eq!(args[0].value(cx), Object::from(hello));
+        assert_eq!(args[1].value(cx), -6);
+        assert_eq!(args[2].value(cx), NIL);
+    }
+
+    #[test]
+    fn test_string_distance() {
+        assert_eq!(string_distance("kitten", "sitting", None), 3);
+        assert_eq!(string_distance("", "abc", None), 3);
+        assert_eq!(string_distance("abc", "abc", None), 0);
+        assert_eq!(string_distance("héllo", "hello", None), 1);
+        assert_eq!(string_distance("héllo", "hello", Some(())), 2);
+    }
+
+    #[test]
+    fn test_string_version_lessp() {
+        assert!(string_version_lessp("foo2.png", "foo12.png"));
+        assert!(!string_version_lessp("foo12.png", "foo2.png"));
+        assert!(string_version_lessp("abc", "abd"));
+        assert!(string_version_lessp("1.2", "1.2.1"));
+        assert!(string_version_lessp("01", "1"));
+        assert!(!string_version_lessp("1", "1"));
+    }
+
+    #[test]
+    fn test_version_to_list() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        assert_eq!(version_to_list(".5", cx).unwrap(), list![0, 5; cx]);
+        assert_eq!(version_to_list("1.0.7.5", cx).unwrap(), list![1, 0, 7, 5; cx]);
+        assert_eq!(version_to_list("0.9 alpha", cx).unwrap(), list![0, 9, -3; cx]);
+        assert_eq!(version_to_list("0.9AlphA1", cx).unwrap(), list![0, 9, -3, 1; cx]);
+        assert_eq!(version_to_list("1.0-git", cx).unwrap(), list![1, 0, -4; cx]);
+        assert_eq!(version_to_list("1.0.cvs", cx).unwrap(), list![1, 0, -4; cx]);
+        assert_eq!(version_to_list("22.8 Beta3", cx).unwrap(), list![22, 8, -2, 3; cx]);
+        assert_eq!(version_to_list("1.2-3", cx).unwrap(), list![1, 2, -4, 3; cx]);
+        assert_eq!(version_to_list("22.3a", cx).unwrap(), list![22, 3, 1; cx]);
+        assert!(version_to_list("1.0prepre2", cx).is_err());
+        assert!(version_to_list("22.8X3", cx).is_err());
+        assert!(version_to_list("alpha3.2", cx).is_err());
+
+        assert!(version_less("1.0", "1.1").unwrap());
+        assert!(version_less("1.0pre2", "1.0").unwrap());
+        assert!(version_less("1.0-git", "1.0alpha").unwrap());
+        assert!(!version_less("1", "1.0.0").unwrap());
+        assert!(version_less_equal("1", "1.0.0").unwrap());
+        assert!(version_equal("1", "1.0.0").unwrap());
+        assert!(!version_less("29.1", "28.2").unwrap());
+    }
+
+    #[test]
+    fn test_secure_hash() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let abc = cx.add("abc");
+        assert_eq!(
+            md5(cx.add(""), None, None, None, None, env).unwrap(),
+            "d41d8cd98f00b204e9800998ecf8427e"
+        );
+        assert_eq!(
+            md5(abc, Some(1), Some(2), None, None, env).unwrap(),
+            "92eb5ffee6ae2fec3ad71c777531578f"
+        );
+        assert_eq!(
+            md5(abc, Some(-2), Some(-1), None, None, env).unwrap(),
+            "92eb5ffee6ae2fec3ad71c777531578f"
+        );
+        assert!(md5(abc, Some(2), Some(1), None, None, env).is_err());
+        let sha = sha1(abc, None, None, None, env, cx).unwrap();
+        assert_eq!(sha, cx.add("a9993e364706816aba3e25717850c26c9cd0d89d"));
+        let sha = secure_hash(sym::SHA256, abc, None, None, None, env, cx).unwrap();
+        assert_eq!(sha, cx.add("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"));
+        let binary = secure_hash(sym::SHA256, abc, None, None, Some(()), env, cx).unwrap();
+        let ObjectType::ByteString(bytes) = binary.untag() else {
+            panic!("expected byte string")
+        };
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(bytes[0], 0xba);
+        assert!(secure_hash(sym::TRUE, abc, None, None, None, env, cx).is_err());
+
+        let buffer =
+            crate::buffer::get_buffer_create(cx.add("test_secure_hash"), Some(NIL), cx).unwrap();
+        crate::buffer::set_buffer(buffer, env, cx).unwrap();
+        // Leave the gap inside the hashed text
+        let current = env.current_buffer.as_mut().unwrap();
+        current.insert(cx.add("xac")).unwrap();
+        current.text.set_cursor(2);
+        current.insert(cx.add("b")).unwrap();
+        let sha = sha1(buffer, Some(1), Some(4), None, env, cx).unwrap();
+        assert_eq!(sha, cx.add("a9993e364706816aba3e25717850c26c9cd0d89d"));
+    }
+
+    #[test]
+    fn test_base64() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let encode = |s: &str| base64_encode_string(cx.add(s), None).unwrap();
+        assert_eq!(encode(""), "");
+        assert_eq!(encode("f"), "Zg==");
+        assert_eq!(encode("fo"), "Zm8=");
+        assert_eq!(encode("foobar"), "Zm9vYmFy");
+        assert!(base64_encode_string(cx.add("λ"), None).is_err());
+        let long = encode(&"a".repeat(60));
+        assert_eq!(long.lines().map(str::len).collect::<Vec<_>>(), vec![76, 4]);
+        let long = base64_encode_string(cx.add("a".repeat(60)), Some(())).unwrap();
+        assert_eq!(long.len(), 80);
+        assert_eq!(base64url_encode_string(cx.add("\u{fb}\u{ff}"), None).unwrap(), "-_8=");
+        assert_eq!(base64url_encode_string(cx.add("\u{fb}\u{ff}"), Some(())).unwrap(), "-_8");
+
+        let decode = |s: &str| base64_decode_string(s, None, None, cx);
+        assert_eq!(decode("Zm9v\nYmFy").unwrap(), cx.add("foobar"));
+        assert_eq!(decode("Zg==").unwrap(), cx.add("f"));
+        assert!(decode("Zg").is_err());
+        assert!(decode("Z*g==").is_err());
+        assert!(decode("Zg==Zg==").is_err());
+        assert_eq!(base64_decode_string("Z*g==", None, Some(()), cx).unwrap(), cx.add("f"));
+        assert_eq!(
+            base64_decode_string("-_8", Some(()), None, cx).unwrap(),
+            cx.add(vec![0xFB_u8, 0xFF])
+        );
+
+        let buffer =
+            crate::buffer::get_buffer_create(cx.add("test_base64"), Some(NIL), cx).unwrap();
+        crate::buffer::set_buffer(buffer, env, cx).unwrap();
+        env.current_buffer.as_mut().unwrap().insert(cx.add("<foobar>")).unwrap();
+        assert_eq!(base64_encode_region(1, 7, None, env).unwrap(), 8);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "<Zm9vYmFy>");
+        assert_eq!(base64_decode_region(1, 9, None, None, env).unwrap(), 6);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "<foobar>");
+        assert!(base64_decode_region(0, 8, None, None, env).is_err());
+    }
+
+    #[test]
+    fn test_list_builders() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let list = list![1, 2, 3, 4; cx];
+        assert_eq!(ntake(2, list.try_into().unwrap()).unwrap(), list![1, 2; cx]);
+        assert_eq!(list, list![1, 2; cx]);
+        assert_eq!(ntake(0, list.try_into().unwrap()).unwrap(), NIL);
+
+        let list = list![1, 2, 3; cx];
+        assert_eq!(last(list.try_into().unwrap(), None).unwrap(), list![3; cx]);
+        assert_eq!(last(list.try_into().unwrap(), Some(2)).unwrap(), list![2, 3; cx]);
+        assert_eq!(last(list.try_into().unwrap(), Some(5)).unwrap(), list);
+        assert_eq!(last(list.try_into().unwrap(), Some(0)).unwrap(), NIL);
+        let dotted = Cons::new(1, Cons::new(2, 3, cx), cx);
+        assert_eq!(last(dotted.into(), None).unwrap(), Object::from(Cons::new(2, 3, cx)));
+
+        assert_eq!(make_list(3, sym::TRUE.into(), cx), list![true, true, true; cx]);
+        assert_eq!(ensure_list(cx.add(1), cx), list![1; cx]);
+        assert_eq!(ensure_list(list, cx), list);
+
+        let num = |x: i64| Number::from(x);
+        let seq = |from, to, sep| number_sequence(from, to, sep, cx).unwrap();
+        assert_eq!(seq(num(1), None, None), list![1; cx]);
+        assert_eq!(seq(num(1), Some(num(4)), None), list![1, 2, 3, 4; cx]);
+        assert_eq!(seq(num(1), Some(num(6)), Some(num(2))), list![1, 3, 5; cx]);
+        assert_eq!(seq(num(4), Some(num(1)), Some(num(-2))), list![4, 2; cx]);
+        assert_eq!(seq(num(4), Some(num(1)), None), NIL);
+        assert!(number_sequence(num(1), Some(num(2)), Some(num(0)), cx).is_err());
+
+        let tree = crate::reader::read("(1 (2 . 3) nil (4 (5 nil)) 6)", cx).unwrap().0;
+        assert_eq!(flatten_tree(tree, cx).unwrap(), list![1, 2, 3, 4, 5, 6; cx]);
+        assert_eq!(flatten_tree(cx.add(7), cx).unwrap(), list![7; cx]);
+        // shared structure is not circular
+        let shared = list![1, 2; cx];
+        let tree = list![shared, shared; cx];
+        assert_eq!(flatten_tree(tree, cx).unwrap(), list![1, 2, 1, 2; cx]);
+        let tree = crate::reader::read("(1 (2 3))", cx).unwrap().0;
+        let ObjectType::Cons(outer) = tree.untag() else { unreachable!() };
+        let ObjectType::Cons(inner) = outer.cdr().untag() else { unreachable!() };
+        inner.set_car(tree).unwrap();
+        assert!(flatten_tree(tree, cx).is_err());
+        inner.set_car(cx.add(2)).unwrap();
+        inner.set_cdr(tree).unwrap();
+        assert!(flatten_tree(tree, cx).is_err());
+    }
+
     #[test]
     fn test_delq() {
         let roots = &RootSet::default();
@@ -835,8 +2176,89 @@ mod test {
         let roots = &RootSet::default();
         let cx = &Context::new(roots);
         let list = list![1, 2, 3; cx];
-        let res = nthcdr(1, list.try_into().unwrap()).unwrap();
-        assert_eq!(res.untag().car(), 2);
+        let res = nthcdr(1, list).unwrap();
+        assert_eq!(res.as_cons().car(), 2);
+    }
+
+    #[test]
+    fn test_copy_tree() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let mut eval_str = |test_str: &str| {
+            let obj = crate::reader::read(test_str, cx).unwrap().0;
+            root!(obj, cx);
+            crate::interpreter::eval(obj, None, env, cx).unwrap().to_string()
+        };
+        let tree = "(let* ((v (vector 1)) (x (list 1 (list 2) v)) (y (copy-tree x)))";
+        let check = |body: &str| format!("{tree} {body})");
+        assert_eq!(
+            eval_str(&check("(list (equal x y) (eq x y) (eq (cadr x) (cadr y)))")),
+            "(t nil nil)"
+        );
+        assert_eq!(eval_str(&check("(eq (nth 2 y) v)")), "t");
+        let vecp = "(let* ((v (vector (list 1))) (y (copy-tree v t))) (list y (eq y v) (eq (aref y 0) (aref v 0))))";
+        assert_eq!(eval_str(vecp), "([(1)] nil nil)");
+        assert_eq!(eval_str("(copy-tree '(1 . 2))"), "(1 . 2)");
+        assert_eq!(eval_str("(copy-tree 5)"), "5");
+        // Circular structure is copied into the same shape
+        let cdr_loop = "(let ((x (list 1 2))) (setcdr (cdr x) x) (let ((y (copy-tree x))) (list (eq x y) (eq (cddr y) y) (car y) (cadr y))))";
+        assert_eq!(eval_str(cdr_loop), "(nil t 1 2)");
+        let car_loop = "(let ((x (list 1))) (setcar x x) (let ((y (copy-tree x))) (list (eq x y) (eq (car y) y))))";
+        assert_eq!(eval_str(car_loop), "(nil t)");
+        let vec_loop = "(let ((v (vector 1 nil))) (aset v 1 (list v)) (let ((y (copy-tree v t))) (list (eq v y) (eq (car (aref y 1)) y))))";
+        assert_eq!(eval_str(vec_loop), "(nil t)");
+        // Shared structure that isn't circular is copied each time it appears
+        let shared = "(let* ((x (list 1)) (y (copy-tree (list x x)))) (eq (car y) (cadr y)))";
+        assert_eq!(eval_str(shared), "nil");
+    }
+
+    #[test]
+    fn test_nth_parity() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let mut eval_str = |test_str: &str| {
+            let form = format!("(condition-case err {test_str} (error err))");
+            let obj = crate::reader::read(&form, cx).unwrap().0;
+            root!(obj, cx);
+            crate::interpreter::eval(obj, None, env, cx).unwrap().to_string()
+        };
+        // Each form with the result Emacs gives for it
+        let cases = [
+            ("(nth 0 '(a b))", "a"),
+            ("(nth 1 '(a b))", "b"),
+            ("(nth 5 '(a))", "nil"),
+            ("(nth -1 '(a b))", "a"),
+            ("(nth 0 nil)", "nil"),
+            ("(nth 1 '(a . b))", "(wrong-type-argument listp b)"),
+            ("(nth 0 5)", "(wrong-type-argument listp 5)"),
+            ("(nth 'a '(a))", "(wrong-type-argument integerp a)"),
+            ("(nthcdr 0 5)", "5"),
+            ("(nthcdr -1 '(a b))", "(a b)"),
+            ("(nthcdr 1 '(a b))", "(b)"),
+            ("(nthcdr 3 '(a b))", "nil"),
+            ("(nthcdr 1 '(a . b))", "b"),
+            ("(nthcdr 2 '(a . b))", "(wrong-type-argument listp b)"),
+            ("(nthcdr 1 5)", "(wrong-type-argument listp 5)"),
+            ("(let ((l (list 1 2 3))) (setcdr (cddr l) l) (nth 1000000001 l))", "3"),
+            ("(let ((l (list 1 2 3))) (setcdr (cddr l) l) (car (nthcdr 7 l)))", "2"),
+            ("(elt '(a b) 1)", "b"),
+            ("(elt '(a b) 2)", "nil"),
+            ("(elt '(a b) -1)", "a"),
+            ("(elt nil 3)", "nil"),
+            ("(elt [1 2] 1)", "2"),
+            ("(elt [1 2] 2)", "(args-out-of-range [1 2] 2)"),
+            ("(elt [1 2] -1)", "(args-out-of-range [1 2] -1)"),
+            ("(elt \"ab\" 1)", "98"),
+            ("(elt \"ab\" 2)", "(args-out-of-range \"ab\" 2)"),
+            ("(elt 5 0)", "(wrong-type-argument sequencep 5)"),
+        ];
+        for (form, expect) in cases {
+            assert_eq!(eval_str(form), expect, "{form}");
+        }
     }
 
     #[test]
@@ -910,8 +2332,29 @@ mod test {
         let roots = &RootSet::default();
         let cx = &Context::new(roots);
         let expect = list![104, 101, 108, 108, 111; cx];
-        let result = append(cx.add("hello"), &[], cx).unwrap();
+        let result = append(&[cx.add("hello"), NIL], cx).unwrap();
         assert_eq!(result, expect);
+        // The last argument is shared, not copied
+        let string = cx.add("hello");
+        assert!(eq(append(&[string], cx).unwrap(), string));
+        assert_eq!(append(&[], cx).unwrap(), NIL);
+        let result = append(&[cx.add(vec![cx.add(1)]), list![2; cx], cx.add(3)], cx).unwrap();
+        assert_eq!(result, Cons::new(1, Cons::new(2, 3, cx), cx).into());
+    }
+
+    #[test]
+    fn test_concat_sequences() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let vector = cx.add(vec![cx.add(1), cx.add(2)]);
+        let result = vconcat(&[cx.add("ab"), vector, list![3; cx], NIL], cx).unwrap();
+        let expect: Object = cx.add(vec![cx.add(97), cx.add(98), cx.add(1), cx.add(2), cx.add(3)]);
+        assert_eq!(Object::from(result), expect);
+        let chars = cx.add(vec![cx.add(99)]);
+        let string = concat(&[cx.add("a"), list![98; cx], chars], cx).unwrap();
+        assert_eq!(string, "abc");
+        assert!(concat(&[list![sym::NIL; cx]], cx).is_err());
+        assert!(vconcat(&[cx.add(1)], cx).is_err());
     }
 
     #[test]
@@ -925,6 +2368,49 @@ mod test {
         assert_eq!(result, element);
     }
 
+    #[test]
+    fn test_hash_table_test() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let mut eval_str = |test_str: &str| {
+            let obj = crate::reader::read(test_str, cx).unwrap().0;
+            root!(obj, cx);
+            crate::interpreter::eval(obj, None, env, cx).unwrap().to_string()
+        };
+        let table = |test: &str, body: &str| format!("(let ((h (make-hash-table{test}))) {body})");
+        // Keys that are `equal' but not `eq' are different keys in an eq table
+        let eq = "(puthash (list 1) 'a h) (puthash (list 1) 'b h) (list (hash-table-count h) (gethash (list 1) h))";
+        assert_eq!(eval_str(&table(" :test 'eq", eq)), "(2 nil)");
+        assert_eq!(eval_str(&table(" :test 'equal", eq)), "(1 b)");
+        let strings =
+            "(puthash \"a\" 1 h) (list (gethash (copy-sequence \"a\") h) (hash-table-test h))";
+        assert_eq!(eval_str(&table(" :test 'equal", strings)), "(1 equal)");
+        assert_eq!(eval_str(&table(" :test 'eql", strings)), "(nil eql)");
+        let floats = "(puthash 1.5 'x h) (list (gethash 1.5 h) (hash-table-test h))";
+        assert_eq!(eval_str(&table("", floats)), "(x eql)");
+        assert_eq!(eval_str(&table(" :test 'eq", floats)), "(nil eq)");
+        let nested = "(puthash (list 1 (vector \"b\")) 'y h) (gethash (list 1 (vector (copy-sequence \"b\"))) h)";
+        assert_eq!(eval_str(&table(" :test 'equal", nested)), "y");
+        // The hashes of an equal table don't change when the keys are moved
+        let moved = "(puthash (copy-sequence \"a\") 1 h) (garbage-collect) (gethash \"a\" h)";
+        assert_eq!(eval_str(&table(" :test 'equal", moved)), "1");
+        let removed = "(puthash (list 1) 'a h) (remhash (list 1) h) (hash-table-count h)";
+        assert_eq!(eval_str(&table(" :test 'eq", removed)), "1");
+        assert_eq!(eval_str(&table(" :test 'equal", removed)), "0");
+        let cleared = "(puthash 1 2 h) (clrhash h) (list (hash-table-count h) (gethash 1 h))";
+        assert_eq!(eval_str(&table("", cleared)), "(0 nil)");
+        assert_eq!(
+            eval_str(&table(" :test 'eq", "(puthash 'a 1 h) h")),
+            "#s(hash-table test eq data (a 1))"
+        );
+        assert_eq!(
+            eval_str("(condition-case nil (make-hash-table :test 'foo) (error 'bad))"),
+            "bad"
+        );
+    }
+
     #[test]
     fn test_maphash() {
         sym::init_symbols();
@@ -945,73 +2431,100 @@ mod test {
     }
 
     #[test]
-    #[allow(clippy::needless_borrow)]
+    fn test_remhash_while_mapping() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let mut table = HashTable::default();
+        for i in 1..=5 {
+            table.insert(i.into(), NIL);
+        }
+        let table: &LispHashTable = table.into_obj(cx).untag();
+        // The first three entries have been visited
+        table.set_iter_index(3);
+        remhash(1.into(), table).unwrap();
+        remhash(5.into(), table).unwrap();
+        let rest: Vec<Object> = (table.get_iter_index()..table.len())
+            .map(|i| table.get_index(i).unwrap().0)
+            .collect();
+        assert_eq!(rest, vec![cx.add(4)]);
+        assert_eq!(table.len(), 3);
+    }
+
+    fn check_sort(seq: &str, args: &[Object<'static>], expect: &str, cx: &mut Context) {
+        root!(env, new(Env), cx);
+        let seq = crate::reader::read(seq, cx).unwrap().0;
+        root!(seq, cx);
+        for arg in args {
+            env.stack.push(*arg).unwrap();
+        }
+        let res = rebind!(sort(seq, ArgSlice::new(args.len()), env, cx).unwrap());
+        let expect = crate::reader::read(expect, cx).unwrap().0;
+        assert_eq!(res, expect);
+    }
+
+    #[test]
     fn test_sort() {
         sym::init_symbols();
         let roots = &RootSet::default();
         let cx = &mut Context::new(roots);
-        root!(env, new(Env), cx);
-        let func = sym::LESS_THAN.func(cx).unwrap();
-        root!(func, cx);
-        {
-            root!(list, ListType::empty(), cx);
-            let res = rebind!(sort(list, func, env, cx).unwrap());
-            assert_eq!(res, NIL);
-        }
-        {
-            let list: List = list![1; cx].try_into().unwrap();
-            root!(list, cx);
-            let res = rebind!(sort(list, func, env, cx).unwrap());
-            assert_eq!(res, list![1; cx]);
-        }
-        {
-            let list: List = list![2, 1; cx].try_into().unwrap();
-            root!(list, cx);
-            let res = rebind!(sort(list, func, env, cx).unwrap());
-            assert_eq!(res, list![1, 2; cx]);
-        }
-        {
-            let list: List = list![1, 2, 3; cx].try_into().unwrap();
-            root!(list, cx);
-            let res = rebind!(sort(list, func, env, cx).unwrap());
-            assert_eq!(res, list![1, 2, 3; cx]);
-        }
-        {
-            let list: List = list![3, 2, 1; cx].try_into().unwrap();
-            root!(list, cx);
-            let res = rebind!(sort(list, func, env, cx).unwrap());
-            assert_eq!(res, list![1, 2, 3; cx]);
-        }
+        let less = sym::LESS_THAN.into();
+        check_sort("nil", &[less], "nil", cx);
+        check_sort("(1)", &[less], "(1)", cx);
+        check_sort("(2 1)", &[less], "(1 2)", cx);
+        check_sort("(1 2 3)", &[less], "(1 2 3)", cx);
+        check_sort("(3 2 1)", &[less], "(1 2 3)", cx);
+        check_sort("(3 1 2)", &[less], "(1 2 3)", cx);
+        check_sort("(1 2 3 4 5)", &[sym::GREATER_THAN.into()], "(5 4 3 2 1)", cx);
+        // check stable sorting
+        let func = sym::CAR_LESS_THAN_CAR.into();
+        check_sort("((1 . 1) (1 . 2) (1 . 3))", &[func], "((1 . 1) (1 . 2) (1 . 3))", cx);
+        check_sort("[3 1 2]", &[less], "[1 2 3]", cx);
+    }
 
-        {
-            let list: List = list![3, 1, 2; cx].try_into().unwrap();
-            root!(list, cx);
-            let res = rebind!(sort(list, func, env, cx).unwrap());
-            assert_eq!(res, list![1, 2, 3; cx]);
-        }
-        {
-            let func = sym::GREATER_THAN.func(cx).unwrap();
-            let list: List = list![1, 2, 3, 4, 5; cx].try_into().unwrap();
-            root!(list, cx);
-            root!(func, cx);
-            let res = rebind!(sort(list, func, env, cx).unwrap());
-            assert_eq!(res, list![5, 4, 3, 2, 1; cx]);
-        }
-        {
-            // check stable sorting
-            let func = sym::CAR_LESS_THAN_CAR.func(cx).unwrap();
-            let list: List =
-                list![Cons::new(1, 1, cx), Cons::new(1, 2, cx), Cons::new(1, 3, cx); cx]
-                    .try_into()
-                    .unwrap();
-            root!(list, cx);
-            root!(func, cx);
-            let res = rebind!(sort(list, func, env, cx).unwrap());
-            assert_eq!(
-                res,
-                list![Cons::new(1, 1, cx), Cons::new(1, 2, cx), Cons::new(1, 3, cx); cx]
-            );
+    #[test]
+    fn test_sort_keywords() {
+        sym::init_symbols();
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let (key, reverse) = (sym::KW_KEY.into(), sym::KW_REVERSE.into());
+        // value< is the default ordering
+        check_sort("(3 1.5 2)", &[], "(1.5 2 3)", cx);
+        check_sort("(\"b\" \"c\" \"a\")", &[], "(\"a\" \"b\" \"c\")", cx);
+        check_sort("((2 1) (1) (2 1 0))", &[], "((1) (2 1) (2 1 0))", cx);
+        // sort by key, keeping equal elements in order
+        let list = "((2 . 1) (1 . 2) (2 . 3))";
+        check_sort(list, &[key, sym::CAR.into()], "((1 . 2) (2 . 1) (2 . 3))", cx);
+        // reverse also keeps equal elements in order
+        let args = [key, sym::CAR.into(), reverse, TRUE];
+        check_sort(list, &args, "((2 . 1) (2 . 3) (1 . 2))", cx);
+    }
+
+    #[test]
+    fn test_sort_in_place() {
+        sym::init_symbols();
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let list = list![3, 1, 2; cx];
+        root!(list, cx);
+        env.stack.push(sym::LESS_THAN).unwrap();
+        sort(list, ArgSlice::new(1), env, cx).unwrap();
+        assert_eq!(list.bind(cx), list![1, 2, 3; cx]);
+
+        // the keyword form returns a copy unless :in-place is given
+        env.stack.push(sym::KW_REVERSE).unwrap();
+        env.stack.push(NIL).unwrap();
+        let res = rebind!(sort(list, ArgSlice::new(2), env, cx).unwrap());
+        assert_eq!(res, list![1, 2, 3; cx]);
+        assert!(!res.ptr_eq(list.bind(cx)));
+
+        let vec = cx.add(vec![cx.add(3), cx.add(1), cx.add(2)]);
+        root!(vec, cx);
+        for arg in [sym::KW_LESSP.into(), sym::GREATER_THAN.into(), sym::KW_IN_PLACE.into(), TRUE] {
+            env.stack.push(arg).unwrap();
         }
+        sort(vec, ArgSlice::new(4), env, cx).unwrap();
+        assert_eq!(vec.bind(cx), cx.add(vec![cx.add(3), cx.add(2), cx.add(1)]));
     }
 
     #[test]
@@ -1023,4 +2536,38 @@ mod test {
         let result = copy_alist(list, cx).unwrap();
         assert_eq!(alist, result);
     }
+    #[test]
+    fn test_string_pad_and_fill() {
+        assert_eq!(string_pad("abc", 5, None, None).unwrap(), "abc  ");
+        assert_eq!(string_pad("abc", 5, Some('-' as usize), Some(())).unwrap(), "--abc");
+        assert_eq!(string_pad("abcdef", 3, None, None).unwrap(), "abcdef");
+        assert_eq!(string_fill("aa bb cc\n\ndd  ee", 5), "aa bb\ncc\n\ndd ee");
+        assert_eq!(string_fill("abcdefgh ij", 4), "abcdefgh\nij");
+        assert_eq!(string_chop_newline("line\n\n"), "line\n");
+        assert_eq!(string_chop_newline("line"), "line");
+    }
+
+    #[test]
+    fn test_format_spec() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let spec = crate::reader::read("((?a . \"alpha\") (?n . 42))", cx);
+        let spec = spec.unwrap().0;
+        root!(spec, cx);
+        let mut format = |template: &str, ignore: Option<Object>, split: Option<()>| {
+            let template: Gc<&LispString> = cx.add_as(template);
+            root!(template, cx);
+            format_spec(template, spec, ignore, split, env, cx).map(|x| x.to_string())
+        };
+        assert_eq!(format("%a: %n%%", None, None).unwrap(), "\"alpha: 42%\"");
+        assert_eq!(format("[%6a|%-4n|%04n]", None, None).unwrap(), "\"[ alpha|42  |0042]\"");
+        assert_eq!(format("%^a %.2a %<3a %>3a", None, None).unwrap(), "\"ALPHA al pha alp\"");
+        assert_eq!(format("%<3.3a %>3.3a", None, None).unwrap(), "\"pha alp\"");
+        assert!(format("%x", None, None).is_err());
+        assert_eq!(format("%x %a", Some(TRUE), None).unwrap(), "\"%x alpha\"");
+        assert_eq!(format("%x %a", Some(sym::DELETE.into()), None).unwrap(), "\" alpha\"");
+        assert_eq!(format("<%a>%n", None, Some(())).unwrap(), "(\"<\" \"alpha\" \">\" \"42\")");
+    }
 }
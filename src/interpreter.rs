@@ -1,15 +1,16 @@
 //! The basic elisp interpreter.
 use crate::{
     core::{
-        cons::{Cons, ElemStreamIter},
+        cons::{watched_changes, Cons, ElemStreamIter},
         env::{sym, CallFrame, Env},
-        error::{ArgError, Type, TypeError},
-        gc::{Context, Rt, Rto, Slot},
+        error::{ArgError, Type, TypeError, VoidError},
+        gc::{collections, Context, Rt, Rto, Slot},
         object::{
-            Function, FunctionType, Gc, List, ListType, Object, ObjectType, Symbol, NIL, TRUE,
+            Closure, FnArgs, Function, FunctionType, Gc, List, ListType, Object, ObjectType,
+            Symbol, WithLifetime, NIL, TRUE,
         },
     },
-    eval::{add_trace, ErrorType, EvalError, EvalResult},
+    eval::{handles_error, ErrorType, EvalError, EvalResult},
     rooted_iter,
 };
 use anyhow::Context as _;
@@ -17,31 +18,70 @@ use anyhow::Result as AnyResult;
 use anyhow::{bail, ensure};
 use fallible_iterator::FallibleIterator;
 use fallible_streaming_iterator::FallibleStreamingIterator;
-use rune_core::macros::{bail_err, call, error, rebind, root};
+use rune_core::hashmap::HashMap;
+use rune_core::macros::{bail_err, call, error, list, rebind, root};
 use rune_macros::defun;
+use std::cell::RefCell;
 
 struct Interpreter<'brw, 'rt> {
     vars: &'brw mut Rt<Vec<Slot<&'rt Cons>>>,
     env: &'brw mut Rt<Env<'rt>>,
+    /// Whether variables are bound lexically. Otherwise every `let` binds
+    /// dynamically and `lambda` makes a plain `(lambda ...)` list.
+    lexical: bool,
 }
 
-#[defun]
+/// Evaluate `form` from Rust. `lexical` is the same as for `eval` in lisp,
+/// except that `None` means the binding mode of `lexical-binding`, which is t
+/// unless a file without a lexical-binding cookie is being loaded.
 pub(crate) fn eval<'ob>(
     form: &Rto<Object>,
     lexical: Option<&Rto<Object>>,
     env: &mut Rt<Env>,
     cx: &'ob mut Context,
 ) -> Result<Object<'ob>, anyhow::Error> {
+    let default = env.vars.get(sym::LEXICAL_BINDING).map_or(true, |x| x.bind(cx) != NIL);
+    eval_in_mode(form, lexical, default, env, cx)
+}
+
+/// Evaluate `form`. `lexical` is an alist of lexical bindings to evaluate it
+/// in, or t for lexical binding with none. Like in Emacs, nil means dynamic
+/// binding.
+#[defun(name = "eval")]
+fn lisp_eval<'ob>(
+    form: &Rto<Object>,
+    lexical: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>, anyhow::Error> {
+    eval_in_mode(form, lexical, false, env, cx)
+}
+
+/// Evaluate `form`, binding lexically by `default` if `lexical` is `None`.
+pub(crate) fn eval_in_mode<'ob>(
+    form: &Rto<Object>,
+    lexical: Option<&Rto<Object>>,
+    default: bool,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>, anyhow::Error> {
+    crate::alloc::sync_gc_policy(env, cx)?;
     cx.garbage_collect(false);
     root!(vars, new(Vec<Slot<&Cons>>), cx);
-    if let Some(ObjectType::Cons(cons)) = lexical.map(|x| x.untag(cx)) {
-        for var in cons.elements() {
-            if let ObjectType::Cons(binding) = var?.untag() {
-                vars.push(binding);
+    let lexical = match lexical.map(|x| x.untag(cx)) {
+        Some(ObjectType::Cons(cons)) => {
+            // Bindings earlier in the alist shadow later ones
+            for var in cons.elements().collect::<Result<Vec<_>, _>>()?.into_iter().rev() {
+                if let ObjectType::Cons(binding) = var.untag() {
+                    vars.push(binding);
+                }
             }
+            true
         }
-    }
-    let mut interpreter = Interpreter { vars, env };
+        Some(_) => true,
+        None => default,
+    };
+    let mut interpreter = Interpreter { vars, env, lexical };
     interpreter.eval_form(form, cx).map_err(Into::into)
 }
 
@@ -71,6 +111,8 @@ impl Interpreter<'_, '_> {
                 sym::LET => self.eval_let(forms, true, cx),
                 sym::LET_STAR => self.eval_let(forms, false, cx),
                 sym::IF => self.eval_if(forms, cx),
+                sym::WHEN => self.eval_when(forms, false, cx),
+                sym::UNLESS => self.eval_when(forms, true, cx),
                 sym::AND => self.eval_and(forms, cx),
                 sym::OR => self.eval_or(forms, cx),
                 sym::COND => self.eval_cond(forms, cx),
@@ -79,7 +121,10 @@ impl Interpreter<'_, '_> {
                 sym::PROG1 => self.eval_progx(forms, 1, cx),
                 sym::PROG2 => self.eval_progx(forms, 2, cx),
                 sym::SETQ => self.setq(forms, cx),
-                sym::DEFVAR | sym::DEFCONST => self.defvar(forms, cx),
+                sym::DEFVAR => self.defvar(forms, cx),
+                sym::DEFCONST => self.defconst(forms, cx),
+                sym::DEFCUSTOM if !sym.has_func() => self.defcustom(forms, cx),
+                sym::DEFGROUP if !sym.has_func() => self.defgroup(forms, cx),
                 sym::FUNCTION => self.eval_function(forms, cx),
                 sym::INTERACTIVE => Ok(NIL), // TODO: implement
                 sym::CATCH => self.catch(forms, cx),
@@ -88,6 +133,16 @@ impl Interpreter<'_, '_> {
                 sym::SAVE_CURRENT_BUFFER => self.save_current_buffer(forms, cx),
                 sym::SAVE_EXCURSION => self.save_excursion(forms, cx),
                 sym::UNWIND_PROTECT => self.unwind_protect(forms, cx),
+                sym::CL_DESTRUCTURING_BIND => self.destructuring_bind(forms, cx),
+                sym::SEQ_LET => self.seq_let(forms, cx),
+                sym::PCASE_LET if is_destructuring_let(forms.bind(cx)) => {
+                    self.pcase_let(forms, true, cx)
+                }
+                sym::PCASE_LET_STAR if is_destructuring_let(forms.bind(cx)) => {
+                    self.pcase_let(forms, false, cx)
+                }
+                sym::CL_LETF if !sym.has_func() => self.letf(forms, true, cx),
+                sym::CL_LETF_STAR if !sym.has_func() => self.letf(forms, false, cx),
                 _ => {
                     root!(sym, cx);
                     self.eval_call(sym, forms, cx)
@@ -105,19 +160,14 @@ impl Interpreter<'_, '_> {
         let result = match self.implicit_progn(forms, cx) {
             Ok(x) => Ok(rebind!(x, cx)),
             Err(e) => {
-                if let ErrorType::Throw(id) = e.error {
-                    if let Some((throw_tag, data)) = self.env.get_exception(id) {
-                        let catch_tag = self.env.catch_stack.last().unwrap();
-                        // TODO: Remove binds
-                        if catch_tag == throw_tag {
-                            return Ok(data.bind(cx));
-                        }
-                    }
+                let catch_tag = self.env.catch_stack.last().unwrap().bind(cx);
+                match e.thrown(cx) {
+                    Some((throw_tag, data)) if crate::fns::eq(throw_tag, catch_tag) => Ok(data),
+                    _ => Err(e),
                 }
-                Err(e)
             }
         };
-        // pop this tag from the catch stack
+        // pop this tag from the catch stack, whether or not it was thrown to
         self.env.catch_stack.pop();
         result
     }
@@ -132,11 +182,7 @@ impl Interpreter<'_, '_> {
         let value = forms.next().unwrap()?;
         // Need to check now that there is a catch, because we may have a
         // condition-case along the unwind path
-        if self.env.catch_stack.iter().any(|x| x.bind(cx) == tag) {
-            Err(EvalError::throw(tag, value, self.env))
-        } else {
-            Err(error!("No catch for {tag}"))
-        }
+        Err(crate::eval::throw_error(tag, value, self.env, cx))
     }
 
     fn defvar<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
@@ -147,59 +193,188 @@ impl Interpreter<'_, '_> {
         root!(name, cx);
         let value = match forms.next()? {
             // (defvar x y)
-            Some(value) => rebind!(self.eval_form(value, cx)?),
+            Some(value) => {
+                let value = rebind!(self.eval_form(value, cx)?);
+                crate::lread::record_definition(name.bind(cx).into(), self.env, cx);
+                value
+            }
             // (defvar x)
             None => NIL,
         };
         self.env.defvar(name.bind(cx), value)?;
+        // (defvar x y "doc")
+        if let Some(doc) = forms.next()? {
+            let doc = doc.bind(cx);
+            if let ObjectType::String(_) = doc.untag() {
+                self.env.set_prop(name.bind(cx), sym::VARIABLE_DOCUMENTATION, doc);
+            }
+        }
         Ok(value)
     }
 
+    fn defconst<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
+        rooted_iter!(forms, obj, cx);
+        // (defconst x y "doc")
+        let Some(sym) = forms.next()? else { bail_err!(ArgError::new(2, 0, "defconst")) };
+        let name: Symbol = sym.bind(cx).try_into()?;
+        root!(name, cx);
+        let Some(value) = forms.next()? else { bail_err!(ArgError::new(2, 1, "defconst")) };
+        let value = rebind!(self.eval_form(value, cx)?);
+        root!(value, cx);
+        crate::lread::record_definition(name.bind(cx).into(), self.env, cx);
+        let doc = forms.next()?.map(|x| x.bind(cx));
+        crate::eval::defconst_1(name.bind(cx), value.bind(cx), doc, self.env)?;
+        Ok(name.bind(cx).into())
+    }
+
+    /// A minimal `defcustom` used until custom.el defines the real macro. The
+    /// variable is defined like `defvar`, and the customization metadata is
+    /// stored on its plist where custom.el expects to find it.
+    fn defcustom<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
+        rooted_iter!(forms, obj, cx);
+        // (defcustom symbol standard doc [keyword value]...)
+        let Some(sym) = forms.next()? else { bail_err!(ArgError::new(2, 0, "defcustom")) };
+        let name: Symbol = sym.bind(cx).try_into()?;
+        root!(name, cx);
+        let Some(standard) = forms.next()? else { bail_err!(ArgError::new(2, 1, "defcustom")) };
+        root!(standard, cx);
+        if let Some(doc) = forms.next()? {
+            let doc = doc.bind(cx);
+            if let ObjectType::String(_) = doc.untag() {
+                self.env.set_prop(name.bind(cx), sym::VARIABLE_DOCUMENTATION, doc);
+            }
+        }
+        let standard_value = list![standard.bind(cx); cx];
+        self.env.set_prop(name.bind(cx), sym::STANDARD_VALUE, standard_value);
+
+        while let Some(keyword) = forms.next()? {
+            let keyword: Symbol = keyword.bind(cx).try_into()?;
+            let prop = match keyword {
+                sym::KW_TYPE => Some(sym::CUSTOM_TYPE),
+                sym::KW_SAFE => Some(sym::SAFE_LOCAL_VARIABLE),
+                sym::KW_RISKY => Some(sym::RISKY_LOCAL_VARIABLE),
+                sym::KW_SET => Some(sym::CUSTOM_SET),
+                sym::KW_GROUP | sym::KW_LOCAL => None,
+                // :initialize, :require, :version, etc. only matter to the
+                // Customize UI
+                _ => {
+                    forms.next()?;
+                    continue;
+                }
+            };
+            let is_group = keyword == sym::KW_GROUP;
+            let Some(value) = forms.next()? else {
+                bail_err!(error!("Keyword {keyword} is missing an argument"))
+            };
+            let value = rebind!(self.eval_form(value, cx)?);
+            let name = name.bind(cx);
+            match prop {
+                Some(prop) => self.env.set_prop(name, prop, value),
+                None if is_group => {
+                    let group: Symbol = value.try_into()?;
+                    crate::eval::custom_add_to_group(
+                        group,
+                        name,
+                        sym::CUSTOM_VARIABLE,
+                        self.env,
+                        cx,
+                    )?;
+                }
+                None if value != NIL => name.make_buffer_local(),
+                None => {}
+            }
+        }
+
+        if self.env.vars.get(name.bind(cx)).is_some() {
+            name.bind(cx).make_special();
+        } else {
+            // A value saved with `custom-set-variables' takes precedence over
+            // the standard value
+            let saved = crate::data::get(name.bind(cx), sym::SAVED_VALUE, self.env, cx);
+            let form = match saved.untag() {
+                ObjectType::Cons(saved) => saved.car(),
+                _ => standard.bind(cx),
+            };
+            root!(form, cx);
+            let value = rebind!(self.eval_form(form, cx)?);
+            self.env.defvar(name.bind(cx), value)?;
+        }
+        Ok(name.bind(cx).into())
+    }
+
+    /// A minimal `defgroup` used until custom.el defines the real macro.
+    fn defgroup<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
+        rooted_iter!(forms, obj, cx);
+        // (defgroup symbol members doc [keyword value]...)
+        let Some(sym) = forms.next()? else { bail_err!(ArgError::new(3, 0, "defgroup")) };
+        let name: Symbol = sym.bind(cx).try_into()?;
+        root!(name, cx);
+        let Some(members) = forms.next()? else { bail_err!(ArgError::new(3, 1, "defgroup")) };
+        let members = members.bind(cx);
+        for member in members.as_list()? {
+            // each member is (SYMBOL WIDGET)
+            let mut member = member?.as_list()?;
+            let (Some(symbol), Some(widget)) = (member.next(), member.next()) else {
+                bail_err!(error!("Invalid defgroup member: {members}"))
+            };
+            let (symbol, widget): (Symbol, Symbol) = (symbol?.try_into()?, widget?.try_into()?);
+            crate::eval::custom_add_to_group(name.bind(cx), symbol, widget, self.env, cx)?;
+        }
+        if let Some(doc) = forms.next()? {
+            let doc = rebind!(self.eval_form(doc, cx)?);
+            self.env.set_prop(name.bind(cx), sym::GROUP_DOCUMENTATION, doc);
+        }
+
+        while let Some(keyword) = forms.next()? {
+            let keyword: Symbol = keyword.bind(cx).try_into()?;
+            let is_group = keyword == sym::KW_GROUP;
+            let Some(value) = forms.next()? else {
+                bail_err!(error!("Keyword {keyword} is missing an argument"))
+            };
+            // Other keywords (:prefix, :link, :version) only matter to the
+            // Customize UI
+            if is_group {
+                let parent = rebind!(self.eval_form(value, cx)?);
+                let parent: Symbol = parent.try_into()?;
+                let name = name.bind(cx);
+                crate::eval::custom_add_to_group(parent, name, sym::CUSTOM_GROUP, self.env, cx)?;
+            }
+        }
+        Ok(name.bind(cx).into())
+    }
+
     fn eval_call<'ob>(
         &mut self,
         sym: &Rto<Symbol>,
         args: &Rto<Object>,
         cx: &'ob mut Context,
     ) -> EvalResult<'ob> {
-        let Some(func) = sym.bind(cx).follow_indirect(cx) else {
-            bail_err!("Invalid function: {sym}")
-        };
+        let func = crate::eval::resolve_function(sym, self.env, cx)?;
         root!(func, cx);
 
-        match func.untag(cx) {
-            FunctionType::Cons(cons) if cons.car() == sym::AUTOLOAD => {
-                crate::eval::autoload_do_load(func.cast(), None, None, self.env, cx)
-                    .map_err(|e| add_trace(e, "autoload", &[]))?;
-                func.set(sym.bind(cx).follow_indirect(cx).unwrap());
-            }
-            FunctionType::Cons(form) if form.car() == sym::MACRO => {
+        if let FunctionType::Cons(form) = func.untag(cx) {
+            if form.car() == sym::MACRO {
                 let mcro: Function = form.cdr().try_into()?;
-
-                let mut iter = args.bind(cx).as_list()?.fallible();
-                let mut frame = CallFrame::new(self.env);
-                while let Some(arg) = iter.next()? {
-                    frame.push_arg(arg);
-                }
                 root!(mcro, cx);
                 let name = sym.bind(cx).name().to_owned();
-                let value = mcro.call(&mut frame, Some(&name), cx)?;
-                drop(frame);
+                let value = crate::eval::call_macro(mcro, args, &name, self.env, cx)?;
                 root!(value, cx);
                 return self.eval_form(value, cx);
             }
-            _ => (),
         }
 
         rooted_iter!(iter, args, cx);
-        root!(args, new(Vec), cx);
+        // Evaluate the arguments directly onto the lisp stack so that calls
+        // don't need to allocate an argument vector. The frame is popped if an
+        // argument signals an error.
+        let frame = &mut CallFrame::new(self.env);
         while let Some(x) = iter.next()? {
-            let result = self.eval_form(x, cx)?;
-            args.push(result);
+            let lexical = self.lexical;
+            let result = Interpreter { vars: self.vars, env: frame, lexical }.eval_form(x, cx)?;
+            frame.push_arg(result)?;
         }
-        let frame = &mut CallFrame::new(self.env);
-        frame.push_arg_slice(Rt::bind_slice(args, cx));
         let name = sym.bind(cx).name().to_owned();
-        func.call(frame, Some(&name), cx)
+        crate::eval::call_function(func, frame, Some(&name), cx)
     }
 
     fn eval_function<'ob>(
@@ -216,18 +391,14 @@ impl Interpreter<'_, '_> {
         let form = forms.next().unwrap()?;
         root!(form, cx); // Polonius
         let ObjectType::Cons(cons) = form.bind(cx).untag() else { return Ok(form.bind(cx)) };
-        if cons.car() != sym::LAMBDA {
+        // Dynamically bound code has no environment to capture
+        if cons.car() != sym::LAMBDA || !self.lexical {
             return Ok(form.bind(cx));
         }
         root!(doc, cons.cdr(), cx);
-        let body = rebind!(self.replace_doc_symbol(doc, cx)?);
-        let env = {
-            let vars = self.vars.bind_ref(cx);
-            let mut tail = Object::from(Cons::new1(true, cx));
-            for var in vars {
-                tail = Cons::new(**var, tail, cx).into();
-            }
-            tail
+        let (body, dynamic_doc) = match rebind!(self.replace_doc_symbol(doc, cx)?) {
+            Some(body) => (body, true),
+            None => (doc.bind(cx), false),
         };
         // This function will trim the environment down to only what is actually
         // captured in the closure
@@ -237,35 +408,59 @@ impl Interpreter<'_, '_> {
                 let closure_fn: Result<&Rto<Function>, _> = closure_fn.try_as();
                 if let Ok(closure_fn) = closure_fn {
                     let lambda = Object::from(Cons::new(sym::LAMBDA, body, cx));
+                    let env = self.closure_env_list(cx);
                     let closure_fn: Function = closure_fn.bind(cx);
                     root!(closure_fn, cx);
                     return call!(closure_fn, lambda, env; self.env, cx);
                 }
             }
         }
-        // If the closure capture function is not defined, use the whole environment
-        let end = Cons::new(env, body, cx);
-        Ok(Cons::new(sym::CLOSURE, end, cx).into())
+        // If the closure capture function is not defined, use the whole
+        // environment. Closures with a computed docstring are oclosures, which
+        // oclosure.el manipulates as `(closure ...)' lists.
+        if dynamic_doc {
+            let end = Cons::new(self.closure_env_list(cx), body, cx);
+            return Ok(Cons::new(sym::CLOSURE, end, cx).into());
+        }
+        let (arg_list, body) = match body.untag() {
+            ObjectType::Cons(cons) => (cons.car(), cons.cdr()),
+            _ => (NIL, NIL),
+        };
+        let (args, params) = parse_params(arg_list)?;
+        let env = self.vars.bind_ref(cx).iter().map(|x| **x).collect();
+        // SAFETY: all the objects are from the same context, and the closure
+        // is allocated immediately.
+        let closure = unsafe { Closure::make(arg_list, params, args, env, body) };
+        Ok(cx.add(closure))
+    }
+
+    /// Build the `((x . 1) (y . 2) t)` list form of the current lexical
+    /// environment.
+    fn closure_env_list<'ob>(&self, cx: &'ob Context) -> Object<'ob> {
+        let vars = self.vars.bind_ref(cx);
+        let mut tail = Object::from(Cons::new1(true, cx));
+        for var in vars {
+            tail = Cons::new(**var, tail, cx).into();
+        }
+        tail
     }
 
     /// Handle special case of (:documentation form) to build the docstring
-    /// dynamically. If the docstring is not of this form, just return the current body.
+    /// dynamically. If the docstring is not of this form, return `None`.
     fn replace_doc_symbol<'ob>(
         &mut self,
         quoted: &Rto<Object>,
         cx: &'ob mut Context,
-    ) -> Result<Object<'ob>, EvalError> {
+    ) -> Result<Option<Object<'ob>>, EvalError> {
         // quoted = ((<args..>) (doc_str) ...)
         let docstring = {
-            let Ok(list) = quoted.bind(cx).as_list() else { return Ok(quoted.bind(cx)) };
-            let Some(doc) = list.fallible().nth(1)? else { return Ok(quoted.bind(cx)) };
-            let ObjectType::Cons(doc_cons) = doc.untag() else { return Ok(quoted.bind(cx)) };
+            let Ok(list) = quoted.bind(cx).as_list() else { return Ok(None) };
+            let Some(doc) = list.fallible().nth(1)? else { return Ok(None) };
+            let ObjectType::Cons(doc_cons) = doc.untag() else { return Ok(None) };
             if doc_cons.car() != sym::KW_DOCUMENTATION {
-                return Ok(quoted.bind(cx));
+                return Ok(None);
             }
-            let ObjectType::Cons(doc_cons) = doc_cons.cdr().untag() else {
-                return Ok(quoted.bind(cx));
-            };
+            let ObjectType::Cons(doc_cons) = doc_cons.cdr().untag() else { return Ok(None) };
             root!(doc_form, doc_cons.car(), cx);
             let docstring = rebind!(self.eval_form(doc_form, cx)?);
             // Handle the special case of oclosure docstrings being a symbol
@@ -284,7 +479,7 @@ impl Interpreter<'_, '_> {
         let arg_list = forms.next().unwrap()?;
         let _old_doc = forms.next().unwrap()?;
         let body = forms.rest()?.map(|x| x.into()).unwrap_or(NIL);
-        Ok(Cons::new(arg_list, Cons::new(docstring, body, cx), cx).into())
+        Ok(Some(Cons::new(arg_list, Cons::new(docstring, body, cx), cx).into()))
     }
 
     fn eval_progx<'ob>(
@@ -293,27 +488,27 @@ impl Interpreter<'_, '_> {
         prog_num: u16,
         cx: &'ob mut Context,
     ) -> EvalResult<'ob> {
+        // Like Emacs, check the number of forms before evaluating any of them
+        let len = obj.bind(cx).as_list()?.len()? as u16;
+        if len < prog_num {
+            let name = match prog_num {
+                1 => "prog1",
+                2 => "prog2",
+                _ => "progn",
+            };
+            bail_err!(ArgError::new(prog_num, len, name));
+        }
         let mut count = 0;
-        root!(returned_form, None::<Object>, cx);
+        root!(returned_form, NIL, cx);
         rooted_iter!(forms, obj, cx);
         while let Some(form) = forms.next()? {
             let value = self.eval_form(form, cx)?;
             count += 1;
             if prog_num == count {
-                returned_form.set(Some(value));
-            }
-        }
-        match &**returned_form {
-            Some(x) => Ok(x.bind(cx)),
-            None => {
-                let name = match prog_num {
-                    1 => "prog1",
-                    2 => "prog2",
-                    _ => "progn",
-                };
-                Err(ArgError::new(prog_num, count, name).into())
+                returned_form.set(value);
             }
         }
+        Ok(returned_form.bind(cx))
     }
 
     fn eval_progn<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
@@ -394,6 +589,27 @@ impl Interpreter<'_, '_> {
         }
     }
 
+    /// `when` and `unless` are macros in subr.el, but they are common enough to
+    /// be worth evaluating directly, which also makes them available before
+    /// subr.el is loaded.
+    fn eval_when<'ob>(
+        &mut self,
+        obj: &Rto<Object>,
+        unless: bool,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        rooted_iter!(forms, obj, cx);
+        let Some(condition) = forms.next()? else {
+            bail_err!(ArgError::new(1, 0, if unless { "unless" } else { "when" }))
+        };
+        root!(condition, cx);
+        if (self.eval_form(condition, cx)? == NIL) == unless {
+            self.implicit_progn(forms, cx)
+        } else {
+            Ok(NIL)
+        }
+    }
+
     fn setq<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
         rooted_iter!(forms, obj, cx);
         let mut arg_cnt = 0;
@@ -404,8 +620,8 @@ impl Interpreter<'_, '_> {
                     root!(var, cx);
                     root!(val, cx);
                     let val = rebind!(self.eval_form(val, cx)?);
-                    self.var_set(var.bind(cx), val, cx)?;
                     last_value.set(val);
+                    self.var_set(var, last_value, cx)?;
                 }
                 (_, Some(_)) => bail_err!(TypeError::new(Type::Symbol, var)),
                 (_, None) => bail_err!(ArgError::new(arg_cnt, arg_cnt + 1, "setq")),
@@ -435,22 +651,28 @@ impl Interpreter<'_, '_> {
             let mut iter = self.vars.iter().rev();
             match iter.find_map(|cons| (cons.car(cx) == sym).then(|| cons.cdr(cx))) {
                 Some(value) => Ok(value),
-                None => match self.env.vars.get(sym) {
+                None => match self.env.var(sym, cx) {
                     Some(v) => Ok(v.bind(cx)),
-                    None => Err(error!("Void variable: {sym}")),
+                    None => Err(VoidError::variable(sym).into()),
                 },
             }
         }
     }
 
-    fn var_set(&mut self, name: Symbol, new_value: Object, cx: &Context) -> AnyResult<()> {
+    fn var_set(
+        &mut self,
+        name: &Rto<Symbol>,
+        new_value: &Rto<Object>,
+        cx: &mut Context,
+    ) -> AnyResult<()> {
         let mut iter = self.vars.iter().rev();
-        match iter.find(|cons| (cons.car(cx) == name)) {
+        match iter.find(|cons| (cons.car(cx) == name.bind(cx))) {
             Some(value) => {
+                let new_value = new_value.bind(cx);
                 value.bind(cx).set_cdr(new_value).expect("variables should never be immutable");
                 Ok(())
             }
-            None => self.env.set_var(name, new_value),
+            None => crate::data::set_variable(name, new_value, self.env, cx),
         }
     }
 
@@ -478,10 +700,11 @@ impl Interpreter<'_, '_> {
             self.let_bind_serial(obj, cx)
         }?;
         let obj = rebind!(self.implicit_progn(iter, cx)?);
+        root!(obj, cx);
         // Remove old bindings
         self.vars.truncate(prev_len);
-        self.env.unbind(varbind_count, cx);
-        Ok(obj)
+        crate::data::unbind(varbind_count, self.env, cx)?;
+        Ok(obj.bind(cx))
     }
 
     fn let_bind_serial(&mut self, form: &Rto<Object>, cx: &mut Context) -> Result<u16, EvalError> {
@@ -495,11 +718,15 @@ impl Interpreter<'_, '_> {
                     let val = rebind!(self.let_bind_value(cons, cx)?);
                     let var: Symbol =
                         cons.untag(cx).car().try_into().context("let variable must be a symbol")?;
-                    varbind_count += self.create_let_binding(var, val, cx);
+                    root!(var, cx);
+                    root!(val, cx);
+                    varbind_count += self.create_let_binding(var, val, cx)?;
                 }
                 // (let (x))
                 ObjectType::Symbol(sym) => {
-                    varbind_count += self.create_let_binding(sym, NIL, cx);
+                    root!(sym, cx);
+                    root!(val, NIL, cx);
+                    varbind_count += self.create_let_binding(sym, val, cx)?;
                 }
                 // (let (1))
                 x => bail_err!(TypeError::new(Type::Cons, x)),
@@ -534,20 +761,30 @@ impl Interpreter<'_, '_> {
             }
         }
         let mut sum = 0;
-        for (var, val) in let_bindings.bind_ref(cx) {
-            sum += self.create_let_binding(**var, **val, cx);
+        for i in 0..let_bindings.len() {
+            let (var, val) = &let_bindings.bind_ref(cx)[i];
+            let (var, val) = (**var, **val);
+            root!(var, cx);
+            root!(val, cx);
+            sum += self.create_let_binding(var, val, cx)?;
         }
         Ok(sum)
     }
 
-    fn create_let_binding(&mut self, var: Symbol, val: Object, cx: &Context) -> u16 {
-        if var.is_special() {
-            self.env.varbind(var, val, cx);
+    fn create_let_binding(
+        &mut self,
+        var: &Rto<Symbol>,
+        val: &Rto<Object>,
+        cx: &mut Context,
+    ) -> AnyResult<u16> {
+        if var.bind(cx).is_special() || !self.lexical {
+            crate::data::notify_variable_watchers(var, val, sym::LET, true, self.env, cx)?;
+            self.env.varbind(var.bind(cx), val.bind(cx), cx);
             // return 1 if the variable is bound
-            1
+            Ok(1)
         } else {
-            self.vars.push(Cons::new(var, val, cx));
-            0
+            self.vars.push(Cons::new(var.bind(cx), val.bind(cx), cx));
+            Ok(0)
         }
     }
 
@@ -570,6 +807,374 @@ impl Interpreter<'_, '_> {
         Ok(value)
     }
 
+    fn destructuring_bind<'ob>(
+        &mut self,
+        obj: &Rto<Object>,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        rooted_iter!(forms, obj, cx);
+        let Some(pattern) = forms.next()? else {
+            bail_err!(ArgError::new(2, 0, "cl-destructuring-bind"))
+        };
+        root!(pattern, cx);
+        let Some(expr) = forms.next()? else {
+            bail_err!(ArgError::new(2, 1, "cl-destructuring-bind"))
+        };
+        let value = rebind!(self.eval_form(expr, cx)?);
+        root!(value, cx);
+        let prev_len = self.vars.len();
+        let varbind_count = self.destructure(pattern, value, Shape::Exact, cx)?;
+        let obj = rebind!(self.implicit_progn(forms, cx)?);
+        root!(obj, cx);
+        // Remove old bindings
+        self.vars.truncate(prev_len);
+        crate::data::unbind(varbind_count, self.env, cx)?;
+        Ok(obj.bind(cx))
+    }
+
+    /// `(seq-let ARGS SEQUENCE BODY...)`. `ARGS` is destructured like a
+    /// lambda list with [`Shape::Loose`], so missing elements are nil.
+    fn seq_let<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
+        rooted_iter!(forms, obj, cx);
+        let Some(pattern) = forms.next()? else { bail_err!(ArgError::new(2, 0, "seq-let")) };
+        root!(pattern, cx);
+        let Some(expr) = forms.next()? else { bail_err!(ArgError::new(2, 1, "seq-let")) };
+        let value = rebind!(self.eval_form(expr, cx)?);
+        root!(value, cx);
+        let prev_len = self.vars.len();
+        let varbind_count = self.destructure(pattern, value, Shape::Loose, cx)?;
+        let obj = rebind!(self.implicit_progn(forms, cx)?);
+        root!(obj, cx);
+        // Remove old bindings
+        self.vars.truncate(prev_len);
+        crate::data::unbind(varbind_count, self.env, cx)?;
+        Ok(obj.bind(cx))
+    }
+
+    /// `pcase-let` and `pcase-let*` whose patterns are all variables,
+    /// literals and backquote patterns. See [`is_destructuring_let`].
+    fn pcase_let<'ob>(
+        &mut self,
+        obj: &Rto<Object>,
+        parallel: bool,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        rooted_iter!(forms, obj, cx);
+        let Some(binding_list) = forms.next()? else {
+            bail_err!(ArgError::new(1, 0, "pcase-let"))
+        };
+        let prev_len = self.vars.len();
+        let mut varbind_count = 0;
+        root!(let_bindings, new(Vec<(Slot<Object>, Slot<Object>)>), cx);
+        rooted_iter!(bindings, binding_list, cx);
+        while let Some(binding) = bindings.next()? {
+            let cons = binding.as_cons();
+            let value = rebind!(self.let_bind_value(cons, cx)?);
+            let pattern = cons.untag(cx).car();
+            if parallel {
+                let_bindings.push((pattern, value));
+            } else {
+                root!(pattern, cx);
+                root!(value, cx);
+                varbind_count += self.destructure_pcase(pattern, value, cx)?;
+            }
+        }
+        for i in 0..let_bindings.len() {
+            let (pattern, value) = &let_bindings.bind_ref(cx)[i];
+            let (pattern, value) = (**pattern, **value);
+            root!(pattern, cx);
+            root!(value, cx);
+            varbind_count += self.destructure_pcase(pattern, value, cx)?;
+        }
+        let obj = rebind!(self.implicit_progn(forms, cx)?);
+        root!(obj, cx);
+        // Remove old bindings
+        self.vars.truncate(prev_len);
+        crate::data::unbind(varbind_count, self.env, cx)?;
+        Ok(obj.bind(cx))
+    }
+
+    /// Bind the variables of the pcase `pattern` to the matching parts of
+    /// `value`. Like `pcase-let`, the value is assumed to match, so parts
+    /// missing from it are nil and literals are not checked.
+    fn destructure_pcase(
+        &mut self,
+        pattern: &Rto<Object>,
+        value: &Rto<Object>,
+        cx: &mut Context,
+    ) -> Result<u16, EvalError> {
+        match pattern.untag(cx) {
+            ObjectType::Symbol(var) if !is_pcase_literal(var) => {
+                root!(var, cx);
+                Ok(self.create_let_binding(var, value, cx)?)
+            }
+            ObjectType::Cons(cons) if cons.car() == sym::BACKQUOTE => {
+                root!(qpat, form_arg(cons), cx);
+                self.destructure_qpat(qpat, value, cx)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    /// Destructure the backquote pattern `qpat`, the part of a pcase pattern
+    /// after the backquote.
+    fn destructure_qpat(
+        &mut self,
+        qpat: &Rto<Object>,
+        value: &Rto<Object>,
+        cx: &mut Context,
+    ) -> Result<u16, EvalError> {
+        match qpat.untag(cx) {
+            // ,PATTERN
+            ObjectType::Cons(cons) if cons.car() == sym::UNQUOTE => {
+                root!(pattern, form_arg(cons), cx);
+                self.destructure_pcase(pattern, value, cx)
+            }
+            ObjectType::Cons(cons) => {
+                let (car, cdr) = match value.untag(cx) {
+                    ObjectType::Cons(value) => (value.car(), value.cdr()),
+                    _ => (NIL, NIL),
+                };
+                root!(car, cx);
+                root!(cdr, cx);
+                root!(car_qpat, cons.car(), cx);
+                let mut varbind_count = self.destructure_qpat(car_qpat, car, cx)?;
+                let cdr_qpat = match qpat.untag(cx) {
+                    ObjectType::Cons(cons) => cons.cdr(),
+                    _ => unreachable!(),
+                };
+                root!(cdr_qpat, cx);
+                varbind_count += self.destructure_qpat(cdr_qpat, cdr, cx)?;
+                Ok(varbind_count)
+            }
+            ObjectType::Vec(qpats) => {
+                let mut varbind_count = 0;
+                for i in 0..qpats.len() {
+                    let ObjectType::Vec(qpats) = qpat.untag(cx) else { unreachable!() };
+                    let elem = match value.untag(cx) {
+                        ObjectType::Vec(vec) => vec.get(i).map_or(NIL, |x| x.get()),
+                        _ => NIL,
+                    };
+                    root!(elem, cx);
+                    root!(elem_qpat, qpats[i].get(), cx);
+                    varbind_count += self.destructure_qpat(elem_qpat, elem, cx)?;
+                }
+                Ok(varbind_count)
+            }
+            // Literals are not checked
+            _ => Ok(0),
+        }
+    }
+
+    /// Bind the variables in `pattern` to the matching parts of `value`. The
+    /// pattern is a CL style lambda list that can contain nested patterns,
+    /// `&optional`, `&rest`, `&key` and `&aux` sections, and a dotted tail.
+    /// Bindings are created in order, so the default forms of later variables
+    /// can refer to earlier ones. This is the shared core of
+    /// `cl-destructuring-bind` style binding forms. Returns the number of
+    /// dynamic bindings that need to be unbound afterwards.
+    fn destructure(
+        &mut self,
+        pattern: &Rto<Object>,
+        value: &Rto<Object>,
+        shape: Shape,
+        cx: &mut Context,
+    ) -> Result<u16, EvalError> {
+        match pattern.untag(cx) {
+            ObjectType::NIL => {
+                if shape == Shape::Exact && value.bind(cx) != NIL {
+                    bail_err!("Too many elements to destructure: {value}");
+                }
+                Ok(0)
+            }
+            ObjectType::Symbol(var) => {
+                root!(var, cx);
+                Ok(self.create_let_binding(var, value, cx)?)
+            }
+            ObjectType::Cons(_) => self.destructure_list(pattern, value, shape, cx),
+            ObjectType::Vec(_) if shape == Shape::Loose => {
+                root!(pattern, sequence_as_list(pattern.bind(cx), cx), cx);
+                self.destructure_list(pattern, value, shape, cx)
+            }
+            x => bail_err!(TypeError::new(Type::List, x)),
+        }
+    }
+
+    fn destructure_list(
+        &mut self,
+        pattern: &Rto<Object>,
+        value: &Rto<Object>,
+        shape: Shape,
+        cx: &mut Context,
+    ) -> Result<u16, EvalError> {
+        let mut varbind_count = 0;
+        let mut section = LambdaListSection::Required;
+        let mut has_rest = false;
+        let mut has_keys = false;
+        let mut allow_other_keys = false;
+        root!(tail, pattern.bind(cx), cx);
+        root!(remaining, value.bind(cx), cx);
+        if shape == Shape::Loose {
+            remaining.set(sequence_as_list(value.bind(cx), cx));
+        }
+        root!(keys, NIL, cx);
+        root!(known_keys, new(Vec<Slot<Symbol>>), cx);
+        loop {
+            let item = match tail.untag(cx) {
+                ObjectType::NIL => break,
+                ObjectType::Cons(cons) => {
+                    tail.set(cons.cdr());
+                    cons.car()
+                }
+                // (a b . rest)
+                ObjectType::Symbol(var) => {
+                    root!(var, cx);
+                    varbind_count += self.create_let_binding(var, remaining, cx)?;
+                    has_rest = true;
+                    break;
+                }
+                x => bail_err!(TypeError::new(Type::List, x)),
+            };
+            root!(item, cx);
+            if let ObjectType::Symbol(marker) = item.untag(cx) {
+                let next_section = match marker {
+                    sym::AND_WHOLE => Some(LambdaListSection::Whole),
+                    sym::AND_OPTIONAL => Some(LambdaListSection::Optional),
+                    sym::AND_REST | sym::AND_BODY => Some(LambdaListSection::Rest),
+                    sym::AND_KEY => Some(LambdaListSection::Key),
+                    sym::AND_AUX => Some(LambdaListSection::Aux),
+                    sym::AND_ALLOW_OTHER_KEYS => {
+                        allow_other_keys = true;
+                        continue;
+                    }
+                    _ => None,
+                };
+                if let Some(next_section) = next_section {
+                    if next_section == LambdaListSection::Key {
+                        // &rest and &key both operate on the same list
+                        has_keys = true;
+                        keys.set(remaining.bind(cx));
+                    }
+                    section = next_section;
+                    continue;
+                }
+            }
+            match section {
+                LambdaListSection::Whole => {
+                    varbind_count += self.destructure(item, value, shape, cx)?;
+                    section = LambdaListSection::Required;
+                }
+                LambdaListSection::Required => {
+                    let elem = match pop_front(remaining, cx) {
+                        Some(elem) => elem,
+                        None if shape == Shape::Loose => NIL,
+                        None => {
+                            bail_err!("Not enough elements to destructure {value} with {pattern}")
+                        }
+                    };
+                    root!(elem, cx);
+                    varbind_count += self.destructure(item, elem, shape, cx)?;
+                }
+                LambdaListSection::Optional => {
+                    let (var, default, svar) = split_var_spec(item.bind(cx))?;
+                    root!(var, cx);
+                    root!(default, cx);
+                    root!(svar, cx);
+                    let elem = pop_front(remaining, cx);
+                    root!(elem, cx);
+                    varbind_count +=
+                        self.bind_with_default(var, (**elem).as_ref(), default, svar, shape, cx)?;
+                }
+                LambdaListSection::Rest => {
+                    varbind_count += self.destructure(item, remaining, shape, cx)?;
+                    has_rest = true;
+                }
+                LambdaListSection::Key => {
+                    let (spec, default, svar) = split_var_spec(item.bind(cx))?;
+                    let (keyword, var) = match spec.untag() {
+                        // ((KEYWORD VAR) DEFAULT SVAR)
+                        ObjectType::Cons(cons) => {
+                            let (keyword, var, _) = split_var_spec(cons.into())?;
+                            let keyword: Symbol = keyword.try_into()?;
+                            (keyword, var)
+                        }
+                        ObjectType::Symbol(var) => {
+                            let keyword = crate::core::env::intern(&format!(":{var}"), cx);
+                            (keyword, spec)
+                        }
+                        x => bail_err!(TypeError::new(Type::Symbol, x)),
+                    };
+                    known_keys.push(keyword);
+                    root!(var, cx);
+                    root!(default, cx);
+                    root!(svar, cx);
+                    let elem = plist_lookup(keys.bind(cx), keyword)?;
+                    root!(elem, cx);
+                    varbind_count +=
+                        self.bind_with_default(var, (**elem).as_ref(), default, svar, shape, cx)?;
+                }
+                LambdaListSection::Aux => {
+                    let (var, init, _) = split_var_spec(item.bind(cx))?;
+                    root!(var, cx);
+                    root!(init, cx);
+                    let value = rebind!(self.eval_form(init, cx)?);
+                    root!(value, cx);
+                    varbind_count += self.destructure(var, value, shape, cx)?;
+                }
+            }
+        }
+
+        if has_keys {
+            let plist = keys.bind(cx);
+            let allow_other_keys = allow_other_keys
+                || plist_lookup(plist, sym::KW_ALLOW_OTHER_KEYS)?.is_some_and(|x| x != NIL);
+            if !allow_other_keys {
+                let mut iter = plist.as_list()?;
+                while let Some(key) = iter.next() {
+                    let key = key?;
+                    if !known_keys.iter().any(|x| key == x.bind(cx)) {
+                        bail_err!("Keyword argument {key} not one of {pattern}");
+                    }
+                    iter.next();
+                }
+            }
+        } else if shape == Shape::Exact && !has_rest && remaining.bind(cx) != NIL {
+            bail_err!("Too many elements to destructure {value} with {pattern}");
+        }
+        Ok(varbind_count)
+    }
+
+    /// Bind `var` to `value` if it was supplied, otherwise to the result of
+    /// evaluating `default`. If `svar` is non-nil it is bound to whether the
+    /// value was supplied.
+    fn bind_with_default(
+        &mut self,
+        var: &Rto<Object>,
+        value: Option<&Rto<Object>>,
+        default: &Rto<Object>,
+        svar: &Rto<Object>,
+        shape: Shape,
+        cx: &mut Context,
+    ) -> Result<u16, EvalError> {
+        let supplied = value.is_some();
+        let value = match value {
+            Some(x) => x.bind(cx),
+            None => rebind!(self.eval_form(default, cx)?),
+        };
+        root!(value, cx);
+        let mut varbind_count = self.destructure(var, value, shape, cx)?;
+        match svar.untag(cx) {
+            ObjectType::NIL => {}
+            ObjectType::Symbol(svar) => {
+                root!(svar, cx);
+                root!(supplied, Object::from(supplied), cx);
+                varbind_count += self.create_let_binding(svar, supplied, cx)?;
+            }
+            x => bail_err!(TypeError::new(Type::Symbol, x)),
+        }
+        Ok(varbind_count)
+    }
+
     fn implicit_progn<'ob>(
         &mut self,
         mut forms: ElemStreamIter<'_>,
@@ -599,29 +1204,194 @@ impl Interpreter<'_, '_> {
         }
     }
 
-    fn save_excursion<'ob>(&mut self, form: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
-        let point = self.env.current_buffer.as_ref().map(|b| b.text.cursor());
-        let buffer = self.env.current_buffer.as_ref().map(|b| (b.lisp_buffer(cx)));
-        root!(buffer, cx);
-        let result = rebind!(self.eval_progn(form, cx)?);
-        if let Some(buffer) = buffer.bind_ref(cx) {
-            self.env.set_buffer(buffer)?;
-            let buf = self.env.current_buffer.as_mut().unwrap();
-            buf.text.set_cursor(point.unwrap().chars());
-        }
-        Ok(result)
-    }
-
-    fn save_current_buffer<'ob>(
+    /// `cl-letf` and `cl-letf*`. Each PLACE is set to its VALUE, and set back
+    /// when the body exits, even by an error or `throw`. A symbol is bound
+    /// like `let`. The arguments of any other place are evaluated once, and it
+    /// is read and set with `setf`, except for the function and value cells of
+    /// a symbol, which are set directly so that a void cell is made void
+    /// again. Used when `cl-letf` is not defined as a macro.
+    fn letf<'ob>(
         &mut self,
-        form: &Rto<Object>,
+        obj: &Rto<Object>,
+        parallel: bool,
         cx: &'ob mut Context,
     ) -> EvalResult<'ob> {
-        let buffer = self.env.current_buffer.as_ref().map(|x| x.lisp_buffer(cx));
-        root!(buffer, cx);
-        let result = rebind!(self.eval_progn(form, cx)?);
-        if let Some(buffer) = buffer.bind_ref(cx) {
-            self.env.set_buffer(buffer)?;
+        let name = if parallel { "cl-letf" } else { "cl-letf*" };
+        rooted_iter!(forms, obj, cx);
+        let Some(bindings) = forms.next()? else { bail_err!(ArgError::new(1, 0, name)) };
+        let prev_len = self.vars.len();
+        // Forms that set the places back, in the order the places were set
+        root!(restore, new(Vec<Slot<Object>>), cx);
+        let varbind_count = match self.letf_bind(bindings, parallel, restore, cx) {
+            Ok(x) => x,
+            Err(e) => {
+                self.letf_restore(restore, cx)?;
+                return Err(e);
+            }
+        };
+        match self.implicit_progn(forms, cx) {
+            Ok(x) => {
+                root!(x, cx);
+                self.letf_restore(restore, cx)?;
+                self.vars.truncate(prev_len);
+                crate::data::unbind(varbind_count, self.env, cx)?;
+                Ok(x.bind(cx))
+            }
+            Err(e) => {
+                self.letf_restore(restore, cx)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Set the places in the `bindings` of a `cl-letf`. `cl-letf` evaluates
+    /// all the values before setting any place, and `cl-letf*` sets each
+    /// place in turn. Returns the number of dynamic bindings made.
+    fn letf_bind(
+        &mut self,
+        bindings: &Rto<Object>,
+        parallel: bool,
+        restore: &mut Rt<Vec<Slot<Object>>>,
+        cx: &mut Context,
+    ) -> Result<u16, EvalError> {
+        let mut varbind_count = 0;
+        // Entries of the form (PLACE) or (PLACE VALUE), where PLACE has its
+        // arguments evaluated
+        root!(entries, new(Vec<Slot<Object>>), cx);
+        rooted_iter!(bindings, bindings, cx);
+        while let Some(binding) = bindings.next()? {
+            rooted_iter!(parts, binding, cx);
+            let Some(place) = parts.next()? else { bail_err!("Invalid cl-letf binding: nil") };
+            let place = rebind!(self.letf_place(place, cx)?);
+            root!(place, cx);
+            let entry = match parts.next()? {
+                Some(value) => {
+                    let value = rebind!(self.eval_form(value, cx)?);
+                    list![place.bind(cx), value; cx]
+                }
+                None => list![place.bind(cx); cx],
+            };
+            if !parts.is_empty() {
+                bail_err!("cl-letf binding can only have 1 value");
+            }
+            if parallel {
+                entries.push(entry);
+            } else {
+                root!(entry, cx);
+                varbind_count += self.letf_set(entry, restore, cx)?;
+            }
+        }
+        for i in 0..entries.len() {
+            varbind_count += self.letf_set(&entries[i], restore, cx)?;
+        }
+        Ok(varbind_count)
+    }
+
+    /// Evaluate the arguments of a `cl-letf` place, so that they are only
+    /// evaluated once. A symbol is returned as is.
+    fn letf_place<'ob>(
+        &mut self,
+        place: &Rto<Object>,
+        cx: &'ob mut Context,
+    ) -> Result<Object<'ob>, EvalError> {
+        match place.untag(cx) {
+            ObjectType::Symbol(_) => return Ok(place.bind(cx)),
+            ObjectType::Cons(_) => {}
+            x => bail_err!(TypeError::new(Type::Cons, x)),
+        }
+        rooted_iter!(forms, place, cx);
+        root!(values, new(Vec<Slot<Object>>), cx);
+        if let Some(func) = forms.next()? {
+            values.push(func.bind(cx));
+        }
+        while let Some(form) = forms.next()? {
+            let value = self.eval_form(form, cx)?;
+            values.push(value);
+        }
+        let mut place = NIL;
+        for value in values.iter().rev() {
+            place = Cons::new(value.bind(cx), place, cx).into();
+        }
+        Ok(place)
+    }
+
+    /// Set the place in `entry`, which is (PLACE) or (PLACE VALUE), and add
+    /// the form that sets it back to `restore`.
+    fn letf_set(
+        &mut self,
+        entry: &Rto<Object>,
+        restore: &mut Rt<Vec<Slot<Object>>>,
+        cx: &mut Context,
+    ) -> Result<u16, EvalError> {
+        let (place, value) = letf_entry(entry.bind(cx));
+        if let ObjectType::Symbol(var) = place.untag() {
+            let value = match value {
+                Some(value) => value,
+                // (cl-letf ((x)))
+                None => self.var_ref(var, cx)?,
+            };
+            root!(var, cx);
+            root!(value, cx);
+            return Ok(self.create_let_binding(var, value, cx)?);
+        }
+        let void = match letf_cell(place) {
+            Some((symbol, sym::FSET, _)) => !symbol.has_func(),
+            Some((symbol, ..)) => self.env.var(symbol, cx).is_none(),
+            None => false,
+        };
+        if void {
+            let (symbol, _, unset) = letf_cell(place).unwrap();
+            restore.push(quoted_call(unset.into(), &[symbol.into()], cx));
+        } else {
+            let getter = letf_getter(place, cx);
+            root!(getter, cx);
+            let old = rebind!(self.eval_form(getter, cx)?);
+            let (place, _) = letf_entry(entry.bind(cx));
+            restore.push(letf_setter(place, old, cx));
+        }
+        if let (place, Some(value)) = letf_entry(entry.bind(cx)) {
+            let setter = letf_setter(place, value, cx);
+            root!(setter, cx);
+            self.eval_form(setter, cx)?;
+        }
+        Ok(0)
+    }
+
+    /// Evaluate the `restore` forms of a `cl-letf`, most recent first.
+    fn letf_restore(
+        &mut self,
+        restore: &Rt<Vec<Slot<Object>>>,
+        cx: &mut Context,
+    ) -> Result<(), EvalError> {
+        for i in (0..restore.len()).rev() {
+            self.eval_form(&restore[i], cx)?;
+        }
+        Ok(())
+    }
+
+    fn save_excursion<'ob>(&mut self, form: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
+        let point = self.env.current_buffer.as_ref().map(|b| b.text.cursor());
+        let buffer = self.env.current_buffer.as_ref().map(|b| (b.lisp_buffer(cx)));
+        root!(buffer, cx);
+        let result = rebind!(self.eval_progn(form, cx)?);
+        if let Some(buffer) = buffer.bind_ref(cx) {
+            self.env.set_buffer(buffer)?;
+            let buf = self.env.current_buffer.as_mut().unwrap();
+            buf.text.set_cursor(point.unwrap().chars());
+        }
+        Ok(result)
+    }
+
+    fn save_current_buffer<'ob>(
+        &mut self,
+        form: &Rto<Object>,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        let buffer = self.env.current_buffer.as_ref().map(|x| x.lisp_buffer(cx));
+        root!(buffer, cx);
+        let result = rebind!(self.eval_progn(form, cx)?);
+        if let Some(buffer) = buffer.bind_ref(cx) {
+            self.env.set_buffer(buffer)?;
         }
         Ok(result)
     }
@@ -634,53 +1404,39 @@ impl Interpreter<'_, '_> {
             bail_err!(ArgError::new(2, 1, "condition-case"))
         };
         let err = match self.eval_form(bodyform, cx) {
-            Ok(x) => return Ok(rebind!(x, cx)),
+            Ok(x) => {
+                root!(x, cx);
+                // A `:success' handler is run with the value of the body
+                while let Some(handler) = forms.next()? {
+                    if let ObjectType::Cons(cons) = handler.untag(cx) {
+                        if cons.car() == sym::KW_SUCCESS {
+                            root!(body, cons.cdr(), cx);
+                            return self.condition_handler(var, x, body, cx);
+                        }
+                    }
+                }
+                return Ok(x.bind(cx));
+            }
             Err(e) => e,
         };
-        if matches!(err.error, ErrorType::Throw(_)) {
+        if matches!(err.error, ErrorType::Throw(..)) {
             return Err(err);
         }
         while let Some(handler) = forms.next()? {
             match handler.untag(cx) {
                 ObjectType::Cons(cons) => {
-                    // Check that conditions match
-                    let condition = cons.car();
-                    match condition.untag() {
-                        ObjectType::Symbol(sym::ERROR | sym::VOID_VARIABLE) => {}
-                        // TODO: Remove this once error handling is correctly implemented
-                        ObjectType::Symbol(s) if s.name() == "cl--generic-cyclic-definition" => {}
-                        ObjectType::Cons(conditions) => {
-                            for condition in conditions {
-                                let condition = condition?;
-                                // TODO: Handle different error symbols
-                                if condition != sym::DEBUG && condition != sym::ERROR {
-                                    bail_err!("non-error conditions {condition} not yet supported")
-                                }
-                            }
-                        }
-                        _ => bail_err!("Invalid condition handler: {condition}"),
+                    if cons.car() == sym::KW_SUCCESS {
+                        continue;
+                    }
+                    let error_symbol = err.symbol(cx);
+                    if !handles_error(cons.car(), error_symbol, self.env, cx)? {
+                        continue;
                     }
                     // Call handlers with error
-                    let error = if let ErrorType::Signal(id) = err.error {
-                        let Some((sym, data)) = self.env.get_exception(id) else {
-                            unreachable!("Exception not found")
-                        };
-                        Cons::new(sym, data, cx)
-                    } else {
-                        // TODO: Need to remove the anyhow branch once
-                        // full errors are implemented
-                        Cons::new(sym::ERROR, format!("{err}"), cx)
-                    };
-                    let binding = Cons::new(var, Cons::new1(error, cx), cx);
-                    self.vars.push(binding);
-                    let list: List = match cons.cdr().try_into() {
-                        Ok(x) => x,
-                        Err(_) => return Ok(NIL),
-                    };
-                    rooted_iter!(handlers, list, cx);
-                    let result = self.implicit_progn(handlers, cx)?;
-                    self.vars.pop();
-                    return Ok(result);
+                    let error = err.to_lisp(cx);
+                    root!(error, cx);
+                    root!(body, cons.cdr(), cx);
+                    return self.condition_handler(var, error, body, cx);
                 }
                 ObjectType::NIL => {}
                 invalid => bail_err!("Invalid condition handler: {invalid}"),
@@ -688,6 +1444,166 @@ impl Interpreter<'_, '_> {
         }
         Err(err)
     }
+
+    /// Run the `body` of a `condition-case` handler with `var` bound to
+    /// `value`, unless `var` is nil. The binding is removed however the body
+    /// exits.
+    fn condition_handler<'ob>(
+        &mut self,
+        var: &Rto<Object>,
+        value: &Rto<Object>,
+        body: &Rto<Object>,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        let list: List = match body.bind(cx).try_into() {
+            Ok(x) => x,
+            Err(_) => return Ok(NIL),
+        };
+        let var: Option<Symbol> = match var.bind(cx).untag() {
+            ObjectType::NIL => None,
+            ObjectType::Symbol(var) => Some(var),
+            other => bail_err!(TypeError::new(Type::Symbol, other)),
+        };
+        match var {
+            Some(var) if self.lexical => {
+                self.vars.push(Cons::new(var, Cons::new1(value.bind(cx), cx), cx));
+            }
+            Some(var) => self.env.varbind(var, value.bind(cx), cx),
+            None => {}
+        }
+        rooted_iter!(handlers, list, cx);
+        let result = self.implicit_progn(handlers, cx);
+        match var {
+            Some(_) if self.lexical => {
+                self.vars.pop();
+            }
+            Some(_) => self.env.unbind(1, cx),
+            None => {}
+        }
+        result
+    }
+}
+
+/// The sections of a destructuring lambda list.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum LambdaListSection {
+    Whole,
+    Required,
+    Optional,
+    Rest,
+    Key,
+    Aux,
+}
+
+/// How closely a value has to follow the shape of a destructuring pattern.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Shape {
+    /// Missing or extra elements are an error, as in `cl-destructuring-bind`.
+    Exact,
+    /// Missing elements are nil, extra ones are ignored and arrays are taken
+    /// apart like lists, as in `seq-let`.
+    Loose,
+}
+
+/// The elements of the array `seq` as a list. Other objects are returned
+/// unchanged.
+fn sequence_as_list<'ob>(seq: Object<'ob>, cx: &'ob Context) -> Object<'ob> {
+    let elements: Vec<Object> = match seq.untag() {
+        ObjectType::Vec(vec) => vec.iter().map(|x| x.get()).collect(),
+        ObjectType::String(string) => string.chars().map(|x| (x as i64).into()).collect(),
+        _ => return seq,
+    };
+    crate::fns::slice_into_list(&elements, None, cx)
+}
+
+/// Whether the symbol `var` is matched literally or ignored in a pcase pattern
+/// instead of being bound.
+fn is_pcase_literal(var: Symbol) -> bool {
+    var.is_const() || var.name() == "_"
+}
+
+/// The argument of a form like `(quote X)`.
+fn form_arg(form: &Cons) -> Object {
+    match form.cdr().untag() {
+        ObjectType::Cons(tail) => tail.car(),
+        _ => NIL,
+    }
+}
+
+/// Whether `forms`, the arguments of a `pcase-let`, only use patterns that
+/// destructure. Other patterns, like `pred` or `or`, are left to the lisp
+/// definition of `pcase-let`.
+fn is_destructuring_let(forms: Object) -> bool {
+    let ObjectType::Cons(forms) = forms.untag() else { return false };
+    let Ok(mut bindings) = forms.car().as_list() else { return false };
+    bindings.all(|binding| match binding.map(|x| x.untag()) {
+        Ok(ObjectType::Cons(binding)) => is_destructuring_pattern(binding.car()),
+        _ => false,
+    })
+}
+
+/// Whether the pcase `pattern` is a variable, a literal or a backquote pattern
+/// made of those.
+fn is_destructuring_pattern(pattern: Object) -> bool {
+    let ObjectType::Cons(cons) = pattern.untag() else { return true };
+    let arg = match cons.cdr().untag() {
+        ObjectType::Cons(tail) if tail.cdr() == NIL => tail.car(),
+        _ => return false,
+    };
+    match cons.car() {
+        head if head == sym::QUOTE => true,
+        head if head == sym::BACKQUOTE => is_destructuring_qpat(arg),
+        _ => false,
+    }
+}
+
+fn is_destructuring_qpat(qpat: Object) -> bool {
+    match qpat.untag() {
+        ObjectType::Cons(cons) if cons.car() == sym::UNQUOTE => match cons.cdr().untag() {
+            ObjectType::Cons(tail) => tail.cdr() == NIL && is_destructuring_pattern(tail.car()),
+            _ => false,
+        },
+        ObjectType::Cons(cons) => {
+            is_destructuring_qpat(cons.car()) && is_destructuring_qpat(cons.cdr())
+        }
+        ObjectType::Vec(vec) => vec.iter().all(|x| is_destructuring_qpat(x.get())),
+        _ => true,
+    }
+}
+
+/// Remove the first element from `list`, returning `None` once it is exhausted.
+fn pop_front<'ob>(list: &mut Rto<Object>, cx: &'ob Context) -> Option<Object<'ob>> {
+    let ObjectType::Cons(cons) = list.untag(cx) else { return None };
+    list.set(cons.cdr());
+    Some(cons.car())
+}
+
+/// Split a variable specifier of the form `VAR` or `(VAR [DEFAULT [SVAR]])`.
+fn split_var_spec(spec: Object) -> AnyResult<(Object, Object, Object)> {
+    match spec.untag() {
+        ObjectType::Cons(cons) => {
+            let mut iter = cons.elements();
+            let var = iter.next().unwrap()?;
+            let default = iter.next().transpose()?.unwrap_or(NIL);
+            let svar = iter.next().transpose()?.unwrap_or(NIL);
+            ensure!(iter.next().is_none(), "Invalid variable specifier: {spec}");
+            Ok((var, default, svar))
+        }
+        _ => Ok((spec, NIL, NIL)),
+    }
+}
+
+/// Find the value of `key` in `plist`. Unlike `plist-get` this distinguishes a
+/// missing key from one with a nil value.
+fn plist_lookup<'ob>(plist: Object<'ob>, key: Symbol) -> AnyResult<Option<Object<'ob>>> {
+    let mut iter = plist.as_list()?;
+    while let Some(prop) = iter.next() {
+        let Some(value) = iter.next() else { bail!("Odd number of elements in keyword list") };
+        if prop? == key {
+            return Ok(Some(value?));
+        }
+    }
+    Ok(None)
 }
 
 pub(crate) fn call_closure<'ob>(
@@ -705,13 +1621,137 @@ pub(crate) fn call_closure<'ob>(
             let args = Rt::bind_slice(&env.stack[..arg_cnt], cx);
             let vars = bind_variables(&mut forms, args, name, cx)?;
             debug!("call vars: {vars:?}");
+            let body = skip_declarations(closure_body(closure));
             root!(vars, cx);
-            Interpreter { vars, env }.implicit_progn(forms, cx)
+            rooted_iter!(body, body, cx);
+            Interpreter { vars, env, lexical: true }.implicit_progn(body, cx)
+        }
+        // A function from dynamically bound code. Its arguments are bound
+        // dynamically for the duration of the call.
+        ObjectType::Symbol(sym::LAMBDA) => {
+            let (arg_list, body) = match closure.cdr().untag() {
+                ObjectType::Cons(cons) => (cons.car(), cons.cdr()),
+                _ => (NIL, NIL),
+            };
+            let args = Rt::bind_slice(&env.stack[..arg_cnt], cx);
+            let mut params = Vec::new();
+            bind_args(arg_list, args, &mut params, name, cx)?;
+            // Check every parameter before binding any, so an error doesn't
+            // leave earlier bindings behind
+            let bindings = params
+                .iter()
+                .map(|param| Ok((param.car().try_into()?, param.cdr())))
+                .collect::<AnyResult<Vec<(Symbol, Object)>>>()?;
+            for (var, value) in bindings {
+                env.varbind(var, value, cx);
+            }
+            let count = params.len() as u16;
+            root!(vars, new(Vec<Slot<&Cons>>), cx);
+            root!(body, skip_declarations(body), cx);
+            let result = Interpreter { vars, env, lexical: false }.eval_progn(body, cx);
+            let value = match result {
+                Ok(x) => rebind!(x, cx),
+                Err(e) => {
+                    crate::data::unbind(count, env, cx)?;
+                    return Err(e);
+                }
+            };
+            root!(value, cx);
+            crate::data::unbind(count, env, cx)?;
+            Ok(value.bind(cx))
         }
         other => Err(TypeError::new(Type::Func, other).into()),
     }
 }
 
+pub(crate) fn call_closure_object<'ob>(
+    closure: &Rto<&Closure>,
+    arg_cnt: usize,
+    name: &str,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> EvalResult<'ob> {
+    cx.garbage_collect(false);
+    let closure = closure.bind(cx);
+    let args = Rt::bind_slice(&env.stack[..arg_cnt], cx);
+    // Reserve room for the parameters up front so binding them doesn't grow
+    // the vector.
+    let mut vars = Vec::with_capacity(closure.env().len() + closure.params().len());
+    vars.extend_from_slice(closure.env());
+    bind_params(closure.params(), closure.args, args, &mut vars, name, cx)?;
+    root!(vars, cx);
+    root!(body, skip_declarations(closure.body()), cx);
+    Interpreter { vars, env, lexical: true }.eval_progn(body, cx)
+}
+
+/// The body of a `(closure ENV ARGS . BODY)` list.
+fn closure_body(closure: &Cons) -> Object {
+    let mut body = closure.cdr();
+    for _ in 0..2 {
+        body = match body.untag() {
+            ObjectType::Cons(cons) => cons.cdr(),
+            _ => NIL,
+        };
+    }
+    body
+}
+
+/// Whether the first form of `body` is a docstring, `declare` or `interactive`
+/// form, which are not evaluated when the function is called. A docstring that
+/// is the last form is the return value instead.
+fn is_declaration(body: &Cons) -> bool {
+    match body.car().untag() {
+        ObjectType::String(_) => !body.cdr().is_nil(),
+        ObjectType::Cons(form) => {
+            matches!(form.car().untag(), ObjectType::Symbol(sym::INTERACTIVE | sym::DECLARE))
+        }
+        _ => false,
+    }
+}
+
+/// Skip the declarations at the start of a function body.
+fn skip_declarations(mut body: Object) -> Object {
+    while let ObjectType::Cons(cons) = body.untag() {
+        if !is_declaration(cons) {
+            break;
+        }
+        body = cons.cdr();
+    }
+    body
+}
+
+/// Return the `(interactive ...)` form of `cmd` if it is an interpreted
+/// command.
+#[defun]
+fn interactive_form<'ob>(cmd: Object<'ob>, cx: &'ob Context) -> Object<'ob> {
+    let func = match cmd.untag() {
+        ObjectType::Symbol(symbol) => match symbol.follow_indirect(cx) {
+            Some(func) => func.into(),
+            None => return NIL,
+        },
+        _ => cmd,
+    };
+    let mut body = match func.untag() {
+        ObjectType::Closure(closure) => closure.body(),
+        ObjectType::Cons(cons) if cons.car() == sym::CLOSURE => closure_body(cons),
+        ObjectType::Cons(cons) if cons.car() == sym::LAMBDA => match cons.cdr().untag() {
+            ObjectType::Cons(lambda) => lambda.cdr(),
+            _ => NIL,
+        },
+        _ => NIL,
+    };
+    while let ObjectType::Cons(cons) = body.untag() {
+        if !is_declaration(cons) {
+            break;
+        }
+        match cons.car().untag() {
+            ObjectType::Cons(form) if form.car() == sym::INTERACTIVE => return cons.car(),
+            _ => body = cons.cdr(),
+        }
+    }
+    NIL
+}
+
 fn bind_variables<'a>(
     forms: &mut ElemStreamIter<'_>,
     args: &[Object<'a>],
@@ -757,10 +1797,87 @@ fn bind_args<'a>(
     name: &str,
     cx: &'a Context,
 ) -> AnyResult<()> {
+    let ObjectType::Cons(head) = arg_list.untag() else {
+        let (spec, params) = parse_params(arg_list)?;
+        return bind_params(&params, spec, args, vars, name, cx);
+    };
+    ARG_LISTS.with_borrow_mut(|cache| {
+        let epoch = (collections(), watched_changes());
+        if cache.epoch != epoch {
+            // Mutable lists may have moved or changed since they were parsed
+            cache.lists.retain(|_, x| !x.mutable);
+            cache.epoch = epoch;
+        }
+        let key = std::ptr::from_ref(head) as usize;
+        if !cache.lists.contains_key(&key) {
+            let (spec, params) = parse_params(arg_list)?;
+            let mutable = head.is_mutable();
+            if mutable {
+                let mut tail = arg_list;
+                while let ObjectType::Cons(cons) = tail.untag() {
+                    cons.watch();
+                    tail = cons.cdr();
+                }
+            }
+            // SAFETY: Entries for mutable lists are dropped before the next
+            // collection could move or free their symbols. Immutable lists are
+            // in a block that is never collected.
+            let params = params.into_iter().map(|x| unsafe { x.with_lifetime() }).collect();
+            cache.lists.insert(key, ParsedArgs { spec, params, mutable });
+        }
+        let parsed = &cache.lists[&key];
+        bind_params(&parsed.params, parsed.spec, args, vars, name, cx)
+    })
+}
+
+/// An argument list parsed by [`parse_params`].
+struct ParsedArgs {
+    spec: FnArgs,
+    params: Vec<Symbol<'static>>,
+    /// Whether the list can be modified or moved by the collector
+    mutable: bool,
+}
+
+/// Parsed argument lists, keyed by the address of the list, so that calling
+/// an interpreted function doesn't parse its arguments again.
+#[derive(Default)]
+struct ArgListCache {
+    /// The values of [`collections`] and [`watched_changes`] when the entries
+    /// for mutable lists were checked. They are dropped when either changes.
+    epoch: (u64, u64),
+    lists: HashMap<usize, ParsedArgs>,
+}
+
+thread_local! {
+    static ARG_LISTS: RefCell<ArgListCache> = RefCell::default();
+}
+
+/// Parse `arg_list` into an argument spec and the parameters ordered as
+/// required, optional, and then rest.
+fn parse_params(arg_list: Object) -> AnyResult<(FnArgs, Vec<Symbol>)> {
     let (required, optional, rest) = parse_arg_list(arg_list)?;
+    let spec = FnArgs {
+        required: required.len() as u16,
+        optional: optional.len() as u16,
+        rest: rest.is_some(),
+        ..FnArgs::default()
+    };
+    let params = required.into_iter().chain(optional).chain(rest).collect();
+    Ok((spec, params))
+}
 
-    let num_required_args = required.len() as u16;
-    let num_optional_args = optional.len() as u16;
+/// Bind `args` to `params`, which are ordered as required, optional, and then
+/// rest parameters as described by `spec`.
+fn bind_params<'a>(
+    params: &[Symbol],
+    spec: FnArgs,
+    args: &[Object<'a>],
+    vars: &mut Vec<&'a Cons>,
+    name: &str,
+    cx: &'a Context,
+) -> AnyResult<()> {
+    let num_required_args = spec.required;
+    let num_optional_args = spec.optional;
     let num_actual_args = args.len() as u16;
     // Ensure the minimum number of arguments is present
     ensure!(
@@ -768,22 +1885,18 @@ fn bind_args<'a>(
         ArgError::new(num_required_args, num_actual_args, name)
     );
 
+    let positional = usize::from(num_required_args + num_optional_args);
     let mut arg_values = args.iter().copied();
-    let rest_offset = args.len().min(required.len() + optional.len());
+    let rest_offset = args.len().min(positional);
 
-    for name in required {
-        let val = arg_values.next().unwrap();
-        vars.push(Cons::new(name, val, cx));
-    }
-
-    for name in optional {
+    for param in &params[..positional] {
         let val = arg_values.next().unwrap_or_default();
-        vars.push(Cons::new(name, val, cx));
+        vars.push(Cons::new(*param, val, cx));
     }
 
-    if let Some(rest_name) = rest {
+    if spec.rest {
         let list = crate::fns::slice_into_list(&args[rest_offset..], None, cx);
-        vars.push(Cons::new(rest_name, list, cx));
+        vars.push(Cons::new(params[positional], list, cx));
     } else {
         // Ensure too many args were not provided
         ensure!(
@@ -820,9 +1933,62 @@ pub(crate) fn parse_arg_list(
     Ok((required, optional, rest))
 }
 
+defsym!(DECLARE);
+
+/// The place of a `cl-letf` entry, and its value if it has one.
+fn letf_entry(entry: Object) -> (Object, Option<Object>) {
+    let ObjectType::Cons(entry) = entry.untag() else {
+        unreachable!("cl-letf entry is a list")
+    };
+    match entry.cdr().untag() {
+        ObjectType::Cons(value) => (entry.car(), Some(value.car())),
+        _ => (entry.car(), None),
+    }
+}
+
+/// If `place` is the function or value cell of a symbol, return the symbol,
+/// the function that sets the cell, and the one that makes it void.
+fn letf_cell(place: Object) -> Option<(Symbol, Symbol<'static>, Symbol<'static>)> {
+    let ObjectType::Cons(place) = place.untag() else { return None };
+    let (set, unset) = match place.car().untag() {
+        ObjectType::Symbol(sym::SYMBOL_FUNCTION) => (sym::FSET, sym::FMAKUNBOUND),
+        ObjectType::Symbol(sym::SYMBOL_VALUE) => (sym::SET, sym::MAKUNBOUND),
+        _ => return None,
+    };
+    let ObjectType::Cons(args) = place.cdr().untag() else { return None };
+    match args.car().untag() {
+        ObjectType::Symbol(symbol) => Some((symbol, set, unset)),
+        _ => None,
+    }
+}
+
+/// A call to `func` with `args` that are already evaluated.
+fn quoted_call<'ob>(func: Object<'ob>, args: &[Object<'ob>], cx: &'ob Context) -> Object<'ob> {
+    let mut call = NIL;
+    for arg in args.iter().rev() {
+        call = Cons::new(list![sym::QUOTE, *arg; cx], call, cx).into();
+    }
+    Cons::new(func, call, cx).into()
+}
+
+/// The form that reads the `cl-letf` place `place`.
+fn letf_getter<'ob>(place: Object<'ob>, cx: &'ob Context) -> Object<'ob> {
+    let ObjectType::Cons(place) = place.untag() else { return place };
+    let args: Vec<Object> = place.elements().skip(1).flatten().collect();
+    quoted_call(place.car(), &args, cx)
+}
+
+/// The form that sets the `cl-letf` place `place` to `value`.
+fn letf_setter<'ob>(place: Object<'ob>, value: Object<'ob>, cx: &'ob Context) -> Object<'ob> {
+    match letf_cell(place) {
+        Some((symbol, set, _)) => quoted_call(set.into(), &[symbol.into(), value], cx),
+        None => list![sym::SETF, letf_getter(place, cx), list![sym::QUOTE, value; cx]; cx],
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::core::{env::intern, gc::RootSet, object::IntoObject};
+    use crate::core::{gc::RootSet, object::IntoObject};
     use rune_core::macros::list;
 
     use super::*;
@@ -841,6 +2007,17 @@ mod test {
         assert_eq!(compare, expect);
     }
 
+    fn check_closure(test_str: &str, expect: &str, cx: &mut Context) {
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        println!("Test String: {test_str}");
+        let obj = crate::reader::read(test_str, cx).unwrap().0;
+        root!(obj, cx);
+        let closure = rebind!(eval(obj, None, env, cx).unwrap());
+        assert!(matches!(closure.untag(), ObjectType::Closure(_)));
+        assert_eq!(closure.to_string(), expect);
+    }
+
     fn check_error(test_str: &str, cx: &mut Context) {
         root!(env, new(Env), cx);
         println!("Test String: {test_str}");
@@ -879,6 +2056,26 @@ mod test {
         check_interpreter("(let* ((x 1) (y x)) y)", 1, cx);
     }
 
+    #[test]
+    fn variable_watchers() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(progn (defvar watch_test 1) (defvar watch_log nil)
+               (defalias 'watch_fn #'(lambda (_sym val op _where) (setq watch_log (cons (list val op) watch_log))))
+               (add-variable-watcher 'watch_test 'watch_fn)
+               (add-variable-watcher 'watch_test 'watch_fn)
+               (setq watch_test 2)
+               (let ((watch_test 3)) (set 'watch_test 4))
+               (makunbound 'watch_test)
+               (remove-variable-watcher 'watch_test 'watch_fn)
+               (setq watch_test 5)
+               (prin1-to-string (cons (get-variable-watchers 'watch_test) watch_log)))",
+            "(nil (nil makunbound) (2 unlet) (4 set) (3 let) (2 set))",
+            cx,
+        );
+    }
+
     #[test]
     fn dyn_variables() {
         let roots = &RootSet::default();
@@ -955,6 +2152,24 @@ mod test {
         let cx = &mut Context::new(roots);
         check_interpreter("(prog1 1 2 3)", 1, cx);
         check_interpreter("(prog2 1 2 3)", 2, cx);
+        check_interpreter("(prog1 1)", 1, cx);
+        check_interpreter("(prog2 1 2)", 2, cx);
+        check_interpreter("(prog2 1 nil 3)", false, cx);
+        check_error("(prog1)", cx);
+        check_error("(prog2 1)", cx);
+        // no forms are evaluated when there are too few
+        check_interpreter(
+            "(let ((x 1)) (condition-case nil (prog2 (setq x 2)) (error nil)) x)",
+            1,
+            cx,
+        );
+        check_interpreter("(when t 1 2)", 2, cx);
+        check_interpreter("(when nil 1 2)", false, cx);
+        check_interpreter("(when t)", false, cx);
+        check_interpreter("(unless nil 1 2)", 2, cx);
+        check_interpreter("(unless t 1 2)", false, cx);
+        check_interpreter("(let ((x 1)) (unless (setq x 2)) x)", 2, cx);
+        check_error("(when)", cx);
         check_interpreter("(progn 1 2 3 4)", 4, cx);
         check_interpreter("(function 1)", 1, cx);
         check_interpreter("(quote 1)", 1, cx);
@@ -967,20 +2182,38 @@ mod test {
     fn test_functions() {
         let roots = &RootSet::default();
         let cx = &mut Context::new(roots);
-        let list = list![sym::CLOSURE, list![true; cx]; cx];
-        root!(list, cx);
-        check_interpreter("(function (lambda))", list, cx);
-        let x = intern("x", cx);
-        let list = list![sym::CLOSURE, list![true; cx], list![x; cx], x; cx];
-        root!(list, cx);
-        check_interpreter("(function (lambda (x) x))", list, cx);
-        // TODO: fix this duplicate intern
-        let x = intern("x", cx);
-        let y = intern("y", cx);
-        let list: Object =
-            list![sym::CLOSURE, list![Cons::new(y, 1, cx), true; cx], list![x; cx], x; cx];
+        check_closure("(function (lambda))", "(closure (t) nil)", cx);
+        check_closure("(function (lambda (x) x))", "(closure (t) (x) x)", cx);
+        check_closure("(let ((y 1)) (function (lambda (x) x)))", "(closure ((y . 1) t) (x) x)", cx);
+        check_closure(
+            "(let ((y 1) (z 2)) (function (lambda (x &rest r) (foo) x)))",
+            "(closure ((z . 2) (y . 1) t) (x &rest r) (foo) x)",
+            cx,
+        );
+        // The printed form can be read back and called
+        let list = list!(1, 2; cx);
         root!(list, cx);
-        check_interpreter("(let ((y 1)) (function (lambda (x) x)))", list, cx);
+        check_interpreter(
+            "(funcall (car (read-from-string (prin1-to-string (let ((y 1)) #'(lambda (x) (list y x)))))) 2)",
+            list,
+            cx,
+        );
+        // Interpreted functions have the slots Emacs gives them
+        check_interpreter("(interpreted-function-p #'(lambda (x) x))", true, cx);
+        check_interpreter("(interpreted-function-p '(lambda (x) x))", false, cx);
+        let slots = crate::reader::read("((x) (y) ((y . 1) t))", cx).unwrap().0;
+        root!(slots, cx);
+        check_interpreter(
+            "(let ((f (let ((y 1)) #'(lambda (x) y)))) (list (aref f 0) (aref f 1) (aref f 2)))",
+            slots,
+            cx,
+        );
+        // oclosures are manipulated as lists
+        check_interpreter(
+            "(car-safe (function (lambda () (:documentation 'my-type) 1)))",
+            sym::CLOSURE,
+            cx,
+        );
 
         let list = list!(5, false; cx);
         root!(list, cx);
@@ -1010,6 +2243,31 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_declarations() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(funcall #'(lambda () \"doc\" (declare (pure t)) (interactive) 3))",
+            3,
+            cx,
+        );
+        check_interpreter("(funcall #'(lambda () \"doc\"))", "doc", cx);
+        check_interpreter("(funcall '(closure (t) () \"doc\" (declare (pure t)) 4))", 4, cx);
+        check_interpreter(
+            "(equal (interactive-form #'(lambda () \"doc\" (interactive \"p\") 1)) '(interactive \"p\"))",
+            true,
+            cx,
+        );
+        check_interpreter(
+            "(equal (interactive-form '(closure (t) () (interactive) 1)) '(interactive))",
+            true,
+            cx,
+        );
+        check_interpreter("(interactive-form #'(lambda () 1 (interactive)))", false, cx);
+        check_interpreter("(interactive-form 'car)", false, cx);
+    }
+
     #[test]
     fn test_call() {
         let roots = &RootSet::default();
@@ -1061,6 +2319,202 @@ mod test {
         check_error("(1+ 1 2)", cx);
     }
 
+    #[test]
+    fn test_buffer_local_variables() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let list = list!(2, 1, 1, 2, 1; cx);
+        root!(list, cx);
+        check_interpreter(
+            "(progn (defvar int-test-local 1)
+               (set-buffer (get-buffer-create \"int-test-local-a\"))
+               (make-local-variable 'int-test-local)
+               (setq int-test-local 2)
+               (list int-test-local
+                     (default-value 'int-test-local)
+                     (progn (set-buffer (get-buffer-create \"int-test-local-b\")) int-test-local)
+                     (buffer-local-value 'int-test-local (get-buffer-create \"int-test-local-a\"))
+                     (progn (set-buffer (get-buffer-create \"int-test-local-a\"))
+                            (kill-local-variable 'int-test-local)
+                            int-test-local)))",
+            list,
+            cx,
+        );
+        // Automatically buffer-local variables
+        let list = list!(5, 3, true, 3, false; cx);
+        root!(list, cx);
+        check_interpreter(
+            "(progn (defvar int-test-auto-local 1)
+               (make-variable-buffer-local 'int-test-auto-local)
+               (set-buffer (get-buffer-create \"int-test-auto-local-a\"))
+               (setq int-test-auto-local 5)
+               (set-default 'int-test-auto-local 3)
+               (let ((int-test-auto-local 7)) int-test-auto-local)
+               (list int-test-auto-local
+                     (default-value 'int-test-auto-local)
+                     (local-variable-p 'int-test-auto-local)
+                     (progn (set-buffer (get-buffer-create \"int-test-auto-local-b\"))
+                            int-test-auto-local)
+                     (local-variable-p 'int-test-auto-local)))",
+            list,
+            cx,
+        );
+        // let binds the buffer-local value when there is one
+        check_interpreter(
+            "(progn (defvar int-test-let-local 1)
+               (set-buffer (get-buffer-create \"int-test-let-local\"))
+               (make-local-variable 'int-test-let-local)
+               (setq int-test-let-local 2)
+               (+ (let ((int-test-let-local 10))
+                    (+ int-test-let-local (default-value 'int-test-let-local)))
+                  int-test-let-local))",
+            13,
+            cx,
+        );
+        // unbinding restores the value in the buffer that was bound, even
+        // when another buffer is current by then
+        let list = list!(2, 1; cx);
+        root!(list, cx);
+        check_interpreter(
+            "(progn (defvar int-test-let-switch 1)
+               (set-buffer (get-buffer-create \"int-test-let-switch-a\"))
+               (make-local-variable 'int-test-let-switch)
+               (setq int-test-let-switch 2)
+               (let ((int-test-let-switch 10))
+                 (set-buffer (get-buffer-create \"int-test-let-switch-b\")))
+               (list (buffer-local-value 'int-test-let-switch
+                                         (get-buffer-create \"int-test-let-switch-a\"))
+                     (default-value 'int-test-let-switch)))",
+            list,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_defcustom() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(progn (defgroup int-test-group nil \"doc\")
+               (defcustom int-test-custom (+ 1 2) \"doc\" :type 'integer :group 'int-test-group)
+               (equal (list int-test-custom
+                            (get 'int-test-custom 'standard-value)
+                            (get 'int-test-custom 'custom-type)
+                            (get 'int-test-group 'custom-group)
+                            (special-variable-p 'int-test-custom))
+                      '(3 ((+ 1 2)) integer ((int-test-custom custom-variable)) t)))",
+            true,
+            cx,
+        );
+        // An existing value is not overwritten
+        check_interpreter(
+            "(progn (defvar int-test-custom-bound 5)
+               (defcustom int-test-custom-bound 1 \"doc\")
+               int-test-custom-bound)",
+            5,
+            cx,
+        );
+        // Saved values are used in place of the standard value
+        check_interpreter(
+            "(progn (custom-set-variables '(int-test-custom-saved (* 2 3)))
+               (defcustom int-test-custom-saved 1 \"doc\")
+               (equal (list int-test-custom-saved (get 'int-test-custom-saved 'saved-value))
+                      '(6 ((* 2 3)))))",
+            true,
+            cx,
+        );
+        check_interpreter(
+            "(progn (defcustom int-test-custom-set 1 \"doc\")
+               (custom-set-variables '(int-test-custom-set 2))
+               int-test-custom-set)",
+            2,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_call_dispatch() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        // The interpreter, funcall, and apply all share the same dispatch
+        let list = list!(3, 3, 3; cx);
+        root!(list, cx);
+        check_interpreter(
+            "(progn (defalias 'int-test-dispatch '+) (list (int-test-dispatch 1 2) (funcall 'int-test-dispatch 1 2) (apply 'int-test-dispatch '(1 2))))",
+            list,
+            cx,
+        );
+        check_interpreter(
+            "(progn (defalias 'int-test-macro (cons 'macro #'(lambda (x) (list '1+ x)))) (int-test-macro 4))",
+            5,
+            cx,
+        );
+        check_error("(int-test-void-function 1)", cx);
+        check_error("(funcall 'int-test-void-function 1)", cx);
+    }
+
+    #[test]
+    fn test_closure_list_args() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(progn (defalias 'int-test-list-args '(closure (t) (x &optional y &rest z) (list x y z))) nil)",
+            false,
+            cx,
+        );
+        let list = list!(1, false, false; cx);
+        root!(list, cx);
+        check_interpreter("(int-test-list-args 1)", list, cx);
+        let rest = list!(3, 4; cx);
+        let list = list!(1, 2, rest; cx);
+        root!(list, cx);
+        check_interpreter("(int-test-list-args 1 2 3 4)", list, cx);
+        check_error("(int-test-list-args)", cx);
+    }
+
+    /// The address of the parameters cached for the argument list of the
+    /// closure `name`, if any.
+    fn cached_params(name: &str, cx: &Context) -> Option<usize> {
+        let func = crate::core::env::intern(name, cx).func(cx).unwrap();
+        let FunctionType::Cons(closure) = func.untag() else { unreachable!() };
+        let arg_list = closure.cdr().as_list().unwrap().nth(1).unwrap().unwrap();
+        let ObjectType::Cons(head) = arg_list.untag() else { return None };
+        let key = std::ptr::from_ref(head) as usize;
+        ARG_LISTS.with_borrow(|cache| cache.lists.get(&key).map(|x| x.params.as_ptr() as usize))
+    }
+
+    #[test]
+    fn test_arg_list_cache() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(progn (defalias 'int-test-cached '(closure (t) (x &optional y) (list x y))) nil)",
+            false,
+            cx,
+        );
+        assert!(cached_params("int-test-cached", cx).is_none());
+        let list = list!(1, false; cx);
+        root!(list, cx);
+        check_interpreter("(int-test-cached 1)", list, cx);
+        let params = cached_params("int-test-cached", cx);
+        assert!(params.is_some());
+        // The second call uses the same parsed list
+        let list = list!(1, 2; cx);
+        root!(list, cx);
+        check_interpreter("(int-test-cached 1 2)", list, cx);
+        assert_eq!(cached_params("int-test-cached", cx), params);
+
+        // Changing a mutable argument list is seen by the next call
+        check_interpreter(
+            "(let* ((args (list 'x 'y)) (f (list 'closure '(t) args 'x)))
+               (list (funcall f 1 2)
+                     (progn (setcdr args nil) (condition-case nil (funcall f 1 2) (error 'error)))
+                     (funcall f 3)))",
+            list!(1, sym::ERROR, 3; cx),
+            cx,
+        );
+    }
+
     #[test]
     fn test_condition_case() {
         let roots = &RootSet::default();
@@ -1074,6 +2528,171 @@ mod test {
         check_error("(condition-case nil (if))", cx);
         check_error("(condition-case nil (if) nil)", cx);
         check_error("(condition-case nil (if) 5 (error 7))", cx);
+        check_interpreter("(condition-case x 1 (:success (+ x 1)) (error 7))", 2, cx);
+        check_interpreter("(condition-case x (if) (:success 3) (error 7))", 7, cx);
+        check_interpreter("(condition-case nil 1 (error 7) (:success))", false, cx);
+        // The variable is unbound again after a handler signals
+        check_interpreter(
+            "(let ((x 1)) (condition-case nil (condition-case x (if) (error (if))) (error x)))",
+            1,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_define_error() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        crate::eval::init_errors(env, cx);
+        let mut eval_str = |test_str: &str| {
+            let obj = crate::reader::read(test_str, cx).unwrap().0;
+            root!(obj, cx);
+            eval(obj, None, env, cx).map(|x| x.to_string()).map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            eval_str("(define-error 'int-test-error \"Test failed\" 'arith-error)"),
+            Ok("nil".into())
+        );
+        assert_eq!(
+            eval_str("(get 'int-test-error 'error-conditions)"),
+            Ok("(int-test-error arith-error error)".into())
+        );
+        let handled = "(condition-case err (signal 'int-test-error '(1 a)) (void-variable 1) (arith-error (error-message-string err)))";
+        assert_eq!(eval_str(handled), Ok("\"Test failed: 1, a\"".into()));
+        assert_eq!(
+            eval_str("(condition-case nil (signal 'int-test-error nil) ((debug t) 2))"),
+            Ok("2".into())
+        );
+        assert!(eval_str("(condition-case nil (signal 'int-test-error nil) (void-variable 1))")
+            .is_err());
+        assert!(eval_str("(condition-case nil (signal 'quit nil) (error 1))").is_err());
+        assert!(eval_str("(define-error 'int-test-error-2 nil '(int-test-unknown))").is_err());
+        // Errors from Rust have their Emacs condition, or are plain errors
+        assert_eq!(
+            eval_str("(condition-case err int-test-void (void-variable err))"),
+            Ok("(void-variable int-test-void)".into())
+        );
+        assert_eq!(
+            eval_str("(condition-case err (int-test-void) (void-function err))"),
+            Ok("(void-function int-test-void)".into())
+        );
+        let plain = "(condition-case nil (aset (make-vector 1 0) 5 1) (arith-error 1) (error 2))";
+        assert_eq!(eval_str(plain), Ok("2".into()));
+    }
+
+    #[test]
+    fn test_mapping() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let mut eval_str = |test_str: &str| {
+            let obj = crate::reader::read(test_str, cx).unwrap().0;
+            root!(obj, cx);
+            eval(obj, None, env, cx).map(|x| x.to_string()).ok()
+        };
+        assert_eq!(eval_str("(mapcar #'1+ [1 2])").unwrap(), "(2 3)");
+        assert_eq!(eval_str("(mapcar #'identity \"ab\")").unwrap(), "(97 98)");
+        let mapcan = "(let ((x (list 1)) (y (list 2))) (mapcan #'identity (list x y)) x)";
+        assert_eq!(eval_str(mapcan).unwrap(), "(1 2)");
+        assert_eq!(eval_str("(mapconcat nil '(\"a\" \"b\") \"-\")").unwrap(), "\"a-b\"");
+        assert_eq!(eval_str("(mapconcat #'symbol-name '(a b))").unwrap(), "\"ab\"");
+        assert_eq!(eval_str("(mapconcat #'identity '(1))"), None);
+        let many = "(cl--mapcar-many #'+ '((1 2 3) [10 20] (100 200 300)) t)";
+        assert_eq!(eval_str(many).unwrap(), "(111 222)");
+        assert_eq!(eval_str("(cl--mapcar-many #'+ '((1 2)))").unwrap(), "nil");
+    }
+
+    #[test]
+    fn test_signal_data() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let mut eval_str = |test_str: &str| {
+            let obj = crate::reader::read(test_str, cx).unwrap().0;
+            root!(obj, cx);
+            eval(obj, None, env, cx).unwrap().to_string()
+        };
+        // A signal raised while another one is unwinding doesn't replace it
+        let nested = "(condition-case err (unwind-protect (signal 'int-test-outer '(1 2)) (condition-case nil (signal 'int-test-inner nil) (error nil))) (error err))";
+        assert_eq!(eval_str(nested), "(int-test-outer 1 2)");
+        let through_rust = "(condition-case err (mapcar (lambda (x) (signal 'int-test-error (list x))) '(5)) (error err))";
+        assert_eq!(eval_str(through_rust), "(int-test-error 5)");
+        let rethrown = "(condition-case err (condition-case e (signal 'int-test-error '(a)) (error (signal (car e) (cdr e)))) (error err))";
+        assert_eq!(eval_str(rethrown), "(int-test-error a)");
+        // Errors from Rust are signaled with the symbol Emacs uses
+        assert_eq!(
+            eval_str("(condition-case err (car 1) (error err))"),
+            "(wrong-type-argument listp 1)"
+        );
+        let wrong_type = "(condition-case nil (car 1) (void-variable 1) (wrong-type-argument 2))";
+        assert_eq!(eval_str(wrong_type), "2");
+        // The data holds the offending objects themselves
+        let same =
+            "(let ((x (list 1))) (condition-case err (symbol-name x) (error (eq (nth 2 err) x))))";
+        assert_eq!(eval_str(same), "t");
+        let buffer = "(let ((b (get-buffer-create \"int-test\"))) (condition-case err (car b) (error (eq (nth 2 err) b))))";
+        assert_eq!(eval_str(buffer), "t");
+        let range =
+            "(let ((s \"abc\")) (condition-case err (substring s 5) (error (eq (nth 1 err) s))))";
+        assert_eq!(eval_str(range), "t");
+        assert_eq!(
+            eval_str("(condition-case err (if) (wrong-number-of-arguments err))"),
+            "(wrong-number-of-arguments if 0)"
+        );
+        assert_eq!(
+            eval_str("(condition-case nil (error \"x\") (args-out-of-range 1) (error 2))"),
+            "2"
+        );
+    }
+
+    #[test]
+    fn test_binding_modes() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let mut eval_str = |test_str: &str| {
+            let obj = crate::reader::read(test_str, cx).unwrap().0;
+            root!(obj, cx);
+            eval(obj, None, env, cx).unwrap().to_string()
+        };
+        // An alist of lexical bindings, where earlier ones shadow later ones
+        assert_eq!(eval_str("(eval '(list x y) '((x . 1) (y . 2) (x . 3)))"), "(1 2)");
+        assert_eq!(eval_str("(eval '(funcall (let ((x 1)) (lambda () x))) t)"), "1");
+        // Without a second argument lisp `eval` binds dynamically, whatever
+        // `lexical-binding` is
+        let dynamic = |form: &str| format!("(let ((lexical-binding t)) (eval '{form}))");
+        assert_eq!(eval_str(&dynamic("(let ((dyn-x 1)) (symbol-value 'dyn-x))")), "1");
+        assert_eq!(eval_str(&dynamic("(function (lambda (a) a))")), "(lambda (a) a)");
+        assert_eq!(eval_str("(eval '(function (lambda (a) a)) nil)"), "(lambda (a) a)");
+        let scoped = "(let ((f (lambda () dyn-y))) (let ((dyn-y 5)) (funcall f)))";
+        assert_eq!(eval_str(&dynamic(scoped)), "5");
+        assert_eq!(
+            eval_str(&dynamic("(condition-case dyn-e (car 1) (error (symbol-value 'dyn-e)))")),
+            "(wrong-type-argument listp 1)"
+        );
+        // The arguments of a lambda are bound dynamically during the call
+        assert_eq!(
+            eval_str("(funcall '(lambda (dyn-a &optional b) (symbol-value 'dyn-a)) 7)"),
+            "7"
+        );
+        assert_eq!(
+            eval_str(
+                "(condition-case nil
+                     (funcall '(lambda (dyn-b) (car dyn-b)) 1)
+                   (error (boundp 'dyn-b)))"
+            ),
+            "nil"
+        );
+        // Evaluating from rust follows `lexical-binding`
+        let form = crate::reader::read("(function (lambda (a) a))", cx).unwrap().0;
+        root!(form, cx);
+        env.set_default(sym::LEXICAL_BINDING, NIL).unwrap();
+        assert_eq!(eval(form, None, env, cx).unwrap().to_string(), "(lambda (a) a)");
     }
 
     #[test]
@@ -1091,5 +2710,246 @@ mod test {
         check_interpreter("(catch 1 (catch 2 (throw 1 3)))", 3, cx);
         check_error("(throw 1 2)", cx);
         check_error("(catch 2 (throw 3 4))", cx);
+        // Tags are compared with eq
+        check_interpreter("(catch 'a (catch 'b (throw 'a 1)) 2)", 1, cx);
+        check_error("(catch (list 1) (throw (list 1) 2))", cx);
+        check_interpreter("(let ((tag (list 1))) (catch tag (throw tag 2)))", 2, cx);
+        check_interpreter("(catch 'x (funcall #'throw 'x 5))", 5, cx);
+    }
+
+    #[test]
+    fn test_no_catch() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let mut eval_str = |test_str: &str| {
+            let obj = crate::reader::read(test_str, cx).unwrap().0;
+            root!(obj, cx);
+            eval(obj, None, env, cx).unwrap().to_string()
+        };
+        assert_eq!(
+            eval_str("(condition-case err (throw 'foo 1) (no-catch err))"),
+            "(no-catch foo 1)"
+        );
+        // A caught throw leaves no tag behind
+        assert_eq!(eval_str("(progn (catch 'bar (throw 'bar 1)) (condition-case nil (throw 'bar 2) (no-catch 3)))"), "3");
+    }
+
+    #[test]
+    fn test_defconst() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter("(eq (defconst defconst-test 1) 'defconst-test)", true, cx);
+        check_interpreter("(progn (defconst defconst-test 2) defconst-test)", 2, cx);
+        check_interpreter("(special-variable-p 'defconst-test)", true, cx);
+        check_interpreter("(get 'defconst-test 'risky-local-variable)", true, cx);
+        check_error("(defconst defconst-test)", cx);
+    }
+
+    #[test]
+    fn test_letf() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let list = list!(2, 1; cx);
+        root!(list, cx);
+        check_interpreter(
+            "(progn (defalias 'letf-test-fn #'(lambda () 1))
+                    (list (cl-letf (((symbol-function 'letf-test-fn) #'(lambda () 2)))
+                            (letf-test-fn))
+                          (letf-test-fn)))",
+            list,
+            cx,
+        );
+        check_interpreter(
+            "(progn (catch 'done
+                      (cl-letf (((symbol-function 'letf-test-fn) #'(lambda () 3)))
+                        (throw 'done nil)))
+                    (letf-test-fn))",
+            1,
+            cx,
+        );
+        check_interpreter(
+            "(progn (condition-case nil
+                        (cl-letf (((symbol-function 'letf-test-fn) #'(lambda () 4)))
+                          (signal 'error nil))
+                      (error nil))
+                    (letf-test-fn))",
+            1,
+            cx,
+        );
+        let list = list!(3, false; cx);
+        root!(list, cx);
+        check_interpreter(
+            "(list (cl-letf (((symbol-function 'letf-test-void) #'car)) (letf-test-void '(3)))
+                   (fboundp 'letf-test-void))",
+            list,
+            cx,
+        );
+        let list = list!(2, 1; cx);
+        root!(list, cx);
+        check_interpreter(
+            "(progn (defvar letf-test-var 1)
+                    (list (cl-letf (((symbol-value 'letf-test-var) 2)) letf-test-var)
+                          letf-test-var))",
+            list,
+            cx,
+        );
+        check_interpreter("(let ((x 1)) (cl-letf ((x 2) (y (+ x 1))) y))", 2, cx);
+        check_interpreter("(let ((x 1)) (cl-letf* ((x 2) (y (+ x 1))) y))", 3, cx);
+        check_interpreter("(let ((x 1)) (cl-letf ((x)) x))", 1, cx);
+    }
+
+    #[test]
+    fn test_destructuring_bind() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter("(cl-destructuring-bind (a b) '(1 2) (+ a b))", 3, cx);
+        check_interpreter("(cl-destructuring-bind () nil 1)", 1, cx);
+        let list = list!(1, 2, 3, list!(4, 5; cx); cx);
+        root!(list, cx);
+        check_interpreter(
+            "(cl-destructuring-bind (a (b c) . d) '(1 (2 3) 4 5) (list a b c d))",
+            list,
+            cx,
+        );
+        let list = list!(1, 2, 7, false; cx);
+        root!(list, cx);
+        check_interpreter(
+            "(cl-destructuring-bind (a &optional (b 5) (c (+ a 6) c-p)) '(1 2) (list a b c c-p))",
+            list,
+            cx,
+        );
+        let list = list!(2, 3; cx);
+        root!(list, cx);
+        check_interpreter("(cl-destructuring-bind (a &rest r) '(1 2 3) r)", list, cx);
+        check_interpreter("(cl-destructuring-bind (a &body r) '(1) r)", false, cx);
+        let list = list!(1, 2, 4; cx);
+        root!(list, cx);
+        check_interpreter(
+            "(cl-destructuring-bind (&key a (b 2) ((:c x) 3)) '(:a 1 :c 4) (list a b x))",
+            list,
+            cx,
+        );
+        check_interpreter(
+            "(cl-destructuring-bind (&key a &allow-other-keys) '(:b 1 :a 2) a)",
+            2,
+            cx,
+        );
+        check_interpreter(
+            "(cl-destructuring-bind (&key a) '(:b 1 :allow-other-keys t :a 2) a)",
+            2,
+            cx,
+        );
+        check_interpreter("(cl-destructuring-bind (a &aux (b (* a 2))) '(3) b)", 6, cx);
+        let list = list!(1; cx);
+        root!(list, cx);
+        check_interpreter("(cl-destructuring-bind (&whole w a) '(1) w)", list, cx);
+        check_interpreter("(let ((x 1)) (cl-destructuring-bind (x) '(2)) x)", 1, cx);
+        check_error("(cl-destructuring-bind (a b) '(1))", cx);
+        check_error("(cl-destructuring-bind (a) '(1 2))", cx);
+        check_error("(cl-destructuring-bind (&key a) '(:b 1))", cx);
+        check_error("(cl-destructuring-bind (&key a) '(:a))", cx);
+        check_error("(cl-destructuring-bind (1) '(1))", cx);
+    }
+
+    #[test]
+    fn test_seq_let() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter("(seq-let (a b) '(1 2) (+ a b))", 3, cx);
+        check_interpreter("(seq-let [a b] [1 2 3] (+ a b))", 3, cx);
+        let list = list!(97, 98; cx);
+        root!(list, cx);
+        check_interpreter("(seq-let (a b) \"ab\" (list a b))", list, cx);
+        let list = list!(1, 2, false; cx);
+        root!(list, cx);
+        check_interpreter("(seq-let (a b c) '(1 2) (list a b c))", list, cx);
+        let list = list!(2, 3; cx);
+        root!(list, cx);
+        check_interpreter("(seq-let (a &rest r) [1 2 3] r)", list, cx);
+        let list = list!(1, 2, 3; cx);
+        root!(list, cx);
+        check_interpreter("(seq-let (a (b c)) '(1 [2 3]) (list a b c))", list, cx);
+    }
+
+    #[test]
+    fn test_pcase_let() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter("(pcase-let ((`(,a ,b) '(1 2))) (+ a b))", 3, cx);
+        let list = list!(2; cx);
+        root!(list, cx);
+        check_interpreter("(pcase-let ((`(,a . ,b) '(1 2))) b)", list, cx);
+        let list = list!(2, false; cx);
+        root!(list, cx);
+        check_interpreter("(pcase-let ((`(,_ (,b) ,c) '(1 (2)))) (list b c))", list, cx);
+        let list = list!(1, 3; cx);
+        root!(list, cx);
+        check_interpreter("(pcase-let ((`[,a 2 ,c] [1 5 3])) (list a c))", list, cx);
+        check_interpreter("(pcase-let ((x 1) (`(,y) (list 2))) (+ x y))", 3, cx);
+        check_interpreter("(let ((a 1)) (pcase-let ((a 2) (b a)) b))", 1, cx);
+        check_interpreter("(let ((a 1)) (pcase-let* ((a 2) (b a)) b))", 2, cx);
+    }
+
+    /// Count the allocations made by evaluating `test_str`. The form is
+    /// evaluated once beforehand so that the lisp stack is already grown, and
+    /// the minimum of several runs is taken to filter out the occasional
+    /// growth of the captured test output.
+    fn count_allocations(test_str: &str, cx: &mut Context) -> usize {
+        root!(env, new(Env), cx);
+        let obj = crate::reader::read(test_str, cx).unwrap().0;
+        root!(obj, cx);
+        eval(obj, None, env, cx).unwrap();
+        let mut min = usize::MAX;
+        for _ in 0..5 {
+            let (result, count) =
+                crate::debug::count_allocations(|| eval(obj, None, env, cx).map(|_| ()));
+            result.unwrap();
+            min = min.min(count);
+        }
+        min
+    }
+
+    #[test]
+    fn test_call_args_allocation() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        // Arguments are passed on the lisp stack, so the number of arguments
+        // should not change the number of allocations.
+        let one = count_allocations("(+ 1)", cx);
+        let many = count_allocations("(+ 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16)", cx);
+        assert_eq!(one, many);
+        let nested = count_allocations("(+ 1 (+ 2 (+ 3 4 5 6) 7 8) 9 10 11 12 13 14 15 16)", cx);
+        let flat = count_allocations("(+ 1 (+ 2 (+ 3)))", cx);
+        assert_eq!(nested, flat);
+    }
+
+    /// Time `count` calls of `func` and return the elapsed time.
+    fn time_calls(func: &str, count: usize, cx: &mut Context) -> std::time::Duration {
+        root!(env, new(Env), cx);
+        let test_str =
+            format!("(let ((f {func}) (i 0)) (while (< i {count}) (funcall f i) (setq i (1+ i))))");
+        let obj = crate::reader::read(&test_str, cx).unwrap().0;
+        root!(obj, cx);
+        let start = std::time::Instant::now();
+        eval(obj, None, env, cx).unwrap();
+        start.elapsed()
+    }
+
+    /// Compare the call overhead of closure objects to `(closure ...)` lists.
+    /// Run with `cargo test --release bench_closure_call -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_closure_call() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        let count = 200_000;
+        let list = time_calls("'(closure ((y . 1) t) (a &optional b &rest c) y a)", count, cx);
+        let object = time_calls("(let ((y 1)) #'(lambda (a &optional b &rest c) y a))", count, cx);
+        println!("closure list:   {list:?} for {count} calls");
+        println!("closure object: {object:?} for {count} calls");
     }
 }
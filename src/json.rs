@@ -0,0 +1,288 @@
+//! Native JSON support.
+use crate::core::{
+    cons::Cons,
+    env::{intern, sym, Env},
+    error::{Type, TypeError},
+    gc::{Context, Rt},
+    object::{HashTable, Object, ObjectType, NIL, TRUE},
+};
+use crate::fns::slice_into_list;
+use anyhow::{bail, Context as _, Result};
+use rune_macros::defun;
+use serde_json::{Map, Number, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ObjectKind {
+    HashTable,
+    Alist,
+    Plist,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ArrayKind {
+    Array,
+    List,
+}
+
+/// The keyword arguments shared by the JSON functions.
+#[derive(Clone, Copy)]
+pub(crate) struct JsonConfig<'ob> {
+    pub(crate) object_type: ObjectKind,
+    pub(crate) array_type: ArrayKind,
+    pub(crate) null_object: Object<'ob>,
+    pub(crate) false_object: Object<'ob>,
+}
+
+impl Default for JsonConfig<'_> {
+    fn default() -> Self {
+        Self {
+            object_type: ObjectKind::HashTable,
+            array_type: ArrayKind::Array,
+            null_object: sym::KW_NULL.into(),
+            false_object: sym::KW_FALSE.into(),
+        }
+    }
+}
+
+impl<'ob> JsonConfig<'ob> {
+    fn parse(args: &[Object<'ob>]) -> Result<Self> {
+        let mut config = Self::default();
+        for pair in args.chunks(2) {
+            let &[key, value] = pair else { bail!("Missing value for keyword {}", pair[0]) };
+            match key.untag() {
+                ObjectType::Symbol(sym::KW_OBJECT_TYPE) => {
+                    config.object_type = match value.untag() {
+                        ObjectType::Symbol(sym::HASH_TABLE) => ObjectKind::HashTable,
+                        ObjectType::Symbol(sym::ALIST) => ObjectKind::Alist,
+                        ObjectType::Symbol(sym::PLIST) => ObjectKind::Plist,
+                        _ => bail!("Invalid :object-type {value}"),
+                    }
+                }
+                ObjectType::Symbol(sym::KW_ARRAY_TYPE) => {
+                    config.array_type = match value.untag() {
+                        ObjectType::Symbol(sym::ARRAY) => ArrayKind::Array,
+                        ObjectType::Symbol(sym::LIST) => ArrayKind::List,
+                        _ => bail!("Invalid :array-type {value}"),
+                    }
+                }
+                ObjectType::Symbol(sym::KW_NULL_OBJECT) => config.null_object = value,
+                ObjectType::Symbol(sym::KW_FALSE_OBJECT) => config.false_object = value,
+                _ => bail!("Invalid keyword argument {key}"),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn object_key(key: Object) -> Result<String> {
+    match key.untag() {
+        ObjectType::String(s) => Ok(s.to_string()),
+        ObjectType::Symbol(s) => {
+            let name = s.name();
+            Ok(name.strip_prefix(':').unwrap_or(name).to_owned())
+        }
+        _ => bail!(TypeError::new(Type::Symbol, key)),
+    }
+}
+
+/// Convert a lisp object to JSON. Lists are objects: an alist if the first
+/// element is a cons, otherwise a plist.
+pub(crate) fn to_json(obj: Object, config: &JsonConfig) -> Result<Value> {
+    if obj == config.null_object {
+        return Ok(Value::Null);
+    }
+    if obj == config.false_object {
+        return Ok(Value::Bool(false));
+    }
+    let value = match obj.untag() {
+        ObjectType::TRUE => Value::Bool(true),
+        ObjectType::Int(i) => Value::Number(i.into()),
+        ObjectType::Float(f) => match Number::from_f64(**f) {
+            Some(n) => Value::Number(n),
+            None => bail!("JSON does not allow the number {obj}"),
+        },
+        ObjectType::String(s) => Value::String(s.to_string()),
+        ObjectType::Vec(vec) => {
+            let elems = vec.iter().map(|x| to_json(x.get(), config));
+            Value::Array(elems.collect::<Result<_>>()?)
+        }
+        ObjectType::HashTable(table) => {
+            let mut map = Map::new();
+            for i in 0..table.len() {
+                let Some((key, value)) = table.get_index(i) else { break };
+                map.insert(object_key(key)?, to_json(value, config)?);
+            }
+            Value::Object(map)
+        }
+        ObjectType::NIL => Value::Object(Map::new()),
+        ObjectType::Cons(cons) => {
+            let mut map = Map::new();
+            if let ObjectType::Cons(_) = cons.car().untag() {
+                for elem in obj.as_list()? {
+                    let ObjectType::Cons(pair) = elem?.untag() else { bail!("Invalid alist") };
+                    let key = object_key(pair.car())?;
+                    // The first occurrence of a key wins, as with `assq'
+                    if !map.contains_key(&key) {
+                        map.insert(key, to_json(pair.cdr(), config)?);
+                    }
+                }
+            } else {
+                let mut elems = obj.as_list()?;
+                while let Some(key) = elems.next() {
+                    let Some(value) = elems.next() else { bail!("Invalid plist {obj}") };
+                    let key = object_key(key?)?;
+                    if !map.contains_key(&key) {
+                        map.insert(key, to_json(value?, config)?);
+                    }
+                }
+            }
+            Value::Object(map)
+        }
+        _ => bail!("Cannot convert {obj} to JSON"),
+    };
+    Ok(value)
+}
+
+/// Convert JSON to a lisp object.
+pub(crate) fn from_json<'ob>(
+    value: &Value,
+    config: &JsonConfig<'ob>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let obj = match value {
+        Value::Null => config.null_object,
+        Value::Bool(true) => TRUE,
+        Value::Bool(false) => config.false_object,
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => cx.add(i),
+            None => cx.add(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => cx.add(s.as_str()),
+        Value::Array(elems) => {
+            let elems: Vec<Object> =
+                elems.iter().map(|x| from_json(x, config, cx)).collect::<Result<_>>()?;
+            match config.array_type {
+                ArrayKind::Array => cx.add(elems),
+                ArrayKind::List => slice_into_list(&elems, None, cx),
+            }
+        }
+        Value::Object(map) => match config.object_type {
+            ObjectKind::HashTable => {
+                let table = HashTable::default();
+                let table = cx.add(table);
+                let ObjectType::HashTable(inner) = table.untag() else { unreachable!() };
+                for (key, value) in map {
+                    inner.insert(cx.add(key.as_str()), from_json(value, config, cx)?);
+                }
+                table
+            }
+            ObjectKind::Alist => {
+                let mut elems: Vec<Object> = Vec::with_capacity(map.len());
+                for (key, value) in map {
+                    let key: Object = intern(key, cx).into();
+                    elems.push(Cons::new(key, from_json(value, config, cx)?, cx).into());
+                }
+                slice_into_list(&elems, None, cx)
+            }
+            ObjectKind::Plist => {
+                let mut elems: Vec<Object> = Vec::with_capacity(map.len() * 2);
+                for (key, value) in map {
+                    elems.push(intern(&format!(":{key}"), cx).into());
+                    elems.push(from_json(value, config, cx)?);
+                }
+                slice_into_list(&elems, None, cx)
+            }
+        },
+    };
+    Ok(obj)
+}
+
+pub(crate) fn parse<'ob>(
+    string: &str,
+    config: &JsonConfig<'ob>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let value: Value = serde_json::from_str(string).context("JSON parse error")?;
+    from_json(&value, config, cx)
+}
+
+#[defun]
+fn json_serialize(object: Object, args: &[Object]) -> Result<String> {
+    let config = JsonConfig::parse(args)?;
+    Ok(to_json(object, &config)?.to_string())
+}
+
+#[defun]
+fn json_insert(object: Object, args: &[Object], env: &mut Rt<Env>) -> Result<()> {
+    let config = JsonConfig::parse(args)?;
+    let json = to_json(object, &config)?.to_string();
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    buffer.text.insert(&json);
+    Ok(())
+}
+
+#[defun]
+fn json_parse_string<'ob>(
+    string: &str,
+    args: &[Object<'ob>],
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let config = JsonConfig::parse(args)?;
+    parse(string, &config, cx)
+}
+
+#[defun]
+fn json_available_p() -> bool {
+    true
+}
+
+defsym!(KW_NULL);
+defsym!(KW_FALSE);
+defsym!(KW_OBJECT_TYPE);
+defsym!(KW_ARRAY_TYPE);
+defsym!(KW_NULL_OBJECT);
+defsym!(KW_FALSE_OBJECT);
+defsym!(ARRAY);
+defsym!(ALIST);
+defsym!(PLIST);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::list;
+
+    #[test]
+    fn test_json_round_trip() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let json = r#"{"a":1,"b":[true,false,null],"c":{"d":"e"},"f":1.5}"#;
+        let table = json_parse_string(json, &[], cx).unwrap();
+        assert!(matches!(table.untag(), ObjectType::HashTable(_)));
+        assert_eq!(json_serialize(table, &[]).unwrap(), json);
+
+        let args = [sym::KW_OBJECT_TYPE.into(), sym::PLIST.into()];
+        let plist = json_parse_string(r#"{"a":1,"b":"c"}"#, &args, cx).unwrap();
+        let expect = list![intern(":a", cx), 1, intern(":b", cx), "c"; cx];
+        assert_eq!(plist, expect);
+        assert_eq!(json_serialize(plist, &[]).unwrap(), r#"{"a":1,"b":"c"}"#);
+
+        let args = [
+            sym::KW_OBJECT_TYPE.into(),
+            sym::ALIST.into(),
+            sym::KW_ARRAY_TYPE.into(),
+            sym::LIST.into(),
+            sym::KW_NULL_OBJECT.into(),
+            NIL,
+        ];
+        let alist = json_parse_string(r#"{"a":[1,null]}"#, &args, cx).unwrap();
+        let pair: Object = Cons::new(intern("a", cx), list![1, NIL; cx], cx).into();
+        let expect = list![pair; cx];
+        assert_eq!(alist, expect);
+
+        assert_eq!(json_serialize(NIL, &[]).unwrap(), "{}");
+        assert!(json_parse_string("{", &[], cx).is_err());
+        assert!(json_serialize(cx.add(f64::NAN), &[]).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+//! Native JSON-RPC framing for LSP-style clients.
+//!
+//! `jsonrpc-native-connect` installs `jsonrpc-native-filter` as the filter of
+//! a process. The filter collects output until a complete `Content-Length`
+//! framed message has arrived, parses it, and dispatches it: requests and
+//! notifications go to the handlers given to `jsonrpc-native-connect`, and
+//! responses go to the callbacks given to `jsonrpc-native-request`. Messages
+//! are converted with `:object-type plist`, `:null-object nil`, and
+//! `:false-object :json-false`, as in `jsonrpc.el`.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Function, HashTable, LispHashTable, Object, ObjectType, NIL},
+};
+use crate::json::{from_json, to_json, ArrayKind, JsonConfig, ObjectKind};
+use crate::process::{process_send_string, set_process_filter, Process};
+use anyhow::{bail, Context as _, Result};
+use rune_core::hashmap::HashMap;
+use rune_core::macros::{call, root};
+use rune_macros::defun;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// Slots of the connection vector `[REQUEST-FN NOTIFICATION-FN CONTINUATIONS]`.
+const REQUEST: usize = 0;
+const NOTIFICATION: usize = 1;
+const CONTINUATIONS: usize = 2;
+
+/// JSON-RPC internal error code, used when a request handler signals.
+const INTERNAL_ERROR: i64 = -32603;
+
+fn config() -> JsonConfig<'static> {
+    JsonConfig {
+        object_type: ObjectKind::Plist,
+        array_type: ArrayKind::Array,
+        null_object: NIL,
+        false_object: sym::KW_JSON_FALSE.into(),
+    }
+}
+
+/// Output received from each process that is not yet a complete message.
+fn pending() -> &'static Mutex<HashMap<i64, Vec<u8>>> {
+    static PENDING: OnceLock<Mutex<HashMap<i64, Vec<u8>>>> = OnceLock::new();
+    PENDING.get_or_init(Mutex::default)
+}
+
+/// Forget the connection and partial output of a process that has exited or
+/// been deleted.
+pub(crate) fn disconnect(id: i64, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    pending().lock().unwrap_or_else(PoisonError::into_inner).remove(&id);
+    connections(env, cx)?.swap_remove(id.into());
+    Ok(())
+}
+
+/// Frame a JSON payload with a `Content-Length` header.
+pub(crate) fn frame(json: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{json}", json.len())
+}
+
+/// Remove the first complete message from `input` and return its body.
+pub(crate) fn next_message(input: &mut Vec<u8>) -> Result<Option<Vec<u8>>> {
+    let Some(header_end) = input.windows(4).position(|x| x == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let headers = std::str::from_utf8(&input[..header_end])?;
+    let mut length = None;
+    for line in headers.split("\r\n") {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse::<usize>().context("Invalid Content-Length")?);
+            }
+        }
+    }
+    let Some(length) = length else { bail!("JSON-RPC message has no Content-Length header") };
+    let start = header_end + 4;
+    if input.len() < start + length {
+        return Ok(None);
+    }
+    let body = input[start..start + length].to_vec();
+    input.drain(..start + length);
+    Ok(Some(body))
+}
+
+/// The table mapping process ids to connections, creating it if needed.
+fn connections<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<&'ob LispHashTable> {
+    let table = match env.vars.get(sym::INTERNAL__JSONRPC_CONNECTIONS).map(|x| x.bind(cx)) {
+        Some(table) if !table.is_nil() => table,
+        _ => {
+            let table = cx.add(HashTable::default());
+            env.set_default(sym::INTERNAL__JSONRPC_CONNECTIONS, table)?;
+            table
+        }
+    };
+    match table.untag() {
+        ObjectType::HashTable(table) => Ok(table),
+        _ => bail!("`internal--jsonrpc-connections' is not a hash table"),
+    }
+}
+
+fn connection_slot<'ob>(
+    process: Object,
+    slot: usize,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let id = Process::try_from(process)?.id();
+    let Some(connection) = connections(env, cx)?.get(id.into()) else {
+        bail!("Process {process} is not a JSON-RPC connection")
+    };
+    match connection.untag() {
+        ObjectType::Vec(vec) => Ok(vec[slot].get()),
+        _ => bail!("Invalid JSON-RPC connection {connection}"),
+    }
+}
+
+fn continuations<'ob>(
+    process: Object,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob LispHashTable> {
+    match connection_slot(process, CONTINUATIONS, env, cx)?.untag() {
+        ObjectType::HashTable(table) => Ok(table),
+        _ => bail!("Invalid JSON-RPC connection {process}"),
+    }
+}
+
+fn send(process: Object, message: &Value, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    process_send_string(process, &frame(&message.to_string()), env, cx)
+}
+
+/// Run the handler or callback for a message from `process`.
+fn dispatch(
+    message: Value,
+    process: &Rt<Object>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let Value::Object(mut message) = message else {
+        bail!("JSON-RPC message is not an object")
+    };
+    let config = config();
+    let id = message.remove("id");
+    let params = message.remove("params").unwrap_or(Value::Null);
+    if let Some(Value::String(method)) = message.remove("method") {
+        let slot = if id.is_some() { REQUEST } else { NOTIFICATION };
+        let handler = connection_slot(process.bind(cx), slot, env, cx)?;
+        if handler.is_nil() {
+            return Ok(());
+        }
+        let func: Function = handler.try_into()?;
+        root!(func, cx);
+        let method = cx.add(method);
+        let params = from_json(&params, &config, cx)?;
+        let result = call!(func, process.bind(cx), method, params; env, cx);
+        // A notification has no reply
+        let Some(id) = id else { return Ok(result.map(|_| ())?) };
+        let reply = match result {
+            Ok(value) => json!({"jsonrpc": "2.0", "id": id, "result": to_json(value, &config)?}),
+            Err(e) => {
+                let error = json!({"code": INTERNAL_ERROR, "message": e.to_string().trim_end()});
+                json!({"jsonrpc": "2.0", "id": id, "error": error})
+            }
+        };
+        return send(process.bind(cx), &reply, env, cx);
+    }
+
+    let Some(id) = id.as_ref().and_then(Value::as_i64) else {
+        bail!("JSON-RPC message has neither a method nor an id")
+    };
+    let table = continuations(process.bind(cx), env, cx)?;
+    // Responses to unknown requests are ignored
+    let Some(callbacks) = table.get(id.into()) else { return Ok(()) };
+    table.swap_remove(id.into());
+    let ObjectType::Cons(callbacks) = callbacks.untag() else {
+        bail!("Invalid JSON-RPC continuation {callbacks}")
+    };
+    let (callback, value) = match message.remove("error") {
+        Some(error) => (callbacks.cdr(), error),
+        None => (callbacks.car(), message.remove("result").unwrap_or(Value::Null)),
+    };
+    if callback.is_nil() {
+        return Ok(());
+    }
+    let func: Function = callback.try_into()?;
+    root!(func, cx);
+    let value = from_json(&value, &config, cx)?;
+    call!(func, value; env, cx)?;
+    Ok(())
+}
+
+#[defun]
+fn jsonrpc_native_connect<'ob>(
+    process: Object<'ob>,
+    request_handler: Object<'ob>,
+    notification_handler: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let proc = Process::try_from(process)?;
+    let continuations = cx.add(HashTable::default());
+    let connection = cx.add(vec![request_handler, notification_handler, continuations]);
+    connections(env, cx)?.insert(proc.id().into(), connection);
+    pending().lock().unwrap_or_else(PoisonError::into_inner).remove(&proc.id());
+    set_process_filter(proc, sym::JSONRPC_NATIVE_FILTER.into())?;
+    Ok(process)
+}
+
+#[defun]
+fn jsonrpc_native_filter(
+    process: Object,
+    string: &str,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let id = Process::try_from(process)?.id();
+    // TODO: process output is decoded before it gets here, so the bytes are
+    // only exact when the output is valid UTF-8.
+    let messages = {
+        let mut pending = pending().lock().unwrap_or_else(PoisonError::into_inner);
+        let input = pending.entry(id).or_default();
+        input.extend_from_slice(string.as_bytes());
+        let mut messages = Vec::new();
+        loop {
+            match next_message(input) {
+                Ok(Some(body)) => messages.push(body),
+                Ok(None) => break,
+                Err(e) => {
+                    // The stream can't be resynchronized, so drop it
+                    input.clear();
+                    return Err(e);
+                }
+            }
+        }
+        messages
+    };
+    root!(process, cx);
+    for body in messages {
+        let message = serde_json::from_slice(&body).context("JSON-RPC parse error")?;
+        dispatch(message, process, env, cx)?;
+    }
+    Ok(())
+}
+
+#[defun]
+fn jsonrpc_native_request(
+    process: Object,
+    method: &str,
+    params: Object,
+    success_fn: Object,
+    error_fn: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<i64> {
+    static NEXT_ID: AtomicI64 = AtomicI64::new(1);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut message = json!({"jsonrpc": "2.0", "id": id, "method": method});
+    if !params.is_nil() {
+        message["params"] = to_json(params, &config())?;
+    }
+    let callbacks = crate::core::cons::Cons::new(success_fn, error_fn.unwrap_or(NIL), cx);
+    continuations(process, env, cx)?.insert(id.into(), callbacks.into());
+    send(process, &message, env, cx)?;
+    Ok(id)
+}
+
+#[defun]
+fn jsonrpc_native_notify(
+    process: Object,
+    method: &str,
+    params: Object,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let mut message = json!({"jsonrpc": "2.0", "method": method});
+    if !params.is_nil() {
+        message["params"] = to_json(params, &config())?;
+    }
+    send(process, &message, env, cx)
+}
+
+#[defun]
+fn jsonrpc_native_send(
+    process: Object,
+    message: Object,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let message = to_json(message, &config())?;
+    send(process, &message, env, cx)
+}
+
+defsym!(KW_JSON_FALSE);
+defsym!(INTERNAL__JSONRPC_CONNECTIONS);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_next_message() {
+        let mut input = frame(r#"{"id":1}"#).into_bytes();
+        input.extend_from_slice(
+            b"Content-Type: application/json\r\ncontent-length: 12\r\n\r\n{\"id\":",
+        );
+        assert_eq!(next_message(&mut input).unwrap().unwrap(), br#"{"id":1}"#);
+        // The second message is incomplete
+        assert_eq!(next_message(&mut input).unwrap(), None);
+        input.extend_from_slice(b"\"\xC3\xA9\"}");
+        assert_eq!(next_message(&mut input).unwrap().unwrap(), "{\"id\":\"é\"}".as_bytes());
+        assert!(input.is_empty());
+        assert_eq!(next_message(&mut input).unwrap(), None);
+
+        let mut input = b"Content-Type: text\r\n\r\n{}".to_vec();
+        assert!(next_message(&mut input).is_err());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod process_test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::{list, rebind};
+
+    fn eval<'ob>(code: &str, env: &mut Rt<Env>, cx: &'ob mut Context) -> Object<'ob> {
+        let obj = crate::reader::read(code, cx).unwrap().0;
+        root!(obj, cx);
+        rebind!(crate::interpreter::eval(obj, None, env, cx).unwrap())
+    }
+
+    #[test]
+    fn test_jsonrpc_echo() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let exec_path = list!["/bin", "/usr/bin"; cx];
+        env.set_default(sym::EXEC_PATH, exec_path).unwrap();
+        // `cat' echoes the request back, so it is delivered as a request
+        // with the same id, and the reply is echoed back as the response.
+        let code = r#"(progn
+            (setq jsonrpc-test-result nil)
+            (setq jsonrpc-test-process (start-process "jsonrpc" nil "cat"))
+            (jsonrpc-native-connect jsonrpc-test-process
+                                    (lambda (_proc method params) (list :method method :params params))
+                                    nil)
+            (jsonrpc-native-request jsonrpc-test-process "ping" '(:x 1)
+                                    (lambda (result) (setq jsonrpc-test-result result)))
+            (let ((tries 0))
+              (while (and (null jsonrpc-test-result) (< tries 100))
+                (accept-process-output nil 0 10)
+                (setq tries (1+ tries))))
+            (delete-process jsonrpc-test-process)
+            jsonrpc-test-result)"#;
+        let result = eval(code, env, cx);
+        root!(result, cx);
+        let expected = rebind!(eval(r#"'(:method "ping" :params (:x 1))"#, env, cx));
+        assert_eq!(result.bind(cx), expected);
+        // Deleting the process forgets its connection
+        let process = eval("jsonrpc-test-process", env, cx);
+        let id = Process::try_from(process).unwrap().id();
+        assert!(!pending().lock().unwrap().contains_key(&id));
+        assert!(connections(env, cx).unwrap().get(id.into()).is_none());
+    }
+}
@@ -0,0 +1,239 @@
+//! `rune lint`, a small checkdoc. Lisp files are checked for definitions
+//! without a docstring and docstring lines that are too wide, and the byte
+//! compiler's warnings about calls with the wrong number of arguments and
+//! unused variables are added. Problems are printed like compiler warnings,
+//! or as a JSON array for editors and other tools.
+use crate::bytecomp::{self, WarningKind};
+use crate::core::{
+    env::Env,
+    gc::{Context, Rt},
+    object::{Object, ObjectType},
+};
+use crate::reader::{self, Lexeme, LexemeKind, Lexer};
+use anyhow::Result;
+use serde_json::json;
+use std::fmt;
+
+/// The widest a docstring line can be, as in checkdoc.
+const MAX_DOC_WIDTH: usize = 80;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Problem {
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    /// The name of the check that found the problem
+    pub(crate) check: &'static str,
+    pub(crate) message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}: {}", self.line, self.column, self.check, self.message)
+    }
+}
+
+/// Where the docstring of a definition made with `definer` goes, and whether
+/// it defines a function. A function docstring is only a docstring if there
+/// is a body after it.
+fn doc_position(definer: &str) -> Option<(usize, bool)> {
+    match definer {
+        "defun" | "defmacro" | "defsubst" | "cl-defun" | "cl-defmacro" | "cl-defsubst"
+        | "define-inline" => Some((3, true)),
+        "defvar" | "defconst" | "defcustom" | "defvar-local" | "defgroup" | "defface" => {
+            Some((3, false))
+        }
+        "define-minor-mode" => Some((2, false)),
+        "define-derived-mode" => Some((4, false)),
+        _ => None,
+    }
+}
+
+/// The offsets of the elements of the list that starts `text`. A prefix like
+/// `'` is part of the element it comes before.
+fn element_offsets(text: &str) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut depth = 0;
+    let mut prefixed = false;
+    for Lexeme { kind, span } in Lexer::new(text) {
+        match kind {
+            LexemeKind::Whitespace | LexemeKind::Comment => continue,
+            LexemeKind::CloseParen | LexemeKind::CloseBracket => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if depth == 1 && !prefixed {
+            offsets.push(span.start);
+        }
+        prefixed = kind == LexemeKind::Prefix;
+        if matches!(kind, LexemeKind::OpenParen | LexemeKind::OpenBracket) {
+            depth += 1;
+        }
+    }
+    offsets
+}
+
+/// Check the docstring of `form`, which starts at `offset` in `source`.
+fn check_docstring(form: Object, source: &str, offset: usize, problems: &mut Vec<Problem>) {
+    let ObjectType::Cons(cons) = form.untag() else { return };
+    let Ok(elements) = cons.elements().collect::<Result<Vec<_>, _>>() else { return };
+    let Some(ObjectType::Symbol(definer)) = elements.first().map(|x| x.untag()) else {
+        return;
+    };
+    let Some((index, function)) = doc_position(definer.name()) else { return };
+    let Some(ObjectType::Symbol(name)) = elements.get(1).map(|x| x.untag()) else { return };
+    // A declaration, or an internal definition that doesn't need documenting
+    if elements.len() == 2 || name.name().contains("--") {
+        return;
+    }
+    let doc = elements.get(index).filter(|_| !function || elements.len() > index + 1);
+    let Some(ObjectType::String(doc)) = doc.map(|x| x.untag()) else {
+        let (line, column) = bytecomp::line_column(source, offset);
+        let message = format!("`{name}' has no docstring");
+        problems.push(Problem { line, column, check: "missing-docstring", message });
+        return;
+    };
+    // The lines of the docstring are assumed to be written as lines in the
+    // source, rather than with `\n'
+    let element = element_offsets(&source[offset..]).get(index).copied().unwrap_or(0);
+    let (line, column) = bytecomp::line_column(source, offset + element);
+    for (i, text) in doc.lines().enumerate() {
+        let width = text.chars().count();
+        if width > MAX_DOC_WIDTH {
+            let message = format!(
+                "docstring line of `{name}' is {width} columns wide, more than {MAX_DOC_WIDTH}"
+            );
+            let column = if i == 0 { column } else { 1 };
+            problems.push(Problem { line: line + i, column, check: "docstring-width", message });
+        }
+    }
+}
+
+/// Check `source`, the contents of a lisp file. Problems are sorted by their
+/// position.
+pub(crate) fn lint_source(
+    source: &str,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+    let mut pos = 0;
+    loop {
+        let (form, len) = match reader::read(&source[pos..], cx) {
+            Ok(x) => x,
+            Err(reader::Error::EmptyStream) => break,
+            Err(mut e) => {
+                e.update_pos(pos);
+                return Err(e.into());
+            }
+        };
+        let start = pos + crate::lread::form_offset(&source[pos..]);
+        check_docstring(form, source, start, &mut problems);
+        pos += len;
+    }
+    for warning in bytecomp::check_source(source, env, cx)? {
+        let check = match warning.kind {
+            WarningKind::Callargs => "callargs",
+            WarningKind::Lexical => "unused-variable",
+            _ => continue,
+        };
+        let bytecomp::Warning { line, column, message, .. } = warning;
+        problems.push(Problem { line, column, check, message });
+    }
+    problems.sort_by_key(|x| (x.line, x.column));
+    Ok(problems)
+}
+
+/// Lint `files` and print the problems, as JSON if `json` is set. Returns
+/// true if there were no problems.
+pub(crate) fn lint_files(
+    files: &[String],
+    json: bool,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> bool {
+    let mut clean = true;
+    let mut records = Vec::new();
+    for file in files {
+        let problems = match std::fs::read_to_string(file) {
+            Ok(source) => lint_source(&source, env, cx),
+            Err(e) => Err(e.into()),
+        };
+        let problems = match problems {
+            Ok(problems) => problems,
+            Err(e) => {
+                // An unreadable file is reported as a problem at its start
+                let message = e.to_string();
+                vec![Problem { line: 1, column: 1, check: "error", message }]
+            }
+        };
+        clean &= problems.is_empty();
+        for problem in problems {
+            if json {
+                records.push(json!({
+                    "file": file,
+                    "line": problem.line,
+                    "column": problem.column,
+                    "check": problem.check,
+                    "message": problem.message,
+                }));
+            } else {
+                println!("{file}:{problem}");
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::Value::Array(records));
+    }
+    clean
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{env::sym, gc::RootSet};
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_lint() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let wide = "x".repeat(MAX_DOC_WIDTH + 1);
+        let source = format!(
+            ";;; -*- lexical-binding: t -*-
+(defvar lint-documented nil \"A variable.\")
+(defvar lint-undocumented '(a b))
+(defvar lint-declared)
+(defun lint-fn (a)
+  \"Return A.
+{wide}\"
+  a)
+(defun lint-no-doc (x) x)
+(defun lint--internal ())
+(defun lint-caller (arg)
+  \"Call `lint-fn' wrongly.\"
+  (let ((unused 1))
+    (lint-fn 1 2)))
+"
+        );
+        let problems: Vec<_> =
+            lint_source(&source, env, cx).unwrap().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "3:1: missing-docstring: `lint-undocumented' has no docstring",
+                "7:1: docstring-width: docstring line of `lint-fn' is 81 columns wide, more than 80",
+                "9:1: missing-docstring: `lint-no-doc' has no docstring",
+                "11:21: unused-variable: Unused lexical argument `arg'",
+                "13:10: unused-variable: Unused lexical variable `unused'",
+                "14:6: callargs: `lint-fn' called with 2 arguments, but accepts only 1",
+            ]
+        );
+    }
+}
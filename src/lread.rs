@@ -1,11 +1,14 @@
 //! Loading elisp from files and strings.
+use crate::coding::Coding;
 use crate::core::cons::Cons;
 use crate::core::env::{sym, Env};
 use crate::core::error::{Type, TypeError};
 use crate::core::gc::{Context, Rt, Rto};
 use crate::core::object::{
-    Function, Gc, LispString, Object, ObjectType, Symbol, WithLifetime, NIL, TRUE,
+    int_to_char, Function, Gc, LispBuffer, LispString, Object, ObjectType, Symbol, NIL, TRUE,
 };
+use crate::eval::EvalError;
+use crate::obarray::{obarray_or_default, Obarray};
 use crate::reader;
 use crate::{interpreter, rooted_iter};
 use anyhow::{anyhow, Context as _};
@@ -43,35 +46,484 @@ pub(crate) fn read_from_string<'ob>(
     string: &str,
     start: Option<i64>,
     end: Option<i64>,
+    env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    let len = string.len();
+    let len = string.chars().count();
     let start = check_lower_bounds(start, len)?;
     let end = check_upper_bounds(end, len)?;
+    let offset = |idx: usize| string.char_indices().nth(idx).map_or(string.len(), |x| x.0);
+    let slice = &string[offset(start)..offset(end)];
 
-    let (obj, new_pos) = match reader::read(&string[start..end], cx) {
+    let shorthands = symbol_shorthands(env, cx)?;
+    let (obj, new_pos) = match reader::read_with_shorthands(slice, &shorthands, cx) {
         Ok((obj, pos)) => (obj, pos),
+        Err(e) if e.is_incomplete() => return Err(end_of_file()),
         Err(mut e) => {
             e.update_pos(start);
             bail!(e);
         }
     };
+    let new_pos = start + slice[..new_pos].chars().count();
     Ok(Cons::new(obj, new_pos as i64, cx).into())
 }
 
+/// The `end-of-file` error, signaled when a stream ends in the middle of an
+/// object or before one.
+fn end_of_file() -> anyhow::Error {
+    EvalError::signal(sym::END_OF_FILE.into(), NIL).into()
+}
+
+/// Read one object from `stream`, which is a string, a buffer, `t` for
+/// standard input, or a function. Reading from a buffer starts at point and
+/// moves point past the object. A function is called with no arguments to get
+/// each character, and with one to unread it. `nil` means `standard-input`.
+#[defun]
+fn read<'ob>(
+    stream: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let stream = match stream.map(|x| x.bind(cx)) {
+        Some(stream) if !stream.is_nil() => stream,
+        _ => env.vars.get(sym::STANDARD_INPUT).map_or(TRUE, |x| x.bind(cx)),
+    };
+    let shorthands = symbol_shorthands(env, cx)?;
+    match stream.untag() {
+        ObjectType::String(string) => match reader::read_with_shorthands(string, &shorthands, cx) {
+            Ok((obj, _)) => Ok(obj),
+            Err(e) if e.is_incomplete() => Err(end_of_file()),
+            Err(e) => Err(e.into()),
+        },
+        ObjectType::Buffer(buffer) => read_from_buffer(buffer, &shorthands, env, cx),
+        ObjectType::TRUE => read_from_stdin(&shorthands, cx),
+        _ => {
+            let function: Function = stream.try_into()?;
+            root!(function, cx);
+            read_from_function(function, &shorthands, env, cx)
+        }
+    }
+}
+
+fn read_from_buffer<'ob>(
+    buffer: &LispBuffer,
+    shorthands: &[(String, String)],
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let result = env.with_buffer_mut(Some(buffer), |b| {
+        let point = b.text.cursor().chars();
+        // With the gap moved before point the rest of the text is contiguous
+        b.text.move_gap_out_of(point..);
+        let [before, after] = b.text.slices(point..);
+        let text = if before.is_empty() { after } else { before };
+        let result = reader::read_with_shorthands(text, shorthands, cx)
+            .map(|(obj, len)| (obj, point + text[..len].chars().count()));
+        if let Ok((_, end)) = result {
+            b.text.set_cursor(end);
+        }
+        result
+    });
+    match result {
+        Some(Ok((obj, _))) => Ok(obj),
+        Some(Err(e)) if e.is_incomplete() => Err(end_of_file()),
+        Some(Err(e)) => Err(e.into()),
+        None => bail!("Selecting deleted buffer"),
+    }
+}
+
+/// Read lines from standard input until they hold a complete object.
+fn read_from_stdin<'ob>(shorthands: &[(String, String)], cx: &'ob Context) -> Result<Object<'ob>> {
+    let mut text = String::new();
+    loop {
+        if std::io::stdin().read_line(&mut text)? == 0 {
+            return Err(end_of_file());
+        }
+        match reader::read_with_shorthands(&text, shorthands, cx) {
+            Ok((obj, _)) => return Ok(obj),
+            Err(e) if e.is_incomplete() => {}
+            Err(e) => bail!(e),
+        }
+    }
+}
+
+/// Pull characters from `function` until they hold a complete object. The
+/// end of a symbol or number is only known from the character after it, which
+/// is handed back to the function to unread.
+fn read_from_function<'ob>(
+    function: &Rto<Function>,
+    shorthands: &[(String, String)],
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let mut text = String::new();
+    let mut scanner = FormScanner::default();
+    loop {
+        let chr = match call!(function; env, cx)?.untag() {
+            ObjectType::Int(chr) => Some(int_to_char(chr)?),
+            _ => None,
+        };
+        let Some(chr) = chr else {
+            // The function has no more characters
+            return match reader::read_with_shorthands(&text, shorthands, cx) {
+                Ok((obj, _)) => Ok(obj),
+                Err(e) if e.is_incomplete() => Err(end_of_file()),
+                Err(e) => Err(e.into()),
+            };
+        };
+        text.push(chr);
+        // Only parse where an object could have ended, so that each object
+        // is parsed once instead of once per character
+        let boundary = scanner.push(chr);
+        if boundary == Boundary::None {
+            continue;
+        }
+        let (obj, end) = match reader::read_with_shorthands(&text, shorthands, cx) {
+            Ok((obj, end)) if end < text.len() || boundary == Boundary::Closed => (obj, end),
+            Ok(_) => continue,
+            Err(reader::Error::EmptyStream) if !scanner.in_comment => {
+                // Nothing but whitespace so far
+                text.clear();
+                continue;
+            }
+            Err(e) if e.is_incomplete() => continue,
+            Err(e) => bail!(e),
+        };
+        root!(obj, cx);
+        for chr in text[end..].chars() {
+            call!(function, i64::from(u32::from(chr)); env, cx)?;
+        }
+        return Ok(obj.bind(cx));
+    }
+}
+
+/// Where the last character pushed to a [`FormScanner`] is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Boundary {
+    /// Inside an object or a comment.
+    None,
+    /// A delimiter at the top level, which ends any symbol or number before it.
+    Delimiter,
+    /// The end of a top level list, vector or string.
+    Closed,
+}
+
+/// Tracks just enough of the syntax of characters read one at a time to tell
+/// where a top level object could end.
+#[derive(Debug)]
+struct FormScanner {
+    depth: usize,
+    in_string: bool,
+    in_comment: bool,
+    escaped: bool,
+    char_literal: bool,
+    after_delimiter: bool,
+}
+
+impl Default for FormScanner {
+    fn default() -> Self {
+        Self {
+            depth: 0,
+            in_string: false,
+            in_comment: false,
+            escaped: false,
+            char_literal: false,
+            after_delimiter: true,
+        }
+    }
+}
+
+impl FormScanner {
+    fn push(&mut self, chr: char) -> Boundary {
+        if self.in_comment {
+            self.in_comment = chr != '\n';
+            return Boundary::None;
+        }
+        if self.escaped {
+            self.escaped = false;
+            self.after_delimiter = false;
+            return Boundary::None;
+        }
+        if self.in_string {
+            match chr {
+                '\\' => self.escaped = true,
+                '"' => {
+                    self.in_string = false;
+                    self.after_delimiter = true;
+                    if self.depth == 0 {
+                        return Boundary::Closed;
+                    }
+                }
+                _ => {}
+            }
+            return Boundary::None;
+        }
+        if self.char_literal {
+            // The character after `?` is never syntax
+            self.char_literal = false;
+            self.escaped = chr == '\\';
+            self.after_delimiter = false;
+            return Boundary::None;
+        }
+        let top = self.depth == 0;
+        let delimiter = chr.is_whitespace() || "()[]\";'`,".contains(chr);
+        let mut boundary = if top && delimiter { Boundary::Delimiter } else { Boundary::None };
+        match chr {
+            '(' | '[' => self.depth += 1,
+            ')' | ']' if !top => {
+                self.depth -= 1;
+                if self.depth == 0 {
+                    boundary = Boundary::Closed;
+                }
+            }
+            '"' => self.in_string = true,
+            ';' => self.in_comment = true,
+            '\\' => self.escaped = true,
+            '?' if self.after_delimiter => self.char_literal = true,
+            _ => {}
+        }
+        self.after_delimiter = delimiter;
+        boundary
+    }
+}
+
+/// Parse an alist of shorthand prefixes in the form used by
+/// `read-symbol-shorthands`.
+fn parse_shorthands(alist: Object) -> Result<Vec<(String, String)>> {
+    let mut shorthands = Vec::new();
+    for elem in alist.as_list().context("`read-symbol-shorthands' was not a list")? {
+        let ObjectType::Cons(pair) = elem?.untag() else {
+            bail!("`read-symbol-shorthands' must be an alist")
+        };
+        let short: &str = pair.car().try_into()?;
+        let long: &str = pair.cdr().try_into()?;
+        shorthands.push((short.to_owned(), long.to_owned()));
+    }
+    Ok(shorthands)
+}
+
+/// The current value of `read-symbol-shorthands`.
+fn symbol_shorthands(env: &Rt<Env>, cx: &Context) -> Result<Vec<(String, String)>> {
+    match env.var(sym::READ_SYMBOL_SHORTHANDS, cx) {
+        Some(alist) => parse_shorthands(alist.bind(cx)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Parse the file local variables set in the `-*-` line and the "Local
+/// Variables" section at the end of `contents`. The values are not evaluated.
+/// Later entries take precedence over earlier ones.
+pub(crate) fn file_local_variables<'ob>(
+    contents: &str,
+    cx: &'ob Context,
+) -> Result<Vec<(Symbol<'ob>, Object<'ob>)>> {
+    let mut vars = Vec::new();
+    // The -*- line can be the second line if the first is a "#!" line
+    let mut lines = contents.lines();
+    let prop_line = match lines.next() {
+        Some(line) if line.starts_with("#!") => lines.next(),
+        line => line,
+    };
+    if let Some(line) = prop_line {
+        parse_prop_line(line, &mut vars, cx)?;
+    }
+    parse_local_variables_section(contents, &mut vars, cx)?;
+    Ok(vars)
+}
+
+/// Parse a line of the form `-*- var1: value1; var2: value2 -*-`.
+fn parse_prop_line<'ob>(
+    line: &str,
+    vars: &mut Vec<(Symbol<'ob>, Object<'ob>)>,
+    cx: &'ob Context,
+) -> Result<()> {
+    const DELIM: &str = "-*-";
+    let Some(start) = line.find(DELIM) else { return Ok(()) };
+    let rest = &line[start + DELIM.len()..];
+    let Some(end) = rest.find(DELIM) else { return Ok(()) };
+    for prop in rest[..end].split(';') {
+        // A property without a colon is the name of the major mode
+        let Some((name, value)) = prop.split_once(':') else { continue };
+        let (value, _) = reader::read(value, cx)
+            .with_context(|| format!("Invalid value for file local variable {name}"))?;
+        add_file_local_var(name.trim(), value, vars, cx);
+    }
+    Ok(())
+}
+
+/// Parse the "Local Variables" section at the end of a file.
+/// ```text
+/// ;; Local Variables:
+/// ;; var1: value1
+/// ;; End:
+/// ```
+fn parse_local_variables_section<'ob>(
+    contents: &str,
+    vars: &mut Vec<(Symbol<'ob>, Object<'ob>)>,
+    cx: &'ob Context,
+) -> Result<()> {
+    const HEADER: &str = "Local Variables:";
+    // Emacs only searches the last 3000 characters for the section
+    let mut start = contents.len().saturating_sub(3000);
+    while !contents.is_char_boundary(start) {
+        start += 1;
+    }
+    let mut tail = &contents[start..];
+    // The section must also be after the last page break
+    if let Some(page) = tail.rfind('\x0C') {
+        tail = &tail[page..];
+    }
+    let Some(header_start) = tail.rfind(HEADER) else { return Ok(()) };
+    // Every line in the section has the same prefix and suffix as the header
+    // line, usually a comment start like ";; "
+    let line_start = tail[..header_start].rfind('\n').map_or(0, |x| x + 1);
+    let prefix = &tail[line_start..header_start];
+    let mut lines = tail[header_start..].lines();
+    let suffix = lines.next().unwrap()[HEADER.len()..].trim();
+    let mut section = String::new();
+    for line in lines {
+        let line = line
+            .strip_prefix(prefix)
+            .or_else(|| line.strip_prefix(prefix.trim_end()))
+            .unwrap_or(line);
+        let line = line.trim_end().strip_suffix(suffix).unwrap_or(line);
+        if line.trim_start().starts_with("End:") {
+            break;
+        }
+        section.push_str(line);
+        section.push('\n');
+    }
+
+    // Values can span multiple lines, so read them from the whole section
+    let mut pos = 0;
+    loop {
+        let rest = section[pos..].trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let Some((name, value)) = rest.split_once(':') else {
+            bail!("Malformed file local variable line: {rest}")
+        };
+        let (value, read) = reader::read(value, cx)
+            .with_context(|| format!("Invalid value for file local variable {name}"))?;
+        add_file_local_var(name.trim(), value, vars, cx);
+        pos = section.len() - rest.len() + name.len() + 1 + read;
+    }
+    Ok(())
+}
+
+fn add_file_local_var<'ob>(
+    name: &str,
+    value: Object<'ob>,
+    vars: &mut Vec<(Symbol<'ob>, Object<'ob>)>,
+    cx: &'ob Context,
+) {
+    match name {
+        // The major mode is irrelevant to loading and `eval' forms are never
+        // run automatically
+        "mode" | "eval" => {}
+        name => vars.push((intern(name, cx), value)),
+    }
+}
+
+/// Bind the file local variables of `contents` for the duration of loading
+/// it. Returns the symbol shorthands to use while reading and the number of
+/// bindings made.
+fn bind_file_local_variables(
+    contents: &str,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<(Vec<(String, String)>, u16)> {
+    let mut shorthands = None;
+    let mut lexical_binding = false;
+    let mut varbind_count = 0;
+    for (var, value) in file_local_variables(contents, cx)? {
+        match var {
+            sym::LEXICAL_BINDING => lexical_binding = value != NIL,
+            sym::READ_SYMBOL_SHORTHANDS => shorthands = Some(parse_shorthands(value)?),
+            // The coding system was already used to decode the file
+            sym::CODING => {}
+            _ => {
+                env.varbind(var, value, cx);
+                varbind_count += 1;
+            }
+        }
+    }
+    // Files without a lexical-binding cookie use dynamic binding
+    env.varbind(sym::LEXICAL_BINDING, lexical_binding.into(), cx);
+    varbind_count += 1;
+    let shorthands = match shorthands {
+        Some(x) => x,
+        None => symbol_shorthands(env, cx)?,
+    };
+    Ok((shorthands, varbind_count))
+}
+
+/// Decode the contents of a file according to the `coding` specified in its
+/// file local variables. Only UTF-8 and Latin-1 are supported.
+fn decode_file(bytes: Vec<u8>, cx: &Context) -> Result<String> {
+    let coding = file_local_variables(&String::from_utf8_lossy(&bytes), cx)?
+        .into_iter()
+        .rev()
+        .find_map(|(var, value)| (var == sym::CODING).then(|| value.to_string()));
+    match Coding::from_name(coding.as_deref().unwrap_or("utf-8"))? {
+        Coding::Utf8 => {
+            let contents = String::from_utf8(bytes).context("File is not valid UTF-8")?;
+            match contents.strip_prefix('\u{FEFF}') {
+                Some(rest) => Ok(rest.to_owned()),
+                None => Ok(contents),
+            }
+        }
+        Coding::Latin1 => Ok(Coding::Latin1.decode(&bytes)),
+    }
+}
+
 pub(crate) fn load_internal(contents: &str, cx: &mut Context, env: &mut Rt<Env>) -> Result<bool> {
+    let (shorthands, varbind_count) = bind_file_local_variables(contents, env, cx)?;
+    let result = load_forms(contents, &shorthands, None, cx, env);
+    env.unbind(varbind_count, cx);
+    result
+}
+
+/// Read and evaluate all the forms in `contents`. When the forms come from a
+/// buffer, `buffer_start` is the buffer position where `contents` begins, and
+/// errors are reported in terms of buffer positions instead of byte offsets.
+fn load_forms(
+    contents: &str,
+    shorthands: &[(String, String)],
+    buffer_start: Option<usize>,
+    cx: &mut Context,
+    env: &mut Rt<Env>,
+) -> Result<bool> {
+    let buffer_pos = |byte_pos: usize| {
+        buffer_start
+            .map(|start| start + contents.char_indices().take_while(|(i, _)| *i < byte_pos).count())
+    };
     let mut pos = 0;
     let macroexpand: Option<Function> = None;
     root!(macroexpand, cx);
     if let Some(fun) = sym::INTERNAL_MACROEXPAND_FOR_LOAD.func(cx) {
         macroexpand.set(Some(fun));
     }
+    // Only forms loaded from files are instrumented
+    let coverage_file = match buffer_start {
+        None if crate::coverage::enabled(env, cx) => {
+            env.var(sym::LOAD_FILE_NAME, cx).and_then(|x| match x.bind(cx).untag() {
+                ObjectType::String(file) => Some(file.to_string()),
+                _ => None,
+            })
+        }
+        _ => None,
+    };
     loop {
-        let (obj, new_pos) = match reader::read(&contents[pos..], cx) {
+        let (obj, new_pos) = match reader::read_with_shorthands(&contents[pos..], shorthands, cx) {
             Ok((obj, pos)) => (obj, pos),
             Err(reader::Error::EmptyStream) => return Ok(true),
             Err(mut e) => {
                 e.update_pos(pos);
+                if let Some(buffer_pos) = buffer_pos(e.position()) {
+                    e.set_pos(buffer_pos);
+                }
                 bail!(e);
             }
         };
@@ -81,12 +533,23 @@ pub(crate) fn load_internal(contents: &str, cx: &mut Context, env: &mut Rt<Env>)
             println!("-----READ END-----");
         }
         root!(obj, cx);
+        let source = coverage_file.as_deref().map(|file| crate::coverage::Source {
+            file,
+            text: contents,
+            span: pos + form_offset(&contents[pos..])..pos + new_pos,
+        });
         let result = if let Some(fun) = macroexpand.as_ref() {
-            eager_expand(obj, fun, env, cx)
+            eager_expand(obj, fun, source.as_ref(), env, cx)
         } else {
+            if let Some(source) = &source {
+                obj.set(crate::coverage::instrument(obj.bind(cx), source, cx));
+            }
             interpreter::eval(obj, None, env, cx)
         };
         if let Err(e) = result {
+            if let Some(buffer_pos) = buffer_pos(pos + form_offset(&contents[pos..])) {
+                return Err(e.context(format!("Error in form at buffer position {buffer_pos}")));
+            }
             let content = &contents[pos..(new_pos + pos)];
             println!("-----LOAD ERROR START-----\n {content}");
             println!("-----LOAD ERROR END-----");
@@ -97,9 +560,23 @@ pub(crate) fn load_internal(contents: &str, cx: &mut Context, env: &mut Rt<Env>)
     }
 }
 
+/// Return the byte offset of the first form in `text`, skipping whitespace and
+/// comments.
+pub(crate) fn form_offset(text: &str) -> usize {
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start();
+        match rest.strip_prefix(';') {
+            Some(comment) => rest = comment.split_once('\n').map_or("", |(_, x)| x),
+            None => return text.len() - rest.len(),
+        }
+    }
+}
+
 fn eager_expand<'ob>(
     obj: &Rto<Object>,
     macroexpand: &Rto<Function>,
+    source: Option<&crate::coverage::Source>,
     env: &mut Rt<Env>,
     cx: &'ob mut Context,
 ) -> Result<Object<'ob>, anyhow::Error> {
@@ -111,7 +588,7 @@ fn eager_expand<'ob>(
             root!(val, NIL, cx);
             rooted_iter!(forms, top.cdr(), cx);
             while let Some(form) = forms.next()? {
-                let result = eager_expand(form, macroexpand, env, cx)?;
+                let result = eager_expand(form, macroexpand, source, env, cx)?;
                 val.set(result);
             }
             return Ok(val.bind(cx));
@@ -119,21 +596,43 @@ fn eager_expand<'ob>(
     }
     let result = call!(macroexpand, obj, TRUE; name, env, cx)?;
     root!(result, cx);
+    if let Some(source) = source {
+        result.set(crate::coverage::instrument(result.bind(cx), source, cx));
+    }
     interpreter::eval(result, None, env, cx)
 }
 
+/// Return `path` if it exists, or its gzipped version if that does.
+pub(crate) fn existing_file(path: PathBuf) -> Option<PathBuf> {
+    if path.exists() {
+        return Some(path);
+    }
+    let mut compressed = path.into_os_string();
+    compressed.push(".gz");
+    let compressed = PathBuf::from(compressed);
+    compressed.exists().then_some(compressed)
+}
+
 fn file_in_path(file: &str, path: &str) -> Option<PathBuf> {
     let path = Path::new(path).join(file);
-    if path.exists() {
-        Some(path)
+    let with_ext = path.with_extension("el");
+    existing_file(path).or_else(|| existing_file(with_ext))
+}
+
+/// Read a file to load, decompressing it if it is gzipped.
+fn read_load_file(file: &Path) -> Result<Vec<u8>> {
+    let bytes =
+        fs::read(file).with_context(|| format!("Couldn't open file {:?}", file.as_os_str()))?;
+    if file.extension().is_some_and(|x| x == "gz") {
+        crate::decompress::decompress(&bytes)
+            .with_context(|| format!("Couldn't decompress file {:?}", file.as_os_str()))
     } else {
-        let with_ext = path.with_extension("el");
-        with_ext.exists().then_some(with_ext)
+        Ok(bytes)
     }
 }
 
 fn find_file_in_load_path(file: &str, cx: &Context, env: &Rt<Env>) -> Result<PathBuf> {
-    let load_path = env.vars.get(sym::LOAD_PATH).unwrap();
+    let load_path = env.var(sym::LOAD_PATH, cx).unwrap();
     let paths = load_path.bind(cx).as_list().context("`load-path' was not a list")?;
     let mut final_file = None;
     for path in paths {
@@ -164,15 +663,14 @@ pub(crate) fn load(
     let noerror = noerror.is_some();
     let nomessage = nomessage.is_some();
     let file: &str = file.untag(cx);
-    let final_file = if Path::new(file).exists() {
-        PathBuf::from(file)
-    } else {
-        match find_file_in_load_path(file, cx, env) {
+    let final_file = match existing_file(PathBuf::from(file)) {
+        Some(x) => x,
+        None => match find_file_in_load_path(file, cx, env) {
             Ok(x) => x,
             Err(e) => {
                 return if noerror { Ok(false) } else { Err(e) };
             }
-        }
+        },
     };
 
     let filename = String::from(file);
@@ -189,51 +687,243 @@ pub(crate) fn load(
         None => NIL,
     };
     root!(prev_load_file, cx);
-    let result = match fs::read_to_string(&final_file)
-        .with_context(|| format!("Couldn't open file {:?}", final_file.as_os_str()))
-    {
-        Ok(content) => load_internal(&content, cx, env),
+    env.varbind(sym::CURRENT_LOAD_LIST, NIL, cx);
+    if crate::coverage::enabled(env, cx) {
+        crate::coverage::start_file(&final_file.to_string_lossy());
+    }
+    let shared_before = crate::alloc::shared_constants();
+    let result = match read_load_file(&final_file) {
+        Ok(bytes) => decode_file(bytes, cx).and_then(|content| load_internal(&content, cx, env)),
         Err(e) => match noerror {
             true => Ok(false),
             false => Err(e),
         },
     };
+    let definitions = env.var(sym::CURRENT_LOAD_LIST, cx).map_or(NIL, |x| x.bind(cx));
+    env.unbind(1, cx);
+    let result = match result {
+        Ok(true) => add_load_history(&final_file.to_string_lossy(), definitions, env, cx),
+        result => result,
+    };
 
     if !nomessage && result.is_ok() {
-        println!("Loading {filename} Done");
+        let shared = crate::alloc::shared_constants();
+        match shared.count - shared_before.count {
+            0 => println!("Loading {filename} Done"),
+            count => {
+                let bytes = shared.bytes - shared_before.bytes;
+                println!(
+                    "Loading {filename} Done (shared {count} constants, saving {bytes} bytes)"
+                );
+            }
+        }
     }
     env.vars.insert(sym::LOAD_FILE_NAME, &*prev_load_file);
     result
 }
 
+/// Record a definition made while loading a file in `current-load-list`, so
+/// that it ends up in `load-history`. Functions are recorded as `(defun .
+/// NAME)` and variables as their name.
+pub(crate) fn record_definition(definition: Object, env: &mut Rt<Env>, cx: &Context) {
+    let loading = env.var(sym::LOAD_FILE_NAME, cx).is_some_and(|x| !x.bind(cx).is_nil());
+    if loading {
+        let list = env.var(sym::CURRENT_LOAD_LIST, cx).map_or(NIL, |x| x.bind(cx));
+        let list = Cons::new(definition, list, cx);
+        env.set_default(sym::CURRENT_LOAD_LIST, list.into())
+            .expect("variable is not constant");
+    }
+}
+
+/// Add the definitions recorded while loading `file` to `load-history`,
+/// replacing any earlier entry for it.
+fn add_load_history(
+    file: &str,
+    definitions: Object,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let definitions = crate::fns::nreverse(definitions.try_into()?)?;
+    let mut entries = vec![Cons::new(cx.add(file), definitions, cx).into()];
+    let history = env.var(sym::LOAD_HISTORY, cx).map_or(NIL, |x| x.bind(cx));
+    for entry in history.as_list()? {
+        let entry = entry?;
+        let same_file = matches!(entry.untag(), ObjectType::Cons(x)
+            if matches!(x.car().untag(), ObjectType::String(name) if **name == *file));
+        if !same_file {
+            entries.push(entry);
+        }
+    }
+    let history = crate::fns::slice_into_list(&entries, None, cx);
+    env.set_default(sym::LOAD_HISTORY, history)?;
+    Ok(true)
+}
+
+/// Execute the accessible portion of BUFFER as Lisp code. BUFFER defaults to
+/// the current buffer and is made current while its forms are evaluated.
+/// File local variables set in the buffer are bound during evaluation.
 #[defun]
-pub(crate) fn intern<'ob>(string: &str, cx: &'ob Context) -> Symbol<'ob> {
-    crate::core::env::intern(string, cx)
+fn eval_buffer(
+    buffer: Option<&Rto<Object>>,
+    _printflag: Option<()>,
+    _filename: Option<()>,
+    _unibyte: Option<()>,
+    _do_allow_print: Option<()>,
+    cx: &mut Context,
+    env: &mut Rt<Env>,
+) -> Result<()> {
+    // TODO: implement printflag once we have `standard-output'
+    let prev_buffer = env.current_buffer.as_ref().map(|x| x.lisp_buffer(cx));
+    root!(prev_buffer, cx);
+    if let Some(buffer) = buffer {
+        let buffer = crate::buffer::resolve_buffer(buffer.bind(cx), cx)?;
+        env.set_buffer(buffer)?;
+    }
+    let Some(contents) = env.with_buffer(None, |b| b.text.to_string()) else {
+        bail!("No current buffer")
+    };
+    let result = bind_file_local_variables(&contents, env, cx).and_then(|(shorthands, count)| {
+        let result = load_forms(&contents, &shorthands, Some(1), cx, env);
+        env.unbind(count, cx);
+        result
+    });
+    if let Some(buffer) = prev_buffer.bind_ref(cx) {
+        env.set_buffer(buffer)?;
+    }
+    result.map(|_| ())
+}
+
+/// Execute the region between START and END in the current buffer as Lisp
+/// code. START and END are buffer positions and may be given in either order.
+#[defun]
+fn eval_region(
+    start: usize,
+    end: usize,
+    _printflag: Option<()>,
+    _read_function: Option<()>,
+    cx: &mut Context,
+    env: &mut Rt<Env>,
+) -> Result<()> {
+    // TODO: implement printflag and read-function
+    let (start, end) = if start <= end { (start, end) } else { (end, start) };
+    let Some(contents) = env.with_buffer(None, |b| b.text.to_string()) else {
+        bail!("No current buffer")
+    };
+    let len = contents.chars().count();
+    ensure!(
+        1 <= start && end <= len + 1,
+        "Args out of range: region {start}..{end} is outside of buffer 1..{}",
+        len + 1
+    );
+    let region: String = contents.chars().skip(start - 1).take(end - start).collect();
+    let shorthands = symbol_shorthands(env, cx)?;
+    load_forms(&region, &shorthands, Some(start), cx, env).map(|_| ())
+}
+
+#[defun]
+pub(crate) fn intern<'ob>(
+    string: &str,
+    obarray: Option<Obarray<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Symbol<'ob>> {
+    Ok(obarray_or_default(obarray, env, cx)?.intern(string, cx))
 }
 
 #[defun]
-pub(crate) fn intern_soft(string: Object, obarray: Option<()>) -> Result<Symbol> {
-    ensure!(obarray.is_none(), "intern-soft obarray not implemented");
+pub(crate) fn intern_soft<'ob>(
+    string: Object<'ob>,
+    obarray: Option<Obarray<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Symbol<'ob>> {
+    let obarray = obarray_or_default(obarray, env, cx)?;
     match string.untag() {
-        ObjectType::Symbol(sym) => {
-            if sym.interned() {
-                Ok(sym)
-            } else {
-                Ok(sym::NIL)
-            }
-        }
-        ObjectType::String(string) => {
-            let map = crate::core::env::interned_symbols().lock().unwrap();
-            match map.get(string) {
-                Some(sym) => Ok(unsafe { sym.with_lifetime() }),
-                None => Ok(sym::NIL),
-            }
-        }
+        ObjectType::Symbol(sym) => match obarray.get(sym.name(), cx) {
+            Some(found) if found == sym => Ok(sym),
+            _ => Ok(sym::NIL),
+        },
+        ObjectType::String(string) => Ok(obarray.get(string, cx).unwrap_or(sym::NIL)),
         x => Err(TypeError::new(Type::String, x).into()),
     }
 }
 
+/// Search for `filename` in each directory of `path`, trying each of
+/// `suffixes` in turn, and return the first candidate accepted by `predicate`.
+/// A `None` directory, like a `nil` element of `exec-path`, means
+/// `default_dir`. Absolute file names skip the search.
+pub(crate) fn locate_file(
+    filename: &str,
+    path: &[Option<&str>],
+    suffixes: &[&str],
+    default_dir: &str,
+    predicate: impl Fn(&Path) -> bool,
+) -> Option<PathBuf> {
+    let suffixes = if suffixes.is_empty() { &[""][..] } else { suffixes };
+    let candidates = |dir: PathBuf| {
+        suffixes.iter().map(move |suffix| {
+            let mut name = dir.join(filename).into_os_string();
+            name.push(suffix);
+            PathBuf::from(name)
+        })
+    };
+    if Path::new(filename).is_absolute() {
+        return candidates(PathBuf::new()).find(|x| predicate(x));
+    }
+    path.iter()
+        .flat_map(|dir| candidates(Path::new(default_dir).join(dir.unwrap_or(""))))
+        .find(|x| predicate(x))
+}
+
+/// Check `file` against an `access`-style `mode` mask: 1 for executable, 2
+/// for writable and 4 for readable. Directories never match.
+pub(crate) fn file_accessible(file: &Path, mode: i64) -> bool {
+    let Ok(metadata) = file.metadata() else { return false };
+    if metadata.is_dir() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let perms = metadata.permissions().mode();
+        let checks = [(1, 0o111), (2, 0o222), (4, 0o444)];
+        if checks.iter().any(|(bit, mask)| mode & bit != 0 && perms & mask == 0) {
+            return false;
+        }
+    }
+    true
+}
+
+#[defun]
+fn locate_file_internal(
+    filename: &str,
+    path: Object,
+    suffixes: Option<Object>,
+    predicate: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<Option<String>> {
+    let mode = match predicate.map(Object::untag) {
+        None | Some(ObjectType::NIL) => 4,
+        Some(ObjectType::Int(mode)) => mode,
+        Some(x) => bail!("locate-file-internal: unsupported predicate {x}"),
+    };
+    let path = path.as_list()?.map(|x| x?.try_into()).collect::<Result<Vec<Option<&str>>>>()?;
+    let suffixes: Vec<&str> = match suffixes {
+        Some(suffixes) => suffixes.as_list()?.map(|x| x?.try_into()).collect::<Result<_>>()?,
+        None => Vec::new(),
+    };
+    let default_dir = match env.var(sym::DEFAULT_DIRECTORY, cx).map(|x| x.untag(cx)) {
+        Some(ObjectType::String(dir)) => dir.to_string(),
+        _ => String::new(),
+    };
+    let file = locate_file(filename, &path, &suffixes, &default_dir, |x| file_accessible(x, mode));
+    Ok(file.map(|x| x.to_string_lossy().into_owned()))
+}
+
 defsym!(INTERNAL_MACROEXPAND_FOR_LOAD);
+defsym!(CODING);
+defsym!(DEFUN);
 defvar!(LEXICAL_BINDING, true);
 defvar!(CURRENT_LOAD_LIST);
 defvar!(LOAD_HISTORY);
@@ -242,13 +932,15 @@ defvar!(LOAD_FILE_NAME);
 defvar!(BYTE_BOOLEAN_VARS);
 defvar!(MACROEXP__DYNVARS);
 defvar!(AFTER_LOAD_ALIST);
+defvar!(READ_SYMBOL_SHORTHANDS);
+defvar!(STANDARD_INPUT, true);
 
 #[cfg(test)]
 mod test {
 
     use super::*;
     use crate::core::gc::RootSet;
-    use rune_core::macros::root;
+    use rune_core::macros::{list, rebind, root};
 
     #[test]
     #[allow(clippy::float_cmp)] // Bug in Clippy
@@ -264,4 +956,237 @@ mod test {
         let val = interpreter::eval(obj, None, env, cx).unwrap();
         assert_eq!(val, 4.5);
     }
+
+    #[test]
+    fn test_load_shorthands() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let file = "(defvar my-pkg-value 7)
+(setq shorthand-test-result (+ mp-value 1))
+;; Local Variables:
+;; read-symbol-shorthands: ((\"mp-\" . \"my-pkg-\")
+;;                          (\"xx-\" . \"unused-\"))
+;; End:
+";
+        load_internal(file, cx, env).unwrap();
+        let obj = reader::read("shorthand-test-result", cx).unwrap().0;
+        root!(obj, cx);
+        let val = interpreter::eval(obj, None, env, cx).unwrap();
+        assert_eq!(val, 8);
+    }
+
+    #[test]
+    fn test_file_local_variables() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        let foo = intern("foo", cx);
+        let bar = intern("bar", cx);
+
+        let vars = file_local_variables(";;; test.el --- -*- lexical-binding: t -*-", cx).unwrap();
+        assert_eq!(vars, vec![(sym::LEXICAL_BINDING, TRUE)]);
+        let vars =
+            file_local_variables("#!/bin/emacs --script\n;; -*- foo: 1; bar: \"x\"; -*-", cx);
+        assert_eq!(vars.unwrap(), vec![(foo, cx.add(1)), (bar, cx.add("x"))]);
+        assert!(file_local_variables(";; -*- emacs-lisp -*-\n(foo: 1)", cx).unwrap().is_empty());
+        assert!(file_local_variables("(setq x 1)", cx).unwrap().is_empty());
+
+        let file = "(setq x 1)
+;; Local Variables:
+;; foo: (1
+;;       2)
+;; mode: emacs-lisp
+;; bar: 3
+;; End:
+";
+        let vars = file_local_variables(file, cx).unwrap();
+        assert_eq!(vars, vec![(foo, list![1, 2; cx]), (bar, cx.add(3))]);
+
+        let file = "/* Local Variables: */\n/* foo: 5 */\n/* End: */\n";
+        let vars = file_local_variables(file, cx).unwrap();
+        assert_eq!(vars, vec![(foo, cx.add(5))]);
+    }
+
+    #[test]
+    fn test_load_file_local_variables() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let file = "(setq file-local-test-seen (cons lexical-binding file-local-test-var))
+;; Local Variables:
+;; file-local-test-var: 5
+;; End:
+";
+        load_internal(file, cx, env).unwrap();
+        let obj = reader::read("file-local-test-seen", cx).unwrap().0;
+        root!(obj, cx);
+        let val = rebind!(interpreter::eval(obj, None, env, cx).unwrap(), cx);
+        assert_eq!(val, cx.add(Cons::new(false, 5, cx)));
+        // the bindings are only active during the load
+        assert!(env.vars.get(intern("file-local-test-var", cx)).is_none());
+        assert!(env.vars.get(sym::LEXICAL_BINDING).is_none());
+
+        let file = ";; -*- lexical-binding: t -*-\n(setq file-local-test-lex lexical-binding)";
+        load_internal(file, cx, env).unwrap();
+        let obj = reader::read("file-local-test-lex", cx).unwrap().0;
+        root!(obj, cx);
+        let val = interpreter::eval(obj, None, env, cx).unwrap();
+        assert_eq!(val, TRUE);
+    }
+
+    #[test]
+    fn test_decode_file() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let utf8 = "(setq x \"\u{e9}\")".as_bytes().to_vec();
+        assert_eq!(decode_file(utf8, cx).unwrap(), "(setq x \"\u{e9}\")");
+        let mut latin1 = b";; -*- coding: latin-1 -*-\n\"".to_vec();
+        latin1.extend([0xE9, b'"']);
+        assert_eq!(decode_file(latin1, cx).unwrap(), ";; -*- coding: latin-1 -*-\n\"\u{e9}\"");
+        let bad = b";; -*- coding: utf-8-unix -*-\n\xff".to_vec();
+        assert!(decode_file(bad, cx).is_err());
+        assert!(decode_file(b";; -*- coding: euc-jp -*-".to_vec(), cx).is_err());
+    }
+
+    #[test]
+    fn test_load_compressed() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let dir = std::env::temp_dir().join(format!("rune-load-gz-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"(setq load-gz-test 3)").unwrap();
+        fs::write(dir.join("gz-test.el.gz"), encoder.finish().unwrap()).unwrap();
+
+        let dir_name = dir.to_string_lossy().into_owned();
+        assert_eq!(file_in_path("gz-test", &dir_name), Some(dir.join("gz-test.el.gz")));
+        assert_eq!(file_in_path("gz-test.el", &dir_name), Some(dir.join("gz-test.el.gz")));
+        assert_eq!(file_in_path("missing", &dir_name), None);
+        let contents = read_load_file(&dir.join("gz-test.el.gz")).unwrap();
+        assert_eq!(contents, b"(setq load-gz-test 3)");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_history() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        crate::core::env::init_variables(cx, env);
+        let dir = std::env::temp_dir().join(format!("rune-load-history-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history-test.el");
+        let source = "(defvar history-test-var 1)
+(defvar history-test-declared)
+(defalias 'history-test-fn #'(lambda () 1))";
+        fs::write(&path, source).unwrap();
+        let file = path.to_string_lossy().into_owned();
+        let name: Gc<&LispString> = cx.add_as(file.clone());
+        root!(name, cx);
+        for _ in 0..2 {
+            assert!(load(name, None, Some(()), cx, env).unwrap());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        let history = env.var(sym::LOAD_HISTORY, cx).unwrap().bind(cx);
+        let expected = format!("((\"{file}\" history-test-var (defun . history-test-fn)))");
+        assert_eq!(history.to_string(), expected);
+        assert!(env.var(sym::CURRENT_LOAD_LIST, cx).unwrap().bind(cx).is_nil());
+    }
+
+    #[test]
+    fn test_eval_buffer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let buffer = crate::buffer::get_buffer_create(cx.add("test_eval_buffer"), None, cx);
+        crate::buffer::set_buffer(buffer.unwrap(), env, cx).unwrap();
+        let text = ";; -*- lexical-binding: t -*-\n(setq eval-buffer-a 1)\n(setq eval-buffer-b (+ eval-buffer-a 2))";
+        env.current_buffer.as_mut().unwrap().insert(cx.add(text)).unwrap();
+        eval_buffer(None, None, None, None, None, cx, env).unwrap();
+        let obj = reader::read("eval-buffer-b", cx).unwrap().0;
+        root!(obj, cx);
+        let val = interpreter::eval(obj, None, env, cx).unwrap();
+        assert_eq!(val, 3);
+
+        // only the region is evaluated
+        let start = text.find("(setq eval-buffer-b").unwrap() + 1;
+        let obj = reader::read("(setq eval-buffer-a 5)", cx).unwrap().0;
+        root!(obj, cx);
+        interpreter::eval(obj, None, env, cx).unwrap();
+        eval_region(start, text.len() + 1, None, None, cx, env).unwrap();
+        let obj = reader::read("eval-buffer-b", cx).unwrap().0;
+        root!(obj, cx);
+        let val = interpreter::eval(obj, None, env, cx).unwrap();
+        assert_eq!(val, 7);
+
+        let err = eval_region(start, start + 10, None, None, cx, env).unwrap_err();
+        let err = err.downcast::<reader::Error>().unwrap();
+        assert_eq!(err, reader::Error::MissingCloseParen(start));
+
+        env.current_buffer.as_mut().unwrap().insert(cx.add("\n  (car 1)")).unwrap();
+        let err = eval_buffer(None, None, None, None, None, cx, env).unwrap_err();
+        let pos = text.chars().count() + 4;
+        assert_eq!(err.to_string(), format!("Error in form at buffer position {pos}"));
+        assert!(eval_region(0, 5, None, None, cx, env).is_err());
+    }
+
+    #[test]
+    fn test_read() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let mut eval = |code: &str, cx: &mut Context| {
+            let obj = reader::read(code, cx).unwrap().0;
+            root!(obj, cx);
+            interpreter::eval(obj, None, env, cx).map(|x| x.to_string()).map_err(|_| ())
+        };
+        assert_eq!(eval("(read-from-string \"é (a b) c\" 1)", cx).unwrap(), "((a b) . 7)");
+        assert_eq!(eval("(read-from-string \"é (a b) c\" -1)", cx).unwrap(), "(c . 9)");
+        assert!(eval("(read-from-string \"(a\")", cx).is_err());
+        assert_eq!(eval("(read \"foo bar\")", cx).unwrap(), "foo");
+        assert!(eval("(read \"\")", cx).is_err());
+        let stream = "(progn
+          (setq read-test-chars '(?1 ?2 ?\\s ?a) read-test-unread nil)
+          (list
+            (read (lambda (&optional c)
+                    (if c
+                        (setq read-test-unread c)
+                      (prog1 (car read-test-chars)
+                        (setq read-test-chars (cdr read-test-chars))))))
+            read-test-unread))";
+        assert_eq!(eval(stream, cx).unwrap(), "(12 32)");
+        // A list is complete at its closing paren, so nothing is unread
+        let stream = "(progn
+          (setq read-test-chars (append \"(a ?\\\\) \\\"b)\\\") c\" nil) read-test-unread nil)
+          (list
+            (read (lambda (&optional c)
+                    (if c
+                        (setq read-test-unread c)
+                      (prog1 (car read-test-chars)
+                        (setq read-test-chars (cdr read-test-chars))))))
+            read-test-unread
+            (length read-test-chars)))";
+        assert_eq!(eval(stream, cx).unwrap(), "((a 41 \"b)\") nil 2)");
+
+        let buffer = crate::buffer::get_buffer_create(cx.add("test_read"), None, cx).unwrap();
+        root!(buffer, cx);
+        crate::buffer::set_buffer(buffer.bind(cx), env, cx).unwrap();
+        env.current_buffer.as_mut().unwrap().insert(cx.add("(a) b")).unwrap();
+        env.current_buffer.as_mut().unwrap().text.set_cursor(0);
+        assert_eq!(read(Some(&*buffer), env, cx).unwrap().to_string(), "(a)");
+        assert_eq!(read(Some(&*buffer), env, cx).unwrap().to_string(), "b");
+        assert!(read(Some(&*buffer), env, cx).is_err());
+    }
 }
@@ -0,0 +1,475 @@
+//! A language server for elisp, started with `rune --lsp`. It speaks JSON-RPC
+//! over stdin and stdout with the framing from [`jsonrpc`](crate::jsonrpc),
+//! and provides:
+//!
+//! - diagnostics from the byte compiler's warnings
+//! - completion of the names in the intern table
+//! - hover documentation from `documentation`
+//! - go to definition, in the open documents or the files in `load-history`
+//!
+//! Documents are synced in full on every change.
+use crate::bytecomp;
+use crate::core::{
+    env::{interned_symbols, sym, Env},
+    gc::{Context, Rt},
+    object::{Object, ObjectType, Symbol},
+};
+use crate::jsonrpc::{frame, next_message};
+use crate::reader;
+use crate::thingatpt::{self, Thing};
+use anyhow::Result;
+use rune_core::hashmap::HashMap;
+use rune_core::macros::root;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+
+/// JSON-RPC error code for a request the server doesn't implement.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The most completion candidates sent at once. If there are more, the
+/// client asks again as the prefix gets longer.
+const MAX_COMPLETIONS: usize = 500;
+
+/// Take over stdout for the protocol. Lisp code prints to stdout, so that
+/// output is sent to stderr from now on, and the returned writer goes to
+/// the original stdout.
+#[cfg(unix)]
+pub(crate) fn protocol_output() -> Result<Box<dyn Write>> {
+    use std::os::unix::io::FromRawFd;
+    // SAFETY: dup returns a new descriptor that only the file owns, and
+    // dup2 replaces stdout with stderr without closing the new descriptor.
+    unsafe {
+        let fd = libc::dup(1);
+        if fd < 0 || libc::dup2(2, 1) < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Box::new(std::fs::File::from_raw_fd(fd)))
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn protocol_output() -> Result<Box<dyn Write>> {
+    Ok(Box::new(std::io::stdout()))
+}
+
+/// The byte offset of the start of line `line`, counting from 0.
+fn line_start(text: &str, line: usize) -> usize {
+    match line {
+        0 => 0,
+        _ => text.match_indices('\n').nth(line - 1).map_or(text.len(), |(i, _)| i + 1),
+    }
+}
+
+/// The LSP position of byte `offset` in `text`. Characters are counted in
+/// UTF-16 code units, as the protocol requires.
+fn position(text: &str, offset: usize) -> Value {
+    let before = text.get(..offset).unwrap_or(text);
+    let line = before.matches('\n').count();
+    let start = before.rfind('\n').map_or(0, |x| x + 1);
+    let character: usize = before[start..].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+/// The byte offset in `text` of the LSP position `pos`.
+fn offset(text: &str, pos: &Value) -> usize {
+    let field = |name: &str| pos[name].as_u64().unwrap_or(0) as usize;
+    let start = line_start(text, field("line"));
+    let character = field("character");
+    let mut units = 0;
+    for (i, chr) in text[start..].char_indices() {
+        if units >= character || chr == '\n' {
+            return start + i;
+        }
+        units += chr.len_utf16();
+    }
+    text.len()
+}
+
+fn range(text: &str, start: usize, end: usize) -> Value {
+    json!({ "start": position(text, start), "end": position(text, end) })
+}
+
+/// The symbol around byte `offset` in `text`, as the start and end offsets.
+fn symbol_bounds(text: &str, offset: usize) -> (usize, usize) {
+    thingatpt::bounds(text, offset, Thing::Symbol).unwrap_or((offset, offset))
+}
+
+/// The offset of the top-level form in `text` that defines `name`. A form
+/// defines a name if its head is a `def` symbol like `defun`, `defvar` or
+/// `cl-defmethod`, and the name, perhaps quoted, comes after it.
+fn find_definition(text: &str, name: &str, cx: &Context) -> Option<usize> {
+    let mut pos = 0;
+    loop {
+        let (form, len) = reader::read(&text[pos..], cx).ok()?;
+        let start = pos + crate::lread::form_offset(&text[pos..]);
+        pos += len;
+        let ObjectType::Cons(cons) = form.untag() else { continue };
+        let ObjectType::Symbol(head) = cons.car().untag() else { continue };
+        let head = head.name();
+        if !head.starts_with("def") && !head.contains("-def") {
+            continue;
+        }
+        let mut defined = match cons.cdr().untag() {
+            ObjectType::Cons(rest) => rest.car(),
+            _ => continue,
+        };
+        if let ObjectType::Cons(quoted) = defined.untag() {
+            if quoted.car() == sym::QUOTE || quoted.car() == sym::FUNCTION {
+                defined = quoted.cdr().as_list().ok()?.next()?.ok()?;
+            }
+        }
+        if matches!(defined.untag(), ObjectType::Symbol(x) if x.name() == name) {
+            return Some(start);
+        }
+    }
+}
+
+/// The file that `load-history` says defined `symbol`.
+fn history_file(symbol: Symbol, env: &Rt<Env>, cx: &Context) -> Option<String> {
+    let history = env.var(sym::LOAD_HISTORY, cx)?.bind(cx);
+    for entry in history.as_list().ok()?.flatten() {
+        let ObjectType::Cons(entry) = entry.untag() else { continue };
+        let ObjectType::String(file) = entry.car().untag() else { continue };
+        let Ok(definitions) = entry.cdr().as_list() else { continue };
+        let found = definitions.flatten().any(|def| match def.untag() {
+            ObjectType::Cons(def) => def.car() == sym::DEFUN && def.cdr() == symbol,
+            _ => def == symbol,
+        });
+        if found {
+            return Some(file.to_string());
+        }
+    }
+    None
+}
+
+struct Server {
+    /// The text of the open documents, by URI
+    documents: HashMap<String, String>,
+    output: Box<dyn Write>,
+}
+
+impl Server {
+    fn send(&mut self, message: &Value) -> Result<()> {
+        self.output.write_all(frame(&message.to_string()).as_bytes())?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Handle a message from the client. Requests are answered, and
+    /// notifications and responses have no reply.
+    fn handle(&mut self, message: &Value, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+        let Some(method) = message["method"].as_str() else { return Ok(()) };
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_owned();
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": {},
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                },
+                "serverInfo": { "name": "rune" },
+            }),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = match method {
+                    "textDocument/didOpen" => &params["textDocument"]["text"],
+                    _ => params["contentChanges"]
+                        .as_array()
+                        .and_then(|x| x.last())
+                        .map_or(&Value::Null, |x| &x["text"]),
+                };
+                let text = text.as_str().unwrap_or_default().to_owned();
+                let diagnostics = diagnostics(&text, env, cx);
+                self.documents.insert(uri.clone(), text);
+                self.publish(&uri, diagnostics)?;
+                Value::Null
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish(&uri, Vec::new())?;
+                Value::Null
+            }
+            "textDocument/completion" => self.completion(&uri, &params["position"], env, cx),
+            "textDocument/hover" => self.hover(&uri, &params["position"], env, cx),
+            "textDocument/definition" => self.definition(&uri, &params["position"], env, cx),
+            "shutdown" | "initialized" => Value::Null,
+            _ => {
+                if let Some(id) = message.get("id") {
+                    let error = json!({ "code": METHOD_NOT_FOUND, "message": "Unknown method" });
+                    self.send(&json!({ "jsonrpc": "2.0", "id": id, "error": error }))?;
+                }
+                return Ok(());
+            }
+        };
+        match message.get("id") {
+            Some(id) => self.send(&json!({ "jsonrpc": "2.0", "id": id, "result": result })),
+            None => Ok(()),
+        }
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Value>) -> Result<()> {
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }))
+    }
+
+    /// The text of document `uri`, and the offset of `pos` in it.
+    fn locate(&self, uri: &str, pos: &Value) -> Option<(&str, usize)> {
+        let text = self.documents.get(uri)?;
+        Some((text, offset(text, pos)))
+    }
+
+    /// The interned symbol at `pos` in document `uri`.
+    fn symbol_at(&self, uri: &str, pos: &Value) -> Option<Symbol<'static>> {
+        let (text, offset) = self.locate(uri, pos)?;
+        let (start, end) = symbol_bounds(text, offset);
+        interned_symbols().get(&text[start..end])
+    }
+
+    fn completion(&self, uri: &str, pos: &Value, env: &Rt<Env>, cx: &Context) -> Value {
+        let Some((text, offset)) = self.locate(uri, pos) else { return Value::Null };
+        let (start, _) = symbol_bounds(text, offset);
+        let prefix = &text[start..offset];
+        let mut items: Vec<_> = interned_symbols()
+            .symbols()
+            .filter(|x| x.name().starts_with(prefix))
+            .map(|symbol| {
+                // Function, Variable or Text
+                let kind = if symbol.func(cx).is_some() {
+                    3
+                } else if env.vars.get(symbol).is_some() {
+                    6
+                } else {
+                    1
+                };
+                (symbol.name().to_owned(), kind)
+            })
+            .collect();
+        items.sort();
+        let incomplete = items.len() > MAX_COMPLETIONS;
+        items.truncate(MAX_COMPLETIONS);
+        let items: Vec<_> = items
+            .into_iter()
+            .map(|(label, kind)| json!({ "label": label, "kind": kind }))
+            .collect();
+        json!({ "isIncomplete": incomplete, "items": items })
+    }
+
+    fn hover(&self, uri: &str, pos: &Value, env: &mut Rt<Env>, cx: &mut Context) -> Value {
+        let Some(symbol) = self.symbol_at(uri, pos) else { return Value::Null };
+        let mut docs = Vec::new();
+        if symbol.func(cx).is_some() {
+            let function: Object = symbol.into();
+            root!(function, cx);
+            let doc = crate::doc::documentation(function, None, env, cx);
+            if let Ok(ObjectType::String(doc)) = doc.as_ref().map(|x| x.untag()) {
+                docs.push(format!("{symbol} is a function.\n\n{doc}"));
+            }
+        }
+        let prop = sym::VARIABLE_DOCUMENTATION;
+        let doc = crate::doc::documentation_property(symbol, prop, None, env, cx);
+        if let Ok(ObjectType::String(doc)) = doc.as_ref().map(|x| x.untag()) {
+            docs.push(format!("{symbol} is a variable.\n\n{doc}"));
+        }
+        if docs.is_empty() {
+            return Value::Null;
+        }
+        json!({ "contents": { "kind": "plaintext", "value": docs.join("\n\n") } })
+    }
+
+    /// Look for the definition in the open documents, starting with this one,
+    /// and then in the file `load-history` says it was loaded from.
+    fn definition(&self, uri: &str, pos: &Value, env: &Rt<Env>, cx: &Context) -> Value {
+        let Some(symbol) = self.symbol_at(uri, pos) else { return Value::Null };
+        let name = symbol.name();
+        let current = self.documents.get_key_value(uri);
+        let others = self.documents.iter().filter(|(x, _)| *x != uri);
+        for (uri, text) in current.into_iter().chain(others) {
+            if let Some(start) = find_definition(text, name, cx) {
+                return json!({ "uri": uri, "range": range(text, start, start) });
+            }
+        }
+        let Some(file) = history_file(symbol, env, cx) else { return Value::Null };
+        let text = std::fs::read_to_string(&file).unwrap_or_default();
+        let start = find_definition(&text, name, cx).unwrap_or(0);
+        json!({ "uri": format!("file://{file}"), "range": range(&text, start, start) })
+    }
+}
+
+/// The byte compiler's warnings about `text` as LSP diagnostics. If the text
+/// can't be read, the error is the only diagnostic.
+fn diagnostics(text: &str, env: &mut Rt<Env>, cx: &mut Context) -> Vec<Value> {
+    let warnings = match bytecomp::check_source(text, env, cx) {
+        Ok(warnings) => warnings,
+        Err(e) => {
+            let start = e.downcast_ref::<reader::Error>().map_or(0, |x| x.position());
+            let start = start.min(text.len());
+            let diagnostic = json!({
+                "range": range(text, start, start),
+                "severity": 1,
+                "source": "rune",
+                "message": e.to_string(),
+            });
+            return vec![diagnostic];
+        }
+    };
+    let diagnostic = |warning: &bytecomp::Warning| {
+        let line = line_start(text, warning.line - 1);
+        let start = text[line..]
+            .char_indices()
+            .nth(warning.column - 1)
+            .map_or(text.len(), |x| line + x.0);
+        let (_, end) = symbol_bounds(text, start);
+        json!({
+            "range": range(text, start, end),
+            "severity": 2,
+            "source": "rune",
+            "message": warning.message,
+        })
+    };
+    warnings.iter().map(diagnostic).collect()
+}
+
+/// Serve a client on stdin until it exits or closes the stream, writing
+/// replies to `output`.
+pub(crate) fn serve(output: Box<dyn Write>, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let mut server = Server { documents: HashMap::default(), output };
+    let mut stdin = std::io::stdin().lock();
+    let mut input = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        while let Some(body) = next_message(&mut input)? {
+            // Messages that aren't JSON are ignored
+            let Ok(message) = serde_json::from_slice::<Value>(&body) else { continue };
+            if message["method"] == "exit" {
+                return Ok(());
+            }
+            server.handle(&message, env, cx)?;
+        }
+        let count = stdin.read(&mut buffer)?;
+        if count == 0 {
+            return Ok(());
+        }
+        input.extend_from_slice(&buffer[..count]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use std::sync::{Arc, Mutex};
+
+    /// A writer whose output can be read back by the test.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Output {
+        fn messages(&self) -> Vec<Value> {
+            let mut input = std::mem::take(&mut *self.0.lock().unwrap());
+            let mut messages = Vec::new();
+            while let Some(body) = next_message(&mut input).unwrap() {
+                messages.push(serde_json::from_slice(&body).unwrap());
+            }
+            messages
+        }
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "(a\n\u{1F600}b c)";
+        assert_eq!(position(text, 7), json!({ "line": 1, "character": 2 }));
+        assert_eq!(offset(text, &json!({ "line": 1, "character": 2 })), 7);
+        assert_eq!(offset(text, &json!({ "line": 0, "character": 9 })), 2);
+        assert_eq!(offset(text, &json!({ "line": 5, "character": 0 })), text.len());
+        assert_eq!(symbol_bounds("(foo-bar baz)", 3), (1, 8));
+        assert_eq!(symbol_bounds("(foo-bar baz)", 1), (1, 8));
+    }
+
+    #[test]
+    fn test_lsp() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let defvar = reader::read("(defvar lsp-test-var 1 \"A test variable.\")", cx).unwrap().0;
+        root!(defvar, cx);
+        crate::interpreter::eval(defvar, None, env, cx).unwrap();
+        let output = Output::default();
+        let mut server = Server { documents: HashMap::default(), output: Box::new(output.clone()) };
+        let mut request = |id: Option<i64>, method: &str, params: Value| {
+            let mut message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+            if let Some(id) = id {
+                message["id"] = id.into();
+            }
+            server.handle(&message, env, cx).unwrap();
+            output.messages()
+        };
+
+        let init = request(Some(1), "initialize", json!({}));
+        assert_eq!(init[0]["id"], 1);
+        assert_eq!(init[0]["result"]["capabilities"]["hoverProvider"], true);
+
+        let uri = "file:///tmp/lsp-test.el";
+        let text = ";;; -*- lexical-binding: t -*-
+(defun lsp-test-fn (a b) (list a))
+(defvar lsp-test-var (lsp-test-fn 1))
+";
+        let opened = request(
+            None,
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": uri, "text": text } }),
+        );
+        assert_eq!(opened[0]["method"], "textDocument/publishDiagnostics");
+        let diagnostics = opened[0]["params"]["diagnostics"].as_array().unwrap();
+        let messages: Vec<_> = diagnostics.iter().map(|x| x["message"].clone()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Unused lexical argument `b'",
+                "`lsp-test-fn' called with 1 argument, but requires 2",
+            ]
+        );
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({
+                "start": { "line": 1, "character": 22 },
+                "end": { "line": 1, "character": 23 },
+            })
+        );
+
+        let at = |line: i64, character: i64| json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } });
+        let definition = request(Some(2), "textDocument/definition", at(2, 24));
+        assert_eq!(definition[0]["result"]["uri"], uri);
+        assert_eq!(definition[0]["result"]["range"]["start"], json!({ "line": 1, "character": 0 }));
+
+        let completion = request(Some(3), "textDocument/completion", at(2, 27));
+        let items = completion[0]["result"]["items"].as_array().unwrap();
+        assert!(items.contains(&json!({ "label": "lsp-test-fn", "kind": 1 })));
+        assert!(items.iter().all(|x| x["label"].as_str().unwrap().starts_with("lsp-t")));
+
+        let hover = request(Some(4), "textDocument/hover", at(2, 10));
+        let contents = &hover[0]["result"]["contents"]["value"];
+        assert_eq!(contents, "lsp-test-var is a variable.\n\nA test variable.");
+        let hover = request(Some(5), "textDocument/hover", at(1, 0));
+        assert!(hover[0]["result"].is_null());
+        let closed =
+            request(None, "textDocument/didClose", json!({ "textDocument": { "uri": uri } }));
+        assert_eq!(closed[0]["params"]["diagnostics"], json!([]));
+        let unknown = request(Some(6), "workspace/unknown", json!({}));
+        assert_eq!(unknown[0]["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
@@ -1,44 +1,73 @@
-#[cfg(all(not(target_env = "msvc"), not(miri)))]
+#[cfg(all(not(target_env = "msvc"), not(miri), not(test)))]
 #[global_allocator]
 #[doc(hidden)]
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
+#[cfg(test)]
+#[global_allocator]
+static GLOBAL: debug::CountingAllocator = debug::CountingAllocator;
+
 #[macro_use]
 mod macros;
 #[macro_use]
 mod core;
 #[macro_use]
 mod debug;
+mod abbrev;
 mod alloc;
 mod arith;
+mod autoload;
+mod autosave;
 mod buffer;
 mod bytecode;
+mod bytecomp;
+mod callproc;
 mod casefiddle;
+mod casetab;
 mod character;
+mod chartab;
+mod coding;
+mod coverage;
 mod data;
+mod decompress;
+mod doc;
 mod editfns;
 mod emacs;
 mod eval;
+mod eventloop;
 mod fileio;
+mod filenotify;
 mod floatfns;
 mod fns;
 mod interpreter;
+mod json;
+mod jsonrpc;
 mod keymap;
+mod lint;
 mod lread;
+mod lsp;
+mod minibuf;
+mod obarray;
+mod package;
+mod pp;
 mod print;
+mod process;
 mod reader;
+mod repl;
 mod search;
+mod server;
+mod startup;
+mod thingatpt;
 mod threads;
 mod timefns;
 
 use crate::core::{
     env::{intern, sym, Env},
     gc::{Context, RootSet, Rt},
-    object::{Gc, LispString, NIL},
+    object::{Gc, LispString, Object, NIL},
 };
 use crate::eval::EvalError;
 use rune_core::macros::root;
-use std::io::{self, Write};
 
 fn main() {
     let roots = &RootSet::default();
@@ -47,78 +76,165 @@ fn main() {
 
     let args = Args::parse();
 
-    sym::init_symbols();
-    crate::core::env::init_variables(cx, env);
-    crate::data::defalias(intern("not", cx), (sym::NULL).into(), None)
-        .expect("null should be defined");
+    init(env, cx);
+    // Only the REPL and the daemon have a user to interact with
+    if (args.repl || args.daemon) && !args.batch {
+        env.set_default(sym::NONINTERACTIVE, NIL).unwrap();
+    }
+    if let Some(functions) = &args.trace_bytecode {
+        let trace = match functions.is_empty() {
+            true => sym::TRUE.into(),
+            false => {
+                let names: Vec<Object> = functions.iter().map(|x| intern(x, cx).into()).collect();
+                crate::fns::slice_into_list(&names, None, cx)
+            }
+        };
+        env.set_default(sym::BYTE_CODE_TRACE, trace).unwrap();
+    }
+
+    if args.coverage.is_some() {
+        env.set_default(sym::LOAD_COVERAGE, sym::TRUE.into()).unwrap();
+    }
+
+    if let Some(files) = &args.lint {
+        if let Err(e) = load_bootstrap(Some(()), env, cx) {
+            print_error(e, env, cx);
+        }
+        let clean = lint::lint_files(files, args.json, env, cx);
+        std::process::exit(if clean { 0 } else { 1 });
+    }
+
+    if args.lsp {
+        let output = match lsp::protocol_output() {
+            Ok(output) => output,
+            Err(e) => {
+                println!("Error: {e}");
+                std::process::exit(1);
+            }
+        };
+        if let Err(e) = load_bootstrap(Some(()), env, cx) {
+            print_error(e, env, cx);
+        }
+        if let Err(e) = lsp::serve(output, env, cx) {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
 
     if args.load {
-        load(env, cx);
+        if !load(env, cx) {
+            batch_exit(&args, env, cx);
+        }
+        let init_dir = match &args.init_directory {
+            Some(dir) => dir.into(),
+            None => startup::default_init_directory(),
+        };
+        if let Err(e) = startup::startup(&init_dir, !args.no_init_file, env, cx) {
+            println!("Error loading init file: {e}");
+            batch_exit(&args, env, cx);
+        }
+    }
+
+    if let Some(file) = &args.coverage {
+        let report = coverage::report(None);
+        match file {
+            Some(file) => {
+                if let Err(e) = std::fs::write(file, report) {
+                    println!("Error writing coverage report: {e}");
+                }
+            }
+            None => print!("{report}"),
+        }
     }
 
     if args.repl {
-        repl(env, cx);
+        repl::repl(env, cx);
+    }
+
+    if args.daemon {
+        daemon(env, cx);
+    }
+
+    if let Err(e) = emacs::shutdown(env, cx) {
+        println!("Error: {e}");
     }
 }
 
-fn parens_closed(buffer: &str) -> bool {
-    let open = buffer.chars().filter(|&x| x == '(').count();
-    let close = buffer.chars().filter(|&x| x == ')').count();
-    open <= close
+/// Set up the builtin functions and the initial state of `env`.
+pub(crate) fn init(env: &mut Rt<Env>, cx: &mut Context) {
+    sym::init_symbols();
+    crate::core::env::init_variables(cx, env);
+    crate::data::fset(intern("not", cx), (sym::NULL).into()).expect("null should be defined");
+    crate::callproc::init_process_environment(env, cx)
+        .expect("process environment should be initialized");
+    crate::casetab::init_case_table(env, cx).expect("case table should be initialized");
+    crate::obarray::init_obarray(env, cx).expect("obarray should be initialized");
+    crate::abbrev::init_abbrevs(env, cx).expect("abbrev tables should be initialized");
+    crate::autosave::init_autosave();
+    crate::eval::init_errors(env, cx);
 }
 
-fn repl(env: &mut Rt<Env>, cx: &mut Context) {
-    println!("Hello, world!");
-    let mut buffer = String::new();
-    let stdin = io::stdin();
+/// Serve clients until killed.
+fn daemon(env: &mut Rt<Env>, cx: &mut Context) {
+    let socket = server::default_socket_path();
+    if let Err(e) = server::start(&socket, env.id) {
+        println!("Error: {e}");
+        return;
+    }
+    println!("Starting rune daemon on {}", socket.display());
     loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
-        stdin.read_line(&mut buffer).unwrap();
-        if buffer.trim() == "exit" {
-            return;
+        eventloop::wait_for_event(autosave::next_auto_save(env, cx), env.id);
+        if let Err(e) = eventloop::dispatch_events(env, cx) {
+            println!("Error: {e}");
         }
-        if buffer.trim().is_empty() {
-            continue;
+        if let Err(e) = alloc::idle_garbage_collect(env, cx) {
+            println!("Error: {e}");
         }
-        if !parens_closed(&buffer) {
-            continue;
-        }
-        let (obj, _) = match reader::read(&buffer, cx) {
-            Ok(obj) => obj,
-            Err(e) => {
-                println!("Error: {e}");
-                buffer.clear();
-                continue;
-            }
-        };
+    }
+}
 
-        root!(obj, cx);
-        match interpreter::eval(obj, None, env, cx) {
-            Ok(val) => println!("{val}"),
-            Err(e) => {
-                print!("Error: {e}");
-                if let Ok(e) = e.downcast::<EvalError>() {
-                    e.print_backtrace();
-                }
-            }
+/// Load the bootstrap files. Returns false if there was an error.
+fn load(env: &mut Rt<Env>, cx: &mut Context) -> bool {
+    match load_bootstrap(None, env, cx) {
+        Ok(val) => {
+            print!("{val}");
+            true
+        }
+        Err(e) => {
+            print_error(e, env, cx);
+            false
         }
-        buffer.clear();
     }
 }
 
-fn load(env: &mut Rt<Env>, cx: &mut Context) {
+/// In batch mode an error that isn't handled ends rune with status 255, as
+/// it does in Emacs.
+fn batch_exit(args: &Args, env: &mut Rt<Env>, cx: &mut Context) {
+    if args.batch {
+        _ = emacs::shutdown(env, cx);
+        std::process::exit(255);
+    }
+}
+
+fn load_bootstrap(
+    nomessage: Option<()>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> anyhow::Result<bool> {
     buffer::get_buffer_create(cx.add("*scratch*"), Some(NIL), cx).unwrap();
     let bootstrap: Gc<&LispString> = cx.add_as("lisp/bootstrap.el");
     root!(bootstrap, cx);
-    match crate::lread::load(bootstrap, None, None, cx, env) {
-        Ok(val) => print!("{val}"),
-        Err(e) => {
-            print!("Error: {e}");
-            if let Ok(e) = e.downcast::<EvalError>() {
-                e.print_backtrace();
-            }
+    crate::lread::load(bootstrap, None, nomessage, cx, env)
+}
+
+fn print_error(error: anyhow::Error, env: &Rt<Env>, cx: &Context) {
+    match error.downcast::<EvalError>() {
+        Ok(e) => {
+            println!("Error: {}", e.message(env, cx));
+            e.print_backtrace();
         }
+        Err(e) => print!("Error: {e}"),
     }
 }
 
@@ -126,19 +242,58 @@ fn load(env: &mut Rt<Env>, cx: &mut Context) {
 struct Args {
     load: bool,
     repl: bool,
+    daemon: bool,
+    lsp: bool,
+    /// Exit when an error isn't handled
+    batch: bool,
+    no_init_file: bool,
+    init_directory: Option<String>,
+    /// The functions to trace with `byte-code-trace`, or all of them if empty
+    trace_bytecode: Option<Vec<String>>,
+    /// Where to write the coverage report of the files loaded, or stdout if
+    /// no file is given
+    coverage: Option<Option<String>>,
+    /// The files to check with `rune lint`
+    lint: Option<Vec<String>>,
+    /// Print lint problems as JSON
+    json: bool,
 }
 
 impl Args {
     fn empty(&self) -> bool {
-        !self.load && !self.repl
+        !self.load && !self.repl && !self.daemon && !self.lsp && self.lint.is_none()
     }
 
     fn parse() -> Self {
         let mut args = Args::default();
-        for arg in std::env::args() {
+        let mut argv = std::env::args();
+        while let Some(arg) = argv.next() {
             match arg.as_str() {
                 "--repl" => args.repl = true,
                 "--load" => args.load = true,
+                "--daemon" => args.daemon = true,
+                "--lsp" => args.lsp = true,
+                "--batch" | "-batch" => args.batch = true,
+                "-q" | "-Q" | "--no-init-file" | "--quick" => args.no_init_file = true,
+                "--init-directory" => args.init_directory = argv.next(),
+                x if x.starts_with("--init-directory=") => {
+                    args.init_directory = Some(x["--init-directory=".len()..].to_owned());
+                }
+                "--trace-bytecode" => args.trace_bytecode = Some(Vec::new()),
+                x if x.starts_with("--trace-bytecode=") => {
+                    let functions = x["--trace-bytecode=".len()..].split(',');
+                    args.trace_bytecode = Some(functions.map(ToOwned::to_owned).collect());
+                }
+                "lint" => {
+                    let (json, files): (Vec<_>, Vec<_>) =
+                        argv.by_ref().partition(|x| x == "--json");
+                    args.json = !json.is_empty();
+                    args.lint = Some(files);
+                }
+                "--coverage" => args.coverage = Some(None),
+                x if x.starts_with("--coverage=") => {
+                    args.coverage = Some(Some(x["--coverage=".len()..].to_owned()));
+                }
                 x => println!("unknown arg: {x}"),
             }
         }
@@ -0,0 +1,147 @@
+//! Reading input from the user. There is no minibuffer, so input is read a
+//! line at a time from the terminal. Interactively that is done with a line
+//! editor. In batch mode it is read from stdin, and running out of input is an
+//! error.
+use crate::core::{
+    env::Env,
+    gc::{Context, Rt},
+    object::{Object, ObjectType},
+};
+use anyhow::{bail, Result};
+use rune_macros::defun;
+use rustyline::error::ReadlineError;
+use std::io::{self, BufRead, Write};
+
+/// Read a line from `input` after writing `prompt` to `output`.
+fn read_batch_line(
+    prompt: &str,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<String> {
+    write!(output, "{prompt}")?;
+    output.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        bail!("Error reading from stdin");
+    }
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    Ok(line)
+}
+
+/// Read a line of input after showing `prompt`. Interactively the line
+/// starts out as `initial`.
+pub(crate) fn read_line(
+    prompt: &str,
+    initial: &str,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<String> {
+    if crate::emacs::noninteractive(env, cx) {
+        return read_batch_line(prompt, &mut io::stdin().lock(), &mut io::stdout());
+    }
+    let mut editor = rustyline::DefaultEditor::new()?;
+    match editor.readline_with_initial(prompt, (initial, "")) {
+        Ok(line) => Ok(line),
+        Err(ReadlineError::Interrupted) => bail!("Quit"),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The default to use for empty input. Of a list of defaults, the first is
+/// used.
+fn default_value(default: Option<Object>) -> Option<Object> {
+    let default = match default?.untag() {
+        ObjectType::Cons(cons) => cons.car(),
+        _ => default?,
+    };
+    (!default.is_nil()).then_some(default)
+}
+
+/// Read a string from the user after showing `prompt`. If `read` is non-nil
+/// the input is read as a lisp object, and empty input reads
+/// `default-value` instead.
+#[defun]
+#[allow(clippy::too_many_arguments)]
+fn read_from_minibuffer<'ob>(
+    prompt: &str,
+    initial_contents: Option<&str>,
+    _keymap: Option<Object>,
+    read: Option<()>,
+    _hist: Option<Object>,
+    default_value: Option<Object>,
+    _inherit_input_method: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let input = read_line(prompt, initial_contents.unwrap_or_default(), env, cx)?;
+    if read.is_none() {
+        return Ok(cx.add(input));
+    }
+    let input = match self::default_value(default_value).map(|x| x.untag()) {
+        Some(ObjectType::String(default)) if input.is_empty() => default.to_string(),
+        _ => input,
+    };
+    Ok(crate::reader::read(&input, cx)?.0)
+}
+
+/// Read a string from the user after showing `prompt`. Empty input returns
+/// `default-value` if it is non-nil.
+#[defun]
+fn read_string<'ob>(
+    prompt: &str,
+    initial_input: Option<&str>,
+    _history: Option<Object>,
+    default_value: Option<Object<'ob>>,
+    _inherit_input_method: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let input = read_line(prompt, initial_input.unwrap_or_default(), env, cx)?;
+    match self::default_value(default_value) {
+        Some(default) if input.is_empty() => Ok(default),
+        _ => Ok(cx.add(input)),
+    }
+}
+
+/// Ask the user a yes or no question, and return t if they answer yes.
+#[defun]
+pub(crate) fn yes_or_no_p(prompt: &str, env: &Rt<Env>, cx: &Context) -> Result<bool> {
+    let prompt = format!("{prompt}(yes or no) ");
+    loop {
+        match read_line(&prompt, "", env, cx)?.trim() {
+            "yes" => return Ok(true),
+            "no" => return Ok(false),
+            _ => println!("Please answer yes or no."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::list;
+
+    #[test]
+    fn test_read_batch_line() {
+        let mut input = io::Cursor::new("first\nsecond");
+        let mut output = Vec::new();
+        assert_eq!(read_batch_line("> ", &mut input, &mut output).unwrap(), "first");
+        assert_eq!(read_batch_line("> ", &mut input, &mut output).unwrap(), "second");
+        assert_eq!(output, b"> > ");
+        assert!(read_batch_line("> ", &mut input, &mut output).is_err());
+    }
+
+    #[test]
+    fn test_default_value() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let defaults = list!["a", "b"; cx];
+        assert_eq!(default_value(Some(defaults)), Some(cx.add("a")));
+        assert_eq!(default_value(Some(cx.add("c"))), Some(cx.add("c")));
+        assert_eq!(default_value(Some(crate::core::object::NIL)), None);
+        assert_eq!(default_value(None), None);
+    }
+}
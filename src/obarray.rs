@@ -0,0 +1,240 @@
+//! Obarrays.
+//!
+//! An obarray is a table of symbols indexed by name. The standard obarray is
+//! the process-wide table of interned symbols. Other obarrays are stored as a
+//! record of the form `#s(obarray TABLE)`, where TABLE is a hash table from
+//! names to symbols. The standard obarray is the record whose TABLE is nil.
+//! Symbols in other obarrays are not interned in the standard one, so the
+//! reader never returns them.
+use crate::core::{
+    env::{interned_symbols, sym, Env},
+    error::{Type, TypeError},
+    gc::{Context, Rt, Rto, Slot},
+    object::{
+        Function, HashTable, LispHashTable, Object, ObjectType, Record, RecordBuilder, Symbol, NIL,
+    },
+};
+use anyhow::{bail, Result};
+use rune_core::macros::{call, root};
+use rune_macros::defun;
+
+const TABLE: usize = 1;
+const LEN: usize = 2;
+
+#[derive(Copy, Clone)]
+pub(crate) struct Obarray<'ob>(&'ob Record);
+
+impl<'ob> TryFrom<Object<'ob>> for Obarray<'ob> {
+    type Error = TypeError;
+
+    fn try_from(obj: Object<'ob>) -> Result<Self, Self::Error> {
+        match obj.untag() {
+            ObjectType::Record(record) if is_obarray(record) => Ok(Obarray(record)),
+            _ => Err(TypeError::new(Type::Obarray, obj)),
+        }
+    }
+}
+
+impl<'ob> TryFrom<Object<'ob>> for Option<Obarray<'ob>> {
+    type Error = TypeError;
+
+    fn try_from(obj: Object<'ob>) -> Result<Self, Self::Error> {
+        if obj.is_nil() {
+            Ok(None)
+        } else {
+            obj.try_into().map(Some)
+        }
+    }
+}
+
+impl<'ob> From<Obarray<'ob>> for Object<'ob> {
+    fn from(obarray: Obarray<'ob>) -> Self {
+        obarray.0.into()
+    }
+}
+
+pub(crate) fn is_obarray(record: &Record) -> bool {
+    record.len() == LEN && record[0].get() == sym::OBARRAY
+}
+
+impl<'ob> Obarray<'ob> {
+    fn create(table: Object<'ob>, cx: &'ob Context) -> Self {
+        let mut record = cx.vec_with_capacity(LEN);
+        record.push(sym::OBARRAY.into());
+        record.push(table);
+        let record: Object = cx.add(RecordBuilder(record));
+        record.try_into().expect("record should be an obarray")
+    }
+
+    /// Create an empty obarray.
+    pub(crate) fn new(cx: &'ob Context) -> Self {
+        Self::create(cx.add(HashTable::default()), cx)
+    }
+
+    /// The table of private symbols, or `None` for the standard obarray.
+    fn table(self) -> Option<&'ob LispHashTable> {
+        match self.0[TABLE].get().untag() {
+            ObjectType::HashTable(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    /// Find the symbol named `name`.
+    pub(crate) fn get(self, name: &str, cx: &'ob Context) -> Option<Symbol<'ob>> {
+        match self.table() {
+            Some(table) => table.get(cx.add(name)).map(|x| cx.bind(x).try_into().unwrap()),
+            None => interned_symbols().get(name).map(|x| cx.bind(x)),
+        }
+    }
+
+    /// Get the symbol named `name`, adding a new one if there is none.
+    pub(crate) fn intern(self, name: &str, cx: &'ob Context) -> Symbol<'ob> {
+        let Some(table) = self.table() else { return crate::core::env::intern(name, cx) };
+        if let Some(symbol) = self.get(name, cx) {
+            return symbol;
+        }
+        let symbol = Symbol::new_uninterned(name, cx);
+        table.insert(cx.add(name), symbol.into());
+        symbol
+    }
+
+    /// Remove `symbol` from the obarray. Returns false if it was not there.
+    fn remove(self, symbol: Symbol, cx: &'ob Context) -> bool {
+        let Some(table) = self.table() else { return interned_symbols().unintern(symbol) };
+        if self.get(symbol.name(), cx) != Some(cx.bind(symbol)) {
+            return false;
+        }
+        table.swap_remove(cx.add(symbol.name()));
+        true
+    }
+
+    /// All the symbols in the obarray.
+    fn symbols(self, cx: &'ob Context) -> Vec<Symbol<'ob>> {
+        match self.table() {
+            Some(table) => (0..table.len())
+                .filter_map(|i| table.get_index(i))
+                .map(|(_, symbol)| cx.bind(symbol).try_into().unwrap())
+                .collect(),
+            None => interned_symbols().symbols_in_order().map(|x| cx.bind(x)).collect(),
+        }
+    }
+}
+
+/// Set `obarray` to the standard obarray.
+pub(crate) fn init_obarray(env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let standard = Obarray::create(NIL, cx);
+    env.set_default(sym::OBARRAY, standard.into())
+}
+
+/// The obarray to use when an optional obarray argument is nil.
+pub(crate) fn obarray_or_default<'ob>(
+    obarray: Option<Obarray<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Obarray<'ob>> {
+    match obarray {
+        Some(obarray) => Ok(obarray),
+        None => match env.var(sym::OBARRAY, cx) {
+            Some(value) => Ok(value.bind(cx).try_into()?),
+            None => Ok(Obarray::create(NIL, cx)),
+        },
+    }
+}
+
+/// The size is only a hint in Emacs, so it is ignored.
+#[defun]
+fn obarray_make(_size: Option<usize>, cx: &Context) -> Object {
+    Obarray::new(cx).into()
+}
+
+#[defun]
+fn obarrayp(object: Object) -> bool {
+    matches!(object.untag(), ObjectType::Record(x) if is_obarray(x))
+}
+
+#[defun]
+fn obarray_clear(obarray: Obarray) -> Result<()> {
+    let Some(table) = obarray.table() else { bail!("The standard obarray can't be cleared") };
+    table.clear();
+    Ok(())
+}
+
+#[defun]
+fn unintern<'ob>(
+    name: Object<'ob>,
+    obarray: Option<Obarray<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<bool> {
+    let obarray = obarray_or_default(obarray, env, cx)?;
+    let symbol = match name.untag() {
+        ObjectType::Symbol(symbol) => symbol,
+        ObjectType::String(name) => match obarray.get(name, cx) {
+            Some(symbol) => symbol,
+            None => return Ok(false),
+        },
+        x => bail!(TypeError::new(Type::String, x)),
+    };
+    Ok(obarray.remove(symbol, cx))
+}
+
+#[defun]
+fn mapatoms(
+    function: &Rto<Function>,
+    obarray: Option<Obarray>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    root!(symbols, new(Vec<Slot<Object>>), cx);
+    let obarray = obarray_or_default(obarray, env, cx)?;
+    for symbol in obarray.symbols(cx) {
+        symbols.push(Object::from(symbol));
+    }
+    for i in 0..symbols.len() {
+        call!(function, &symbols[i]; env, cx)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_obarray() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let obarray = Obarray::new(cx);
+        let foo = obarray.intern("obarray-test-foo", cx);
+        assert_eq!(obarray.intern("obarray-test-foo", cx), foo);
+        assert_eq!(obarray.get("obarray-test-foo", cx), Some(foo));
+        assert!(!foo.interned());
+        assert!(interned_symbols().get("obarray-test-foo").is_none());
+        assert_ne!(crate::core::env::intern("obarray-test-foo", cx), foo);
+
+        let bar = obarray.intern("obarray-test-bar", cx);
+        assert_eq!(obarray.symbols(cx), vec![foo, bar]);
+        assert!(obarray.remove(foo, cx));
+        assert!(!obarray.remove(foo, cx));
+        assert_eq!(obarray.get("obarray-test-foo", cx), None);
+        assert_eq!(obarray.symbols(cx), vec![bar]);
+
+        let standard = Obarray::create(NIL, cx);
+        assert_eq!(standard.get("car", cx), Some(sym::CAR));
+        obarray_clear(obarray).unwrap();
+        assert!(obarray.symbols(cx).is_empty());
+
+        // Uninterning from the standard obarray leaves the symbol usable, and
+        // interning the name again makes a new one
+        let baz = crate::core::env::intern("obarray-test-baz", cx);
+        assert!(standard.remove(baz, cx));
+        assert!(!standard.remove(baz, cx));
+        assert_eq!(standard.get("obarray-test-baz", cx), None);
+        assert_eq!(baz.name(), "obarray-test-baz");
+        assert_ne!(standard.intern("obarray-test-baz", cx), baz);
+    }
+}
+
+defvar!(OBARRAY);
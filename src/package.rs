@@ -0,0 +1,206 @@
+//! The runtime side of package.el. Installed packages live in
+//! `package-user-dir`, one directory per package named `NAME-VERSION`.
+//! Activating a package adds its directory to `load-path` and loads its
+//! `NAME-autoloads.el` file, which is generated from the autoload cookies in
+//! its sources if the package doesn't have one.
+use crate::core::{
+    cons::Cons,
+    env::{intern, sym, Env},
+    gc::{Context, Rt},
+    object::{Gc, LispString, Object, Symbol, NIL, TRUE},
+};
+use crate::fns::{parse_version, slice_into_list, version_list_cmp};
+use anyhow::Result;
+use rune_core::hashmap::HashMap;
+use rune_core::macros::root;
+use rune_macros::defun;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Package {
+    name: String,
+    version: Vec<i64>,
+    dir: PathBuf,
+}
+
+/// Activated packages by name.
+fn activated() -> &'static Mutex<HashMap<String, Package>> {
+    static ACTIVATED: OnceLock<Mutex<HashMap<String, Package>>> = OnceLock::new();
+    ACTIVATED.get_or_init(Mutex::default)
+}
+
+/// Split a package directory name like `magit-3.3.0` into the package name and
+/// version.
+fn parse_package_dir(dir_name: &str) -> Option<(&str, Vec<i64>)> {
+    let (name, version) = dir_name.rsplit_once('-')?;
+    if name.is_empty() || !version.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((name, parse_version(version).ok()?))
+}
+
+/// The newest version of each package installed in `dir`.
+fn installed_packages(dir: &Path) -> Vec<Package> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut packages: HashMap<String, Package> = HashMap::default();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let file_name = entry.file_name();
+        let Some((name, version)) = parse_package_dir(&file_name.to_string_lossy()) else {
+            continue;
+        };
+        let newer = packages
+            .get(name)
+            .map_or(true, |x| version_list_cmp(&version, &x.version).is_gt());
+        if newer {
+            let package = Package { name: name.to_owned(), version, dir: path };
+            packages.insert(name.to_owned(), package);
+        }
+    }
+    let mut packages: Vec<_> = packages.into_values().collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages
+}
+
+fn package_user_dir(env: &Rt<Env>, cx: &Context) -> Result<String> {
+    let dir = match env.var(sym::PACKAGE_USER_DIR, cx) {
+        Some(dir) => dir.bind(cx).try_into()?,
+        None => "",
+    };
+    crate::fileio::expand_file_name(dir, None, env, cx)
+}
+
+/// Add `package` to `load-path` and load its autoloads, generating them if
+/// the package doesn't have an autoloads file.
+fn activate(package: &Package, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let dir = cx.add(package.dir.to_string_lossy().into_owned());
+    let load_path = env.var(sym::LOAD_PATH, cx).map_or(NIL, |x| x.bind(cx));
+    let load_path: Object = Cons::new(dir, load_path, cx).into();
+    env.set_default(sym::LOAD_PATH, load_path)?;
+
+    let autoloads = package.dir.join(format!("{}-autoloads.el", package.name));
+    if !autoloads.exists() {
+        crate::autoload::make_directory_autoloads(&package.dir, &autoloads, cx)?;
+    }
+    let file: Gc<&LispString> = cx.add_as(autoloads.to_string_lossy().into_owned());
+    root!(file, cx);
+    crate::lread::load(file, None, Some(()), cx, env)?;
+    activated()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(package.name.clone(), package.clone());
+    Ok(())
+}
+
+#[defun]
+fn package_initialize(no_activate: Option<()>, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let dir = package_user_dir(env, cx)?;
+    let packages = installed_packages(Path::new(&dir));
+    if no_activate.is_none() {
+        for package in &packages {
+            if !activated()
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .contains_key(&package.name)
+            {
+                activate(package, env, cx)?;
+            }
+        }
+    }
+    let names: Vec<Object> = {
+        let activated = activated().lock().unwrap_or_else(PoisonError::into_inner);
+        let mut names: Vec<&String> = activated.keys().collect();
+        names.sort();
+        names.into_iter().map(|x| intern(x, cx).into()).collect()
+    };
+    let list = slice_into_list(&names, None, cx);
+    env.set_default(sym::PACKAGE_ACTIVATED_LIST, list)?;
+    env.set_default(sym::PACKAGE__INITIALIZED, TRUE)?;
+    Ok(())
+}
+
+#[defun]
+fn package_installed_p(
+    package: Symbol,
+    min_version: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let min_version: Vec<i64> = match min_version {
+        Some(list) => list.as_list()?.map(|x| Ok(x?.try_into()?)).collect::<Result<_>>()?,
+        None => Vec::new(),
+    };
+    let name = package.name();
+    let version = match activated().lock().unwrap_or_else(PoisonError::into_inner).get(name) {
+        Some(package) => Some(package.version.clone()),
+        None => {
+            let dir = package_user_dir(env, cx)?;
+            let installed = installed_packages(Path::new(&dir));
+            installed.into_iter().find(|x| x.name == name).map(|x| x.version)
+        }
+    };
+    Ok(version.is_some_and(|x| version_list_cmp(&x, &min_version).is_ge()))
+}
+
+defvar!(PACKAGE_USER_DIR, "~/.config/rune/elpa/");
+defvar!(PACKAGE_ACTIVATED_LIST);
+defvar!(PACKAGE__INITIALIZED);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::{list, rebind};
+
+    #[test]
+    fn test_parse_package_dir() {
+        assert_eq!(parse_package_dir("magit-3.3.0"), Some(("magit", vec![3, 3, 0])));
+        assert_eq!(
+            parse_package_dir("git-commit-20230101.1200"),
+            Some(("git-commit", vec![20230101, 1200]))
+        );
+        assert_eq!(parse_package_dir("archives"), None);
+        assert_eq!(parse_package_dir("foo-bar"), None);
+    }
+
+    #[test]
+    fn test_package_initialize() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let dir = std::env::temp_dir().join(format!("rune-package-test-{}", std::process::id()));
+        for version in ["1.0", "1.2"] {
+            let pkg = dir.join(format!("rune-test-pkg-{version}"));
+            std::fs::create_dir_all(&pkg).unwrap();
+            let autoloads = format!("(setq rune-test-pkg-autoloaded \"{version}\")");
+            std::fs::write(pkg.join("rune-test-pkg-autoloads.el"), autoloads).unwrap();
+        }
+        let user_dir = cx.add(dir.to_string_lossy().into_owned());
+        env.set_default(sym::PACKAGE_USER_DIR, user_dir).unwrap();
+        env.set_default(sym::LOAD_PATH, NIL).unwrap();
+
+        let pkg = intern("rune-test-pkg", cx);
+        assert!(package_installed_p(pkg, None, env, cx).unwrap());
+        package_initialize(None, env, cx).unwrap();
+        let newest = dir.join("rune-test-pkg-1.2").to_string_lossy().into_owned();
+        let load_path = env.vars.get(sym::LOAD_PATH).unwrap().bind(cx);
+        assert_eq!(load_path, list![newest; cx]);
+        let obj = crate::reader::read("rune-test-pkg-autoloaded", cx).unwrap().0;
+        root!(obj, cx);
+        let autoloaded = rebind!(crate::interpreter::eval(obj, None, env, cx).unwrap());
+        assert_eq!(autoloaded, cx.add("1.2"));
+
+        let pkg = intern("rune-test-pkg", cx);
+        let version = list![1, 1; cx];
+        assert!(package_installed_p(pkg, Some(version), env, cx).unwrap());
+        let version = list![2; cx];
+        assert!(!package_installed_p(pkg, Some(version), env, cx).unwrap());
+        assert!(!package_installed_p(intern("rune-no-such-pkg", cx), None, env, cx).unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
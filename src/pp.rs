@@ -0,0 +1,315 @@
+//! Pretty printing.
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{address, Ancestors, Object, ObjectType, Symbol},
+};
+use anyhow::Result;
+use rune_macros::defun;
+use std::fmt::{self, Write as _};
+
+/// The number of arguments of a special form that stay on the line of its
+/// name. The rest are body forms indented by two. Forms not listed here have
+/// their arguments lined up under the first one.
+fn distinguished_args(name: &str) -> Option<usize> {
+    let count = match name {
+        "progn"
+        | "save-excursion"
+        | "save-restriction"
+        | "save-current-buffer"
+        | "save-match-data"
+        | "with-temp-buffer" => 0,
+        "lambda"
+        | "let"
+        | "let*"
+        | "letrec"
+        | "when"
+        | "unless"
+        | "while"
+        | "dolist"
+        | "dotimes"
+        | "prog1"
+        | "unwind-protect"
+        | "catch"
+        | "with-current-buffer"
+        | "pcase" => 1,
+        "defun" | "defmacro" | "defsubst" | "cl-defun" | "cl-defmacro" | "if"
+        | "condition-case" | "prog2" => 2,
+        _ => return None,
+    };
+    Some(count)
+}
+
+/// The column after `text` is written starting at `column`.
+fn next_column(column: usize, text: &str) -> usize {
+    match text.rfind('\n') {
+        Some(i) => text[i + 1..].chars().count(),
+        None => column + text.chars().count(),
+    }
+}
+
+struct Printer {
+    out: String,
+    /// The column at the end of `out`
+    column: usize,
+    width: usize,
+    /// The conses being printed, to find cycles
+    seen: Ancestors<()>,
+}
+
+/// Counts the characters written to it, failing once there are more than
+/// `limit` so that measuring a large object stops early.
+struct Measure {
+    len: usize,
+    limit: usize,
+}
+
+impl fmt::Write for Measure {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.len += s.chars().count();
+        if self.len > self.limit {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Printer {
+    fn push_str(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.column = next_column(self.column, text);
+    }
+
+    fn push(&mut self, chr: char) {
+        self.out.push(chr);
+        self.column = if chr == '\n' { 0 } else { self.column + 1 };
+    }
+
+    fn print_atom(&mut self, obj: Object) {
+        let start = self.out.len();
+        let _ = write!(self.out, "{obj}");
+        self.column = next_column(self.column, &self.out[start..]);
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        self.out.extend(std::iter::repeat(' ').take(indent));
+        self.column = indent;
+    }
+
+    fn fits(&self, obj: Object) -> bool {
+        let Some(limit) = self.width.checked_sub(self.column) else { return false };
+        write!(Measure { len: 0, limit }, "{obj}").is_ok()
+    }
+
+    fn print(&mut self, obj: Object) {
+        match obj.untag() {
+            ObjectType::Cons(cons) => self.print_cons(cons),
+            ObjectType::Vec(vec) => {
+                let elements: Vec<_> = vec.iter().map(|x| x.get()).collect();
+                let flat = self.fits(obj);
+                self.push('[');
+                self.print_aligned(&elements, flat);
+                self.push(']');
+            }
+            _ => self.print_atom(obj),
+        }
+    }
+
+    fn print_cons(&mut self, cons: &Cons) {
+        let ptr = address(cons);
+        if self.seen.contains(ptr) {
+            self.push_str("#0");
+            return;
+        }
+        if let Some((prefix, obj)) = cons.abbreviation() {
+            self.push_str(prefix);
+            self.seen.enter(ptr, ());
+            self.print(obj);
+            self.seen.leave(ptr);
+            return;
+        }
+
+        let mut elements = vec![];
+        let mut conses = vec![];
+        let mut tail = None;
+        let mut cell = cons;
+        loop {
+            let ptr = address(cell);
+            if self.seen.contains(ptr) || conses.contains(&ptr) {
+                tail = Some(None);
+                break;
+            }
+            conses.push(ptr);
+            elements.push(cell.car());
+            match cell.cdr().untag() {
+                ObjectType::Cons(next) => cell = next,
+                ObjectType::NIL => break,
+                _ => {
+                    tail = Some(Some(cell.cdr()));
+                    break;
+                }
+            }
+        }
+        let flat = self.fits(cons.into());
+        for &ptr in &conses {
+            self.seen.enter(ptr, ());
+        }
+
+        self.push('(');
+        let indent = self.column;
+        match elements[0].untag() {
+            ObjectType::Symbol(head) if !flat && elements.len() > 1 => {
+                self.print_call(head, &elements, indent);
+            }
+            _ => self.print_aligned(&elements, flat),
+        }
+        match tail {
+            Some(Some(obj)) => {
+                self.push_str(" . ");
+                self.print(obj);
+            }
+            Some(None) => self.push_str(" . #0"),
+            None => {}
+        }
+        self.push(')');
+
+        for ptr in conses {
+            self.seen.leave(ptr);
+        }
+    }
+
+    /// Print a form whose head is a symbol, which has not fit on one line.
+    /// `indent` is the column after the open paren.
+    fn print_call(&mut self, head: Symbol, elements: &[Object], indent: usize) {
+        self.push_str(head.name());
+        let args = &elements[1..];
+        match distinguished_args(head.name()) {
+            Some(count) => {
+                let count = count.min(args.len());
+                for arg in &args[..count] {
+                    self.push(' ');
+                    self.print(*arg);
+                }
+                for arg in &args[count..] {
+                    self.newline(indent + 1);
+                    self.print(*arg);
+                }
+            }
+            None => {
+                self.push(' ');
+                self.print_aligned(args, false);
+            }
+        }
+    }
+
+    /// Print `elements` separated by spaces if `flat`, otherwise one per line
+    /// lined up under the first.
+    fn print_aligned(&mut self, elements: &[Object], flat: bool) {
+        let indent = self.column;
+        for (i, obj) in elements.iter().enumerate() {
+            if i > 0 {
+                match flat {
+                    true => self.push(' '),
+                    false => self.newline(indent),
+                }
+            }
+            self.print(*obj);
+        }
+    }
+}
+
+/// Pretty print `obj`, breaking lists that don't fit in `width` columns over
+/// several lines.
+pub(crate) fn pretty_print(obj: Object, width: usize) -> String {
+    let mut printer = Printer { out: String::new(), column: 0, width, seen: Ancestors::default() };
+    printer.print(obj);
+    printer.out
+}
+
+/// The line width for pretty printing, from `fill-column`.
+pub(crate) fn line_width(env: &Rt<Env>, cx: &Context) -> usize {
+    match env.var(sym::FILL_COLUMN, cx).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::Int(width)) if width > 0 => width as usize,
+        _ => 70,
+    }
+}
+
+#[defun]
+fn pp_to_string(
+    object: Object,
+    _pp_function: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> String {
+    let _settings = crate::print::load_print_settings(env, cx);
+    let mut string = pretty_print(object, line_width(env, cx));
+    string.push('\n');
+    string
+}
+
+#[defun]
+fn pp(
+    object: &Rto<Object>,
+    stream: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let text = pp_to_string(object.bind(cx), None, env, cx);
+    crate::print::write_output(&text, stream, env, cx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::{get_buffer_create, set_buffer};
+    use crate::core::{gc::RootSet, object::NIL};
+    use rune_core::macros::root;
+
+    fn pretty(code: &str, width: usize) -> String {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        let obj = crate::reader::read(code, cx).unwrap().0;
+        pretty_print(obj, width)
+    }
+
+    #[test]
+    fn test_pp() {
+        assert_eq!(pretty("(a b c)", 70), "(a b c)");
+        assert_eq!(pretty("(quote (a #'b))", 70), "'(a #'b)");
+        assert_eq!(pretty("(a . b)", 70), "(a . b)");
+        assert_eq!(pretty("(foo aaaa bbbb cccc)", 10), "(foo aaaa\n     bbbb\n     cccc)");
+        assert_eq!(
+            pretty("(let ((a 1) (b 2)) (message \"hi\") (+ a b))", 20),
+            "(let ((a 1) (b 2))\n  (message \"hi\")\n  (+ a b))"
+        );
+        assert_eq!(
+            pretty("(defun foo (x) \"doc\" (bar x))", 20),
+            "(defun foo (x)\n  \"doc\"\n  (bar x))"
+        );
+        assert_eq!(
+            pretty("(lambda (x) '(aaaa bbbb cccc))", 20),
+            "(lambda (x)\n  '(aaaa bbbb\n         cccc))"
+        );
+        assert_eq!(pretty("[aaaa bbbb cccc]", 10), "[aaaa\n bbbb\n cccc]");
+    }
+
+    #[test]
+    fn test_pp_to_stream() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let buffer = get_buffer_create(cx.add("test_pp_to_stream"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        root!(buffer, cx);
+        let obj = crate::reader::read("(a \"b\")", cx).unwrap().0;
+        root!(obj, cx);
+        pp(obj, Some(&*buffer), env, cx).unwrap();
+        let text = env.with_buffer(None, |b| b.text.to_string()).unwrap();
+        assert_eq!(text, "(a \"b\")\n");
+    }
+}
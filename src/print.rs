@@ -1,13 +1,276 @@
 //! Printing utilities.
-use crate::core::object::Object;
+//!
+//! The print functions send their text to a `printcharfun`, which defaults to
+//! `standard-output`. This can be `t` for standard out, a buffer to insert the
+//! text at its point, a function to call with each character, or a string
+//! output stream. A stream is a heap object that collects the printed text, so
+//! that `with-output-to-string` can build a string piecewise without a buffer.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, Object, ObjectType, StringStream, StringStreamInner, NIL},
+};
+use anyhow::{bail, Result};
+use rune_core::macros::{call, root};
 use rune_macros::defun;
+use std::fmt::Write as _;
+use std::io::Write as _;
 
 #[defun]
-fn error_message_string(obj: Object) -> String {
-    // TODO: implement
-    format!("Error: {obj}")
+pub(crate) fn error_message_string(obj: Object, env: &Rt<Env>, cx: &Context) -> Result<String> {
+    const PECULIAR: &str = "peculiar error";
+    let _settings = load_print_settings(env, cx);
+    let ObjectType::Cons(error) = obj.untag() else { return Ok(PECULIAR.into()) };
+    let ObjectType::Symbol(name) = error.car().untag() else { return Ok(PECULIAR.into()) };
+    // `error` takes its message from the data
+    let (mut message, mut data, file_error) = if name == sym::ERROR {
+        match error.cdr().untag() {
+            ObjectType::Cons(data) => (data.car(), data.cdr(), false),
+            _ => (NIL, NIL, false),
+        }
+    } else {
+        let conditions = crate::data::get(name, sym::ERROR_CONDITIONS, env, cx);
+        let mut file_error = false;
+        for condition in conditions.as_list()? {
+            file_error |= condition? == sym::FILE_ERROR;
+        }
+        (crate::data::get(name, sym::ERROR_MESSAGE, env, cx), error.cdr(), file_error)
+    };
+    // The first datum of a file error is its message
+    if file_error {
+        if let ObjectType::Cons(cons) = data.untag() {
+            (message, data) = (cons.car(), cons.cdr());
+        }
+    }
+    let mut result = match message.untag() {
+        ObjectType::String(message) => message.to_string(),
+        _ => PECULIAR.into(),
+    };
+    let princ = file_error || name == sym::END_OF_FILE || name == sym::USER_ERROR;
+    let mut separator = ": ";
+    while let ObjectType::Cons(cons) = data.untag() {
+        result += separator;
+        match cons.car().untag() {
+            ObjectType::String(string) if princ => write!(result, "{string}")?,
+            obj => write!(result, "{obj}")?,
+        }
+        separator = ", ";
+        data = cons.cdr();
+    }
+    Ok(result)
+}
+
+/// The printing variables carried over to the printer by
+/// [`load_print_settings`]. The previous settings are put back when this is
+/// dropped, so a binding of a printing variable doesn't outlive its `let`.
+#[must_use]
+pub(crate) struct PrintSettings {
+    previous_quoted: bool,
+}
+
+impl Drop for PrintSettings {
+    fn drop(&mut self) {
+        crate::core::cons::set_print_quoted(self.previous_quoted);
+    }
+}
+
+/// Carry the printing variables over to the printer. Objects are printed
+/// through `Display`, which can't see the environment, so this is called before
+/// printing on behalf of lisp, and the result is held until printing is done.
+pub(crate) fn load_print_settings(env: &Rt<Env>, cx: &Context) -> PrintSettings {
+    let quoted = env.var(sym::PRINT_QUOTED, cx).map_or(true, |x| x.bind(cx) != NIL);
+    PrintSettings { previous_quoted: crate::core::cons::set_print_quoted(quoted) }
 }
 
 defvar!(PRINT_LENGTH);
 defvar!(PRINT_LEVEL);
 defvar_bool!(PRINT_ESCAPE_NEWLINES, false);
+defvar_bool!(PRINT_QUOTED, true);
+defsym!(END_OF_FILE);
+defsym!(FILE_ERROR);
+defsym!(USER_ERROR);
+
+defvar!(STANDARD_OUTPUT, true);
+defsym!(STRING_OUTPUT_STREAM);
+
+/// Send `text` to `printcharfun`, or to `standard-output` if it is nil.
+pub(crate) fn write_output(
+    text: &str,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let output = match printcharfun.map(|x| x.bind(cx)) {
+        Some(output) if output != NIL => output,
+        _ => env.var(sym::STANDARD_OUTPUT, cx).map_or(sym::TRUE.into(), |x| x.bind(cx)),
+    };
+    if output == sym::TRUE {
+        let mut stdout = std::io::stdout();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()?;
+    } else if let ObjectType::StringStream(stream) = output.untag() {
+        stream.push_str(text, cx)?;
+    } else if let ObjectType::Buffer(buffer) = output.untag() {
+        let inserted = env.with_buffer_mut(Some(buffer), |b| {
+            b.text.insert(text);
+            b.modified = true;
+        });
+        if inserted.is_none() {
+            bail!("Selecting deleted buffer");
+        }
+    } else {
+        let func: Function = output.try_into()?;
+        root!(func, cx);
+        for chr in text.chars() {
+            call!(func, cx.add(i64::from(u32::from(chr))); env, cx)?;
+        }
+    }
+    Ok(())
+}
+
+/// The text `princ` prints for `object`. Strings are printed without quotes.
+fn princ_text(object: Object) -> String {
+    match object.untag() {
+        ObjectType::String(string) => string.to_string(),
+        obj => obj.to_string(),
+    }
+}
+
+#[defun]
+fn prin1<'ob>(
+    object: &Rto<Object>,
+    printcharfun: Option<&Rto<Object>>,
+    _overrides: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let settings = load_print_settings(env, cx);
+    let text = object.bind(cx).to_string();
+    drop(settings);
+    write_output(&text, printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+#[defun]
+fn princ<'ob>(
+    object: &Rto<Object>,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let settings = load_print_settings(env, cx);
+    let text = princ_text(object.bind(cx));
+    drop(settings);
+    write_output(&text, printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+#[defun]
+fn print<'ob>(
+    object: &Rto<Object>,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let settings = load_print_settings(env, cx);
+    let text = format!("\n{}\n", object.bind(cx));
+    drop(settings);
+    write_output(&text, printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+#[defun]
+fn terpri(
+    printcharfun: Option<&Rto<Object>>,
+    _ensure: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    write_output("\n", printcharfun, env, cx)?;
+    Ok(true)
+}
+
+#[defun]
+fn write_char(
+    character: char,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<char> {
+    write_output(character.encode_utf8(&mut [0; 4]), printcharfun, env, cx)?;
+    Ok(character)
+}
+
+/// Return a new string output stream. Text printed to it is collected until
+/// the stream is closed.
+#[defun]
+fn internal__make_string_output_stream(cx: &Context) -> Object {
+    cx.add(StringStreamInner::new())
+}
+
+/// Return the text printed to `stream` so far.
+#[defun]
+fn internal__string_output_stream_contents(stream: &StringStream) -> Result<String> {
+    match stream.contents() {
+        Some(text) => Ok(text.to_owned()),
+        None => bail!("String output stream is closed"),
+    }
+}
+
+/// Close `stream` and release its text. Closing a stream twice does nothing.
+#[defun]
+fn internal__close_string_output_stream(stream: &StringStream) {
+    stream.close();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::{get_buffer_create, set_buffer};
+    use crate::core::gc::RootSet;
+
+    fn as_stream(obj: Object) -> &StringStream {
+        obj.try_into().unwrap()
+    }
+
+    #[test]
+    fn test_print_to_stream() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let stream = internal__make_string_output_stream(cx);
+        root!(stream, cx);
+        let stream = &*stream;
+        let string = cx.add("a\"b");
+        root!(string, cx);
+        prin1(string, Some(stream), None, env, cx).unwrap();
+        princ(string, Some(stream), env, cx).unwrap();
+        write_char('λ', Some(stream), env, cx).unwrap();
+        terpri(Some(stream), None, env, cx).unwrap();
+        let contents = internal__string_output_stream_contents(as_stream(stream.bind(cx))).unwrap();
+        assert_eq!(contents, "\"a\\\"b\"a\"bλ\n");
+        // The text moves with the stream during a collection
+        cx.garbage_collect(true);
+        let contents = internal__string_output_stream_contents(as_stream(stream.bind(cx))).unwrap();
+        assert_eq!(contents, "\"a\\\"b\"a\"bλ\n");
+
+        // `standard-output` is used when there is no printcharfun
+        env.set_var(sym::STANDARD_OUTPUT, stream.bind(cx), cx).unwrap();
+        let list = crate::reader::read("(1 \"x\")", cx).unwrap().0;
+        root!(list, cx);
+        print(list, None, env, cx).unwrap();
+        let contents = internal__string_output_stream_contents(as_stream(stream.bind(cx))).unwrap();
+        assert!(contents.ends_with("\n(1 \"x\")\n"));
+        internal__close_string_output_stream(as_stream(stream.bind(cx)));
+        internal__close_string_output_stream(as_stream(stream.bind(cx)));
+        assert!(internal__string_output_stream_contents(as_stream(stream.bind(cx))).is_err());
+        assert!(princ(string, Some(stream), env, cx).is_err());
+
+        let buffer = get_buffer_create(cx.add("test_print_to_stream"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        root!(buffer, cx);
+        princ(string, Some(&*buffer), env, cx).unwrap();
+        let text = env.with_buffer(None, |b| b.text.to_string()).unwrap();
+        assert_eq!(text, "a\"b");
+    }
+}
@@ -0,0 +1,1007 @@
+//! Asynchronous subprocesses.
+//!
+//! A process is a record of the form `#s(process NAME ID BUFFER FILTER
+//! SENTINEL COMMAND STATUS CODING)`. The operating system process is kept in a
+//! registry keyed by ID. Threads read its output and wait for it to exit, and
+//! hand the results to the [event loop](crate::eventloop), which runs the
+//! filter and sentinel in the environment that created the process. Output is
+//! decoded with the process's coding system as it is read.
+//!
+//! When `delete-exited-processes` is non-nil, a process that has exited is
+//! deleted once its sentinel has run. Its final status and coding systems are
+//! kept in the STATUS and CODING slots, which are `nil` while it is known to
+//! the registry.
+use crate::coding::{Coding, Decoder};
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env, EnvId},
+    error::{Type, TypeError},
+    gc::{Context, Rt, Slot},
+    object::{
+        Function, HashTable, LispBuffer, LispHashTable, Number, Object, ObjectType, Record,
+        RecordBuilder, NIL,
+    },
+};
+use crate::eventloop::{self, push_event, Event};
+use crate::fns::slice_into_list;
+use anyhow::{bail, Context as _, Result};
+use rune_core::hashmap::HashMap;
+use rune_core::macros::{call, root};
+use rune_macros::defun;
+use std::io::{Read, Write};
+use std::process::{ChildStdin, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+const NAME: usize = 1;
+const ID: usize = 2;
+const BUFFER: usize = 3;
+const FILTER: usize = 4;
+const SENTINEL: usize = 5;
+const COMMAND: usize = 6;
+const STATUS: usize = 7;
+const CODING: usize = 8;
+const LEN: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Run,
+    Exit(i32),
+    Signal(i32),
+}
+
+impl Status {
+    fn from_exit(status: ExitStatus) -> Self {
+        #[cfg(unix)]
+        if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+            return Status::Signal(signal);
+        }
+        Status::Exit(status.code().unwrap_or(-1))
+    }
+
+    /// The status as `(exit . CODE)` or `(signal . SIGNAL)`, to keep in a
+    /// deleted process.
+    fn to_object<'ob>(self, cx: &'ob Context) -> Object<'ob> {
+        let (kind, code) = match self {
+            Status::Signal(signal) => (sym::SIGNAL, signal),
+            Status::Exit(code) => (sym::EXIT, code),
+            // Only a process that has exited is deleted
+            Status::Run => (sym::EXIT, -1),
+        };
+        Cons::new(kind, i64::from(code), cx).into()
+    }
+
+    fn from_object(obj: Object) -> Option<Self> {
+        let ObjectType::Cons(cons) = obj.untag() else { return None };
+        let code = i32::try_from(i64::try_from(cons.cdr()).ok()?).ok()?;
+        Some(match cons.car() == sym::SIGNAL {
+            true => Status::Signal(code),
+            false => Status::Exit(code),
+        })
+    }
+
+    /// The message passed to the sentinel.
+    fn message(self) -> String {
+        match self {
+            Status::Run => "run\n".to_owned(),
+            Status::Exit(0) => "finished\n".to_owned(),
+            Status::Exit(code) => format!("exited abnormally with code {code}\n"),
+            Status::Signal(signal) => match signal_name(signal) {
+                Some("kill") => "killed\n".to_owned(),
+                Some("int") => "interrupt\n".to_owned(),
+                Some("term") => "terminated\n".to_owned(),
+                Some("hup") => "hangup\n".to_owned(),
+                _ => format!("signal {signal}\n"),
+            },
+        }
+    }
+}
+
+/// The parts of a process that live outside the lisp heap.
+struct Handle {
+    pid: u32,
+    stdin: Option<ChildStdin>,
+    status: Status,
+    decoding: Coding,
+    encoding: Coding,
+}
+
+fn registry() -> &'static Mutex<HashMap<i64, Handle>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<i64, Handle>>> = OnceLock::new();
+    REGISTRY.get_or_init(Mutex::default)
+}
+
+fn status(id: i64) -> Status {
+    registry()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&id)
+        .map_or(Status::Exit(-1), |x| x.status)
+}
+
+#[derive(Debug)]
+pub(crate) enum ProcessEvent {
+    Output(i64, String),
+    Exit(i64),
+}
+
+#[derive(Copy, Clone)]
+pub(crate) struct Process<'ob>(&'ob Record);
+
+impl<'ob> TryFrom<Object<'ob>> for Process<'ob> {
+    type Error = TypeError;
+
+    fn try_from(obj: Object<'ob>) -> Result<Self, Self::Error> {
+        match obj.untag() {
+            ObjectType::Record(record) if is_process(record) => Ok(Process(record)),
+            _ => Err(TypeError::new(Type::Process, obj)),
+        }
+    }
+}
+
+impl<'ob> From<Process<'ob>> for Object<'ob> {
+    fn from(process: Process<'ob>) -> Self {
+        process.0.into()
+    }
+}
+
+pub(crate) fn is_process(record: &Record) -> bool {
+    record.len() == LEN && record[0].get() == sym::PROCESS
+}
+
+impl<'ob> Process<'ob> {
+    fn slot(self, idx: usize) -> Object<'ob> {
+        self.0[idx].get()
+    }
+
+    fn set_slot(self, idx: usize, value: Object) -> Result<()> {
+        self.0.try_mut()?[idx].set(value);
+        Ok(())
+    }
+
+    fn name(self) -> &'ob str {
+        self.slot(NAME).try_into().expect("process name should be a string")
+    }
+
+    pub(crate) fn id(self) -> i64 {
+        self.slot(ID).try_into().expect("process id should be an integer")
+    }
+
+    fn buffer(self) -> Option<&'ob LispBuffer> {
+        match self.slot(BUFFER).untag() {
+            ObjectType::Buffer(buffer) => Some(buffer),
+            _ => None,
+        }
+    }
+
+    fn status(self) -> Status {
+        Status::from_object(self.slot(STATUS)).unwrap_or_else(|| status(self.id()))
+    }
+}
+
+/// The table of all processes, keyed by id.
+fn process_table<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<&'ob LispHashTable> {
+    let table = match env.vars.get(sym::INTERNAL__PROCESSES).map(|x| x.bind(cx)) {
+        Some(table) if !table.is_nil() => table,
+        _ => {
+            let table = cx.add(HashTable::default());
+            env.set_default(sym::INTERNAL__PROCESSES, table)?;
+            table
+        }
+    };
+    match table.untag() {
+        ObjectType::HashTable(table) => Ok(table),
+        _ => bail!("`internal--processes' is not a hash table"),
+    }
+}
+
+fn processes<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<Vec<Process<'ob>>> {
+    let table = process_table(env, cx)?;
+    let processes = (0..table.len()).filter_map(|i| table.get_index(i));
+    Ok(processes.filter_map(|(_, process)| process.try_into().ok()).collect())
+}
+
+/// Find the process designated by `obj`: a process, the name of one, or a
+/// buffer it is associated with. `nil` means the current buffer.
+fn find_process<'ob>(
+    obj: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Process<'ob>> {
+    let found = match obj.untag() {
+        ObjectType::Record(_) => return Ok(obj.try_into()?),
+        ObjectType::String(name) => processes(env, cx)?.into_iter().find(|x| x.name() == name),
+        ObjectType::Buffer(buffer) => {
+            processes(env, cx)?.into_iter().find(|x| x.buffer() == Some(buffer))
+        }
+        ObjectType::NIL => match env.current_buffer.as_ref() {
+            Some(current) => {
+                let current = current.lisp_buffer(cx);
+                processes(env, cx)?.into_iter().find(|x| x.buffer() == Some(current))
+            }
+            None => None,
+        },
+        _ => bail!(TypeError::new(Type::Process, obj)),
+    };
+    match found {
+        Some(process) => Ok(process),
+        None => bail!("Process {obj} does not exist"),
+    }
+}
+
+fn spawn_output_reader(
+    id: i64,
+    owner: EnvId,
+    mut pipe: impl Read + Send + 'static,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        let mut decoder = Decoder::default();
+        loop {
+            match pipe.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    // The coding system can be changed while the process runs
+                    let coding = registry()
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .get(&id)
+                        .map(|x| x.decoding);
+                    let text = decoder.decode(&buffer[..n], coding.unwrap_or_default());
+                    if !text.is_empty() {
+                        push_event(owner, Event::Process(ProcessEvent::Output(id, text)));
+                    }
+                }
+            }
+        }
+        let rest = decoder.finish();
+        if !rest.is_empty() {
+            push_event(owner, Event::Process(ProcessEvent::Output(id, rest)));
+        }
+    })
+}
+
+/// Start `command` as a process. `functions` are its filter and sentinel.
+fn create_process<'ob>(
+    name: &str,
+    buffer: Object<'ob>,
+    command: &[&str],
+    coding: (Coding, Coding),
+    functions: (Object<'ob>, Object<'ob>),
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    static NEXT_ID: AtomicI64 = AtomicI64::new(1);
+    let Some((program, args)) = command.split_first() else { bail!("No program specified") };
+    let buffer = match buffer.untag() {
+        ObjectType::NIL => NIL,
+        _ => crate::buffer::get_buffer_create(buffer, None, cx)?,
+    };
+    // Names are made unique by adding <N>
+    let taken: Vec<&str> = processes(env, cx)?.iter().map(|x| x.name()).collect();
+    let mut unique = name.to_owned();
+    for i in 1.. {
+        if !taken.contains(&unique.as_str()) {
+            break;
+        }
+        unique = format!("{name}<{i}>");
+    }
+
+    let mut child = crate::callproc::make_command(program, env, cx)?
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Creating process {unique}"))?;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let (decoding, encoding) = coding;
+    let handle = Handle {
+        pid: child.id(),
+        stdin: child.stdin.take(),
+        status: Status::Run,
+        decoding,
+        encoding,
+    };
+    registry().lock().unwrap_or_else(PoisonError::into_inner).insert(id, handle);
+    let owner = env.id;
+    let readers = [
+        spawn_output_reader(id, owner, child.stdout.take().unwrap()),
+        spawn_output_reader(id, owner, child.stderr.take().unwrap()),
+    ];
+    std::thread::spawn(move || {
+        // All output is delivered before the process exits
+        for reader in readers {
+            _ = reader.join();
+        }
+        let status = child.wait().map_or(Status::Exit(-1), Status::from_exit);
+        if let Some(handle) = registry().lock().unwrap_or_else(PoisonError::into_inner).get_mut(&id)
+        {
+            handle.status = status;
+            handle.stdin = None;
+        }
+        push_event(owner, Event::Process(ProcessEvent::Exit(id)));
+    });
+
+    let command: Vec<Object> = command.iter().map(|x| cx.add(*x)).collect();
+    let (filter, sentinel) = functions;
+    let mut record = cx.vec_with_capacity(LEN);
+    record.push(sym::PROCESS.into());
+    record.push(cx.add(unique));
+    record.push(cx.add(id));
+    record.push(buffer);
+    record.push(filter);
+    record.push(sentinel);
+    record.push(slice_into_list(&command, None, cx));
+    record.push(NIL);
+    record.push(NIL);
+    let process: Object = cx.add(RecordBuilder(record)).into();
+    process_table(env, cx)?.insert(cx.add(id), process);
+    Ok(process)
+}
+
+/// Insert process output at the end of `buffer`. Point follows the text if it
+/// was at the end.
+fn insert_process_output(buffer: &LispBuffer, text: &str, env: &mut Rt<Env>) -> Result<()> {
+    let result = env.with_buffer_mut(Some(buffer), |b| {
+        let end = b.text.len_chars();
+        let point = b.text.cursor().chars();
+        b.text.set_cursor(end);
+        b.text.insert(text);
+        if point != end {
+            b.text.set_cursor(point);
+        }
+    });
+    match result {
+        Some(()) => Ok(()),
+        None => bail!("Selecting deleted buffer"),
+    }
+}
+
+/// Forget a process that has exited, keeping its final status and coding
+/// systems in the record.
+fn reap(process: Process, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let handle = registry().lock().unwrap_or_else(PoisonError::into_inner).remove(&process.id());
+    if let Some(handle) = handle {
+        process.set_slot(STATUS, handle.status.to_object(cx))?;
+        let coding = Cons::new(handle.decoding.symbol(cx), handle.encoding.symbol(cx), cx);
+        process.set_slot(CODING, coding.into())?;
+    }
+    process_table(env, cx)?.swap_remove(cx.add(process.id()));
+    Ok(())
+}
+
+/// Run the filter or sentinel of a process for an event.
+pub(crate) fn handle_event(event: ProcessEvent, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let id = match &event {
+        ProcessEvent::Output(id, _) | ProcessEvent::Exit(id) => *id,
+    };
+    // The process was deleted before the event was delivered
+    let Some(process) = process_table(env, cx)?.get(cx.add(id)) else { return Ok(()) };
+    root!(process, cx);
+    let exited = matches!(event, ProcessEvent::Exit(_));
+    let result = notify(process, event, env, cx);
+    let delete = env.vars.get(sym::DELETE_EXITED_PROCESSES).is_some_and(|x| !x.bind(cx).is_nil());
+    if exited {
+        crate::jsonrpc::disconnect(id, env, cx)?;
+    }
+    if exited && delete {
+        reap(process.bind(cx).try_into()?, env, cx)?;
+    }
+    result
+}
+
+fn notify(
+    process: &Rt<Slot<Object>>,
+    event: ProcessEvent,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let process: Process = process.bind(cx).try_into()?;
+    let (function, text) = match &event {
+        ProcessEvent::Output(_, text) => (process.slot(FILTER), text.clone()),
+        ProcessEvent::Exit(_) => (process.slot(SENTINEL), process.status().message()),
+    };
+    if function.is_nil() {
+        // The default filter and sentinel insert into the process buffer
+        let Some(buffer) = process.buffer() else { return Ok(()) };
+        let text = match event {
+            ProcessEvent::Output(..) => text,
+            ProcessEvent::Exit(_) => format!("\nProcess {} {text}", process.name()),
+        };
+        return insert_process_output(buffer, &text, env);
+    }
+    let process: Object = process.into();
+    let text = cx.add(text);
+    let func: Function = function.try_into()?;
+    root!(func, cx);
+    call!(func, process, text; env, cx)?;
+    Ok(())
+}
+
+/// Signal names without the SIG prefix, in lower case.
+#[cfg(unix)]
+const SIGNALS: &[(&str, i32)] = &[
+    ("hup", libc::SIGHUP),
+    ("int", libc::SIGINT),
+    ("quit", libc::SIGQUIT),
+    ("ill", libc::SIGILL),
+    ("trap", libc::SIGTRAP),
+    ("abrt", libc::SIGABRT),
+    ("kill", libc::SIGKILL),
+    ("usr1", libc::SIGUSR1),
+    ("segv", libc::SIGSEGV),
+    ("usr2", libc::SIGUSR2),
+    ("pipe", libc::SIGPIPE),
+    ("alrm", libc::SIGALRM),
+    ("term", libc::SIGTERM),
+    ("chld", libc::SIGCHLD),
+    ("cont", libc::SIGCONT),
+    ("stop", libc::SIGSTOP),
+    ("tstp", libc::SIGTSTP),
+    ("winch", libc::SIGWINCH),
+];
+
+#[cfg(not(unix))]
+const SIGNALS: &[(&str, i32)] = &[];
+
+fn signal_name(signal: i32) -> Option<&'static str> {
+    SIGNALS.iter().find(|(_, x)| *x == signal).map(|(name, _)| *name)
+}
+
+fn signal_number(signal: &str) -> Option<i32> {
+    let name = signal
+        .strip_prefix("SIG")
+        .or_else(|| signal.strip_prefix("sig"))
+        .unwrap_or(signal);
+    let name = name.to_ascii_lowercase();
+    SIGNALS.iter().find(|(x, _)| *x == name).map(|(_, signal)| *signal)
+}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: i32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else { return false };
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(pid, signal) == 0 }
+}
+
+#[cfg(not(unix))]
+fn send_signal(_pid: u32, _signal: i32) -> bool {
+    false
+}
+
+fn signal(process: Process, signal: i32) -> Result<()> {
+    let registry = registry().lock().unwrap_or_else(PoisonError::into_inner);
+    match registry.get(&process.id()) {
+        Some(handle) if handle.status == Status::Run => {
+            if !send_signal(handle.pid, signal) {
+                bail!("Failed to signal process {}", process.name());
+            }
+            Ok(())
+        }
+        _ => bail!("Process {} is not running", process.name()),
+    }
+}
+
+/// Kill every running process. Called when rune exits.
+pub(crate) fn kill_all() {
+    let registry = registry().lock().unwrap_or_else(PoisonError::into_inner);
+    let kill = signal_number("kill").unwrap_or(9);
+    for handle in registry.values().filter(|x| x.status == Status::Run) {
+        send_signal(handle.pid, kill);
+    }
+}
+
+#[defun]
+fn make_process<'ob>(
+    args: &[Object<'ob>],
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let mut name = None;
+    let mut buffer = NIL;
+    let mut command = NIL;
+    let mut filter = NIL;
+    let mut sentinel = NIL;
+    let mut coding = NIL;
+    for pair in args.chunks(2) {
+        let &[key, value] = pair else { bail!("Missing value for keyword {}", pair[0]) };
+        match key.untag() {
+            ObjectType::Symbol(sym::KW_NAME) => name = Some(<&str>::try_from(value)?),
+            ObjectType::Symbol(sym::KW_BUFFER) => buffer = value,
+            ObjectType::Symbol(sym::KW_COMMAND) => command = value,
+            ObjectType::Symbol(sym::KW_FILTER) => filter = value,
+            ObjectType::Symbol(sym::KW_SENTINEL) => sentinel = value,
+            ObjectType::Symbol(sym::KW_CODING) => coding = value,
+            // Other keywords like :noquery don't apply
+            _ => {}
+        }
+    }
+    let Some(name) = name else { bail!(":name value not a string") };
+    let command: Vec<&str> =
+        command.as_list()?.map(|x| Ok(x?.try_into()?)).collect::<Result<_>>()?;
+    // A cons is (DECODING . ENCODING), and a symbol is used for both
+    let coding = match coding.untag() {
+        ObjectType::Cons(cons) => {
+            (Coding::from_object(cons.car())?, Coding::from_object(cons.cdr())?)
+        }
+        _ => (Coding::from_object(coding)?, Coding::from_object(coding)?),
+    };
+    create_process(name, buffer, &command, coding, (filter, sentinel), env, cx)
+}
+
+#[defun]
+fn start_process<'ob>(
+    name: &str,
+    buffer: Object<'ob>,
+    program: &str,
+    program_args: &[&str],
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let mut command = vec![program];
+    command.extend(program_args);
+    create_process(name, buffer, &command, Default::default(), (NIL, NIL), env, cx)
+}
+
+#[defun]
+fn processp(object: Object) -> bool {
+    matches!(object.untag(), ObjectType::Record(x) if is_process(x))
+}
+
+#[defun]
+fn get_process<'ob>(name: &str, env: &mut Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let process = processes(env, cx)?.into_iter().find(|x| x.name() == name);
+    Ok(process.map_or(NIL, Into::into))
+}
+
+#[defun]
+fn get_buffer_process<'ob>(
+    buffer: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let ObjectType::Buffer(buffer) = crate::buffer::get_buffer(buffer, cx)?.untag() else {
+        return Ok(NIL);
+    };
+    let process = processes(env, cx)?.into_iter().find(|x| x.buffer() == Some(buffer));
+    Ok(process.map_or(NIL, Into::into))
+}
+
+#[defun]
+fn process_list<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let processes: Vec<Object> = processes(env, cx)?.into_iter().map(Into::into).collect();
+    Ok(slice_into_list(&processes, None, cx))
+}
+
+#[defun]
+fn process_name(process: Process) -> &str {
+    process.name()
+}
+
+#[defun]
+fn process_id(process: Process) -> Option<i64> {
+    registry()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&process.id())
+        .map(|x| i64::from(x.pid))
+}
+
+#[defun]
+fn process_command(process: Process) -> Object {
+    process.slot(COMMAND)
+}
+
+#[defun]
+fn process_buffer(process: Process) -> Object {
+    process.slot(BUFFER)
+}
+
+#[defun]
+fn set_process_buffer<'ob>(process: Process, buffer: Object<'ob>) -> Result<Object<'ob>> {
+    if !matches!(buffer.untag(), ObjectType::NIL | ObjectType::Buffer(_)) {
+        bail!(TypeError::new(Type::Buffer, buffer));
+    }
+    process.set_slot(BUFFER, buffer)?;
+    Ok(buffer)
+}
+
+#[defun]
+fn process_status<'ob>(
+    process: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    // A name that doesn't match a process is not an error
+    let Ok(process) = find_process(process, env, cx) else { return Ok(NIL) };
+    Ok(match process.status() {
+        Status::Run => sym::RUN.into(),
+        Status::Exit(_) => sym::EXIT.into(),
+        Status::Signal(_) => sym::SIGNAL.into(),
+    })
+}
+
+#[defun]
+fn process_exit_status(process: Process) -> i64 {
+    match process.status() {
+        Status::Run => 0,
+        Status::Exit(code) | Status::Signal(code) => code.into(),
+    }
+}
+
+#[defun]
+fn process_live_p(process: Object) -> bool {
+    match Process::try_from(process) {
+        Ok(process) => process.status() == Status::Run,
+        Err(_) => false,
+    }
+}
+
+#[defun]
+fn process_filter(process: Process) -> Object {
+    process.slot(FILTER)
+}
+
+#[defun]
+pub(crate) fn set_process_filter<'ob>(
+    process: Process,
+    filter: Object<'ob>,
+) -> Result<Object<'ob>> {
+    process.set_slot(FILTER, filter)?;
+    Ok(filter)
+}
+
+#[defun]
+fn process_sentinel(process: Process) -> Object {
+    process.slot(SENTINEL)
+}
+
+#[defun]
+fn set_process_sentinel<'ob>(process: Process, sentinel: Object<'ob>) -> Result<Object<'ob>> {
+    process.set_slot(SENTINEL, sentinel)?;
+    Ok(sentinel)
+}
+
+#[defun]
+pub(crate) fn process_send_string(
+    process: Object,
+    string: &str,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let process = find_process(process, env, cx)?;
+    let mut registry = registry().lock().unwrap_or_else(PoisonError::into_inner);
+    let handle = registry.get_mut(&process.id());
+    let Some(Handle { stdin: Some(stdin), encoding, .. }) = handle else {
+        bail!("Process {} not running", process.name())
+    };
+    stdin.write_all(&encoding.encode(string)?)?;
+    stdin.flush()?;
+    Ok(())
+}
+
+/// Set the coding systems used to decode the output of `process` and
+/// encode its input. `nil` means UTF-8.
+#[defun]
+fn set_process_coding_system(
+    process: Object,
+    decoding: Option<Object>,
+    encoding: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let process = find_process(process, env, cx)?;
+    let decoding_coding = Coding::from_object(decoding.unwrap_or_default())?;
+    let encoding_coding = Coding::from_object(encoding.unwrap_or_default())?;
+    let mut registry = registry().lock().unwrap_or_else(PoisonError::into_inner);
+    match registry.get_mut(&process.id()) {
+        Some(handle) => {
+            handle.decoding = decoding_coding;
+            handle.encoding = encoding_coding;
+        }
+        None if !process.slot(CODING).is_nil() => {
+            let coding = Cons::new(decoding_coding.symbol(cx), encoding_coding.symbol(cx), cx);
+            process.set_slot(CODING, coding.into())?;
+        }
+        None => bail!("Process {} does not exist", process.name()),
+    }
+    Ok(())
+}
+
+/// Return the coding systems of `process` as `(DECODING . ENCODING)`.
+#[defun]
+fn process_coding_system<'ob>(
+    process: Object,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let process = find_process(process, env, cx)?;
+    let coding = process.slot(CODING);
+    if !coding.is_nil() {
+        return Ok(coding);
+    }
+    let registry = registry().lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(handle) = registry.get(&process.id()) else {
+        bail!("Process {} does not exist", process.name())
+    };
+    let (decoding, encoding) = (handle.decoding.symbol(cx), handle.encoding.symbol(cx));
+    Ok(Cons::new(decoding, encoding, cx).into())
+}
+
+#[defun]
+fn process_send_eof<'ob>(
+    process: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let process = find_process(process, env, cx)?;
+    if let Some(handle) =
+        registry().lock().unwrap_or_else(PoisonError::into_inner).get_mut(&process.id())
+    {
+        // Closing stdin sends EOF
+        handle.stdin = None;
+    }
+    Ok(process.into())
+}
+
+#[defun]
+fn signal_process(
+    process: Object,
+    sigcode: Object,
+    _remote: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<i64> {
+    let signal = match sigcode.untag() {
+        ObjectType::Int(signal) => i32::try_from(signal)?,
+        ObjectType::Symbol(name) => match signal_number(name.name()) {
+            Some(signal) => signal,
+            None => bail!("Undefined signal name {name}"),
+        },
+        _ => bail!(TypeError::new(Type::Int, sigcode)),
+    };
+    let pid = match process.untag() {
+        ObjectType::Int(pid) => u32::try_from(pid)?,
+        _ => {
+            let process = find_process(process, env, cx)?;
+            match registry().lock().unwrap_or_else(PoisonError::into_inner).get(&process.id()) {
+                Some(handle) => handle.pid,
+                None => return Ok(-1),
+            }
+        }
+    };
+    Ok(if send_signal(pid, signal) { 0 } else { -1 })
+}
+
+#[defun]
+fn interrupt_process<'ob>(
+    process: Object<'ob>,
+    _current_group: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let process = find_process(process, env, cx)?;
+    signal(process, signal_number("int").unwrap_or(2))?;
+    Ok(process.into())
+}
+
+#[defun]
+fn kill_process<'ob>(
+    process: Object<'ob>,
+    _current_group: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let process = find_process(process, env, cx)?;
+    signal(process, signal_number("kill").unwrap_or(9))?;
+    Ok(process.into())
+}
+
+#[defun]
+fn delete_process(process: Object, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let process = find_process(process, env, cx)?;
+    if let Some(handle) =
+        registry().lock().unwrap_or_else(PoisonError::into_inner).remove(&process.id())
+    {
+        if handle.status == Status::Run {
+            send_signal(handle.pid, signal_number("kill").unwrap_or(9));
+        }
+    }
+    crate::jsonrpc::disconnect(process.id(), env, cx)?;
+    process_table(env, cx)?.swap_remove(cx.add(process.id()));
+    Ok(())
+}
+
+#[defun]
+fn accept_process_output(
+    process: Option<Object>,
+    seconds: Option<Number>,
+    millisec: Option<i64>,
+    _just_this_one: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    let id = match process {
+        Some(process) => Some(find_process(process, env, cx)?.id()),
+        None => None,
+    };
+    let deadline = match (seconds, millisec) {
+        (Some(seconds), _) => eventloop::deadline(eventloop::timeout_seconds(seconds, millisec)),
+        (None, Some(millisec)) => eventloop::deadline(millisec as f64 / 1000.0),
+        (None, None) => None,
+    };
+    loop {
+        if eventloop::dispatch_events(env, cx)? {
+            return Ok(true);
+        }
+        // Don't wait forever for output that can't come
+        let running = match id {
+            Some(id) => status(id) == Status::Run,
+            None => processes(env, cx)?.iter().any(|x| x.status() == Status::Run),
+        };
+        if !running && deadline.is_none() {
+            return Ok(false);
+        }
+        if !eventloop::wait_for_event(deadline, env.id) {
+            return Ok(false);
+        }
+    }
+}
+
+defsym!(PROCESS);
+defsym!(INTERNAL__PROCESSES);
+defsym!(KW_NAME);
+defsym!(KW_BUFFER);
+defsym!(KW_COMMAND);
+defsym!(KW_FILTER);
+defsym!(KW_SENTINEL);
+defsym!(KW_CODING);
+defsym!(RUN);
+defsym!(EXIT);
+defvar!(DELETE_EXITED_PROCESSES, true);
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::{list, rebind};
+
+    /// Wait for `process` to exit, delivering its output.
+    fn wait(process: Object, env: &mut Rt<Env>, cx: &mut Context) {
+        let process: Process = process.try_into().unwrap();
+        let id = process.id();
+        for _ in 0..500 {
+            accept_process_output(None, None, Some(10), None, env, cx).unwrap();
+            if status(id) != Status::Run {
+                // deliver the exit event
+                crate::eventloop::dispatch_events(env, cx).unwrap();
+                return;
+            }
+        }
+        panic!("process did not exit");
+    }
+
+    fn init(env: &mut Rt<Env>, cx: &Context) {
+        let exec_path = list!["/bin", "/usr/bin"; cx];
+        env.set_default(sym::EXEC_PATH, exec_path).unwrap();
+    }
+
+    #[test]
+    fn test_process_buffer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        init(env, cx);
+        env.set_default(sym::DELETE_EXITED_PROCESSES, sym::TRUE.into()).unwrap();
+        // Looking up a process doesn't create the buffer
+        let missing = cx.add("process-test-missing");
+        assert_eq!(get_buffer_process(missing, env, cx).unwrap(), NIL);
+        assert_eq!(crate::buffer::get_buffer(missing, cx).unwrap(), NIL);
+        let buffer = cx.add("process-test");
+        let args = ["-c", "printf hello; exit 3"];
+        let process = start_process("test", buffer, "sh", &args, env, cx).unwrap();
+        root!(process, cx);
+        assert!(processp(process.bind(cx)));
+        assert!(process_live_p(process.bind(cx)));
+        assert_eq!(get_buffer_process(buffer, env, cx).unwrap(), process.bind(cx));
+        wait(process.bind(cx), env, cx);
+        // The exited process was deleted but keeps its status
+        let process = process.bind(cx);
+        assert_eq!(get_process("test", env, cx).unwrap(), NIL);
+        assert_eq!(get_buffer_process(buffer, env, cx).unwrap(), NIL);
+        assert!(!process_live_p(process));
+        assert_eq!(process_status(process, env, cx).unwrap(), sym::EXIT);
+        assert_eq!(process_exit_status(process.try_into().unwrap()), 3);
+        let buffer = crate::buffer::get_buffer(buffer, cx).unwrap();
+        let ObjectType::Buffer(buffer) = buffer.untag() else { unreachable!() };
+        let text = env.with_buffer(Some(buffer), |b| b.text.to_string()).unwrap();
+        assert_eq!(text, "hello\nProcess test exited abnormally with code 3\n");
+        delete_process(process, env, cx).unwrap();
+    }
+
+    #[test]
+    fn test_process_coding() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        init(env, cx);
+        env.set_default(sym::DELETE_EXITED_PROCESSES, sym::TRUE.into()).unwrap();
+        let latin1 = crate::core::env::intern("latin-1", cx);
+        let args = [
+            sym::KW_NAME.into(),
+            cx.add("coding"),
+            sym::KW_BUFFER.into(),
+            cx.add("process-coding-test"),
+            sym::KW_COMMAND.into(),
+            list!["sh", "-c", "printf '\\351'; cat"; cx],
+            sym::KW_CODING.into(),
+            latin1.into(),
+        ];
+        let process = make_process(&args, env, cx).unwrap();
+        root!(process, cx);
+        let coding = process_coding_system(process.bind(cx), env, cx).unwrap();
+        assert_eq!(coding.to_string(), "(iso-latin-1 . iso-latin-1)");
+        // Latin-1 can't encode characters past 255
+        assert!(process_send_string(process.bind(cx), "\u{20ac}", env, cx).is_err());
+        process_send_string(process.bind(cx), "\u{e9}", env, cx).unwrap();
+        process_send_eof(process.bind(cx), env, cx).unwrap();
+        wait(process.bind(cx), env, cx);
+        let buffer = crate::buffer::get_buffer_create(cx.add("process-coding-test"), None, cx);
+        let ObjectType::Buffer(buffer) = buffer.unwrap().untag() else { unreachable!() };
+        let text = env.with_buffer(Some(buffer), |b| b.text.to_string()).unwrap();
+        assert_eq!(text, "\u{e9}\u{e9}\nProcess coding finished\n");
+
+        // A deleted process keeps its coding systems
+        let coding = process_coding_system(process.bind(cx), env, cx).unwrap();
+        assert_eq!(coding.to_string(), "(iso-latin-1 . iso-latin-1)");
+        set_process_coding_system(process.bind(cx), None, None, env, cx).unwrap();
+        let coding = process_coding_system(process.bind(cx), env, cx).unwrap();
+        assert_eq!(coding.to_string(), "(utf-8 . utf-8)");
+    }
+
+    #[test]
+    fn test_process_filter_and_signal() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        init(env, cx);
+        let code = "(progn (setq process-test-output \"\")
+                      (lambda (proc string)
+                        (setq process-test-output (concat process-test-output string))))";
+        let filter = crate::reader::read(code, cx).unwrap().0;
+        root!(filter, cx);
+        let filter = rebind!(crate::interpreter::eval(filter, None, env, cx).unwrap());
+        let command = list!["cat"; cx];
+        let args = [
+            sym::KW_NAME.into(),
+            cx.add("cat"),
+            sym::KW_COMMAND.into(),
+            command,
+            sym::KW_FILTER.into(),
+            filter,
+        ];
+        let process = make_process(&args, env, cx).unwrap();
+        root!(process, cx);
+        process_send_string(process.bind(cx), "ping", env, cx).unwrap();
+        process_send_eof(process.bind(cx), env, cx).unwrap();
+        wait(process.bind(cx), env, cx);
+        let output = crate::reader::read("process-test-output", cx).unwrap().0;
+        root!(output, cx);
+        let output = rebind!(crate::interpreter::eval(output, None, env, cx).unwrap());
+        assert_eq!(output, cx.add("ping"));
+
+        let process = start_process("sleep", NIL, "sleep", &["10"], env, cx).unwrap();
+        root!(process, cx);
+        let sigterm = crate::core::env::intern("SIGTERM", cx);
+        assert_eq!(signal_process(process.bind(cx), sigterm.into(), None, env, cx).unwrap(), 0);
+        wait(process.bind(cx), env, cx);
+        assert_eq!(process_status(process.bind(cx), env, cx).unwrap(), sym::SIGNAL);
+        assert!(kill_process(process.bind(cx), None, env, cx).is_err());
+        assert_eq!(signal_number("SIGKILL"), signal_number("kill"));
+        assert_eq!(signal_number("bogus"), None);
+    }
+}
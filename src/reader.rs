@@ -2,12 +2,18 @@
 use crate::core::{
     env::{intern, sym},
     gc::Context,
-    object::{Object, Symbol},
+    object::{
+        parse_float, HashTable, HashTableTest, IntoObject, Object, ObjectType, RecordBuilder,
+        Symbol, NIL,
+    },
 };
 use crate::fns;
+use rune_core::hashmap::HashMap;
 use rune_core::macros::list;
+use std::borrow::Cow;
 use std::fmt::Display;
 use std::str;
+use std::sync::{OnceLock, RwLock};
 use std::{fmt, iter::Peekable, str::CharIndices};
 
 type Result<T> = std::result::Result<T, Error>;
@@ -20,12 +26,16 @@ pub(crate) enum Error {
     MissingStringDel(usize),
     MissingQuotedItem(usize),
     ExtraItemInCdr(usize),
+    UnexpectedDot(usize),
     ExtraCloseParen(usize),
     ExtraCloseBracket(usize),
     UnexpectedChar(char, usize),
     UnknownMacroCharacter(char, usize),
     ParseInt(u8, usize),
     MalformedUnicdoe(usize),
+    InvalidRecord(usize),
+    InvalidSharpSyntax(usize),
+    InvalidByteCode(usize),
     EmptyStream,
 }
 
@@ -39,8 +49,12 @@ impl Display for Error {
             Error::ExtraCloseBracket(i) => write!(f, "Extra Closing brace: at {i}"),
             Error::UnexpectedChar(chr, i) => write!(f, "Unexpected character {chr}: at {i}"),
             Error::MalformedUnicdoe(i) => write!(f, "Malformed unicode: at {i}"),
+            Error::InvalidRecord(i) => write!(f, "Invalid record syntax: at {i}"),
+            Error::InvalidSharpSyntax(i) => write!(f, "Invalid # syntax: at {i}"),
+            Error::InvalidByteCode(i) => write!(f, "Invalid byte-code object: at {i}"),
             Error::EmptyStream => write!(f, "Empty Stream"),
             Error::ExtraItemInCdr(i) => write!(f, "Extra item in cdr: at {i}"),
+            Error::UnexpectedDot(i) => write!(f, "Dot without a preceding list item: at {i}"),
             Error::MissingQuotedItem(i) => write!(f, "Missing element after quote: at {i}"),
             Error::ParseInt(radix, i) => {
                 write!(f, "invalid character for radix {radix}: at {i}")
@@ -55,7 +69,7 @@ impl Display for Error {
 impl std::error::Error for Error {}
 
 impl Error {
-    const fn position(&self) -> usize {
+    pub(crate) const fn position(&self) -> usize {
         match self {
             Error::MissingQuotedItem(x)
             | Error::MissingCloseParen(x)
@@ -64,8 +78,12 @@ impl Error {
             | Error::ExtraCloseParen(x)
             | Error::ExtraCloseBracket(x)
             | Error::ExtraItemInCdr(x)
+            | Error::UnexpectedDot(x)
             | Error::UnexpectedChar(_, x)
             | Error::MalformedUnicdoe(x)
+            | Error::InvalidRecord(x)
+            | Error::InvalidSharpSyntax(x)
+            | Error::InvalidByteCode(x)
             | Error::ParseInt(_, x)
             | Error::UnknownMacroCharacter(_, x) => *x,
             Error::EmptyStream => 0,
@@ -79,7 +97,11 @@ impl Error {
             | Error::MissingStringDel(i)
             | Error::UnexpectedChar(_, i)
             | Error::MalformedUnicdoe(i)
+            | Error::InvalidRecord(i)
+            | Error::InvalidSharpSyntax(i)
+            | Error::InvalidByteCode(i)
             | Error::ExtraItemInCdr(i)
+            | Error::UnexpectedDot(i)
             | Error::ExtraCloseParen(i)
             | Error::ExtraCloseBracket(i)
             | Error::MissingQuotedItem(i)
@@ -89,11 +111,30 @@ impl Error {
         }
     }
 
+    /// Whether the input ended before the object was complete, so that more
+    /// input could make it readable.
+    pub(crate) const fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            Error::MissingCloseParen(_)
+                | Error::MissingCloseBracket(_)
+                | Error::MissingStringDel(_)
+                | Error::MissingQuotedItem(_)
+                | Error::EmptyStream
+        )
+    }
+
     pub(crate) fn update_pos(&mut self, offset: usize) {
         if let Some(pos) = self.mut_pos() {
             *pos += offset;
         }
     }
+
+    pub(crate) fn set_pos(&mut self, new_pos: usize) {
+        if let Some(pos) = self.mut_pos() {
+            *pos = new_pos;
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Copy, Clone)]
@@ -107,7 +148,7 @@ enum Token<'a> {
     Unquote(usize),
     Splice(usize),
     Sharp(usize),
-    QuestionMark(usize, char),
+    QuestionMark(usize, u32),
     Ident(&'a str),
     String(&'a str),
     Error(Error),
@@ -125,7 +166,10 @@ impl<'a> Display for Token<'a> {
             Token::Unquote(_) => write!(f, ","),
             Token::Splice(_) => write!(f, ",@"),
             Token::Sharp(_) => write!(f, "#"),
-            Token::QuestionMark(_, chr) => write!(f, "?{chr}"),
+            Token::QuestionMark(_, code) => match char::from_u32(*code) {
+                Some(chr) => write!(f, "?{chr}"),
+                None => write!(f, "?{code}"),
+            },
             Token::Ident(x) => write!(f, "{x}"),
             Token::String(x) => write!(f, "\"{x}\""),
             Token::Error(_) => write!(f, "error"),
@@ -133,6 +177,34 @@ impl<'a> Display for Token<'a> {
     }
 }
 
+// The bits that modifier keys add to a character code
+const ALT_MODIFIER: u32 = 1 << 22;
+const SUPER_MODIFIER: u32 = 1 << 23;
+const HYPER_MODIFIER: u32 = 1 << 24;
+const SHIFT_MODIFIER: u32 = 1 << 25;
+const CTRL_MODIFIER: u32 = 1 << 26;
+const META_MODIFIER: u32 = 1 << 27;
+const MODIFIER_MASK: u32 =
+    ALT_MODIFIER | SUPER_MODIFIER | HYPER_MODIFIER | SHIFT_MODIFIER | CTRL_MODIFIER | META_MODIFIER;
+
+/// Apply the control modifier to `code`. Letters and the characters from `@`
+/// to `_` become ASCII control characters, and `?` becomes DEL. Other
+/// characters get the control bit instead.
+const fn control_char(code: u32) -> u32 {
+    let base = code & !MODIFIER_MASK;
+    if base == '?' as u32 {
+        0x7F | (code & MODIFIER_MASK)
+    } else if base >= 0x80 {
+        code | CTRL_MODIFIER
+    } else if ((base & 0x5F) >= 'A' as u32 && (base & 0x5F) <= 'Z' as u32)
+        || (base >= '@' as u32 && base <= '_' as u32)
+    {
+        code & (0x1F | !0x7F)
+    } else {
+        code | CTRL_MODIFIER
+    }
+}
+
 #[derive(Clone)]
 struct Tokenizer<'a> {
     slice: &'a str,
@@ -186,23 +258,38 @@ impl<'a> Tokenizer<'a> {
 
     /// Skip whitespace and comments until the next valid read character.
     fn skip_till_char(&mut self) {
-        let mut in_comment = false;
-        let valid_char = |chr: char| {
-            if in_comment {
-                if chr == '\n' {
-                    in_comment = false;
-                }
-                false
-            } else if chr.is_ascii_whitespace() {
-                false
-            } else if chr == ';' {
-                in_comment = true;
-                false
-            } else {
-                true
+        loop {
+            self.skip_till(|c| !c.is_ascii_whitespace());
+            if !self.skip_comment() {
+                return;
             }
+        }
+    }
+
+    /// Skip the comment at the current position. Besides `;` comments this
+    /// skips `#!` lines, which start executable scripts, and `#@COUNT` which
+    /// skips the COUNT bytes after the next character, or the rest of the text
+    /// if COUNT is `00`. Returns false if there is no comment here.
+    fn skip_comment(&mut self) -> bool {
+        let start = self.cur_pos();
+        let rest = &self.slice[start..];
+        if rest.starts_with(';') || rest.starts_with("#!") {
+            self.skip_till(|c| c == '\n');
+            return true;
+        }
+        let Some(count) = rest.strip_prefix("#@") else { return false };
+        let digits = count.find(|c: char| !c.is_ascii_digit()).unwrap_or(count.len());
+        let end = match &count[..digits] {
+            "" => return false,
+            "00" => self.slice.len(),
+            count => match count.parse::<usize>() {
+                // The # and @, the digits, and the separating character
+                Ok(count) => start + 2 + digits + 1 + count,
+                Err(_) => return false,
+            },
         };
-        self.skip_till(valid_char);
+        while self.iter.next_if(|(i, _)| *i < end).is_some() {}
+        true
     }
 
     fn get_string(&mut self, open_delim_pos: usize) -> Token<'a> {
@@ -228,53 +315,117 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
+    /// Read the character literal after a `?`. This can be an escape
+    /// sequence, which may add modifiers like `\C-` and `\M-` to the
+    /// character code.
     fn read_quoted_char(&mut self, idx: usize) -> Token<'a> {
-        match self.iter.next() {
-            Some((start, item)) => {
-                if item == '\\' {
-                    let Token::Ident(tok) = self.get_symbol(start, item) else { unreachable!() };
-                    let Some(chr) = tok.chars().nth(1) else {
-                        return Token::Error(Error::MissingQuotedItem(start));
-                    };
-                    if chr == 'u' || chr == 'x' {
-                        match u32::from_str_radix(&tok[2..], 16) {
-                            Ok(digits) => match char::from_u32(digits) {
-                                Some(c) => Token::QuestionMark(start, c),
-                                None => Token::Error(Error::MalformedUnicdoe(start)),
-                            },
-                            Err(_) => Token::Error(Error::MalformedUnicdoe(start)),
-                        }
-                    } else if tok.chars().count() == 2 {
-                        let new = match chr {
-                            'a' => '\u{07}',
-                            'b' => '\u{08}',
-                            'e' => '\u{1B}',
-                            'f' => '\u{0C}',
-                            'n' => '\n',
-                            'r' => '\r',
-                            's' => ' ',
-                            't' => '\t',
-                            'v' => '\u{0B}',
-                            c => c,
-                        };
-                        Token::QuestionMark(start, new)
-                    } else {
-                        // TODO implement keycode parsing
-                        Token::QuestionMark(start, '\0')
-                    }
-                } else {
-                    match self.iter.peek() {
-                        Some((i, chr)) if symbol_char(*chr) && *chr != '?' => {
-                            Token::Error(Error::UnexpectedChar(*chr, *i)) // ?aa
-                        }
-                        _ => Token::QuestionMark(idx, item), // ?a
-                    }
+        let code = match self.iter.next() {
+            Some((start, '\\')) => match self.read_char_escape(start) {
+                Ok(code) => code,
+                Err(e) => return Token::Error(e),
+            },
+            Some((_, chr)) => u32::from(chr),
+            None => return Token::Error(Error::MissingQuotedItem(idx)),
+        };
+        match self.iter.peek() {
+            Some((i, chr)) if symbol_char(*chr) && *chr != '?' => {
+                Token::Error(Error::UnexpectedChar(*chr, *i)) // ?aa
+            }
+            _ => Token::QuestionMark(idx, code),
+        }
+    }
+
+    /// Read the escape sequence after the `\` at `start` in a character
+    /// literal.
+    fn read_char_escape(&mut self, start: usize) -> Result<u32> {
+        let Some((_, chr)) = self.iter.next() else {
+            return Err(Error::MissingQuotedItem(start));
+        };
+        let modifier = match chr {
+            'A' => ALT_MODIFIER,
+            's' => SUPER_MODIFIER,
+            'H' => HYPER_MODIFIER,
+            'S' => SHIFT_MODIFIER,
+            'C' => CTRL_MODIFIER,
+            'M' => META_MODIFIER,
+            _ => 0,
+        };
+        if modifier != 0 && self.iter.next_if(|x| x.1 == '-').is_some() {
+            let code = self.read_modified_char(start)?;
+            return Ok(match modifier {
+                CTRL_MODIFIER => control_char(code),
+                _ => code | modifier,
+            });
+        }
+        Ok(match chr {
+            '^' => control_char(self.read_modified_char(start)?),
+            'a' => 0x07,
+            'b' => 0x08,
+            'd' => 0x7F,
+            'e' => 0x1B,
+            'f' => 0x0C,
+            'n' => u32::from('\n'),
+            'r' => u32::from('\r'),
+            's' => u32::from(' '),
+            't' => u32::from('\t'),
+            'v' => 0x0B,
+            'x' => self.read_hex_digits(start, usize::MAX, false)?,
+            'u' => self.read_hex_digits(start, 4, true)?,
+            'U' => self.read_hex_digits(start, 8, true)?,
+            'N' => {
+                // Only the `\N{U+X}` form, and not character names
+                if self.iter.next_if(|x| x.1 == '{').is_none()
+                    || self.iter.next_if(|x| x.1 == 'U').is_none()
+                    || self.iter.next_if(|x| x.1 == '+').is_none()
+                {
+                    return Err(Error::MalformedUnicdoe(start));
+                }
+                let code = self.read_hex_digits(start, 8, true)?;
+                if self.iter.next_if(|x| x.1 == '}').is_none() {
+                    return Err(Error::MalformedUnicdoe(start));
+                }
+                code
+            }
+            '0'..='7' => {
+                let mut code = chr.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    let Some((_, digit)) = self.iter.next_if(|x| x.1.is_digit(8)) else { break };
+                    code = code * 8 + digit.to_digit(8).unwrap();
                 }
+                code
             }
-            None => Token::Error(Error::MissingQuotedItem(idx)),
+            other => u32::from(other),
+        })
+    }
+
+    /// Read the character after a modifier like `\C-`, which can be another
+    /// escape sequence.
+    fn read_modified_char(&mut self, start: usize) -> Result<u32> {
+        match self.iter.next() {
+            Some((_, '\\')) => self.read_char_escape(start),
+            Some((_, chr)) => Ok(u32::from(chr)),
+            None => Err(Error::MissingQuotedItem(start)),
         }
     }
 
+    /// Read up to `max` hex digits of a character code. If `unicode` is set
+    /// the code has to be a valid unicode character.
+    fn read_hex_digits(&mut self, start: usize, max: usize, unicode: bool) -> Result<u32> {
+        let mut code: u32 = 0;
+        let mut digits = 0;
+        while digits < max {
+            let Some((_, digit)) = self.iter.next_if(|x| x.1.is_ascii_hexdigit()) else { break };
+            let digit = digit.to_digit(16).unwrap();
+            let next = code.checked_mul(16).and_then(|x| x.checked_add(digit));
+            code = next.ok_or(Error::MalformedUnicdoe(start))?;
+            digits += 1;
+        }
+        if digits == 0 || (unicode && char::from_u32(code).is_none()) {
+            return Err(Error::MalformedUnicdoe(start));
+        }
+        Ok(code)
+    }
+
     fn read_char(&mut self) -> Option<char> {
         self.iter.next().map(|x| x.1)
     }
@@ -304,7 +455,108 @@ impl<'a> Iterator for Tokenizer<'a> {
     }
 }
 
-fn intern_symbol<'ob>(symbol: &str, cx: &'ob Context) -> Symbol<'ob> {
+/// The kind of a [`Lexeme`].
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub(crate) enum LexemeKind {
+    Whitespace,
+    Comment,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    /// The reader macros `'`, `` ` ``, `,`, `,@` and `#`
+    Prefix,
+    /// A character literal like `?a`
+    Character,
+    String,
+    Number,
+    Keyword,
+    Symbol,
+    /// Text that can't be read
+    Error,
+}
+
+/// A span of the text given to a [`Lexer`].
+#[derive(PartialEq, Debug, Clone)]
+pub(crate) struct Lexeme {
+    pub(crate) kind: LexemeKind,
+    pub(crate) span: std::ops::Range<usize>,
+}
+
+/// A lossless tokenizer. Unlike the reader, which skips whitespace and
+/// comments, this yields every part of the text, so the spans of the lexemes
+/// cover it without gaps. This is meant for syntax highlighting and other
+/// tools that need to know where the tokens are rather than what they read
+/// as.
+pub(crate) struct Lexer<'a> {
+    tokens: Tokenizer<'a>,
+}
+
+impl<'a> Lexer<'a> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        Self { tokens: Tokenizer::new(text) }
+    }
+
+    fn classify_ident(ident: &str) -> LexemeKind {
+        let int = ident.strip_suffix('.').unwrap_or(ident);
+        if int.parse::<i64>().is_ok() || parse_float(ident).is_some() {
+            LexemeKind::Number
+        } else if ident.starts_with(':') {
+            LexemeKind::Keyword
+        } else {
+            LexemeKind::Symbol
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Lexeme;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.tokens.cur_pos();
+        let &(_, chr) = self.tokens.iter.peek()?;
+        let kind = if chr.is_ascii_whitespace() {
+            self.tokens.skip_till(|c| !c.is_ascii_whitespace());
+            LexemeKind::Whitespace
+        } else if self.tokens.skip_comment() {
+            LexemeKind::Comment
+        } else {
+            match self.tokens.next()? {
+                Token::OpenParen(_) => LexemeKind::OpenParen,
+                Token::CloseParen(_) => LexemeKind::CloseParen,
+                Token::OpenBracket(_) => LexemeKind::OpenBracket,
+                Token::CloseBracket(_) => LexemeKind::CloseBracket,
+                Token::Quote(_)
+                | Token::Backquote(_)
+                | Token::Unquote(_)
+                | Token::Splice(_)
+                | Token::Sharp(_) => LexemeKind::Prefix,
+                Token::QuestionMark(..) => LexemeKind::Character,
+                Token::String(_) => LexemeKind::String,
+                Token::Ident(ident) => Self::classify_ident(ident),
+                Token::Error(_) => LexemeKind::Error,
+            }
+        };
+        Some(Lexeme { kind, span: start..self.tokens.cur_pos() })
+    }
+}
+
+/// Rename `symbol` using the first prefix in `shorthands` that matches it. This
+/// implements `read-symbol-shorthands`.
+fn apply_shorthands<'a>(symbol: &'a str, shorthands: &[(String, String)]) -> Cow<'a, str> {
+    for (short, long) in shorthands {
+        if let Some(rest) = symbol.strip_prefix(short.as_str()) {
+            return Cow::Owned(format!("{long}{rest}"));
+        }
+    }
+    Cow::Borrowed(symbol)
+}
+
+fn intern_symbol<'ob>(
+    symbol: &str,
+    shorthands: &[(String, String)],
+    cx: &'ob Context,
+) -> Symbol<'ob> {
     let mut escaped = false;
     let is_not_escape = |c: &char| {
         if escaped {
@@ -319,24 +571,57 @@ fn intern_symbol<'ob>(symbol: &str, cx: &'ob Context) -> Symbol<'ob> {
     };
     if symbol.contains('\\') {
         let escaped_slice: String = symbol.chars().filter(is_not_escape).collect();
-        intern(escaped_slice.as_str(), cx)
+        intern(&apply_shorthands(&escaped_slice, shorthands), cx)
     } else {
-        intern(symbol, cx)
+        intern(&apply_shorthands(symbol, shorthands), cx)
     }
 }
 
 /// Parse a symbol from a string. This will either by a true symbol or a number
 /// literal.
-fn parse_symbol<'a>(slice: &str, cx: &'a Context) -> Object<'a> {
-    match slice.parse::<i64>() {
+fn parse_symbol<'a>(slice: &str, shorthands: &[(String, String)], cx: &'a Context) -> Object<'a> {
+    // A trailing decimal point without any digits after it is still an integer
+    let int = slice.strip_suffix('.').unwrap_or(slice);
+    match int.parse::<i64>() {
         Ok(num) => cx.add(num),
-        Err(_) => match slice.parse::<f64>() {
-            Ok(num) => cx.add(num),
-            Err(_) => cx.add(intern_symbol(slice, cx)),
+        Err(_) => match parse_float(slice) {
+            Some(num) => cx.add(num),
+            None => cx.add(intern_symbol(slice, shorthands, cx)),
         },
     }
 }
 
+/// Process the escape characters in the string slice of a unibyte string,
+/// which can also use octal escapes like `\303`. Returns `None` if the string
+/// has a character that isn't a byte.
+fn unescape_bytes(string: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(string.len());
+    let mut chars = string.chars().peekable();
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            bytes.push(u8::try_from(chr).ok()?);
+            continue;
+        }
+        match chars.next()? {
+            'n' => bytes.push(b'\n'),
+            't' => bytes.push(b'\t'),
+            'r' => bytes.push(b'\r'),
+            '\n' | ' ' => {}
+            digit @ '0'..='7' => {
+                let mut value = digit.to_digit(8)?;
+                for _ in 0..2 {
+                    let Some(next) = chars.peek().and_then(|x| x.to_digit(8)) else { break };
+                    value = value * 8 + next;
+                    chars.next();
+                }
+                bytes.push(u8::try_from(value).ok()?);
+            }
+            other => bytes.push(u8::try_from(other).ok()?),
+        }
+    }
+    Some(bytes)
+}
+
 /// process escape characters in the string slice and return the resulting
 /// string.
 fn unescape_string<'a>(string: &str, cx: &'a Context) -> Object<'a> {
@@ -367,7 +652,7 @@ fn unescape_string<'a>(string: &str, cx: &'a Context) -> Object<'a> {
 }
 
 /// Return true if `chr` is a valid symbol character.
-const fn symbol_char(chr: char) -> bool {
+pub(crate) const fn symbol_char(chr: char) -> bool {
     !matches!(chr, '\x00'..=' ' | '(' | ')' | '[' | ']' | '#' | ',' | '`' | ';' | '"' | '\'')
 }
 
@@ -389,6 +674,8 @@ struct Reader<'a, 'ob> {
     tokens: Tokenizer<'a>,
     /// New objects are allocated in the context.
     cx: &'ob Context<'ob>,
+    /// Symbol prefixes to rename while reading, from `read-symbol-shorthands`.
+    shorthands: &'a [(String, String)],
 }
 
 impl<'a, 'ob> Reader<'a, 'ob> {
@@ -400,6 +687,10 @@ impl<'a, 'ob> Reader<'a, 'ob> {
     fn read_cdr(&mut self, delim: usize) -> Result<Option<Object<'ob>>> {
         match self.tokens.next() {
             Some(Token::CloseParen(_)) => Ok(None),
+            // (1 . . 2)
+            Some(token @ Token::Ident(".")) => {
+                Err(Error::UnexpectedDot(self.tokens.relative_pos(token)))
+            }
             Some(sexp) => {
                 let obj = self.read_sexp(sexp)?;
                 match self.tokens.next() {
@@ -418,9 +709,13 @@ impl<'a, 'ob> Reader<'a, 'ob> {
             match token {
                 Token::CloseParen(_) => return Ok(fns::slice_into_list(&objects, None, self.cx)),
                 Token::Ident(".") => {
+                    // (. 1)
+                    if objects.is_empty() {
+                        return Err(Error::UnexpectedDot(self.tokens.relative_pos(token)));
+                    }
                     let cdr = self.read_cdr(delim)?;
                     if cdr.is_none() {
-                        objects.push(parse_symbol(".", self.cx));
+                        objects.push(parse_symbol(".", self.shorthands, self.cx));
                     }
                     return Ok(fns::slice_into_list(&objects, cdr, self.cx));
                 }
@@ -441,6 +736,106 @@ impl<'a, 'ob> Reader<'a, 'ob> {
         Err(Error::MissingCloseBracket(delim))
     }
 
+    /// Read the elements of a list up to the closing paren, without a cdr.
+    fn read_items(&mut self, delim: usize) -> Result<Vec<Object<'ob>>> {
+        let mut objects = Vec::new();
+        while let Some(token) = self.tokens.next() {
+            match token {
+                Token::CloseParen(_) => return Ok(objects),
+                tok => objects.push(self.read_sexp(tok)?),
+            }
+        }
+        Err(Error::MissingCloseParen(delim))
+    }
+
+    /// Read `#s(hash-table PROPERTIES...)` or the record `#s(TYPE SLOTS...)`.
+    fn read_record(&mut self, delim: usize, pos: usize) -> Result<Object<'ob>> {
+        let slots = self.read_items(delim)?;
+        match slots.first() {
+            None => Err(Error::InvalidRecord(pos)),
+            Some(&kind) if kind == sym::HASH_TABLE => self.hash_table(&slots[1..], pos),
+            Some(_) => {
+                let mut record = self.cx.vec_new();
+                record.extend(slots);
+                Ok(self.cx.add(RecordBuilder(record)))
+            }
+        }
+    }
+
+    /// Create a hash table from the properties of its printed form. Only the
+    /// `test` and `data` properties are used.
+    fn hash_table(&self, properties: &[Object<'ob>], pos: usize) -> Result<Object<'ob>> {
+        let mut test = HashTableTest::default();
+        let mut data = Vec::new();
+        for property in properties.chunks(2) {
+            let &[name, value] = property else { return Err(Error::InvalidRecord(pos)) };
+            if name == sym::TEST {
+                let ObjectType::Symbol(value) = value.untag() else {
+                    return Err(Error::InvalidRecord(pos));
+                };
+                test = HashTableTest::from_symbol(value).ok_or(Error::InvalidRecord(pos))?;
+            } else if name == sym::DATA {
+                for element in value.as_list().map_err(|_| Error::InvalidRecord(pos))? {
+                    data.push(element.map_err(|_| Error::InvalidRecord(pos))?);
+                }
+            }
+        }
+        let table = HashTable::default().into_obj(self.cx);
+        table.untag().set_test(test);
+        for pair in data.chunks(2) {
+            let &[key, value] = pair else { return Err(Error::InvalidRecord(pos)) };
+            table.untag().insert(key, value);
+        }
+        Ok(table.into())
+    }
+
+    /// Read the slots of a char-table printed as `#^[SLOTS...]`.
+    fn read_char_table(&mut self, delim: usize, pos: usize) -> Result<Object<'ob>> {
+        let mut record = self.cx.vec_new();
+        record.push(sym::CHAR_TABLE.into());
+        while let Some(token) = self.tokens.next() {
+            match token {
+                Token::CloseBracket(_) if record.len() > 1 => {
+                    return Ok(self.cx.add(RecordBuilder(record)));
+                }
+                Token::CloseBracket(_) => return Err(Error::InvalidRecord(pos)),
+                tok => record.push(self.read_sexp(tok)?),
+            }
+        }
+        Err(Error::MissingCloseBracket(delim))
+    }
+
+    /// Read a byte-code function printed as `#[ARGS CODE CONSTANTS DEPTH ...]`.
+    /// Like `make-byte-code`, the slots after the depth are ignored.
+    fn read_byte_code(&mut self, pos: usize) -> Result<Object<'ob>> {
+        let mut slots = Vec::new();
+        let mut code = None;
+        loop {
+            match self.tokens.next() {
+                Some(Token::CloseBracket(_)) => break,
+                // The code is a unibyte string, so it is read as raw bytes
+                Some(Token::String(x)) if slots.len() == 1 => {
+                    code = unescape_bytes(x);
+                    slots.push(NIL);
+                }
+                Some(token) => slots.push(self.read_sexp(token)?),
+                None => return Err(Error::MissingCloseBracket(pos)),
+            }
+        }
+        let invalid = Error::InvalidByteCode(pos);
+        let (Some(code), &[args, _, constants, depth, ..]) = (code, &slots[..]) else {
+            return Err(invalid);
+        };
+        let ObjectType::Vec(constants) = constants.untag() else { return Err(invalid) };
+        let constants = constants.iter().map(|x| x.get()).collect();
+        let args = u64::try_from(args).map_err(|_| invalid)?;
+        let depth = usize::try_from(depth).map_err(|_| invalid)?;
+        match crate::alloc::new_byte_code(args, &code, constants, depth, self.cx) {
+            Ok(bytefn) => Ok(bytefn.into()),
+            Err(_) => Err(invalid),
+        }
+    }
+
     /// Quote an item using `symbol`.
     fn quote_item(&mut self, pos: usize, symbol: Symbol) -> Result<Object<'ob>> {
         let obj: Object = match self.tokens.next() {
@@ -461,26 +856,26 @@ impl<'a, 'ob> Reader<'a, 'ob> {
         }
     }
 
-    /// read a sharp quoted character. This could be used for reader macro's in
-    /// the future, but right now it just handles the special cases from elisp.
+    /// Read the syntax following a `#` using the [dispatch
+    /// table](dispatch_table). A registered tag is tried first, then the
+    /// single character after the `#`.
     fn read_sharp(&mut self, pos: usize) -> Result<Object<'ob>> {
-        match self.tokens.read_char() {
-            Some('\'') => match self.tokens.next() {
-                Some(Token::OpenParen(i)) => {
-                    let list = self.read_list(i)?;
-                    Ok(list!(sym::FUNCTION, list; self.cx))
-                }
-                Some(token) => {
-                    let obj = self.read_sexp(token)?;
-                    Ok(list!(sym::FUNCTION, obj; self.cx))
-                }
-                None => Err(Error::MissingQuotedItem(pos)),
-            },
-            Some('b') => self.read_radix(pos, 2),
-            Some('o') => self.read_radix(pos, 8),
-            Some('x') => self.read_radix(pos, 16),
-            Some(chr) => Err(Error::UnknownMacroCharacter(chr, pos)),
-            None => Err(Error::MissingQuotedItem(pos)),
+        let start = self.tokens.cur_pos();
+        let end = self.tokens.clone().skip_till(|c| !symbol_char(c));
+        if let Some(Dispatch::Tagged(reader)) = lookup_dispatch(&self.tokens.slice[start..end]) {
+            self.tokens.skip_till(|c| !symbol_char(c));
+            let obj = match self.tokens.next() {
+                Some(token) => self.read_sexp(token)?,
+                None => return Err(Error::MissingQuotedItem(pos)),
+            };
+            return reader(obj, self.cx).ok_or(Error::InvalidSharpSyntax(pos));
+        }
+        let Some(chr) = self.tokens.read_char() else {
+            return Err(Error::MissingQuotedItem(pos));
+        };
+        match lookup_dispatch(chr.encode_utf8(&mut [0; 4])) {
+            Some(Dispatch::Builtin(reader)) => reader(self, pos),
+            _ => Err(Error::UnknownMacroCharacter(chr, pos)),
         }
     }
 
@@ -495,30 +890,160 @@ impl<'a, 'ob> Reader<'a, 'ob> {
             Token::Splice(i) => self.quote_item(i, sym::SPLICE),
             Token::Backquote(i) => self.quote_item(i, sym::BACKQUOTE),
             Token::Sharp(i) => self.read_sharp(i),
-            Token::QuestionMark(_, c) => Ok((c as i64).into()),
-            Token::Ident(x) => Ok(parse_symbol(x, self.cx)),
+            Token::QuestionMark(_, c) => Ok(i64::from(c).into()),
+            Token::Ident(x) => Ok(parse_symbol(x, self.shorthands, self.cx)),
             Token::String(x) => Ok(unescape_string(x, self.cx)),
             Token::Error(e) => Err(e),
         }
     }
 }
 
+/// A reader for `#TAG OBJECT` syntax added with [`register_sharp_syntax`]. It
+/// is given the object following the tag and returns the object the syntax
+/// stands for, or `None` if the syntax is invalid.
+pub(crate) type SharpSyntax = for<'ob> fn(Object<'ob>, &'ob Context) -> Option<Object<'ob>>;
+
+/// A reader for the syntax after a `#` that is built into the reader. It is
+/// given the position of the `#`.
+type BuiltinSyntax = for<'a, 'ob> fn(&mut Reader<'a, 'ob>, usize) -> Result<Object<'ob>>;
+
+#[derive(Copy, Clone)]
+enum Dispatch {
+    Builtin(BuiltinSyntax),
+    Tagged(SharpSyntax),
+}
+
+/// The `#` dispatch table, mapping what follows the `#` to its reader.
+/// Builtin syntax is a single character, while registered syntax is a tag made
+/// of symbol characters.
+fn dispatch_table() -> &'static RwLock<HashMap<String, Dispatch>> {
+    static TABLE: OnceLock<RwLock<HashMap<String, Dispatch>>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let builtins: [(&str, BuiltinSyntax); 7] = [
+            ("'", |reader, pos| reader.quote_item(pos, sym::FUNCTION)),
+            ("[", |reader, pos| reader.read_byte_code(pos)),
+            ("s", |reader, pos| match reader.tokens.next() {
+                Some(Token::OpenParen(i)) => reader.read_record(i, pos),
+                _ => Err(Error::InvalidRecord(pos)),
+            }),
+            ("^", |reader, pos| match reader.tokens.next() {
+                Some(Token::OpenBracket(i)) => reader.read_char_table(i, pos),
+                _ => Err(Error::InvalidRecord(pos)),
+            }),
+            ("b", |reader, pos| reader.read_radix(pos, 2)),
+            ("o", |reader, pos| reader.read_radix(pos, 8)),
+            ("x", |reader, pos| reader.read_radix(pos, 16)),
+        ];
+        let table = builtins
+            .into_iter()
+            .map(|(x, reader)| (x.to_owned(), Dispatch::Builtin(reader)));
+        RwLock::new(table.collect())
+    })
+}
+
+fn lookup_dispatch(syntax: &str) -> Option<Dispatch> {
+    dispatch_table().read().unwrap().get(syntax).copied()
+}
+
+/// Extend the reader with the syntax `#TAG OBJECT`, which is read as the result
+/// of calling `reader` on `OBJECT`. Registering a tag again replaces its
+/// reader. The builtin syntax can't be replaced, but a tag that only starts
+/// with a builtin character, like `#xml`, is fine.
+#[allow(dead_code)] // Not used by the rune binary itself
+pub(crate) fn register_sharp_syntax(tag: &str, reader: SharpSyntax) -> anyhow::Result<()> {
+    if tag.is_empty() || !tag.chars().all(symbol_char) {
+        anyhow::bail!("Invalid # syntax tag: {tag}");
+    }
+    let mut table = dispatch_table().write().unwrap();
+    if let Some(Dispatch::Builtin(_)) = table.get(tag) {
+        anyhow::bail!("# syntax {tag} is builtin");
+    }
+    table.insert(tag.to_owned(), Dispatch::Tagged(reader));
+    Ok(())
+}
+
 /// read a lisp object from `slice`. Return the object and index of next
 /// remaining character in the slice.
 pub(crate) fn read<'ob>(slice: &str, cx: &'ob Context) -> Result<(Object<'ob>, usize)> {
-    let mut reader = Reader { tokens: Tokenizer::new(slice), cx };
+    read_with_shorthands(slice, &[], cx)
+}
+
+/// Like [`read`], but rename symbols that start with one of the prefixes in
+/// `shorthands`. Each entry is a pair of the shorthand prefix and the prefix it
+/// should be expanded to.
+pub(crate) fn read_with_shorthands<'ob>(
+    slice: &str,
+    shorthands: &[(String, String)],
+    cx: &'ob Context,
+) -> Result<(Object<'ob>, usize)> {
+    let mut reader = Reader { tokens: Tokenizer::new(slice), cx, shorthands };
     match reader.tokens.next() {
         Some(t) => reader.read_sexp(t).map(|x| (x, reader.tokens.cur_pos())),
         None => Err(Error::EmptyStream),
     }
 }
 
+defsym!(DATA);
+defsym!(TEST);
+
 #[cfg(test)]
 mod test {
-    use crate::core::{cons::Cons, gc::RootSet};
+    use crate::core::{cons::Cons, gc::RootSet, object::ObjectType};
 
     use super::*;
 
+    #[test]
+    fn test_comments() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let read = |text| read(text, cx).map(|x| x.0.to_string());
+        assert_eq!(read("#!/usr/bin/env rune\n(a ; one\n b . ;two\n c)"), Ok("(a b . c)".into()));
+        assert_eq!(read("'; comment\nfoo"), Ok("'foo".into()));
+        assert_eq!(read("(a #@5 skip b)"), Ok("(a b)".into()));
+        assert_eq!(read("; only a comment"), Err(Error::EmptyStream));
+        assert_eq!(read("#@00 (a b)"), Err(Error::EmptyStream));
+        assert_eq!(read("#@"), Err(Error::UnknownMacroCharacter('@', 0)));
+    }
+
+    #[test]
+    fn test_lexer() {
+        use LexemeKind as K;
+        let text = "(foo :key 1.5 ?a \"s\\\"\") ; comment\n'[bar] ,@x \"open";
+        let lexemes: Vec<_> = Lexer::new(text).collect();
+        let kinds: Vec<_> = lexemes.iter().map(|x| x.kind).collect();
+        let expect = [
+            K::OpenParen,
+            K::Symbol,
+            K::Whitespace,
+            K::Keyword,
+            K::Whitespace,
+            K::Number,
+            K::Whitespace,
+            K::Character,
+            K::Whitespace,
+            K::String,
+            K::CloseParen,
+            K::Whitespace,
+            K::Comment,
+            K::Whitespace,
+            K::Prefix,
+            K::OpenBracket,
+            K::Symbol,
+            K::CloseBracket,
+            K::Whitespace,
+            K::Prefix,
+            K::Symbol,
+            K::Whitespace,
+            K::Error,
+        ];
+        assert_eq!(kinds, expect);
+        // The lexemes cover the text without gaps
+        let joined: String = lexemes.iter().map(|x| &text[x.span.clone()]).collect();
+        assert_eq!(joined, text);
+        assert_eq!(&text[lexemes[9].span.clone()], "\"s\\\"\"");
+        assert_eq!(&text[lexemes[12].span.clone()], "; comment");
+    }
+
     #[test]
     fn tokens() {
         let mut iter = Tokenizer::new("1 foo (\"bar\" . 1.3)");
@@ -559,6 +1084,18 @@ mod test {
         check_reader!(0x1, "#x001", cx);
         check_reader!(0x10, "#x10", cx);
         check_reader!(0xdead_beef_i64, "#xDeAdBeEf", cx);
+        check_reader!(1, "1.", cx);
+        check_reader!(-2, "-2.", cx);
+        check_reader!(0.5, ".5", cx);
+        check_reader!(1e100, "1e+100", cx);
+        check_reader!(1000.0, "1.e3", cx);
+        check_reader!(f64::INFINITY, "1.0e+INF", cx);
+        check_reader!(f64::NEG_INFINITY, "-1.0e+INF", cx);
+        let nan = read("0.0e+NaN", cx).unwrap().0;
+        assert!(matches!(nan.untag(), ObjectType::Float(x) if x.is_nan()));
+        check_reader!(intern("inf", cx), "inf", cx);
+        check_reader!(intern("nan", cx), "nan", cx);
+        check_reader!(intern("1e", cx), "1e", cx);
     }
 
     #[test]
@@ -601,6 +1138,29 @@ mod test {
         check_reader!(intern("+-*/_~!@$%^&=:<>{}", cx), "+-*/_~!@$%^&=:<>{}", cx);
     }
 
+    #[test]
+    fn test_read_shorthands() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let shorthands = [
+            ("s-".to_owned(), "string-".to_owned()),
+            ("sx-".to_owned(), "shorthand-x-".to_owned()),
+        ];
+        let read = |x| read_with_shorthands(x, &shorthands, cx).unwrap().0;
+        assert_eq!(read("s-trim"), intern("string-trim", cx));
+        assert_eq!(read("sx-foo"), intern("shorthand-x-foo", cx));
+        assert_eq!(read("s\\-trim"), intern("string-trim", cx));
+        assert_eq!(read("my-s-trim"), intern("my-s-trim", cx));
+        assert_eq!(read("s-1"), intern("string-1", cx));
+        assert_eq!(read("-1"), -1);
+        assert_eq!(
+            read("(s-a . s-b)"),
+            cx.add(Cons::new(intern("string-a", cx), intern("string-b", cx), cx))
+        );
+        // symbols are not renamed without shorthands
+        check_reader!(intern("s-trim", cx), "s-trim", cx);
+    }
+
     #[test]
     fn test_read_string() {
         let roots = &RootSet::default();
@@ -632,6 +1192,74 @@ baz""#,
         check_reader!(list!(1, 1.5, intern("...", cx), 2; cx), "(1 1.5 ... 2)", cx);
     }
 
+    #[test]
+    fn test_read_dotted() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let a = intern("a", cx);
+        let b = intern("b", cx);
+        let c = intern("c", cx);
+        check_reader!(Cons::new(a, b, cx), "(a . b)", cx);
+        check_reader!(Cons::new(a, b, cx), "( a  .  b )", cx);
+        check_reader!(Cons::new(a, b, cx), "(a\n.\nb)", cx);
+        check_reader!(Cons::new(a, b, cx), "(a . ; comment\n b)", cx);
+        check_reader!(Cons::new(a, Cons::new(b, c, cx), cx), "(a b . c)", cx);
+        check_reader!(list!(a, b; cx), "(a . (b))", cx);
+        check_reader!(list!(a, b; cx), "(a . (b . nil))", cx);
+        check_reader!(list!(a; cx), "(a . nil)", cx);
+        check_reader!(list!(a; cx), "(a . ())", cx);
+        check_reader!(Cons::new(Cons::new(a, b, cx), c, cx), "((a . b) . c)", cx);
+        check_reader!(Cons::new(a, list!(sym::QUOTE, b; cx), cx), "(a . 'b)", cx);
+        check_reader!(Cons::new(a, 1.5, cx), "(a . 1.5)", cx);
+        check_reader!(Cons::new(a, intern(".b", cx), cx), "(a . .b)", cx);
+        check_reader!(list!(intern(".a", cx), b; cx), "(.a b)", cx);
+        // dotted forms inside quasiquote
+        let unquote_b = list!(sym::UNQUOTE, b; cx);
+        check_reader!(list!(sym::BACKQUOTE, Cons::new(a, unquote_b, cx); cx), "`(a . ,b)", cx);
+        check_reader!(
+            list!(sym::BACKQUOTE, Cons::new(a, list!(sym::SPLICE, b; cx), cx); cx),
+            "`(a . ,@b)",
+            cx
+        );
+        check_reader!(
+            list!(sym::BACKQUOTE, list!(Cons::new(a, list!(sym::UNQUOTE, b; cx), cx); cx); cx),
+            "`((a . ,b))",
+            cx
+        );
+
+        assert_error("(. a)", Error::UnexpectedDot(1), cx);
+        assert_error("(.)", Error::UnexpectedDot(1), cx);
+        assert_error("(a . . b)", Error::UnexpectedDot(5), cx);
+        assert_error("(a . b c)", Error::ExtraItemInCdr(7), cx);
+        assert_error("(a . b . c)", Error::ExtraItemInCdr(7), cx);
+        assert_error("(a . b", Error::MissingCloseParen(0), cx);
+        assert_error("(a .", Error::MissingCloseParen(0), cx);
+        assert_error("(a . ]", Error::ExtraCloseBracket(5), cx);
+        assert_error("`(. ,a)", Error::UnexpectedDot(2), cx);
+    }
+
+    #[test]
+    fn test_print_dotted() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let cases = [
+            ("(a . b)", "(a . b)"),
+            ("(a b . c)", "(a b . c)"),
+            ("(a . (b . (c . d)))", "(a b c . d)"),
+            ("((a . b) . (c . d))", "((a . b) c . d)"),
+            ("(a . (b . nil))", "(a b)"),
+            ("(1 . 2.5)", "(1 . 2.5)"),
+            ("(a . \"b\")", "(a . \"b\")"),
+            ("[(a . b)]", "[(a . b)]"),
+        ];
+        for (input, expect) in cases {
+            let obj = read(input, cx).unwrap().0;
+            assert_eq!(obj.to_string(), expect);
+            // printed form should read back to the same object
+            assert_eq!(read(expect, cx).unwrap().0, obj);
+        }
+    }
+
     #[test]
     fn read_quote() {
         let roots = &RootSet::default();
@@ -645,6 +1273,27 @@ baz""#,
         check_reader!(u32::from('\t'), "?\\t", cx);
         check_reader!(u32::from('\u{AFD}'), "?\\uafd", cx);
         check_reader!(0xabc_u32, "?\\xabc", cx);
+        check_reader!(0x1F600_u32, "?\\U0001F600", cx);
+        check_reader!(0xE9_u32, "?\\N{U+E9}", cx);
+        check_reader!(65, "?\\101", cx);
+        check_reader!(127, "?\\d", cx);
+        check_reader!(u32::from('('), "?\\(", cx);
+        // Modifiers
+        check_reader!(3, "?\\C-c", cx);
+        check_reader!(3, "?\\C-C", cx);
+        check_reader!(13, "?\\^M", cx);
+        check_reader!(127, "?\\C-?", cx);
+        check_reader!((1 << 26) | u32::from('%'), "?\\C-%", cx);
+        check_reader!((1 << 27) | u32::from('x'), "?\\M-x", cx);
+        check_reader!((1 << 27) | 1, "?\\C-\\M-a", cx);
+        check_reader!((1 << 27) | 1, "?\\M-\\C-a", cx);
+        check_reader!((1 << 23) | u32::from('a'), "?\\s-a", cx);
+        check_reader!((1 << 27) | u32::from('('), "?\\M-(", cx);
+        check_reader!(list!(1, 10; cx), "(?\\C-a ?\\n)", cx);
+        assert_error("?\\na", Error::UnexpectedChar('a', 3), cx);
+        assert_error("?\\u", Error::MalformedUnicdoe(1), cx);
+        assert_error("?\\uD800", Error::MalformedUnicdoe(1), cx);
+        assert_error("?\\C-", Error::MissingQuotedItem(1), cx);
     }
 
     #[test]
@@ -663,6 +1312,65 @@ baz""#,
         assert_error("#a", Error::UnknownMacroCharacter('a', 0), cx);
     }
 
+    #[test]
+    fn read_sharp_syntax() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let upcase: SharpSyntax = |obj, cx| match obj.untag() {
+            ObjectType::String(string) => Some(cx.add(string.to_uppercase())),
+            _ => None,
+        };
+        register_sharp_syntax("upcase", upcase).unwrap();
+        register_sharp_syntax("xupcase", upcase).unwrap();
+        assert!(register_sharp_syntax("x", upcase).is_err());
+        assert!(register_sharp_syntax("a b", upcase).is_err());
+
+        check_reader!("ABC", "#upcase \"abc\"", cx);
+        check_reader!("ABC", "#xupcase \"abc\"", cx);
+        check_reader!(list!(1, "A"; cx), "(1 #upcase \"a\")", cx);
+        check_reader!(255, "#xff", cx);
+        assert_error(" #upcase 1", Error::InvalidSharpSyntax(1), cx);
+        assert_error("#upcase", Error::MissingQuotedItem(0), cx);
+        assert_error("#upcased 1", Error::UnknownMacroCharacter('u', 0), cx);
+    }
+
+    #[test]
+    fn read_records() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let print = |input: &str| read(input, cx).unwrap().0.to_string();
+        let table = "#s(hash-table test equal data (a 1 \"b\" (2)))";
+        assert_eq!(print(table), table);
+        assert_eq!(print("#s(hash-table size 3)"), "#s(hash-table test eql data ())");
+        let eq = "#s(hash-table test eq data (\"a\" 1 \"a\" 2))";
+        assert_eq!(print(eq), eq);
+        let equal = "#s(hash-table test equal data (\"a\" 1 \"a\" 2))";
+        assert_eq!(print(equal), "#s(hash-table test equal data (\"a\" 2))");
+        assert_eq!(print("#s(foo 1 #s(bar))"), "#s(foo 1 #s(bar))");
+        assert_eq!(print("#^[nil 0 nil]"), "#^[nil 0 nil]");
+        assert_error("#s()", Error::InvalidRecord(0), cx);
+        assert_error(" #s[1]", Error::InvalidRecord(1), cx);
+        assert_error("#s(hash-table data (a))", Error::InvalidRecord(0), cx);
+        assert_error("#s(hash-table test foo)", Error::InvalidRecord(0), cx);
+        assert_error("#^[]", Error::InvalidRecord(0), cx);
+    }
+
+    #[test]
+    fn read_byte_code() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let print = |input: &str| read(input, cx).unwrap().0.to_string();
+        let bytefn = r#"#[257 "\300\207" [a "b" [c]] 3]"#;
+        assert_eq!(print(bytefn), bytefn);
+        assert!(matches!(read(bytefn, cx).unwrap().0.untag(), ObjectType::ByteFn(_)));
+        assert_eq!(print(r#"#[0 "\300\n\\\"" [] 1 "doc" nil]"#), r#"#[0 "\300\012\\\"" [] 1]"#);
+        assert_error(r#"#[0 "\300" []]"#, Error::InvalidByteCode(0), cx);
+        assert_error(r#" #[0 "\300" nil 1]"#, Error::InvalidByteCode(1), cx);
+        assert_error(r#"#[0 code [] 1]"#, Error::InvalidByteCode(0), cx);
+        assert_error("#[0 \"λ\" [] 1]", Error::InvalidByteCode(0), cx);
+        assert_error(r#"#[0 "" [] 1"#, Error::MissingCloseBracket(0), cx);
+    }
+
     #[test]
     fn test_read_vec() {
         let roots = &RootSet::default();
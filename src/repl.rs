@@ -0,0 +1,436 @@
+//! The interactive REPL. Input is read with a line editor, so earlier inputs
+//! can be recalled with the arrow keys or `M-p` and `M-n`. The history is kept
+//! in the init directory between sessions. The last three values are stored
+//! in the variables `*`, `**` and `***`.
+//!
+//! Tab completes the name of the function or variable at point, or a file
+//! name inside a string. While typing the arguments of a call, the arglist of
+//! the function is shown as a hint. Input is highlighted using the
+//! [lexer](crate::reader::Lexer).
+use crate::core::{
+    env::{interned_symbols, sym, Env},
+    gc::{Context, Rt},
+    object::{Function, FunctionType, Object, ObjectType, NIL},
+};
+use crate::reader::{symbol_char, LexemeKind, Lexer};
+use crate::{alloc, eventloop, interpreter, pp, print, reader};
+use anyhow::Result;
+use rune_core::macros::{rebind, root};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Cmd, Editor, Helper, KeyEvent,
+};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// The number of inputs kept in the history.
+const HISTORY_SIZE: usize = 1000;
+
+fn history_file() -> PathBuf {
+    crate::startup::default_init_directory().join("repl-history")
+}
+
+fn parens_closed(buffer: &str) -> bool {
+    let open = buffer.chars().filter(|&x| x == '(').count();
+    let close = buffer.chars().filter(|&x| x == ')').count();
+    open <= close
+}
+
+/// Shift `value` into the result variables.
+fn record_result(value: Object, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let var = |name| env.vars.get(name).map_or(NIL, |x| x.bind(cx));
+    let (last, second) = (var(sym::MUL), var(sym::STAR_STAR));
+    env.set_default(sym::STAR_STAR_STAR, second)?;
+    env.set_default(sym::STAR_STAR, last)?;
+    env.set_default(sym::MUL, value)
+}
+
+/// The arglist of `function` as it would be written in its definition.
+/// Builtin functions don't record the names of their arguments, so they are
+/// numbered instead.
+fn arglist(function: Function, cx: &Context) -> Option<String> {
+    let args = match function.untag() {
+        FunctionType::Closure(closure) => Some(closure.arg_list()),
+        FunctionType::Cons(cons) => {
+            let arg_pos = match cons.car().untag() {
+                ObjectType::Symbol(sym::CLOSURE) => 2,
+                _ => 1,
+            };
+            Some(cons.elements().nth(arg_pos)?.ok()?)
+        }
+        _ => None,
+    };
+    if let Some(args) = args {
+        return Some(match args.untag() {
+            ObjectType::NIL => "()".to_owned(),
+            _ => args.to_string(),
+        });
+    }
+    let arity = crate::eval::func_arity(function, cx).ok()?;
+    let required: usize = arity.car().try_into().ok()?;
+    let mut args: Vec<String> = (1..=required).map(|i| format!("arg{i}")).collect();
+    match arity.cdr().untag() {
+        ObjectType::Int(max) if max as usize > required => {
+            args.push("&optional".to_owned());
+            args.extend((required + 1..=max as usize).map(|i| format!("arg{i}")));
+        }
+        ObjectType::Int(_) => {}
+        _ => args.push("&rest rest".to_owned()),
+    }
+    Some(format!("({})", args.join(" ")))
+}
+
+/// A hint that is only displayed, and not inserted by the right arrow key.
+struct ArglistHint(String);
+
+impl rustyline::hint::Hint for ArglistHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Completion and hints for the line editor. The line editor has no access to
+/// the lisp environment, so the names it needs are copied before each line is
+/// read.
+#[derive(Default)]
+struct LispHelper {
+    /// Function names and their arglists
+    functions: BTreeMap<String, String>,
+    variables: BTreeSet<String>,
+}
+
+impl LispHelper {
+    fn update(&mut self, env: &Rt<Env>, cx: &Context) {
+        self.functions.clear();
+        self.variables.clear();
+        for symbol in interned_symbols().symbols() {
+            if let Some(function) = symbol.func(cx) {
+                let arglist = arglist(function, cx).unwrap_or_default();
+                self.functions.insert(symbol.name().to_owned(), arglist);
+            }
+            if env.vars.get(symbol).is_some() {
+                self.variables.insert(symbol.name().to_owned());
+            }
+        }
+    }
+
+    /// The names starting with `prefix`. Only functions are considered when
+    /// the name is at the start of a call.
+    fn complete_symbol(&self, prefix: &str, call: bool) -> Vec<String> {
+        let variables = self.variables.iter().filter(|_| !call);
+        let names = self.functions.keys().chain(variables).filter(|x| x.starts_with(prefix));
+        let mut names: Vec<String> = names.cloned().collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// The position of the opening quote if `pos` is inside a string.
+fn string_start(line: &str, pos: usize) -> Option<usize> {
+    let mut start = None;
+    let mut escaped = false;
+    for (i, chr) in line[..pos].char_indices() {
+        match chr {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => start = if start.is_some() { None } else { Some(i) },
+            _ => {}
+        }
+    }
+    start
+}
+
+/// The names of the files that complete `partial`, which may start with a
+/// directory. Directories end in a slash.
+fn complete_file_name(partial: &str) -> Vec<String> {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(i) => (&partial[..=i], &partial[i + 1..]),
+        None => ("", partial),
+    };
+    let expanded = match dir.strip_prefix('~') {
+        Some(rest) => format!("{}{rest}", crate::fileio::home_directory().unwrap_or_default()),
+        None => dir.to_owned(),
+    };
+    let dir_path = if expanded.is_empty() { Path::new(".") } else { Path::new(&expanded) };
+    let Ok(entries) = std::fs::read_dir(dir_path) else { return Vec::new() };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{name}{slash}"))
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// The start of the symbol that ends at `pos`.
+fn symbol_start(line: &str, pos: usize) -> usize {
+    line[..pos].rfind(|x| !symbol_char(x)).map_or(0, |i| i + 1)
+}
+
+/// The name of the function called by the innermost list that is open at
+/// `pos`. Nothing is returned while the name is still being typed.
+fn enclosing_call(line: &str, pos: usize) -> Option<&str> {
+    let mut depth = 0;
+    for (i, chr) in line[..pos].char_indices().rev() {
+        match chr {
+            ')' => depth += 1,
+            '(' if depth > 0 => depth -= 1,
+            '(' => {
+                let rest = &line[i + 1..pos];
+                let end = rest.find(|x| !symbol_char(x))?;
+                return Some(&rest[..end]).filter(|x| !x.is_empty());
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+impl Completer for LispHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        if let Some(quote) = string_start(line, pos) {
+            // Only the last component of the file name is replaced
+            let partial = &line[quote + 1..pos];
+            let start = partial.rfind('/').map_or(quote + 1, |i| quote + i + 2);
+            return Ok((start, complete_file_name(partial)));
+        }
+        let start = symbol_start(line, pos);
+        let call = line[..start].trim_end().ends_with('(');
+        Ok((start, self.complete_symbol(&line[start..pos], call)))
+    }
+}
+
+impl Hinter for LispHelper {
+    type Hint = ArglistHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &rustyline::Context<'_>) -> Option<ArglistHint> {
+        if pos < line.len() || string_start(line, pos).is_some() {
+            return None;
+        }
+        let name = enclosing_call(line, pos)?;
+        let arglist = self.functions.get(name)?;
+        let args = arglist.strip_prefix('(')?.strip_suffix(')')?;
+        match args.is_empty() {
+            true => Some(ArglistHint(format!("  ({name})"))),
+            false => Some(ArglistHint(format!("  ({name} {args})"))),
+        }
+    }
+}
+
+/// The ANSI escape sequence to color a lexeme of `kind`.
+fn lexeme_color(kind: LexemeKind) -> Option<&'static str> {
+    match kind {
+        LexemeKind::Comment => Some("\x1b[90m"),
+        LexemeKind::String => Some("\x1b[32m"),
+        LexemeKind::Number | LexemeKind::Character => Some("\x1b[36m"),
+        LexemeKind::Keyword => Some("\x1b[35m"),
+        LexemeKind::Prefix => Some("\x1b[33m"),
+        LexemeKind::Error => Some("\x1b[31m"),
+        _ => None,
+    }
+}
+
+impl Highlighter for LispHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let mut highlighted = String::with_capacity(line.len());
+        for lexeme in Lexer::new(line) {
+            let text = &line[lexeme.span];
+            match lexeme_color(lexeme.kind) {
+                Some(color) => highlighted.extend([color, text, "\x1b[0m"]),
+                None => highlighted.push_str(text),
+            }
+        }
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[90m{hint}\x1b[0m"))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        true
+    }
+}
+
+impl Validator for LispHelper {}
+
+impl Helper for LispHelper {}
+
+type LineEditor = Editor<LispHelper, DefaultHistory>;
+
+fn new_editor() -> rustyline::Result<LineEditor> {
+    let config = rustyline::Config::builder().max_history_size(HISTORY_SIZE)?.build();
+    let mut editor = LineEditor::with_config(config)?;
+    editor.set_helper(Some(LispHelper::default()));
+    editor.bind_sequence(KeyEvent::alt('p'), Cmd::PreviousHistory);
+    editor.bind_sequence(KeyEvent::alt('n'), Cmd::NextHistory);
+    // There is no history the first time the REPL is run
+    _ = editor.load_history(&history_file());
+    Ok(editor)
+}
+
+fn save_history(editor: &mut LineEditor) -> Result<()> {
+    let file = history_file();
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    editor.save_history(&file)?;
+    Ok(())
+}
+
+pub(crate) fn repl(env: &mut Rt<Env>, cx: &mut Context) {
+    println!("Hello, world!");
+    let mut editor = match new_editor() {
+        Ok(editor) => editor,
+        Err(e) => {
+            println!("Error: {e}");
+            return;
+        }
+    };
+    for var in [sym::MUL, sym::STAR_STAR, sym::STAR_STAR_STAR] {
+        env.set_default(var, NIL).unwrap();
+    }
+    let mut buffer = String::new();
+    loop {
+        if buffer.is_empty() {
+            if let Err(e) = eventloop::dispatch_events(env, cx) {
+                println!("Error: {e}");
+            }
+            if let Err(e) = alloc::idle_garbage_collect(env, cx) {
+                println!("Error: {e}");
+            }
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.update(env, cx);
+        }
+        let prompt = if buffer.is_empty() { "> " } else { "  " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                buffer.push_str(&line);
+                buffer.push('\n');
+            }
+            // C-c discards the current input
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                println!("Error: {e}");
+                break;
+            }
+        }
+        if buffer.trim() == "exit" {
+            break;
+        }
+        if buffer.trim().is_empty() {
+            buffer.clear();
+            continue;
+        }
+        if !parens_closed(&buffer) {
+            continue;
+        }
+        _ = editor.add_history_entry(buffer.trim());
+        let (obj, _) = match reader::read(&buffer, cx) {
+            Ok(obj) => obj,
+            Err(e) => {
+                println!("Error: {e}");
+                buffer.clear();
+                continue;
+            }
+        };
+
+        root!(obj, cx);
+        match interpreter::eval(obj, None, env, cx) {
+            Ok(val) => {
+                let val = rebind!(val, cx);
+                let settings = print::load_print_settings(env, cx);
+                println!("{}", pp::pretty_print(val, pp::line_width(env, cx)));
+                drop(settings);
+                if let Err(e) = record_result(val, env, cx) {
+                    println!("Error: {e}");
+                }
+            }
+            Err(e) => {
+                crate::print_error(e, env, cx);
+                env.reset_to_toplevel(cx);
+            }
+        }
+        buffer.clear();
+    }
+    if let Err(e) = save_history(&mut editor) {
+        println!("Error saving history: {e}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_record_result() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        for value in 1..=4 {
+            record_result(cx.add(value), env, cx).unwrap();
+        }
+        let var = |name| env.vars.get(name).unwrap().bind(cx);
+        assert_eq!(var(sym::MUL), cx.add(4));
+        assert_eq!(var(sym::STAR_STAR), cx.add(3));
+        assert_eq!(var(sym::STAR_STAR_STAR), cx.add(2));
+    }
+
+    #[test]
+    fn test_completion() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        env.set_default(sym::FILL_COLUMN, cx.add(70)).unwrap();
+        let mut helper = LispHelper::default();
+        helper.update(env, cx);
+        assert_eq!(helper.functions["car"], "(arg1)");
+        assert_eq!(helper.functions["substring"], "(arg1 &optional arg2 arg3)");
+        let closure = reader::read("#'(lambda (a &optional b) a)", cx).unwrap().0;
+        root!(closure, cx);
+        let closure = rebind!(interpreter::eval(closure, None, env, cx).unwrap());
+        let closure: Function = closure.try_into().unwrap();
+        assert!(matches!(closure.untag(), FunctionType::Closure(_)));
+        assert_eq!(arglist(closure, cx).as_deref(), Some("(a &optional b)"));
+        assert!(helper.complete_symbol("fill-col", false).contains(&"fill-column".to_owned()));
+        assert!(helper.complete_symbol("fill-col", true).is_empty());
+        assert!(helper.complete_symbol("setc", true).contains(&"setcar".to_owned()));
+
+        assert_eq!(symbol_start("(foo (bar-b", 11), 6);
+        assert_eq!(enclosing_call("(foo (bar 1) ", 13), Some("foo"));
+        assert_eq!(enclosing_call("(foo (bar 1", 11), Some("bar"));
+        assert_eq!(enclosing_call("foo", 3), None);
+        assert_eq!(string_start(r#"(load "a\"b"#, 11), Some(6));
+        assert_eq!(string_start(r#"(load "a" b"#, 11), None);
+    }
+}
+
+defsym!(STAR_STAR, "**");
+defsym!(STAR_STAR_STAR, "***");
@@ -1,12 +1,15 @@
 //! Search utilities.
+use crate::casefiddle::{casify, CaseOp};
+use crate::casetab::case_table;
 use crate::core::{
-    env::Env,
+    env::{sym, Env},
+    error::ArgRangeError,
     gc::{Context, Rt},
-    object::{List, Object, ObjectType, NIL},
+    object::{List, Object, ObjectType, Symbol, NIL},
 };
 use anyhow::{bail, ensure, Result};
 use fallible_iterator::FallibleIterator;
-use fancy_regex::Regex;
+use fancy_regex::{Captures, Regex};
 use rune_macros::defun;
 
 #[defun]
@@ -74,6 +77,178 @@ fn replace_match(
     Ok(new_string)
 }
 
+/// The text to replace the match in `captures` with. Unless `literal` is set,
+/// `\&` in `newtext` stands for the whole match, `\N` for group N, and `\\`
+/// for a backslash.
+fn expand_replacement(newtext: &str, literal: bool, captures: &Captures) -> Result<String> {
+    if literal {
+        return Ok(newtext.to_owned());
+    }
+    let mut expanded = String::with_capacity(newtext.len());
+    let mut chars = newtext.chars();
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            expanded.push(chr);
+            continue;
+        }
+        match chars.next() {
+            Some('&') => expanded += captures.get(0).map_or("", |x| x.as_str()),
+            Some('\\') => expanded.push('\\'),
+            Some(group @ '0'..='9') => {
+                let group = group.to_digit(10).unwrap() as usize;
+                expanded += captures.get(group).map_or("", |x| x.as_str());
+            }
+            _ => bail!("Invalid use of `\\' in replacement text"),
+        }
+    }
+    Ok(expanded)
+}
+
+/// How the case of `matched` should carry over to its replacement: all caps
+/// if it is all caps, and with initial capitals if each of its words has one. `prev` is the
+/// character before the match.
+fn match_case(matched: &str, prev: Option<char>) -> Option<CaseOp> {
+    let mut some_lowercase = false;
+    let mut some_uppercase = false;
+    let mut some_nonuppercase_initial = false;
+    let mut some_multiletter_word = false;
+    let mut in_word = prev.is_some_and(char::is_alphanumeric);
+    for chr in matched.chars() {
+        if chr.is_lowercase() {
+            some_lowercase = true;
+            if in_word {
+                some_multiletter_word = true;
+            } else {
+                some_nonuppercase_initial = true;
+            }
+        } else if chr.is_uppercase() {
+            some_uppercase = true;
+            some_multiletter_word |= in_word;
+        } else if !in_word {
+            some_nonuppercase_initial = true;
+        }
+        in_word = chr.is_alphanumeric();
+    }
+    if !some_lowercase && some_multiletter_word {
+        Some(CaseOp::Up)
+    } else if !some_nonuppercase_initial && some_multiletter_word {
+        Some(CaseOp::UpInitials)
+    } else if !some_nonuppercase_initial && some_uppercase {
+        Some(CaseOp::Up)
+    } else {
+        None
+    }
+}
+
+/// Replace the matches of `re` between `start` and `end` in the current buffer
+/// with `to`. If `preserve_case` is set, each replacement takes the case of
+/// the text it replaces. Returns the number of replacements.
+#[allow(clippy::too_many_arguments)]
+fn replace_in_region(
+    re: &Regex,
+    to: &str,
+    literal: bool,
+    preserve_case: bool,
+    start: Option<usize>,
+    end: Option<usize>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<usize> {
+    let table = case_table(env, cx);
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    let len = buffer.text.len_chars();
+    let (beg, end) = (start.unwrap_or(0), end.unwrap_or(len));
+    let (beg, end) = (beg.min(end), beg.max(end));
+    ensure!(end <= len, ArgRangeError::new(&[beg, end]));
+    // With the gap moved out of the region its text is contiguous
+    buffer.text.move_gap_out_of(beg..end);
+    let [before, after] = buffer.text.slices(beg..end);
+    let text = if before.is_empty() { after } else { before };
+    // The character positions of the matches and their replacements
+    let mut edits = Vec::new();
+    let (mut byte_pos, mut char_pos) = (0, beg);
+    for captures in re.captures_iter(&text) {
+        let captures = captures?;
+        let whole = captures.get(0).unwrap();
+        let mut new = expand_replacement(to, literal, &captures)?;
+        if preserve_case {
+            let prev = text[..whole.start()]
+                .chars()
+                .next_back()
+                .or_else(|| beg.checked_sub(1).and_then(|x| buffer.text.char_at(x)));
+            if let Some(op) = match_case(whole.as_str(), prev) {
+                new = casify(op, &new, table);
+            }
+        }
+        char_pos += text[byte_pos..whole.start()].chars().count();
+        let match_end = char_pos + whole.as_str().chars().count();
+        edits.push((char_pos, match_end, new));
+        (byte_pos, char_pos) = (whole.end(), match_end);
+    }
+    // Edit from the end, so the positions of the earlier matches stay valid
+    for (match_beg, match_end, new) in edits.iter().rev() {
+        buffer.replace_region(*match_beg, *match_end, new);
+    }
+    Ok(edits.len())
+}
+
+/// Whether the search for `from` ignores case, and whether replacements
+/// should keep the case of what they replace. Case is only ignored if `from`
+/// is all lower case.
+fn case_handling(from: &str, env: &Rt<Env>, cx: &Context) -> (bool, bool) {
+    let set = |var: Symbol| env.var(var, cx).is_some_and(|x| !x.bind(cx).is_nil());
+    let fold = set(sym::CASE_FOLD_SEARCH) && !from.chars().any(char::is_uppercase);
+    (fold, fold && set(sym::CASE_REPLACE))
+}
+
+fn build_regex(pattern: &str, fold: bool, delimited: Option<Object>) -> Result<Regex> {
+    let mut pattern = match delimited {
+        Some(x) if !x.is_nil() => format!(r"\b(?:{pattern})\b"),
+        _ => pattern.to_owned(),
+    };
+    if fold {
+        pattern.insert_str(0, "(?i)");
+    }
+    Ok(Regex::new(&pattern)?)
+}
+
+/// Replace occurrences of `from-string` with `to-string` between `start` and
+/// `end` in the current buffer, or in all of it. If `delimited` is non-nil,
+/// only whole words are replaced. Returns the number of replacements.
+#[defun]
+fn replace_string(
+    from_string: &str,
+    to_string: &str,
+    delimited: Option<Object>,
+    start: Option<usize>,
+    end: Option<usize>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<usize> {
+    let (fold, preserve_case) = case_handling(from_string, env, cx);
+    let re = build_regex(&fancy_regex::escape(from_string), fold, delimited)?;
+    replace_in_region(&re, to_string, true, preserve_case, start, end, env, cx)
+}
+
+/// Replace matches of `regexp` with `to-string` between `start` and `end` in
+/// the current buffer, or in all of it. In `to-string`, `\&` is the whole
+/// match and `\N` is group N. If `delimited` is non-nil, only matches that
+/// are whole words are replaced. Returns the number of replacements.
+#[defun]
+fn replace_regexp(
+    regexp: &str,
+    to_string: &str,
+    delimited: Option<Object>,
+    start: Option<usize>,
+    end: Option<usize>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<usize> {
+    let (fold, preserve_case) = case_handling(regexp, env, cx);
+    let re = build_regex(&lisp_regex_to_rust(regexp), fold, delimited)?;
+    replace_in_region(&re, to_string, false, preserve_case, start, end, env, cx)
+}
+
 #[defun]
 fn regexp_quote(string: &str) -> String {
     let mut quoted = String::new();
@@ -175,8 +350,12 @@ fn string_equal(s1: &str, s2: &str) -> bool {
     s1 == s2
 }
 
+defvar!(CASE_FOLD_SEARCH, true);
+defvar!(CASE_REPLACE, true);
+
 #[cfg(test)]
 mod test {
+    use crate::buffer::{get_buffer_create, set_buffer};
     use crate::core::gc::RootSet;
     use rune_core::macros::root;
 
@@ -205,4 +384,40 @@ mod test {
         let result = replace_match(newtext, None, None, Some(string), None, env, cx).unwrap();
         assert_eq!(result, "foo quux baz");
     }
+
+    #[test]
+    fn test_replace_in_region() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let buffer = get_buffer_create(cx.add("test_replace_in_region"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        let text = cx.add("foo Foo FOO (a) food foo");
+        env.current_buffer.as_mut().unwrap().insert(text).unwrap();
+        env.set_default(sym::CASE_FOLD_SEARCH, sym::TRUE.into()).unwrap();
+        env.set_default(sym::CASE_REPLACE, sym::TRUE.into()).unwrap();
+
+        let count = replace_string("foo", "bar", Some(sym::TRUE.into()), None, None, env, cx);
+        assert_eq!(count.unwrap(), 4);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "bar Bar BAR (a) food bar");
+        // Only the region is changed
+        let count =
+            replace_regexp("\\(b\\)a\\(r\\)", "<\\2\\1\\&>", None, Some(4), Some(11), env, cx);
+        assert_eq!(count.unwrap(), 2);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "bar <RBBar> <RBBAR> (a) food bar");
+        let count = replace_string("(a)", "x", None, None, None, env, cx);
+        assert_eq!(count.unwrap(), 1);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "bar <RBBar> <RBBAR> x food bar");
+        // Upper case in the search turns off case folding
+        assert_eq!(replace_string("Food", "baz", None, None, None, env, cx).unwrap(), 0);
+        assert!(replace_regexp("b", "\\q", None, None, None, env, cx).is_err());
+        // A match that the gap is in the middle of
+        let buffer = env.current_buffer.as_mut().unwrap();
+        buffer.text.set_cursor(24);
+        buffer.insert(cx.add("o")).unwrap();
+        let count = replace_regexp("fo+d", "meal", None, None, None, env, cx);
+        assert_eq!(count.unwrap(), 1);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "bar <RBBar> <RBBAR> x meal bar");
+    }
 }
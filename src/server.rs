@@ -0,0 +1,215 @@
+//! Server mode, so that other programs can drive a running rune. The server
+//! listens on a unix domain socket and each connection sends expressions to
+//! evaluate using the [protocol]. Connections are served on their own
+//! threads, which hand the expressions to the [event
+//! loop](crate::eventloop) so they are evaluated on the main thread.
+use crate::core::{
+    env::{Env, EnvId},
+    gc::{Context, Rt},
+};
+use crate::eval::EvalError;
+use crate::eventloop::{push_event, Event};
+use crate::{interpreter, reader};
+use anyhow::{bail, Result};
+use rune_core::macros::{rebind, root};
+use rune_macros::defun;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex, PoisonError};
+
+pub(crate) mod protocol;
+
+pub(crate) use protocol::default_socket_path;
+
+/// Expressions to evaluate for a client, and where to send the reply.
+#[derive(Debug)]
+pub(crate) struct Request {
+    text: String,
+    reply: mpsc::Sender<String>,
+}
+
+/// The socket of the running server.
+static SOCKET: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Start a server listening on `path`. Requests are evaluated in the
+/// environment `owner`.
+#[cfg(unix)]
+pub(crate) fn start(path: &Path, owner: EnvId) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let mut socket = SOCKET.lock().unwrap_or_else(PoisonError::into_inner);
+    if socket.is_some() {
+        bail!("The server is already running");
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+        // Only the owner may connect
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!("There is already a server listening on {}", path.display());
+        }
+        // Left behind by a server that didn't shut down
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    let listening = path.to_owned();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            // The server was stopped
+            if SOCKET.lock().unwrap_or_else(PoisonError::into_inner).as_ref() != Some(&listening) {
+                break;
+            }
+            if let Ok(stream) = stream {
+                std::thread::spawn(move || serve(stream, owner));
+            }
+        }
+    });
+    *socket = Some(path.to_owned());
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn start(_path: &Path, _owner: EnvId) -> Result<()> {
+    bail!("The server requires unix domain sockets")
+}
+
+/// Stop the server. Returns false if it was not running.
+pub(crate) fn stop() -> bool {
+    let Some(path) = SOCKET.lock().unwrap_or_else(PoisonError::into_inner).take() else {
+        return false;
+    };
+    // Wake the listener so that it sees the server has stopped
+    #[cfg(unix)]
+    _ = std::os::unix::net::UnixStream::connect(&path);
+    _ = std::fs::remove_file(path);
+    true
+}
+
+/// Answer the requests on a connection until the client disconnects.
+#[cfg(unix)]
+fn serve(stream: std::os::unix::net::UnixStream, owner: EnvId) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let Ok(mut writer) = stream.try_clone() else { return };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { return };
+        let reply = match protocol::decode(&line) {
+            (protocol::EVAL, text) => {
+                let (reply, receiver) = mpsc::channel();
+                push_event(owner, Event::Server(Request { text, reply }));
+                match receiver.recv() {
+                    Ok(reply) => reply,
+                    Err(_) => return,
+                }
+            }
+            (command, _) => {
+                protocol::encode(protocol::ERROR, &format!("Unknown command {command}"))
+            }
+        };
+        if writer.write_all(reply.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Evaluate the forms in `text`, returning the printed value of the last one.
+pub(crate) fn eval_string(text: &str, env: &mut Rt<Env>, cx: &mut Context) -> Result<String> {
+    let mut pos = 0;
+    let mut value = String::from("nil");
+    loop {
+        let (obj, new_pos) = match reader::read(&text[pos..], cx) {
+            Ok(x) => x,
+            Err(reader::Error::EmptyStream) => return Ok(value),
+            Err(e) => bail!(e),
+        };
+        pos += new_pos;
+        root!(obj, cx);
+        let result = rebind!(interpreter::eval(obj, None, env, cx)?, cx);
+        let _settings = crate::print::load_print_settings(env, cx);
+        value = result.to_string();
+    }
+}
+
+/// Evaluate a request from a client and send the reply.
+pub(crate) fn handle_event(request: Request, env: &mut Rt<Env>, cx: &mut Context) {
+    let reply = match eval_string(&request.text, env, cx) {
+        Ok(value) => protocol::encode(protocol::PRINT, &value),
+        Err(e) => {
+            let message = match e.downcast_ref::<EvalError>() {
+                Some(e) => e.message(env, cx),
+                None => e.to_string(),
+            };
+            protocol::encode(protocol::ERROR, message.trim_end())
+        }
+    };
+    // The client may have disconnected
+    _ = request.reply.send(reply);
+}
+
+#[defun]
+fn server_start(leave_dead: Option<()>, env: &Rt<Env>) -> Result<()> {
+    stop();
+    if leave_dead.is_none() {
+        start(&default_socket_path(), env.id)?;
+    }
+    Ok(())
+}
+
+#[defun]
+fn server_running_p() -> bool {
+    SOCKET.lock().unwrap_or_else(PoisonError::into_inner).is_some()
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::core::{env::sym, gc::RootSet};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn test_server() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let path = std::env::temp_dir().join(format!("rune-test-{}", std::process::id()));
+        let path = path.join("server");
+        start(&path, env.id).unwrap();
+        assert!(server_running_p());
+        assert!(start(&path, env.id).is_err());
+
+        let client = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let mut stream = UnixStream::connect(path).unwrap();
+                let mut replies = BufReader::new(stream.try_clone().unwrap()).lines();
+                let mut send = |expr: &str| {
+                    stream.write_all(protocol::encode(protocol::EVAL, expr).as_bytes()).unwrap();
+                    let line = replies.next().unwrap().unwrap();
+                    let (command, arg) = protocol::decode(&line);
+                    (command.to_owned(), arg)
+                };
+                [
+                    send("(setq server-test-var \"a\nb\") (+ 1 2)"),
+                    send("server-test-var"),
+                    send("(car 1)"),
+                ]
+            })
+        };
+        while !client.is_finished() {
+            crate::eventloop::wait_for_event(crate::eventloop::deadline(0.01), env.id);
+            crate::eventloop::dispatch_events(env, cx).unwrap();
+        }
+        let [sum, string, error] = client.join().unwrap();
+        assert_eq!(sum, (protocol::PRINT.to_owned(), "3".to_owned()));
+        assert_eq!(string, (protocol::PRINT.to_owned(), "\"a\nb\"".to_owned()));
+        assert_eq!(error.0, protocol::ERROR);
+
+        assert!(stop());
+        assert!(!server_running_p());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,72 @@
+//! The wire format shared by the server and `rune-client`. Each message is a
+//! single line holding a command and its argument. The argument is quoted so
+//! that it can't contain a newline: `&` becomes `&&` and a newline becomes
+//! `&n`. Requests are `-eval EXPR`, and the replies are `-print VALUE` or
+//! `-error MESSAGE`, where `VALUE` is printed so that it can be read back.
+use std::path::PathBuf;
+
+pub(crate) const EVAL: &str = "-eval";
+pub(crate) const PRINT: &str = "-print";
+pub(crate) const ERROR: &str = "-error";
+
+fn quote(arg: &str) -> String {
+    arg.replace('&', "&&").replace('\n', "&n")
+}
+
+fn unquote(arg: &str) -> String {
+    let mut out = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '&' => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => {}
+            },
+            chr => out.push(chr),
+        }
+    }
+    out
+}
+
+/// Encode a message, including the terminating newline.
+pub(crate) fn encode(command: &str, arg: &str) -> String {
+    format!("{command} {}\n", quote(arg))
+}
+
+/// Split a line into its command and argument.
+pub(crate) fn decode(line: &str) -> (&str, String) {
+    let line = line.trim_end_matches(['\r', '\n']);
+    match line.split_once(' ') {
+        Some((command, arg)) => (command, unquote(arg)),
+        None => (line, String::new()),
+    }
+}
+
+/// The socket used when none is given, `$XDG_RUNTIME_DIR/rune/server` or
+/// `/tmp/runeUID/server`.
+pub(crate) fn default_socket_path() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return PathBuf::from(dir).join("rune").join("server");
+    }
+    #[cfg(unix)]
+    // SAFETY: getuid is always successful
+    let uid = unsafe { libc::getuid() };
+    #[cfg(not(unix))]
+    let uid = 0;
+    std::env::temp_dir().join(format!("rune{uid}")).join("server")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let expr = "(insert \"a&b\nc\")";
+        let line = encode(EVAL, expr);
+        assert_eq!(line, "-eval (insert \"a&&b&nc\")\n");
+        assert_eq!(decode(&line), (EVAL, expr.to_owned()));
+        assert_eq!(decode("-print"), (PRINT, String::new()));
+    }
+}
@@ -0,0 +1,90 @@
+//! Loading the user's init file at startup.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Gc, LispString},
+};
+use anyhow::Result;
+use rune_core::macros::root;
+use std::path::{Path, PathBuf};
+
+/// The directory holding the user's configuration. This is
+/// `$XDG_CONFIG_HOME/rune/`, which defaults to `~/.config/rune/`.
+pub(crate) fn default_init_directory() -> PathBuf {
+    let config = match std::env::var_os("XDG_CONFIG_HOME").filter(|x| !x.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => match crate::fileio::home_directory() {
+            Some(home) => Path::new(&home).join(".config"),
+            None => std::env::temp_dir(),
+        },
+    };
+    config.join("rune")
+}
+
+/// Directory names end in a slash, as file name functions expect.
+fn directory_name(dir: &Path) -> String {
+    let dir = dir.to_string_lossy();
+    match dir.ends_with(std::path::MAIN_SEPARATOR) {
+        true => dir.into_owned(),
+        false => format!("{dir}{}", std::path::MAIN_SEPARATOR),
+    }
+}
+
+/// Set `user-emacs-directory` to `init_dir`, with installed packages in its
+/// `elpa` subdirectory, and load the init file in it unless `load_init` is
+/// false. `user-init-file` is set to the file that was loaded.
+pub(crate) fn startup(
+    init_dir: &Path,
+    load_init: bool,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    env.set_default(sym::USER_EMACS_DIRECTORY, cx.add(directory_name(init_dir)))?;
+    env.set_default(sym::PACKAGE_USER_DIR, cx.add(directory_name(&init_dir.join("elpa"))))?;
+    let init_file = init_dir.join("init.el");
+    if !load_init || crate::lread::existing_file(init_file.clone()).is_none() {
+        return Ok(());
+    }
+    let init_file = init_file.to_string_lossy().into_owned();
+    env.set_default(sym::USER_INIT_FILE, cx.add(init_file.as_str()))?;
+    let file: Gc<&LispString> = cx.add_as(init_file);
+    root!(file, cx);
+    crate::lread::load(file, None, Some(()), cx, env)?;
+    Ok(())
+}
+
+defvar!(USER_EMACS_DIRECTORY, "~/.config/rune/");
+defvar!(USER_INIT_FILE);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use crate::core::object::NIL;
+    use rune_core::macros::rebind;
+
+    #[test]
+    fn test_startup() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let dir = std::env::temp_dir().join(format!("rune-init-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("init.el"), "(setq startup-test-loaded t)").unwrap();
+
+        startup(&dir, false, env, cx).unwrap();
+        let user_dir = env.vars.get(sym::USER_EMACS_DIRECTORY).unwrap().bind(cx);
+        assert_eq!(user_dir, cx.add(directory_name(&dir)));
+        assert!(env.vars.get(sym::USER_INIT_FILE).is_none());
+
+        startup(&dir, true, env, cx).unwrap();
+        let init_file = env.vars.get(sym::USER_INIT_FILE).unwrap().bind(cx);
+        assert_eq!(init_file, cx.add(dir.join("init.el").to_string_lossy().into_owned()));
+        let obj = crate::reader::read("startup-test-loaded", cx).unwrap().0;
+        root!(obj, cx);
+        let loaded = rebind!(crate::interpreter::eval(obj, None, env, cx).unwrap());
+        assert_ne!(loaded, NIL);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
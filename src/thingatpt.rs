@@ -0,0 +1,183 @@
+//! Finding the word, symbol, line or sexp at a position. The functions here
+//! work on byte offsets in a string, so tools like the language server can
+//! share them with `thing-at-point` on buffers. There are no syntax tables
+//! yet, so words are alphanumeric characters and symbols are the characters
+//! the reader accepts in a symbol.
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Object, Symbol, NIL},
+};
+use crate::reader::{self, Lexeme, LexemeKind, Lexer};
+use anyhow::{bail, Result};
+use rune_macros::defun;
+
+defsym!(WORD);
+defsym!(LINE);
+defsym!(SEXP);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Thing {
+    Word,
+    Symbol,
+    Line,
+    Sexp,
+}
+
+impl Thing {
+    fn from_symbol(thing: Symbol) -> Result<Self> {
+        let things = [
+            (sym::WORD, Self::Word),
+            (sym::SYMBOL, Self::Symbol),
+            (sym::LINE, Self::Line),
+            (sym::SEXP, Self::Sexp),
+        ];
+        match things.into_iter().find(|x| x.0 == thing) {
+            Some((_, thing)) => Ok(thing),
+            None => bail!("Unsupported thing: {thing}"),
+        }
+    }
+}
+
+/// The run of characters matching `pred` around `pos`.
+fn bounds_of_chars(text: &str, pos: usize, pred: impl Fn(char) -> bool) -> Option<(usize, usize)> {
+    let start = text[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, chr)| pred(*chr))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let end = text[pos..].find(|chr| !pred(chr)).map_or(text.len(), |i| pos + i);
+    (start < end).then_some((start, end))
+}
+
+/// The line `pos` is on, including its newline.
+fn bounds_of_line(text: &str, pos: usize) -> Option<(usize, usize)> {
+    let start = text[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[pos..].find('\n').map_or(text.len(), |i| pos + i + 1);
+    (start < end).then_some((start, end))
+}
+
+/// The sexp that `pos` is at the start of or inside, or failing that the one
+/// that ends at `pos`. A position between the elements of a list is not at
+/// the list.
+fn bounds_of_sexp(text: &str, pos: usize) -> Option<(usize, usize)> {
+    // The start, end, and whether it is a list, of every sexp in the text
+    let mut sexps = Vec::new();
+    let mut open = Vec::new();
+    // A prefix like `'` is part of the sexp after it
+    let mut prefix = None;
+    for Lexeme { kind, span } in Lexer::new(text) {
+        match kind {
+            LexemeKind::Whitespace | LexemeKind::Comment | LexemeKind::Error => {}
+            LexemeKind::Prefix => prefix = prefix.or(Some(span.start)),
+            LexemeKind::OpenParen | LexemeKind::OpenBracket => {
+                open.push(prefix.take().unwrap_or(span.start));
+            }
+            LexemeKind::CloseParen | LexemeKind::CloseBracket => {
+                if let Some(start) = open.pop() {
+                    sexps.push((start, span.end, true));
+                }
+            }
+            _ => sexps.push((prefix.take().unwrap_or(span.start), span.end, false)),
+        }
+    }
+    let size = |x: &&(usize, usize, bool)| x.1 - x.0;
+    let inside = sexps.iter().filter(|x| x.0 <= pos && pos < x.1).min_by_key(size);
+    let found = match inside {
+        Some(sexp) if !sexp.2 || !text[sexp.0..pos].contains(['(', '[']) => Some(sexp),
+        _ => sexps.iter().filter(|x| x.1 == pos).min_by_key(size),
+    };
+    found.map(|x| (x.0, x.1))
+}
+
+/// The start and end of the `thing` at byte offset `pos` in `text`, if
+/// there is one.
+pub(crate) fn bounds(text: &str, pos: usize, thing: Thing) -> Option<(usize, usize)> {
+    let pos = pos.min(text.len());
+    match thing {
+        Thing::Word => bounds_of_chars(text, pos, char::is_alphanumeric),
+        Thing::Symbol => bounds_of_chars(text, pos, reader::symbol_char),
+        Thing::Line => bounds_of_line(text, pos),
+        Thing::Sexp => bounds_of_sexp(text, pos),
+    }
+}
+
+/// The positions of the `thing` at point in the current buffer.
+fn buffer_bounds(thing: Symbol, env: &Rt<Env>) -> Result<Option<(usize, usize)>> {
+    let thing = Thing::from_symbol(thing)?;
+    let found = env.with_buffer(None, |b| {
+        let text = b.text.to_string();
+        let point = b.text.cursor().chars();
+        let offset = text.char_indices().nth(point).map_or(text.len(), |x| x.0);
+        let (start, end) = bounds(&text, offset, thing)?;
+        let start_pos = text[..start].chars().count();
+        Some((start_pos, start_pos + text[start..end].chars().count()))
+    });
+    let Some(found) = found else { bail!("No current buffer") };
+    Ok(found)
+}
+
+/// Return the start and end of the `thing` at point as a cons, or nil if
+/// there is none. `thing` is one of `word`, `symbol`, `line` or `sexp`.
+#[defun]
+fn bounds_of_thing_at_point<'ob>(
+    thing: Symbol,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    match buffer_bounds(thing, env)? {
+        Some((start, end)) => Ok(Cons::new(start, end, cx).into()),
+        None => Ok(NIL),
+    }
+}
+
+/// Return the text of the `thing` at point, or nil if there is none. See
+/// `bounds-of-thing-at-point` for the things there can be.
+#[defun]
+fn thing_at_point<'ob>(
+    thing: Symbol,
+    _no_properties: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let Some((start, end)) = buffer_bounds(thing, env)? else { return Ok(NIL) };
+    let text = env.with_buffer(None, |b| {
+        let text = b.text.to_string();
+        text.chars().skip(start).take(end - start).collect::<String>()
+    });
+    Ok(text.map_or(NIL, |x| cx.add(x)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bounds() {
+        let text = "(foo-bar 'baz  \"a b\")\n;; word1 x\n[1 2]";
+        let at = |pos, thing| bounds(text, pos, thing).map(|(start, end)| &text[start..end]);
+        assert_eq!(at(3, Thing::Word), Some("foo"));
+        assert_eq!(at(3, Thing::Symbol), Some("foo-bar"));
+        assert_eq!(at(8, Thing::Symbol), Some("foo-bar"));
+        assert_eq!(at(0, Thing::Symbol), None);
+        assert_eq!(at(5, Thing::Line), Some("(foo-bar 'baz  \"a b\")\n"));
+        assert_eq!(at(text.len(), Thing::Line), Some("[1 2]"));
+
+        let list = "(foo-bar 'baz  \"a b\")";
+        assert_eq!(at(0, Thing::Sexp), Some(list));
+        assert_eq!(at(21, Thing::Sexp), Some(list));
+        assert_eq!(at(9, Thing::Sexp), Some("'baz"));
+        assert_eq!(at(11, Thing::Sexp), Some("'baz"));
+        assert_eq!(at(17, Thing::Sexp), Some("\"a b\""));
+        assert_eq!(at(8, Thing::Sexp), Some("foo-bar"));
+        // Between the elements of a list
+        assert_eq!(at(14, Thing::Sexp), None);
+        assert_eq!(at(text.len() - 2, Thing::Sexp), Some("2"));
+        assert_eq!(at(text.len(), Thing::Sexp), Some("[1 2]"));
+        // Comments aren't sexps
+        assert_eq!(at(28, Thing::Sexp), None);
+        assert_eq!(at(28, Thing::Word), Some("word1"));
+    }
+}
@@ -4,10 +4,72 @@ use crate::core::{
     gc::{Block, Context, RootSet},
     object::{CloneIn, Object},
 };
+use crate::eval::EvalError;
 use rune_core::macros::root;
 use rune_macros::defun;
+use std::sync::mpsc;
 use std::thread::{self, JoinHandle};
 
+type Reply = Result<String, String>;
+
+/// An interpreter with its own thread, heap, environment and
+/// [namespace](crate::core::env::namespace), for embedders that want to keep
+/// plugins from seeing each other's definitions. Only the builtin symbols and
+/// subroutines are shared with the rest of the process.
+// Not used by the rune binary itself
+#[allow(dead_code)]
+pub(crate) struct Instance {
+    requests: Option<mpsc::Sender<(String, mpsc::Sender<Reply>)>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[allow(dead_code)]
+impl Instance {
+    pub(crate) fn new() -> Self {
+        let (requests, receiver) = mpsc::channel::<(String, mpsc::Sender<Reply>)>();
+        let thread = thread::spawn(move || {
+            crate::core::env::namespace::enter_namespace();
+            let roots = &RootSet::default();
+            let cx = &mut Context::new(roots);
+            root!(env, new(Env), cx);
+            crate::init(env, cx);
+            for (text, reply) in receiver {
+                let result = match crate::server::eval_string(&text, env, cx) {
+                    Ok(value) => Ok(value),
+                    Err(e) => Err(match e.downcast_ref::<EvalError>() {
+                        Some(e) => e.message(env, cx),
+                        None => e.to_string(),
+                    }),
+                };
+                // The caller may have given up on the reply
+                _ = reply.send(result);
+            }
+        });
+        Self { requests: Some(requests), thread: Some(thread) }
+    }
+
+    /// Evaluate the forms in `text`, returning the printed value of the last
+    /// one or the error message.
+    pub(crate) fn eval(&self, text: &str) -> Reply {
+        let (reply, receiver) = mpsc::channel();
+        let requests = self.requests.as_ref().unwrap();
+        let sent = requests.send((text.to_owned(), reply));
+        sent.ok()
+            .and_then(|()| receiver.recv().ok())
+            .unwrap_or_else(|| Err("Instance exited".into()))
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        // Closing the channel stops the thread
+        self.requests.take();
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
 #[defun]
 fn go(obj: Object) {
     go_internal(obj);
@@ -54,6 +116,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_instances() {
+        let first = Instance::new();
+        let second = Instance::new();
+        assert_eq!(first.eval("(defalias 'instance-fn #'(lambda () 1))"), Ok("instance-fn".into()));
+        assert_eq!(
+            second.eval("(defalias 'instance-fn #'(lambda () 2))"),
+            Ok("instance-fn".into())
+        );
+        assert_eq!(first.eval("(setq instance-var 'first) (instance-fn)"), Ok("1".into()));
+        assert_eq!(second.eval("(instance-fn)"), Ok("2".into()));
+        assert!(second.eval("instance-var").is_err());
+        assert_eq!(second.eval("(intern-soft \"instance-var\")"), Ok("nil".into()));
+        assert_eq!(first.eval("(not nil)"), Ok("t".into()));
+    }
+
     #[test]
     fn test_go_message() {
         let roots = &RootSet::default();
@@ -1,9 +1,11 @@
 //! Time analysis
 use crate::core::{
+    cons::Cons,
     env::{sym, Env},
     gc::{Context, Rt},
-    object::Object,
+    object::{Object, ObjectType, NIL},
 };
+use anyhow::{bail, ensure, Result};
 use rune_core::macros::list;
 use rune_macros::defun;
 use std::time::SystemTime;
@@ -13,12 +15,16 @@ defvar!(CURRENT_TIME_LIST, true);
 #[defun]
 fn current_time<'ob>(cx: &'ob Context, env: &Rt<Env>) -> Object<'ob> {
     assert!(
-        env.vars.get(sym::CURRENT_TIME_LIST).unwrap() == &sym::TRUE,
+        env.var(sym::CURRENT_TIME_LIST, cx).unwrap() == &sym::TRUE,
         "current-time-list is nil"
     );
-    let duration = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("System time is before the epoch");
+    system_time_to_list(SystemTime::now(), cx)
+}
+
+/// Convert `time` to the `(HIGH LOW USEC PSEC)` list format. Times before the
+/// epoch are clamped to it.
+pub(crate) fn system_time_to_list(time: SystemTime, cx: &Context) -> Object {
+    let duration = time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
 
     let secs = duration.as_secs();
     let micros = duration.subsec_micros();
@@ -27,3 +33,1232 @@ fn current_time<'ob>(cx: &'ob Context, env: &Rt<Env>) -> Object<'ob> {
 
     list![high, low, micros, 0; cx]
 }
+
+/// The number of picoseconds in a second, the resolution of lisp timestamps.
+const PICOS: i128 = 1_000_000_000_000;
+
+/// A lisp timestamp as the number of picoseconds since the epoch.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Time(i128);
+
+impl Time {
+    fn now() -> Self {
+        let duration = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(duration) => duration.as_nanos() as i128,
+            Err(e) => -(e.duration().as_nanos() as i128),
+        };
+        Time(duration * 1000)
+    }
+
+    fn from_secs(secs: i64) -> Self {
+        Time(i128::from(secs) * PICOS)
+    }
+
+    /// The whole seconds, rounded down.
+    fn secs(self) -> i64 {
+        self.0.div_euclid(PICOS) as i64
+    }
+
+    /// The picoseconds past the whole seconds.
+    fn subsec_picos(self) -> i128 {
+        self.0.rem_euclid(PICOS)
+    }
+
+    fn as_f64(self) -> f64 {
+        self.0 as f64 / PICOS as f64
+    }
+
+    /// The time in the `(HIGH LOW USEC PSEC)` list format.
+    fn to_list(self, cx: &Context) -> Object {
+        let secs = self.secs();
+        let picos = self.subsec_picos() as i64;
+        list![secs >> 16, secs & 0xffff, picos / 1_000_000, picos % 1_000_000; cx]
+    }
+
+    /// The time in the `(TICKS . HZ)` format, using the lowest power of ten
+    /// for `HZ` that represents it exactly.
+    fn to_ticks(self, cx: &Context) -> Result<Object> {
+        let mut hz = 1;
+        while self.0 % (PICOS / hz) != 0 {
+            hz *= 10;
+        }
+        let ticks = i64::try_from(self.0 / (PICOS / hz))?;
+        Ok(Cons::new(ticks, hz as i64, cx).into())
+    }
+}
+
+/// Convert a lisp time value to a [`Time`]. Time values are nil for the
+/// current time, a number of seconds, a `(TICKS . HZ)` pair, or a list of the
+/// form `(HIGH LOW USEC PSEC)` where the trailing elements are optional.
+fn lisp_time(time: Object) -> Result<Time> {
+    match time.untag() {
+        ObjectType::NIL => Ok(Time::now()),
+        ObjectType::Int(secs) => Ok(Time::from_secs(secs)),
+        ObjectType::Float(secs) => {
+            ensure!(secs.is_finite(), "Invalid time specification: {time}");
+            // Convert the fraction separately so that it isn't rounded
+            let whole = secs.floor();
+            let fraction = ((**secs - whole) * PICOS as f64).round() as i128;
+            Ok(Time(whole as i128 * PICOS + fraction))
+        }
+        ObjectType::Cons(cons) => {
+            if let ObjectType::Int(hz) = cons.cdr().untag() {
+                let ticks: i64 = cons.car().try_into()?;
+                ensure!(hz > 0, "Invalid time specification: {time}");
+                return Ok(Time(i128::from(ticks) * PICOS / i128::from(hz)));
+            }
+            let mut parts = [0i64; 4];
+            for (i, part) in time.as_list()?.enumerate() {
+                ensure!(i < parts.len(), "Invalid time specification: {time}");
+                parts[i] = part?.try_into()?;
+            }
+            let [high, low, usec, psec] = parts.map(i128::from);
+            Ok(Time(((high << 16) + low) * PICOS + usec * 1_000_000 + psec))
+        }
+        _ => bail!("Invalid time specification: {time}"),
+    }
+}
+
+/// Return `time` in the form of the arguments it was computed from: an integer
+/// if they were both integers, a float if either was a float, and a list
+/// otherwise.
+fn time_like<'ob>(time: Time, a: Object, b: Object, cx: &'ob Context) -> Object<'ob> {
+    match (a.untag(), b.untag()) {
+        (ObjectType::Float(_), _) | (_, ObjectType::Float(_)) => cx.add(time.as_f64()),
+        (ObjectType::Int(_), ObjectType::Int(_)) => cx.add(time.secs()),
+        _ => time.to_list(cx),
+    }
+}
+
+#[defun]
+fn time_add<'ob>(a: Object, b: Object, cx: &'ob Context) -> Result<Object<'ob>> {
+    let time = Time(lisp_time(a)?.0 + lisp_time(b)?.0);
+    Ok(time_like(time, a, b, cx))
+}
+
+#[defun]
+fn time_subtract<'ob>(a: Object, b: Object, cx: &'ob Context) -> Result<Object<'ob>> {
+    let time = Time(lisp_time(a)?.0 - lisp_time(b)?.0);
+    Ok(time_like(time, a, b, cx))
+}
+
+#[defun]
+fn time_less_p(a: Object, b: Object) -> Result<bool> {
+    Ok(lisp_time(a)? < lisp_time(b)?)
+}
+
+#[defun]
+fn time_equal_p(a: Object, b: Object) -> Result<bool> {
+    // nil is the current time, which would differ between the two calls
+    if a.is_nil() && b.is_nil() {
+        return Ok(true);
+    }
+    Ok(lisp_time(a)? == lisp_time(b)?)
+}
+
+#[defun]
+fn float_time(time: Option<Object>) -> Result<f64> {
+    Ok(lisp_time(time.unwrap_or_default())?.as_f64())
+}
+
+#[defun]
+fn time_convert<'ob>(
+    time: Object,
+    form: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let time = lisp_time(time)?;
+    let list_form = env.var(sym::CURRENT_TIME_LIST, cx).is_some_and(|x| x.bind(cx) != NIL);
+    match form.map(|x| x.untag()) {
+        Some(ObjectType::Symbol(sym::INTEGER)) => Ok(cx.add(time.secs())),
+        Some(ObjectType::Symbol(sym::LIST)) => Ok(time.to_list(cx)),
+        Some(ObjectType::Symbol(sym::TRUE)) => time.to_ticks(cx),
+        Some(ObjectType::Int(hz)) => {
+            ensure!(hz > 0, "Invalid time frequency: {hz}");
+            let ticks = (time.0 * i128::from(hz)).div_euclid(PICOS);
+            Ok(Cons::new(i64::try_from(ticks)?, hz, cx).into())
+        }
+        None if list_form => Ok(time.to_list(cx)),
+        None => time.to_ticks(cx),
+        Some(form) => bail!("Invalid time form: {form}"),
+    }
+}
+
+/// The number of days since the epoch of a date in the proleptic Gregorian
+/// calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The `(YEAR MONTH DAY)` of a number of days since the epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Zone {
+    Local,
+    /// A fixed number of seconds east of UTC
+    Fixed(i64),
+    /// A POSIX TZ rule, such as "EST5EDT"
+    Rule(Box<TzRule>),
+    /// A zone from the tz database, such as "Europe/Berlin"
+    Database(Box<TzFile>),
+}
+
+impl Zone {
+    /// Parse a time zone argument. nil or `wall` is local time, t is UTC, an
+    /// integer is an offset in seconds, `(OFFSET ABBR)` is an offset with a
+    /// name, and a string is a POSIX TZ string or the name of a zone in the
+    /// tz database.
+    fn new(zone: Option<Object>) -> Result<Self> {
+        let Some(zone) = zone else { return Ok(Zone::Local) };
+        match zone.untag() {
+            ObjectType::NIL | ObjectType::Symbol(sym::WALL) => Ok(Zone::Local),
+            ObjectType::Symbol(sym::TRUE) => Ok(Zone::Fixed(0)),
+            ObjectType::Int(offset) => Ok(Zone::Fixed(offset)),
+            ObjectType::Cons(cons) => Ok(Zone::Fixed(cons.car().try_into()?)),
+            ObjectType::String(tz) => {
+                let tz: &str = tz;
+                if matches!(tz, "UTC" | "GMT" | "Z") {
+                    return Ok(Zone::Fixed(0));
+                }
+                if let Some(rule) = TzRule::parse(tz) {
+                    return Ok(Zone::Rule(Box::new(rule)));
+                }
+                match TzFile::load(tz) {
+                    Some(file) => Ok(Zone::Database(Box::new(file))),
+                    None => bail!("Unknown time zone: {tz}"),
+                }
+            }
+            _ => bail!("Invalid time zone specification: {zone}"),
+        }
+    }
+
+    /// The offset from UTC in seconds at `secs` since the epoch, and whether
+    /// daylight saving time is in effect.
+    fn offset(&self, secs: i64) -> (i64, Option<bool>) {
+        match self {
+            Zone::Local => {
+                let (offset, dst) = local_offset(secs);
+                (offset, Some(dst))
+            }
+            Zone::Fixed(offset) => (*offset, None),
+            Zone::Rule(rule) => {
+                let (offset, _, dst) = rule.at(secs);
+                (offset, Some(dst))
+            }
+            Zone::Database(file) => {
+                let (offset, _, dst) = file.at(secs);
+                (offset, Some(dst))
+            }
+        }
+    }
+}
+
+/// A POSIX TZ rule: the name and offset of standard time, optionally followed
+/// by those of daylight saving time and the dates it starts and ends, as in
+/// "CET-1CEST,M3.5.0,M10.5.0/3".
+#[derive(Debug, Clone, PartialEq)]
+struct TzRule {
+    std_name: String,
+    /// Seconds east of UTC
+    std_offset: i64,
+    dst: Option<DstRule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DstRule {
+    name: String,
+    /// Seconds east of UTC
+    offset: i64,
+    /// When daylight saving time starts, in standard time, and the number of
+    /// seconds after midnight
+    start: (RuleDate, i64),
+    /// When daylight saving time ends, in daylight saving time
+    end: (RuleDate, i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RuleDate {
+    /// `Jn`: day 1 to 365, where February 29th is never counted
+    Julian(i64),
+    /// `n`: day 0 to 365, counting February 29th in leap years
+    Day(i64),
+    /// `Mm.w.d`: day `d` of the week (0 is Sunday) in week `w` of month `m`,
+    /// where week 5 is the last one
+    Month(i64, i64, i64),
+}
+
+impl RuleDate {
+    /// The day this falls on in `year`, in days since the epoch.
+    fn day(self, year: i64) -> i64 {
+        let jan1 = days_from_civil(year, 1, 1);
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        match self {
+            RuleDate::Julian(day) => jan1 + day - 1 + i64::from(leap && day >= 60),
+            RuleDate::Day(day) => jan1 + day,
+            RuleDate::Month(month, week, weekday) => {
+                let first = days_from_civil(year, month, 1);
+                let next_month = days_from_civil(year + month / 12, month % 12 + 1, 1);
+                // The epoch was a Thursday
+                let mut day = first + (weekday - (first + 4)).rem_euclid(7) + (week - 1) * 7;
+                while day >= next_month {
+                    day -= 7;
+                }
+                day
+            }
+        }
+    }
+}
+
+impl TzRule {
+    fn parse(tz: &str) -> Option<Self> {
+        let mut rest = tz;
+        let std_name = take_tz_name(&mut rest)?;
+        // POSIX offsets are west of UTC
+        let std_offset = -take_tz_offset(&mut rest)?;
+        if rest.is_empty() {
+            return Some(Self { std_name, std_offset, dst: None });
+        }
+        let name = take_tz_name(&mut rest)?;
+        let offset = match rest.chars().next() {
+            None | Some(',') => std_offset + 3600,
+            Some(_) => -take_tz_offset(&mut rest)?,
+        };
+        let (start, end) = if rest.is_empty() {
+            // The rules of the United States are the default
+            ((RuleDate::Month(3, 2, 0), 7200), (RuleDate::Month(11, 1, 0), 7200))
+        } else {
+            rest = rest.strip_prefix(',')?;
+            let start = take_rule_date(&mut rest)?;
+            rest = rest.strip_prefix(',')?;
+            (start, take_rule_date(&mut rest)?)
+        };
+        let dst = DstRule { name, offset, start, end };
+        rest.is_empty().then_some(Self { std_name, std_offset, dst: Some(dst) })
+    }
+
+    /// The offset east of UTC at `secs` since the epoch, the name of the
+    /// zone then, and whether daylight saving time is in effect.
+    fn at(&self, secs: i64) -> (i64, &str, bool) {
+        let Some(dst) = &self.dst else {
+            return (self.std_offset, self.std_name.as_str(), false);
+        };
+        let (year, _, _) = civil_from_days((secs + self.std_offset).div_euclid(86400));
+        let start = dst.start.0.day(year) * 86400 + dst.start.1 - self.std_offset;
+        let end = dst.end.0.day(year) * 86400 + dst.end.1 - dst.offset;
+        // In the southern hemisphere daylight saving time spans the new year
+        let in_dst = if start < end {
+            (start..end).contains(&secs)
+        } else {
+            !(end..start).contains(&secs)
+        };
+        if in_dst {
+            (dst.offset, dst.name.as_str(), true)
+        } else {
+            (self.std_offset, self.std_name.as_str(), false)
+        }
+    }
+}
+
+/// Take the name of a zone from the start of `rest`: either letters, or any
+/// characters between `<` and `>`.
+fn take_tz_name(rest: &mut &str) -> Option<String> {
+    let (name, tail) = match rest.strip_prefix('<') {
+        Some(quoted) => {
+            let end = quoted.find('>')?;
+            (&quoted[..end], &quoted[end + 1..])
+        }
+        None => {
+            let end = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+            rest.split_at(end)
+        }
+    };
+    if name.len() < 3 {
+        return None;
+    }
+    *rest = tail;
+    Some(name.to_owned())
+}
+
+/// Take a number from the start of `rest`.
+fn take_number(rest: &mut &str) -> Option<i64> {
+    let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let number = rest[..end].parse().ok()?;
+    *rest = &rest[end..];
+    Some(number)
+}
+
+/// Take a time of the form `[+-]hh[:mm[:ss]]` from the start of `rest`, in
+/// seconds.
+fn take_tz_offset(rest: &mut &str) -> Option<i64> {
+    let sign = match rest.chars().next() {
+        Some('-') => -1,
+        _ => 1,
+    };
+    *rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+    let mut offset = take_number(rest)? * 3600;
+    for unit in [60, 1] {
+        let Some(tail) = rest.strip_prefix(':') else { break };
+        *rest = tail;
+        offset += take_number(rest)? * unit;
+    }
+    Some(sign * offset)
+}
+
+/// Take a date and optional time at which daylight saving time starts or
+/// ends from the start of `rest`. The time defaults to 2:00.
+fn take_rule_date(rest: &mut &str) -> Option<(RuleDate, i64)> {
+    let date = if let Some(tail) = rest.strip_prefix('J') {
+        *rest = tail;
+        let day = take_number(rest)?;
+        (1..=365).contains(&day).then_some(RuleDate::Julian(day))?
+    } else if let Some(tail) = rest.strip_prefix('M') {
+        *rest = tail;
+        let month = take_number(rest)?;
+        *rest = rest.strip_prefix('.')?;
+        let week = take_number(rest)?;
+        *rest = rest.strip_prefix('.')?;
+        let weekday = take_number(rest)?;
+        let valid = (1..=12).contains(&month) && (1..=5).contains(&week) && weekday < 7;
+        valid.then_some(RuleDate::Month(month, week, weekday))?
+    } else {
+        let day = take_number(rest)?;
+        (day <= 365).then_some(RuleDate::Day(day))?
+    };
+    let time = match rest.strip_prefix('/') {
+        Some(tail) => {
+            *rest = tail;
+            take_tz_offset(rest)?
+        }
+        None => 7200,
+    };
+    Some((date, time))
+}
+
+/// A zone read from a TZif file in the tz database.
+#[derive(Debug, Clone, PartialEq)]
+struct TzFile {
+    /// The times at which the offset changes, and the index in `types` of the
+    /// offset from then on
+    transitions: Vec<(i64, usize)>,
+    /// The offset east of UTC, whether it is daylight saving time, and the
+    /// name of the zone
+    types: Vec<(i64, bool, String)>,
+    /// The rule for the times after the last transition
+    rule: Option<TzRule>,
+}
+
+impl TzFile {
+    /// Read the zone `name` from the directory in `TZDIR`, or the system's
+    /// zoneinfo directory.
+    fn load(name: &str) -> Option<Self> {
+        // The name can't leave the directory
+        if name.is_empty() || name.starts_with('/') || name.split('/').any(|x| x == "..") {
+            return None;
+        }
+        let dir = std::env::var_os("TZDIR").unwrap_or_else(|| "/usr/share/zoneinfo".into());
+        Self::parse(&std::fs::read(std::path::Path::new(&dir).join(name)).ok()?)
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        // The version, and the counts of UT indicators, standard time
+        // indicators, leap seconds, transitions, types and name bytes
+        let header = |data: &[u8]| -> Option<(u8, [usize; 6])> {
+            if data.get(..4)? != b"TZif" {
+                return None;
+            }
+            let mut counts = [0; 6];
+            for (i, count) in counts.iter_mut().enumerate() {
+                let bytes = data.get(20 + i * 4..24 + i * 4)?;
+                *count = u32::from_be_bytes(bytes.try_into().ok()?) as usize;
+            }
+            Some((data[4], counts))
+        };
+        let block_len = |[ut, std, leap, times, types, chars]: [usize; 6], time_size: usize| {
+            44 + times * (time_size + 1) + types * 6 + chars + leap * (time_size + 4) + std + ut
+        };
+        let (version, counts) = header(data)?;
+        // Files from version 2 on repeat the data with 64 bit times, followed
+        // by a rule
+        let (data, counts, time_size) = if version >= b'2' {
+            let data = data.get(block_len(counts, 4)..)?;
+            (data, header(data)?.1, 8)
+        } else {
+            (data, counts, 4)
+        };
+        let [_, _, _, time_count, type_count, char_count] = counts;
+        let mut parts: [&[u8]; 4] = [&[]; 4];
+        let mut pos = 44;
+        let sizes = [time_count * time_size, time_count, type_count * 6, char_count];
+        for (part, size) in parts.iter_mut().zip(sizes) {
+            *part = data.get(pos..pos + size)?;
+            pos += size;
+        }
+        let [times, indices, types, chars] = parts;
+        let transitions: Vec<_> = times
+            .chunks(time_size)
+            .zip(indices)
+            .map(|(time, index)| {
+                let time = if time_size == 8 {
+                    i64::from_be_bytes(time.try_into().unwrap())
+                } else {
+                    i64::from(i32::from_be_bytes(time.try_into().unwrap()))
+                };
+                (time, usize::from(*index))
+            })
+            .collect();
+        let types: Vec<_> = types
+            .chunks(6)
+            .map(|ty| {
+                let offset = i64::from(i32::from_be_bytes(ty[..4].try_into().unwrap()));
+                let name = chars.get(usize::from(ty[5])..).unwrap_or_default();
+                let name = name.split(|x| *x == 0).next().unwrap_or_default();
+                (offset, ty[4] != 0, String::from_utf8_lossy(name).into_owned())
+            })
+            .collect();
+        if types.is_empty() || transitions.iter().any(|x| x.1 >= types.len()) {
+            return None;
+        }
+        let rule = if version >= b'2' {
+            let footer = data.get(block_len(counts, 8)..).unwrap_or_default();
+            std::str::from_utf8(footer)
+                .ok()
+                .and_then(|x| TzRule::parse(x.trim_matches('\n')))
+        } else {
+            None
+        };
+        Some(Self { transitions, types, rule })
+    }
+
+    /// The offset east of UTC at `secs` since the epoch, the name of the
+    /// zone then, and whether daylight saving time is in effect.
+    fn at(&self, secs: i64) -> (i64, &str, bool) {
+        let index = self.transitions.partition_point(|x| x.0 <= secs);
+        if index == self.transitions.len() {
+            if let Some(rule) = &self.rule {
+                return rule.at(secs);
+            }
+        }
+        // Before the first transition, the first standard time type applies
+        let ty = match index {
+            0 => self.types.iter().position(|x| !x.1).unwrap_or(0),
+            _ => self.transitions[index - 1].1,
+        };
+        let (offset, dst, name) = &self.types[ty];
+        (*offset, name.as_str(), *dst)
+    }
+}
+
+/// The offset of local time from UTC in seconds, and whether daylight saving
+/// time is in effect.
+#[cfg(unix)]
+fn local_offset(secs: i64) -> (i64, bool) {
+    let time = secs as libc::time_t;
+    // SAFETY: `tm` is plain data and localtime_r only writes to it
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return (0, false);
+    }
+    (i64::from(tm.tm_gmtoff), tm.tm_isdst > 0)
+}
+
+#[cfg(not(unix))]
+fn local_offset(_secs: i64) -> (i64, bool) {
+    (0, false)
+}
+
+/// The abbreviation of the local time zone, such as "CET".
+#[cfg(unix)]
+fn local_zone_name(secs: i64) -> Option<String> {
+    let time = secs as libc::time_t;
+    // SAFETY: as in `local_offset`. `tm_zone` points to static storage.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() || tm.tm_zone.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(tm.tm_zone) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn local_zone_name(_secs: i64) -> Option<String> {
+    None
+}
+
+/// A time broken down into calendar fields.
+struct Decoded {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+    subsec_picos: i128,
+    /// Days since Sunday
+    day_of_week: i64,
+    /// Days since January 1st
+    day_of_year: i64,
+    /// Seconds east of UTC
+    offset: i64,
+    dst: Option<bool>,
+}
+
+impl Decoded {
+    fn new(time: Time, zone: &Zone) -> Self {
+        let (offset, dst) = zone.offset(time.secs());
+        let local = Time(time.0 + i128::from(offset) * PICOS);
+        let days = local.secs().div_euclid(86400);
+        let time_of_day = local.secs().rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        Decoded {
+            year,
+            month,
+            day,
+            hour: time_of_day / 3600,
+            minute: time_of_day / 60 % 60,
+            second: time_of_day % 60,
+            subsec_picos: local.subsec_picos(),
+            // The epoch was a Thursday
+            day_of_week: (days + 4).rem_euclid(7),
+            day_of_year: days - days_from_civil(year, 1, 1),
+            offset,
+            dst,
+        }
+    }
+}
+
+#[defun]
+fn decode_time<'ob>(
+    time: Option<Object>,
+    zone: Option<Object>,
+    form: Option<Object>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let time = Decoded::new(lisp_time(time.unwrap_or_default())?, &Zone::new(zone)?);
+    let second = match form {
+        Some(form) if !form.is_nil() && time.subsec_picos != 0 => {
+            Time(i128::from(time.second) * PICOS + time.subsec_picos).to_ticks(cx)?
+        }
+        _ => cx.add(time.second),
+    };
+    let dst = match time.dst {
+        Some(dst) => cx.add(dst),
+        None => NIL,
+    };
+    Ok(list![
+        second,
+        time.minute,
+        time.hour,
+        time.day,
+        time.month,
+        time.year,
+        time.day_of_week,
+        dst,
+        time.offset;
+        cx
+    ])
+}
+
+#[defun]
+fn encode_time<'ob>(
+    time: Object,
+    obsolescent_arguments: &[Object],
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    // The old calling convention was SECOND MINUTE HOUR DAY MONTH YEAR &optional ZONE
+    let (fields, zone) = match obsolescent_arguments {
+        [] => {
+            let fields = time.as_list()?.collect::<Result<Vec<_>, _>>()?;
+            let zone = fields.get(8).copied();
+            (fields, zone)
+        }
+        [_, _, _, _, _, rest @ ..] => {
+            let mut fields = vec![time];
+            fields.extend_from_slice(&obsolescent_arguments[..5]);
+            (fields, rest.last().copied())
+        }
+        _ => bail!("Wrong number of arguments to encode-time"),
+    };
+    let [second, minute, hour, day, month, year, ..] = fields[..] else {
+        bail!("Invalid decoded time: {time}")
+    };
+    ensure!(!second.is_nil(), "Invalid decoded time: {time}");
+    let second = lisp_time(second)?;
+    let mut ints = [0i64; 5];
+    for (int, field) in ints.iter_mut().zip([minute, hour, day, month, year]) {
+        *int = field.try_into()?;
+    }
+    let [minute, hour, day, month, year] = ints;
+    // Out of range fields carry over, so that month 13 is January of the next year
+    let days =
+        days_from_civil(year + (month - 1).div_euclid(12), (month - 1).rem_euclid(12) + 1, 1);
+    let secs = (days + day - 1) * 86400 + hour * 3600 + minute * 60;
+    let zone = Zone::new(zone)?;
+    let (guess, _) = zone.offset(secs);
+    let (offset, _) = zone.offset(secs - guess);
+    Ok(Time(i128::from(secs - offset) * PICOS + second.0).to_list(cx))
+}
+
+/// The names of the months and days of the week in the C locale. The names
+/// of other locales are not available, so these are used whatever the value
+/// of `system-time-locale`.
+pub(crate) const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+pub(crate) const DAY_NAMES: [&str; 7] =
+    ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+/// The number of ISO 8601 weeks in `year`.
+fn iso_weeks_in_year(year: i64) -> i64 {
+    let jan1 =
+        |year: i64| (year + year.div_euclid(4) - year.div_euclid(100) + year.div_euclid(400)) % 7;
+    if jan1(year) == 4 || jan1(year - 1) == 3 {
+        53
+    } else {
+        52
+    }
+}
+
+/// The ISO 8601 year and week number of `time`.
+fn iso_week(time: &Decoded) -> (i64, i64) {
+    let weekday = (time.day_of_week + 6) % 7 + 1;
+    let week = (time.day_of_year + 1 - weekday + 10) / 7;
+    if week < 1 {
+        (time.year - 1, iso_weeks_in_year(time.year - 1))
+    } else if week > iso_weeks_in_year(time.year) {
+        (time.year + 1, 1)
+    } else {
+        (time.year, week)
+    }
+}
+
+/// Format `time` according to `format`, which uses the directives of
+/// strftime. Padding can be changed with the `-`, `_` and `0` flags and a
+/// field width, and text can be upcased with `^` or have its case swapped
+/// with `#`.
+fn format_time(format: &str, time: &Decoded, zone_name: &str, secs: i64) -> Result<String> {
+    let mut out = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(chr) = chars.next() {
+        if chr != '%' {
+            out.push(chr);
+            continue;
+        }
+        let mut pad = None;
+        let mut case = None;
+        while let Some(flag) = chars.next_if(|x| matches!(x, '-' | '_' | '0' | '^' | '#')) {
+            match flag {
+                '^' | '#' => case = Some(flag),
+                _ => pad = Some(flag),
+            }
+        }
+        let mut width = None;
+        while let Some(digit) = chars.peek().and_then(|x| x.to_digit(10)) {
+            width = Some(width.unwrap_or(0) * 10 + digit as usize);
+            chars.next();
+        }
+        let mut colons = 0;
+        while chars.next_if_eq(&':').is_some() {
+            colons += 1;
+        }
+        // The alternative representations of the E and O modifiers are the
+        // same as the normal ones in the C locale
+        chars.next_if(|x| matches!(x, 'E' | 'O'));
+        let Some(directive) = chars.next() else {
+            out.push('%');
+            break;
+        };
+        let number = |value: i64, default_pad: char, default_width: usize| {
+            let width = width.unwrap_or(default_width);
+            let digits = value.unsigned_abs().to_string();
+            let sign = if value < 0 { "-" } else { "" };
+            match pad.unwrap_or(default_pad) {
+                '-' => format!("{sign}{digits}"),
+                '_' | ' ' => format!("{:>width$}", format!("{sign}{digits}")),
+                _ => {
+                    let width = width.saturating_sub(sign.len());
+                    format!("{sign}{digits:0>width$}")
+                }
+            }
+        };
+        let text = |text: &str| {
+            let text = match case {
+                Some('^') => text.to_uppercase(),
+                Some('#') if text.chars().any(char::is_lowercase) => text.to_uppercase(),
+                Some('#') => text.to_lowercase(),
+                _ => text.to_owned(),
+            };
+            match width {
+                Some(width) if pad == Some('0') => format!("{text:0>width$}"),
+                Some(width) => format!("{text:>width$}"),
+                None => text,
+            }
+        };
+        let hour12 = (time.hour + 11) % 12 + 1;
+        let month_name = MONTH_NAMES[time.month as usize - 1];
+        let day_name = DAY_NAMES[time.day_of_week as usize];
+        let formatted = match directive {
+            'a' => text(&day_name[..3]),
+            'A' => text(day_name),
+            'b' | 'h' => text(&month_name[..3]),
+            'B' => text(month_name),
+            'c' => text(&format_time("%a %b %e %H:%M:%S %Y", time, zone_name, secs)?),
+            'C' => number(time.year.div_euclid(100), '0', 2),
+            'd' => number(time.day, '0', 2),
+            'D' | 'x' => text(&format_time("%m/%d/%y", time, zone_name, secs)?),
+            'e' => number(time.day, ' ', 2),
+            'F' => text(&format_time("%Y-%m-%d", time, zone_name, secs)?),
+            'g' => number(iso_week(time).0.rem_euclid(100), '0', 2),
+            'G' => number(iso_week(time).0, '0', 1),
+            'H' => number(time.hour, '0', 2),
+            'I' => number(hour12, '0', 2),
+            'j' => number(time.day_of_year + 1, '0', 3),
+            'k' => number(time.hour, ' ', 2),
+            'l' => number(hour12, ' ', 2),
+            'm' => number(time.month, '0', 2),
+            'M' => number(time.minute, '0', 2),
+            'n' => "\n".to_owned(),
+            'N' => {
+                let nanos = format!("{:09}", time.subsec_picos / 1000);
+                let digits = width.unwrap_or(9).min(9);
+                nanos[..digits].to_owned()
+            }
+            'p' => text(if time.hour < 12 { "AM" } else { "PM" }),
+            'P' => text(if time.hour < 12 { "am" } else { "pm" }),
+            'r' => text(&format_time("%I:%M:%S %p", time, zone_name, secs)?),
+            'R' => text(&format_time("%H:%M", time, zone_name, secs)?),
+            's' => number(secs, '0', 1),
+            'S' => number(time.second, '0', 2),
+            't' => "\t".to_owned(),
+            'T' | 'X' => text(&format_time("%H:%M:%S", time, zone_name, secs)?),
+            'u' => number((time.day_of_week + 6) % 7 + 1, '0', 1),
+            'U' => number((time.day_of_year + 7 - time.day_of_week) / 7, '0', 2),
+            'V' => number(iso_week(time).1, '0', 2),
+            'w' => number(time.day_of_week, '0', 1),
+            'W' => number((time.day_of_year + 7 - (time.day_of_week + 6) % 7) / 7, '0', 2),
+            'y' => number(time.year.rem_euclid(100), '0', 2),
+            'Y' => number(time.year, '0', 1),
+            'z' => {
+                let sign = if time.offset < 0 { '-' } else { '+' };
+                let offset = time.offset.abs();
+                let (hours, minutes, seconds) = (offset / 3600, offset / 60 % 60, offset % 60);
+                match colons {
+                    0 => format!("{sign}{hours:02}{minutes:02}"),
+                    1 => format!("{sign}{hours:02}:{minutes:02}"),
+                    _ => format!("{sign}{hours:02}:{minutes:02}:{seconds:02}"),
+                }
+            }
+            'Z' => text(zone_name),
+            '%' => "%".to_owned(),
+            _ => bail!("Invalid format directive %{directive}"),
+        };
+        out.push_str(&formatted);
+    }
+    Ok(out)
+}
+
+#[defun]
+fn format_time_string(
+    format_string: &str,
+    time: Option<Object>,
+    zone: Option<Object>,
+) -> Result<String> {
+    let time = lisp_time(time.unwrap_or_default())?;
+    let zone = Zone::new(zone)?;
+    let decoded = Decoded::new(time, &zone);
+    let zone_name = match &zone {
+        Zone::Local => local_zone_name(time.secs()),
+        Zone::Fixed(0) => Some("UTC".to_owned()),
+        Zone::Fixed(_) => None,
+        Zone::Rule(rule) => Some(rule.at(time.secs()).1.to_owned()),
+        Zone::Database(file) => Some(file.at(time.secs()).1.to_owned()),
+    };
+    let zone_name = match zone_name {
+        Some(name) => name,
+        None => format_time("%z", &decoded, "", 0)?,
+    };
+    format_time(format_string, &decoded, &zone_name, time.secs())
+}
+
+/// The fields of a date and time that has been parsed. Fields that were not
+/// present are `None`.
+#[derive(Debug, Default, PartialEq)]
+struct ParsedTime {
+    second: Option<i64>,
+    minute: Option<i64>,
+    hour: Option<i64>,
+    day: Option<i64>,
+    month: Option<i64>,
+    year: Option<i64>,
+    day_of_week: Option<i64>,
+    /// Seconds east of UTC
+    zone: Option<i64>,
+}
+
+impl ParsedTime {
+    /// The decoded time list of the fields. Daylight saving time is unknown.
+    fn to_list(&self, cx: &Context) -> Object {
+        let field = |x: Option<i64>| x.map_or(NIL, |x| cx.add(x));
+        list![
+            field(self.second),
+            field(self.minute),
+            field(self.hour),
+            field(self.day),
+            field(self.month),
+            field(self.year),
+            field(self.day_of_week),
+            -1,
+            field(self.zone);
+            cx
+        ]
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// The index in `names` of the name that `word` is or abbreviates.
+fn name_index(word: &str, names: &[&str]) -> Option<i64> {
+    let word = word.to_ascii_lowercase();
+    let word = word.trim_end_matches('.');
+    if word.len() < 3 {
+        return None;
+    }
+    names.iter().position(|name| word.starts_with(name)).map(|i| i as i64)
+}
+
+/// The offset of a zone written as a name or as `+HHMM`.
+fn zone_offset(word: &str) -> Option<i64> {
+    let hours = match word.to_ascii_uppercase().as_str() {
+        "Z" | "UT" | "UTC" | "GMT" => 0,
+        "EST" => -5,
+        "EDT" | "AST" => -4,
+        "CST" => -6,
+        "CDT" => -5,
+        "MST" => -7,
+        "MDT" => -6,
+        "PST" => -8,
+        "PDT" => -7,
+        _ => {
+            let (sign, digits) = match word.split_at_checked(1)? {
+                ("+", digits) => (1, digits),
+                ("-", digits) => (-1, digits),
+                _ => return None,
+            };
+            let digits = digits.replace(':', "");
+            if !matches!(digits.len(), 2 | 4) || !digits.bytes().all(|x| x.is_ascii_digit()) {
+                return None;
+            }
+            let hours: i64 = digits[..2].parse().ok()?;
+            let minutes: i64 = digits.get(2..).map_or(Ok(0), str::parse).ok()?;
+            return Some(sign * (hours * 3600 + minutes * 60));
+        }
+    };
+    Some(hours * 3600)
+}
+
+/// Parse `HH:MM[:SS[.FRACTION]]`. The fraction is dropped.
+fn parse_clock(word: &str) -> Option<(i64, i64, Option<i64>)> {
+    let mut parts = word.split(':');
+    let hour = parts.next()?.parse().ok()?;
+    let minute = parts.next()?.parse().ok()?;
+    let second = match parts.next() {
+        Some(second) => Some(second.split(['.', ',']).next()?.parse().ok()?),
+        None => None,
+    };
+    if parts.next().is_some() || hour > 24 || minute > 59 {
+        return None;
+    }
+    Some((hour, minute, second))
+}
+
+/// Parse an ISO 8601 date and time, such as `2024-02-29T13:45:30+01:00`.
+/// Either part can be left out, and the separators of the basic format are
+/// optional.
+fn parse_iso8601(string: &str) -> Option<ParsedTime> {
+    let mut parsed = ParsedTime::default();
+    let (date, time) = match string.find(['T', 't', ' ']) {
+        Some(i) => (&string[..i], Some(&string[i + 1..])),
+        None if string.contains(':') => ("", Some(string)),
+        None => (string, None),
+    };
+    if !date.is_empty() {
+        let digits = date.replace('-', "");
+        if !digits.bytes().all(|x| x.is_ascii_digit()) {
+            return None;
+        }
+        let (year, rest) = digits.split_at_checked(4)?;
+        parsed.year = Some(year.parse().ok()?);
+        match rest.len() {
+            0 => {}
+            2 | 4 => {
+                parsed.month = Some(rest[..2].parse().ok()?);
+                parsed.day = rest.get(2..4).map(str::parse).transpose().ok()?;
+            }
+            _ => return None,
+        }
+    }
+    if let Some(time) = time {
+        let end = time.find(['Z', 'z', '+', '-']).unwrap_or(time.len());
+        let (clock, zone) = time.split_at(end);
+        let (hour, minute, second) = match clock.contains(':') {
+            true => parse_clock(clock)?,
+            false => {
+                let digits = clock.split(['.', ',']).next()?;
+                if !matches!(digits.len(), 4 | 6) {
+                    return None;
+                }
+                let second = digits.get(4..6).map(str::parse).transpose().ok()?;
+                (digits[..2].parse().ok()?, digits[2..4].parse().ok()?, second)
+            }
+        };
+        parsed.hour = Some(hour);
+        parsed.minute = Some(minute);
+        parsed.second = Some(second.unwrap_or(0));
+        if !zone.is_empty() {
+            parsed.zone = Some(zone_offset(zone)?);
+        }
+    }
+    Some(parsed)
+}
+
+/// Parse a date in the free form of email headers, such as
+/// `Thu, 29 Feb 2024 13:45:30 +0100`. Fields that are not recognized are
+/// ignored.
+fn parse_rfc822(string: &str) -> ParsedTime {
+    let mut parsed = ParsedTime::default();
+    for word in string.split(|c: char| c.is_whitespace() || c == ',').filter(|x| !x.is_empty()) {
+        if let Some((hour, minute, second)) = parse_clock(word) {
+            parsed.hour = Some(hour);
+            parsed.minute = Some(minute);
+            parsed.second = Some(second.unwrap_or(0));
+        } else if let Some(offset) = zone_offset(word) {
+            parsed.zone = Some(offset);
+        } else if let Some(date) = parse_iso8601(word).filter(|_| word.contains('-')) {
+            parsed.year = date.year;
+            parsed.month = date.month;
+            parsed.day = date.day;
+        } else if let Some(month) = name_index(word, &MONTHS) {
+            parsed.month = Some(month + 1);
+        } else if let Some(day) = name_index(word, &WEEKDAYS) {
+            parsed.day_of_week = Some(day);
+        } else if let Ok(number) = word.parse::<i64>() {
+            if parsed.day.is_none() && word.len() <= 2 && (1..=31).contains(&number) {
+                parsed.day = Some(number);
+            } else if parsed.year.is_none() {
+                // Two digit years are in the nearest century
+                parsed.year = Some(match (word.len(), number) {
+                    (2, 0..=49) => number + 2000,
+                    (2, _) => number + 1900,
+                    _ => number,
+                });
+            }
+        }
+    }
+    parsed
+}
+
+#[defun]
+fn parse_time_string<'ob>(string: &str, _form: Option<Object>, cx: &'ob Context) -> Object<'ob> {
+    let string = string.trim();
+    let parsed = parse_iso8601(string).unwrap_or_else(|| parse_rfc822(string));
+    parsed.to_list(cx)
+}
+
+#[defun]
+fn iso8601_parse<'ob>(
+    string: &str,
+    _form: Option<Object>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    match parse_iso8601(string.trim()) {
+        Some(parsed) => Ok(parsed.to_list(cx)),
+        None => bail!("Unable to parse {string} as an ISO 8601 time"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_lisp_time() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let list = list![1, 2, 3, 4; cx];
+        let time = lisp_time(list).unwrap();
+        assert_eq!(time, Time(65538 * PICOS + 3_000_004));
+        assert_eq!(time.to_list(cx), list);
+        assert_eq!(lisp_time(Cons::new(3, 2, cx).into()).unwrap(), Time(PICOS * 3 / 2));
+        assert_eq!(lisp_time(cx.add(1.5)).unwrap(), Time(PICOS * 3 / 2));
+        assert_eq!(Time(PICOS * 3 / 2).to_ticks(cx).unwrap(), Cons::new(15, 10, cx).into());
+        assert!(lisp_time(cx.add("now")).is_err());
+
+        assert_eq!(time_add(cx.add(1), cx.add(2), cx).unwrap(), cx.add(3));
+        assert_eq!(time_subtract(cx.add(1), cx.add(0.5), cx).unwrap(), cx.add(0.5));
+        assert_eq!(time_add(list, cx.add(1), cx).unwrap(), list![1, 3, 3, 4; cx]);
+        assert!(time_less_p(cx.add(1), list).unwrap());
+        assert!(time_equal_p(cx.add(2), Cons::new(4, 2, cx).into()).unwrap());
+    }
+
+    #[test]
+    fn test_decode_time() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let utc = Some(sym::TRUE.into());
+        // 2024-02-29 13:45:30 UTC
+        let time = cx.add(1_709_214_330);
+        let decoded = decode_time(Some(time), utc, None, cx).unwrap();
+        assert_eq!(decoded, list![30, 45, 13, 29, 2, 2024, 4, NIL, 0; cx]);
+        let decoded = decode_time(Some(time), Some(cx.add(-3600)), None, cx).unwrap();
+        assert_eq!(decoded, list![30, 45, 12, 29, 2, 2024, 4, NIL, -3600; cx]);
+        let decoded = decode_time(Some(time), Some(cx.add("EST5EDT")), None, cx).unwrap();
+        assert_eq!(decoded, list![30, 45, 8, 29, 2, 2024, 4, NIL, -18000; cx]);
+        let decoded = decode_time(Some(cx.add(-1)), utc, None, cx).unwrap();
+        assert_eq!(decoded, list![59, 59, 23, 31, 12, 1969, 3, NIL, 0; cx]);
+
+        let fields = list![30, 45, 13, 29, 2, 2024, NIL, NIL, sym::TRUE; cx];
+        let encoded = encode_time(fields, &[], cx).unwrap();
+        assert_eq!(lisp_time(encoded).unwrap(), Time::from_secs(1_709_214_330));
+        let args = [45, 13, 29, 14, 2023, 0].map(|x| cx.add(x));
+        let encoded = encode_time(cx.add(30), &args, cx).unwrap();
+        assert_eq!(lisp_time(encoded).unwrap(), Time::from_secs(1_709_214_330));
+    }
+
+    #[test]
+    fn test_time_zones() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let offset = |tz, secs| Zone::new(Some(cx.add(tz))).unwrap().offset(secs);
+        // 2024-07-01 12:00:00 UTC
+        assert_eq!(offset("EST5EDT", 1_719_835_200), (-14400, Some(true)));
+        assert_eq!(offset("EST5EDT", 1_709_214_330), (-18000, Some(false)));
+        // Daylight saving time starts at 2024-03-31 01:00:00 UTC
+        let cet = "CET-1CEST,M3.5.0,M10.5.0/3";
+        assert_eq!(offset(cet, 1_711_846_799), (3600, Some(false)));
+        assert_eq!(offset(cet, 1_711_846_800), (7200, Some(true)));
+        // In the southern hemisphere it spans the new year
+        let aest = "AEST-10AEDT,M10.1.0,M4.1.0/3";
+        assert_eq!(offset(aest, 1_704_110_400), (39600, Some(true)));
+        assert_eq!(offset(aest, 1_719_835_200), (36000, Some(false)));
+        assert_eq!(offset("<+0330>-3:30", 0), (12600, Some(false)));
+        assert!(Zone::new(Some(cx.add("Nowhere/Special"))).is_err());
+        assert!(TzFile::load("../etc/passwd").is_none());
+
+        let time = Some(cx.add(1_719_835_200));
+        let zone = Some(cx.add(aest));
+        assert_eq!(format_time_string("%H %Z %z", time, zone).unwrap(), "22 AEST +1000");
+
+        // A version 2 file with an empty version 1 block, one transition at
+        // the epoch and a rule for the times after it
+        let header = |counts: [u32; 6]| {
+            let mut header = b"TZif2".to_vec();
+            header.extend([0; 15]);
+            counts.iter().for_each(|x| header.extend(x.to_be_bytes()));
+            header
+        };
+        let mut data = header([0; 6]);
+        data.extend(header([0, 0, 0, 1, 2, 8]));
+        data.extend(0_i64.to_be_bytes());
+        data.push(1);
+        data.extend(600_i32.to_be_bytes());
+        data.extend([0, 0]);
+        data.extend(3600_i32.to_be_bytes());
+        data.extend([0, 4]);
+        data.extend(b"LMT\0CET\0\nCET-1CEST,M3.5.0,M10.5.0/3\n");
+        let file = TzFile::parse(&data).unwrap();
+        assert_eq!(file.at(-1), (600, "LMT", false));
+        assert_eq!(file.at(0), (3600, "CET", false));
+        assert_eq!(file.at(1_711_846_800), (7200, "CEST", true));
+        assert!(TzFile::parse(&data[..60]).is_none());
+    }
+
+    #[test]
+    fn test_format_time_string() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let utc = Some(sym::TRUE.into());
+        let time = Some(cx.add(1_709_214_330.25));
+        let format = |format| format_time_string(format, time, utc).unwrap();
+        assert_eq!(format("%Y-%m-%d %H:%M:%S %Z"), "2024-02-29 13:45:30 UTC");
+        assert_eq!(format("%a %A %b %B %^a"), "Thu Thursday Feb February THU");
+        assert_eq!(format("%e|%-d|%_m|%5Y|%j|%3N|%%"), "29|29| 2|02024|060|250|%");
+        assert_eq!(format("%I%p %l %u %w %U %W %V %G"), "01PM  1 4 4 08 09 09 2024");
+        assert_eq!(format("%F %T %s %z %:z"), "2024-02-29 13:45:30 1709214330 +0000 +00:00");
+        let zone = Some(cx.add(-12600));
+        assert_eq!(format_time_string("%R %z %Z", time, zone).unwrap(), "10:15 -0330 -0330");
+        assert!(format_time_string("%Q", time, utc).is_err());
+    }
+
+    #[test]
+    fn test_parse_time_string() {
+        let expected = ParsedTime {
+            second: Some(30),
+            minute: Some(45),
+            hour: Some(13),
+            day: Some(29),
+            month: Some(2),
+            year: Some(2024),
+            day_of_week: None,
+            zone: Some(3600),
+        };
+        assert_eq!(parse_iso8601("2024-02-29T13:45:30+01:00"), Some(expected));
+        let expected = ParsedTime { day_of_week: Some(4), ..expected };
+        assert_eq!(parse_rfc822("Thu, 29 Feb 2024 13:45:30 +0100"), expected);
+        assert_eq!(parse_rfc822("Thursday February 29 13:45:30 CET 2024").month, Some(2));
+        assert_eq!(parse_rfc822("29 Feb 24").year, Some(2024));
+
+        let date = parse_iso8601("20240229").unwrap();
+        assert_eq!(
+            (date.year, date.month, date.day, date.hour),
+            (Some(2024), Some(2), Some(29), None)
+        );
+        let time = parse_iso8601("T1345Z").unwrap();
+        assert_eq!((time.hour, time.minute, time.zone), (Some(13), Some(45), Some(0)));
+        assert_eq!(parse_iso8601("Feb 29"), None);
+
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let parsed = parse_time_string("2024-02-29", None, cx);
+        assert_eq!(parsed, list![NIL, NIL, NIL, 29, 2, 2024, NIL, -1, NIL; cx]);
+        assert!(iso8601_parse("yesterday", None, cx).is_err());
+    }
+}
+
+defsym!(WALL);
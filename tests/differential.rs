@@ -0,0 +1,20 @@
+//! Differential tests between the interpreter and the bytecode VM. The corpus
+//! is in `differential/init.el`, which compiles each case with the byte
+//! compiler. It is run in its own rune process because loading the compiler
+//! defines functions that the unit tests expect to be undefined.
+use std::process::Command;
+
+#[test]
+fn interpreter_matches_bytecode() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rune"))
+        .args(["--batch", "--load", "--init-directory=tests/differential"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("rune should start");
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,89 @@
+;;; init.el --- Differential tests of the interpreter and the VM -*- lexical-binding: t -*-
+
+;; Each case is a lambda followed by the argument lists to call it with. The
+;; lambda is evaluated by the interpreter and compiled with `byte-compile', and
+;; both versions must agree, including on which calls signal an error. Cases
+;; for the list functions that have their own opcode also check that the
+;; compiler emitted it. This file is loaded as the init file of a batch rune
+;; by tests/differential.rs. When RUNE_DIFFERENTIAL_ITERATIONS is set, each
+;; call is also timed that many times, which is how benches/differential.rs
+;; reports the compiler's payoff.
+
+(defconst differential-corpus
+  '((nil (lambda () 5) ())
+    (nil (lambda (x) (+ x 5)) (7) (-2.5) (a))
+    (nil (lambda (x &optional y) (+ x y)) (3 4) (3))
+    (nil (lambda (x y z) (+ x y z)) (1 2 3))
+    (nil (lambda (&rest x) (apply '+ x)) () (1 2 3))
+    (nil (lambda (x) (if x 2 3)) (nil) (t))
+    (nil (lambda (x) (let ((y 0)) (while (< 0 x) (setq x (1- x)) (setq y (1+ y))) y))
+         (5) (0))
+    (nil (lambda (x) (symbol-name x)) (aref) (5))
+    (nil (lambda () (let ((load-path 5)) load-path)) ())
+    (nil (lambda () (list 1 2 3 4 5 6)) ())
+    (nil (lambda (y) (condition-case nil (symbol-name y) (error (+ y 4)))) (3) (floor))
+    (byte-car (lambda (x) (car x)) ((1 2)) (nil) (5))
+    (byte-cons (lambda (x y) (cons x y)) (1 2) (a (b)))
+    (byte-length (lambda (x) (length x)) ((1 2 3)) ("abcd") (5))
+    (byte-nth (lambda (n l) (nth n l)) (1 (a b c)) (5 (a)) (0 nil) (-1 (a)) (1 (a . b)) (a (a)))
+    (byte-not (lambda (x) (not x)) (nil) (1))
+    (byte-memq (lambda (x l) (memq x l)) (b (a b c)) (d (a b)) (a 5))
+    (byte-member (lambda (x l) (member x l)) ((1) (a (1) b)) ("x" ("x")) (c (a b)))
+    (byte-assq (lambda (k l) (assq k l)) (b ((a . 1) (b . 2))) (c ((a . 1) x)) (a 5))
+    (byte-car-safe (lambda (x) (car-safe x)) ((1 . 2)) (5) (nil))
+    (byte-cdr-safe (lambda (x) (cdr-safe x)) ((1 . 2)) (5) (nil))
+    (byte-nreverse (lambda (x y) (nreverse (list x y))) (1 2) (nil (a)))
+    (byte-setcar (lambda (x) (let ((c (list 1 2))) (setcar c x) (setcdr c x) c)) (a) ((3))))
+  "Cases of the form (OPCODE LAMBDA ARGS...).
+OPCODE, when non-nil, is the variable holding the opcode that the compiled
+LAMBDA must use, so a call the compiler open codes is tested in the VM.")
+
+(defun differential-call (func args)
+  "Call FUNC with ARGS, returning a list of the value or `error'."
+  (condition-case nil
+      (list (apply func args))
+    (error 'error)))
+
+(defun differential-time (func args iterations)
+  "Return the seconds taken to call FUNC with ARGS ITERATIONS times."
+  (let ((start (float-time))
+        (i 0))
+    (while (< i iterations)
+      (differential-call func args)
+      (setq i (1+ i)))
+    (- (float-time) start)))
+
+(let ((iterations (getenv "RUNE_DIFFERENTIAL_ITERATIONS"))
+      (failures 0))
+  (setq iterations (and iterations (string-to-number iterations)))
+  (dolist (case differential-corpus)
+    (let* ((opcode (car case))
+           (source (cadr case))
+           (interpreted (eval source t))
+           (compiled (byte-compile source)))
+      (cond
+       ((not (byte-code-function-p compiled))
+        (setq failures (1+ failures))
+        (message "%S was not compiled" source))
+       ((and opcode (not (memq (symbol-value opcode) (append (aref compiled 1) nil))))
+        (setq failures (1+ failures))
+        (message "%S was not compiled to `%s'" source opcode)))
+      (dolist (args (cddr case))
+        (let ((expect (differential-call interpreted args))
+              (actual (differential-call compiled args)))
+          (unless (equal expect actual)
+            (setq failures (1+ failures))
+            (message "%S called with %S: interpreter %S, bytecode %S"
+                     source args expect actual))))
+      (when iterations
+        (let ((interpreter-time 0)
+              (bytecode-time 0))
+          (dolist (args (cddr case))
+            (setq interpreter-time
+                  (+ interpreter-time (differential-time interpreted args iterations)))
+            (setq bytecode-time
+                  (+ bytecode-time (differential-time compiled args iterations))))
+          (message "%S: interpreter %ss, bytecode %ss (%sx)"
+                   source interpreter-time bytecode-time
+                   (/ interpreter-time (max bytecode-time 1e-9)))))))
+  (kill-emacs (if (zerop failures) 0 1)))